@@ -0,0 +1,64 @@
+/*
+ * C interface to the rpi_peripherals I2C bus code (feature `ffi`).
+ *
+ * Functions that can fail return 0, or a count, on success and a negative
+ * exit code on failure, as the command line exits with:
+ *   -1 other error, -2 device not found, -3 bus error (NACK),
+ *   -4 timeout, -5 permission denied.
+ * rpi_last_error() has the message for the calling thread.
+ *
+ * Addresses are 7-bit unless above 0x7F, when they are 10-bit.
+ */
+
+#ifndef RPI_PERIPHERALS_H
+#define RPI_PERIPHERALS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An open bus. One handle can be shared between threads. */
+typedef struct RpiBus rpi_bus;
+
+/* Open /dev/i2c-<bus>; NULL on failure. */
+rpi_bus *rpi_bus_open(uint8_t bus);
+
+/* An in-memory bus where every address NACKs, for running without hardware. */
+rpi_bus *rpi_bus_open_stub(void);
+
+/* Close a handle; NULL is ignored. */
+void rpi_bus_close(rpi_bus *bus);
+
+/* Fail transactions that take longer than ms milliseconds (10 ms resolution). */
+int rpi_bus_set_timeout(rpi_bus *bus, uint32_t ms);
+
+/* Clock out a slave holding SDA low, then send a STOP. */
+int rpi_bus_recover(rpi_bus *bus);
+
+int rpi_i2c_write(rpi_bus *bus, uint16_t address, const uint8_t *data, size_t len);
+
+int rpi_i2c_read(rpi_bus *bus, uint16_t address, uint8_t *buffer, size_t len);
+
+/* Write, then read after a repeated START, as for reading a register. */
+int rpi_i2c_write_read(rpi_bus *bus, uint16_t address, const uint8_t *data, size_t len, uint8_t *buffer, size_t buffer_len);
+
+/* Show text on the HD44780 behind a PCF8574 backpack at address, or at
+ * whichever of 0x27 and 0x3F answers for 0. '\n' starts the next row. */
+int rpi_lcd_print(rpi_bus *bus, uint16_t address, uint8_t cols, uint8_t rows, const char *text);
+
+/* Probe 0x08..0x77 as i2cdetect does. Stores up to capacity answering
+ * addresses in found and returns how many answered, which may be more. */
+int rpi_scan(rpi_bus *bus, uint8_t *found, size_t capacity);
+
+/* The last error's message on this thread, "" if none. Valid until the
+ * next failing call on the same thread. */
+const char *rpi_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
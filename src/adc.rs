@@ -0,0 +1,60 @@
+//! Analog inputs through SPI converter chips.
+//!
+//! ```no_run
+//! use rpi_peripherals::adc::{self, Input, Mcp3008};
+//!
+//! let mut adc = Mcp3008::from_spi(0, 0)?;
+//! let pot = adc.read(Input::Single(0))?;
+//! let bridge = adc.read(Input::Differential { positive: 2, negative: 3 })?;
+//! println!("{:.3} V, bridge {}", adc::volts(pot, 3.3), bridge);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod mcp3008;
+
+pub use mcp3008::{volts, Mcp3008, MCP3008_CLOCK, MCP3008_MAX};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// What a conversion measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+    /// One channel against ground.
+    Single(u8),
+    /// One channel against its neighbour in a pair (0 and 1, 2 and 3, ...),
+    /// either way round. Reads 0 when `negative` is the higher of the two.
+    Differential { positive: u8, negative: u8 },
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Single(channel) => write!(f, "ch{}", channel),
+            Input::Differential { positive, negative } => write!(f, "ch{}-ch{}", positive, negative),
+        }
+    }
+}
+
+/// `3` is channel 3 alone, `2-3` channel 2 against channel 3; a `ch`
+/// prefix is allowed, as Display writes them.
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let channel = |c: &str| {
+            let c = c.trim();
+            c.strip_prefix("ch")
+                .unwrap_or(c)
+                .parse::<u8>()
+                .map_err(|_| format!("'{}' is not a channel number, as in 3 or 2-3", s))
+        };
+        match s.split_once('-') {
+            Some((positive, negative)) => Ok(Input::Differential {
+                positive: channel(positive)?,
+                negative: channel(negative)?,
+            }),
+            None => Ok(Input::Single(channel(s)?)),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use super::Input;
+use embedded_hal::spi::SpiDevice;
+use rppal::spi::SimpleHalSpiDevice;
+use std::error::Error;
+
+/// The datasheet's limit at 2.7 V, so it holds whatever the chip runs on;
+/// at 5 V it takes 3.6 MHz.
+pub const MCP3008_CLOCK: u32 = 1_350_000;
+
+/// Full scale, 10 bits.
+pub const MCP3008_MAX: u16 = 1023;
+
+const CHANNELS: u8 = 8;
+
+/// MCP3008 eight-channel 10-bit ADC (and the four-channel MCP3004, which
+/// answers the same way for channels 0-3).
+pub struct Mcp3008<SPI> {
+    spi: SPI,
+}
+
+impl Mcp3008<SimpleHalSpiDevice> {
+    /// The chip on `/dev/spidev<bus>.<cs>` at [`MCP3008_CLOCK`].
+    pub fn from_spi(bus: u8, cs: u8) -> Result<Self, Box<dyn Error>> {
+        let spi = crate::spi::open(bus, cs, MCP3008_CLOCK)?;
+        Ok(Mcp3008::new(SimpleHalSpiDevice::new(spi)))
+    }
+}
+
+impl<SPI> Mcp3008<SPI>
+where
+    SPI: SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    pub fn new(spi: SPI) -> Self {
+        Mcp3008 { spi }
+    }
+
+    /// One conversion, 0 to [`MCP3008_MAX`].
+    pub fn read(&mut self, input: Input) -> Result<u16, Box<dyn Error>> {
+        let select = match input {
+            Input::Single(channel) if channel < CHANNELS => 0x80 | channel << 4,
+            Input::Differential { positive, negative } if positive < CHANNELS && negative == positive ^ 1 => {
+                // The pair is picked by the positive channel alone
+                positive << 4
+            }
+            Input::Single(_) | Input::Differential { .. } => {
+                return Err(format!("{} is not an MCP3008 input (channels 0-7, pairs 0-1 to 6-7)", input).into())
+            }
+        };
+        // Start bit, then mode and channel; the 10 result bits come back in
+        // the last two bytes, so each byte boundary lines up
+        let mut frame = [0x01, select, 0x00];
+        self.spi.transfer_in_place(&mut frame)?;
+        Ok(u16::from(frame[1] & 0x03) << 8 | u16::from(frame[2]))
+    }
+
+    /// `read` for each of `inputs` in turn.
+    pub fn read_all(&mut self, inputs: &[Input]) -> Result<Vec<u16>, Box<dyn Error>> {
+        inputs.iter().map(|&input| self.read(input)).collect()
+    }
+
+    /// Volts at `input` with `vref` on the VREF pin.
+    pub fn read_volts(&mut self, input: Input, vref: f64) -> Result<f64, Box<dyn Error>> {
+        Ok(volts(self.read(input)?, vref))
+    }
+
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+/// An MCP3008 reading in volts: full scale is `vref` less one step.
+pub fn volts(raw: u16, vref: f64) -> f64 {
+    f64::from(raw) * vref / f64::from(MCP3008_MAX + 1)
+}
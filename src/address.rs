@@ -0,0 +1,292 @@
+//! 7-bit and 10-bit I2C addresses.
+//!
+//! `embedded-hal` buses take a raw `u8`, and `rppal` only reaches 10-bit
+//! slaves after `set_addr_10bit(true)`. [`Address`] carries the mode with the
+//! value, and [`AddressedI2c`] is implemented by every bus type in the crate
+//! so callers can hand it either kind.
+
+use embedded_hal::i2c::{I2c, Operation};
+use rppal::i2c::I2c as RppalI2c;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Address {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+impl Address {
+    /// A 7-bit address, rejecting the reserved 0x00-0x07 and 0x78-0x7F blocks.
+    pub fn seven_bit(address: u8) -> Result<Self, Box<dyn Error>> {
+        if address > 0x7F {
+            return Err(format!("0x{:02X} does not fit in 7 bits", address).into());
+        }
+        let addr = Address::SevenBit(address);
+        if let Some(reason) = addr.reserved_for() {
+            return Err(format!("0x{:02X} is reserved for {}", address, reason).into());
+        }
+        Ok(addr)
+    }
+
+    pub fn ten_bit(address: u16) -> Result<Self, Box<dyn Error>> {
+        if address > 0x3FF {
+            return Err(format!("0x{:03X} does not fit in 10 bits", address).into());
+        }
+        Ok(Address::TenBit(address))
+    }
+
+    /// 7-bit if it fits, 10-bit otherwise.
+    pub fn from_raw(address: u16) -> Result<Self, Box<dyn Error>> {
+        match u8::try_from(address) {
+            Ok(a) if a <= 0x7F => Address::seven_bit(a),
+            _ => Address::ten_bit(address),
+        }
+    }
+
+    pub fn raw(&self) -> u16 {
+        match *self {
+            Address::SevenBit(a) => u16::from(a),
+            Address::TenBit(a) => a,
+        }
+    }
+
+    pub fn is_ten_bit(&self) -> bool {
+        matches!(self, Address::TenBit(_))
+    }
+
+    /// What the I2C spec reserves this address for, if anything.
+    pub fn reserved_for(&self) -> Option<&'static str> {
+        let Address::SevenBit(a) = *self else {
+            return None;
+        };
+        match a {
+            0x00 => Some("general call / START byte"),
+            0x01 => Some("CBUS"),
+            0x02 => Some("a different bus format"),
+            0x03 => Some("future purposes"),
+            0x04..=0x07 => Some("Hs-mode master codes"),
+            0x78..=0x7B => Some("10-bit addressing"),
+            0x7C..=0x7F => Some("device ID"),
+            _ => None,
+        }
+    }
+
+    /// Bytes the master puts on the wire to address the slave, R/W bit included.
+    pub fn wire_bytes(&self, read: bool) -> Vec<u8> {
+        match *self {
+            Address::SevenBit(a) => vec![(a << 1) | read as u8],
+            // 11110 + two high bits + R/W, then the low eight bits.
+            Address::TenBit(a) => vec![0xF0 | ((a >> 7) as u8 & 0x06) | read as u8, a as u8],
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Address::SevenBit(a) => write!(f, "0x{:02X}", a),
+            Address::TenBit(a) => write!(f, "0x{:03X} (10-bit)", a),
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = Box<dyn Error>;
+
+    /// `0x27` / `39` (7-bit unless too large), or `10:0x050` to force 10-bit.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (force_ten, number) = match s.strip_prefix("10:") {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let value = match number.strip_prefix("0x").or_else(|| number.strip_prefix("0X")) {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => number.parse(),
+        }
+        .map_err(|_| format!("invalid I2C address '{}'", s))?;
+
+        if force_ten {
+            Address::ten_bit(value)
+        } else {
+            Address::from_raw(value)
+        }
+    }
+}
+
+/// Stored in the same notation [`FromStr`] accepts: `"0x27"` or `"10:0x123"`.
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Address::SevenBit(a) => s.serialize_str(&format!("0x{:02X}", a)),
+            Address::TenBit(a) => s.serialize_str(&format!("10:0x{:03X}", a)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Buses that can run a transaction against either kind of [`Address`].
+pub trait AddressedI2c {
+    fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Box<dyn Error>>;
+
+    fn write_at(&mut self, address: Address, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.transaction_at(address, &mut [Operation::Write(bytes)])
+    }
+
+    fn read_at(&mut self, address: Address, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.transaction_at(address, &mut [Operation::Read(buffer)])
+    }
+
+    fn write_read_at(
+        &mut self,
+        address: Address,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Box<dyn Error>> {
+        self.transaction_at(address, &mut [Operation::Write(bytes), Operation::Read(buffer)])
+    }
+
+    /// Whether a transaction of several operations at `address` goes out
+    /// as one, with a repeated START between them. Buses that split it
+    /// into separate transfers, a STOP after each, say `false`.
+    fn repeated_start(&self, address: Address) -> bool {
+        let _ = address;
+        true
+    }
+}
+
+/// Lets a driver borrow a bus instead of owning it.
+impl<T: AddressedI2c + ?Sized> AddressedI2c for &mut T {
+    fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Box<dyn Error>> {
+        (**self).transaction_at(address, operations)
+    }
+
+    fn repeated_start(&self, address: Address) -> bool {
+        (**self).repeated_start(address)
+    }
+}
+
+/// [`AddressedI2c::transaction_at`] for buses that only speak 7-bit.
+pub fn seven_bit_transaction<I2C>(
+    i2c: &mut I2C,
+    address: Address,
+    operations: &mut [Operation<'_>],
+) -> Result<(), Box<dyn Error>>
+where
+    I2C: I2c,
+    I2C::Error: Error + 'static,
+{
+    match address {
+        Address::SevenBit(a) => Ok(i2c.transaction(a, operations)?),
+        Address::TenBit(_) => Err(format!("{} needs a bus with 10-bit support", address).into()),
+    }
+}
+
+/// 7-bit transactions go out combined. rppal has no call for a combined
+/// 10-bit one, so those don't: see `ten_bit_transaction`.
+impl AddressedI2c for RppalI2c {
+    fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Box<dyn Error>> {
+        let Address::TenBit(raw) = address else {
+            return seven_bit_transaction(self, address, operations);
+        };
+        // Fails with FeatureNotSupported unless the kernel driver has 10-bit support.
+        self.set_addr_10bit(true)?;
+        let result = ten_bit_transaction(self, raw, operations);
+        self.set_addr_10bit(false)?;
+        result
+    }
+
+    fn repeated_start(&self, address: Address) -> bool {
+        matches!(address, Address::SevenBit(_))
+    }
+}
+
+/// Each operation as its own read or write call, so a STOP and a fresh
+/// START go between them where a combined transaction would have a
+/// repeated START. Devices that drop a register pointer on STOP need the
+/// `i2cdev` backend, which sends 10-bit transactions combined.
+fn ten_bit_transaction(
+    i2c: &mut RppalI2c,
+    address: u16,
+    operations: &mut [Operation<'_>],
+) -> Result<(), Box<dyn Error>> {
+    i2c.set_slave_address(address)?;
+    for op in operations {
+        match op {
+            Operation::Read(buf) => {
+                i2c.read(buf)?;
+            }
+            Operation::Write(buf) => {
+                i2c.write(buf)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seven_bit_rejects_reserved_blocks() {
+        for reserved in [0x00, 0x03, 0x07, 0x78, 0x7B, 0x7C, 0x7F] {
+            assert!(Address::seven_bit(reserved).is_err(), "0x{:02X}", reserved);
+        }
+        assert!(Address::seven_bit(0x80).is_err());
+        assert_eq!(Address::seven_bit(0x08).unwrap(), Address::SevenBit(0x08));
+        assert_eq!(Address::seven_bit(0x77).unwrap(), Address::SevenBit(0x77));
+        assert_eq!(Address::SevenBit(0x7A).reserved_for(), Some("10-bit addressing"));
+        assert_eq!(Address::TenBit(0x000).reserved_for(), None);
+    }
+
+    #[test]
+    fn parses_both_widths() {
+        assert_eq!("0x27".parse::<Address>().unwrap(), Address::SevenBit(0x27));
+        assert_eq!(" 39 ".parse::<Address>().unwrap(), Address::SevenBit(0x27));
+        assert_eq!("0x150".parse::<Address>().unwrap(), Address::TenBit(0x150));
+        assert_eq!("10:0x050".parse::<Address>().unwrap(), Address::TenBit(0x050));
+        assert_eq!("10:0X3FF".parse::<Address>().unwrap(), Address::TenBit(0x3FF));
+        // forced 10-bit skips the 7-bit reservations
+        assert_eq!("10:0x00".parse::<Address>().unwrap(), Address::TenBit(0x000));
+        for bad in ["0x400", "10:0x400", "10:1024", "0x03", "65536", "10:", "0xZZ", ""] {
+            assert!(bad.parse::<Address>().is_err(), "{:?}", bad);
+        }
+        for address in [Address::SevenBit(0x27), Address::TenBit(0x123)] {
+            let json = serde_json::to_string(&address).unwrap();
+            assert_eq!(serde_json::from_str::<Address>(&json).unwrap(), address);
+        }
+    }
+
+    #[test]
+    fn wire_bytes_carry_the_header() {
+        assert_eq!(Address::SevenBit(0x27).wire_bytes(false), [0x4E]);
+        assert_eq!(Address::SevenBit(0x27).wire_bytes(true), [0x4F]);
+        // 11110 A9 A8 R/W, then A7-A0
+        assert_eq!(Address::TenBit(0x000).wire_bytes(false), [0xF0, 0x00]);
+        assert_eq!(Address::TenBit(0x150).wire_bytes(false), [0xF2, 0x50]);
+        assert_eq!(Address::TenBit(0x2AB).wire_bytes(true), [0xF5, 0xAB]);
+        assert_eq!(Address::TenBit(0x3FF).wire_bytes(true), [0xF7, 0xFF]);
+    }
+}
@@ -0,0 +1,653 @@
+//! One place to decide how loudly to tell someone something went wrong.
+//!
+//! Modules raise an [`Alert`] with a [`Severity`]; the [`Alerter`] looks the
+//! severity up in the `[alerts]` config section and drives whichever outputs
+//! it names, so nothing else touches the buzzer or the status LED:
+//!
+//! ```toml
+//! [alerts]
+//! repeat_after = "10m"          # the same alert again stays silent until then
+//! quiet_hours = "22:00-07:00"   # mutes quiet_outputs (the buzzer) overnight
+//! quiet_override = "critical"   # ... except for this severity and above
+//!
+//! [alerts.buzzer]
+//! pin = 18
+//!
+//! [alerts.led]                  # a WS2812 status pixel on SPI0 MOSI
+//! count = 1
+//!
+//! [alerts.levels.warning]
+//! buzzer = "double"
+//! led = "#ff8000"
+//! banner = true
+//! mqtt = true
+//! ```
+//!
+//! Severities without a `levels` entry keep the defaults: info shows a
+//! banner, warning beeps once and lights the LED amber, critical sounds the
+//! alarm and lights it red, and both of those publish to MQTT. The LED shows
+//! the most severe alert still active until each is cleared; the banner
+//! lasts `banner_for` and is up to whatever owns the LCD to show, through
+//! [`Alerter::banner`]. The LED needs the `leds` feature and MQTT the
+//! `mqtt` feature; without them those outputs are skipped.
+
+use crate::clock::{self, Clock};
+#[cfg(feature = "leds")]
+use crate::leds::{Rgb, Strip};
+use crate::localtime;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::Publisher;
+use crate::parse::{self, serde_helpers};
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::Gpio;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" | "warn" => Ok(Severity::Warning),
+            "critical" | "crit" => Ok(Severity::Critical),
+            _ => Err(format!("unknown severity '{}' (expected info, warning or critical)", s)),
+        }
+    }
+}
+
+/// Where an alert can go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    Buzzer,
+    Led,
+    Banner,
+    Mqtt,
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Output::Buzzer => "buzzer",
+            Output::Led => "led",
+            Output::Banner => "banner",
+            Output::Mqtt => "mqtt",
+        })
+    }
+}
+
+/// Something worth telling someone about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Alert {
+    /// What this is about, such as `presence.lcd`: repeats are limited per
+    /// key, and [`Alerter::clear`] takes it back.
+    pub key: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Alert {
+    pub fn new(key: impl Into<String>, severity: Severity, message: impl Into<String>) -> Self {
+        Alert {
+            key: key.into(),
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+/// Times a buzzer is on then off, in turn.
+///
+/// Written as a name (`beep`, `double`, `triple`, `chirp`, `alarm`) or as
+/// durations alternating on and off: `"200ms,100ms,600ms"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct BeepPattern(Vec<(Duration, Duration)>);
+
+impl BeepPattern {
+    pub fn new(steps: Vec<(Duration, Duration)>) -> Result<Self, Box<dyn Error>> {
+        if steps.is_empty() || steps.iter().all(|(on, _)| on.is_zero()) {
+            return Err("a beep pattern needs some time on".into());
+        }
+        Ok(BeepPattern(steps))
+    }
+
+    pub fn steps(&self) -> &[(Duration, Duration)] {
+        &self.0
+    }
+
+    /// How long it takes to play.
+    pub fn duration(&self) -> Duration {
+        self.0.iter().map(|(on, off)| *on + *off).sum()
+    }
+}
+
+impl FromStr for BeepPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ms = Duration::from_millis;
+        let steps = match s.trim() {
+            "beep" => vec![(ms(150), ms(0))],
+            "double" => vec![(ms(100), ms(100)), (ms(100), ms(0))],
+            "triple" => vec![(ms(100), ms(100)), (ms(100), ms(100)), (ms(100), ms(0))],
+            "chirp" => vec![(ms(30), ms(0))],
+            "alarm" => vec![(ms(400), ms(200)); 5],
+            custom => {
+                let times = custom
+                    .split(',')
+                    .map(|t| parse::duration(t.trim()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| {
+                        format!(
+                            "unknown beep pattern '{}' (expected beep, double, triple, chirp, alarm or times such as 200ms,100ms,200ms)",
+                            s
+                        )
+                    })?;
+                times.chunks(2).map(|pair| (pair[0], pair.get(1).copied().unwrap_or_default())).collect()
+            }
+        };
+        BeepPattern::new(steps).map_err(|e| format!("beep pattern '{}': {}", s, e))
+    }
+}
+
+impl TryFrom<String> for BeepPattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A stretch of the day, such as `"22:00-07:00"`; may run past midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct QuietHours {
+    /// Minutes after local midnight.
+    pub start: u16,
+    pub end: u16,
+}
+
+impl QuietHours {
+    pub fn contains(&self, minute_of_day: u16) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute_of_day)
+        } else {
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let time = |t: &str| -> Option<u16> {
+            let (h, m) = t.trim().split_once(':')?;
+            let (h, m) = (h.parse::<u16>().ok()?, m.parse::<u16>().ok()?);
+            (h < 24 && m < 60).then_some(h * 60 + m)
+        };
+        let bad = || format!("quiet hours '{}' should look like 22:00-07:00", s);
+        let (start, end) = s.split_once('-').ok_or_else(bad)?;
+        Ok(QuietHours {
+            start: time(start).ok_or_else(bad)?,
+            end: time(end).ok_or_else(bad)?,
+        })
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// What one severity does.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelConfig {
+    #[serde(default)]
+    pub buzzer: Option<BeepPattern>,
+    #[cfg(feature = "leds")]
+    #[serde(default)]
+    pub led: Option<Rgb>,
+    #[serde(default)]
+    pub banner: bool,
+    #[serde(default)]
+    pub mqtt: bool,
+}
+
+impl LevelConfig {
+    /// What a severity does without a `levels` entry.
+    pub fn default_for(severity: Severity) -> Self {
+        let pattern = |name: &str| name.parse().ok();
+        match severity {
+            Severity::Info => LevelConfig {
+                banner: true,
+                ..LevelConfig::default()
+            },
+            Severity::Warning => LevelConfig {
+                buzzer: pattern("beep"),
+                #[cfg(feature = "leds")]
+                led: Some(Rgb::new(255, 128, 0)),
+                banner: true,
+                mqtt: true,
+            },
+            Severity::Critical => LevelConfig {
+                buzzer: pattern("alarm"),
+                #[cfg(feature = "leds")]
+                led: Some(Rgb::new(255, 0, 0)),
+                banner: true,
+                mqtt: true,
+            },
+        }
+    }
+
+    fn outputs(&self) -> Vec<Output> {
+        let mut outputs = Vec::new();
+        if self.buzzer.is_some() {
+            outputs.push(Output::Buzzer);
+        }
+        #[cfg(feature = "leds")]
+        if self.led.is_some() {
+            outputs.push(Output::Led);
+        }
+        if self.banner {
+            outputs.push(Output::Banner);
+        }
+        if self.mqtt {
+            outputs.push(Output::Mqtt);
+        }
+        outputs
+    }
+}
+
+/// An active buzzer (one with its own oscillator) on a GPIO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuzzerConfig {
+    pub pin: u8,
+    /// Sounds when the pin is low, as through a PNP transistor.
+    #[serde(default)]
+    pub active_low: bool,
+}
+
+/// WS2812 LEDs used as a status light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LedConfig {
+    #[serde(default)]
+    pub spi: u8,
+    #[serde(default = "default_led_count")]
+    pub count: usize,
+    #[serde(default = "default_led_brightness")]
+    pub brightness: u8,
+}
+
+fn default_led_count() -> usize {
+    1
+}
+
+fn default_led_brightness() -> u8 {
+    64
+}
+
+/// The `[alerts]` config section.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    #[serde(default)]
+    pub levels: BTreeMap<Severity, LevelConfig>,
+    /// The same key stays silent this long after it last went out, unless
+    /// its severity goes up.
+    #[serde(default = "default_repeat_after", deserialize_with = "serde_helpers::duration")]
+    pub repeat_after: Duration,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    /// Muted during quiet hours.
+    #[serde(default = "default_quiet_outputs")]
+    pub quiet_outputs: Vec<Output>,
+    /// This severity and above go out in quiet hours regardless.
+    #[serde(default = "default_quiet_override")]
+    pub quiet_override: Severity,
+    /// How long [`Alerter::banner`] keeps showing an alert.
+    #[serde(default = "default_banner_for", deserialize_with = "serde_helpers::duration")]
+    pub banner_for: Duration,
+    #[serde(default)]
+    pub buzzer: Option<BuzzerConfig>,
+    #[serde(default)]
+    pub led: Option<LedConfig>,
+}
+
+fn default_repeat_after() -> Duration {
+    Duration::from_secs(300)
+}
+
+fn default_quiet_outputs() -> Vec<Output> {
+    vec![Output::Buzzer]
+}
+
+fn default_quiet_override() -> Severity {
+    Severity::Critical
+}
+
+fn default_banner_for() -> Duration {
+    Duration::from_secs(30)
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        AlertConfig {
+            levels: BTreeMap::new(),
+            repeat_after: default_repeat_after(),
+            quiet_hours: None,
+            quiet_outputs: default_quiet_outputs(),
+            quiet_override: default_quiet_override(),
+            banner_for: default_banner_for(),
+            buzzer: None,
+            led: None,
+        }
+    }
+}
+
+impl AlertConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(led) = &self.led {
+            if led.count == 0 {
+                return Err("alerts.led.count must be at least 1".into());
+            }
+        }
+        if self.banner_for.is_zero() {
+            return Err("alerts.banner_for must be greater than zero".into());
+        }
+        Ok(())
+    }
+
+    /// The `levels` entry for `severity`, else its default.
+    pub fn level(&self, severity: Severity) -> LevelConfig {
+        self.levels.get(&severity).cloned().unwrap_or_else(|| LevelConfig::default_for(severity))
+    }
+
+    /// Whether any severity publishes to MQTT.
+    pub fn uses_mqtt(&self) -> bool {
+        [Severity::Info, Severity::Warning, Severity::Critical].into_iter().any(|s| self.level(s).mqtt)
+    }
+}
+
+/// Plays [`BeepPattern`]s; a new one cuts off whatever is playing.
+pub trait Buzzer: Send {
+    fn play(&mut self, pattern: &BeepPattern) -> Result<(), Box<dyn Error>>;
+}
+
+/// A buzzer on a GPIO, played from a thread of its own so raising an alert
+/// never waits for the beeps.
+pub struct GpioBuzzer {
+    patterns: Sender<BeepPattern>,
+}
+
+impl GpioBuzzer {
+    pub fn from_gpio(config: &BuzzerConfig) -> Result<Self, Box<dyn Error>> {
+        let pin = Gpio::new()?
+            .get(config.pin)
+            .map_err(|e| format!("buzzer GPIO {}: {}", config.pin, e))?
+            .into_output();
+        GpioBuzzer::spawn(pin, config.active_low)
+    }
+
+    pub fn spawn<P>(mut pin: P, active_low: bool) -> Result<Self, Box<dyn Error>>
+    where
+        P: OutputPin + Send + 'static,
+    {
+        let (patterns, queue) = mpsc::channel();
+        let _ = set_pin(&mut pin, false, active_low);
+        thread::Builder::new()
+            .name("buzzer".into())
+            .spawn(move || play_patterns(pin, active_low, queue))?;
+        Ok(GpioBuzzer { patterns })
+    }
+}
+
+impl Buzzer for GpioBuzzer {
+    fn play(&mut self, pattern: &BeepPattern) -> Result<(), Box<dyn Error>> {
+        self.patterns.send(pattern.clone()).map_err(|_| "the buzzer thread has stopped")?;
+        Ok(())
+    }
+}
+
+fn set_pin<P: OutputPin>(pin: &mut P, on: bool, active_low: bool) -> Result<(), P::Error> {
+    if on != active_low {
+        pin.set_high()
+    } else {
+        pin.set_low()
+    }
+}
+
+/// Until the [`GpioBuzzer`] is dropped; GPIO errors just leave it silent.
+fn play_patterns<P: OutputPin>(mut pin: P, active_low: bool, queue: Receiver<BeepPattern>) {
+    let mut next = queue.recv().ok();
+    while let Some(pattern) = next.take() {
+        'steps: for &(on, off) in pattern.steps() {
+            for (level, time) in [(true, on), (false, off)] {
+                if time.is_zero() {
+                    continue;
+                }
+                let _ = set_pin(&mut pin, level, active_low);
+                match queue.recv_timeout(time) {
+                    Ok(newer) => {
+                        next = Some(newer);
+                        break 'steps;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break 'steps,
+                }
+            }
+        }
+        let _ = set_pin(&mut pin, false, active_low);
+        if next.is_none() {
+            next = queue.recv().ok();
+        }
+    }
+}
+
+struct Banner {
+    text: String,
+    key: String,
+    until: Instant,
+}
+
+/// Applies the `[alerts]` policy and drives the outputs it has been given;
+/// outputs a level names but nobody attached are skipped.
+pub struct Alerter {
+    config: AlertConfig,
+    buzzer: Option<Box<dyn Buzzer>>,
+    #[cfg(feature = "leds")]
+    led: Option<Box<dyn Strip + Send>>,
+    #[cfg(feature = "mqtt")]
+    publisher: Option<Publisher>,
+    /// When each key last went out, and how severe it was.
+    sent: HashMap<String, (Severity, Instant)>,
+    /// Raised and not yet cleared, for the LED.
+    active: BTreeMap<String, Severity>,
+    banner: Option<Banner>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Alerter {
+    pub fn new(config: AlertConfig) -> Result<Self, Box<dyn Error>> {
+        config.validate()?;
+        Ok(Alerter {
+            config,
+            buzzer: None,
+            #[cfg(feature = "leds")]
+            led: None,
+            #[cfg(feature = "mqtt")]
+            publisher: None,
+            sent: HashMap::new(),
+            active: BTreeMap::new(),
+            banner: None,
+            clock: clock::system(),
+        })
+    }
+
+    pub fn config(&self) -> &AlertConfig {
+        &self.config
+    }
+
+    pub fn set_buzzer(&mut self, buzzer: Box<dyn Buzzer>) {
+        self.buzzer = Some(buzzer);
+    }
+
+    #[cfg(feature = "leds")]
+    pub fn set_led(&mut self, led: Box<dyn Strip + Send>) {
+        self.led = Some(led);
+    }
+
+    #[cfg(feature = "mqtt")]
+    pub fn set_publisher(&mut self, publisher: Publisher) {
+        self.publisher = Some(publisher);
+    }
+
+    /// Time rate limits and banners by `clock`; quiet hours still follow
+    /// the local time of day.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Send `alert` wherever its severity goes, less anything rate-limited
+    /// or in quiet hours. Returns the outputs it went to; if any of them
+    /// failed the rest are still tried, and the failures returned together.
+    pub fn raise(&mut self, alert: &Alert) -> Result<Vec<Output>, Box<dyn Error>> {
+        let now = self.clock.now();
+        self.active.insert(alert.key.clone(), alert.severity);
+        let outputs = self.outputs_for(alert, now, localtime::now().map(|t| t.minute_of_day()));
+        if outputs.is_empty() {
+            return Ok(outputs);
+        }
+        self.sent.insert(alert.key.clone(), (alert.severity, now));
+        let level = self.config.level(alert.severity);
+        let mut sent = Vec::new();
+        let mut failures = Vec::new();
+        for output in outputs {
+            let result = match output {
+                Output::Buzzer => match (&mut self.buzzer, &level.buzzer) {
+                    (Some(buzzer), Some(pattern)) => buzzer.play(pattern).map(|_| true),
+                    _ => Ok(false),
+                },
+                Output::Led => self.refresh_led(),
+                Output::Banner => {
+                    self.banner = Some(Banner {
+                        text: alert.message.clone(),
+                        key: alert.key.clone(),
+                        until: now + self.config.banner_for,
+                    });
+                    Ok(true)
+                }
+                #[cfg(feature = "mqtt")]
+                Output::Mqtt => match &mut self.publisher {
+                    Some(publisher) => publisher.alert(alert).map(|_| true),
+                    None => Ok(false),
+                },
+                #[cfg(not(feature = "mqtt"))]
+                Output::Mqtt => Ok(false),
+            };
+            match result {
+                Ok(true) => sent.push(output),
+                Ok(false) => {}
+                Err(e) => failures.push(format!("{}: {}", output, e)),
+            }
+        }
+        if !failures.is_empty() {
+            return Err(failures.join("; ").into());
+        }
+        Ok(sent)
+    }
+
+    /// `key` is over: the LED falls back to whatever else is active and its
+    /// banner comes down. The next alert for it goes out at once.
+    pub fn clear(&mut self, key: &str) -> Result<(), Box<dyn Error>> {
+        self.sent.remove(key);
+        if self.banner.as_ref().is_some_and(|b| b.key == key) {
+            self.banner = None;
+        }
+        if self.active.remove(key).is_some() {
+            self.refresh_led()?;
+        }
+        Ok(())
+    }
+
+    /// The message to show across the LCD, while there is one.
+    pub fn banner(&self) -> Option<&str> {
+        self.banner.as_ref().filter(|b| self.clock.now() < b.until).map(|b| b.text.as_str())
+    }
+
+    /// Keys raised and not cleared, with their severities.
+    pub fn active(&self) -> impl Iterator<Item = (&str, Severity)> {
+        self.active.iter().map(|(key, severity)| (key.as_str(), *severity))
+    }
+
+    fn outputs_for(&self, alert: &Alert, now: Instant, minute: Option<u16>) -> Vec<Output> {
+        if let Some(&(severity, at)) = self.sent.get(&alert.key) {
+            if alert.severity <= severity && now.duration_since(at) < self.config.repeat_after {
+                return Vec::new();
+            }
+        }
+        let quiet = alert.severity < self.config.quiet_override
+            && matches!((self.config.quiet_hours, minute), (Some(hours), Some(minute)) if hours.contains(minute));
+        let mut outputs = self.config.level(alert.severity).outputs();
+        if quiet {
+            outputs.retain(|output| !self.config.quiet_outputs.contains(output));
+        }
+        outputs
+    }
+
+    /// Light the LED for the most severe active alert that has a colour,
+    /// or switch it off. `false` if there is no LED.
+    #[cfg(feature = "leds")]
+    fn refresh_led(&mut self) -> Result<bool, Box<dyn Error>> {
+        let Some(led) = &mut self.led else {
+            return Ok(false);
+        };
+        let color = self
+            .active
+            .values()
+            .filter_map(|&severity| self.config.level(severity).led.map(|color| (severity, color)))
+            .max_by_key(|(severity, _)| *severity)
+            .map_or(Rgb::OFF, |(_, color)| color);
+        led.fill(color);
+        led.show()?;
+        Ok(true)
+    }
+
+    #[cfg(not(feature = "leds"))]
+    fn refresh_led(&mut self) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+}
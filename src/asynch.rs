@@ -0,0 +1,88 @@
+//! Async access to blocking drivers for tokio applications.
+//!
+//! Drivers here are full of `thread::sleep` between bytes and commands. Rather
+//! than make each one async, [`AsyncDevice`] moves a driver onto a dedicated
+//! I/O thread and feeds it closures over a channel; the caller awaits the
+//! result without blocking its runtime. Requests run one at a time in order,
+//! so multi-step sequences from different tasks never interleave.
+
+use crate::address::AddressedI2c;
+use crate::transmitter::SimpleI2cTransmitter;
+use std::error::Error;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+type Job<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// A driver owned by its own I/O thread. Clones share the same thread; the
+/// thread exits once every clone is dropped.
+pub struct AsyncDevice<T> {
+    jobs: mpsc::Sender<Job<T>>,
+}
+
+impl<T> Clone for AsyncDevice<T> {
+    fn clone(&self) -> Self {
+        AsyncDevice {
+            jobs: self.jobs.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> AsyncDevice<T> {
+    pub fn spawn(mut device: T) -> Result<Self, Box<dyn Error>> {
+        let (jobs, queue) = mpsc::channel::<Job<T>>();
+        thread::Builder::new()
+            .name("peripheral-io".into())
+            .spawn(move || {
+                for job in queue {
+                    job(&mut device);
+                }
+            })?;
+        Ok(AsyncDevice { jobs })
+    }
+
+    /// Run `f` against the driver on its I/O thread and await the result.
+    pub async fn call<R, F>(&self, f: F) -> Result<R, Box<dyn Error>>
+    where
+        F: FnOnce(&mut T) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |device| {
+                // The caller may have stopped waiting; that's not our problem.
+                let _ = reply.send(f(device));
+            }))
+            .map_err(|_| "device I/O thread has stopped")?;
+        Ok(result.await.map_err(|_| "device I/O thread dropped the request")?)
+    }
+}
+
+/// Async front end for [`SimpleI2cTransmitter`].
+pub struct AsyncTransmitter<I2C> {
+    device: AsyncDevice<SimpleI2cTransmitter<I2C>>,
+}
+
+impl<I2C: AddressedI2c + Send + 'static> AsyncTransmitter<I2C> {
+    pub fn new(transmitter: SimpleI2cTransmitter<I2C>) -> Result<Self, Box<dyn Error>> {
+        Ok(AsyncTransmitter {
+            device: AsyncDevice::spawn(transmitter)?,
+        })
+    }
+
+    /// Async [`SimpleI2cTransmitter::send_message`].
+    pub async fn send_message(&self, message_number: u32) -> Result<Duration, Box<dyn Error>> {
+        // Box<dyn Error> isn't Send, so errors cross the thread as text.
+        self.device
+            .call(move |tx| tx.send_message(message_number).map_err(|e| e.to_string()))
+            .await?
+            .map_err(Into::into)
+    }
+
+    /// The underlying device, for running arbitrary calls on the transmitter.
+    pub fn device(&self) -> &AsyncDevice<SimpleI2cTransmitter<I2C>> {
+        &self.device
+    }
+}
@@ -0,0 +1,320 @@
+//! Sound level and spectrum from an ALSA capture device.
+//!
+//! Anything ALSA can record from works: a USB sound card, or an I2S MEMS
+//! microphone such as the INMP441 or SPH0645 through a device tree overlay
+//! (`dtoverlay=googlevoicehat-soundcard` suits both). Samples come from
+//! `arecord`, so nothing links against libasound; `arecord -l` lists the
+//! devices.
+//!
+//! ```no_run
+//! use rpi_peripherals::audio::{Analyzer, Capture, DEFAULT_RATE, FFT_SIZE};
+//!
+//! let mut capture = Capture::open("plughw:1", DEFAULT_RATE)?;
+//! let analyzer = Analyzer::new(DEFAULT_RATE, FFT_SIZE, 16)?;
+//! let mut block = vec![0.0; FFT_SIZE];
+//! capture.read(&mut block)?;
+//! let analysis = analyzer.analyze(&block);
+//! println!("level {:.2}, bass {:.2}", analysis.level, analysis.bands[0]);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! For noise monitoring, [`spl`] reads an I2S microphone at its full 32 bits
+//! and turns what it hears into dB SPL:
+//!
+//! ```no_run
+//! use rpi_peripherals::audio::spl::{Microphone, SplMeter, MIC_RATE};
+//!
+//! let mic = Microphone::Inmp441;
+//! let mut capture = mic.open("hw:CARD=sndrpigooglevoi", MIC_RATE)?;
+//! let mut meter = SplMeter::new(mic, MIC_RATE);
+//! let mut block = vec![0.0; MIC_RATE as usize];
+//! capture.read(&mut block)?;
+//! meter.feed(&block);
+//! if let Some(reading) = meter.take() {
+//!     println!("{:.1} dB SPL", reading.leq);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod spl;
+
+use std::error::Error;
+use std::f32::consts::PI;
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+pub const DEFAULT_RATE: u32 = 44_100;
+
+/// 23 ms at 44.1 kHz: enough bins for the bass, short enough to follow a
+/// beat.
+pub const FFT_SIZE: usize = 1024;
+
+/// The quietest sound that still registers, as [`Analysis`] values scale
+/// it: this many dB below full scale is 0.0, full scale is 1.0.
+pub const FLOOR_DB: f32 = 60.0;
+
+/// Band edges for the spectrum, roughly where music has something to show.
+const LOWEST_HZ: f32 = 40.0;
+const HIGHEST_HZ: f32 = 16_000.0;
+
+/// How `arecord` is asked to hand samples over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    S16Le,
+    /// What I2S microphones send: 24 or 18 bits, left-aligned in 32.
+    S32Le,
+}
+
+impl SampleFormat {
+    pub fn bytes(self) -> usize {
+        match self {
+            SampleFormat::S16Le => 2,
+            SampleFormat::S32Le => 4,
+        }
+    }
+
+    fn arecord_name(self) -> &'static str {
+        match self {
+            SampleFormat::S16Le => "S16_LE",
+            SampleFormat::S32Le => "S32_LE",
+        }
+    }
+}
+
+/// Samples from `arecord`, which is stopped when this is dropped.
+pub struct Capture {
+    child: Child,
+    stdout: ChildStdout,
+    rate: u32,
+    channels: usize,
+    format: SampleFormat,
+    /// The channel [`Capture::read`] returns.
+    channel: usize,
+    raw: Vec<u8>,
+    frames: Vec<i32>,
+}
+
+impl Capture {
+    /// Mono 16-bit from ALSA `device` (`default`, `plughw:1`, ...) at `rate`
+    /// Hz.
+    pub fn open(device: &str, rate: u32) -> Result<Self, Box<dyn Error>> {
+        Capture::open_pcm(device, rate, 1, SampleFormat::S16Le)
+    }
+
+    /// `channels` interleaved channels in `format`. I2S devices only take
+    /// their own format and two channels unless opened through `plughw`.
+    pub fn open_pcm(device: &str, rate: u32, channels: usize, format: SampleFormat) -> Result<Self, Box<dyn Error>> {
+        if channels == 0 {
+            return Err("need at least one channel".into());
+        }
+        let mut child = Command::new("arecord")
+            .args(["-q", "-D", device, "-f", format.arecord_name(), "-t", "raw"])
+            .arg("-c")
+            .arg(channels.to_string())
+            .arg("-r")
+            .arg(rate.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => "arecord not found; install alsa-utils".to_string(),
+                _ => format!("arecord: {}", e),
+            })?;
+        let stdout = child.stdout.take().ok_or("arecord has no stdout")?;
+        Ok(Capture {
+            child,
+            stdout,
+            rate,
+            channels,
+            format,
+            channel: 0,
+            raw: Vec::new(),
+            frames: Vec::new(),
+        })
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Which channel [`Capture::read`] returns, 0 (left) by default. An I2S
+    /// microphone is on the right when its L/R pin is tied high.
+    pub fn select_channel(&mut self, channel: usize) -> Result<(), Box<dyn Error>> {
+        if channel >= self.channels {
+            return Err(format!("channel {} out of range for {} channels", channel, self.channels).into());
+        }
+        self.channel = channel;
+        Ok(())
+    }
+
+    /// Fill `frames` with the next samples as recorded, channels interleaved
+    /// and scaled to 32 bits whatever the format, so full scale is always
+    /// `i32::MAX`. Its length must be a multiple of the channel count.
+    pub fn read_frames(&mut self, frames: &mut [i32]) -> Result<(), Box<dyn Error>> {
+        if !frames.len().is_multiple_of(self.channels) {
+            return Err(format!("{} samples is not a whole number of {}-channel frames", frames.len(), self.channels).into());
+        }
+        let width = self.format.bytes();
+        self.raw.resize(frames.len() * width, 0);
+        if let Err(e) = self.stdout.read_exact(&mut self.raw) {
+            // arecord has usually said why on stderr by now
+            let status = self.child.try_wait().ok().flatten();
+            return Err(match status {
+                Some(status) => format!("arecord stopped ({})", status),
+                None => format!("arecord: {}", e),
+            }
+            .into());
+        }
+        for (sample, bytes) in frames.iter_mut().zip(self.raw.chunks_exact(width)) {
+            *sample = match self.format {
+                SampleFormat::S16Le => i32::from(i16::from_le_bytes([bytes[0], bytes[1]])) << 16,
+                SampleFormat::S32Le => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            };
+        }
+        Ok(())
+    }
+
+    /// Fill `samples` with the next ones from the selected channel, scaled
+    /// to -1.0..1.0. Blocks until they have been recorded.
+    pub fn read(&mut self, samples: &mut [f32]) -> Result<(), Box<dyn Error>> {
+        let mut frames = std::mem::take(&mut self.frames);
+        frames.resize(samples.len() * self.channels, 0);
+        let result = self.read_frames(&mut frames);
+        for (sample, frame) in samples.iter_mut().zip(frames.chunks_exact(self.channels)) {
+            *sample = frame[self.channel] as f32 / 2_147_483_648.0;
+        }
+        self.frames = frames;
+        result
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// How loud one block was, overall and per band, each 0.0 (at or below
+/// [`FLOOR_DB`] down) to 1.0 (full scale).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub level: f32,
+    /// Lowest frequencies first, spaced evenly on a log scale.
+    pub bands: Vec<f32>,
+}
+
+/// Windowed FFT of fixed-size blocks, summed into log-spaced bands.
+pub struct Analyzer {
+    size: usize,
+    window: Vec<f32>,
+    /// FFT bins `start..end` of each band.
+    bands: Vec<(usize, usize)>,
+}
+
+impl Analyzer {
+    /// Blocks of `size` samples (a power of two) at `rate` Hz, split into
+    /// `bands` bands.
+    pub fn new(rate: u32, size: usize, bands: usize) -> Result<Self, Box<dyn Error>> {
+        if !size.is_power_of_two() || size < 64 {
+            return Err(format!("FFT size must be a power of two of at least 64, not {}", size).into());
+        }
+        if bands == 0 {
+            return Err("need at least one band".into());
+        }
+        let hz_per_bin = rate as f32 / size as f32;
+        let top = HIGHEST_HZ.min(rate as f32 / 2.0);
+        let mut edges = Vec::with_capacity(bands);
+        let mut start = ((LOWEST_HZ / hz_per_bin) as usize).max(1);
+        for n in 1..=bands {
+            let hz = LOWEST_HZ * (top / LOWEST_HZ).powf(n as f32 / bands as f32);
+            // Every band gets at least one bin, even where they are wider
+            // than the bands down in the bass
+            let end = ((hz / hz_per_bin) as usize).max(start + 1).min(size / 2);
+            edges.push((start.min(size / 2 - 1), end));
+            start = end;
+        }
+        // Hann: no leakage from a loud bass drowning the treble bands
+        let window = (0..size).map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / size as f32).cos()).collect();
+        Ok(Analyzer { size, window, bands: edges })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn band_count(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// `samples` must be [`Analyzer::size`] long.
+    pub fn analyze(&self, samples: &[f32]) -> Analysis {
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt();
+        let mut re: Vec<f32> = samples.iter().zip(&self.window).map(|(s, w)| s * w).collect();
+        re.resize(self.size, 0.0);
+        let mut im = vec![0.0; self.size];
+        fft(&mut re, &mut im);
+        // A full-scale sine peaks at size / 4 through the Hann window
+        let full_scale = self.size as f32 / 4.0;
+        let bands = self
+            .bands
+            .iter()
+            .map(|&(start, end)| {
+                let peak = (start..end).map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt()).fold(0.0, f32::max);
+                scale(peak / full_scale)
+            })
+            .collect();
+        // RMS of a full-scale sine is 1/√2
+        Analysis {
+            level: scale(rms * std::f32::consts::SQRT_2),
+            bands,
+        }
+    }
+}
+
+/// Amplitude 0..1 to 0..1 on a dB scale spanning [`FLOOR_DB`].
+fn scale(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return 0.0;
+    }
+    (1.0 + 20.0 * amplitude.log10() / FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/// In-place iterative radix-2 FFT; both slices the same power-of-two length.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
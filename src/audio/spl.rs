@@ -0,0 +1,184 @@
+use super::{Capture, SampleFormat};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// What the I2S overlays clock the microphones at.
+pub const MIC_RATE: u32 = 48_000;
+
+/// The "fast" time weighting of a sound level meter, over which
+/// [`SplReading::min`] and [`SplReading::max`] are taken.
+const FAST_SECS: f64 = 0.125;
+
+/// Below this the DC blocker lets nothing through.
+const DC_CUTOFF_HZ: f64 = 10.0;
+
+/// The I2S MEMS microphones with datasheet figures here. Both want
+/// `dtoverlay=googlevoicehat-soundcard` (or any simple I2S capture overlay)
+/// and give 32-bit stereo with the microphone in one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Microphone {
+    /// 18 bits, with a DC offset that [`SplMeter`] filters out.
+    Sph0645,
+    /// 24 bits.
+    Inmp441,
+}
+
+impl Microphone {
+    /// Output for 94 dB SPL (1 Pa) at 1 kHz, in dBFS.
+    pub fn sensitivity_dbfs(self) -> f32 {
+        match self {
+            Microphone::Sph0645 => -26.0,
+            Microphone::Inmp441 => -26.0,
+        }
+    }
+
+    /// The microphone's own noise, 94 dB less its signal-to-noise ratio:
+    /// a reading this low is the microphone, not the room.
+    pub fn noise_floor_db(self) -> f32 {
+        match self {
+            Microphone::Sph0645 => 29.0,
+            Microphone::Inmp441 => 33.0,
+        }
+    }
+
+    /// Start recording from ALSA `device` in the format the microphone
+    /// sends; select the channel it is on before reading.
+    pub fn open(self, device: &str, rate: u32) -> Result<Capture, Box<dyn Error>> {
+        Capture::open_pcm(device, rate, 2, SampleFormat::S32Le)
+    }
+}
+
+impl fmt::Display for Microphone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Microphone::Sph0645 => "SPH0645",
+            Microphone::Inmp441 => "INMP441",
+        })
+    }
+}
+
+/// Sound levels over one stretch of samples, in dB SPL with no frequency
+/// weighting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SplReading {
+    /// The equivalent continuous level: the energy average of the whole
+    /// stretch.
+    pub leq: f32,
+    /// Quietest and loudest 125 ms within it.
+    pub min: f32,
+    pub max: f32,
+    pub samples: usize,
+}
+
+/// Turns samples from a [`Microphone`] into sound pressure levels, for
+/// noise logging rather than certified measurement: it is only as good as
+/// the datasheet sensitivity, unless calibrated against a real meter.
+pub struct SplMeter {
+    /// dB SPL less dBFS, from the sensitivity.
+    offset: f32,
+    calibration: f32,
+    fast_len: usize,
+    dc: DcBlocker,
+    total: f64,
+    count: usize,
+    fast: f64,
+    fast_count: usize,
+    min: Option<f32>,
+    max: Option<f32>,
+}
+
+impl SplMeter {
+    pub fn new(mic: Microphone, rate: u32) -> Self {
+        SplMeter {
+            offset: 94.0 - mic.sensitivity_dbfs(),
+            calibration: 0.0,
+            fast_len: ((f64::from(rate) * FAST_SECS) as usize).max(1),
+            dc: DcBlocker::new(rate),
+            total: 0.0,
+            count: 0,
+            fast: 0.0,
+            fast_count: 0,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// Add `db` to every reading, found by holding the microphone next to a
+    /// sound level meter.
+    pub fn set_calibration(&mut self, db: f32) {
+        self.calibration = db;
+    }
+
+    /// Samples scaled to -1.0..1.0, as [`Capture::read`] gives them.
+    pub fn feed(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let sample = self.dc.filter(f64::from(sample));
+            let square = sample * sample;
+            self.total += square;
+            self.count += 1;
+            self.fast += square;
+            self.fast_count += 1;
+            if self.fast_count == self.fast_len {
+                let level = self.level(self.fast / self.fast_count as f64);
+                self.min = Some(self.min.map_or(level, |m| m.min(level)));
+                self.max = Some(self.max.map_or(level, |m| m.max(level)));
+                self.fast = 0.0;
+                self.fast_count = 0;
+            }
+        }
+    }
+
+    /// The levels since the last call, or `None` if nothing has been fed.
+    pub fn take(&mut self) -> Option<SplReading> {
+        if self.count == 0 {
+            return None;
+        }
+        let leq = self.level(self.total / self.count as f64);
+        let reading = SplReading {
+            leq,
+            // Under 125 ms of samples has no fast level of its own
+            min: self.min.unwrap_or(leq),
+            max: self.max.unwrap_or(leq),
+            samples: self.count,
+        };
+        self.total = 0.0;
+        self.count = 0;
+        self.min = None;
+        self.max = None;
+        Some(reading)
+    }
+
+    /// dB SPL for a mean square; full scale is a sine peaking at 1.0, whose
+    /// mean square is 1/2.
+    fn level(&self, mean_square: f64) -> f32 {
+        let dbfs = 10.0 * (mean_square * 2.0).max(1e-20).log10();
+        dbfs as f32 + self.offset + self.calibration
+    }
+}
+
+/// One-pole high-pass: takes out the DC offset and the wind rumble below
+/// [`DC_CUTOFF_HZ`] that would otherwise count as sound.
+struct DcBlocker {
+    pole: f64,
+    /// `None` until the first sample, so the offset doesn't start as a step.
+    last_in: Option<f64>,
+    last_out: f64,
+}
+
+impl DcBlocker {
+    fn new(rate: u32) -> Self {
+        DcBlocker {
+            pole: 1.0 - 2.0 * PI * DC_CUTOFF_HZ / f64::from(rate.max(1)),
+            last_in: None,
+            last_out: 0.0,
+        }
+    }
+
+    fn filter(&mut self, sample: f64) -> f64 {
+        let out = sample - self.last_in.unwrap_or(sample) + self.pole * self.last_out;
+        self.last_in = Some(sample);
+        self.last_out = out;
+        out
+    }
+}
@@ -0,0 +1,244 @@
+//! Token authentication for the network API.
+//!
+//! Each token carries a scope: `read` may only observe (scans, readings,
+//! event streams), `control` may also drive the hardware (bus writes, GPIO,
+//! relays). Requests present `Authorization: Bearer <token>`.
+//!
+//! Tokens live in a file with one `<token> <scope>` pair per line; `#` starts
+//! a comment. The file should be readable only by the service user.
+//!
+//! Without TLS, tokens and readings cross the network in the clear. `serve`
+//! speaks HTTPS when given `--tls-cert` and `--tls-key`; without them it
+//! only listens on loopback unless told `--allow-plaintext`, and is reached
+//! from elsewhere through an SSH tunnel.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Whether the `--bind` address `host` only accepts connections from this
+/// machine: `localhost`, 127.0.0.0/8 or `::1`.
+pub fn is_loopback(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// What a token is allowed to do. `Control` implies `Read`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Control,
+}
+
+impl Scope {
+    /// Scope an HTTP request needs: anything that isn't a plain read is control.
+    pub fn required_for(method: &str) -> Scope {
+        match method {
+            "GET" | "HEAD" | "OPTIONS" => Scope::Read,
+            _ => Scope::Control,
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" | "read-only" => Ok(Scope::Read),
+            "control" => Ok(Scope::Control),
+            _ => Err(format!("unknown scope '{}' (expected read or control)", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::Read => "read",
+            Scope::Control => "control",
+        })
+    }
+}
+
+/// Why a request was turned away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// No or malformed credentials (HTTP 401).
+    Missing,
+    /// Token not recognised (HTTP 401).
+    Invalid,
+    /// Valid token, but its scope is too narrow (HTTP 403).
+    Forbidden { have: Scope, need: Scope },
+}
+
+impl AuthError {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            AuthError::Missing | AuthError::Invalid => 401,
+            AuthError::Forbidden { .. } => 403,
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => write!(f, "missing bearer token"),
+            AuthError::Invalid => write!(f, "invalid token"),
+            AuthError::Forbidden { have, need } => {
+                write!(f, "token has {} scope, {} required", have, need)
+            }
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+#[derive(Default)]
+pub struct TokenStore {
+    tokens: Vec<(String, Scope)>,
+}
+
+impl TokenStore {
+    pub fn new() -> Self {
+        TokenStore::default()
+    }
+
+    pub fn add(&mut self, token: impl Into<String>, scope: Scope) {
+        self.tokens.push((token.into(), scope));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Load a token file, refusing files other users can read.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        check_permissions(path)?;
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Check an `Authorization` header value against the scope a request needs.
+    pub fn authorize(&self, header: Option<&str>, need: Scope) -> Result<Scope, AuthError> {
+        let token = header
+            .and_then(|h| h.trim().strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .ok_or(AuthError::Missing)?;
+
+        // Compare against every token so timing doesn't reveal which one matched.
+        let mut found = None;
+        for (known, scope) in &self.tokens {
+            if constant_time_eq(known.as_bytes(), token.as_bytes()) {
+                found = Some(*scope);
+            }
+        }
+        let have = found.ok_or(AuthError::Invalid)?;
+        if have < need {
+            return Err(AuthError::Forbidden { have, need });
+        }
+        Ok(have)
+    }
+}
+
+impl FromStr for TokenStore {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut store = TokenStore::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(token), Some(scope), None) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(format!("token file line {}: expected '<token> <scope>'", n + 1).into());
+            };
+            store.add(token, scope.parse()?);
+        }
+        Ok(store)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "{} is accessible by other users (mode {:o}); run chmod 600 on it",
+            path.display(),
+            mode & 0o777
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> TokenStore {
+        "viewer read\nadmin control # the bench laptop\n".parse().unwrap()
+    }
+
+    #[test]
+    fn missing_and_malformed_headers() {
+        let tokens = store();
+        assert_eq!(tokens.authorize(None, Scope::Read), Err(AuthError::Missing));
+        for header in ["", "Bearer ", "Bearer    ", "admin", "Basic YWRtaW46eA==", "bearer admin", "Bearer"] {
+            assert_eq!(tokens.authorize(Some(header), Scope::Read), Err(AuthError::Missing), "{:?}", header);
+        }
+        assert_eq!(AuthError::Missing.status_code(), 401);
+    }
+
+    #[test]
+    fn wrong_tokens_are_invalid() {
+        let tokens = store();
+        for header in ["Bearer admin2", "Bearer admi", "Bearer viewer read", "Bearer ADMIN"] {
+            assert_eq!(tokens.authorize(Some(header), Scope::Read), Err(AuthError::Invalid), "{:?}", header);
+        }
+        assert_eq!(TokenStore::new().authorize(Some("Bearer admin"), Scope::Read), Err(AuthError::Invalid));
+        assert_eq!(AuthError::Invalid.status_code(), 401);
+    }
+
+    #[test]
+    fn correct_tokens_get_their_scope() {
+        let tokens = store();
+        assert_eq!(tokens.authorize(Some("Bearer admin"), Scope::Control), Ok(Scope::Control));
+        assert_eq!(tokens.authorize(Some(" Bearer  admin "), Scope::Read), Ok(Scope::Control));
+        assert_eq!(tokens.authorize(Some("Bearer viewer"), Scope::Read), Ok(Scope::Read));
+        let refused = tokens.authorize(Some("Bearer viewer"), Scope::required_for("POST"));
+        assert_eq!(refused, Err(AuthError::Forbidden { have: Scope::Read, need: Scope::Control }));
+        assert_eq!(refused.unwrap_err().status_code(), 403);
+    }
+
+    #[test]
+    fn loopback_binds() {
+        for host in ["127.0.0.1", "127.1.2.3", "::1", "[::1]", "localhost"] {
+            assert!(is_loopback(host), "{}", host);
+        }
+        for host in ["0.0.0.0", "::", "192.168.1.20", "pi.local", ""] {
+            assert!(!is_loopback(host), "{}", host);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Throughput and error rate at each clock speed, for finding how fast a
+//! given cable run can go before the bus degrades.
+//!
+//! [`run`] switches the bus to each speed in turn and then moves
+//! [`BenchConfig::size`]-byte transfers to one device back to back for
+//! [`BenchConfig::duration`], counting NACKs and other failures. A long or
+//! badly terminated run shows up as a falling efficiency (bytes/s against
+//! the nine bit times each byte takes on the wire) and then as errors.
+//!
+//! Runtime speed changes need a bus that supports them, which in practice
+//! means `--soft-i2c`.
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use crate::metrics::is_nack;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BenchMode {
+    /// Read `size` bytes; disturbs nothing on most chips.
+    #[default]
+    Read,
+    /// Write `size` counting bytes; only for a device where that's harmless.
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchConfig {
+    pub address: Address,
+    pub speeds: Vec<u32>,
+    /// Bytes per transfer.
+    pub size: usize,
+    /// How long to run at each speed.
+    pub duration: Duration,
+    pub mode: BenchMode,
+}
+
+impl BenchConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.speeds.is_empty() {
+            return Err("bench needs at least one speed".into());
+        }
+        if let Some(hz) = self.speeds.iter().find(|&&hz| hz == 0) {
+            return Err(format!("bench speed {} Hz must be greater than zero", hz).into());
+        }
+        if !(1..=4096).contains(&self.size) {
+            return Err(format!("bench transfer size {} out of range (1-4096)", self.size).into());
+        }
+        if self.duration.is_zero() {
+            return Err("bench duration must be greater than zero".into());
+        }
+        Ok(())
+    }
+}
+
+/// What one speed managed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpeedResult {
+    pub speed: u32,
+    pub transfers: u64,
+    /// Bytes in transfers that succeeded.
+    pub bytes: u64,
+    pub nacks: u64,
+    /// Failures other than NACKs: timeouts, arbitration loss, stuck lines.
+    pub errors: u64,
+    pub elapsed: Duration,
+}
+
+impl SpeedResult {
+    pub fn failures(&self) -> u64 {
+        self.nacks + self.errors
+    }
+
+    pub fn bytes_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Failed transfers as a fraction of all of them.
+    pub fn error_rate(&self) -> f64 {
+        if self.transfers == 0 {
+            return 0.0;
+        }
+        self.failures() as f64 / self.transfers as f64
+    }
+
+    /// Throughput against the clock's own limit of one byte per nine bit
+    /// times, ignoring addressing and START/STOP.
+    pub fn efficiency(&self) -> f64 {
+        self.bytes_per_second() * 9.0 / f64::from(self.speed)
+    }
+}
+
+impl fmt::Display for SpeedResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>8} Hz {:>12.0} {:>6.1}% {:>9} {:>6} {:>6} {:>8.3}%",
+            self.speed,
+            self.bytes_per_second(),
+            self.efficiency() * 100.0,
+            self.transfers,
+            self.nacks,
+            self.errors,
+            self.error_rate() * 100.0
+        )
+    }
+}
+
+/// Column headings lining up with [`SpeedResult`]'s `Display`.
+pub const TABLE_HEADER: &str = "      speed      bytes/s    eff. transfers  NACKs errors    failed";
+
+/// The fastest speed with no failures, if any was clean.
+pub fn fastest_clean(results: &[SpeedResult]) -> Option<u32> {
+    results.iter().filter(|r| r.transfers > 0 && r.failures() == 0).map(|r| r.speed).max()
+}
+
+/// Bench every speed in `config.speeds`, in order, until done or `stop` is
+/// set. `each` gets every result as it is finished. The bus is put back to
+/// the speed it started at, if it can say what that was.
+pub fn run<I2C, F>(i2c: &mut I2C, config: &BenchConfig, stop: &AtomicBool, mut each: F) -> Result<Vec<SpeedResult>, Box<dyn Error>>
+where
+    I2C: AddressedI2c + BusControl,
+    F: FnMut(&SpeedResult),
+{
+    config.validate()?;
+    let original = i2c.clock_speed().ok();
+    let mut results = Vec::with_capacity(config.speeds.len());
+    let outcome = (|| {
+        for &speed in &config.speeds {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            set_speed(i2c, speed)?;
+            let result = bench_speed(i2c, config, speed, stop);
+            each(&result);
+            results.push(result);
+        }
+        Ok::<_, Box<dyn Error>>(())
+    })();
+    if let Some(hz) = original {
+        let _ = i2c.set_clock_speed(hz);
+    }
+    outcome?;
+    Ok(results)
+}
+
+/// A bus that can't change speed is still fine at the speed it's at.
+fn set_speed<I2C: BusControl>(i2c: &mut I2C, speed: u32) -> Result<(), Box<dyn Error>> {
+    match i2c.set_clock_speed(speed) {
+        Ok(()) => Ok(()),
+        Err(_) if i2c.clock_speed().is_ok_and(|hz| hz == speed) => Ok(()),
+        Err(e) => Err(format!("bench at {} Hz: {}", speed, e).into()),
+    }
+}
+
+fn bench_speed<I2C: AddressedI2c>(i2c: &mut I2C, config: &BenchConfig, speed: u32, stop: &AtomicBool) -> SpeedResult {
+    let payload: Vec<u8> = (0..config.size).map(|i| i as u8).collect();
+    let mut buf = vec![0; config.size];
+    let mut result = SpeedResult {
+        speed,
+        ..SpeedResult::default()
+    };
+    let start = Instant::now();
+    while start.elapsed() < config.duration && !stop.load(Ordering::Relaxed) {
+        let outcome = match config.mode {
+            BenchMode::Read => i2c.read_at(config.address, &mut buf),
+            BenchMode::Write => i2c.write_at(config.address, &payload),
+        };
+        result.transfers += 1;
+        match outcome {
+            Ok(()) => result.bytes += config.size as u64,
+            Err(e) if is_nack(e.as_ref()) => result.nacks += 1,
+            Err(_) => result.errors += 1,
+        }
+    }
+    result.elapsed = start.elapsed();
+    result
+}
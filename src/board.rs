@@ -0,0 +1,272 @@
+//! Which Raspberry Pi this is, and what its pins can do.
+//!
+//! [`Board::detect`] reads the model string and revision code from the
+//! device tree. The revision code's processor field picks the [`Soc`], and
+//! that decides the defaults the rest of the crate uses: which I2C buses
+//! exist and on which pins ([`Board::i2c_buses`]), how many hardware PWM
+//! channels there are, and whether the header has 26 or 40 pins.
+//! [`Board::current`] caches the result for the process.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::sync::OnceLock;
+
+const MODEL_PATH: &str = "/proc/device-tree/model";
+const REVISION_PATH: &str = "/proc/device-tree/system/linux,revision";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Soc {
+    Bcm2835,
+    Bcm2836,
+    Bcm2837,
+    Bcm2711,
+    Bcm2712,
+}
+
+impl fmt::Display for Soc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Soc::Bcm2835 => "BCM2835",
+            Soc::Bcm2836 => "BCM2836",
+            Soc::Bcm2837 => "BCM2837",
+            Soc::Bcm2711 => "BCM2711",
+            Soc::Bcm2712 => "BCM2712",
+        })
+    }
+}
+
+/// An I2C controller the board has, and what it takes to use it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2cBus {
+    /// `/dev/i2c-N` once enabled.
+    pub bus: u8,
+    pub sda: u8,
+    pub scl: u8,
+    /// The `config.txt` line that enables it on its default pins.
+    pub enable: &'static str,
+}
+
+/// A hardware PWM channel and the GPIOs it can be routed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PwmChannel {
+    pub channel: u8,
+    pub pins: &'static [u8],
+}
+
+const I2C_CLASSIC: &[I2cBus] = &[
+    I2cBus { bus: 0, sda: 0, scl: 1, enable: "dtparam=i2c_vc=on" },
+    I2cBus { bus: 1, sda: 2, scl: 3, enable: "dtparam=i2c_arm=on" },
+];
+
+const I2C_BCM2711: &[I2cBus] = &[
+    I2cBus { bus: 0, sda: 0, scl: 1, enable: "dtparam=i2c_vc=on" },
+    I2cBus { bus: 1, sda: 2, scl: 3, enable: "dtparam=i2c_arm=on" },
+    I2cBus { bus: 3, sda: 4, scl: 5, enable: "dtoverlay=i2c3" },
+    I2cBus { bus: 4, sda: 8, scl: 9, enable: "dtoverlay=i2c4" },
+    I2cBus { bus: 5, sda: 12, scl: 13, enable: "dtoverlay=i2c5" },
+    I2cBus { bus: 6, sda: 22, scl: 23, enable: "dtoverlay=i2c6" },
+];
+
+const I2C_BCM2712: &[I2cBus] = &[
+    I2cBus { bus: 0, sda: 0, scl: 1, enable: "dtoverlay=i2c0-pi5" },
+    I2cBus { bus: 1, sda: 2, scl: 3, enable: "dtparam=i2c_arm=on" },
+    I2cBus { bus: 2, sda: 4, scl: 5, enable: "dtoverlay=i2c2-pi5" },
+    I2cBus { bus: 3, sda: 6, scl: 7, enable: "dtoverlay=i2c3-pi5" },
+];
+
+const PWM_CLASSIC: &[PwmChannel] = &[
+    PwmChannel { channel: 0, pins: &[12, 18] },
+    PwmChannel { channel: 1, pins: &[13, 19] },
+];
+
+const PWM_BCM2712: &[PwmChannel] = &[
+    PwmChannel { channel: 0, pins: &[12] },
+    PwmChannel { channel: 1, pins: &[13] },
+    PwmChannel { channel: 2, pins: &[18] },
+    PwmChannel { channel: 3, pins: &[19] },
+];
+
+/// GPIOs on the original Model A/B's 26-pin header (revision 2 numbering).
+const HEADER_26: &[u8] = &[2, 3, 4, 7, 8, 9, 10, 11, 14, 15, 17, 18, 22, 23, 24, 25, 27];
+
+/// What's on one pin of the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderPin {
+    Gpio(u8),
+    /// A supply rail, `3.3V` or `5V`.
+    Power(&'static str),
+    Ground,
+}
+
+/// Physical pins 1 to 40, in order; a 26-pin header is the first 26.
+const HEADER_LAYOUT: [HeaderPin; 40] = [
+    HeaderPin::Power("3.3V"), HeaderPin::Power("5V"),
+    HeaderPin::Gpio(2), HeaderPin::Power("5V"),
+    HeaderPin::Gpio(3), HeaderPin::Ground,
+    HeaderPin::Gpio(4), HeaderPin::Gpio(14),
+    HeaderPin::Ground, HeaderPin::Gpio(15),
+    HeaderPin::Gpio(17), HeaderPin::Gpio(18),
+    HeaderPin::Gpio(27), HeaderPin::Ground,
+    HeaderPin::Gpio(22), HeaderPin::Gpio(23),
+    HeaderPin::Power("3.3V"), HeaderPin::Gpio(24),
+    HeaderPin::Gpio(10), HeaderPin::Ground,
+    HeaderPin::Gpio(9), HeaderPin::Gpio(25),
+    HeaderPin::Gpio(11), HeaderPin::Gpio(8),
+    HeaderPin::Ground, HeaderPin::Gpio(7),
+    HeaderPin::Gpio(0), HeaderPin::Gpio(1),
+    HeaderPin::Gpio(5), HeaderPin::Ground,
+    HeaderPin::Gpio(6), HeaderPin::Gpio(12),
+    HeaderPin::Gpio(13), HeaderPin::Ground,
+    HeaderPin::Gpio(19), HeaderPin::Gpio(16),
+    HeaderPin::Gpio(26), HeaderPin::Gpio(20),
+    HeaderPin::Ground, HeaderPin::Gpio(21),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    /// As the firmware reports it, e.g. `Raspberry Pi 4 Model B Rev 1.4`.
+    pub model: String,
+    /// Revision code, e.g. `0xc03114`.
+    pub revision: Option<u32>,
+    pub soc: Soc,
+    /// RAM in MB, from new-style revision codes.
+    pub memory_mb: Option<u32>,
+    /// Number of header pins.
+    pub header: u8,
+}
+
+impl Board {
+    /// Read the model and revision from the device tree.
+    pub fn detect() -> Result<Self, Box<dyn Error>> {
+        let model = fs::read_to_string(MODEL_PATH)
+            .map_err(|e| format!("{}: {} (not a Raspberry Pi?)", MODEL_PATH, e))?;
+        let revision = fs::read(REVISION_PATH)
+            .ok()
+            .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+            .map(u32::from_be_bytes);
+        Ok(Board::from_parts(model.trim_end_matches('\0').trim(), revision))
+    }
+
+    /// The board this process runs on, detected once; `None` off a Pi.
+    pub fn current() -> Option<&'static Board> {
+        static BOARD: OnceLock<Option<Board>> = OnceLock::new();
+        BOARD.get_or_init(|| Board::detect().ok()).as_ref()
+    }
+
+    /// Work out the rest from a model string and revision code.
+    pub fn from_parts(model: &str, revision: Option<u32>) -> Self {
+        // Bit 23 marks the new-style code with bit fields; old ones are all Pi 1
+        let fields = revision.filter(|r| r & (1 << 23) != 0);
+        let soc = match fields.map(|r| (r >> 12) & 0xF) {
+            Some(0) => Soc::Bcm2835,
+            Some(1) => Soc::Bcm2836,
+            Some(2) => Soc::Bcm2837,
+            Some(3) => Soc::Bcm2711,
+            Some(4) => Soc::Bcm2712,
+            _ => soc_from_model(model),
+        };
+        let memory_mb = fields.map(|r| 256 << ((r >> 20) & 0x7));
+        // Model A and B (old codes below 0x10, new-style types 0 and 1) have 26 pins
+        let header = match (revision, fields) {
+            (_, Some(r)) if (r >> 4) & 0xFF <= 1 => 26,
+            (Some(r), None) if r & 0xFFFF < 0x10 => 26,
+            _ => 40,
+        };
+        Board {
+            model: model.to_string(),
+            revision,
+            soc,
+            memory_mb,
+            header,
+        }
+    }
+
+    /// I2C controllers this board can bring out on the header.
+    pub fn i2c_buses(&self) -> &'static [I2cBus] {
+        match self.soc {
+            Soc::Bcm2711 => I2C_BCM2711,
+            Soc::Bcm2712 => I2C_BCM2712,
+            _ if self.header == 26 => &I2C_CLASSIC[1..],
+            _ => I2C_CLASSIC,
+        }
+    }
+
+    /// (SDA, SCL) for bus `id`, if it's one of this board's controllers.
+    pub fn i2c_pins(&self, id: u8) -> Option<(u8, u8)> {
+        self.i2c_buses().iter().find(|b| b.bus == id).map(|b| (b.sda, b.scl))
+    }
+
+    pub fn pwm_channels(&self) -> &'static [PwmChannel] {
+        match self.soc {
+            Soc::Bcm2712 => PWM_BCM2712,
+            _ => PWM_CLASSIC,
+        }
+    }
+
+    /// GPIOs reachable on the header.
+    pub fn header_gpios(&self) -> Vec<u8> {
+        if self.header == 26 {
+            HEADER_26.to_vec()
+        } else {
+            (0..=27).collect()
+        }
+    }
+
+    /// The header, physical pin 1 first.
+    pub fn header_pins(&self) -> &'static [HeaderPin] {
+        &HEADER_LAYOUT[..usize::from(self.header)]
+    }
+
+    /// The physical header pin `gpio` comes out on.
+    pub fn physical_pin(&self, gpio: u8) -> Option<u8> {
+        let index = self.header_pins().iter().position(|&p| p == HeaderPin::Gpio(gpio))?;
+        Some(index as u8 + 1)
+    }
+
+    /// What `pin` can be besides a plain GPIO here, e.g. `["SDA1"]`.
+    pub fn pin_functions(&self, pin: u8) -> Vec<String> {
+        let mut functions = Vec::new();
+        for bus in self.i2c_buses() {
+            if bus.sda == pin {
+                functions.push(format!("SDA{}", bus.bus));
+            }
+            if bus.scl == pin {
+                functions.push(format!("SCL{}", bus.bus));
+            }
+        }
+        for channel in self.pwm_channels() {
+            if channel.pins.contains(&pin) {
+                functions.push(format!("PWM{}", channel.channel));
+            }
+        }
+        let fixed = match pin {
+            7 => "SPI0 CE1",
+            8 => "SPI0 CE0",
+            9 => "SPI0 MISO",
+            10 => "SPI0 MOSI",
+            11 => "SPI0 SCLK",
+            14 => "UART TXD",
+            15 => "UART RXD",
+            _ => "",
+        };
+        if !fixed.is_empty() {
+            functions.push(fixed.to_string());
+        }
+        functions
+    }
+}
+
+fn soc_from_model(model: &str) -> Soc {
+    if model.contains("Pi 5") || model.contains("Compute Module 5") || model.contains("Pi 500") {
+        Soc::Bcm2712
+    } else if model.contains("Pi 4") || model.contains("Pi 400") || model.contains("Compute Module 4") {
+        Soc::Bcm2711
+    } else if model.contains("Pi 3") || model.contains("Zero 2") || model.contains("Compute Module 3") {
+        Soc::Bcm2837
+    } else if model.contains("Pi 2") {
+        Soc::Bcm2836
+    } else {
+        Soc::Bcm2835
+    }
+}
@@ -0,0 +1,306 @@
+//! Sharing one I2C bus between several devices.
+//!
+//! [`BusManager`] owns the bus behind a mutex. It hands out [`SharedBus`]
+//! clones for drivers that take an `embedded-hal` bus plus an address, and
+//! [`I2cDevice`] handles that are bound to one slave address. Every
+//! transaction locks the bus and sets the slave address for itself, so an
+//! LCD, a sensor and an RTC can be used from different threads.
+//!
+//! Each transaction also waits its turn with the manager's scheduler. A
+//! [`DevicePolicy`] caps a device's transactions per second and sets its
+//! [`Priority`], so a chatty sensor poller can't starve the LCD; what each
+//! device has used of the bus is in [`BusManager::usage`].
+//!
+//! [`open`] and [`available_buses`] cover the other buses a Pi can expose:
+//! bus 0 on the HAT pins and the software buses from `dtoverlay=i2c-gpio`.
+//! [`DryRun`] stands in for all of them when nothing should reach the wires.
+//!
+//! A [`Backend`] is how a bus gets opened: `rppal` on a Pi, [`LinuxI2c`]
+//! (feature `i2cdev`) on other boards with `/dev/i2c-*`, or [`StubBus`],
+//! an in-memory bus for tests and CI machines with no I2C at all.
+
+mod backend;
+mod discover;
+mod dry_run;
+#[cfg(feature = "i2cdev")]
+mod i2cdev;
+mod schedule;
+mod stub;
+
+pub use backend::{Backend, BackendKind};
+pub use discover::{available_buses, bus_path, open, BusInfo, BusNotFound};
+pub use dry_run::DryRun;
+#[cfg(feature = "i2cdev")]
+pub use i2cdev::{LinuxI2c, LinuxI2cError};
+pub use schedule::{DevicePolicy, DeviceUsage, Priority};
+pub use stub::{StubBus, StubError, StubTransaction};
+
+use crate::address::{Address, AddressedI2c};
+use crate::board::Board;
+use crate::metrics::Metrics;
+use crate::softi2c::{SoftI2c, SoftI2cConfig, Stretching};
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use rppal::i2c::I2c as RppalI2c;
+use schedule::Scheduler;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Bus settings that live outside `embedded-hal`.
+pub trait BusControl {
+    /// Clock speed the kernel configured for the bus, in Hz.
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>>;
+
+    /// Fail transactions that take longer than `timeout` (10 ms resolution).
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>>;
+
+    /// Change the clock at runtime. Hardware buses can't: the kernel fixes
+    /// their speed at boot from `dtparam=i2c_arm_baudrate`.
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        Err(format!("this bus can't switch to {} Hz at runtime; use --soft-i2c", hz).into())
+    }
+
+    /// Free a bus a slave is holding low mid-byte: clock SCL until it lets
+    /// go of SDA, then send STOP.
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        Err("this bus has no recovery sequence".into())
+    }
+
+    /// Clock stretching seen so far, on masters that watch SCL for it.
+    fn stretching(&self) -> Option<Stretching> {
+        None
+    }
+}
+
+impl BusControl for RppalI2c {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        Ok(RppalI2c::clock_speed(self)?)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let ms = u32::try_from(timeout.as_millis()).map_err(|_| "timeout too long")?;
+        RppalI2c::set_timeout(self, ms)?;
+        Ok(())
+    }
+
+    /// Borrows the pins from the controller as GPIOs for the sequence; they
+    /// go back to the I2C function when it's done.
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        recover_hardware(self.bus())
+    }
+}
+
+/// The recovery sequence on a hardware bus's pins, bit-banged.
+fn recover_hardware(bus: u8) -> Result<(), Box<dyn Error>> {
+    let (sda, scl) =
+        hardware_pins(bus).ok_or_else(|| format!("don't know which pins bus {} is on; use --soft-i2c to recover it", bus))?;
+    let mut pins = SoftI2c::from_gpio(sda, scl, SoftI2cConfig::default())?;
+    pins.recover()?;
+    Ok(())
+}
+
+/// GPIO (SDA, SCL) of a hardware bus, from the [`Board`] when it's known;
+/// otherwise just 0 on the HAT EEPROM pins and 1 on the header's I2C pins.
+pub fn hardware_pins(bus: u8) -> Option<(u8, u8)> {
+    if let Some(board) = Board::current() {
+        return board.i2c_pins(bus);
+    }
+    match bus {
+        0 => Some((0, 1)),
+        1 => Some((2, 3)),
+        _ => None,
+    }
+}
+
+/// Outcome of comparing a requested clock speed with the kernel's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedCheck {
+    pub requested: u32,
+    /// `None` when the bus can't report its speed.
+    pub actual: Option<u32>,
+}
+
+impl SpeedCheck {
+    pub fn matches(&self) -> bool {
+        self.actual == Some(self.requested)
+    }
+
+    /// How to get the requested speed, since userspace can't change it.
+    pub fn advice(&self) -> String {
+        format!(
+            "add 'dtparam=i2c_arm_baudrate={}' to /boot/firmware/config.txt and reboot",
+            self.requested
+        )
+    }
+}
+
+/// Compare `requested` against what the bus is actually running at.
+pub fn check_speed<B: BusControl>(bus: &B, requested: u32) -> SpeedCheck {
+    SpeedCheck {
+        requested,
+        actual: bus.clock_speed().ok(),
+    }
+}
+
+pub struct BusManager<I2C> {
+    bus: Arc<Mutex<I2C>>,
+    schedule: Arc<Scheduler>,
+}
+
+impl<I2C: I2c> BusManager<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        BusManager {
+            bus: Arc::new(Mutex::new(i2c)),
+            schedule: Arc::new(Scheduler::new()),
+        }
+    }
+
+    /// Clonable bus handle implementing [`I2c`].
+    pub fn shared(&self) -> SharedBus<I2C> {
+        SharedBus {
+            bus: Arc::clone(&self.bus),
+            schedule: Arc::clone(&self.schedule),
+        }
+    }
+
+    /// Limit and prioritize the device at `address`. Devices without a
+    /// policy get [`DevicePolicy::default`]: no limit, normal priority.
+    pub fn set_policy(&self, address: Address, policy: DevicePolicy) -> Result<(), Box<dyn Error>> {
+        policy.validate()?;
+        self.schedule.set_policy(address, policy);
+        Ok(())
+    }
+
+    /// Export each device's bus time and utilization to `metrics`.
+    pub fn set_metrics(&self, metrics: Metrics) {
+        self.schedule.set_metrics(metrics);
+    }
+
+    /// Bus time per device so far, in address order.
+    pub fn usage(&self) -> Vec<DeviceUsage> {
+        self.schedule.usage()
+    }
+
+    /// Handle for the device at `address`.
+    pub fn device(&self, address: u8) -> I2cDevice<I2C> {
+        I2cDevice {
+            bus: self.shared(),
+            address,
+        }
+    }
+
+    /// Run `f` with exclusive access to the underlying bus, e.g. to use
+    /// rppal-specific calls such as `clock_speed()`.
+    pub fn with_bus<R>(&self, f: impl FnOnce(&mut I2C) -> R) -> R {
+        let mut bus = self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut bus)
+    }
+}
+
+/// A clone of the managed bus. Cheap to clone; each transaction takes the lock.
+pub struct SharedBus<I2C> {
+    bus: Arc<Mutex<I2C>>,
+    schedule: Arc<Scheduler>,
+}
+
+impl<I2C> Clone for SharedBus<I2C> {
+    fn clone(&self) -> Self {
+        SharedBus {
+            bus: Arc::clone(&self.bus),
+            schedule: Arc::clone(&self.schedule),
+        }
+    }
+}
+
+impl<I2C: I2c> ErrorType for SharedBus<I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> I2c for SharedBus<I2C> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.schedule.run(Address::SevenBit(address), || {
+            let mut bus = self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            bus.transaction(address, operations)
+        })
+    }
+}
+
+impl<I2C: AddressedI2c> AddressedI2c for SharedBus<I2C> {
+    fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Box<dyn Error>> {
+        self.schedule.run(address, || {
+            let mut bus = self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            bus.transaction_at(address, operations)
+        })
+    }
+
+    fn repeated_start(&self, address: Address) -> bool {
+        self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).repeated_start(address)
+    }
+}
+
+impl<I2C: BusControl> BusControl for SharedBus<I2C> {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clock_speed()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).set_timeout(timeout)
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).set_clock_speed(hz)
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).recover()
+    }
+
+    fn stretching(&self) -> Option<Stretching> {
+        self.bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).stretching()
+    }
+}
+
+/// One slave on the managed bus.
+#[derive(Clone)]
+pub struct I2cDevice<I2C> {
+    bus: SharedBus<I2C>,
+    address: u8,
+}
+
+impl<I2C> I2cDevice<I2C>
+where
+    I2C: I2c,
+    I2C::Error: Error + 'static,
+{
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.bus.write(self.address, bytes)?;
+        Ok(())
+    }
+
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.bus.read(self.address, buffer)?;
+        Ok(())
+    }
+
+    pub fn write_read(&mut self, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.bus.write_read(self.address, bytes, buffer)?;
+        Ok(())
+    }
+
+    /// The shared bus this device talks through, for handing to a driver.
+    pub fn bus(&self) -> SharedBus<I2C> {
+        self.bus.clone()
+    }
+}
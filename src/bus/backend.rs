@@ -0,0 +1,107 @@
+use super::{BusControl, StubBus};
+use crate::address::AddressedI2c;
+use embedded_hal::i2c::I2c;
+use rppal::i2c::I2c as RppalI2c;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A way of reaching bus N: through `rppal`, straight through the kernel's
+/// i2c-dev ioctls, or not at all.
+///
+/// Code that opens its own bus can take the backend as a type parameter
+/// and be run against [`StubBus`] in tests:
+///
+/// ```no_run
+/// use rpi_peripherals::bus::Backend;
+///
+/// fn open_lcd_bus<B: Backend>() -> Result<B, Box<dyn std::error::Error>> {
+///     let bus = B::open(1)?;
+///     println!("bus 1 through {}", B::NAME);
+///     Ok(bus)
+/// }
+/// ```
+pub trait Backend: I2c + AddressedI2c + BusControl + Send + Sized {
+    /// What `--backend` calls it.
+    const NAME: &'static str;
+
+    fn open(bus: u8) -> Result<Self, Box<dyn Error>>;
+}
+
+/// Works on every Pi and, since `rppal` is plain Rust over `/dev`, builds
+/// for any Linux target.
+impl Backend for RppalI2c {
+    const NAME: &'static str = "rppal";
+
+    fn open(bus: u8) -> Result<Self, Box<dyn Error>> {
+        super::open(bus)
+    }
+}
+
+/// An empty bus where every address NACKs; add devices to it with
+/// [`StubBus::add_map`].
+impl Backend for StubBus {
+    const NAME: &'static str = "stub";
+
+    fn open(_bus: u8) -> Result<Self, Box<dyn Error>> {
+        Ok(StubBus::new())
+    }
+}
+
+#[cfg(feature = "i2cdev")]
+impl Backend for super::LinuxI2c {
+    const NAME: &'static str = "i2cdev";
+
+    fn open(bus: u8) -> Result<Self, Box<dyn Error>> {
+        super::LinuxI2c::open(bus)
+    }
+}
+
+/// A [`Backend`] picked at runtime.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    #[default]
+    Rppal,
+    /// `/dev/i2c-N` through the kernel's ioctls, for boards `rppal` doesn't
+    /// know. Needs the `i2cdev` feature.
+    I2cdev,
+    Stub,
+}
+
+impl BackendKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BackendKind::Rppal => RppalI2c::NAME,
+            BackendKind::I2cdev => "i2cdev",
+            BackendKind::Stub => StubBus::NAME,
+        }
+    }
+
+    /// Whether this build can open it.
+    pub fn is_available(self) -> bool {
+        self != BackendKind::I2cdev || cfg!(feature = "i2cdev")
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let kind = match s {
+            "rppal" => BackendKind::Rppal,
+            "i2cdev" | "linux-i2cdev" => BackendKind::I2cdev,
+            "stub" => BackendKind::Stub,
+            other => return Err(format!("unknown backend '{}' (rppal, i2cdev, stub)", other).into()),
+        };
+        if !kind.is_available() {
+            return Err(format!("this build has no {} backend; rebuild with the `{}` feature", kind, kind).into());
+        }
+        Ok(kind)
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
@@ -0,0 +1,101 @@
+use rppal::i2c::I2c as RppalI2c;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+const I2C_DEV_CLASS: &str = "/sys/class/i2c-dev";
+
+/// A `/dev/i2c-N` node and what the kernel says about its adapter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusInfo {
+    pub id: u8,
+    pub path: PathBuf,
+    /// Adapter name, e.g. `bcm2835 (i2c@7e804000)` or `i2c-gpio-3`.
+    pub name: Option<String>,
+    /// Clock speed from the device tree, in Hz. Not reported by every adapter.
+    pub clock_speed: Option<u32>,
+}
+
+impl BusInfo {
+    /// True for buses created with `dtoverlay=i2c-gpio`.
+    pub fn is_software(&self) -> bool {
+        self.name.as_deref().is_some_and(|n| n.starts_with("i2c-gpio"))
+    }
+}
+
+/// Device node for bus `id`.
+pub fn bus_path(id: u8) -> PathBuf {
+    PathBuf::from(format!("/dev/i2c-{}", id))
+}
+
+/// Every I2C bus the kernel exposes, sorted by number.
+pub fn available_buses() -> Vec<BusInfo> {
+    let mut buses = Vec::new();
+    let Ok(entries) = fs::read_dir("/dev") else {
+        return buses;
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let Some(id) = file_name
+            .to_str()
+            .and_then(|n| n.strip_prefix("i2c-"))
+            .and_then(|n| n.parse::<u8>().ok())
+        else {
+            continue;
+        };
+        let sysfs = format!("{}/i2c-{}", I2C_DEV_CLASS, id);
+        buses.push(BusInfo {
+            id,
+            path: entry.path(),
+            name: fs::read_to_string(format!("{}/name", sysfs))
+                .ok()
+                .map(|s| s.trim().to_string()),
+            clock_speed: read_clock_frequency(id),
+        });
+    }
+    buses.sort_by_key(|b| b.id);
+    buses
+}
+
+/// `clock-frequency` is a big-endian u32 in the adapter's device tree node.
+fn read_clock_frequency(id: u8) -> Option<u32> {
+    let path = format!("/sys/class/i2c-adapter/i2c-{}/of_node/clock-frequency", id);
+    let bytes: [u8; 4] = fs::read(path).ok()?.try_into().ok()?;
+    Some(u32::from_be_bytes(bytes))
+}
+
+/// Open bus `id`, failing with [`BusNotFound`] if its device node is missing.
+pub fn open(id: u8) -> Result<RppalI2c, Box<dyn Error>> {
+    if !bus_path(id).exists() {
+        let available = available_buses().into_iter().map(|b| b.id).collect();
+        return Err(BusNotFound { id, available }.into());
+    }
+    Ok(RppalI2c::with_bus(id)?)
+}
+
+/// The requested `/dev/i2c-N` doesn't exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusNotFound {
+    pub id: u8,
+    pub available: Vec<u8>,
+}
+
+impl fmt::Display for BusNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} does not exist", bus_path(self.id).display())?;
+        if self.available.is_empty() {
+            write!(f, " (no I2C buses found; enable one with raspi-config or dtparam=i2c_arm=on)")
+        } else {
+            let ids: Vec<String> = self.available.iter().map(u8::to_string).collect();
+            write!(
+                f,
+                " (available: {}; extra buses need dtoverlay=i2c-gpio,bus={})",
+                ids.join(", "),
+                self.id
+            )
+        }
+    }
+}
+
+impl Error for BusNotFound {}
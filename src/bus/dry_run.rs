@@ -0,0 +1,108 @@
+use super::BusControl;
+use crate::address::{Address, AddressedI2c};
+use crate::clock::{self, Clock};
+use crate::say;
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use std::convert::Infallible;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A bus that touches no hardware and prints every transaction instead,
+/// with the time since the previous one so the delays a driver puts
+/// between writes show up too:
+///
+/// ```text
+/// 🧪 +0.0ms    0x27   write [30]
+/// 🧪 +4.6ms    0x27   write [34 30]
+/// 🧪 +0.2ms    0x68   write [00] read 3
+/// ```
+///
+/// Every address ACKs and reads come back as zeros, so code that branches
+/// on what it reads takes the same path it would on a blank device.
+pub struct DryRun {
+    clock: u32,
+    last: Option<Instant>,
+    time: Arc<dyn Clock>,
+}
+
+impl DryRun {
+    /// `clock` is what [`BusControl::clock_speed`] reports.
+    pub fn new(clock: u32) -> Self {
+        DryRun {
+            clock,
+            last: None,
+            time: clock::system(),
+        }
+    }
+
+    /// Take the gaps between transactions from `time`, so a simulated run
+    /// prints the same every time.
+    pub fn set_clock(&mut self, time: Arc<dyn Clock>) {
+        self.time = time;
+        self.last = None;
+    }
+
+    fn print(&mut self, address: Address, operations: &mut [Operation<'_>]) {
+        let now = self.time.now();
+        let gap = self.last.map_or(Duration::ZERO, |last| now - last);
+        self.last = Some(now);
+
+        let ops: Vec<String> = operations
+            .iter_mut()
+            .map(|op| match op {
+                Operation::Write(bytes) => format!("write [{}]", hex(bytes)),
+                Operation::Read(buf) => {
+                    buf.fill(0);
+                    format!("read {}", buf.len())
+                }
+            })
+            .collect();
+        let gap = format!("+{:.1}ms", gap.as_secs_f64() * 1e3);
+        let address = address.to_string();
+        say!("🧪 {:<9} {:<6} {}", gap, address, ops.join(" "));
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+impl ErrorType for DryRun {
+    type Error = Infallible;
+}
+
+impl I2c for DryRun {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Infallible> {
+        self.print(Address::SevenBit(address), operations);
+        Ok(())
+    }
+}
+
+impl AddressedI2c for DryRun {
+    fn transaction_at(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+        self.print(address, operations);
+        Ok(())
+    }
+}
+
+impl BusControl for DryRun {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        Ok(self.clock)
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        say!("🧪 clock → {} Hz", hz);
+        self.clock = hz;
+        Ok(())
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        say!("🧪 bus recovery: 9 clocks, then STOP");
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use super::{available_buses, bus_path, recover_hardware, BusControl, BusNotFound};
+use crate::address::{Address, AddressedI2c};
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+// From linux/i2c-dev.h and linux/i2c.h.
+const I2C_TIMEOUT: u32 = 0x0702;
+const I2C_FUNCS: u32 = 0x0705;
+const I2C_RDWR: u32 = 0x0707;
+const I2C_FUNC_I2C: libc::c_ulong = 0x0000_0001;
+const I2C_FUNC_10BIT_ADDR: libc::c_ulong = 0x0000_0002;
+const I2C_M_RD: u16 = 0x0001;
+const I2C_M_TEN: u16 = 0x0010;
+/// `I2C_RDWR_IOCTL_MAX_MSGS`: the most messages one transfer can carry.
+const MAX_MSGS: usize = 42;
+
+#[repr(C)]
+struct I2cMsg {
+    addr: u16,
+    flags: u16,
+    len: u16,
+    buf: *mut u8,
+}
+
+#[repr(C)]
+struct I2cRdwrIoctlData {
+    msgs: *mut I2cMsg,
+    nmsgs: u32,
+}
+
+/// A failed i2c-dev call on bus `bus`.
+#[derive(Debug)]
+pub struct LinuxI2cError {
+    pub bus: u8,
+    pub source: io::Error,
+}
+
+impl fmt::Display for LinuxI2cError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", bus_path(self.bus).display(), self.source)
+    }
+}
+
+impl Error for LinuxI2cError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl i2c::Error for LinuxI2cError {
+    fn kind(&self) -> ErrorKind {
+        match self.source.raw_os_error() {
+            Some(libc::ENXIO) | Some(libc::EREMOTEIO) => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Some(libc::EAGAIN) => ErrorKind::ArbitrationLoss,
+            Some(libc::EIO) => ErrorKind::Bus,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// `/dev/i2c-N` through the kernel's `I2C_RDWR` ioctl, with no help from
+/// `rppal`: the backend for other boards with an i2c-dev node, such as a
+/// Rock Pi, an Orange Pi or a PC's SMBus adapter with a full I2C driver.
+///
+/// Each transaction is one `I2C_RDWR` call, so its writes and reads are
+/// joined by repeated STARTs exactly as `embedded-hal` asks.
+pub struct LinuxI2c {
+    file: File,
+    bus: u8,
+    functions: libc::c_ulong,
+}
+
+impl LinuxI2c {
+    /// Open bus `bus`, failing with [`BusNotFound`] if its device node is
+    /// missing, or if its adapter only does SMBus transfers.
+    pub fn open(bus: u8) -> Result<Self, Box<dyn Error>> {
+        let path = bus_path(bus);
+        if !path.exists() {
+            let available = available_buses().into_iter().map(|b| b.id).collect();
+            return Err(BusNotFound { id: bus, available }.into());
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|source| LinuxI2cError { bus, source })?;
+        let mut i2c = LinuxI2c { file, bus, functions: 0 };
+        let mut functions: libc::c_ulong = 0;
+        i2c.ioctl(I2C_FUNCS, &mut functions as *mut libc::c_ulong as libc::c_ulong)?;
+        if functions & I2C_FUNC_I2C == 0 {
+            return Err(format!("{}: the adapter only speaks SMBus, not plain I2C transfers", path.display()).into());
+        }
+        i2c.functions = functions;
+        Ok(i2c)
+    }
+
+    pub fn bus(&self) -> u8 {
+        self.bus
+    }
+
+    /// Whether the adapter can address 10-bit slaves.
+    pub fn has_ten_bit(&self) -> bool {
+        self.functions & I2C_FUNC_10BIT_ADDR != 0
+    }
+
+    fn ioctl(&mut self, request: u32, arg: libc::c_ulong) -> Result<(), LinuxI2cError> {
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg) } < 0 {
+            return Err(LinuxI2cError {
+                bus: self.bus,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(())
+    }
+
+    fn transfer(&mut self, addr: u16, flags: u16, operations: &mut [Operation<'_>]) -> Result<(), LinuxI2cError> {
+        let invalid = |message: String| LinuxI2cError {
+            bus: self.bus,
+            source: io::Error::new(io::ErrorKind::InvalidInput, message),
+        };
+        if operations.is_empty() {
+            return Ok(());
+        }
+        if operations.len() > MAX_MSGS {
+            return Err(invalid(format!(
+                "{} operations in one transaction; the kernel takes at most {}",
+                operations.len(),
+                MAX_MSGS
+            )));
+        }
+        let mut msgs = Vec::with_capacity(operations.len());
+        for op in operations.iter_mut() {
+            let (buf, len, rd) = match op {
+                Operation::Read(buf) => (buf.as_mut_ptr(), buf.len(), I2C_M_RD),
+                // the kernel only reads from a write message's buffer
+                Operation::Write(bytes) => (bytes.as_ptr() as *mut u8, bytes.len(), 0),
+            };
+            let len = u16::try_from(len).map_err(|_| invalid(format!("{}-byte transfer is too long for i2c-dev", len)))?;
+            msgs.push(I2cMsg {
+                addr,
+                flags: flags | rd,
+                len,
+                buf,
+            });
+        }
+        let mut data = I2cRdwrIoctlData {
+            msgs: msgs.as_mut_ptr(),
+            nmsgs: msgs.len() as u32,
+        };
+        self.ioctl(I2C_RDWR, &mut data as *mut I2cRdwrIoctlData as libc::c_ulong)
+    }
+}
+
+impl ErrorType for LinuxI2c {
+    type Error = LinuxI2cError;
+}
+
+impl I2c for LinuxI2c {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), LinuxI2cError> {
+        self.transfer(u16::from(address), 0, operations)
+    }
+}
+
+impl AddressedI2c for LinuxI2c {
+    fn transaction_at(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+        match address {
+            Address::SevenBit(a) => Ok(self.transfer(u16::from(a), 0, operations)?),
+            Address::TenBit(_) if !self.has_ten_bit() => {
+                Err(format!("{} needs 10-bit support, which the adapter on bus {} lacks", address, self.bus).into())
+            }
+            Address::TenBit(a) => Ok(self.transfer(a, I2C_M_TEN, operations)?),
+        }
+    }
+}
+
+impl BusControl for LinuxI2c {
+    /// From the device tree, as the kernel has no call for it.
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        available_buses()
+            .into_iter()
+            .find(|b| b.id == self.bus)
+            .and_then(|b| b.clock_speed)
+            .ok_or_else(|| format!("bus {} doesn't report its clock speed", self.bus).into())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        // in jiffies, nominally 10 ms
+        let ticks = timeout.as_millis().div_ceil(10);
+        let ticks = libc::c_ulong::try_from(ticks).map_err(|_| "timeout too long")?;
+        self.ioctl(I2C_TIMEOUT, ticks)?;
+        Ok(())
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        recover_hardware(self.bus)
+    }
+}
@@ -0,0 +1,341 @@
+use crate::address::Address;
+use crate::metrics::Metrics;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Who goes first when several devices are waiting for the bus.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl FromStr for Priority {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            other => Err(format!("unknown priority '{}' (low, normal, high)", other).into()),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        })
+    }
+}
+
+/// One device's share of a [`BusManager`](super::BusManager) bus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DevicePolicy {
+    /// Most transactions per second; `None` for as many as it likes.
+    pub rate: Option<f64>,
+    /// Transactions it may send back to back before the rate holds it back.
+    pub burst: u32,
+    pub priority: Priority,
+}
+
+impl Default for DevicePolicy {
+    fn default() -> Self {
+        DevicePolicy {
+            rate: None,
+            burst: 1,
+            priority: Priority::Normal,
+        }
+    }
+}
+
+impl DevicePolicy {
+    /// Slowest `rate`: one transaction in about 17 minutes.
+    pub const MIN_RATE: f64 = 0.001;
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(rate) = self.rate {
+            if !(rate >= Self::MIN_RATE && rate.is_finite()) {
+                return Err(format!("transaction rate must be at least {}, not {}", Self::MIN_RATE, rate).into());
+            }
+        }
+        if self.burst == 0 {
+            return Err("transaction burst must be at least 1".into());
+        }
+        Ok(())
+    }
+}
+
+/// The bus time one device has had since the manager was made.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceUsage {
+    pub address: Address,
+    pub transactions: u64,
+    /// Time its transactions held the bus.
+    pub busy: Duration,
+    /// Time they spent held back by its rate or queued behind others.
+    pub waited: Duration,
+    /// `busy` as a share of all the time since the manager was made.
+    pub utilization: f64,
+}
+
+#[derive(Default)]
+struct Counters {
+    transactions: u64,
+    busy: Duration,
+    waited: Duration,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    policies: HashMap<Address, DevicePolicy>,
+    buckets: HashMap<Address, Bucket>,
+    usage: BTreeMap<Address, Counters>,
+    /// A transaction has the bus.
+    busy: bool,
+    /// Waiting transactions, highest priority then oldest first.
+    queue: BTreeSet<(Reverse<Priority>, u64)>,
+    tickets: u64,
+    metrics: Option<Metrics>,
+}
+
+impl State {
+    /// Take a token from `address`'s bucket, returning how long to wait
+    /// before it's really there.
+    fn reserve(&mut self, address: Address, now: Instant) -> Duration {
+        let policy = self.policies.get(&address).copied().unwrap_or_default();
+        let Some(rate) = policy.rate else {
+            return Duration::ZERO;
+        };
+        let burst = f64::from(policy.burst.max(1));
+        let bucket = self.buckets.entry(address).or_insert(Bucket { tokens: burst, updated: now });
+        let refill = now.saturating_duration_since(bucket.updated).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(burst) - 1.0;
+        bucket.updated = now;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            // However far behind a queue has put the bucket, wait no longer than a Duration holds
+            Duration::try_from_secs_f64(-bucket.tokens / rate).unwrap_or(Duration::MAX)
+        }
+    }
+}
+
+/// Hands the bus to one transaction at a time: rate limits first, then
+/// priority, then order of arrival.
+pub(super) struct Scheduler {
+    state: Mutex<State>,
+    turn: Condvar,
+    started: Instant,
+}
+
+impl Scheduler {
+    pub(super) fn new() -> Self {
+        Scheduler {
+            state: Mutex::new(State::default()),
+            turn: Condvar::new(),
+            started: Instant::now(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(super) fn set_policy(&self, address: Address, policy: DevicePolicy) {
+        let mut state = self.lock();
+        state.buckets.remove(&address);
+        state.policies.insert(address, policy);
+    }
+
+    pub(super) fn set_metrics(&self, metrics: Metrics) {
+        self.lock().metrics = Some(metrics);
+    }
+
+    pub(super) fn usage(&self) -> Vec<DeviceUsage> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        self.lock()
+            .usage
+            .iter()
+            .map(|(&address, counters)| DeviceUsage {
+                address,
+                transactions: counters.transactions,
+                busy: counters.busy,
+                waited: counters.waited,
+                utilization: if elapsed > 0.0 { counters.busy.as_secs_f64() / elapsed } else { 0.0 },
+            })
+            .collect()
+    }
+
+    /// Run `transaction` for `address` once it's its turn.
+    pub(super) fn run<R>(&self, address: Address, transaction: impl FnOnce() -> R) -> R {
+        let asked = Instant::now();
+        let delay = self.lock().reserve(address, asked);
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        let mut state = self.lock();
+        let priority = state.policies.get(&address).map_or(Priority::Normal, |p| p.priority);
+        let ticket = (Reverse(priority), state.tickets);
+        state.tickets += 1;
+        state.queue.insert(ticket);
+        while state.busy || state.queue.first() != Some(&ticket) {
+            state = self.turn.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        state.queue.remove(&ticket);
+        state.busy = true;
+        drop(state);
+
+        let turn = Turn {
+            scheduler: self,
+            address,
+            asked,
+            started: Instant::now(),
+        };
+        let result = transaction();
+        drop(turn);
+        result
+    }
+}
+
+/// One transaction's hold on the bus, given back even if it panics.
+struct Turn<'a> {
+    scheduler: &'a Scheduler,
+    address: Address,
+    asked: Instant,
+    started: Instant,
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        let busy = self.started.elapsed();
+        let waited = self.started.saturating_duration_since(self.asked);
+        let mut state = self.scheduler.lock();
+        state.busy = false;
+        let counters = state.usage.entry(self.address).or_default();
+        counters.transactions += 1;
+        counters.busy += busy;
+        counters.waited += waited;
+        let total_busy = counters.busy;
+        if let Some(metrics) = &state.metrics {
+            let address = self.address.to_string();
+            let labels = [("address", address.as_str())];
+            metrics.inc(
+                "rpi_peripherals_bus_busy_seconds_total",
+                "Time each device's transactions held the shared bus",
+                &labels,
+                busy.as_secs_f64(),
+            );
+            metrics.inc(
+                "rpi_peripherals_bus_wait_seconds_total",
+                "Time each device's transactions waited for the shared bus",
+                &labels,
+                waited.as_secs_f64(),
+            );
+            let elapsed = self.scheduler.started.elapsed().as_secs_f64();
+            metrics.set(
+                "rpi_peripherals_bus_utilization",
+                "Share of the time each device has held the shared bus",
+                &labels,
+                if elapsed > 0.0 { total_busy.as_secs_f64() / elapsed } else { 0.0 },
+            );
+        }
+        drop(state);
+        self.scheduler.turn.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn rate_limit_spaces_transactions_out() {
+        let scheduler = Scheduler::new();
+        let address = Address::SevenBit(0x48);
+        scheduler.set_policy(
+            address,
+            DevicePolicy {
+                rate: Some(50.0),
+                burst: 2,
+                ..DevicePolicy::default()
+            },
+        );
+        let start = Instant::now();
+        for _ in 0..4 {
+            scheduler.run(address, || ());
+        }
+        // the burst goes at once, the other two 20 ms apart
+        assert!(start.elapsed() >= Duration::from_millis(38));
+        let usage = scheduler.usage();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].transactions, 4);
+        assert!(usage[0].waited >= Duration::from_millis(38));
+    }
+
+    #[test]
+    fn rates_are_bounded() {
+        let policy = |rate| DevicePolicy { rate: Some(rate), ..DevicePolicy::default() };
+        assert!(policy(DevicePolicy::MIN_RATE).validate().is_ok());
+        for rate in [0.0, 1e-300, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(policy(rate).validate().is_err(), "{}", rate);
+        }
+    }
+
+    #[test]
+    fn higher_priority_goes_first() {
+        let scheduler = Arc::new(Scheduler::new());
+        let lcd = Address::SevenBit(0x27);
+        let sensor = Address::SevenBit(0x40);
+        scheduler.set_policy(
+            lcd,
+            DevicePolicy {
+                priority: Priority::High,
+                ..DevicePolicy::default()
+            },
+        );
+        let order = Arc::new(Mutex::new(Vec::new()));
+        // hold the bus while both queue up behind it
+        let (held, release) = std::sync::mpsc::channel::<()>();
+        let blocker = {
+            let scheduler = Arc::clone(&scheduler);
+            thread::spawn(move || scheduler.run(sensor, || release.recv().unwrap()))
+        };
+        while !scheduler.lock().busy {
+            thread::yield_now();
+        }
+        let mut waiters = Vec::new();
+        for address in [sensor, lcd] {
+            let waiter = Arc::clone(&scheduler);
+            let order = Arc::clone(&order);
+            waiters.push(thread::spawn(move || waiter.run(address, || order.lock().unwrap().push(address))));
+            while scheduler.lock().queue.len() < waiters.len() {
+                thread::yield_now();
+            }
+        }
+        held.send(()).unwrap();
+        blocker.join().unwrap();
+        waiters.into_iter().for_each(|w| w.join().unwrap());
+        assert_eq!(*order.lock().unwrap(), [lcd, sensor]);
+    }
+}
@@ -0,0 +1,248 @@
+use super::BusControl;
+use crate::address::{Address, AddressedI2c};
+use crate::i2c::{RegisterFile, SlaveMap};
+use crate::trace::{Direction, TraceOp};
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// What [`BusControl::clock_speed`] reports until it's changed.
+const DEFAULT_CLOCK: u32 = 100_000;
+
+/// Nothing on the stub bus at this address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StubError {
+    pub address: Address,
+}
+
+impl fmt::Display for StubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NACK: no stub device at {}", self.address)
+    }
+}
+
+impl Error for StubError {}
+
+impl i2c::Error for StubError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+    }
+}
+
+/// A transaction as the stub bus saw it. Reads hold what was sent back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StubTransaction {
+    pub address: Address,
+    pub ops: Vec<TraceOp>,
+    /// False if nothing answered.
+    pub acked: bool,
+}
+
+enum Device {
+    /// A sensor-style register file: the first byte of each write moves
+    /// the pointer.
+    Registers(Box<RegisterFile>),
+    /// A PCF8574-style port: writes set it, reads return it.
+    Port(u8),
+}
+
+#[derive(Default)]
+struct State {
+    devices: BTreeMap<Address, Device>,
+    log: Vec<StubTransaction>,
+    clock: u32,
+}
+
+/// A bus that lives in memory, for running drivers and jobs anywhere:
+/// a CI machine, a laptop, a board with no I2C at all.
+///
+/// Devices are [`RegisterFile`]s or bare ports, and every other address
+/// NACKs. Every transaction is logged, so a test can check the exact bytes
+/// a driver sent. Clones share the devices and the log: hand one to the
+/// driver and keep one to look through.
+#[derive(Clone)]
+pub struct StubBus {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for StubBus {
+    fn default() -> Self {
+        StubBus::new()
+    }
+}
+
+impl StubBus {
+    pub fn new() -> Self {
+        StubBus {
+            state: Arc::new(Mutex::new(State {
+                clock: DEFAULT_CLOCK,
+                ..State::default()
+            })),
+        }
+    }
+
+    /// Answer at `map.address` with its registers, as `i2c-slave` would.
+    pub fn add_map(&self, map: &SlaveMap) -> Result<(), Box<dyn Error>> {
+        let address = map.address()?;
+        self.add_registers(address, RegisterFile::new(map));
+        Ok(())
+    }
+
+    pub fn add_registers(&self, address: Address, registers: RegisterFile) {
+        self.lock().devices.insert(address, Device::Registers(Box::new(registers)));
+    }
+
+    /// Answer at `address` as an 8-bit port holding `value`.
+    pub fn add_port(&self, address: Address, value: u8) {
+        self.lock().devices.insert(address, Device::Port(value));
+    }
+
+    pub fn remove(&self, address: Address) {
+        self.lock().devices.remove(&address);
+    }
+
+    /// Every address with a device on it.
+    pub fn addresses(&self) -> Vec<Address> {
+        self.lock().devices.keys().copied().collect()
+    }
+
+    /// A copy of the register file at `address`.
+    pub fn registers(&self, address: Address) -> Option<RegisterFile> {
+        match self.lock().devices.get(&address)? {
+            Device::Registers(file) => Some((**file).clone()),
+            Device::Port(_) => None,
+        }
+    }
+
+    /// Change a register from the device's side, as a sensor updates its
+    /// readings.
+    pub fn set_register(&self, address: Address, register: u8, value: u8) -> Result<(), Box<dyn Error>> {
+        match self.lock().devices.get_mut(&address) {
+            Some(Device::Registers(file)) => {
+                file.set(register, value);
+                Ok(())
+            }
+            _ => Err(format!("no stub register file at {}", address).into()),
+        }
+    }
+
+    pub fn port(&self, address: Address) -> Option<u8> {
+        match self.lock().devices.get(&address)? {
+            Device::Port(value) => Some(*value),
+            Device::Registers(_) => None,
+        }
+    }
+
+    /// Every transaction so far, oldest first.
+    pub fn transactions(&self) -> Vec<StubTransaction> {
+        self.lock().log.clone()
+    }
+
+    /// Every byte written to `address`, one `Vec` per write.
+    pub fn writes(&self, address: Address) -> Vec<Vec<u8>> {
+        self.lock()
+            .log
+            .iter()
+            .filter(|t| t.address == address && t.acked)
+            .flat_map(|t| t.ops.iter().filter(|op| op.direction == Direction::Write).map(|op| op.bytes.clone()))
+            .collect()
+    }
+
+    pub fn clear_log(&self) {
+        self.lock().log.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn run(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), StubError> {
+        let mut state = self.lock();
+        let State { devices, log, .. } = &mut *state;
+        let Some(device) = devices.get_mut(&address) else {
+            log.push(StubTransaction {
+                address,
+                ops: Vec::new(),
+                acked: false,
+            });
+            return Err(StubError { address });
+        };
+        let mut ops = Vec::with_capacity(operations.len());
+        for op in operations.iter_mut() {
+            match op {
+                Operation::Write(bytes) => {
+                    match device {
+                        Device::Registers(file) => {
+                            if let Some((&register, data)) = bytes.split_first() {
+                                file.select(register);
+                                data.iter().for_each(|&b| file.write(b));
+                            }
+                        }
+                        Device::Port(value) => {
+                            if let Some(&last) = bytes.last() {
+                                *value = last;
+                            }
+                        }
+                    }
+                    ops.push(TraceOp {
+                        direction: Direction::Write,
+                        bytes: bytes.to_vec(),
+                    });
+                }
+                Operation::Read(buf) => {
+                    match device {
+                        Device::Registers(file) => {
+                            buf.copy_from_slice(&file.peek(buf.len()));
+                            file.advance(buf.len());
+                        }
+                        Device::Port(value) => buf.fill(*value),
+                    }
+                    ops.push(TraceOp {
+                        direction: Direction::Read,
+                        bytes: buf.to_vec(),
+                    });
+                }
+            }
+        }
+        log.push(StubTransaction { address, ops, acked: true });
+        Ok(())
+    }
+}
+
+impl ErrorType for StubBus {
+    type Error = StubError;
+}
+
+impl I2c for StubBus {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), StubError> {
+        self.run(Address::SevenBit(address), operations)
+    }
+}
+
+impl AddressedI2c for StubBus {
+    fn transaction_at(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+        Ok(self.run(address, operations)?)
+    }
+}
+
+impl BusControl for StubBus {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        Ok(self.lock().clock)
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        self.lock().clock = hz;
+        Ok(())
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+//! CAN bus through an MCP2515 controller on SPI.
+//!
+//! The common Pi CAN modules pair an MCP2515 with a TJA1050 or MCP2551
+//! transceiver. The kernel can drive them as `can0` through the
+//! `mcp2515-can0` overlay; this talks to the chip over spidev instead, so it
+//! works without the overlay and in the CAN modes socketcan doesn't expose
+//! easily, such as loopback.
+//!
+//! ```no_run
+//! use rpi_peripherals::can::{CanFrame, Filter, Mcp2515, OperatingMode, DEFAULT_OSCILLATOR};
+//! use std::time::Duration;
+//!
+//! let mut can = Mcp2515::from_spi(0, 0)?;
+//! can.init(DEFAULT_OSCILLATOR, 500_000, OperatingMode::Normal)?;
+//! can.set_filters(&["100:700".parse::<Filter>()?])?;
+//! can.send(&"123#DEADBEEF".parse::<CanFrame>()?)?;
+//! can.flush(Duration::from_millis(100))?;
+//! if let Some(frame) = can.receive()? {
+//!     println!("{}", frame);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Frames and filters are written the way `cansend` and `candump` write
+//! them, so commands carry over between the two.
+
+mod frame;
+mod mcp2515;
+
+pub use frame::{CanFrame, CanId, Filter, EXTENDED_MAX, MAX_DATA, STANDARD_MAX};
+pub use mcp2515::{BitTiming, ErrorState, Mcp2515, OperatingMode, DEFAULT_OSCILLATOR, MCP2515_CLOCK};
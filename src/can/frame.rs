@@ -0,0 +1,198 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Most data bytes a classic CAN frame carries.
+pub const MAX_DATA: usize = 8;
+
+pub const STANDARD_MAX: u16 = 0x7FF;
+pub const EXTENDED_MAX: u32 = 0x1FFF_FFFF;
+
+/// An 11-bit standard or 29-bit extended identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CanId {
+    Standard(u16),
+    Extended(u32),
+}
+
+impl CanId {
+    pub fn standard(id: u16) -> Result<Self, Box<dyn Error>> {
+        if id > STANDARD_MAX {
+            return Err(format!("0x{:X} is not an 11-bit CAN ID", id).into());
+        }
+        Ok(CanId::Standard(id))
+    }
+
+    pub fn extended(id: u32) -> Result<Self, Box<dyn Error>> {
+        if id > EXTENDED_MAX {
+            return Err(format!("0x{:X} is not a 29-bit CAN ID", id).into());
+        }
+        Ok(CanId::Extended(id))
+    }
+
+    pub fn raw(&self) -> u32 {
+        match *self {
+            CanId::Standard(id) => u32::from(id),
+            CanId::Extended(id) => id,
+        }
+    }
+
+    pub fn is_extended(&self) -> bool {
+        matches!(self, CanId::Extended(_))
+    }
+
+    /// Every bit of the identifier: what a mask compares when it matches
+    /// one ID exactly.
+    pub fn full_mask(&self) -> u32 {
+        match self {
+            CanId::Standard(_) => u32::from(STANDARD_MAX),
+            CanId::Extended(_) => EXTENDED_MAX,
+        }
+    }
+}
+
+/// As `candump` and `cansend` write them: three hex digits for a standard
+/// ID, eight for an extended one.
+impl fmt::Display for CanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CanId::Standard(id) => write!(f, "{:03X}", id),
+            CanId::Extended(id) => write!(f, "{:08X}", id),
+        }
+    }
+}
+
+impl FromStr for CanId {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+        let id = u32::from_str_radix(digits, 16).map_err(|_| format!("'{}' is not a hex CAN ID", s))?;
+        // cansend's rule: more than three digits is an extended ID
+        if digits.len() > 3 {
+            CanId::extended(id)
+        } else {
+            CanId::standard(id as u16)
+        }
+    }
+}
+
+/// A classic CAN data or remote frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanFrame {
+    pub id: CanId,
+    /// Empty in a remote frame, which asks for `dlc` bytes instead.
+    pub data: Vec<u8>,
+    pub remote: bool,
+    /// The length code: `data.len()`, or the bytes a remote frame asks for.
+    pub dlc: u8,
+}
+
+impl CanFrame {
+    pub fn new(id: CanId, data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if data.len() > MAX_DATA {
+            return Err(format!("{} data bytes; a CAN frame takes at most {}", data.len(), MAX_DATA).into());
+        }
+        Ok(CanFrame {
+            id,
+            data: data.to_vec(),
+            remote: false,
+            dlc: data.len() as u8,
+        })
+    }
+
+    /// A request for `dlc` bytes from whichever node sends `id`.
+    pub fn remote(id: CanId, dlc: u8) -> Result<Self, Box<dyn Error>> {
+        if usize::from(dlc) > MAX_DATA {
+            return Err(format!("remote frame length {} is over {}", dlc, MAX_DATA).into());
+        }
+        Ok(CanFrame {
+            id,
+            data: Vec::new(),
+            remote: true,
+            dlc,
+        })
+    }
+}
+
+/// `candump`'s layout: `123   [4]  DE AD BE EF`.
+impl fmt::Display for CanFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}   [{}] ", self.id, self.dlc)?;
+        if self.remote {
+            return write!(f, " remote request");
+        }
+        for byte in &self.data {
+            write!(f, " {:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// `cansend`'s syntax: `123#DEADBEEF`, `12345678#00.11`, or `123#R` and
+/// `123#R4` for remote frames.
+impl FromStr for CanFrame {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, data) = s.split_once('#').ok_or_else(|| format!("'{}' is not ID#DATA, as in 123#DEADBEEF", s))?;
+        let id: CanId = id.parse()?;
+        if let Some(dlc) = data.strip_prefix(['R', 'r']) {
+            let dlc = if dlc.is_empty() { 0 } else { dlc.parse().map_err(|_| format!("'{}' is not a length", dlc))? };
+            return CanFrame::remote(id, dlc);
+        }
+        let hex: String = data.chars().filter(|&c| c != '.').collect();
+        if !hex.len().is_multiple_of(2) {
+            return Err(format!("'{}' is not whole bytes of hex", data).into());
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|n| u8::from_str_radix(&hex[n..n + 2], 16).map_err(|_| format!("'{}' is not hex", data)))
+            .collect::<Result<Vec<u8>, _>>()?;
+        CanFrame::new(id, &bytes)
+    }
+}
+
+/// Accept frames whose ID matches `id` in the bits set in `mask`, and of
+/// the same kind, standard or extended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Filter {
+    pub id: CanId,
+    pub mask: u32,
+}
+
+impl Filter {
+    /// `id` and nothing else.
+    pub fn exact(id: CanId) -> Self {
+        Filter { id, mask: id.full_mask() }
+    }
+
+    pub fn matches(&self, id: CanId) -> bool {
+        id.is_extended() == self.id.is_extended() && (id.raw() ^ self.id.raw()) & self.mask == 0
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{:X}", self.id, self.mask)
+    }
+}
+
+/// `ID` for one identifier, or `ID:MASK` as `candump` takes them, such as
+/// `100:700` for 0x100-0x1FF.
+impl FromStr for Filter {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((id, mask)) = s.split_once(':') else {
+            return Ok(Filter::exact(s.parse()?));
+        };
+        let id: CanId = id.parse()?;
+        let mask = u32::from_str_radix(mask.trim(), 16).map_err(|_| format!("'{}' is not a hex mask", mask))?;
+        if mask & !id.full_mask() != 0 {
+            return Err(format!("mask {:X} is wider than the ID {}", mask, id).into());
+        }
+        Ok(Filter { id, mask })
+    }
+}
@@ -0,0 +1,469 @@
+use super::{CanFrame, CanId, Filter};
+use embedded_hal::spi::{Operation, SpiDevice};
+use rppal::spi::SimpleHalSpiDevice;
+use std::error::Error;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The chip takes 10 MHz; most modules' wiring is happier a little under.
+pub const MCP2515_CLOCK: u32 = 8_000_000;
+
+/// The crystal on most MCP2515 modules. Some carry 16 MHz, which the bit
+/// timing has to be told about.
+pub const DEFAULT_OSCILLATOR: u32 = 8_000_000;
+
+const RESET: u8 = 0xC0;
+const READ: u8 = 0x03;
+const WRITE: u8 = 0x02;
+const BIT_MODIFY: u8 = 0x05;
+const READ_STATUS: u8 = 0xA0;
+/// Load a transmit buffer from its SIDH; TXB1 and TXB2 are +2 and +4.
+const LOAD_TX: u8 = 0x40;
+/// Request to send, buffer n in bit n.
+const RTS: u8 = 0x80;
+/// Read a receive buffer from its SIDH, clearing its flag; RXB1 is +4.
+const READ_RX: u8 = 0x90;
+
+const CANSTAT: u8 = 0x0E;
+const CANCTRL: u8 = 0x0F;
+const TEC: u8 = 0x1C;
+const REC: u8 = 0x1D;
+const CNF3: u8 = 0x28;
+const CANINTE: u8 = 0x2B;
+const EFLG: u8 = 0x2D;
+const TXB0CTRL: u8 = 0x30;
+const RXB0CTRL: u8 = 0x60;
+const RXB1CTRL: u8 = 0x70;
+const RXF: [u8; 6] = [0x00, 0x04, 0x08, 0x10, 0x14, 0x18];
+const RXM: [u8; 2] = [0x20, 0x24];
+
+const REQOP_MASK: u8 = 0xE0;
+/// Abort all pending transmissions.
+const ABAT: u8 = 0x10;
+const TXREQ: u8 = 0x08;
+const EXIDE: u8 = 0x08;
+const RTR: u8 = 0x40;
+/// In RXBnSIDL: a standard remote frame.
+const SRR: u8 = 0x10;
+/// RXBnCTRL: take every frame, filters or not.
+const RXM_ANY: u8 = 0x60;
+/// RXB0CTRL: roll over into RXB1 when RXB0 is full.
+const BUKT: u8 = 0x04;
+const RX0OVR: u8 = 0x40;
+const RX1OVR: u8 = 0x80;
+
+/// Up to this long for the chip to change mode after reset or a request.
+const MODE_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// How the controller takes part in the bus.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OperatingMode {
+    #[default]
+    Normal,
+    /// Frames go straight back to the receive buffers and nothing reaches
+    /// the bus: a test without a second node.
+    Loopback,
+    /// Receive without ever ACKing or sending, to watch a live bus
+    /// without disturbing it.
+    ListenOnly,
+    Configuration,
+}
+
+impl OperatingMode {
+    fn reqop(self) -> u8 {
+        match self {
+            OperatingMode::Normal => 0x00,
+            OperatingMode::Loopback => 0x40,
+            OperatingMode::ListenOnly => 0x60,
+            OperatingMode::Configuration => 0x80,
+        }
+    }
+}
+
+impl fmt::Display for OperatingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            OperatingMode::Normal => "normal",
+            OperatingMode::Loopback => "loopback",
+            OperatingMode::ListenOnly => "listen-only",
+            OperatingMode::Configuration => "configuration",
+        })
+    }
+}
+
+/// One bit's time quanta: the sync quantum, then propagation and phase 1
+/// up to the sample point, then phase 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitTiming {
+    /// Oscillator divider: a quantum is `2 * brp` oscillator periods.
+    pub brp: u8,
+    pub prop_seg: u8,
+    pub phase1: u8,
+    pub phase2: u8,
+}
+
+impl BitTiming {
+    /// The timing for `bitrate` from `oscillator`, sampling as near 87.5%
+    /// through the bit as the quanta allow, as CANopen recommends.
+    pub fn new(oscillator: u32, bitrate: u32) -> Result<Self, Box<dyn Error>> {
+        // Fastest is 8 quanta of 2 oscillator periods each
+        let fastest = oscillator / 16;
+        if !(1..=fastest).contains(&bitrate) {
+            return Err(format!("CAN bitrate {} is outside 1-{} bit/s for a {} Hz oscillator", bitrate, fastest, oscillator).into());
+        }
+        // More quanta a bit means finer placement of the sample point
+        for quanta in (8..=25u32).rev() {
+            let Some(divider) = bitrate.checked_mul(2 * quanta) else {
+                continue;
+            };
+            if !oscillator.is_multiple_of(divider) || !(1..=64).contains(&(oscillator / divider)) {
+                continue;
+            }
+            let phase2 = ((quanta + 4) / 8).clamp(2, 8);
+            let before = quanta - 1 - phase2;
+            let prop_seg = (before / 2).clamp(1, 8);
+            let phase1 = before - prop_seg;
+            if !(1..=8).contains(&phase1) || phase1 + prop_seg < phase2 {
+                continue;
+            }
+            return Ok(BitTiming {
+                brp: (oscillator / divider) as u8,
+                prop_seg: prop_seg as u8,
+                phase1: phase1 as u8,
+                phase2: phase2 as u8,
+            });
+        }
+        Err(format!("no MCP2515 bit timing gives {} bit/s from a {} Hz oscillator", bitrate, oscillator).into())
+    }
+
+    pub fn quanta(&self) -> u8 {
+        1 + self.prop_seg + self.phase1 + self.phase2
+    }
+
+    /// Percent of the bit before the sample point.
+    pub fn sample_point(&self) -> f64 {
+        f64::from(1 + self.prop_seg + self.phase1) * 100.0 / f64::from(self.quanta())
+    }
+
+    /// CNF3, CNF2, CNF1, in register order from CNF3. Resynchronisation
+    /// jumps one quantum.
+    fn registers(&self) -> [u8; 3] {
+        let cnf1 = self.brp - 1;
+        // BTLMODE: phase 2 from CNF3 rather than derived
+        let cnf2 = 0x80 | ((self.phase1 - 1) << 3) | (self.prop_seg - 1);
+        let cnf3 = self.phase2 - 1;
+        [cnf3, cnf2, cnf1]
+    }
+}
+
+/// The bus error state: counters and EFLG flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorState {
+    pub transmit: u8,
+    pub receive: u8,
+    pub flags: u8,
+}
+
+impl ErrorState {
+    /// Off the bus after too many transmit errors, until reset.
+    pub fn bus_off(&self) -> bool {
+        self.flags & 0x20 != 0
+    }
+
+    /// Still on the bus, but only sending passive error frames.
+    pub fn passive(&self) -> bool {
+        self.flags & 0x18 != 0
+    }
+
+    /// A receive buffer overflowed since the flags were last cleared.
+    pub fn overflowed(&self) -> bool {
+        self.flags & (RX0OVR | RX1OVR) != 0
+    }
+}
+
+impl fmt::Display for ErrorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.bus_off() {
+            "bus-off"
+        } else if self.passive() {
+            "error-passive"
+        } else {
+            "error-active"
+        };
+        write!(f, "{}, TEC {}, REC {}", state, self.transmit, self.receive)?;
+        if self.overflowed() {
+            write!(f, ", receive overflow")?;
+        }
+        Ok(())
+    }
+}
+
+/// Microchip MCP2515 stand-alone CAN controller, on SPI.
+pub struct Mcp2515<SPI> {
+    spi: SPI,
+    mode: OperatingMode,
+}
+
+impl Mcp2515<SimpleHalSpiDevice> {
+    /// The chip on `/dev/spidev<bus>.<cs>` at [`MCP2515_CLOCK`].
+    pub fn from_spi(bus: u8, cs: u8) -> Result<Self, Box<dyn Error>> {
+        let spi = crate::spi::open(bus, cs, MCP2515_CLOCK)?;
+        Ok(Mcp2515::new(SimpleHalSpiDevice::new(spi)))
+    }
+}
+
+impl<SPI> Mcp2515<SPI>
+where
+    SPI: SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    /// Nothing is sent until [`Self::init`].
+    pub fn new(spi: SPI) -> Self {
+        Mcp2515 {
+            spi,
+            mode: OperatingMode::Configuration,
+        }
+    }
+
+    /// Reset the chip, set the bit timing for `bitrate` and accept every
+    /// frame, then go into `mode`.
+    pub fn init(&mut self, oscillator: u32, bitrate: u32, mode: OperatingMode) -> Result<BitTiming, Box<dyn Error>> {
+        let timing = BitTiming::new(oscillator, bitrate)?;
+        self.spi.write(&[RESET])?;
+        // The oscillator restarts after reset: 128 cycles, well under this
+        thread::sleep(Duration::from_millis(1));
+        if self.read(CANSTAT)? & REQOP_MASK != OperatingMode::Configuration.reqop() {
+            return Err("MCP2515 didn't come out of reset in configuration mode; check the SPI wiring".into());
+        }
+        self.mode = OperatingMode::Configuration;
+        self.write(CNF3, &timing.registers())?;
+        // Polled, so no interrupt pin is needed
+        self.write(CANINTE, &[0])?;
+        self.set_filters(&[])?;
+        self.set_mode(mode)?;
+        Ok(timing)
+    }
+
+    pub fn mode(&self) -> OperatingMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: OperatingMode) -> Result<(), Box<dyn Error>> {
+        self.modify(CANCTRL, REQOP_MASK, mode.reqop())?;
+        let start = Instant::now();
+        while self.read(CANSTAT)? & REQOP_MASK != mode.reqop() {
+            if start.elapsed() > MODE_TIMEOUT {
+                return Err(format!("MCP2515 didn't enter {} mode", mode).into());
+            }
+            thread::sleep(Duration::from_micros(100));
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// Take only frames some filter matches; with none, take all. There
+    /// are two masks, one for each receive buffer: all the filters can share
+    /// one, or the first two filters of the list can have one of their own
+    /// and the other four another. Standard and extended filters can't
+    /// share a mask.
+    pub fn set_filters(&mut self, filters: &[Filter]) -> Result<(), Box<dyn Error>> {
+        let mode = self.mode;
+        if mode != OperatingMode::Configuration {
+            self.set_mode(OperatingMode::Configuration)?;
+        }
+        if filters.is_empty() {
+            self.write(RXB0CTRL, &[RXM_ANY | BUKT])?;
+            self.write(RXB1CTRL, &[RXM_ANY])?;
+        } else {
+            let (rxb0, rxb1) = split_filters(filters)?;
+            for (buffer, group) in [(0, rxb0), (1, rxb1)] {
+                let mask = group[0];
+                self.write(RXM[buffer], &id_registers(mask.id.is_extended(), mask.mask, false))?;
+                let slots = if buffer == 0 { &RXF[..2] } else { &RXF[2..] };
+                for (n, &slot) in slots.iter().enumerate() {
+                    // Unused slots repeat a filter rather than matching ID 0
+                    let filter = group[n.min(group.len() - 1)];
+                    self.write(slot, &id_registers(filter.id.is_extended(), filter.id.raw(), filter.id.is_extended()))?;
+                }
+            }
+            self.write(RXB0CTRL, &[BUKT])?;
+            self.write(RXB1CTRL, &[0])?;
+        }
+        if mode != OperatingMode::Configuration {
+            self.set_mode(mode)?;
+        }
+        Ok(())
+    }
+
+    /// Queue `frame` in a free transmit buffer; [`Self::flush`] waits for it
+    /// to go. Fails if all three are still waiting.
+    pub fn send(&mut self, frame: &CanFrame) -> Result<(), Box<dyn Error>> {
+        let buffer = (0..3u8)
+            .find_map(|n| match self.read(TXB0CTRL + 0x10 * n) {
+                Ok(ctrl) if ctrl & TXREQ == 0 => Some(Ok(n)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .transpose()?
+            .ok_or("all three MCP2515 transmit buffers are still waiting to send")?;
+        let mut bytes = vec![LOAD_TX + 2 * buffer];
+        bytes.extend(id_registers(frame.id.is_extended(), frame.id.raw(), frame.id.is_extended()));
+        bytes.push(if frame.remote { RTR } else { 0 } | frame.dlc);
+        bytes.extend_from_slice(&frame.data);
+        self.spi.write(&bytes)?;
+        self.spi.write(&[RTS | (1 << buffer)])?;
+        Ok(())
+    }
+
+    /// Wait until every queued frame is sent, for up to `timeout`. A frame
+    /// nobody ACKs is retried for ever, so on timeout they are aborted.
+    pub fn flush(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let start = Instant::now();
+        loop {
+            let mut pending = false;
+            for n in 0..3u8 {
+                pending |= self.read(TXB0CTRL + 0x10 * n)? & TXREQ != 0;
+            }
+            if !pending {
+                return Ok(());
+            }
+            if start.elapsed() > timeout {
+                let errors = self.errors()?;
+                self.modify(CANCTRL, ABAT, ABAT)?;
+                self.modify(CANCTRL, ABAT, 0)?;
+                return Err(format!("frames not sent after {:?} ({}); is another node on the bus to ACK them?", timeout, errors).into());
+            }
+            thread::sleep(Duration::from_micros(200));
+        }
+    }
+
+    /// The next received frame, if one is waiting.
+    pub fn receive(&mut self) -> Result<Option<CanFrame>, Box<dyn Error>> {
+        let status = self.status()?;
+        let buffer = match status & 0x03 {
+            0 => return Ok(None),
+            // RXB0 first: it fills first and rolls over into RXB1
+            flags if flags & 0x01 != 0 => 0,
+            _ => 1,
+        };
+        let mut registers = [0; 13];
+        self.spi.transaction(&mut [Operation::Write(&[READ_RX + 4 * buffer]), Operation::Read(&mut registers)])?;
+        Ok(Some(decode(&registers)?))
+    }
+
+    pub fn errors(&mut self) -> Result<ErrorState, Box<dyn Error>> {
+        Ok(ErrorState {
+            transmit: self.read(TEC)?,
+            receive: self.read(REC)?,
+            flags: self.read(EFLG)?,
+        })
+    }
+
+    /// Clear the receive overflow flags, the only EFLG bits that don't
+    /// clear themselves.
+    pub fn clear_overflow(&mut self) -> Result<(), Box<dyn Error>> {
+        self.modify(EFLG, RX0OVR | RX1OVR, 0)
+    }
+
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+
+    /// READ STATUS: RX0IF and RX1IF in bits 0 and 1.
+    fn status(&mut self) -> Result<u8, Box<dyn Error>> {
+        let mut frame = [READ_STATUS, 0];
+        self.spi.transfer_in_place(&mut frame)?;
+        Ok(frame[1])
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, Box<dyn Error>> {
+        let mut frame = [READ, register, 0];
+        self.spi.transfer_in_place(&mut frame)?;
+        Ok(frame[2])
+    }
+
+    /// `bytes` into consecutive registers from `register`.
+    fn write(&mut self, register: u8, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut frame = vec![WRITE, register];
+        frame.extend_from_slice(bytes);
+        self.spi.write(&frame)?;
+        Ok(())
+    }
+
+    fn modify(&mut self, register: u8, mask: u8, value: u8) -> Result<(), Box<dyn Error>> {
+        self.spi.write(&[BIT_MODIFY, register, mask, value])?;
+        Ok(())
+    }
+}
+
+/// SIDH, SIDL, EID8 and EID0 for an identifier, or a mask of one.
+/// `exide` marks a filter or frame as extended.
+fn id_registers(extended: bool, id: u32, exide: bool) -> [u8; 4] {
+    if extended {
+        let sid = id >> 18;
+        [
+            (sid >> 3) as u8,
+            ((sid & 0x07) << 5) as u8 | if exide { EXIDE } else { 0 } | ((id >> 16) & 0x03) as u8,
+            (id >> 8) as u8,
+            id as u8,
+        ]
+    } else {
+        // Mask and filter bits over the extended ID would also compare the
+        // first data bytes of standard frames, so they stay clear
+        [(id >> 3) as u8, ((id & 0x07) << 5) as u8, 0, 0]
+    }
+}
+
+/// A receive buffer from SIDH to D7.
+fn decode(registers: &[u8; 13]) -> Result<CanFrame, Box<dyn Error>> {
+    let [sidh, sidl, eid8, eid0, dlc] = [registers[0], registers[1], registers[2], registers[3], registers[4]];
+    let sid = u32::from(sidh) << 3 | u32::from(sidl >> 5);
+    let (id, remote) = if sidl & EXIDE != 0 {
+        let id = sid << 18 | u32::from(sidl & 0x03) << 16 | u32::from(eid8) << 8 | u32::from(eid0);
+        (CanId::extended(id)?, dlc & RTR != 0)
+    } else {
+        (CanId::standard(sid as u16)?, sidl & SRR != 0)
+    };
+    // The length code can say up to 15, meaning 8
+    let length = dlc & 0x0F;
+    if remote {
+        return CanFrame::remote(id, length.min(8));
+    }
+    CanFrame::new(id, &registers[5..5 + usize::from(length.min(8))])
+}
+
+/// Filters for RXB0 and RXB1, each group led by the filter whose mask it uses.
+fn split_filters(filters: &[Filter]) -> Result<(Vec<Filter>, Vec<Filter>), Box<dyn Error>> {
+    let same_mask = |a: &Filter, b: &Filter| a.mask == b.mask && a.id.is_extended() == b.id.is_extended();
+    if filters.len() > 6 {
+        return Err(format!("{} filters; the MCP2515 has 6", filters.len()).into());
+    }
+    if filters.iter().all(|f| same_mask(f, &filters[0])) {
+        let split = filters.len().min(2);
+        let rxb1 = if filters.len() > 2 { filters[split..].to_vec() } else { filters.to_vec() };
+        return Ok((filters[..split].to_vec(), rxb1));
+    }
+    let (rxb0, rxb1) = filters.split_at(filters.len().min(2));
+    let rxb0_shares = rxb0.iter().all(|f| same_mask(f, &rxb0[0]));
+    if !rxb0_shares || rxb1.is_empty() || !rxb1.iter().all(|f| same_mask(f, &rxb1[0])) {
+        return Err("the MCP2515 has two masks: give the first two filters one mask and the rest another".into());
+    }
+    Ok((rxb0.to_vec(), rxb1.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_timing() {
+        let timing = BitTiming::new(8_000_000, 500_000).unwrap();
+        assert_eq!(timing.brp, 1);
+        assert_eq!(timing.quanta(), 8);
+        assert!(BitTiming::new(16_000_000, 1_000_000).is_ok());
+        for bitrate in [0, 1_000_001, u32::MAX / 2, u32::MAX] {
+            assert!(BitTiming::new(16_000_000, bitrate).is_err(), "{}", bitrate);
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//! Charlieplexed LED arrays: N*(N-1) LEDs on N GPIOs, no driver chip.
+//!
+//! Every ordered pair of pins has one LED between them, anode on the first.
+//! Lighting it means driving the anode high, the cathode low and leaving
+//! every other pin floating, so the pins have to switch between output and
+//! input ([`TriStatePin`]). [`Charlieplex::spawn`] starts a refresh thread
+//! that scans one anode at a time: in its slot, the anode drives high and
+//! the cathodes of that row's lit LEDs sink low, so up to N-1 LEDs share
+//! the anode pin's current. Size the resistors (one per pin, so two in
+//! series per LED) for that, and for the 16 mA a Pi pin can source.
+//!
+//! A [`Layout`] maps the logical grid users draw on to pin pairs, since the
+//! wiring of a board rarely follows the pin order.
+//!
+//! ```no_run
+//! use rpi_peripherals::charlieplex::{Charlieplex, CharlieConfig, Layout};
+//!
+//! // Four pins drive twelve LEDs, here arranged as four columns of three
+//! let layout = Layout::natural(4, 4)?;
+//! let leds = Charlieplex::from_gpio(&[17, 27, 22, 23], layout, CharlieConfig::default())?;
+//! leds.set(0, 0, true)?;
+//! leds.set(3, 2, true)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::timing::PreciseDelay;
+use rppal::gpio::{Gpio, IoPin, Mode};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A pin that can drive either level or float.
+pub trait TriStatePin {
+    type Error: fmt::Debug;
+
+    fn drive(&mut self, high: bool) -> Result<(), Self::Error>;
+
+    /// Switch to input, so the pin neither sources nor sinks current.
+    fn float(&mut self) -> Result<(), Self::Error>;
+}
+
+impl TriStatePin for IoPin {
+    type Error = Infallible;
+
+    fn drive(&mut self, high: bool) -> Result<(), Infallible> {
+        // Latch the level before switching to output so the pin never glitches.
+        if high {
+            self.set_high();
+        } else {
+            self.set_low();
+        }
+        self.set_mode(Mode::Output);
+        Ok(())
+    }
+
+    fn float(&mut self) -> Result<(), Infallible> {
+        self.set_mode(Mode::Input);
+        Ok(())
+    }
+}
+
+/// Number of LEDs `pins` pins can address.
+pub fn led_count(pins: usize) -> usize {
+    pins * pins.saturating_sub(1)
+}
+
+/// (anode, cathode) pin indices of LED `index` in the usual wiring: the
+/// first N-1 LEDs have pin 0 as anode, each with the next pin over as
+/// cathode, skipping itself; then pin 1, and so on.
+pub fn pins_for(index: usize, pins: usize) -> Option<(usize, usize)> {
+    if index >= led_count(pins) {
+        return None;
+    }
+    let anode = index / (pins - 1);
+    let k = index % (pins - 1);
+    let cathode = if k < anode { k } else { k + 1 };
+    Some((anode, cathode))
+}
+
+/// Where each LED of a `width`-wide grid is, as (anode, cathode) pin
+/// indices, row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pins: usize,
+    width: usize,
+    pairs: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    /// Every LED [`pins_for`] knows, in that order, wrapped at `width`.
+    pub fn natural(pins: usize, width: usize) -> Result<Self, Box<dyn Error>> {
+        let pairs = (0..led_count(pins)).filter_map(|i| pins_for(i, pins)).collect();
+        Layout::new(pins, width, pairs)
+    }
+
+    /// A custom mapping; `pairs[y * width + x]` is the LED at (x, y). A
+    /// short last row is fine.
+    pub fn new(pins: usize, width: usize, pairs: Vec<(usize, usize)>) -> Result<Self, Box<dyn Error>> {
+        if pins < 2 {
+            return Err("charlieplexing needs at least two pins".into());
+        }
+        if width == 0 {
+            return Err("layout width must be at least 1".into());
+        }
+        let mut seen = HashSet::new();
+        for (i, &(anode, cathode)) in pairs.iter().enumerate() {
+            if anode >= pins || cathode >= pins {
+                return Err(format!("LED {} uses pin {} of {}", i, anode.max(cathode), pins).into());
+            }
+            if anode == cathode {
+                return Err(format!("LED {} has pin {} as both anode and cathode", i, anode).into());
+            }
+            if !seen.insert((anode, cathode)) {
+                return Err(format!("LED {} repeats pins {}->{}", i, anode, cathode).into());
+            }
+        }
+        Ok(Layout { pins, width, pairs })
+    }
+
+    pub fn pins(&self) -> usize {
+        self.pins
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.pairs.len().div_ceil(self.width)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Pin pair of the LED at (`x`, `y`).
+    pub fn pair(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        if x >= self.width {
+            return None;
+        }
+        self.pairs.get(y * self.width + x).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharlieConfig {
+    /// Full passes over all anodes per second.
+    pub refresh_hz: u32,
+    /// Percent of each anode's slot its LEDs are lit, 1-100.
+    pub brightness: u8,
+    /// Spun part of each wait; see [`PreciseDelay`].
+    pub spin: Duration,
+}
+
+impl Default for CharlieConfig {
+    fn default() -> Self {
+        CharlieConfig {
+            refresh_hz: 100,
+            brightness: 100,
+            spin: Duration::from_micros(500),
+        }
+    }
+}
+
+impl CharlieConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !(1..=10_000).contains(&self.refresh_hz) {
+            return Err(format!("refresh rate {} Hz out of range (1-10000)", self.refresh_hz).into());
+        }
+        if !(1..=100).contains(&self.brightness) {
+            return Err(format!("brightness {}% out of range (1-100)", self.brightness).into());
+        }
+        Ok(())
+    }
+}
+
+struct Shared {
+    /// `lit[anode][cathode]`.
+    lit: Vec<Vec<bool>>,
+    brightness: u8,
+}
+
+/// A running array. Dropping it stops the refresh and floats every pin.
+pub struct Charlieplex {
+    layout: Layout,
+    shared: Arc<Mutex<Shared>>,
+    stop: Arc<AtomicBool>,
+    refresh: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl Charlieplex {
+    /// Take BCM `pins`, in the order the layout numbers them, and start
+    /// refreshing.
+    pub fn from_gpio(pins: &[u8], layout: Layout, config: CharlieConfig) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let pins = pins
+            .iter()
+            .map(|&pin| -> Result<IoPin, Box<dyn Error>> {
+                Ok(gpio.get(pin).map_err(|e| format!("charlieplex GPIO {}: {}", pin, e))?.into_io(Mode::Input))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Charlieplex::spawn(pins, layout, config)
+    }
+
+    /// Start the refresh thread on `pins`, one per layout pin.
+    pub fn spawn<P>(pins: Vec<P>, layout: Layout, config: CharlieConfig) -> Result<Self, Box<dyn Error>>
+    where
+        P: TriStatePin + Send + 'static,
+    {
+        config.validate()?;
+        if pins.len() != layout.pins() {
+            return Err(format!("{} pins for a layout of {}", pins.len(), layout.pins()).into());
+        }
+        let n = pins.len();
+        let shared = Arc::new(Mutex::new(Shared {
+            lit: vec![vec![false; n]; n],
+            brightness: config.brightness,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut scanner = Scanner { pins, config };
+        scanner.float_all().map_err(|e| format!("{:?}", e))?;
+        let refresh = {
+            let shared = Arc::clone(&shared);
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name("charlieplex".into())
+                .spawn(move || scanner.run(&shared, &stop))?
+        };
+        Ok(Charlieplex {
+            layout,
+            shared,
+            stop,
+            refresh: Some(refresh),
+        })
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub fn set(&self, x: usize, y: usize, on: bool) -> Result<(), Box<dyn Error>> {
+        let (anode, cathode) = self
+            .layout
+            .pair(x, y)
+            .ok_or_else(|| format!("no LED at {},{} in a {}x{} layout", x, y, self.layout.width(), self.layout.height()))?;
+        self.check_running()?;
+        lock(&self.shared).lit[anode][cathode] = on;
+        Ok(())
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<bool> {
+        let (anode, cathode) = self.layout.pair(x, y)?;
+        Some(lock(&self.shared).lit[anode][cathode])
+    }
+
+    /// Set every LED from a row-by-row frame, one entry per layout LED.
+    pub fn show(&self, frame: &[bool]) -> Result<(), Box<dyn Error>> {
+        if frame.len() != self.layout.len() {
+            return Err(format!("frame of {} for {} LEDs", frame.len(), self.layout.len()).into());
+        }
+        self.check_running()?;
+        let mut shared = lock(&self.shared);
+        for (&(anode, cathode), &on) in self.layout.pairs.iter().zip(frame) {
+            shared.lit[anode][cathode] = on;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
+        self.show(&vec![false; self.layout.len()])
+    }
+
+    /// Set the duty cycle, clamped to 1-100%.
+    pub fn set_brightness(&self, percent: u8) {
+        lock(&self.shared).brightness = percent.clamp(1, 100);
+    }
+
+    pub fn brightness(&self) -> u8 {
+        lock(&self.shared).brightness
+    }
+
+    /// Stop refreshing and float the pins, reporting a pin error that ended
+    /// the refresh thread early.
+    pub fn stop(mut self) -> Result<(), Box<dyn Error>> {
+        self.join()
+    }
+
+    fn check_running(&self) -> Result<(), Box<dyn Error>> {
+        match &self.refresh {
+            Some(handle) if !handle.is_finished() => Ok(()),
+            _ => Err("the charlieplex refresh thread has stopped".into()),
+        }
+    }
+
+    fn join(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.refresh.take().map(JoinHandle::join) {
+            Some(Ok(Err(e))) => Err(format!("charlieplex refresh: {}", e).into()),
+            Some(Err(_)) => Err("charlieplex refresh thread panicked".into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Charlieplex {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+struct Scanner<P> {
+    pins: Vec<P>,
+    config: CharlieConfig,
+}
+
+impl<P: TriStatePin> Scanner<P> {
+    fn run(&mut self, shared: &Mutex<Shared>, stop: &AtomicBool) -> Result<(), String> {
+        let delay = PreciseDelay::new(self.config.spin);
+        let n = self.pins.len();
+        let slot = Duration::from_secs(1) / (self.config.refresh_hz * n as u32);
+        let mut row = vec![false; n];
+        let mut next = Instant::now();
+        let mut anode = 0;
+        while !stop.load(Ordering::Relaxed) {
+            let brightness = {
+                let shared = lock(shared);
+                row.copy_from_slice(&shared.lit[anode]);
+                shared.brightness
+            };
+            // Empty rows keep their slot so brightness doesn't depend on content
+            if row.contains(&true) {
+                self.light(anode, &row).map_err(|e| format!("{:?}", e))?;
+            }
+            delay.until(next + slot * u32::from(brightness) / 100);
+            self.float_all().map_err(|e| format!("{:?}", e))?;
+            next += slot;
+            // Fell behind (a long preemption): resync rather than race
+            if Instant::now() > next + slot {
+                next = Instant::now();
+            }
+            delay.until(next);
+            anode = (anode + 1) % n;
+        }
+        self.float_all().map_err(|e| format!("{:?}", e))
+    }
+
+    /// Cathodes first, then the anode, so nothing lights half-configured.
+    fn light(&mut self, anode: usize, cathodes: &[bool]) -> Result<(), P::Error> {
+        for (pin, &lit) in cathodes.iter().enumerate() {
+            if lit && pin != anode {
+                self.pins[pin].drive(false)?;
+            }
+        }
+        self.pins[anode].drive(true)
+    }
+
+    fn float_all(&mut self) -> Result<(), P::Error> {
+        for pin in &mut self.pins {
+            pin.float()?;
+        }
+        Ok(())
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> std::sync::MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,193 @@
+//! Where the time comes from, so timing logic can run on a virtual clock.
+//!
+//! Debounce, rate limits and bus timestamps read a [`Clock`] rather than
+//! `Instant::now()` directly. The [`SystemClock`] is the default; a
+//! [`SimClock`] only moves when told to, so a simulation or test covering
+//! minutes of button presses and alert repeats runs in no time and does
+//! the same thing every run:
+//!
+//! ```no_run
+//! use rpi_peripherals::clock::SimClock;
+//! use rpi_peripherals::input::{Button, ButtonEvent};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let clock = SimClock::new(7);
+//! let mut button = Button::from_gpio(13)?;
+//! button.set_clock(Arc::new(clock.clone()));
+//! // 30 ms on: past the debounce, well short of a long press
+//! clock.advance(Duration::from_millis(30));
+//! if let Some(ButtonEvent::LongPress) = button.poll()? {
+//!     unreachable!();
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! `sleep` on a `SimClock` moves it on at once, or after a fraction of the
+//! time when [accelerated](SimClock::accelerated) to watch a run go by
+//! quickly. Either way the readings are the same: only `advance` and
+//! `sleep` move it, never the wall clock. A seed drives the optional wake-up
+//! [jitter](SimClock::set_jitter), so timing noise is reproducible too.
+
+use crate::rng::Rng;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How far [`SimClock::accelerated`] goes: from a thousand times slower
+/// to a million times faster, a day in under a tenth of a second.
+pub const SPEED_RANGE: RangeInclusive<f64> = 1e-3..=1e6;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+
+    fn elapsed_since(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+}
+
+/// The real time: `Instant::now()` and `thread::sleep`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A [`SystemClock`] to share, for drivers that take an `Arc<dyn Clock>`.
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// Virtual time. Clones share one clock, so the copy a test keeps
+/// moves the one it handed to the code under test.
+///
+/// Instants it returns only mean something relative to each other: the
+/// clock starts at whatever `Instant::now()` was when it was made.
+#[derive(Clone)]
+pub struct SimClock {
+    state: Arc<Mutex<SimState>>,
+}
+
+struct SimState {
+    origin: Instant,
+    elapsed: Duration,
+    /// How much faster than real time sleeps go; `None` doesn't wait.
+    speed: Option<f64>,
+    jitter: Duration,
+    rng: Rng,
+}
+
+impl SimClock {
+    /// A stepped clock: sleeps return at once. `seed` picks the jitter
+    /// sequence.
+    pub fn new(seed: u64) -> Self {
+        SimClock {
+            state: Arc::new(Mutex::new(SimState {
+                origin: Instant::now(),
+                elapsed: Duration::ZERO,
+                speed: None,
+                jitter: Duration::ZERO,
+                rng: Rng::new(seed),
+            })),
+        }
+    }
+
+    /// Sleeps take `1 / factor` of their length in real time: 60 runs a
+    /// minute a second. `factor` is within [`SPEED_RANGE`].
+    pub fn accelerated(seed: u64, factor: f64) -> Result<Self, String> {
+        if !SPEED_RANGE.contains(&factor) {
+            return Err(format!("speed-up must be from {} to {}, got {}", SPEED_RANGE.start(), SPEED_RANGE.end(), factor));
+        }
+        let clock = SimClock::new(seed);
+        clock.state().speed = Some(factor);
+        Ok(clock)
+    }
+
+    /// Each sleep oversleeps by up to `max`, as a busy scheduler would.
+    pub fn set_jitter(&self, max: Duration) {
+        self.state().jitter = max;
+    }
+
+    /// Move the clock on by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.state().elapsed += duration;
+    }
+
+    /// Virtual time since the clock was made.
+    pub fn elapsed(&self) -> Duration {
+        self.state().elapsed
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        // The state is always left whole, so a panic elsewhere doesn't matter
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> Instant {
+        let state = self.state();
+        state.origin + state.elapsed
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let (duration, speed) = {
+            let mut state = self.state();
+            let jitter = state.jitter_sample();
+            (duration + jitter, state.speed)
+        };
+        if let Some(factor) = speed {
+            // A long sleep slowed down may not fit a Duration; as long as one goes will do
+            thread::sleep(Duration::try_from_secs_f64(duration.as_secs_f64() / factor).unwrap_or(Duration::MAX));
+        }
+        self.advance(duration);
+    }
+}
+
+impl fmt::Debug for SimClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+        f.debug_struct("SimClock")
+            .field("elapsed", &state.elapsed)
+            .field("speed", &state.speed)
+            .field("jitter", &state.jitter)
+            .finish()
+    }
+}
+
+impl SimState {
+    /// 0 to `jitter`.
+    fn jitter_sample(&mut self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        let nanos = self.jitter.as_nanos() as u64;
+        Duration::from_nanos(self.rng.below(nanos.saturating_add(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_up_is_bounded() {
+        for factor in [0.0, 1e-300, 1e300, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(SimClock::accelerated(0, factor).is_err(), "{}", factor);
+        }
+        let clock = SimClock::accelerated(0, 1e6).unwrap();
+        clock.sleep(Duration::from_millis(100));
+        assert_eq!(clock.elapsed(), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,581 @@
+//! TOML configuration for long-running deployments.
+//!
+//! ```toml
+//! [[buses]]
+//! id = 1
+//! speed = "100k"
+//!
+//! [[devices]]
+//! name = "lcd"
+//! driver = "pcf8574"
+//! address = 0x27
+//! rail = "display"
+//!
+//! # Startup carries on without it, minus the pages and thresholds that use it.
+//! [[devices]]
+//! name = "ina219"
+//! driver = "smbus"
+//! address = 0x40
+//! optional = true
+//! # At most 20 reads a second, and the LCD goes first.
+//! rate = 20
+//! priority = "low"
+//!
+//! # Load switches, powered up in this order.
+//! [[rails]]
+//! name = "display"
+//! pin = 22
+//! active_low = true
+//! settle = "50ms"
+//!
+//! [monitor]
+//! interval = "5s"
+//! recover_after = 3      # stuck rounds before bus recovery
+//!
+//! [thresholds."ina219.current"]
+//! max = 1.0
+//!
+//! # Derived measurements, recorded and exported like the rest.
+//! [watches]
+//! "ina219.power" = "ina219.voltage * ina219.current"
+//!
+//! # Running sums, saved across restarts.
+//! [totals]
+//! state = "/var/lib/rpi_peripherals/totals.json"
+//!
+//! [totals.counters."rail.energy"]
+//! source = "ina219.power"
+//! kind = "rate"
+//! per = "1h"
+//!
+//! # Other Pis to poll with `fleet`.
+//! [[fleet.agents]]
+//! name = "garage"
+//! url = "http://10.0.0.21:8080"
+//!
+//! # Audio-reactive LEDs for `leds react`.
+//! [reactive]
+//! device = "plughw:1"
+//! effect = "spectrum"
+//! palette = ["#0000ff", "#00ff00", "#ff0000"]
+//! decay = "400ms"
+//!
+//! # What each alert severity sets off; see `alert`.
+//! [alerts]
+//! quiet_hours = "22:00-07:00"
+//!
+//! [alerts.buzzer]
+//! pin = 18
+//!
+//! [[pages]]
+//! lines = ["{ip}", "{cpu_temp}"]
+//! duration = "4s"
+//!
+//! # What happens when a reading crosses a line; see `rules`.
+//! [[rules]]
+//! name = "overcurrent"
+//! when = "ina219.current > 1.0"
+//! actions = ["flash", "buzz alarm", "mqtt"]
+//!
+//! [watchdog]
+//! max_error_rate = 0.2
+//! window = "60s"
+//! lockups = 3            # hung-bus errors in a row before `sysinfo` recovers
+//!
+//! # How readings are shown; stored values stay in °C / hPa / mm.
+//! [units]
+//! temperature = "f"
+//!
+//! [history]
+//! depth = 720
+//!
+//! [mqtt]
+//! broker = "homeassistant.local"
+//!
+//! # Selected with --profile bench: entries replace base ones with the same
+//! # name/id/key, new ones are added.
+//! [profile.bench.monitor]
+//! interval = "1s"
+//!
+//! [[profile.bench.devices]]
+//! name = "lcd"
+//! driver = "pcf8574"
+//! address = 0x3F
+//! ```
+//!
+//! `[fleet]`, `[reactive]` and `[mqtt]` are only known with the `fleet`,
+//! `leds` and `audio`, and `mqtt` features; without them a config that has
+//! them is turned down as having unknown fields.
+
+mod watch;
+
+pub use watch::{ConfigWatcher, ReloadOutcome};
+
+use crate::address::Address;
+use crate::alert::AlertConfig;
+use crate::bus::{DevicePolicy, Priority};
+#[cfg(feature = "fleet")]
+use crate::fleet::FleetConfig;
+#[cfg(feature = "lcd")]
+use crate::lcd::Template;
+#[cfg(all(feature = "leds", feature = "audio"))]
+use crate::leds::reactive::ReactiveConfig;
+use crate::history::HistoryConfig;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::MqttConfig;
+use crate::parse::serde_helpers;
+use crate::power::SwitchConfig;
+use crate::rules::{self, RuleConfig};
+use crate::startup::StartupPlan;
+use crate::totals::TotalsConfig;
+use crate::units::UnitsConfig;
+use crate::watchdog::Policy;
+use crate::watches;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub buses: Vec<BusConfig>,
+    #[serde(default)]
+    pub devices: Vec<DeviceConfig>,
+    /// GPIO-switched supplies, in power-up order.
+    #[serde(default)]
+    pub rails: Vec<RailConfig>,
+    #[serde(default)]
+    pub monitor: MonitorConfig,
+    /// Limits keyed by measurement name, e.g. `"ina219.current"`.
+    #[serde(default)]
+    pub thresholds: BTreeMap<String, Threshold>,
+    /// Watch expressions keyed by the measurement name they record as.
+    #[serde(default)]
+    pub watches: BTreeMap<String, String>,
+    /// Totalizers and where their values are saved.
+    #[serde(default)]
+    pub totals: TotalsConfig,
+    /// Agents polled by the `fleet` commands.
+    #[cfg(feature = "fleet")]
+    #[serde(default)]
+    pub fleet: FleetConfig,
+    /// Audio input and effect for an LED strip.
+    #[cfg(all(feature = "leds", feature = "audio"))]
+    #[serde(default)]
+    pub reactive: ReactiveConfig,
+    /// The outputs each alert severity sets off.
+    #[serde(default)]
+    pub alerts: AlertConfig,
+    /// Pages rotated on the display.
+    #[serde(default)]
+    pub pages: Vec<PageConfig>,
+    /// Conditions on measurements and what they set off.
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    /// Error budget for the device watchdog.
+    #[serde(default)]
+    pub watchdog: Policy,
+    /// Display units for readings.
+    #[serde(default)]
+    pub units: UnitsConfig,
+    /// Ring buffer depths for in-memory measurement history.
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Broker to publish readings and bus events to, if any.
+    #[cfg(feature = "mqtt")]
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+    /// Named overrides for different deployments of the same hardware.
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+}
+
+/// Overrides applied on top of the base config by [`Config::with_profile`].
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default)]
+    pub buses: Vec<BusConfig>,
+    #[serde(default)]
+    pub devices: Vec<DeviceConfig>,
+    #[serde(default)]
+    pub rails: Vec<RailConfig>,
+    pub monitor: Option<MonitorConfig>,
+    #[serde(default)]
+    pub thresholds: BTreeMap<String, Threshold>,
+    #[serde(default)]
+    pub watches: BTreeMap<String, String>,
+    pub totals: Option<TotalsConfig>,
+    #[cfg(feature = "fleet")]
+    pub fleet: Option<FleetConfig>,
+    #[cfg(all(feature = "leds", feature = "audio"))]
+    pub reactive: Option<ReactiveConfig>,
+    pub alerts: Option<AlertConfig>,
+    /// Replaces the base pages entirely when present.
+    pub pages: Option<Vec<PageConfig>>,
+    /// Likewise for the rules.
+    pub rules: Option<Vec<RuleConfig>>,
+    pub watchdog: Option<Policy>,
+    pub units: Option<UnitsConfig>,
+    pub history: Option<HistoryConfig>,
+    #[cfg(feature = "mqtt")]
+    pub mqtt: Option<MqttConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BusConfig {
+    pub id: u8,
+    /// Expected clock speed; the kernel sets the real one at boot.
+    #[serde(default, deserialize_with = "serde_helpers::frequency_opt")]
+    pub speed: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+    pub name: String,
+    pub driver: String,
+    #[serde(default = "default_bus")]
+    pub bus: u8,
+    #[serde(default)]
+    pub address: Option<u16>,
+    /// Name of the switched rail that powers the device.
+    #[serde(default)]
+    pub rail: Option<String>,
+    /// Devices or rails that must be up before this one is initialized.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// How long the init sequence may take before startup gives up on it.
+    #[serde(default, deserialize_with = "serde_helpers::duration_opt")]
+    pub init_timeout: Option<Duration>,
+    /// Start without it if it doesn't come up; pages and thresholds that
+    /// use its readings are dropped instead.
+    #[serde(default)]
+    pub optional: bool,
+    /// Most transactions per second it may send on a shared bus.
+    #[serde(default)]
+    pub rate: Option<f64>,
+    /// Transactions it may send back to back before `rate` holds it back.
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+    /// Its place in the queue when other devices want the bus too.
+    #[serde(default)]
+    pub priority: Priority,
+    /// Wait after each write: an EEPROM's page write cycle, an LCD's
+    /// instruction time. `eeprom --tune` and `lcd tune` measure it.
+    #[serde(default, deserialize_with = "serde_helpers::duration_opt")]
+    pub write_delay: Option<Duration>,
+    /// An LCD's wait after a clear or home.
+    #[serde(default, deserialize_with = "serde_helpers::duration_opt")]
+    pub clear_delay: Option<Duration>,
+}
+
+fn default_bus() -> u8 {
+    1
+}
+
+fn default_burst() -> u32 {
+    DevicePolicy::default().burst
+}
+
+impl DeviceConfig {
+    /// Its share of a shared bus.
+    pub fn policy(&self) -> DevicePolicy {
+        DevicePolicy {
+            rate: self.rate,
+            burst: self.burst,
+            priority: self.priority,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RailConfig {
+    pub name: String,
+    /// BCM GPIO number driving the switch's enable or the FET gate.
+    pub pin: u8,
+    #[serde(default)]
+    pub active_low: bool,
+    #[serde(default = "default_settle", deserialize_with = "serde_helpers::duration")]
+    pub settle: Duration,
+    #[serde(default = "default_off_time", deserialize_with = "serde_helpers::duration")]
+    pub off_time: Duration,
+}
+
+fn default_settle() -> Duration {
+    SwitchConfig::default().settle
+}
+
+fn default_off_time() -> Duration {
+    SwitchConfig::default().off_time
+}
+
+impl RailConfig {
+    pub fn switch(&self) -> SwitchConfig {
+        SwitchConfig {
+            active_low: self.active_low,
+            settle: self.settle,
+            off_time: self.off_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorConfig {
+    #[serde(default = "default_interval", deserialize_with = "serde_helpers::duration")]
+    pub interval: Duration,
+    /// Stuck-bus rounds in a row before `monitor` runs bus recovery.
+    #[serde(default = "default_recover_after")]
+    pub recover_after: u32,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        MonitorConfig {
+            interval: default_interval(),
+            recover_after: default_recover_after(),
+        }
+    }
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_recover_after() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Threshold {
+    pub fn contains(&self, value: f64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PageConfig {
+    pub lines: Vec<String>,
+    #[serde(default = "default_interval", deserialize_with = "serde_helpers::duration")]
+    pub duration: Duration,
+}
+
+impl PageConfig {
+    /// Names inside `{...}` in the page's lines, e.g. `ip` or `bme280.temperature`,
+    /// without any `:format` after them.
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().flat_map(|line| {
+            line.split('{').skip(1).filter_map(|rest| {
+                let (field, _) = rest.split_once('}')?;
+                Some(field.split_once(':').map_or(field, |(name, _)| name).trim())
+            })
+        })
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        text.parse()
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Load `path` and apply `profile` on top, if one is named.
+    pub fn load_profile(path: &Path, profile: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let config = Config::load(path)?;
+        match profile {
+            Some(name) => config.with_profile(name),
+            None => Ok(config),
+        }
+    }
+
+    /// The effective config for one deployment. Buses merge by id, devices and
+    /// rails by name, thresholds and watches by key; monitor, totals, fleet,
+    /// reactive, alerts, pages, watchdog, units, history and mqtt are replaced
+    /// wholesale.
+    pub fn with_profile(mut self, name: &str) -> Result<Self, Box<dyn Error>> {
+        let Some(profile) = self.profile.remove(name) else {
+            let known: Vec<_> = self.profile.keys().map(String::as_str).collect();
+            return Err(format!("no profile '{}' (available: {})", name, known.join(", ")).into());
+        };
+        self.profile.clear();
+
+        for bus in profile.buses {
+            match self.buses.iter_mut().find(|b| b.id == bus.id) {
+                Some(existing) => *existing = bus,
+                None => self.buses.push(bus),
+            }
+        }
+        for device in profile.devices {
+            match self.devices.iter_mut().find(|d| d.name == device.name) {
+                Some(existing) => *existing = device,
+                None => self.devices.push(device),
+            }
+        }
+        for rail in profile.rails {
+            match self.rails.iter_mut().find(|r| r.name == rail.name) {
+                Some(existing) => *existing = rail,
+                None => self.rails.push(rail),
+            }
+        }
+        if let Some(monitor) = profile.monitor {
+            self.monitor = monitor;
+        }
+        self.thresholds.extend(profile.thresholds);
+        self.watches.extend(profile.watches);
+        if let Some(totals) = profile.totals {
+            self.totals = totals;
+        }
+        #[cfg(feature = "fleet")]
+        if let Some(fleet) = profile.fleet {
+            self.fleet = fleet;
+        }
+        #[cfg(all(feature = "leds", feature = "audio"))]
+        if let Some(reactive) = profile.reactive {
+            self.reactive = reactive;
+        }
+        if let Some(alerts) = profile.alerts {
+            self.alerts = alerts;
+        }
+        if let Some(pages) = profile.pages {
+            self.pages = pages;
+        }
+        if let Some(rules) = profile.rules {
+            self.rules = rules;
+        }
+        if let Some(watchdog) = profile.watchdog {
+            self.watchdog = watchdog;
+        }
+        if let Some(units) = profile.units {
+            self.units = units;
+        }
+        if let Some(history) = profile.history {
+            self.history = history;
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = profile.mqtt {
+            self.mqtt = Some(mqtt);
+        }
+
+        self.validate()
+            .map_err(|e| format!("profile '{}': {}", name, e))?;
+        Ok(self)
+    }
+
+    /// Check the things serde can't: cross references and value ranges.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let mut names = HashSet::new();
+        for device in &self.devices {
+            if !names.insert(device.name.as_str()) {
+                return Err(format!("device '{}' is defined twice", device.name).into());
+            }
+            if !self.buses.is_empty() && !self.buses.iter().any(|b| b.id == device.bus) {
+                return Err(format!("device '{}' is on undeclared bus {}", device.name, device.bus).into());
+            }
+            if let Some(address) = device.address {
+                Address::from_raw(address).map_err(|e| format!("device '{}': {}", device.name, e))?;
+            }
+            device.policy().validate().map_err(|e| format!("device '{}': {}", device.name, e))?;
+            if !device.optional {
+                if let Some(dep) = device
+                    .depends_on
+                    .iter()
+                    .find(|dep| self.device(dep).is_some_and(|d| d.optional))
+                {
+                    return Err(format!(
+                        "device '{}' depends on optional device '{}'; mark it optional too",
+                        device.name, dep
+                    )
+                    .into());
+                }
+            }
+            if let Some(rail) = &device.rail {
+                if !self.rails.iter().any(|r| &r.name == rail) {
+                    return Err(format!("device '{}' is on undeclared rail '{}'", device.name, rail).into());
+                }
+            }
+        }
+        let mut rail_names = HashSet::new();
+        let mut pins = HashSet::new();
+        for rail in &self.rails {
+            if !rail_names.insert(rail.name.as_str()) {
+                return Err(format!("rail '{}' is defined twice", rail.name).into());
+            }
+            if !pins.insert(rail.pin) {
+                return Err(format!("rail '{}' reuses GPIO {}", rail.name, rail.pin).into());
+            }
+            if names.contains(rail.name.as_str()) {
+                return Err(format!("'{}' names both a rail and a device", rail.name).into());
+            }
+        }
+        StartupPlan::from_config(self)?;
+        if self.monitor.interval.is_zero() {
+            return Err("monitor.interval must be greater than zero".into());
+        }
+        if self.monitor.recover_after == 0 {
+            return Err("monitor.recover_after must be at least 1".into());
+        }
+        for (name, threshold) in &self.thresholds {
+            if let (Some(min), Some(max)) = (threshold.min, threshold.max) {
+                if min > max {
+                    return Err(format!("threshold '{}' has min {} above max {}", name, min, max).into());
+                }
+            }
+        }
+        watches::parse_all(&self.watches)?;
+        self.totals.validate()?;
+        #[cfg(feature = "fleet")]
+        self.fleet.validate()?;
+        #[cfg(all(feature = "leds", feature = "audio"))]
+        self.reactive.validate()?;
+        self.alerts.validate()?;
+        for (n, page) in self.pages.iter().enumerate() {
+            if page.lines.is_empty() {
+                return Err(format!("page {} has no lines", n + 1).into());
+            }
+            #[cfg(feature = "lcd")]
+            for line in &page.lines {
+                Template::parse(line).map_err(|e| format!("page {}: {}", n + 1, e))?;
+            }
+        }
+        rules::validate(&self.rules)?;
+        self.watchdog.validate()?;
+        self.history.validate()?;
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn device(&self, name: &str) -> Option<&DeviceConfig> {
+        self.devices.iter().find(|d| d.name == name)
+    }
+}
+
+impl FromStr for Config {
+    type Err = Box<dyn Error>;
+
+    /// Parse and validate.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: Config = toml::from_str(s)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
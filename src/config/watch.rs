@@ -0,0 +1,130 @@
+use super::Config;
+use crate::say;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+type Applier = Box<dyn FnMut(&Config) -> Result<(), Box<dyn Error>> + Send>;
+
+/// What a [`ConfigWatcher::poll`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReloadOutcome {
+    Unchanged,
+    Applied,
+    /// The new file didn't parse or validate; the running config was kept.
+    Rejected(String),
+    /// An applier refused the new config and everything was put back.
+    RolledBack(String),
+}
+
+/// Reloads a config file when it changes and pushes it to the running code.
+///
+/// Subsystems register appliers with [`ConfigWatcher::on_change`]. A new
+/// config is applied to each in turn; if one fails, the ones already updated
+/// get the previous config again so the daemon never runs half-reloaded.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    profile: Option<String>,
+    current: Arc<RwLock<Config>>,
+    modified: Option<SystemTime>,
+    appliers: Vec<Applier>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: impl Into<PathBuf>, profile: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let config = Config::load_profile(&path, profile)?;
+        Ok(ConfigWatcher {
+            modified: modified(&path),
+            path,
+            profile: profile.map(str::to_string),
+            current: Arc::new(RwLock::new(config)),
+            appliers: Vec::new(),
+        })
+    }
+
+    /// Shared handle to the live config, updated in place on reload.
+    pub fn current(&self) -> Arc<RwLock<Config>> {
+        Arc::clone(&self.current)
+    }
+
+    pub fn snapshot(&self) -> Config {
+        self.current.read().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    pub fn on_change<F>(&mut self, applier: F)
+    where
+        F: FnMut(&Config) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        self.appliers.push(Box::new(applier));
+    }
+
+    /// Check the file once and reload it if its modification time moved.
+    pub fn poll(&mut self) -> ReloadOutcome {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return ReloadOutcome::Unchanged;
+        }
+        self.modified = modified;
+
+        let new = match Config::load_profile(&self.path, self.profile.as_deref()) {
+            Ok(config) => config,
+            Err(e) => return ReloadOutcome::Rejected(e.to_string()),
+        };
+        let old = self.snapshot();
+        if new == old {
+            return ReloadOutcome::Unchanged;
+        }
+
+        for i in 0..self.appliers.len() {
+            if let Err(e) = (self.appliers[i])(&new) {
+                for applier in self.appliers[..=i].iter_mut() {
+                    // Best effort: the old config was accepted before.
+                    let _ = applier(&old);
+                }
+                return ReloadOutcome::RolledBack(e.to_string());
+            }
+        }
+        *self.current.write().unwrap_or_else(|p| p.into_inner()) = new;
+        ReloadOutcome::Applied
+    }
+
+    /// [`poll`](Self::poll), logging what it did; whether a new config
+    /// was applied.
+    pub fn poll_logged(&mut self) -> bool {
+        match self.poll() {
+            ReloadOutcome::Unchanged => false,
+            ReloadOutcome::Applied => {
+                say!("🔄 Reloaded {}", self.path.display());
+                true
+            }
+            ReloadOutcome::Rejected(e) => {
+                say!("⚠️  Ignoring invalid config change: {}", e);
+                false
+            }
+            ReloadOutcome::RolledBack(e) => {
+                say!("⚠️  Config change rolled back: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Poll on a background thread every `interval` until `stop` is set,
+    /// logging each reload.
+    pub fn spawn(mut self, interval: Duration, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                self.poll_logged();
+                thread::sleep(interval);
+            }
+        })
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,74 @@
+//! Checksums used by the bus protocols.
+
+/// CRC-8 with polynomial x^8 + x^2 + x + 1 (0x07), initial value 0: the SMBus PEC.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, &byte| crc8_update(crc, byte))
+}
+
+pub fn crc8_update(crc: u8, byte: u8) -> u8 {
+    let mut crc = crc ^ byte;
+    for _ in 0..8 {
+        crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+    }
+    crc
+}
+
+/// Dallas/Maxim CRC-8, polynomial x^8 + x^5 + x^4 + 1 (0x31 reflected),
+/// initial value 0: 1-Wire ROM codes and scratchpads.
+pub fn crc8_maxim(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, &byte| {
+        let mut crc = crc ^ byte;
+        for _ in 0..8 {
+            crc = if crc & 0x01 != 0 { (crc >> 1) ^ 0x8C } else { crc >> 1 };
+        }
+        crc
+    })
+}
+
+/// CRC-16/CCITT-FALSE, polynomial x^16 + x^12 + x^5 + 1 (0x1021), initial
+/// value 0xFFFF: the framed link's checksum.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| crc16_update(crc, byte))
+}
+
+pub fn crc16_update(crc: u16, byte: u8) -> u16 {
+    let mut crc = crc ^ (u16::from(byte) << 8);
+    for _ in 0..8 {
+        crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+    }
+    crc
+}
+
+/// CRC-16/MODBUS: polynomial 0x8005 reflected (0xA001), initial value
+/// 0xFFFF. Modbus RTU sends it low byte first.
+pub fn crc16_modbus(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        let mut crc = crc ^ u16::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 0x0001 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The catalogue's check values: each CRC of the ASCII digits 1-9.
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn check_values() {
+        assert_eq!(crc8(CHECK), 0xF4);
+        assert_eq!(crc8_maxim(CHECK), 0xA1);
+        assert_eq!(crc16(CHECK), 0x29B1);
+        assert_eq!(crc16_modbus(CHECK), 0x4B37);
+    }
+
+    #[test]
+    fn updates_match_whole_buffer() {
+        assert_eq!(CHECK.iter().fold(0, |crc, &b| crc8_update(crc, b)), crc8(CHECK));
+        assert_eq!(CHECK.iter().fold(0xFFFF, |crc, &b| crc16_update(crc, b)), crc16(CHECK));
+    }
+}
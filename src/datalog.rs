@@ -0,0 +1,402 @@
+//! `log`: sample configured sensors on an interval into CSV or SQLite.
+//!
+//! Each [`Sample`] is one value from one device, so any mix of drivers
+//! fits one file: a row is the UTC time, the device's config name, the
+//! quantity, the value and its unit. [`open_device`] turns a configured
+//! device into a [`LoggedDevice`] for the drivers in [`LOGGED_DRIVERS`].
+//!
+//! A [`DataLogger`] writes through a [`LogSink`] and starts a new file when
+//! its [`Rotation`] says: past a size, the full file is renamed
+//! `samples.1.csv`, `samples.2.csv` and so on and a new one begun; daily,
+//! each local day gets its own `samples-2024-06-01.csv`. Rows are buffered
+//! until [`DataLogger::flush`], which the `log` command calls every
+//! `--flush` and once more on the way out, so Ctrl-C loses nothing.
+//!
+//! ```no_run
+//! use rpi_peripherals::datalog::{DataLogger, Format, Rotation, Sample};
+//! use std::path::Path;
+//! use std::time::SystemTime;
+//!
+//! let rotation = Rotation { max_size: Some(10 << 20), daily: true };
+//! let mut logger = DataLogger::open(Path::new("samples.db"), Format::Sqlite, rotation)?;
+//! logger.append(&[Sample::new(SystemTime::now(), "solar", "power", 12.5, "W")])?;
+//! logger.flush()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod csv;
+mod sqlite;
+
+pub use csv::{CsvSink, CSV_HEADER};
+pub use sqlite::SqliteSink;
+
+use crate::address::{Address, AddressedI2c};
+use crate::config::DeviceConfig;
+use crate::localtime;
+use crate::sensors::{Apds9960, Engine, Ina219, Ina226, PowerMonitor};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Drivers [`open_device`] can sample.
+pub const LOGGED_DRIVERS: &[&str] = &["ina219", "ina226", "apds9960", "pcf8574"];
+
+/// The config has no shunt settings, so INA2xx devices are taken to be on
+/// the common 0.1 Ω breakout, calibrated to its full range.
+const SHUNT_OHMS: f64 = 0.1;
+const INA219_MAX_CURRENT: f64 = 3.2;
+const INA226_MAX_CURRENT: f64 = 0.8;
+
+/// One value from one device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub time: SystemTime,
+    /// The device's config name.
+    pub device: String,
+    pub quantity: String,
+    /// Written empty, or NULL, when not finite.
+    pub value: f64,
+    pub unit: String,
+}
+
+impl Sample {
+    pub fn new(time: SystemTime, device: &str, quantity: &str, value: f64, unit: &str) -> Self {
+        Sample {
+            time,
+            device: device.to_string(),
+            quantity: quantity.to_string(),
+            value,
+            unit: unit.to_string(),
+        }
+    }
+
+    /// RFC 3339 in UTC to the millisecond, e.g. `2024-06-01T12:00:00.250Z`,
+    /// which sorts as text.
+    pub fn timestamp(&self) -> String {
+        let since = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seconds = since.as_secs();
+        let (year, month, day) = civil_date(seconds / 86_400);
+        let time = seconds % 86_400;
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60,
+            time % 60,
+            since.subsec_millis()
+        )
+    }
+}
+
+/// Where samples go.
+pub trait LogSink: Send {
+    /// May only buffer the rows.
+    fn append(&mut self, samples: &[Sample]) -> Result<(), Box<dyn Error>>;
+
+    /// Get everything appended so far onto the disk.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Sqlite,
+}
+
+impl Format {
+    /// SQLite for `.db`, `.sqlite` and `.sqlite3`, else CSV.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("db" | "sqlite" | "sqlite3") => Format::Sqlite,
+            _ => Format::Csv,
+        }
+    }
+
+    fn open(self, path: &Path) -> Result<Box<dyn LogSink>, Box<dyn Error>> {
+        Ok(match self {
+            Format::Csv => Box::new(CsvSink::open(path)?),
+            Format::Sqlite => Box::new(SqliteSink::open(path)?),
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "sqlite" | "sqlite3" | "db" => Ok(Format::Sqlite),
+            _ => Err(format!("unknown log format '{}' (csv or sqlite)", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Csv => "CSV",
+            Format::Sqlite => "SQLite",
+        })
+    }
+}
+
+/// When to start a new file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rotation {
+    /// Bytes; checked after each flush, so a file can run over by one
+    /// flush's worth.
+    pub max_size: Option<u64>,
+    /// One file per local day, the date added to its name.
+    pub daily: bool,
+}
+
+pub struct DataLogger {
+    base: PathBuf,
+    format: Format,
+    rotation: Rotation,
+    /// The local date the current file is for, with daily rotation.
+    date: Option<String>,
+    path: PathBuf,
+    sink: Box<dyn LogSink>,
+    rows: u64,
+    /// Rows in the current file from this run, so an empty one isn't
+    /// rotated for the size of its header or schema.
+    rows_in_file: u64,
+}
+
+impl DataLogger {
+    /// Log to `path`, or with daily rotation to today's file beside it.
+    pub fn open(path: &Path, format: Format, rotation: Rotation) -> Result<Self, Box<dyn Error>> {
+        let date = if rotation.daily { Some(localtime::now().ok_or("can't read the local date")?.date()) } else { None };
+        let current = current_path(path, date.as_deref());
+        Ok(DataLogger {
+            base: path.to_path_buf(),
+            format,
+            rotation,
+            date,
+            sink: format.open(&current)?,
+            path: current,
+            rows: 0,
+            rows_in_file: 0,
+        })
+    }
+
+    /// The file being written now.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Rows appended since the logger was opened, across rotations.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Moving to a new day's file first if the first sample is from a
+    /// later day than the current file.
+    pub fn append(&mut self, samples: &[Sample]) -> Result<(), Box<dyn Error>> {
+        let Some(first) = samples.first() else {
+            return Ok(());
+        };
+        if self.rotation.daily {
+            let date = localtime::at(first.time).ok_or("can't read the local date")?.date();
+            if self.date.as_deref() != Some(date.as_str()) {
+                self.sink.flush()?;
+                self.path = current_path(&self.base, Some(&date));
+                self.sink = self.format.open(&self.path)?;
+                self.date = Some(date);
+                self.rows_in_file = 0;
+            }
+        }
+        self.sink.append(samples)?;
+        self.rows += samples.len() as u64;
+        self.rows_in_file += samples.len() as u64;
+        Ok(())
+    }
+
+    /// Write out buffered rows, then rotate if the file has grown past
+    /// [`Rotation::max_size`]. The path the full file was renamed to is
+    /// returned.
+    pub fn flush(&mut self) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        self.sink.flush()?;
+        let Some(max_size) = self.rotation.max_size else {
+            return Ok(None);
+        };
+        if self.rows_in_file == 0 || fs::metadata(&self.path)?.len() < max_size {
+            return Ok(None);
+        }
+        let rotated = (1..)
+            .map(|n| numbered_path(&self.path, n))
+            .find(|p| !p.exists())
+            .expect("some number is free");
+        // Closed before the rename, so SQLite has nothing left to write
+        self.sink = Box::new(NullSink);
+        fs::rename(&self.path, &rotated)
+            .map_err(|e| format!("cannot rename {} to {}: {}", self.path.display(), rotated.display(), e))?;
+        self.sink = self.format.open(&self.path)?;
+        self.rows_in_file = 0;
+        Ok(Some(rotated))
+    }
+}
+
+/// Stands in while the real sink is swapped.
+struct NullSink;
+
+impl LogSink for NullSink {
+    fn append(&mut self, _: &[Sample]) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// `samples.csv`, or `samples-2024-06-01.csv` for that day.
+fn current_path(base: &Path, date: Option<&str>) -> PathBuf {
+    match date {
+        None => base.to_path_buf(),
+        Some(date) => with_stem_suffix(base, &format!("-{}", date)),
+    }
+}
+
+/// `samples.csv` to `samples.3.csv`.
+fn numbered_path(path: &Path, n: u32) -> PathBuf {
+    with_stem_suffix(path, &format!(".{}", n))
+}
+
+fn with_stem_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// `(quantity, value, unit)` for everything a device measures.
+pub type Values = Vec<(&'static str, f64, &'static str)>;
+
+/// One configured device, read for the log.
+pub trait LoggedDevice: Send {
+    /// The config name, written on each of its rows.
+    fn name(&self) -> &str;
+
+    fn sample(&mut self) -> Result<Values, Box<dyn Error>>;
+}
+
+struct PowerDevice {
+    name: String,
+    sensor: Box<dyn PowerMonitor + Send>,
+}
+
+impl LoggedDevice for PowerDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sample(&mut self) -> Result<Values, Box<dyn Error>> {
+        let reading = self.sensor.read_power()?;
+        Ok(vec![
+            ("bus_voltage", reading.bus_voltage, "V"),
+            ("shunt_voltage", reading.shunt_voltage, "V"),
+            ("current", reading.current, "A"),
+            ("power", reading.power, "W"),
+        ])
+    }
+}
+
+struct LightDevice<I2C> {
+    name: String,
+    sensor: Apds9960<I2C>,
+}
+
+impl<I2C: AddressedI2c + Send> LoggedDevice for LightDevice<I2C> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sample(&mut self) -> Result<Values, Box<dyn Error>> {
+        let proximity = self.sensor.proximity()?;
+        let color = self.sensor.color()?;
+        Ok(vec![
+            ("proximity", f64::from(proximity), ""),
+            ("clear", f64::from(color.clear), ""),
+            ("red", f64::from(color.red), ""),
+            ("green", f64::from(color.green), ""),
+            ("blue", f64::from(color.blue), ""),
+        ])
+    }
+}
+
+struct PortDevice<I2C> {
+    name: String,
+    i2c: I2C,
+    address: Address,
+}
+
+impl<I2C: AddressedI2c + Send> LoggedDevice for PortDevice<I2C> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sample(&mut self) -> Result<Values, Box<dyn Error>> {
+        let mut port = [0];
+        self.i2c.read_at(self.address, &mut port)?;
+        Ok(vec![("port", f64::from(port[0]), "")])
+    }
+}
+
+/// Set up `device` for logging on `i2c`, one of [`LOGGED_DRIVERS`].
+pub fn open_device<I2C>(device: &DeviceConfig, i2c: I2C) -> Result<Box<dyn LoggedDevice>, Box<dyn Error>>
+where
+    I2C: AddressedI2c + Send + 'static,
+{
+    let raw = device.address.ok_or_else(|| format!("device '{}' has no address to read", device.name))?;
+    let address = Address::from_raw(raw)?;
+    let name = device.name.clone();
+    Ok(match device.driver.as_str() {
+        "ina219" => Box::new(PowerDevice {
+            name,
+            sensor: Box::new(Ina219::new(i2c, address, SHUNT_OHMS, INA219_MAX_CURRENT)?),
+        }),
+        "ina226" => Box::new(PowerDevice {
+            name,
+            sensor: Box::new(Ina226::new(i2c, address, SHUNT_OHMS, INA226_MAX_CURRENT)?),
+        }),
+        "apds9960" => {
+            let mut sensor = Apds9960::new(i2c, address)?;
+            sensor.set_engine(Engine::Proximity, true)?;
+            sensor.set_engine(Engine::Color, true)?;
+            Box::new(LightDevice { name, sensor })
+        }
+        "pcf8574" => Box::new(PortDevice { name, i2c, address }),
+        other => {
+            return Err(format!("device '{}': a {} has no readings to log ({})", device.name, other, LOGGED_DRIVERS.join(", ")).into())
+        }
+    })
+}
+
+/// Year, month and day of `days` since 1970-01-01, after Howard Hinnant's
+/// `civil_from_days`.
+fn civil_date(days: u64) -> (i64, u32, u32) {
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
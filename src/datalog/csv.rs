@@ -0,0 +1,62 @@
+use super::{LogSink, Sample};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub const CSV_HEADER: &str = "time,device,quantity,value,unit";
+
+/// A CSV file, one row per sample, appended to if it already exists.
+pub struct CsvSink {
+    file: BufWriter<File>,
+}
+
+impl CsvSink {
+    /// Open `path` for appending, writing the header if it is new or empty.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+        let empty = file.metadata()?.len() == 0;
+        let mut file = BufWriter::new(file);
+        if empty {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        Ok(CsvSink { file })
+    }
+}
+
+impl LogSink for CsvSink {
+    fn append(&mut self, samples: &[Sample]) -> Result<(), Box<dyn Error>> {
+        for sample in samples {
+            let value = if sample.value.is_finite() { sample.value.to_string() } else { String::new() };
+            writeln!(
+                self.file,
+                "{},{},{},{},{}",
+                sample.timestamp(),
+                field(&sample.device),
+                field(&sample.quantity),
+                value,
+                field(&sample.unit)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        Ok(())
+    }
+}
+
+/// Quoted if it would otherwise split the row.
+fn field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,181 @@
+use super::{LogSink, Sample};
+use std::error::Error;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The shared library is loaded when the first database is opened, so
+/// CSV logging works on a system without it.
+const LIBRARY: &str = "libsqlite3.so.0";
+
+const SQLITE_OK: c_int = 0;
+
+const CREATE: &str = "CREATE TABLE IF NOT EXISTS samples (
+    time TEXT NOT NULL,
+    device TEXT NOT NULL,
+    quantity TEXT NOT NULL,
+    value REAL,
+    unit TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS samples_time ON samples (time);";
+
+type Callback = unsafe extern "C" fn(*mut c_void, c_int, *mut *mut c_char, *mut *mut c_char) -> c_int;
+type OpenFn = unsafe extern "C" fn(*const c_char, *mut *mut c_void) -> c_int;
+type ExecFn = unsafe extern "C" fn(*mut c_void, *const c_char, Option<Callback>, *mut c_void, *mut *mut c_char) -> c_int;
+type ErrmsgFn = unsafe extern "C" fn(*mut c_void) -> *const c_char;
+type FreeFn = unsafe extern "C" fn(*mut c_void);
+type CloseFn = unsafe extern "C" fn(*mut c_void) -> c_int;
+
+/// The few entry points used, looked up once.
+struct Api {
+    open: OpenFn,
+    exec: ExecFn,
+    errmsg: ErrmsgFn,
+    free: FreeFn,
+    close: CloseFn,
+}
+
+fn api() -> Result<&'static Api, Box<dyn Error>> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(load).as_ref().map_err(|e| e.clone().into())
+}
+
+fn load() -> Result<Api, String> {
+    let name = CString::new(LIBRARY).expect("no NUL in the library name");
+    // SAFETY: dlopen and dlsym are given NUL-terminated names, and each
+    // symbol is cast to the signature the SQLite C API documents. The
+    // library is never closed, so the pointers stay valid.
+    unsafe {
+        let handle = libc::dlopen(name.as_ptr(), libc::RTLD_NOW);
+        if handle.is_null() {
+            return Err(format!("SQLite logging needs {} (apt install libsqlite3-0)", LIBRARY));
+        }
+        let symbol = |name: &str| -> Result<*mut c_void, String> {
+            let c_name = CString::new(name).expect("no NUL in symbol names");
+            let pointer = libc::dlsym(handle, c_name.as_ptr());
+            if pointer.is_null() {
+                return Err(format!("{} has no {}", LIBRARY, name));
+            }
+            Ok(pointer)
+        };
+        Ok(Api {
+            open: std::mem::transmute::<*mut c_void, OpenFn>(symbol("sqlite3_open")?),
+            exec: std::mem::transmute::<*mut c_void, ExecFn>(symbol("sqlite3_exec")?),
+            errmsg: std::mem::transmute::<*mut c_void, ErrmsgFn>(symbol("sqlite3_errmsg")?),
+            free: std::mem::transmute::<*mut c_void, FreeFn>(symbol("sqlite3_free")?),
+            close: std::mem::transmute::<*mut c_void, CloseFn>(symbol("sqlite3_close")?),
+        })
+    }
+}
+
+/// A SQLite database with one `samples` table, one row per sample.
+///
+/// Rows are kept in memory until [`flush`](LogSink::flush), which commits
+/// them as one transaction: a crash loses at most an interval's worth, and
+/// the SD card sees one write per flush rather than one per row.
+pub struct SqliteSink {
+    api: &'static Api,
+    db: *mut c_void,
+    pending: Vec<Sample>,
+}
+
+// SAFETY: the connection is only ever used through &mut self, and SQLite's
+// default threading mode allows a connection to move between threads.
+unsafe impl Send for SqliteSink {}
+
+impl SqliteSink {
+    /// Open or create the database at `path`, adding the table if missing.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let api = api()?;
+        let c_path = CString::new(path.as_os_str().as_encoded_bytes())
+            .map_err(|_| format!("{} has a NUL in it", path.display()))?;
+        let mut db = std::ptr::null_mut();
+        // SAFETY: a NUL-terminated path and a pointer for the handle.
+        let status = unsafe { (api.open)(c_path.as_ptr(), &mut db) };
+        let mut sink = SqliteSink {
+            api,
+            db,
+            pending: Vec::new(),
+        };
+        if status != SQLITE_OK {
+            return Err(format!("cannot open {}: {}", path.display(), sink.errmsg()).into());
+        }
+        sink.exec(CREATE).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(sink)
+    }
+
+    fn exec(&mut self, sql: &str) -> Result<(), Box<dyn Error>> {
+        let sql = CString::new(sql).map_err(|_| "SQL with a NUL in it")?;
+        let mut message = std::ptr::null_mut();
+        // SAFETY: an open connection and NUL-terminated SQL, without a
+        // callback; SQLite allocates any error message, freed below.
+        let status = unsafe { (self.api.exec)(self.db, sql.as_ptr(), None, std::ptr::null_mut(), &mut message) };
+        if status == SQLITE_OK {
+            return Ok(());
+        }
+        let text = if message.is_null() {
+            self.errmsg()
+        } else {
+            // SAFETY: SQLite's own NUL-terminated message, freed once.
+            unsafe {
+                let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+                (self.api.free)(message.cast());
+                text
+            }
+        };
+        Err(text.into())
+    }
+
+    fn errmsg(&self) -> String {
+        // SAFETY: sqlite3_errmsg takes a connection, or null when opening
+        // ran out of memory, and returns a string it owns.
+        unsafe { CStr::from_ptr((self.api.errmsg)(self.db)).to_string_lossy().into_owned() }
+    }
+}
+
+impl LogSink for SqliteSink {
+    fn append(&mut self, samples: &[Sample]) -> Result<(), Box<dyn Error>> {
+        self.pending.extend_from_slice(samples);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut sql = String::from("BEGIN;\n");
+        for sample in &self.pending {
+            let value = if sample.value.is_finite() { sample.value.to_string() } else { "NULL".to_string() };
+            sql.push_str(&format!(
+                "INSERT INTO samples VALUES ({}, {}, {}, {}, {});\n",
+                quote(&sample.timestamp()),
+                quote(&sample.device),
+                quote(&sample.quantity),
+                value,
+                quote(&sample.unit)
+            ));
+        }
+        sql.push_str("COMMIT;");
+        if let Err(e) = self.exec(&sql) {
+            let _ = self.exec("ROLLBACK;");
+            return Err(e);
+        }
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl Drop for SqliteSink {
+    fn drop(&mut self) {
+        // SAFETY: the connection was opened by sqlite3_open and is closed
+        // once; close takes null too, for a failed open.
+        unsafe {
+            (self.api.close)(self.db);
+        }
+    }
+}
+
+/// A SQL string literal.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
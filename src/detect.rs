@@ -0,0 +1,196 @@
+//! Finding a device's address when it isn't given.
+//!
+//! A [`Detection`] says which addresses to try and what counts as the
+//! device being there. On the command line it is written as `--addr`:
+//!
+//! ```text
+//! 0x27              that address, as is
+//! auto              the usual LCD backpack addresses, 0x27 then 0x3F
+//! auto:lcd          the same
+//! auto:0x20,0x27    these, in order
+//! auto:scan         every address from 0x08 to 0x77
+//! auto:bme280       where a BME280 can be strapped, checked by its chip ID
+//! ```
+//!
+//! Any name in [`identify::PROFILES`](crate::identify::PROFILES) works
+//! after `auto:`. A chip with an ID register is only found where that
+//! register says it is there; one without, at the first of its addresses
+//! that ACKs and isn't identified as some other chip.
+
+use crate::address::{Address, AddressedI2c};
+use crate::exit::DeviceNotFound;
+use crate::identify::{self, DeviceProfile};
+use crate::scan::{self, FIRST_ADDRESS, LAST_ADDRESS};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// PCF8574 and PCF8574A LCD backpacks as they ship.
+pub const LCD_ADDRESSES: &[u8] = &[0x27, 0x3F];
+
+/// Where to look for a device, and how to tell it is there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Detection {
+    /// This address, without probing.
+    Fixed(Address),
+    /// The first of these to ACK.
+    Candidates(Vec<Address>),
+    /// The first address on the bus to ACK.
+    Scan,
+    /// The first address the chip can be at where it is identified.
+    Profile(&'static DeviceProfile),
+}
+
+/// The LCD backpack addresses.
+impl Default for Detection {
+    fn default() -> Self {
+        Detection::lcd()
+    }
+}
+
+impl Detection {
+    pub fn lcd() -> Self {
+        Detection::Candidates(LCD_ADDRESSES.iter().map(|&a| Address::SevenBit(a)).collect())
+    }
+
+    /// The addresses tried, in order.
+    pub fn addresses(&self) -> Vec<Address> {
+        match self {
+            Detection::Fixed(address) => vec![*address],
+            Detection::Candidates(candidates) => candidates.clone(),
+            Detection::Scan => (FIRST_ADDRESS..=LAST_ADDRESS).map(Address::SevenBit).collect(),
+            Detection::Profile(profile) => profile.addresses.iter().map(|&a| Address::SevenBit(a)).collect(),
+        }
+    }
+
+    /// Where to send when nothing was found: the first address tried.
+    pub fn fallback(&self) -> Address {
+        self.addresses().first().copied().unwrap_or(Address::SevenBit(LCD_ADDRESSES[0]))
+    }
+
+    /// Whether the device is at `address`. Probes are reads, as for a
+    /// scan, so an expander's outputs or an EEPROM's pointer stay put.
+    pub fn accepts<I2C: AddressedI2c>(&self, i2c: &mut I2C, address: Address) -> bool {
+        match self {
+            Detection::Fixed(_) => true,
+            Detection::Candidates(_) | Detection::Scan => scan::probe(i2c, address),
+            Detection::Profile(profile) => {
+                if !scan::probe(i2c, address) {
+                    return false;
+                }
+                match identify::identify(i2c, address).identified {
+                    Some((found, _)) => found.name == profile.name,
+                    None => profile.id.is_none(),
+                }
+            }
+        }
+    }
+
+    /// The first address that [`accepts`](Self::accepts), telling
+    /// `report` of each one tried and whether it did.
+    pub fn detect_with<I2C, F>(&self, i2c: &mut I2C, mut report: F) -> Option<Address>
+    where
+        I2C: AddressedI2c,
+        F: FnMut(Address, bool),
+    {
+        self.addresses().into_iter().find(|&address| {
+            let found = self.accepts(i2c, address);
+            report(address, found);
+            found
+        })
+    }
+
+    pub fn detect<I2C: AddressedI2c>(&self, i2c: &mut I2C) -> Option<Address> {
+        self.detect_with(i2c, |_, _| {})
+    }
+
+    /// [`detect`](Self::detect), or what was tried.
+    pub fn find<I2C: AddressedI2c>(&self, i2c: &mut I2C) -> Result<Address, DeviceNotFound> {
+        self.detect(i2c).ok_or_else(|| self.not_found())
+    }
+
+    /// The error for nothing having been found.
+    pub fn not_found(&self) -> DeviceNotFound {
+        let tried = match self {
+            Detection::Scan => Vec::new(),
+            _ => self.addresses(),
+        };
+        DeviceNotFound { tried }
+    }
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Detection::Fixed(address) => address.to_string(),
+            Detection::Candidates(_) if *self == Detection::lcd() => "auto:lcd".to_string(),
+            Detection::Candidates(candidates) => {
+                let candidates: Vec<String> = candidates.iter().map(Address::to_string).collect();
+                format!("auto:{}", candidates.join(","))
+            }
+            Detection::Scan => "auto:scan".to_string(),
+            Detection::Profile(profile) => format!("auto:{}", profile.name.to_lowercase()),
+        };
+        f.pad(&text)
+    }
+}
+
+impl FromStr for Detection {
+    type Err = Box<dyn Error>;
+
+    /// An address, or `auto` with `:lcd`, `:scan`, a profile name or a
+    /// comma-separated list of addresses.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let Some(rest) = s.strip_prefix("auto") else {
+            return Ok(Detection::Fixed(s.parse()?));
+        };
+        let spec = match rest.strip_prefix(':') {
+            Some(spec) => spec.trim(),
+            None if rest.is_empty() => return Ok(Detection::default()),
+            None => return Err(format!("invalid address '{}' (an address, or auto:lcd, auto:scan, auto:<chip> or auto:<addr>,<addr>)", s).into()),
+        };
+        if spec.eq_ignore_ascii_case("lcd") {
+            return Ok(Detection::lcd());
+        }
+        if spec.eq_ignore_ascii_case("scan") {
+            return Ok(Detection::Scan);
+        }
+        if spec.starts_with(|c: char| c.is_ascii_digit()) {
+            let candidates = spec.split(',').map(str::parse).collect::<Result<Vec<Address>, _>>()?;
+            return Ok(Detection::Candidates(candidates));
+        }
+        identify::find(spec)
+            .map(Detection::Profile)
+            .ok_or_else(|| format!("unknown chip '{}' in '{}' (see `scan --identify` for the names)", spec, s).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::StubBus;
+
+    #[test]
+    fn parses_and_detects() {
+        assert_eq!("0x3F".parse::<Detection>().unwrap(), Detection::Fixed(Address::SevenBit(0x3F)));
+        assert_eq!("auto".parse::<Detection>().unwrap(), Detection::lcd());
+        assert_eq!("auto:scan".parse::<Detection>().unwrap(), Detection::Scan);
+        let list: Detection = "auto:0x20, 0x27".parse().unwrap();
+        assert_eq!(list.addresses(), [Address::SevenBit(0x20), Address::SevenBit(0x27)]);
+        assert_eq!(list.to_string(), "auto:0x20,0x27");
+        assert_eq!(Detection::lcd().to_string(), "auto:lcd");
+        let bme: Detection = "auto:BME280".parse().unwrap();
+        assert_eq!(bme.to_string(), "auto:bme280");
+        assert!("auto:nothing".parse::<Detection>().is_err());
+        assert!("automatic".parse::<Detection>().is_err());
+
+        let mut stub = StubBus::new();
+        stub.add_port(Address::SevenBit(0x3F), 0);
+        stub.add_port(Address::SevenBit(0x77), 0);
+        assert_eq!(Detection::lcd().detect(&mut stub), Some(Address::SevenBit(0x3F)));
+        assert_eq!(Detection::Scan.detect(&mut stub), Some(Address::SevenBit(0x3F)));
+        // 0x77 answers, but not with a BME280's chip ID
+        assert_eq!(bme.find(&mut stub).unwrap_err().tried, [Address::SevenBit(0x76), Address::SevenBit(0x77)]);
+    }
+}
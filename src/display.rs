@@ -0,0 +1,73 @@
+//! LED displays run by a driver chip, which does its own multiplexing so
+//! nothing here has to keep refreshing them.
+//!
+//! A [`Tm1637`] is the usual four-digit clock module on two GPIOs:
+//!
+//! ```no_run
+//! use rpi_peripherals::display::Tm1637;
+//! use std::sync::atomic::AtomicBool;
+//!
+//! let mut clock = Tm1637::from_gpio(23, 24)?;
+//! clock.set_brightness(2)?;
+//! clock.clock(false, &AtomicBool::new(false))?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! A [`Max7219`] drives either an 8x8 matrix or eight seven-segment digits,
+//! and several of them share one chip select as a daisy chain:
+//!
+//! ```no_run
+//! use rpi_peripherals::display::Max7219;
+//! use rpi_peripherals::spi::ChainOrder;
+//! use std::sync::atomic::AtomicBool;
+//! use std::time::Duration;
+//!
+//! // A four-matrix FC-16 module, wired in from the right hand end
+//! let mut matrix = Max7219::from_spi(0, 0, 4, ChainOrder::FarthestFirst)?;
+//! matrix.set_intensity(3)?;
+//! matrix.scroll_text("Hello, world", Duration::from_millis(40), &AtomicBool::new(false))?;
+//!
+//! let mut digits = Max7219::from_spi(0, 1, 1, ChainOrder::NearestFirst)?;
+//! digits.show_number(0, -12.345, None)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Several screens showing the same status go in a [`DisplayHub`]: anything
+//! that is a [`TextDisplay`] (these, an LCD, the [`Console`], or with `oled`
+//! a [`PixelText`] panel) is added under a name, and one call reaches them
+//! all, each message cut to fit each screen. Topics can be routed to some
+//! of them only. Two LCDs at the same address sit behind a mux:
+//!
+//! ```no_run
+//! use rpi_peripherals::address::Address;
+//! use rpi_peripherals::display::{Console, DisplayHub, Tm1637};
+//! use rpi_peripherals::lcd::Lcd;
+//! use rpi_peripherals::mux::Tca9548a;
+//! # fn demo(i2c: rppal::i2c::I2c) -> Result<(), Box<dyn std::error::Error>> {
+//!
+//! let mux = Tca9548a::new(i2c, 0x70)?;
+//! let mut hub = DisplayHub::new();
+//! hub.add("front", Lcd::new(mux.channel(0)?, Address::SevenBit(0x27), 16, 2)?)?;
+//! hub.add("back", Lcd::new(mux.channel(1)?, Address::SevenBit(0x27), 20, 4)?)?;
+//! hub.add("clock", Tm1637::from_gpio(23, 24)?)?;
+//! hub.add("console", Console::new(40, 4))?;
+//! hub.route("time", &["clock"])?;
+//!
+//! hub.mirror("Pump running\n2.1 bar")?;
+//! hub.send("time", "1230")?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod font;
+mod hub;
+#[cfg(feature = "spi")]
+mod max7219;
+mod tm1637;
+
+#[cfg(feature = "oled")]
+pub use hub::PixelText;
+pub use hub::{Console, DisplayHub, TextDisplay};
+#[cfg(feature = "spi")]
+pub use max7219::{Max7219, DIGITS, MAX7219_CLOCK, MAX_INTENSITY};
+pub use tm1637::{Tm1637, MAX_BRIGHTNESS, TM1637_DIGITS};
@@ -0,0 +1,334 @@
+#[cfg(any(feature = "spi", feature = "oled"))]
+use super::font;
+#[cfg(feature = "spi")]
+use super::Max7219;
+use super::Tm1637;
+#[cfg(feature = "lcd")]
+use crate::lcd::{Lcd, LcdInterface};
+use crate::softi2c::OpenDrainPin;
+#[cfg(feature = "oled")]
+use crate::sparkline::{Canvas, MonoBuffer};
+use crate::term::{self, Stream};
+#[cfg(feature = "spi")]
+use embedded_hal::spi::SpiDevice;
+use std::error::Error;
+
+/// A screen that shows a few lines of text.
+pub trait TextDisplay {
+    /// Columns and rows of text.
+    fn text_size(&self) -> (usize, usize);
+
+    /// Replace what is shown with `lines`, which already fit
+    /// [`text_size`](TextDisplay::text_size).
+    fn show_lines(&mut self, lines: &[String]) -> Result<(), Box<dyn Error>>;
+}
+
+#[cfg(feature = "lcd")]
+impl<B: LcdInterface> TextDisplay for Lcd<B> {
+    fn text_size(&self) -> (usize, usize) {
+        let (cols, rows) = self.size();
+        (cols.into(), rows.into())
+    }
+
+    /// Only the characters that changed go out.
+    fn show_lines(&mut self, lines: &[String]) -> Result<(), Box<dyn Error>> {
+        self.update(&lines.join("\n"))
+    }
+}
+
+impl<P: OpenDrainPin> TextDisplay for Tm1637<P> {
+    fn text_size(&self) -> (usize, usize) {
+        (super::TM1637_DIGITS, 1)
+    }
+
+    fn show_lines(&mut self, lines: &[String]) -> Result<(), Box<dyn Error>> {
+        match lines.first() {
+            Some(line) => self.show(line),
+            None => self.clear(),
+        }
+    }
+}
+
+#[cfg(feature = "spi")]
+impl<SPI> TextDisplay for Max7219<SPI>
+where
+    SPI: SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    /// One line across the matrices.
+    fn text_size(&self) -> (usize, usize) {
+        (self.device_count() * 8 / (font::WIDTH + font::SPACING), 1)
+    }
+
+    fn show_lines(&mut self, lines: &[String]) -> Result<(), Box<dyn Error>> {
+        self.show_text(lines.first().map_or("", String::as_str))
+    }
+}
+
+/// The terminal as a display: each change printed, as the screens would
+/// show it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Console {
+    cols: usize,
+    rows: usize,
+}
+
+impl Console {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Console { cols, rows }
+    }
+}
+
+impl TextDisplay for Console {
+    fn text_size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    fn show_lines(&mut self, lines: &[String]) -> Result<(), Box<dyn Error>> {
+        for (i, line) in lines.iter().enumerate() {
+            term::write(&format!("{} {}\n", if i == 0 { "🖥️ " } else { "   " }, line), Stream::Stdout);
+        }
+        Ok(())
+    }
+}
+
+/// Text drawn in the 5x7 [`font`] into a [`MonoBuffer`], which `flush`
+/// sends to the panel: a whole frame each time, or with a
+/// [`FrameShadow`](crate::sparkline::FrameShadow) only what changed.
+#[cfg(feature = "oled")]
+pub struct PixelText<F> {
+    frame: MonoBuffer,
+    flush: F,
+}
+
+#[cfg(feature = "oled")]
+impl<F> PixelText<F>
+where
+    F: FnMut(&MonoBuffer) -> Result<(), Box<dyn Error>>,
+{
+    /// A `width` x `height` panel, e.g. 128 x 32 for an SSD1306.
+    pub fn new(width: u32, height: u32, flush: F) -> Self {
+        PixelText {
+            frame: MonoBuffer::new(width, height),
+            flush,
+        }
+    }
+
+    pub fn frame(&self) -> &MonoBuffer {
+        &self.frame
+    }
+}
+
+#[cfg(feature = "oled")]
+impl<F> TextDisplay for PixelText<F>
+where
+    F: FnMut(&MonoBuffer) -> Result<(), Box<dyn Error>>,
+{
+    /// A character cell is 6 x 8 pixels, spacing included.
+    fn text_size(&self) -> (usize, usize) {
+        let (width, height) = self.frame.size();
+        (width as usize / (font::WIDTH + font::SPACING), height as usize / (font::HEIGHT + 1))
+    }
+
+    fn show_lines(&mut self, lines: &[String]) -> Result<(), Box<dyn Error>> {
+        self.frame.clear();
+        for (row, line) in lines.iter().enumerate() {
+            let y = (row * (font::HEIGHT + 1)) as u32;
+            for (col, ch) in line.chars().enumerate() {
+                let x = col * (font::WIDTH + font::SPACING);
+                for (dx, bits) in font::glyph(ch).iter().enumerate() {
+                    for dy in 0..font::HEIGHT {
+                        if bits & (1 << dy) != 0 {
+                            self.frame.set_pixel((x + dx) as u32, y + dy as u32, true);
+                        }
+                    }
+                }
+            }
+        }
+        (self.flush)(&self.frame)
+    }
+}
+
+struct Entry {
+    name: String,
+    display: Box<dyn TextDisplay>,
+    /// What it shows, when known; an unchanged message isn't sent again.
+    shown: Option<Vec<String>>,
+}
+
+/// Several displays behind one call: every message is fitted to each
+/// screen (lines cut to its width, rows beyond its height dropped) and
+/// sent to the displays [routed](DisplayHub::route) for its topic, or to
+/// all of them. A display that fails doesn't stop the message reaching
+/// the rest; the error names every one that did.
+#[derive(Default)]
+pub struct DisplayHub {
+    displays: Vec<Entry>,
+    routes: Vec<(String, Vec<String>)>,
+}
+
+impl DisplayHub {
+    pub fn new() -> Self {
+        DisplayHub::default()
+    }
+
+    /// Add a display under `name`, which routes refer to.
+    pub fn add(&mut self, name: &str, display: impl TextDisplay + 'static) -> Result<(), Box<dyn Error>> {
+        if self.position(name).is_some() {
+            return Err(format!("there is already a display called '{}'", name).into());
+        }
+        self.displays.push(Entry {
+            name: name.to_string(),
+            display: Box::new(display),
+            shown: None,
+        });
+        Ok(())
+    }
+
+    /// Take a display out, and out of every route; a route left with no
+    /// displays goes back to all of them.
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn TextDisplay>> {
+        let entry = self.displays.remove(self.position(name)?);
+        for (_, names) in &mut self.routes {
+            names.retain(|n| *n != name);
+        }
+        self.routes.retain(|(_, names)| !names.is_empty());
+        Some(entry.display)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.displays.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    /// Send messages on `topic` to just these displays, in place of every
+    /// one. No names takes the route away again.
+    pub fn route(&mut self, topic: &str, names: &[&str]) -> Result<(), Box<dyn Error>> {
+        if let Some(name) = names.iter().find(|name| self.position(name).is_none()) {
+            return Err(format!("no display called '{}' to route '{}' to", name, topic).into());
+        }
+        self.routes.retain(|(t, _)| t != topic);
+        if !names.is_empty() {
+            self.routes.push((topic.to_string(), names.iter().map(|name| name.to_string()).collect()));
+        }
+        Ok(())
+    }
+
+    /// The displays a message on `topic` goes to.
+    pub fn targets(&self, topic: &str) -> Vec<&str> {
+        match self.routes.iter().find(|(t, _)| t == topic) {
+            Some((_, names)) => names.iter().map(String::as_str).collect(),
+            None => self.names(),
+        }
+    }
+
+    /// `text` on every display, one line per row.
+    pub fn mirror(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let all: Vec<usize> = (0..self.displays.len()).collect();
+        self.show(&all, text)
+    }
+
+    /// `text` on the displays routed for `topic`, or on all of them.
+    pub fn send(&mut self, topic: &str, text: &str) -> Result<(), Box<dyn Error>> {
+        let targets: Vec<usize> = self.targets(topic).iter().filter_map(|name| self.position(name)).collect();
+        self.show(&targets, text)
+    }
+
+    /// `text` on the one display `name`.
+    pub fn send_to(&mut self, name: &str, text: &str) -> Result<(), Box<dyn Error>> {
+        let index = self.position(name).ok_or_else(|| format!("no display called '{}'", name))?;
+        self.show(&[index], text)
+    }
+
+    /// Send everything again on the next message, e.g. after a display
+    /// was reset.
+    pub fn force_full_refresh(&mut self) {
+        for entry in &mut self.displays {
+            entry.shown = None;
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.displays.iter().position(|entry| entry.name == name)
+    }
+
+    fn show(&mut self, targets: &[usize], text: &str) -> Result<(), Box<dyn Error>> {
+        let mut failed = Vec::new();
+        for &index in targets {
+            let entry = &mut self.displays[index];
+            let (cols, rows) = entry.display.text_size();
+            let lines: Vec<String> = text.lines().take(rows).map(|line| line.chars().take(cols).collect()).collect();
+            if entry.shown.as_ref() == Some(&lines) {
+                continue;
+            }
+            match entry.display.show_lines(&lines) {
+                Ok(()) => entry.shown = Some(lines),
+                Err(e) => {
+                    entry.shown = None;
+                    failed.push(format!("{}: {}", entry.name, e));
+                }
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{} of {} displays failed ({})", failed.len(), targets.len(), failed.join("; ")).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records what it was sent; fails while `broken`.
+    struct Screen {
+        size: (usize, usize),
+        sent: Arc<Mutex<Vec<Vec<String>>>>,
+        broken: bool,
+    }
+
+    impl TextDisplay for Screen {
+        fn text_size(&self) -> (usize, usize) {
+            self.size
+        }
+
+        fn show_lines(&mut self, lines: &[String]) -> Result<(), Box<dyn Error>> {
+            if self.broken {
+                return Err("NACK".into());
+            }
+            self.sent.lock().unwrap().push(lines.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mirrors_routes_and_fits_each_screen() {
+        let (lcd, segments) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+        let mut hub = DisplayHub::new();
+        hub.add("lcd", Screen { size: (8, 2), sent: lcd.clone(), broken: false }).unwrap();
+        hub.add("clock", Screen { size: (4, 1), sent: segments.clone(), broken: false }).unwrap();
+        hub.add("spare", Screen { size: (16, 2), sent: Arc::default(), broken: true }).unwrap();
+        assert!(hub.add("lcd", Console::new(16, 2)).is_err());
+
+        let e = hub.mirror("Temperature\n21.5C\nextra").unwrap_err();
+        assert_eq!(e.to_string(), "1 of 3 displays failed (spare: NACK)");
+        assert_eq!(lcd.lock().unwrap().last().unwrap(), &["Temperat", "21.5C"]);
+        assert_eq!(segments.lock().unwrap().last().unwrap(), &["Temp"]);
+
+        hub.route("time", &["clock"]).unwrap();
+        assert!(hub.route("time", &["tft"]).is_err());
+        hub.send("time", "1230").unwrap();
+        assert_eq!(segments.lock().unwrap().last().unwrap(), &["1230"]);
+        assert_eq!(lcd.lock().unwrap().len(), 1);
+
+        // unchanged, so not sent again
+        hub.send("time", "1230").unwrap();
+        assert_eq!(segments.lock().unwrap().len(), 2);
+
+        hub.remove("spare").unwrap();
+        hub.send("status", "ok").unwrap();
+        assert_eq!(hub.targets("status"), ["lcd", "clock"]);
+        assert_eq!(lcd.lock().unwrap().last().unwrap(), &["ok"]);
+    }
+}
@@ -0,0 +1,229 @@
+use super::font;
+use crate::segment;
+use crate::spi::{ChainConfig, ChainOrder, DaisyChain};
+use embedded_hal::spi::SpiDevice;
+use rppal::spi::SimpleHalSpiDevice;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// The chip takes 10 MHz; this leaves room for long jumpers between boards.
+pub const MAX7219_CLOCK: u32 = 1_000_000;
+
+/// Digit registers per chip: eight digits, or the eight rows of a matrix.
+pub const DIGITS: usize = 8;
+
+pub const MAX_INTENSITY: u8 = 15;
+
+const NO_OP: u8 = 0x00;
+const DIGIT_0: u8 = 0x01;
+const DECODE_MODE: u8 = 0x09;
+const INTENSITY: u8 = 0x0A;
+const SCAN_LIMIT: u8 = 0x0B;
+const SHUTDOWN: u8 = 0x0C;
+const DISPLAY_TEST: u8 = 0x0F;
+
+/// A chain of MAX7219 (or MAX7221) chips on one chip select.
+///
+/// Each chip's digit registers are kept in a buffer: the `set_*` calls only
+/// change it and [`Max7219::flush`] sends it, while the `show_*` calls do
+/// both. Matrices are taken to be wired as on the common FC-16 modules,
+/// one digit register per row from the top and bit 7 the leftmost column;
+/// [`Max7219::set_flipped`] turns them the other way up.
+pub struct Max7219<SPI> {
+    chain: DaisyChain<SPI>,
+    digits: Vec<[u8; DIGITS]>,
+    flipped: bool,
+}
+
+impl Max7219<SimpleHalSpiDevice> {
+    /// `devices` chips on `/dev/spidev<bus>.<cs>` at [`MAX7219_CLOCK`].
+    pub fn from_spi(bus: u8, cs: u8, devices: usize, order: ChainOrder) -> Result<Self, Box<dyn Error>> {
+        let spi = crate::spi::open(bus, cs, MAX7219_CLOCK)?;
+        Max7219::new(SimpleHalSpiDevice::new(spi), devices, order)
+    }
+}
+
+impl<SPI> Max7219<SPI>
+where
+    SPI: SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    /// Wake every chip up blank, with all eight digits scanned, no BCD
+    /// decoding and mid intensity.
+    pub fn new(spi: SPI, devices: usize, order: ChainOrder) -> Result<Self, Box<dyn Error>> {
+        let config = ChainConfig::new(devices, 2).with_order(order).with_no_op(&[NO_OP, 0]);
+        let mut display = Max7219 {
+            chain: DaisyChain::new(spi, config)?,
+            digits: vec![[0; DIGITS]; devices],
+            flipped: false,
+        };
+        display.command(DISPLAY_TEST, 0)?;
+        display.command(SCAN_LIMIT, DIGITS as u8 - 1)?;
+        display.command(DECODE_MODE, 0)?;
+        display.command(INTENSITY, 7)?;
+        display.flush()?;
+        display.command(SHUTDOWN, 1)?;
+        Ok(display)
+    }
+
+    pub fn device_count(&self) -> usize {
+        self.chain.device_count()
+    }
+
+    /// Brightness of every chip, 0 to [`MAX_INTENSITY`]. Even 0 is lit;
+    /// [`Max7219::set_shutdown`] turns a display off.
+    pub fn set_intensity(&mut self, level: u8) -> Result<(), Box<dyn Error>> {
+        check_intensity(level)?;
+        self.command(INTENSITY, level)
+    }
+
+    /// Brightness of one chip, leaving the others as they are.
+    pub fn set_device_intensity(&mut self, device: usize, level: u8) -> Result<(), Box<dyn Error>> {
+        check_intensity(level)?;
+        self.chain.write_device(device, &[INTENSITY, level])
+    }
+
+    /// Blank every chip, keeping what they show, or bring them back.
+    pub fn set_shutdown(&mut self, shutdown: bool) -> Result<(), Box<dyn Error>> {
+        self.command(SHUTDOWN, u8::from(!shutdown))
+    }
+
+    /// Light every LED at full brightness, whatever the registers say.
+    pub fn set_test(&mut self, test: bool) -> Result<(), Box<dyn Error>> {
+        self.command(DISPLAY_TEST, u8::from(test))
+    }
+
+    pub fn set_flipped(&mut self, flipped: bool) {
+        self.flipped = flipped;
+    }
+
+    /// The segments or row of `digit` (0-7) on `device` as the chip takes
+    /// them: decimal point in bit 7, then segments a to g from bit 6 down.
+    pub fn set_raw(&mut self, device: usize, digit: usize, pattern: u8) -> Result<(), Box<dyn Error>> {
+        self.check(device)?;
+        if digit >= DIGITS {
+            return Err(format!("digit {} out of range 0-{}", digit, DIGITS - 1).into());
+        }
+        self.digits[device][digit] = pattern;
+        Ok(())
+    }
+
+    /// The buffered digit registers of `device`.
+    pub fn raw(&self, device: usize) -> &[u8; DIGITS] {
+        &self.digits[device]
+    }
+
+    /// Send the buffer: one latch per digit register, across the whole chain.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        for digit in 0..DIGITS {
+            for device in 0..self.device_count() {
+                let pattern = self.digits[device][digit];
+                self.chain.set_slot(device, &[DIGIT_0 + digit as u8, pattern])?;
+            }
+            self.chain.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<(), Box<dyn Error>> {
+        self.digits.iter_mut().for_each(|d| *d = [0; DIGITS]);
+        self.flush()
+    }
+
+    /// `text` right-aligned on the eight digits of `device`, as
+    /// [`segment::encode_str`] takes it.
+    pub fn show_segments(&mut self, device: usize, text: &str) -> Result<(), Box<dyn Error>> {
+        self.check(device)?;
+        let patterns = segment::encode_str(text, DIGITS)?;
+        // Digit 0 is the rightmost on the usual eight-digit boards
+        for (position, &pattern) in patterns.iter().enumerate() {
+            self.digits[device][DIGITS - 1 - position] = to_register(pattern);
+        }
+        self.flush()
+    }
+
+    /// `value` on `device`, formatted by [`segment::format_number`].
+    pub fn show_number(&mut self, device: usize, value: f64, decimals: Option<usize>) -> Result<(), Box<dyn Error>> {
+        let text = segment::format_number(value, DIGITS, decimals)?;
+        self.show_segments(device, &text)
+    }
+
+    /// Columns of pixels across the chained matrices from the left, top row
+    /// in bit 0 as [`font`] draws them. Anything past the last matrix is cut
+    /// off, and matrices past the last column are blanked.
+    pub fn show_columns(&mut self, columns: &[u8]) -> Result<(), Box<dyn Error>> {
+        let devices = self.device_count();
+        for device in 0..devices {
+            let mut rows = [0u8; DIGITS];
+            for (x, &column) in columns.iter().skip(device * 8).take(8).enumerate() {
+                for (y, row) in rows.iter_mut().enumerate() {
+                    if column & (1 << y) != 0 {
+                        *row |= 0x80 >> x;
+                    }
+                }
+            }
+            if self.flipped {
+                rows.reverse();
+                rows.iter_mut().for_each(|row| *row = row.reverse_bits());
+                self.digits[devices - 1 - device] = rows;
+            } else {
+                self.digits[device] = rows;
+            }
+        }
+        self.flush()
+    }
+
+    /// `text` from the left of the matrices, cut off if it doesn't fit.
+    pub fn show_text(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        self.show_columns(&font::render(text))
+    }
+
+    /// Scroll `text` in from the right and out to the left, a column every
+    /// `step`, returning once it has gone or `stop` is set.
+    pub fn scroll_text(&mut self, text: &str, step: Duration, stop: &AtomicBool) -> Result<(), Box<dyn Error>> {
+        let width = self.device_count() * 8;
+        let mut columns = vec![0; width];
+        columns.extend(font::render(text));
+        columns.extend(std::iter::repeat_n(0, width));
+        for start in 0..=columns.len() - width {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            self.show_columns(&columns[start..start + width])?;
+            thread::sleep(step);
+        }
+        Ok(())
+    }
+
+    pub fn release(self) -> SPI {
+        self.chain.release()
+    }
+
+    /// The same register write to every chip, in one latch.
+    fn command(&mut self, register: u8, value: u8) -> Result<(), Box<dyn Error>> {
+        self.chain.write_all(&[register, value])
+    }
+
+    fn check(&self, device: usize) -> Result<(), Box<dyn Error>> {
+        if device >= self.device_count() {
+            return Err(format!("device {} out of range for a chain of {}", device, self.device_count()).into());
+        }
+        Ok(())
+    }
+}
+
+fn check_intensity(level: u8) -> Result<(), Box<dyn Error>> {
+    if level > MAX_INTENSITY {
+        return Err(format!("intensity {} out of range 0-{}", level, MAX_INTENSITY).into());
+    }
+    Ok(())
+}
+
+/// A [`segment`] pattern (a in bit 0 up to g in bit 6) in the chip's order,
+/// which runs the other way with the decimal point left in bit 7.
+fn to_register(pattern: u8) -> u8 {
+    let segments = (0..7).filter(|s| pattern & (1 << s) != 0).fold(0, |bits, s| bits | (0x40 >> s));
+    segments | (pattern & segment::DP)
+}
@@ -0,0 +1,238 @@
+use crate::localtime;
+use crate::segment::{self, DP};
+use crate::softi2c::OpenDrainPin;
+use rppal::gpio::{Bias, Gpio, IoPin, Mode};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const TM1637_DIGITS: usize = 4;
+
+pub const MAX_BRIGHTNESS: u8 = 7;
+
+/// Write data, auto-incrementing the address.
+const DATA_AUTO: u8 = 0x40;
+const ADDRESS: u8 = 0xC0;
+const DISPLAY_ON: u8 = 0x88;
+const DISPLAY_OFF: u8 = 0x80;
+
+/// About 50 kHz: the modules put 100 pF on both lines, which the pull-ups
+/// take a few microseconds to charge.
+const HALF_PERIOD: Duration = Duration::from_micros(10);
+
+/// Digit whose bit 7 lights the colon on clock modules.
+const COLON_DIGIT: usize = 1;
+
+/// A TM1637 four-digit module on two GPIOs.
+///
+/// Its CLK and DIO lines look like I2C but aren't: there is no address and
+/// bytes go LSB first, so it is bit-banged here, open-drain as the chip
+/// expects. Segment patterns are the [`segment`] ones. Clock modules wire
+/// the colon to the decimal point of the second digit, so on those the
+/// colon and a `.` there are the same LED.
+pub struct Tm1637<P> {
+    clk: P,
+    dio: P,
+    digits: [u8; TM1637_DIGITS],
+    colon: bool,
+    brightness: u8,
+    on: bool,
+}
+
+impl Tm1637<IoPin> {
+    /// BCM pins `clk` and `dio`, pulled up inside the Pi as well as on the
+    /// module.
+    pub fn from_gpio(clk: u8, dio: u8) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let open = |pin: u8| -> Result<IoPin, Box<dyn Error>> {
+            let mut pin = gpio.get(pin).map_err(|e| format!("TM1637 GPIO {}: {}", pin, e))?.into_io(Mode::Input);
+            pin.set_bias(Bias::PullUp);
+            Ok(pin)
+        };
+        Tm1637::new(open(clk)?, open(dio)?)
+    }
+}
+
+impl<P: OpenDrainPin> Tm1637<P> {
+    /// Blank the display and switch it on at full brightness.
+    pub fn new(mut clk: P, mut dio: P) -> Result<Self, Box<dyn Error>> {
+        pin(clk.release())?;
+        pin(dio.release())?;
+        let mut display = Tm1637 {
+            clk,
+            dio,
+            digits: [0; TM1637_DIGITS],
+            colon: false,
+            brightness: MAX_BRIGHTNESS,
+            on: true,
+        };
+        display.flush()?;
+        Ok(display)
+    }
+
+    /// 0 (dim, but lit) to [`MAX_BRIGHTNESS`].
+    pub fn set_brightness(&mut self, level: u8) -> Result<(), Box<dyn Error>> {
+        if level > MAX_BRIGHTNESS {
+            return Err(format!("brightness {} out of range 0-{}", level, MAX_BRIGHTNESS).into());
+        }
+        self.brightness = level;
+        self.control()
+    }
+
+    /// Blank the display, keeping what it shows, or bring it back.
+    pub fn set_on(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.on = on;
+        self.control()
+    }
+
+    pub fn set_colon(&mut self, colon: bool) -> Result<(), Box<dyn Error>> {
+        self.colon = colon;
+        self.flush()
+    }
+
+    /// Segment patterns, leftmost digit first; missing digits are blanked.
+    pub fn set_raw(&mut self, patterns: &[u8]) -> Result<(), Box<dyn Error>> {
+        if patterns.len() > TM1637_DIGITS {
+            return Err(format!("{} patterns for {} digits", patterns.len(), TM1637_DIGITS).into());
+        }
+        self.digits = [0; TM1637_DIGITS];
+        self.digits[..patterns.len()].copy_from_slice(patterns);
+        self.flush()
+    }
+
+    /// `text` right-aligned, as [`segment::encode_str`] takes it.
+    pub fn show(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let patterns = segment::encode_str(text, TM1637_DIGITS)?;
+        self.set_raw(&patterns)
+    }
+
+    /// `value`, formatted by [`segment::format_number`].
+    pub fn show_number(&mut self, value: f64, decimals: Option<usize>) -> Result<(), Box<dyn Error>> {
+        self.show(&segment::format_number(value, TM1637_DIGITS, decimals)?)
+    }
+
+    /// `HH:MM`, or `H:MM` for hours under 10.
+    pub fn show_time(&mut self, hours: u8, minutes: u8, colon: bool) -> Result<(), Box<dyn Error>> {
+        if hours > 23 || minutes > 59 {
+            return Err(format!("{}:{:02} is not a time of day", hours, minutes).into());
+        }
+        self.colon = colon;
+        self.show(&format!("{:>2}{:02}", hours, minutes))
+    }
+
+    pub fn clear(&mut self) -> Result<(), Box<dyn Error>> {
+        self.set_raw(&[])
+    }
+
+    /// Show the local time until `stop` is set, the colon lit for the first
+    /// half of every second. `twelve_hour` shows 1-12 rather than 0-23.
+    pub fn clock(&mut self, twelve_hour: bool, stop: &AtomicBool) -> Result<(), Box<dyn Error>> {
+        let mut shown = None;
+        while !stop.load(Ordering::Relaxed) {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            let local = localtime::at(UNIX_EPOCH + now).ok_or("can't read the local time")?;
+            let (hours, minutes) = (local.hour, local.minute);
+            let hours = if twelve_hour { (hours + 11) % 12 + 1 } else { hours };
+            let colon = now.subsec_millis() < 500;
+            if shown != Some((hours, minutes, colon)) {
+                self.show_time(hours, minutes, colon)?;
+                shown = Some((hours, minutes, colon));
+            }
+            // Wake on the next half second, so the colon keeps to the clock
+            let into_half = Duration::from_nanos(u64::from(now.subsec_nanos() % 500_000_000));
+            thread::sleep(Duration::from_millis(500) - into_half);
+        }
+        Ok(())
+    }
+
+    pub fn release(self) -> (P, P) {
+        (self.clk, self.dio)
+    }
+
+    /// Send every digit, then the display control.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut digits = self.digits;
+        if self.colon {
+            digits[COLON_DIGIT] |= DP;
+        }
+        self.command(&[DATA_AUTO])?;
+        let mut frame = vec![ADDRESS];
+        frame.extend_from_slice(&digits);
+        self.command(&frame)?;
+        self.control()
+    }
+
+    fn control(&mut self) -> Result<(), Box<dyn Error>> {
+        let control = if self.on { DISPLAY_ON | self.brightness } else { DISPLAY_OFF };
+        self.command(&[control])
+    }
+
+    /// One start-to-stop transfer.
+    fn command(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.start()?;
+        let result = bytes.iter().try_for_each(|&byte| self.write_byte(byte));
+        // Always stop, so a missed ACK doesn't leave the lines held
+        self.stop()?;
+        result
+    }
+
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        pin(self.clk.release())?;
+        pin(self.dio.release())?;
+        delay();
+        pin(self.dio.drive_low())?;
+        delay();
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        pin(self.clk.drive_low())?;
+        pin(self.dio.drive_low())?;
+        delay();
+        pin(self.clk.release())?;
+        delay();
+        pin(self.dio.release())?;
+        delay();
+        Ok(())
+    }
+
+    /// LSB first, then the chip pulls DIO low on the ninth clock.
+    fn write_byte(&mut self, byte: u8) -> Result<(), Box<dyn Error>> {
+        for bit in 0..8 {
+            pin(self.clk.drive_low())?;
+            if byte & (1 << bit) != 0 {
+                pin(self.dio.release())?;
+            } else {
+                pin(self.dio.drive_low())?;
+            }
+            delay();
+            pin(self.clk.release())?;
+            delay();
+        }
+        pin(self.clk.drive_low())?;
+        pin(self.dio.release())?;
+        delay();
+        pin(self.clk.release())?;
+        delay();
+        let acked = !self.dio.is_high().map_err(|e| format!("TM1637 GPIO: {:?}", e))?;
+        pin(self.clk.drive_low())?;
+        if !acked {
+            return Err("TM1637 didn't acknowledge; check the CLK and DIO pins".into());
+        }
+        Ok(())
+    }
+}
+
+fn pin<E: fmt::Debug>(result: Result<(), E>) -> Result<(), Box<dyn Error>> {
+    result.map_err(|e| format!("TM1637 GPIO: {:?}", e).into())
+}
+
+/// Busy-wait, as the half period is far below sleep granularity.
+fn delay() {
+    let start = Instant::now();
+    while start.elapsed() < HALF_PERIOD {
+        std::hint::spin_loop();
+    }
+}
@@ -0,0 +1,250 @@
+//! Registry of the chips this crate has drivers for.
+//!
+//! The `driver` field of a config device names one of these entries; the CLI
+//! uses the registry to list what's supported and what each device can do.
+
+use std::fmt;
+
+/// How the chip is attached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interface {
+    I2c,
+    Spi,
+    Serial,
+    Gpio,
+    OneWire,
+    I2s,
+}
+
+impl fmt::Display for Interface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Interface::I2c => "i2c",
+            Interface::Spi => "spi",
+            Interface::Serial => "serial",
+            Interface::Gpio => "gpio",
+            Interface::OneWire => "1-wire",
+            Interface::I2s => "i2s",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Drives output pins or registers.
+    Output,
+    /// Reads input pins or registers.
+    Input,
+    /// Switches between downstream buses.
+    Multiplex,
+    /// Shows text or graphics.
+    Display,
+    Print,
+    Barcode,
+    /// Supports packet error checking (SMBus PEC).
+    Pec,
+    /// Several chips share one chip select in a shift-register chain.
+    DaisyChain,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Capability::Output => "output",
+            Capability::Input => "input",
+            Capability::Multiplex => "multiplex",
+            Capability::Display => "display",
+            Capability::Print => "print",
+            Capability::Barcode => "barcode",
+            Capability::Pec => "pec",
+            Capability::DaisyChain => "daisy-chain",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverInfo {
+    /// Name used in the config's `driver` field.
+    pub name: &'static str,
+    pub description: &'static str,
+    pub interface: Interface,
+    /// Addresses the chip can be strapped to, empty if not addressable.
+    pub addresses: &'static [u16],
+    pub capabilities: &'static [Capability],
+}
+
+pub const DRIVERS: &[DriverInfo] = &[
+    DriverInfo {
+        name: "pcf8574",
+        description: "8-bit I/O expander (HD44780 LCD backpacks)",
+        interface: Interface::I2c,
+        addresses: &[0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F],
+        capabilities: &[Capability::Output, Capability::Input],
+    },
+    DriverInfo {
+        name: "hd44780",
+        description: "Character LCD on a PCF8574 backpack (16x2, 20x4)",
+        interface: Interface::I2c,
+        addresses: &[0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F],
+        capabilities: &[Capability::Display],
+    },
+    DriverInfo {
+        name: "mcp23017",
+        description: "16-bit I/O expander with pull-ups and interrupt-on-change",
+        interface: Interface::I2c,
+        addresses: &[0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27],
+        capabilities: &[Capability::Output, Capability::Input],
+    },
+    DriverInfo {
+        name: "tca9548a",
+        description: "1-to-8 I2C multiplexer",
+        interface: Interface::I2c,
+        addresses: &[0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77],
+        capabilities: &[Capability::Multiplex],
+    },
+    DriverInfo {
+        name: "ina219",
+        description: "High-side current, voltage and power monitor (12-bit, 26 V)",
+        interface: Interface::I2c,
+        addresses: &[0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "ina226",
+        description: "High-side current, voltage and power monitor (16-bit, 36 V)",
+        interface: Interface::I2c,
+        addresses: &[0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "apds9960",
+        description: "Proximity, RGB light and swipe gesture sensor",
+        interface: Interface::I2c,
+        addresses: &[0x39],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "hcsr04",
+        description: "Ultrasonic distance sensor, 2 cm to 4 m (TRIG and ECHO on GPIO)",
+        interface: Interface::Gpio,
+        addresses: &[],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "ws2812",
+        description: "Addressable RGB LED strip (NeoPixel), driven from SPI MOSI",
+        interface: Interface::Spi,
+        addresses: &[],
+        capabilities: &[Capability::Output, Capability::Display],
+    },
+    DriverInfo {
+        name: "apa102",
+        description: "Addressable RGB LED strip (DotStar, SK9822) on SPI MOSI and SCLK",
+        interface: Interface::Spi,
+        addresses: &[],
+        capabilities: &[Capability::Output, Capability::Display],
+    },
+    DriverInfo {
+        name: "max7219",
+        description: "8x8 LED matrix or eight-digit seven-segment driver, chainable on one chip select",
+        interface: Interface::Spi,
+        addresses: &[],
+        capabilities: &[Capability::Display, Capability::DaisyChain],
+    },
+    DriverInfo {
+        name: "servo",
+        description: "Hobby servo on hardware or software PWM, angle mapped onto a 50 Hz pulse",
+        interface: Interface::Gpio,
+        addresses: &[],
+        capabilities: &[Capability::Output],
+    },
+    DriverInfo {
+        name: "stepper",
+        description: "4-wire stepper (28BYJ-48 on a ULN2003), full or half steps with speed ramps",
+        interface: Interface::Gpio,
+        addresses: &[],
+        capabilities: &[Capability::Output],
+    },
+    DriverInfo {
+        name: "tsop38238",
+        description: "38 kHz IR receiver module, decoding NEC remotes on one GPIO",
+        interface: Interface::Gpio,
+        addresses: &[],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "tm1637",
+        description: "Four-digit seven-segment display with colon, on two bit-banged GPIOs",
+        interface: Interface::Gpio,
+        addresses: &[],
+        capabilities: &[Capability::Display],
+    },
+    DriverInfo {
+        name: "ds18b20",
+        description: "1-Wire temperature sensor, through the kernel's w1-gpio driver",
+        interface: Interface::OneWire,
+        addresses: &[],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "sph0645",
+        description: "I2S MEMS microphone, 18-bit, captured through ALSA",
+        interface: Interface::I2s,
+        addresses: &[],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "inmp441",
+        description: "I2S MEMS microphone, 24-bit, captured through ALSA",
+        interface: Interface::I2s,
+        addresses: &[],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "mcp2515",
+        description: "CAN controller, standard and extended frames with acceptance filters",
+        interface: Interface::Spi,
+        addresses: &[],
+        capabilities: &[Capability::Input, Capability::Output],
+    },
+    DriverInfo {
+        name: "mcp3008",
+        description: "Eight-channel 10-bit ADC, single-ended or differential pairs",
+        interface: Interface::Spi,
+        addresses: &[],
+        capabilities: &[Capability::Input],
+    },
+    DriverInfo {
+        name: "smbus",
+        description: "Generic SMBus device (byte/word/block commands)",
+        interface: Interface::I2c,
+        addresses: &[],
+        capabilities: &[Capability::Input, Capability::Output, Capability::Pec],
+    },
+    DriverInfo {
+        name: "escpos",
+        description: "ESC/POS thermal receipt printer",
+        interface: Interface::Serial,
+        addresses: &[],
+        capabilities: &[Capability::Print, Capability::Barcode],
+    },
+    DriverInfo {
+        name: "modbus-rtu",
+        description: "Modbus RTU slave on RS-485 or serial: holding and input registers",
+        interface: Interface::Serial,
+        addresses: &[],
+        capabilities: &[Capability::Input, Capability::Output],
+    },
+    DriverInfo {
+        name: "74hc595",
+        description: "8-bit shift register outputs, chainable, bit-banged on GPIO or on SPI",
+        interface: Interface::Spi,
+        addresses: &[],
+        capabilities: &[Capability::Output, Capability::DaisyChain],
+    },
+];
+
+/// Look up a driver by its config name, ignoring case.
+pub fn find(name: &str) -> Option<&'static DriverInfo> {
+    DRIVERS.iter().find(|d| d.name.eq_ignore_ascii_case(name))
+}
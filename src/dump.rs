@@ -0,0 +1,143 @@
+//! Reading out a register space or EEPROM, for `dump`.
+//!
+//! [`read`] sets a device's register pointer (one byte, or two for
+//! 24C32 and larger EEPROMs) and reads in chunks from there. [`hexdump`]
+//! prints the result the way `hexdump -C` does, offsets and all, so an
+//! image can be checked by eye or diffed against a dump taken elsewhere;
+//! [`compare`] lists where it differs from an expected image.
+
+use crate::address::{Address, AddressedI2c};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+
+/// Bytes per hexdump line.
+const LINE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpConfig {
+    pub address: Address,
+    /// First register or memory offset.
+    pub start: u32,
+    pub len: usize,
+    /// Bytes of register pointer sent before each read: 1, or 2 for
+    /// EEPROMs of 4 KiB and up.
+    pub pointer_width: u8,
+    /// Bytes per read; SMBus-style chips stop at 32.
+    pub chunk: usize,
+}
+
+impl DumpConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let space: u64 = match self.pointer_width {
+            1 => 0x100,
+            2 => 0x1_0000,
+            other => return Err(format!("register pointer width must be 1 or 2 bytes, not {}", other).into()),
+        };
+        if self.len == 0 {
+            return Err("dump length must be greater than zero".into());
+        }
+        if u64::from(self.start) + self.len as u64 > space {
+            return Err(format!(
+                "0x{:X} + {} bytes runs past 0x{:X}, the end of a {}-byte offset",
+                self.start, self.len, space, self.pointer_width
+            )
+            .into());
+        }
+        if self.chunk == 0 {
+            return Err("dump chunk size must be greater than zero".into());
+        }
+        Ok(())
+    }
+}
+
+/// Read `config.len` bytes from `config.start`, one chunk at a time.
+pub fn read<I2C: AddressedI2c>(i2c: &mut I2C, config: &DumpConfig) -> Result<Vec<u8>, Box<dyn Error>> {
+    read_with(i2c, config, |_| {})
+}
+
+/// [`read`], calling `progress` with the bytes read so far after every
+/// chunk.
+pub fn read_with<I2C, F>(i2c: &mut I2C, config: &DumpConfig, mut progress: F) -> Result<Vec<u8>, Box<dyn Error>>
+where
+    I2C: AddressedI2c,
+    F: FnMut(usize),
+{
+    config.validate()?;
+    let mut bytes = vec![0; config.len];
+    for (i, chunk) in bytes.chunks_mut(config.chunk).enumerate() {
+        let offset = config.start + (i * config.chunk) as u32;
+        let pointer = offset.to_be_bytes();
+        let pointer = &pointer[4 - usize::from(config.pointer_width)..];
+        i2c.write_read_at(config.address, pointer, chunk)
+            .map_err(|e| format!("{} at 0x{:04X}: {}", config.address, offset, e))?;
+        progress(i * config.chunk + chunk.len());
+    }
+    Ok(bytes)
+}
+
+/// `bytes` as `hexdump -C` prints them, with `start` as the first offset.
+/// Runs of identical lines are folded into a `*`.
+pub fn hexdump(bytes: &[u8], start: u32) -> String {
+    let mut out = String::new();
+    let mut previous: Option<&[u8]> = None;
+    let mut folded = false;
+    for (i, line) in bytes.chunks(LINE).enumerate() {
+        if previous == Some(line) && line.len() == LINE {
+            if !folded {
+                out.push_str("*\n");
+                folded = true;
+            }
+            continue;
+        }
+        previous = Some(line);
+        folded = false;
+        let _ = write!(out, "{:08x}  ", start as usize + i * LINE);
+        for column in 0..LINE {
+            match line.get(column) {
+                Some(byte) => {
+                    let _ = write!(out, "{:02x} ", byte);
+                }
+                None => out.push_str("   "),
+            }
+            if column == LINE / 2 - 1 {
+                out.push(' ');
+            }
+        }
+        out.push_str(" |");
+        out.extend(line.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        out.push_str("|\n");
+    }
+    let _ = writeln!(out, "{:08x}", start as usize + bytes.len());
+    out
+}
+
+/// A byte that isn't what the image says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Difference {
+    pub offset: u32,
+    pub expected: Option<u8>,
+    pub actual: Option<u8>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let byte = |b: Option<u8>| b.map_or("nothing".to_string(), |b| format!("0x{:02X}", b));
+        write!(f, "0x{:04X}: expected {}, read {}", self.offset, byte(self.expected), byte(self.actual))
+    }
+}
+
+/// Every offset where `actual` and `expected` differ, including bytes
+/// only one of them has.
+pub fn compare(actual: &[u8], expected: &[u8], start: u32) -> Vec<Difference> {
+    (0..actual.len().max(expected.len()))
+        .filter_map(|i| {
+            let (a, e) = (actual.get(i).copied(), expected.get(i).copied());
+            (a != e).then_some(Difference {
+                offset: start + i as u32,
+                expected: e,
+                actual: a,
+            })
+        })
+        .collect()
+}
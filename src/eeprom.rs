@@ -0,0 +1,171 @@
+//! Writing a 24Cxx EEPROM, for `eeprom`.
+//!
+//! [`write`] sends an image a page at a time, never across a page
+//! boundary (the chip would wrap to the start of the page), and waits out
+//! each page's write cycle before the next. The wait is `write_cycle`;
+//! with `tune` it starts there and shrinks to what the chip needs, with
+//! [`ack_poll`] saying how much longer it stayed busy each time. Either
+//! way the chip is ACK-polled after the wait, so a wait that was too
+//! short costs time rather than a lost page. Read it back with
+//! [`dump::read`](crate::dump::read) to check.
+
+use crate::address::{Address, AddressedI2c};
+use crate::throttle::{ack_poll, DelayTuner};
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The 24Cxx datasheets' worst case is 5 ms; some clones take up to 10.
+pub const WRITE_CYCLE: Duration = Duration::from_millis(10);
+
+/// 24C02 pages; 24C32/64 have 32 bytes, 24C256 64.
+pub const DEFAULT_PAGE: usize = 8;
+
+/// Longest a chip may stay busy after the wait before it counts as gone.
+const POLL_TIMEOUT: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EepromConfig {
+    pub address: Address,
+    /// Offset of the first byte written.
+    pub start: u32,
+    /// Bytes of memory offset: 1, or 2 for 24C32 and larger.
+    pub pointer_width: u8,
+    /// Bytes per page write.
+    pub page: usize,
+    pub write_cycle: Duration,
+    /// Shrink `write_cycle` to what the chip takes.
+    pub tune: bool,
+}
+
+impl EepromConfig {
+    pub fn validate(&self, len: usize) -> Result<(), Box<dyn Error>> {
+        let space: u64 = match self.pointer_width {
+            1 => 0x100,
+            2 => 0x1_0000,
+            other => return Err(format!("memory offset width must be 1 or 2 bytes, not {}", other).into()),
+        };
+        if !(1..=256).contains(&self.page) {
+            return Err(format!("page size must be 1-256 bytes, not {}", self.page).into());
+        }
+        if u64::from(self.start) + len as u64 > space {
+            return Err(format!("0x{:X} + {} bytes runs past 0x{:X}, the end of a {}-byte offset", self.start, len, space, self.pointer_width).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteReport {
+    pub pages: u32,
+    pub bytes: usize,
+    pub elapsed: Duration,
+    /// The wait after each page by the end: `write_cycle`, or tuned.
+    pub write_cycle: Duration,
+    /// Longest a page took to write, when the chip was caught busy.
+    pub longest: Option<Duration>,
+    /// Pages after which the chip was still busy.
+    pub caught_busy: u32,
+}
+
+/// Write `data` from `config.start`, a page at a time.
+pub fn write<I2C: AddressedI2c>(i2c: &mut I2C, config: &EepromConfig, data: &[u8]) -> Result<WriteReport, Box<dyn Error>> {
+    write_with(i2c, config, data, |_| {})
+}
+
+/// [`write`], calling `progress` with the bytes written so far after
+/// every page.
+pub fn write_with<I2C, F>(i2c: &mut I2C, config: &EepromConfig, data: &[u8], mut progress: F) -> Result<WriteReport, Box<dyn Error>>
+where
+    I2C: AddressedI2c,
+    F: FnMut(usize),
+{
+    config.validate(data.len())?;
+    let started = Instant::now();
+    let floor = if config.tune { Duration::ZERO } else { config.write_cycle };
+    let mut tuner = DelayTuner::new(config.write_cycle, floor);
+    let mut written = 0;
+    let mut pages = 0;
+    while written < data.len() {
+        let offset = config.start + written as u32;
+        let room = config.page - offset as usize % config.page;
+        let page = &data[written..data.len().min(written + room)];
+        let pointer = offset.to_be_bytes();
+        let mut bytes = pointer[4 - usize::from(config.pointer_width)..].to_vec();
+        bytes.extend_from_slice(page);
+        i2c.write_at(config.address, &bytes)
+            .map_err(|e| format!("{} at 0x{:04X}: {}", config.address, offset, e))?;
+        thread::sleep(tuner.delay());
+        let still = ack_poll(i2c, config.address, POLL_TIMEOUT)?;
+        tuner.feedback(still);
+        written += page.len();
+        pages += 1;
+        progress(written);
+    }
+    Ok(WriteReport {
+        pages,
+        bytes: written,
+        elapsed: started.elapsed(),
+        write_cycle: tuner.delay(),
+        longest: tuner.longest(),
+        caught_busy: tuner.caught_busy(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::Operation;
+
+    /// NACKs every probe for `busy` after a page write.
+    struct Chip {
+        memory: [u8; 256],
+        busy: Duration,
+        since: Option<Instant>,
+        pages: Vec<usize>,
+    }
+
+    impl AddressedI2c for Chip {
+        fn transaction_at(&mut self, _: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+            if self.since.is_some_and(|since| since.elapsed() < self.busy) {
+                return Err("NACK".into());
+            }
+            for operation in operations {
+                if let Operation::Write(bytes) = operation {
+                    let (offset, data) = bytes.split_first().unwrap();
+                    self.memory[*offset as usize..][..data.len()].copy_from_slice(data);
+                    self.pages.push(data.len());
+                    self.since = Some(Instant::now());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_whole_pages_and_tunes_the_wait() {
+        let mut chip = Chip {
+            memory: [0xFF; 256],
+            busy: Duration::from_millis(4),
+            since: None,
+            pages: Vec::new(),
+        };
+        let config = EepromConfig {
+            address: Address::SevenBit(0x50),
+            start: 0x05,
+            pointer_width: 1,
+            page: 8,
+            write_cycle: Duration::from_millis(10),
+            tune: true,
+        };
+        let data: Vec<u8> = (0..40).collect();
+        let report = write(&mut chip, &config, &data).unwrap();
+        // 3 bytes to the page boundary at 0x08, then whole pages
+        assert_eq!(chip.pages, [3, 8, 8, 8, 8, 5]);
+        assert_eq!(&chip.memory[0x05..0x2D], &data[..]);
+        assert_eq!(report.pages, 6);
+        assert!(report.caught_busy >= 1);
+        assert!(report.write_cycle < config.write_cycle && report.write_cycle >= Duration::from_millis(4), "{:?}", report);
+        assert!(config.validate(0x100).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! Energy monitoring: power from an INA219 or INA226, totalled into kWh.
+//!
+//! [`EnergyMonitor`] reads the sensor, records power, voltage and current
+//! into its [`History`] as `<device>.power` and so on, and keeps two
+//! [`Totals`] over the power: `<device>.energy`, the kWh since it was
+//! last reset, and `<device>.today`, which starts again at local midnight.
+//! Both are checkpointed to the state file like any other total, so a
+//! restart picks up where it left off. With a [`Tariff`] each sample also
+//! carries what the energy cost.
+//!
+//! ```no_run
+//! use rpi_peripherals::address::Address;
+//! use rpi_peripherals::energy::{EnergyMonitor, Tariff};
+//! use rpi_peripherals::sensors::Ina219;
+//! use std::time::Duration;
+//! # fn demo<I2C: rpi_peripherals::address::AddressedI2c>(i2c: I2C) -> Result<(), Box<dyn std::error::Error>> {
+//!
+//! let sensor = Ina219::new(i2c, Address::SevenBit(0x40), 0.1, 3.2)?;
+//! let tariff = Tariff { price: 0.30, currency: "EUR".into() };
+//! let mut monitor = EnergyMonitor::new(sensor, "solar", None, Duration::from_secs(1), Some(tariff))?;
+//! loop {
+//!     let sample = monitor.sample()?;
+//!     println!("{:.1} W, {:.3} kWh today", sample.power, sample.today_kwh);
+//!     std::thread::sleep(Duration::from_secs(1));
+//! }
+//! # }
+//! ```
+
+use crate::history::{History, HistoryConfig};
+use crate::localtime;
+use crate::metrics::Metrics;
+use crate::say;
+use crate::sensors::PowerMonitor;
+use crate::totals::{Kind, TotalConfig, Totals, TotalsConfig};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often the totals are saved when there is a state file.
+pub const CHECKPOINT: Duration = Duration::from_secs(60);
+
+/// The price of energy, for cost estimates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tariff {
+    /// Per kWh.
+    pub price: f64,
+    /// Only a label, e.g. `"EUR"`.
+    pub currency: String,
+}
+
+/// One reading and the totals after it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnergySample {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub power: f64,
+    pub voltage: f64,
+    pub current: f64,
+    pub total_kwh: f64,
+    pub today_kwh: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_total: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_today: Option<f64>,
+}
+
+pub struct EnergyMonitor<S> {
+    sensor: S,
+    device: String,
+    history: History,
+    totals: Totals,
+    tariff: Option<Tariff>,
+    metrics: Option<Metrics>,
+    saved_at: Instant,
+}
+
+impl<S: PowerMonitor> EnergyMonitor<S> {
+    /// Monitor `sensor` under the name `device`, sampled about every
+    /// `interval`. With a `state` file the totals carry on from the last
+    /// run; without one they start from zero.
+    pub fn new(
+        sensor: S,
+        device: &str,
+        state: Option<PathBuf>,
+        interval: Duration,
+        tariff: Option<Tariff>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let total = TotalConfig {
+            source: format!("{}.power", device),
+            kind: Kind::Rate,
+            // W over hours, in thousands
+            scale: 1e-3,
+            per: Duration::from_secs(3600),
+            // A few missed reads are bridged, a stopped process isn't
+            max_gap: (interval * 3).max(Duration::from_secs(300)),
+            rollover: None,
+        };
+        let config = TotalsConfig {
+            state,
+            checkpoint: CHECKPOINT,
+            counters: BTreeMap::from([
+                (format!("{}.energy", device), total.clone()),
+                (format!("{}.today", device), total),
+            ]),
+        };
+        let history = History::new(HistoryConfig::default());
+        Ok(EnergyMonitor {
+            sensor,
+            device: device.to_string(),
+            totals: Totals::from_config(&config, history.clone())?,
+            history,
+            tariff,
+            metrics: None,
+            saved_at: Instant::now(),
+        })
+    }
+
+    /// Also export the readings, totals and costs as gauges.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    pub fn tariff(&self) -> Option<&Tariff> {
+        self.tariff.as_ref()
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn totals(&self) -> &Totals {
+        &self.totals
+    }
+
+    /// Read the sensor and bring the totals up to date, saving them every
+    /// [`CHECKPOINT`]. The daily total starts again on the first sample of
+    /// a new local day.
+    pub fn sample(&mut self) -> Result<EnergySample, Box<dyn Error>> {
+        let reading = self.sensor.read_power()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let today = format!("{}.today", self.device);
+        let since = self.totals.list().into_iter().find(|t| t.name == today).map(|t| t.since);
+        let day = |unix| localtime::at(UNIX_EPOCH + Duration::from_secs(unix)).map(|t| (t.year, t.day_of_year));
+        if since.is_some_and(|since| day(since) != day(now)) {
+            self.totals.reset(&today)?;
+        }
+
+        self.history.record(&format!("{}.power", self.device), reading.power);
+        self.history.record(&format!("{}.voltage", self.device), reading.bus_voltage);
+        self.history.record(&format!("{}.current", self.device), reading.current);
+        self.totals.update();
+        if self.saved_at.elapsed() >= CHECKPOINT {
+            if let Err(e) = self.totals.save() {
+                say!("⚠️  Energy totals checkpoint: {}", e);
+            }
+            self.saved_at = Instant::now();
+        }
+
+        let total_kwh = self.totals.get(&format!("{}.energy", self.device)).unwrap_or(0.0);
+        let today_kwh = self.totals.get(&today).unwrap_or(0.0);
+        let price = self.tariff.as_ref().map(|t| t.price);
+        let sample = EnergySample {
+            timestamp: now,
+            power: reading.power,
+            voltage: reading.bus_voltage,
+            current: reading.current,
+            total_kwh,
+            today_kwh,
+            cost_total: price.map(|p| p * total_kwh),
+            cost_today: price.map(|p| p * today_kwh),
+        };
+        if let Some(metrics) = &self.metrics {
+            self.export(metrics, &sample);
+        }
+        Ok(sample)
+    }
+
+    /// Write the totals to the state file if they changed, e.g. before
+    /// stopping.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        self.totals.save()
+    }
+
+    pub fn release(self) -> S {
+        self.sensor
+    }
+
+    fn export(&self, metrics: &Metrics, sample: &EnergySample) {
+        let device = [("device", self.device.as_str())];
+        metrics.set("rpi_peripherals_power_watts", "Power drawn by the load", &device, sample.power);
+        metrics.set("rpi_peripherals_bus_voltage_volts", "Supply voltage at the load", &device, sample.voltage);
+        metrics.set("rpi_peripherals_current_amps", "Current drawn by the load", &device, sample.current);
+        for (period, kwh, cost) in [
+            ("total", sample.total_kwh, sample.cost_total),
+            ("today", sample.today_kwh, sample.cost_today),
+        ] {
+            let labels = [("device", self.device.as_str()), ("period", period)];
+            metrics.set("rpi_peripherals_energy_kwh", "Energy used since the last reset or midnight", &labels, kwh);
+            if let (Some(cost), Some(tariff)) = (cost, &self.tariff) {
+                let labels = [("device", self.device.as_str()), ("period", period), ("currency", tariff.currency.as_str())];
+                metrics.set("rpi_peripherals_energy_cost", "Estimated cost of the energy used", &labels, cost);
+            }
+        }
+    }
+}
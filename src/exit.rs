@@ -0,0 +1,173 @@
+//! Process exit codes, so scripts and Ansible can tell failures apart.
+//!
+//! | code | meaning              |
+//! |------|----------------------|
+//! | 0    | success              |
+//! | 1    | any other error      |
+//! | 2    | device not found     |
+//! | 3    | bus error            |
+//! | 4    | timeout              |
+//! | 5    | permission denied    |
+//! | 6    | verification failed  |
+//! | 130  | interrupted (Ctrl-C) |
+//!
+//! Bad arguments exit with 1 as well, not clap's usual 2.
+//!
+//! With `--quiet` the command prints nothing but errors and its own output
+//! (a dump, an export), so the code is all a script has to go on:
+//! `rpi_peripherals scan -q && systemctl start display`.
+
+use crate::address::Address;
+use crate::bus::{BusNotFound, StubError};
+use crate::expander::WriteMismatch;
+use crate::preflight::PreflightReport;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// The table above, for the end of `--help`.
+pub const HELP: &str = "\
+Exit codes:
+  0    success
+  1    any other error, bad arguments included
+  2    device not found (or a scan found nothing)
+  3    bus error
+  4    timeout
+  5    permission denied
+  6    verification failed
+  130  interrupted (Ctrl-C)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Success = 0,
+    Failure = 1,
+    DeviceNotFound = 2,
+    BusError = 3,
+    Timeout = 4,
+    PermissionDenied = 5,
+    VerificationFailed = 6,
+    Interrupted = 130,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Pick the exit status for an error by walking its source chain.
+    pub fn classify(err: &(dyn Error + 'static)) -> ExitStatus {
+        let mut current = Some(err);
+        while let Some(e) = current {
+            if e.is::<DeviceNotFound>() {
+                return ExitStatus::DeviceNotFound;
+            }
+            if e.is::<VerificationFailed>() || e.is::<WriteMismatch>() {
+                return ExitStatus::VerificationFailed;
+            }
+            if e.is::<Interrupted>() {
+                return ExitStatus::Interrupted;
+            }
+            if e.is::<TimedOut>() {
+                return ExitStatus::Timeout;
+            }
+            if let Some(report) = e.downcast_ref::<PreflightReport>() {
+                return report.exit_status();
+            }
+            if let Some(rppal::i2c::Error::Io(io)) = e.downcast_ref::<rppal::i2c::Error>() {
+                return classify_io(io, ExitStatus::BusError);
+            }
+            #[cfg(feature = "i2cdev")]
+            if let Some(linux) = e.downcast_ref::<crate::bus::LinuxI2cError>() {
+                return classify_io(&linux.source, ExitStatus::BusError);
+            }
+            if e.is::<StubError>() {
+                return ExitStatus::BusError;
+            }
+            if let Some(io) = e.downcast_ref::<io::Error>() {
+                return classify_io(io, ExitStatus::Failure);
+            }
+            if e.is::<BusNotFound>() || e.is::<rppal::i2c::Error>() {
+                return ExitStatus::BusError;
+            }
+            current = e.source();
+        }
+        ExitStatus::Failure
+    }
+}
+
+fn classify_io(err: &io::Error, otherwise: ExitStatus) -> ExitStatus {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => ExitStatus::PermissionDenied,
+        io::ErrorKind::TimedOut => ExitStatus::Timeout,
+        // NACKs from the i2c-dev driver come back as ENXIO / EREMOTEIO.
+        _ if matches!(err.raw_os_error(), Some(6) | Some(121)) => ExitStatus::BusError,
+        _ => otherwise,
+    }
+}
+
+impl From<ExitStatus> for std::process::ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        std::process::ExitCode::from(status.code())
+    }
+}
+
+/// Nothing answered at the addresses that were tried; empty for a whole
+/// bus scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceNotFound {
+    pub tried: Vec<Address>,
+}
+
+impl fmt::Display for DeviceNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.tried.is_empty() {
+            return f.write_str("no device answered anywhere on the bus");
+        }
+        let tried: Vec<String> = self.tried.iter().map(Address::to_string).collect();
+        write!(f, "no device answered at {}", tried.join(", "))
+    }
+}
+
+impl Error for DeviceNotFound {}
+
+/// A device answered, but not with what was expected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationFailed {
+    pub details: String,
+}
+
+impl fmt::Display for VerificationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "verification failed: {}", self.details)
+    }
+}
+
+impl Error for VerificationFailed {}
+
+/// The run was stopped by SIGINT/SIGTERM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+impl Error for Interrupted {}
+
+/// Gave up waiting for something that never happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedOut {
+    pub waiting_for: String,
+    pub after: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gave up waiting for {} after {:.1}s", self.waiting_for, self.after.as_secs_f64())
+    }
+}
+
+impl Error for TimedOut {}
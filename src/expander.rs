@@ -0,0 +1,59 @@
+//! GPIO expanders on I2C.
+//!
+//! A [`Pcf8574`] gives eight quasi-bidirectional pins, and is what
+//! `lcd::Backpack` drives the LCD backpacks through. With
+//! [`verify_writes`](Pcf8574::verify_writes) on, each write is read back
+//! and a pin that didn't take is reported as a [`WriteMismatch`].
+//!
+//! For general I/O the [`Mcp23017`] gives 16 pins with real inputs and
+//! outputs, pull-ups, and an interrupt line to wake the Pi on a change:
+//!
+//! ```no_run
+//! use rpi_peripherals::address::Address;
+//! use rpi_peripherals::expander::{Direction, InterruptPin, Mcp23017};
+//! use std::time::Duration;
+//!
+//! let i2c = rppal::i2c::I2c::new()?;
+//! let mut io = Mcp23017::new(i2c, Address::seven_bit(0x20)?)?;
+//! // GPA0 drives an LED; GPB0-GPB3 are buttons to ground
+//! io.set_direction(0, Direction::Output)?;
+//! for pin in 8..12 {
+//!     io.set_direction(pin, Direction::Input)?;
+//!     io.set_pull_up(pin, true)?;
+//!     io.set_interrupt(pin, true)?;
+//! }
+//! let line = InterruptPin::from_gpio(17)?;
+//! loop {
+//!     if line.wait(Duration::from_secs(1)) {
+//!         for change in io.take_changes()? {
+//!             println!("GPIO {} went {}", change.pin, if change.high { "high" } else { "low" });
+//!         }
+//!     }
+//!     let pressed = !io.read_pin(8)?;
+//!     io.write_pin(0, pressed)?;
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Outputs only, a [`Sn74hc595`] chain adds eight pins a chip for the cost
+//! of three GPIOs or the SPI bus. Wired the way the PCF8574 backpacks are
+//! (RS, RW, E and backlight on Q0-Q3, D4-D7 on Q4-Q7), it drives an LCD
+//! through the same `Lcd` code:
+//!
+//! ```no_run
+//! use rpi_peripherals::expander::Sn74hc595;
+//! use rpi_peripherals::lcd::Lcd;
+//!
+//! let register = Sn74hc595::from_gpio(17, 27, 22, 1)?;
+//! let mut lcd = Lcd::with_interface(register, 16, 2)?;
+//! lcd.show("shift register\nbackpack")?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod mcp23017;
+mod pcf8574;
+mod sn74hc595;
+
+pub use mcp23017::{Change, Direction, InterruptPin, Mcp23017, MCP23017_PINS};
+pub use pcf8574::{Pcf8574, PortState, WriteMismatch, PCF8574_POWER_ON};
+pub use sn74hc595::{ShiftPins, ShiftPinsError, Sn74hc595, SN74HC595_CLOCK};
@@ -0,0 +1,223 @@
+use crate::address::{Address, AddressedI2c};
+use rppal::gpio::{Gpio, InputPin, Trigger};
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+pub const MCP23017_PINS: u8 = 16;
+
+// Port A's register of each pair, as laid out with IOCON.BANK = 0; port B's
+// follows it, so a two-byte access covers both ports.
+const IODIR: u8 = 0x00;
+const GPINTEN: u8 = 0x04;
+const INTCON: u8 = 0x08;
+const IOCON: u8 = 0x0A;
+const GPPU: u8 = 0x0C;
+const INTF: u8 = 0x0E;
+const INTCAP: u8 = 0x10;
+const GPIO: u8 = 0x12;
+const OLAT: u8 = 0x14;
+
+/// INTA and INTB both fire for a change on either port.
+const MIRROR: u8 = 0x40;
+/// Interrupt outputs open-drain, so several chips can share one Pi pin.
+const ODR: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// A pin that changed, with its level when the interrupt fired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub pin: u8,
+    pub high: bool,
+}
+
+/// Microchip MCP23017: 16 I/O pins in two 8-bit ports. Pins 0-7 are GPA0-7
+/// and 8-15 GPB0-7; every pin starts as an input without a pull-up.
+///
+/// Directions, pull-ups, interrupt enables and outputs are cached, so
+/// changing one pin is a single write.
+pub struct Mcp23017<I2C> {
+    i2c: I2C,
+    address: Address,
+    /// Set bits are inputs, as in IODIR.
+    inputs: u16,
+    pull_ups: u16,
+    interrupts: u16,
+    outputs: u16,
+}
+
+impl<I2C: AddressedI2c> Mcp23017<I2C> {
+    /// Put every pin back to an input with no pull-up or interrupt, and set
+    /// the interrupt pins mirrored and open-drain, active low.
+    pub fn new(i2c: I2C, address: Address) -> Result<Self, Box<dyn Error>> {
+        if !(0x20..=0x27).contains(&address.raw()) {
+            return Err(format!("{} is not an MCP23017 address (0x20-0x27)", address).into());
+        }
+        let mut expander = Mcp23017 {
+            i2c,
+            address,
+            inputs: 0xFFFF,
+            pull_ups: 0,
+            interrupts: 0,
+            outputs: 0,
+        };
+        expander.write_byte(IOCON, MIRROR | ODR)?;
+        expander.write_pair(OLAT, 0)?;
+        expander.write_pair(IODIR, 0xFFFF)?;
+        expander.write_pair(GPPU, 0)?;
+        // Compare with the previous level, so both edges interrupt
+        expander.write_pair(INTCON, 0)?;
+        expander.write_pair(GPINTEN, 0)?;
+        Ok(expander)
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn set_direction(&mut self, pin: u8, direction: Direction) -> Result<(), Box<dyn Error>> {
+        let inputs = with_bit(self.inputs, pin, direction == Direction::Input)?;
+        self.write_pair(IODIR, inputs)?;
+        self.inputs = inputs;
+        Ok(())
+    }
+
+    /// All 16 at once: set bits are inputs.
+    pub fn set_inputs(&mut self, mask: u16) -> Result<(), Box<dyn Error>> {
+        self.write_pair(IODIR, mask)?;
+        self.inputs = mask;
+        Ok(())
+    }
+
+    /// The internal 100 kΩ pull-up, for inputs.
+    pub fn set_pull_up(&mut self, pin: u8, on: bool) -> Result<(), Box<dyn Error>> {
+        let pull_ups = with_bit(self.pull_ups, pin, on)?;
+        self.write_pair(GPPU, pull_ups)?;
+        self.pull_ups = pull_ups;
+        Ok(())
+    }
+
+    /// Pull INT low when `pin` changes either way, until
+    /// [`take_changes`](Mcp23017::take_changes) reads what happened.
+    pub fn set_interrupt(&mut self, pin: u8, on: bool) -> Result<(), Box<dyn Error>> {
+        let interrupts = with_bit(self.interrupts, pin, on)?;
+        self.write_pair(GPINTEN, interrupts)?;
+        self.interrupts = interrupts;
+        Ok(())
+    }
+
+    /// Every pin's level, GPA0 in bit 0; outputs read back what they drive.
+    pub fn read_port(&mut self) -> Result<u16, Box<dyn Error>> {
+        self.read_pair(GPIO)
+    }
+
+    pub fn read_pin(&mut self, pin: u8) -> Result<bool, Box<dyn Error>> {
+        let bit = bit(pin)?;
+        Ok(self.read_port()? & bit != 0)
+    }
+
+    /// Set every output's level; bits for inputs are kept for when they
+    /// become outputs.
+    pub fn write_port(&mut self, levels: u16) -> Result<(), Box<dyn Error>> {
+        self.write_pair(OLAT, levels)?;
+        self.outputs = levels;
+        Ok(())
+    }
+
+    pub fn write_pin(&mut self, pin: u8, high: bool) -> Result<(), Box<dyn Error>> {
+        let outputs = with_bit(self.outputs, pin, high)?;
+        self.write_pair(OLAT, outputs)?;
+        self.outputs = outputs;
+        Ok(())
+    }
+
+    /// The pins behind the last interrupt, and clear it. A pin that changes
+    /// again before this is called shows its level when it first changed.
+    pub fn take_changes(&mut self) -> Result<Vec<Change>, Box<dyn Error>> {
+        let flags = self.read_pair(INTF)?;
+        // Reading the capture is what releases INT
+        let captured = self.read_pair(INTCAP)?;
+        Ok((0..MCP23017_PINS)
+            .filter(|&pin| flags & 1 << pin != 0)
+            .map(|pin| Change {
+                pin,
+                high: captured & 1 << pin != 0,
+            })
+            .collect())
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn write_byte(&mut self, register: u8, value: u8) -> Result<(), Box<dyn Error>> {
+        self.i2c.write_at(self.address, &[register, value])
+    }
+
+    /// Port A then port B, little-endian as the pin numbers go.
+    fn write_pair(&mut self, register: u8, value: u16) -> Result<(), Box<dyn Error>> {
+        let [a, b] = value.to_le_bytes();
+        self.i2c.write_at(self.address, &[register, a, b])
+    }
+
+    fn read_pair(&mut self, register: u8) -> Result<u16, Box<dyn Error>> {
+        let mut buf = [0; 2];
+        self.i2c.write_read_at(self.address, &[register], &mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+}
+
+fn bit(pin: u8) -> Result<u16, Box<dyn Error>> {
+    if pin >= MCP23017_PINS {
+        return Err(format!("MCP23017 has no pin {} (0-15)", pin).into());
+    }
+    Ok(1 << pin)
+}
+
+fn with_bit(mask: u16, pin: u8, set: bool) -> Result<u16, Box<dyn Error>> {
+    let bit = bit(pin)?;
+    Ok(if set { mask | bit } else { mask & !bit })
+}
+
+/// The Pi GPIO an expander's INTA or INTB is wired to, pulled up inside
+/// the Pi as the open-drain output needs.
+pub struct InterruptPin {
+    pin: InputPin,
+    edges: Receiver<()>,
+}
+
+impl InterruptPin {
+    pub fn from_gpio(pin: u8) -> Result<Self, Box<dyn Error>> {
+        let mut input = Gpio::new()?
+            .get(pin)
+            .map_err(|e| format!("interrupt GPIO {}: {}", pin, e))?
+            .into_input_pullup();
+        let (tx, edges) = mpsc::channel();
+        input
+            .set_async_interrupt(Trigger::FallingEdge, None, move |_| {
+                let _ = tx.send(());
+            })
+            .map_err(|e| format!("interrupt GPIO {}: {}", pin, e))?;
+        Ok(InterruptPin { pin: input, edges })
+    }
+
+    /// Whether the line is asserted: now, or by the end of `timeout`.
+    /// Still low counts, so an interrupt from before this was opened, or
+    /// not yet taken, isn't waited out.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        while self.edges.try_recv().is_ok() {}
+        if self.pin.is_low() {
+            return true;
+        }
+        self.edges.recv_timeout(timeout).is_ok()
+    }
+
+    pub fn is_asserted(&self) -> bool {
+        self.pin.is_low()
+    }
+}
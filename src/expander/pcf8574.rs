@@ -0,0 +1,237 @@
+use crate::address::{Address, AddressedI2c};
+use std::error::Error;
+use std::fmt;
+
+/// The port's latch at power-on: every pin high, so every pin an input.
+pub const PCF8574_POWER_ON: u8 = 0xFF;
+
+/// What the driver knows of the output latch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    /// Nothing written yet, or the last write failed part-way.
+    Unknown,
+    /// Written, not read back.
+    Written(u8),
+    /// Written and read back the same.
+    Verified(u8),
+    /// Written, but the port read back different.
+    Mismatch { wrote: u8, read: u8 },
+}
+
+impl PortState {
+    /// The latch, if it is known.
+    pub fn latch(&self) -> Option<u8> {
+        match *self {
+            PortState::Unknown => None,
+            PortState::Written(latch) | PortState::Verified(latch) => Some(latch),
+            PortState::Mismatch { wrote, .. } => Some(wrote),
+        }
+    }
+}
+
+/// A port that read back different from what was written to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteMismatch {
+    pub address: Address,
+    pub wrote: u8,
+    pub read: u8,
+    /// The pins compared; inputs are left out.
+    pub mask: u8,
+}
+
+impl WriteMismatch {
+    /// Pins that came back wrong.
+    pub fn pins(&self) -> u8 {
+        (self.wrote ^ self.read) & self.mask
+    }
+}
+
+impl fmt::Display for WriteMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pins: Vec<String> = (0..8).filter(|p| self.pins() & (1 << p) != 0).map(|p| format!("P{}", p)).collect();
+        write!(
+            f,
+            "PCF8574 at {} wrote 0x{:02X} but read back 0x{:02X} ({} wrong: a bus error, or something driving the pin)",
+            self.address,
+            self.wrote,
+            self.read,
+            pins.join(" ")
+        )
+    }
+}
+
+impl Error for WriteMismatch {}
+
+/// NXP PCF8574 / PCF8574A: eight quasi-bidirectional pins. A pin written
+/// low sinks current; written high it is a weak pull-up, which reads as
+/// whatever is driving it.
+///
+/// The driver keeps the latch as a [`PortState`], so one pin can change
+/// without a read first. With [`verify_writes`](Pcf8574::verify_writes)
+/// on, every write is followed by a read of the port, and pins that don't
+/// read back as written are a [`WriteMismatch`]: a corrupted write, or an
+/// output fighting something that holds it low. Pins set as
+/// [inputs](Pcf8574::set_inputs) are kept high and left out.
+pub struct Pcf8574<I2C> {
+    i2c: I2C,
+    address: Address,
+    state: PortState,
+    inputs: u8,
+    verify: bool,
+    mismatches: u32,
+}
+
+impl<I2C: AddressedI2c> Pcf8574<I2C> {
+    /// Nothing is sent until the first write.
+    pub fn new(i2c: I2C, address: Address) -> Self {
+        Pcf8574 {
+            i2c,
+            address,
+            state: PortState::Unknown,
+            inputs: 0,
+            verify: false,
+            mismatches: 0,
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn state(&self) -> PortState {
+        self.state
+    }
+
+    /// Read the port back after every write, failing on a mismatch.
+    pub fn verify_writes(&mut self, on: bool) {
+        self.verify = on;
+    }
+
+    /// Writes that read back wrong so far.
+    pub fn mismatches(&self) -> u32 {
+        self.mismatches
+    }
+
+    /// Pins used as inputs: always written high and never compared.
+    pub fn set_inputs(&mut self, mask: u8) {
+        self.inputs = mask;
+    }
+
+    pub fn write(&mut self, port: u8) -> Result<(), Box<dyn Error>> {
+        self.write_sequence(&[port])
+    }
+
+    /// Several port values in one transaction, each on the pins for a
+    /// byte's time, as for strobing a line; the last one stays.
+    pub fn write_sequence(&mut self, values: &[u8]) -> Result<(), Box<dyn Error>> {
+        let Some(&last) = values.last() else {
+            return Ok(());
+        };
+        let values: Vec<u8> = values.iter().map(|value| value | self.inputs).collect();
+        if let Err(e) = self.i2c.write_at(self.address, &values) {
+            self.state = PortState::Unknown;
+            return Err(e);
+        }
+        self.state = PortState::Written(last | self.inputs);
+        if self.verify {
+            self.verify()?;
+        }
+        Ok(())
+    }
+
+    /// Change one pin, leaving the others as last written (or as at
+    /// power-on, before anything was).
+    pub fn set_pin(&mut self, pin: u8, high: bool) -> Result<(), Box<dyn Error>> {
+        if pin > 7 {
+            return Err(format!("PCF8574 pin {} out of range (0-7)", pin).into());
+        }
+        let latch = self.state.latch().unwrap_or(PCF8574_POWER_ON);
+        self.write(if high { latch | 1 << pin } else { latch & !(1 << pin) })
+    }
+
+    /// The pin levels, outputs and inputs alike.
+    pub fn read(&mut self) -> Result<u8, Box<dyn Error>> {
+        let mut port = [0];
+        self.i2c.read_at(self.address, &mut port)?;
+        Ok(port[0])
+    }
+
+    pub fn read_pin(&mut self, pin: u8) -> Result<bool, Box<dyn Error>> {
+        Ok(self.read()? & (1 << pin) != 0)
+    }
+
+    /// Read the port and compare it with the latch.
+    pub fn verify(&mut self) -> Result<(), Box<dyn Error>> {
+        let wrote = self.state.latch().ok_or("nothing written to the PCF8574 yet")?;
+        let read = self.read()?;
+        let mismatch = WriteMismatch {
+            address: self.address,
+            wrote,
+            read,
+            mask: !self.inputs,
+        };
+        if mismatch.pins() != 0 {
+            self.mismatches += 1;
+            self.state = PortState::Mismatch { wrote, read };
+            return Err(mismatch.into());
+        }
+        self.state = PortState::Verified(wrote);
+        Ok(())
+    }
+
+    /// The bus underneath, e.g. to recover it.
+    pub fn i2c_mut(&mut self) -> &mut I2C {
+        &mut self.i2c
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::Operation;
+
+    /// A port with `held_low` pins shorted to ground.
+    struct Port {
+        latch: u8,
+        held_low: u8,
+    }
+
+    impl AddressedI2c for Port {
+        fn transaction_at(&mut self, _: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => self.latch = *bytes.last().unwrap(),
+                    Operation::Read(buffer) => buffer.fill(self.latch & !self.held_low),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn read_back_catches_a_pin_held_low() {
+        let address = Address::SevenBit(0x20);
+        let mut port = Pcf8574::new(Port { latch: 0xFF, held_low: 0x04 }, address);
+        assert_eq!(port.state(), PortState::Unknown);
+        port.write(0x0F).unwrap();
+        assert_eq!(port.state(), PortState::Written(0x0F));
+
+        port.verify_writes(true);
+        let e = port.set_pin(7, true).unwrap_err();
+        assert!(e.to_string().contains("(P2 wrong"), "{}", e);
+        assert_eq!(e.downcast_ref::<WriteMismatch>().unwrap().pins(), 0x04);
+        assert_eq!(port.state(), PortState::Mismatch { wrote: 0x8F, read: 0x8B });
+        port.write(0xF0).unwrap();
+        assert_eq!(port.state(), PortState::Verified(0xF0));
+
+        // as an input, P2 is kept high and may read low
+        port.set_inputs(0x04);
+        port.write(0x00).unwrap();
+        assert_eq!(port.state(), PortState::Verified(0x04));
+        assert_eq!(port.mismatches(), 1);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::spi::{ChainConfig, ChainOrder, DaisyChain};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::{self, ErrorKind, ErrorType, Operation, SpiDevice};
+use rppal::gpio::{Gpio, OutputPin as GpioPin};
+#[cfg(feature = "spi")]
+use rppal::spi::SimpleHalSpiDevice;
+use std::error::Error;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+/// Well inside what a 74HC595 takes at 3.3 V, and long wires too.
+pub const SN74HC595_CLOCK: u32 = 4_000_000;
+
+/// SER, SRCLK and RCLK on any three output pins, bit-banged as a
+/// write-only [`SpiDevice`]: SER is MOSI, SRCLK the clock and RCLK the chip
+/// select, latching at the end of each transaction. The chip takes edges
+/// far faster than GPIO can make them, so there are no delays.
+pub struct ShiftPins<P> {
+    data: P,
+    clock: P,
+    latch: P,
+}
+
+impl<P: OutputPin> ShiftPins<P>
+where
+    P::Error: Error + 'static,
+{
+    pub fn new(mut data: P, mut clock: P, mut latch: P) -> Result<Self, Box<dyn Error>> {
+        data.set_low()?;
+        clock.set_low()?;
+        latch.set_low()?;
+        Ok(ShiftPins { data, clock, latch })
+    }
+
+    pub fn release(self) -> (P, P, P) {
+        (self.data, self.clock, self.latch)
+    }
+}
+
+impl<P: OutputPin> ShiftPins<P> {
+    fn shift(&mut self, byte: u8) -> Result<(), P::Error> {
+        for bit in (0..8).rev() {
+            if byte & (1 << bit) != 0 {
+                self.data.set_high()?;
+            } else {
+                self.data.set_low()?;
+            }
+            self.clock.set_high()?;
+            self.clock.set_low()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ShiftPinsError<E> {
+    Pin(E),
+    /// A 74HC595 has no line back to the Pi.
+    WriteOnly,
+}
+
+impl<E: fmt::Debug> fmt::Display for ShiftPinsError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShiftPinsError::Pin(e) => write!(f, "shift register pin error: {:?}", e),
+            ShiftPinsError::WriteOnly => write!(f, "shift register pins can only be written"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> Error for ShiftPinsError<E> {}
+
+impl<E: fmt::Debug> spi::Error for ShiftPinsError<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl<P: OutputPin> ErrorType for ShiftPins<P> {
+    type Error = ShiftPinsError<P::Error>;
+}
+
+impl<P: OutputPin> SpiDevice for ShiftPins<P> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        for op in operations {
+            match op {
+                Operation::Write(bytes) => {
+                    for &byte in bytes.iter() {
+                        self.shift(byte).map_err(ShiftPinsError::Pin)?;
+                    }
+                }
+                Operation::DelayNs(ns) => thread::sleep(Duration::from_nanos(u64::from(*ns))),
+                _ => return Err(ShiftPinsError::WriteOnly),
+            }
+        }
+        self.latch.set_high().map_err(ShiftPinsError::Pin)?;
+        self.latch.set_low().map_err(ShiftPinsError::Pin)
+    }
+}
+
+/// 74HC595 8-bit shift registers, one or several chained Q7' to SER, as
+/// outputs. Pin `n` is Q`n % 8` of chip `n / 8`, so the API reads like an
+/// I/O expander's: every change shifts the whole chain's state out again.
+///
+/// OE has to be tied low (or driven low once set up) for the outputs to
+/// show; SRCLR tied high.
+pub struct Sn74hc595<SPI> {
+    chain: DaisyChain<SPI>,
+}
+
+impl Sn74hc595<ShiftPins<GpioPin>> {
+    /// `chips` registers bit-banged on BCM pins `data` (SER), `clock`
+    /// (SRCLK) and `latch` (RCLK), chip 0 nearest the Pi.
+    pub fn from_gpio(data: u8, clock: u8, latch: u8, chips: usize) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let open = |pin: u8| -> Result<GpioPin, Box<dyn Error>> {
+            Ok(gpio.get(pin).map_err(|e| format!("74HC595 GPIO {}: {}", pin, e))?.into_output_low())
+        };
+        let pins = ShiftPins::new(open(data)?, open(clock)?, open(latch)?)?;
+        Sn74hc595::new(pins, chips, ChainOrder::NearestFirst)
+    }
+}
+
+#[cfg(feature = "spi")]
+impl Sn74hc595<SimpleHalSpiDevice> {
+    /// `chips` registers on `/dev/spidev<bus>.<cs>`, chip 0 nearest the Pi.
+    pub fn from_spi(bus: u8, cs: u8, chips: usize) -> Result<Self, Box<dyn Error>> {
+        let spi = crate::spi::open(bus, cs, SN74HC595_CLOCK)?;
+        Sn74hc595::new(SimpleHalSpiDevice::new(spi), chips, ChainOrder::NearestFirst)
+    }
+}
+
+impl<SPI> Sn74hc595<SPI>
+where
+    SPI: SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    /// Every output low; `order` says which end of the chain chip 0 is.
+    pub fn new(spi: SPI, chips: usize, order: ChainOrder) -> Result<Self, Box<dyn Error>> {
+        if chips == 0 {
+            return Err("a 74HC595 chain needs at least one chip".into());
+        }
+        let mut chain = DaisyChain::new(spi, ChainConfig::new(chips, 1).with_order(order))?;
+        chain.flush()?;
+        Ok(Sn74hc595 { chain })
+    }
+
+    pub fn chip_count(&self) -> usize {
+        self.chain.device_count()
+    }
+
+    pub fn pin_count(&self) -> usize {
+        self.chip_count() * 8
+    }
+
+    /// One byte per chip, chip 0 first.
+    pub fn write(&mut self, levels: &[u8]) -> Result<(), Box<dyn Error>> {
+        if levels.len() != self.chip_count() {
+            return Err(format!("{} bytes for {} chips", levels.len(), self.chip_count()).into());
+        }
+        for (chip, &level) in levels.iter().enumerate() {
+            self.chain.set_slot(chip, &[level])?;
+        }
+        self.chain.flush()
+    }
+
+    pub fn write_chip(&mut self, chip: usize, levels: u8) -> Result<(), Box<dyn Error>> {
+        if chip >= self.chip_count() {
+            return Err(format!("chip {} is past the end of a {}-chip chain", chip, self.chip_count()).into());
+        }
+        self.chain.write_device(chip, &[levels])
+    }
+
+    pub fn write_pin(&mut self, pin: usize, high: bool) -> Result<(), Box<dyn Error>> {
+        if pin >= self.pin_count() {
+            return Err(format!("74HC595 chain has no pin {} (0-{})", pin, self.pin_count() - 1).into());
+        }
+        let bit = 1 << (pin % 8);
+        let levels = self.chip(pin / 8);
+        self.write_chip(pin / 8, if high { levels | bit } else { levels & !bit })
+    }
+
+    /// What each chip is driving, chip 0 first; the chips can't be read.
+    pub fn levels(&self) -> Vec<u8> {
+        (0..self.chip_count()).map(|chip| self.chip(chip)).collect()
+    }
+
+    /// What `chip` is driving.
+    pub fn chip(&self, chip: usize) -> u8 {
+        self.chain.slot(chip)[0]
+    }
+
+    pub fn pin(&self, pin: usize) -> bool {
+        pin < self.pin_count() && self.chip(pin / 8) & (1 << (pin % 8)) != 0
+    }
+
+    pub fn release(self) -> SPI {
+        self.chain.release()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::convert::Infallible;
+    use std::rc::Rc;
+
+    /// SER's level, the bits clocked in since the last latch, and each
+    /// latched frame.
+    #[derive(Default)]
+    struct Wire {
+        data: bool,
+        bits: Vec<bool>,
+        frames: Vec<Vec<u8>>,
+    }
+
+    struct Pin(Rc<RefCell<Wire>>, usize);
+
+    impl embedded_hal::digital::ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            if self.1 == 0 {
+                self.0.borrow_mut().data = false;
+            }
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            let mut wire = self.0.borrow_mut();
+            match self.1 {
+                0 => wire.data = true,
+                1 => {
+                    let bit = wire.data;
+                    wire.bits.push(bit);
+                }
+                _ => {
+                    let frame = wire.bits.chunks(8).map(|b| b.iter().fold(0, |byte, &bit| byte << 1 | bit as u8)).collect();
+                    wire.frames.push(frame);
+                    wire.bits.clear();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn far_chip_goes_out_first() {
+        let wire = Rc::new(RefCell::new(Wire::default()));
+        let pin = |n| Pin(Rc::clone(&wire), n);
+        let pins = ShiftPins::new(pin(0), pin(1), pin(2)).unwrap();
+        let mut register = Sn74hc595::new(pins, 2, ChainOrder::NearestFirst).unwrap();
+        register.write_pin(9, true).unwrap();
+        register.write_chip(0, 0xA5).unwrap();
+        assert_eq!(register.levels(), [0xA5, 0x02]);
+        assert!(register.pin(9) && !register.pin(8) && !register.pin(16));
+        assert_eq!(wire.borrow().frames, [vec![0, 0], vec![0x02, 0], vec![0x02, 0xA5]]);
+        assert!(register.write_chip(2, 0).is_err());
+        assert!(register.write(&[1]).is_err());
+    }
+}
@@ -0,0 +1,425 @@
+//! Arithmetic over measurements, for watch expressions like
+//! `bme280.temperature - ds18b20.temperature`.
+//!
+//! The grammar is the usual one: numbers, measurement names (letters,
+//! digits, `_` and `.`, not starting with a digit), `+ - * /` with the
+//! usual precedence, unary minus, parentheses, and the functions `abs(x)`,
+//! `min(a, b, ...)` and `max(a, b, ...)`. An expression with a measurement
+//! that has no value yet evaluates to `None` rather than failing.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Deepest nesting of parentheses, calls and unary signs accepted, so a
+/// hostile expression can't overflow the stack.
+const MAX_DEPTH: usize = 32;
+
+/// Most tokens in one expression; a long flat chain like `1+1+...` nests
+/// as deeply once parsed.
+const MAX_TOKENS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn symbol(self) -> char {
+        match self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Func {
+    Abs,
+    Min,
+    Max,
+}
+
+impl Func {
+    fn name(self) -> &'static str {
+        match self {
+            Func::Abs => "abs",
+            Func::Min => "min",
+            Func::Max => "max",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Measurement(String),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+impl Expr {
+    /// The value, looking measurements up with `lookup`; `None` if one of
+    /// them has no value.
+    pub fn eval(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        Some(match self {
+            Expr::Number(n) => *n,
+            Expr::Measurement(name) => lookup(name)?,
+            Expr::Neg(inner) => -inner.eval(lookup)?,
+            Expr::Binary(op, left, right) => {
+                let (a, b) = (left.eval(lookup)?, right.eval(lookup)?);
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                }
+            }
+            Expr::Call(func, args) => {
+                let values = args.iter().map(|arg| arg.eval(lookup)).collect::<Option<Vec<_>>>()?;
+                match func {
+                    Func::Abs => values[0].abs(),
+                    Func::Min => values.into_iter().fold(f64::INFINITY, f64::min),
+                    Func::Max => values.into_iter().fold(f64::NEG_INFINITY, f64::max),
+                }
+            }
+        })
+    }
+
+    /// Every measurement named, in order of appearance, repeats included.
+    pub fn measurements(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect(&mut names);
+        names
+    }
+
+    fn collect<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Measurement(name) => names.push(name),
+            Expr::Neg(inner) => inner.collect(names),
+            Expr::Binary(_, left, right) => {
+                left.collect(names);
+                right.collect(names);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.collect(names)),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    /// Fully parenthesized, so the grouping is never in doubt.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Measurement(name) => f.write_str(name),
+            Expr::Neg(inner) => write!(f, "-{}", inner),
+            Expr::Binary(op, left, right) => write!(f, "({} {} {})", left, op.symbol(), right),
+            Expr::Call(func, args) => {
+                write!(f, "{}(", func.name())?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { tokens: tokenize(s)?, pos: 0, depth: 0 };
+        let expr = parser.sum()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {} in '{}'", token, s).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(char),
+    Open,
+    Close,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Name(name) => write!(f, "'{}'", name),
+            Token::Op(op) => write!(f, "'{}'", op),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+            Token::Comma => f.write_str("','"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(start, ch)) = chars.peek() {
+        match ch {
+            _ if ch.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    // Exponent signs belong to the number: 1e-3
+                    let sign = matches!(c, '+' | '-') && matches!(s[..i].chars().last(), Some('e' | 'E'));
+                    if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || sign) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let text = &s[start..end];
+                let n = text.parse().map_err(|_| format!("invalid number '{}'", text))?;
+                tokens.push(Token::Number(n));
+            }
+            _ if ch.is_alphabetic() || ch == '_' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Name(s[start..end].to_string()));
+            }
+            '+' | '-' | '*' | '/' => {
+                tokens.push(Token::Op(ch));
+                chars.next();
+            }
+            '(' | ')' | ',' => {
+                tokens.push(match ch {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => Token::Comma,
+                });
+                chars.next();
+            }
+            _ => return Err(format!("unexpected '{}' at offset {} in '{}'", ch, start, s).into()),
+        }
+    }
+    if tokens.is_empty() {
+        return Err("empty expression".into());
+    }
+    if tokens.len() > MAX_TOKENS {
+        return Err(format!("expression is too long ({} tokens, at most {})", tokens.len(), MAX_TOKENS).into());
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// `parse` one level further in, failing past [`MAX_DEPTH`].
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr, Box<dyn Error>>) -> Result<Expr, Box<dyn Error>> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("expression nested deeper than {}", MAX_DEPTH).into());
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn expect(&mut self, wanted: Token) -> Result<(), Box<dyn Error>> {
+        match self.next() {
+            Some(token) if token == wanted => Ok(()),
+            Some(token) => Err(format!("expected {}, found {}", wanted, token).into()),
+            None => Err(format!("expected {} at the end", wanted).into()),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, Box<dyn Error>> {
+        let mut expr = self.product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            let op = if *op == '+' { Op::Add } else { Op::Sub };
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, Box<dyn Error>> {
+        let mut expr = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek() {
+            let op = if *op == '*' { Op::Mul } else { Op::Div };
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Box<dyn Error>> {
+        match self.peek() {
+            Some(Token::Op('-')) => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.nested(Self::unary)?)))
+            }
+            Some(Token::Op('+')) => {
+                self.pos += 1;
+                self.nested(Self::unary)
+            }
+            _ => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Open) => {
+                let expr = self.nested(Self::sum)?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) if self.peek() == Some(&Token::Open) => self.nested(|parser| parser.call(&name)),
+            Some(Token::Name(name)) => Ok(Expr::Measurement(name)),
+            Some(token) => Err(format!("unexpected {}", token).into()),
+            None => Err("expression ends too early".into()),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr, Box<dyn Error>> {
+        let func = match name {
+            "abs" => Func::Abs,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            _ => return Err(format!("unknown function '{}' (abs, min, max)", name).into()),
+        };
+        self.expect(Token::Open)?;
+        let mut args = vec![self.sum()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.pos += 1;
+            args.push(self.sum()?);
+        }
+        self.expect(Token::Close)?;
+        if func == Func::Abs && args.len() != 1 {
+            return Err(format!("abs takes one argument, got {}", args.len()).into());
+        }
+        Ok(Expr::Call(func, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Expr {
+        s.parse().unwrap()
+    }
+
+    fn error(s: &str) -> String {
+        s.parse::<Expr>().unwrap_err().to_string()
+    }
+
+    fn eval(s: &str) -> Option<f64> {
+        let lookup = |name: &str| match name {
+            "bme280.temperature" => Some(21.5),
+            "ds18b20.temperature" => Some(19.0),
+            _ => None,
+        };
+        parse(s).eval(&lookup)
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(parse("1 + 2 * 3").to_string(), "(1 + (2 * 3))");
+        assert_eq!(parse("8 / 4 / 2").to_string(), "((8 / 4) / 2)");
+        assert_eq!(parse("(1 + 2) * 3").to_string(), "((1 + 2) * 3)");
+        assert_eq!(eval("10 - 4 - 3"), Some(3.0));
+    }
+
+    #[test]
+    fn unary_signs() {
+        assert_eq!(eval("-2 * -3"), Some(6.0));
+        assert_eq!(eval("+4"), Some(4.0));
+        assert_eq!(eval("--1"), Some(1.0));
+    }
+
+    #[test]
+    fn numbers_with_exponents() {
+        assert_eq!(eval("1e-3 * 1000"), Some(1.0));
+        assert_eq!(eval("2.5E+1"), Some(25.0));
+        assert_eq!(error("1.2.3"), "invalid number '1.2.3'");
+    }
+
+    #[test]
+    fn measurements_are_looked_up() {
+        assert_eq!(eval("bme280.temperature - ds18b20.temperature"), Some(2.5));
+        assert_eq!(parse("a + b * a").measurements(), ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn missing_measurement_is_none() {
+        assert_eq!(eval("sht31.humidity + 1"), None);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(eval("abs(ds18b20.temperature - bme280.temperature)"), Some(2.5));
+        assert_eq!(eval("min(3, 1, 2)"), Some(1.0));
+        assert_eq!(eval("max(3, 1, 2)"), Some(3.0));
+        assert_eq!(error("abs(1, 2)"), "abs takes one argument, got 2");
+        assert_eq!(error("sqrt(4)"), "unknown function 'sqrt' (abs, min, max)");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error(""), "empty expression");
+        assert_eq!(error("1 +"), "expression ends too early");
+        assert_eq!(error("(1 + 2"), "expected ')' at the end");
+        assert_eq!(error("1 2"), "unexpected number 2 in '1 2'");
+        assert_eq!(error("1 % 2"), "unexpected '%' at offset 2 in '1 % 2'");
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let ok = "(".repeat(MAX_DEPTH) + "1" + &")".repeat(MAX_DEPTH);
+        assert_eq!(eval(&ok), Some(1.0));
+        let deep = "(".repeat(MAX_DEPTH + 1) + "1" + &")".repeat(MAX_DEPTH + 1);
+        assert_eq!(error(&deep), format!("expression nested deeper than {}", MAX_DEPTH));
+        assert!(error(&("-".repeat(MAX_DEPTH + 1) + "1")).contains("nested deeper"));
+        assert!(error(&"abs(".repeat(MAX_DEPTH + 1)).contains("nested deeper"));
+    }
+
+    #[test]
+    fn hostile_input_is_rejected_not_overflowed() {
+        assert!(error(&("-".repeat(60000) + "1")).contains("too long"));
+        assert!(error(&("1+".repeat(30000) + "1")).contains("too long"));
+    }
+}
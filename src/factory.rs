@@ -0,0 +1,451 @@
+//! Production test runner for small-batch hardware.
+//!
+//! A test plan is a TOML list of stimulus and measurement steps run against
+//! each unit on the fixture:
+//!
+//! ```toml
+//! name = "sensor-hat rev B"
+//!
+//! [[steps]]
+//! kind = "gpio"
+//! name = "enable 5V rail"
+//! pin = 17
+//! level = "high"
+//!
+//! [[steps]]
+//! kind = "delay"
+//! duration = "200ms"
+//!
+//! [[steps]]
+//! kind = "probe"
+//! name = "RTC present"
+//! address = 0x68
+//!
+//! [[steps]]
+//! kind = "measure"
+//! name = "3V3 rail"
+//! address = 0x48
+//! register = 0x00
+//! bytes = 2
+//! signed = true
+//! scale = 0.000125
+//! unit = "V"
+//! min = 3.2
+//! max = 3.4
+//!
+//! [[steps]]
+//! kind = "lcd"
+//! address = 0x27
+//! text = "SELF TEST\nlook at LED"
+//!
+//! [[steps]]
+//! kind = "prompt"
+//! message = "Is the status LED green?"
+//! ```
+//!
+//! [`Fixture::run`] produces a [`UnitReport`] per unit; each serializes to
+//! one JSON line for the batch log.
+
+use crate::address::{Address, AddressedI2c};
+use crate::lcd::Lcd;
+use crate::parse::serde_helpers;
+use crate::scan;
+use crate::units::{Quantity, UnitsConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestPlan {
+    pub name: String,
+    /// Skip the remaining steps once one fails, so a shorted unit isn't
+    /// powered any longer than needed.
+    #[serde(default = "default_stop_on_failure")]
+    pub stop_on_failure: bool,
+    pub steps: Vec<Step>,
+}
+
+fn default_stop_on_failure() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    High,
+    Low,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endian {
+    #[default]
+    Big,
+    Little,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Step {
+    /// Drive a fixture GPIO; it keeps the level for the rest of the unit.
+    Gpio {
+        name: Option<String>,
+        pin: u8,
+        level: Level,
+    },
+    Delay {
+        #[serde(deserialize_with = "serde_helpers::duration")]
+        duration: Duration,
+    },
+    /// Pass if something ACKs at `address`.
+    Probe { name: Option<String>, address: u16 },
+    Measure(Measure),
+    /// Show text on an HD44780 LCD, e.g. instructions for the operator.
+    Lcd {
+        address: u16,
+        #[serde(default = "default_cols")]
+        cols: u8,
+        #[serde(default = "default_rows")]
+        rows: u8,
+        text: String,
+    },
+    /// Ask the operator a yes/no question; "no" fails the step.
+    Prompt { message: String },
+}
+
+fn default_cols() -> u8 {
+    16
+}
+
+fn default_rows() -> u8 {
+    2
+}
+
+/// Read a register, scale it and check it falls in `min..=max`; an ADC
+/// channel, a current monitor, a temperature sensor.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Measure {
+    pub name: String,
+    pub address: u16,
+    pub register: u8,
+    /// Bytes to read, 1-4.
+    #[serde(default = "default_bytes")]
+    pub bytes: usize,
+    #[serde(default)]
+    pub endian: Endian,
+    #[serde(default)]
+    pub signed: bool,
+    /// Bits to drop from the right, for left-justified ADC results.
+    #[serde(default)]
+    pub shift: u32,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    pub unit: Option<String>,
+    /// What the scaled value measures, in its base unit (°C, hPa, mm). The
+    /// report then shows it in the display units; limits stay in base units.
+    pub quantity: Option<Quantity>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+fn default_bytes() -> usize {
+    2
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Measure {
+    /// Raw register bytes to the scaled value.
+    pub fn convert(&self, raw: &[u8]) -> f64 {
+        let mut value: u32 = 0;
+        let ordered: Vec<u8> = match self.endian {
+            Endian::Big => raw.to_vec(),
+            Endian::Little => raw.iter().rev().copied().collect(),
+        };
+        for b in ordered {
+            value = (value << 8) | b as u32;
+        }
+        let bits = 8 * raw.len() as u32;
+        let n = if self.signed && bits < 32 && value & (1 << (bits - 1)) != 0 {
+            value as i64 - (1i64 << bits)
+        } else if self.signed {
+            value as i32 as i64
+        } else {
+            value as i64
+        };
+        (n >> self.shift) as f64 * self.scale + self.offset
+    }
+}
+
+impl Step {
+    pub fn label(&self) -> String {
+        let address = |a: &u16| Address::from_raw(*a).map_or(format!("0x{:02X}", a), |a| a.to_string());
+        match self {
+            Step::Gpio { name: Some(name), .. } | Step::Probe { name: Some(name), .. } => name.clone(),
+            Step::Gpio { pin, level, .. } => format!("GPIO {} {:?}", pin, level).to_lowercase(),
+            Step::Delay { duration } => format!("delay {}ms", duration.as_millis()),
+            Step::Probe { address: a, .. } => format!("probe {}", address(a)),
+            Step::Measure(m) => m.name.clone(),
+            Step::Lcd { address: a, .. } => format!("LCD {}", address(a)),
+            Step::Prompt { message } => message.clone(),
+        }
+    }
+}
+
+impl TestPlan {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        text.parse()
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.steps.is_empty() {
+            return Err("test plan has no steps".into());
+        }
+        for (n, step) in self.steps.iter().enumerate() {
+            let context = |e: Box<dyn Error>| format!("step {} ({}): {}", n + 1, step.label(), e);
+            match step {
+                Step::Probe { address, .. } | Step::Lcd { address, .. } => {
+                    Address::from_raw(*address).map_err(context)?;
+                }
+                Step::Measure(m) => {
+                    Address::from_raw(m.address).map_err(context)?;
+                    if !(1..=4).contains(&m.bytes) {
+                        return Err(context("bytes must be 1-4".into()).into());
+                    }
+                    if let (Some(min), Some(max)) = (m.min, m.max) {
+                        if min > max {
+                            return Err(context(format!("min {} is above max {}", min, max).into()).into());
+                        }
+                    }
+                }
+                Step::Gpio { .. } | Step::Delay { .. } | Step::Prompt { .. } => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TestPlan {
+    type Err = Box<dyn Error>;
+
+    /// Parse and validate.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let plan: TestPlan = toml::from_str(s)?;
+        plan.validate()?;
+        Ok(plan)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Pass,
+    Fail,
+    Skipped,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Verdict::Pass => "PASS",
+            Verdict::Fail => "FAIL",
+            Verdict::Skipped => "SKIP",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StepResult {
+    pub step: String,
+    pub verdict: Verdict,
+    /// Measured value, or why the step failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnitReport {
+    pub plan: String,
+    pub serial: String,
+    /// Seconds since the Unix epoch.
+    pub started: u64,
+    pub passed: bool,
+    pub steps: Vec<StepResult>,
+}
+
+/// What a step found on the unit.
+enum Check {
+    /// With a reading to log, for measurements.
+    Pass(Option<String>),
+    Fail(String),
+}
+
+type GpioDriver = Box<dyn FnMut(u8, bool) -> Result<(), Box<dyn Error>> + Send>;
+type Operator = Box<dyn FnMut(&str) -> Result<bool, Box<dyn Error>> + Send>;
+
+/// The bus, GPIOs and operator a plan runs against.
+pub struct Fixture<I2C> {
+    i2c: I2C,
+    gpio: Option<GpioDriver>,
+    operator: Option<Operator>,
+    units: UnitsConfig,
+}
+
+impl<I2C: AddressedI2c> Fixture<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Fixture {
+            i2c,
+            gpio: None,
+            operator: None,
+            units: UnitsConfig::default(),
+        }
+    }
+
+    /// Display units for readings of `measure` steps with a `quantity`.
+    pub fn set_units(&mut self, units: UnitsConfig) {
+        self.units = units;
+    }
+
+    /// How `gpio` steps drive a pin. Without one they fail.
+    pub fn set_gpio<F>(&mut self, gpio: F)
+    where
+        F: FnMut(u8, bool) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        self.gpio = Some(Box::new(gpio));
+    }
+
+    /// How `prompt` steps ask the operator. Without one they fail.
+    pub fn set_operator<F>(&mut self, operator: F)
+    where
+        F: FnMut(&str) -> Result<bool, Box<dyn Error>> + Send + 'static,
+    {
+        self.operator = Some(Box::new(operator));
+    }
+
+    /// Drive `gpio` steps through the Pi's GPIO. Pins stay claimed, at the
+    /// level last driven, until the fixture is dropped.
+    pub fn use_pi_gpio(&mut self) -> Result<(), Box<dyn Error>> {
+        let gpio = rppal::gpio::Gpio::new()?;
+        let mut pins: HashMap<u8, rppal::gpio::OutputPin> = HashMap::new();
+        self.set_gpio(move |pin, high| {
+            let output = match pins.entry(pin) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => e.insert(gpio.get(pin)?.into_output()),
+            };
+            output.write(high.into());
+            Ok(())
+        });
+        Ok(())
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Run every step of `plan` on the unit with serial number `serial`.
+    pub fn run(&mut self, plan: &TestPlan, serial: &str) -> UnitReport {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mut steps = Vec::new();
+        let mut failed = false;
+        for step in &plan.steps {
+            if failed && plan.stop_on_failure {
+                steps.push(StepResult {
+                    step: step.label(),
+                    verdict: Verdict::Skipped,
+                    detail: None,
+                    duration_ms: 0,
+                });
+                continue;
+            }
+            let began = Instant::now();
+            let (verdict, detail) = match self.step(step) {
+                Ok(Check::Pass(detail)) => (Verdict::Pass, detail),
+                Ok(Check::Fail(why)) => (Verdict::Fail, Some(why)),
+                Err(e) => (Verdict::Fail, Some(e.to_string())),
+            };
+            failed |= verdict == Verdict::Fail;
+            steps.push(StepResult {
+                step: step.label(),
+                verdict,
+                detail,
+                duration_ms: began.elapsed().as_millis() as u64,
+            });
+        }
+        UnitReport {
+            plan: plan.name.clone(),
+            serial: serial.to_string(),
+            started,
+            passed: !failed,
+            steps,
+        }
+    }
+
+    /// `Err` if the step couldn't run at all, which fails it just the same.
+    fn step(&mut self, step: &Step) -> Result<Check, Box<dyn Error>> {
+        match step {
+            Step::Gpio { pin, level, .. } => {
+                let gpio = self.gpio.as_mut().ok_or("no GPIO access on this fixture")?;
+                gpio(*pin, *level == Level::High)?;
+                Ok(Check::Pass(None))
+            }
+            Step::Delay { duration } => {
+                thread::sleep(*duration);
+                Ok(Check::Pass(None))
+            }
+            Step::Probe { address, .. } => {
+                let address = Address::from_raw(*address)?;
+                if scan::probe(&mut self.i2c, address) {
+                    Ok(Check::Pass(None))
+                } else {
+                    Ok(Check::Fail(format!("no response at {}", address)))
+                }
+            }
+            Step::Measure(m) => {
+                let mut raw = vec![0; m.bytes];
+                self.i2c.write_read_at(Address::from_raw(m.address)?, &[m.register], &mut raw)?;
+                let value = m.convert(&raw);
+                let reading = match m.quantity {
+                    Some(quantity) => self.units.format(quantity, value, 2),
+                    None => format!("{:.4}{}", value, m.unit.as_deref().unwrap_or("")),
+                };
+                let in_range = m.min.is_none_or(|min| value >= min) && m.max.is_none_or(|max| value <= max);
+                if in_range {
+                    Ok(Check::Pass(Some(reading)))
+                } else {
+                    let bound = |b: Option<f64>| b.map_or("-".to_string(), |b| b.to_string());
+                    Ok(Check::Fail(format!("{} outside {}..{}", reading, bound(m.min), bound(m.max))))
+                }
+            }
+            Step::Lcd { address, cols, rows, text } => {
+                let mut lcd = Lcd::new(&mut self.i2c, Address::from_raw(*address)?, *cols, *rows)?;
+                lcd.show(text)?;
+                Ok(Check::Pass(None))
+            }
+            Step::Prompt { message } => {
+                let operator = self.operator.as_mut().ok_or("no operator on this fixture")?;
+                if operator(message)? {
+                    Ok(Check::Pass(None))
+                } else {
+                    Ok(Check::Fail("operator answered no".to_string()))
+                }
+            }
+        }
+    }
+}
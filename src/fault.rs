@@ -0,0 +1,311 @@
+//! Deliberately malformed traffic, to test what a slave does with it.
+//!
+//! A [`FaultInjector`] wraps a bus and, before each write goes out, may
+//! flip a bit in a byte, leave a byte out, cut the write in two with a
+//! STOP and a fresh START in between, or break its framing by ending it
+//! early or tacking junk on the end. Each fault has its own probability
+//! in a [`FaultConfig`], per byte for corruption and drops and per write
+//! for the rest. The choices come from a seeded generator, so a run that
+//! upsets the firmware under test can be repeated exactly:
+//!
+//! ```text
+//! --inject corrupt=0.01,drop=0.005,stop=0.02,framing=0.01,seed=42,address=0x27
+//! ```
+//!
+//! Reads pass through untouched. What was injected is counted behind a
+//! [`FaultLog`] handle.
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use crate::rng::Rng;
+use crate::softi2c::Stretching;
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Most junk bytes a framing fault appends.
+const MAX_JUNK: u64 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultConfig {
+    /// Starts the generator; the same seed and traffic inject the same faults.
+    pub seed: u64,
+    /// Chance per written byte of one bit flipped.
+    pub corrupt: f64,
+    /// Chance per written byte of it being left out.
+    pub drop: f64,
+    /// Chance per write of a STOP in the middle of it.
+    pub stop: f64,
+    /// Chance per write of it being cut short or running on with junk.
+    pub framing: f64,
+    /// Only writes to this address are touched; `None` for all of them.
+    pub address: Option<Address>,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        FaultConfig {
+            seed: 0,
+            corrupt: 0.0,
+            drop: 0.0,
+            stop: 0.0,
+            framing: 0.0,
+            address: None,
+        }
+    }
+}
+
+impl FaultConfig {
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (name, p) in [("corrupt", self.corrupt), ("drop", self.drop), ("stop", self.stop), ("framing", self.framing)] {
+            if !(0.0..=1.0).contains(&p) {
+                return Err(format!("{} chance {} is not between 0 and 1", name, p).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FaultConfig {
+    type Err = Box<dyn Error>;
+
+    /// `KEY=VALUE` pairs separated by commas, as in the module docs; keys
+    /// left out keep their defaults.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = FaultConfig::default();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| format!("'{}' is not KEY=VALUE", pair))?;
+            let chance = || value.trim().parse::<f64>().map_err(|_| format!("{}: '{}' is not a number", key, value));
+            match key.trim() {
+                "corrupt" => config.corrupt = chance()?,
+                "drop" => config.drop = chance()?,
+                "stop" => config.stop = chance()?,
+                "framing" => config.framing = chance()?,
+                "seed" => config.seed = value.trim().parse().map_err(|_| format!("seed '{}' is not a whole number", value))?,
+                "address" => config.address = Some(value.parse()?),
+                other => return Err(format!("unknown fault '{}' (corrupt, drop, stop, framing, seed or address)", other).into()),
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// What a [`FaultInjector`] has done so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FaultCounts {
+    /// Writes that went through the injector, faulty or not.
+    pub writes: u64,
+    pub corrupted: u64,
+    pub dropped: u64,
+    pub stops: u64,
+    pub framing: u64,
+}
+
+impl FaultCounts {
+    pub fn total(&self) -> u64 {
+        self.corrupted + self.dropped + self.stops + self.framing
+    }
+}
+
+impl fmt::Display for FaultCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} fault(s) in {} write(s): {} corrupted, {} dropped, {} stray STOP(s), {} framing",
+            self.total(),
+            self.writes,
+            self.corrupted,
+            self.dropped,
+            self.stops,
+            self.framing
+        )
+    }
+}
+
+/// Shared handle to a [`FaultInjector`]'s counts, readable after the bus
+/// has been handed to a driver.
+#[derive(Debug, Clone, Default)]
+pub struct FaultLog {
+    counts: Arc<Mutex<FaultCounts>>,
+}
+
+impl FaultLog {
+    pub fn snapshot(&self) -> FaultCounts {
+        *self.counts.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn update(&self, f: impl FnOnce(&mut FaultCounts)) {
+        f(&mut self.counts.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    }
+}
+
+pub struct FaultInjector<I2C> {
+    i2c: I2C,
+    config: FaultConfig,
+    rng: Rng,
+    log: FaultLog,
+}
+
+/// One write as it will go out, and where a stray STOP splits it.
+struct Planned {
+    bytes: Vec<u8>,
+    split: Option<usize>,
+}
+
+impl<I2C> FaultInjector<I2C> {
+    pub fn new(i2c: I2C, config: FaultConfig) -> Self {
+        FaultInjector {
+            i2c,
+            rng: Rng::new(config.seed),
+            config,
+            log: FaultLog::default(),
+        }
+    }
+
+    pub fn config(&self) -> &FaultConfig {
+        &self.config
+    }
+
+    pub fn log(&self) -> FaultLog {
+        self.log.clone()
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// The faulty version of every write in `operations`, in order.
+    fn plan(&mut self, address: Address, operations: &[Operation<'_>]) -> Vec<Planned> {
+        let targeted = self.config.address.is_none_or(|a| a == address);
+        let mut plans = Vec::new();
+        for op in operations {
+            let Operation::Write(bytes) = op else {
+                continue;
+            };
+            let mut counts = FaultCounts {
+                writes: 1,
+                ..FaultCounts::default()
+            };
+            let mut out = Vec::with_capacity(bytes.len());
+            let mut split = None;
+            if targeted {
+                for &byte in bytes.iter() {
+                    if self.rng.chance(self.config.drop) {
+                        counts.dropped += 1;
+                        continue;
+                    }
+                    if self.rng.chance(self.config.corrupt) {
+                        counts.corrupted += 1;
+                        out.push(byte ^ 1 << self.rng.below(8));
+                    } else {
+                        out.push(byte);
+                    }
+                }
+                if self.rng.chance(self.config.framing) {
+                    counts.framing += 1;
+                    if !out.is_empty() && self.rng.chance(0.5) {
+                        let keep = self.rng.below(out.len() as u64) as usize;
+                        out.truncate(keep);
+                    } else {
+                        for _ in 0..=self.rng.below(MAX_JUNK) {
+                            out.push(self.rng.next_u64() as u8);
+                        }
+                    }
+                }
+                if out.len() >= 2 && self.rng.chance(self.config.stop) {
+                    counts.stops += 1;
+                    split = Some(1 + self.rng.below(out.len() as u64 - 1) as usize);
+                }
+            } else {
+                out.extend_from_slice(bytes);
+            }
+            self.log.update(|c| {
+                c.writes += counts.writes;
+                c.corrupted += counts.corrupted;
+                c.dropped += counts.dropped;
+                c.stops += counts.stops;
+                c.framing += counts.framing;
+            });
+            plans.push(Planned { bytes: out, split });
+        }
+        plans
+    }
+}
+
+/// Run `operations` with their writes replaced by `plans`, as one
+/// transaction per stray STOP plus one.
+fn execute<E>(
+    operations: &mut [Operation<'_>],
+    plans: &[Planned],
+    mut transaction: impl FnMut(&mut [Operation<'_>]) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut plans = plans.iter();
+    let mut current: Vec<Operation<'_>> = Vec::with_capacity(operations.len() + 1);
+    for op in operations.iter_mut() {
+        match op {
+            Operation::Read(buffer) => current.push(Operation::Read(buffer)),
+            Operation::Write(_) => {
+                let plan = plans.next().expect("a plan per write");
+                match plan.split {
+                    Some(at) => {
+                        current.push(Operation::Write(&plan.bytes[..at]));
+                        transaction(&mut current)?;
+                        current.clear();
+                        current.push(Operation::Write(&plan.bytes[at..]));
+                    }
+                    None => current.push(Operation::Write(&plan.bytes)),
+                }
+            }
+        }
+    }
+    transaction(&mut current)
+}
+
+impl<I2C: I2c> ErrorType for FaultInjector<I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> I2c for FaultInjector<I2C> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        let plans = self.plan(Address::SevenBit(address), operations);
+        let i2c = &mut self.i2c;
+        execute(operations, &plans, |ops| i2c.transaction(address, ops))
+    }
+}
+
+impl<I2C: AddressedI2c> AddressedI2c for FaultInjector<I2C> {
+    fn transaction_at(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+        let plans = self.plan(address, operations);
+        let i2c = &mut self.i2c;
+        execute(operations, &plans, |ops| i2c.transaction_at(address, ops))
+    }
+
+    fn repeated_start(&self, address: Address) -> bool {
+        self.i2c.repeated_start(address)
+    }
+}
+
+impl<I2C: BusControl> BusControl for FaultInjector<I2C> {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        self.i2c.clock_speed()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.i2c.set_timeout(timeout)
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        self.i2c.set_clock_speed(hz)
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        self.i2c.recover()
+    }
+    fn stretching(&self) -> Option<Stretching> {
+        self.i2c.stretching()
+    }
+}
@@ -0,0 +1,329 @@
+//! A C interface to the bus, for test fixtures written in C.
+//!
+//! The functions are declared in `include/rpi_peripherals.h`. Build the
+//! library for linking with
+//!
+//! ```text
+//! cargo rustc --release --lib --no-default-features --features ffi --crate-type staticlib
+//! cc fixture.c -Iinclude target/release/librpi_peripherals.a -lpthread -ldl -lm
+//! ```
+//!
+//! or `--crate-type cdylib` for a shared library. Everything goes through
+//! the same bus code as the command line: addresses are checked,
+//! [`rpi_bus_set_timeout`] bounds every transaction, [`rpi_bus_recover`]
+//! clocks a stuck slave free, and a handle can be used from several
+//! threads, one transaction at a time.
+//!
+//! Functions that can fail return 0 or a count on success and a negative
+//! [`ExitStatus`] code on failure, e.g. -2 for a device that didn't
+//! answer; [`rpi_last_error`] has the message. Functions that return a
+//! handle return `NULL` on failure instead.
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus::{self, BusControl, StubBus};
+use crate::detect::Detection;
+use crate::exit::ExitStatus;
+use crate::lcd::Lcd;
+use crate::scan;
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The C header, as shipped in `include/`.
+pub const HEADER: &str = include_str!("../include/rpi_peripherals.h");
+
+trait FfiBus: AddressedI2c + BusControl + Send {}
+
+impl<T: AddressedI2c + BusControl + Send> FfiBus for T {}
+
+/// An open bus; `rpi_bus` to C, which only ever sees a pointer to it.
+pub struct RpiBus {
+    i2c: Mutex<Box<dyn FfiBus>>,
+}
+
+impl RpiBus {
+    fn new(i2c: impl AddressedI2c + BusControl + Send + 'static) -> *mut RpiBus {
+        Box::into_raw(Box::new(RpiBus {
+            i2c: Mutex::new(Box::new(i2c)),
+        }))
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).expect("NULs were replaced");
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// 0, or the error's negative exit code with the message kept for
+/// [`rpi_last_error`].
+fn status(result: Result<c_int, Box<dyn Error>>) -> c_int {
+    match result {
+        Ok(n) => n,
+        Err(e) => {
+            set_error(&e.to_string());
+            -c_int::from(ExitStatus::classify(e.as_ref()).code())
+        }
+    }
+}
+
+/// Run `f` on the bus behind `bus`, or fail for a `NULL` handle.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or a handle from one of the open functions, not yet
+/// closed.
+unsafe fn with_bus<F>(bus: *mut RpiBus, f: F) -> c_int
+where
+    F: FnOnce(&mut dyn FfiBus) -> Result<c_int, Box<dyn Error>>,
+{
+    // SAFETY: the caller passes a live handle or NULL, which as_ref turns
+    // into None.
+    let Some(bus) = (unsafe { bus.as_ref() }) else {
+        set_error("bus handle is NULL");
+        return -c_int::from(ExitStatus::Failure.code());
+    };
+    let mut i2c = bus.i2c.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    status(f(i2c.as_mut()))
+}
+
+/// `len` bytes at `data`, allowing `NULL` when there are none.
+///
+/// # Safety
+///
+/// Unless `len` is 0, `data` points to `len` readable bytes.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err("data is NULL".into()),
+        // SAFETY: non-NULL, and the caller vouches for len bytes
+        (false, _) => Ok(unsafe { slice::from_raw_parts(data, len) }),
+    }
+}
+
+/// # Safety
+///
+/// Unless `len` is 0, `buffer` points to `len` writable bytes.
+unsafe fn bytes_mut<'a>(buffer: *mut u8, len: usize) -> Result<&'a mut [u8], Box<dyn Error>> {
+    match (buffer.is_null(), len) {
+        (_, 0) => Ok(&mut []),
+        (true, _) => Err("buffer is NULL".into()),
+        // SAFETY: non-NULL, and the caller vouches for len bytes
+        (false, _) => Ok(unsafe { slice::from_raw_parts_mut(buffer, len) }),
+    }
+}
+
+/// Open `/dev/i2c-<bus>`; `NULL` if it can't be.
+#[no_mangle]
+pub extern "C" fn rpi_bus_open(bus: u8) -> *mut RpiBus {
+    match bus::open(bus) {
+        Ok(i2c) => RpiBus::new(i2c),
+        Err(e) => {
+            set_error(&e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// An in-memory bus where every address NACKs, for fixtures that run
+/// without hardware.
+#[no_mangle]
+pub extern "C" fn rpi_bus_open_stub() -> *mut RpiBus {
+    RpiBus::new(StubBus::new())
+}
+
+/// Close a handle; `NULL` is ignored.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or an open handle, and isn't used again.
+#[no_mangle]
+pub unsafe extern "C" fn rpi_bus_close(bus: *mut RpiBus) {
+    if !bus.is_null() {
+        // SAFETY: it came from Box::into_raw in RpiBus::new and is dropped once
+        drop(unsafe { Box::from_raw(bus) });
+    }
+}
+
+/// Fail transactions that take longer than `ms` milliseconds.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or an open handle.
+#[no_mangle]
+pub unsafe extern "C" fn rpi_bus_set_timeout(bus: *mut RpiBus, ms: u32) -> c_int {
+    // SAFETY: passed on from the caller
+    unsafe {
+        with_bus(bus, |i2c| {
+            i2c.set_timeout(Duration::from_millis(u64::from(ms)))?;
+            Ok(0)
+        })
+    }
+}
+
+/// Clock out a slave holding SDA low, then send a STOP.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or an open handle.
+#[no_mangle]
+pub unsafe extern "C" fn rpi_bus_recover(bus: *mut RpiBus) -> c_int {
+    // SAFETY: passed on from the caller
+    unsafe {
+        with_bus(bus, |i2c| {
+            i2c.recover()?;
+            Ok(0)
+        })
+    }
+}
+
+/// Write `len` bytes to `address`, 7-bit unless above 0x7F.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or an open handle; `data` points to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rpi_i2c_write(bus: *mut RpiBus, address: u16, data: *const u8, len: usize) -> c_int {
+    // SAFETY: passed on from the caller
+    unsafe {
+        with_bus(bus, |i2c| {
+            i2c.write_at(Address::from_raw(address)?, bytes(data, len)?)?;
+            Ok(0)
+        })
+    }
+}
+
+/// Read `len` bytes from `address` into `buffer`.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or an open handle; `buffer` has room for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rpi_i2c_read(bus: *mut RpiBus, address: u16, buffer: *mut u8, len: usize) -> c_int {
+    // SAFETY: passed on from the caller
+    unsafe {
+        with_bus(bus, |i2c| {
+            i2c.read_at(Address::from_raw(address)?, bytes_mut(buffer, len)?)?;
+            Ok(0)
+        })
+    }
+}
+
+/// Write `len` bytes, then read `buffer_len` back after a repeated START,
+/// as for reading a register.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or an open handle; `data` points to `len` bytes and
+/// `buffer` has room for `buffer_len`.
+#[no_mangle]
+pub unsafe extern "C" fn rpi_i2c_write_read(
+    bus: *mut RpiBus,
+    address: u16,
+    data: *const u8,
+    len: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+) -> c_int {
+    // SAFETY: passed on from the caller
+    unsafe {
+        with_bus(bus, |i2c| {
+            i2c.write_read_at(Address::from_raw(address)?, bytes(data, len)?, bytes_mut(buffer, buffer_len)?)?;
+            Ok(0)
+        })
+    }
+}
+
+/// Initialise the HD44780 behind a PCF8574 backpack at `address`, or at
+/// whichever of 0x27 and 0x3F answers for 0, and show `text` on it. A
+/// `\n` starts the next row.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or an open handle; `text` is a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rpi_lcd_print(bus: *mut RpiBus, address: u16, cols: u8, rows: u8, text: *const c_char) -> c_int {
+    if text.is_null() {
+        set_error("text is NULL");
+        return -c_int::from(ExitStatus::Failure.code());
+    }
+    // SAFETY: non-NULL, and the caller vouches for the terminating NUL
+    let text = unsafe { CStr::from_ptr(text) }.to_string_lossy();
+    // SAFETY: passed on from the caller
+    unsafe {
+        with_bus(bus, |mut i2c| {
+            let address = match address {
+                0 => Detection::lcd().find(&mut i2c)?,
+                raw => Address::from_raw(raw)?,
+            };
+            Lcd::new(i2c, address, cols, rows)?.show(&text)?;
+            Ok(0)
+        })
+    }
+}
+
+/// Probe every 7-bit address from 0x08 to 0x77 with a one-byte read, as
+/// `i2cdetect` does. Writes up to `capacity` of the ones that answer to
+/// `found` and returns how many answered, which may be more.
+///
+/// # Safety
+///
+/// `bus` is `NULL` or an open handle; `found` has room for `capacity`
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rpi_scan(bus: *mut RpiBus, found: *mut u8, capacity: usize) -> c_int {
+    // SAFETY: passed on from the caller
+    unsafe {
+        with_bus(bus, |mut i2c| {
+            let addresses = scan::scan(&mut i2c);
+            let out = bytes_mut(found, capacity)?;
+            for (slot, address) in out.iter_mut().zip(&addresses) {
+                *slot = address.raw() as u8;
+            }
+            Ok(addresses.len() as c_int)
+        })
+    }
+}
+
+/// The message of the last error on this thread, empty if there was none.
+/// It stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn rpi_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stub_bus_from_c() {
+        let bus = rpi_bus_open_stub();
+        let mut found = [0u8; 4];
+        // SAFETY: the handle is open and the buffers are the lengths given
+        unsafe {
+            assert_eq!(rpi_scan(bus, found.as_mut_ptr(), found.len()), 0);
+            assert_eq!(rpi_i2c_write(bus, 0x27, [0x08].as_ptr(), 1), -3);
+            assert!(CStr::from_ptr(rpi_last_error()).to_str().unwrap().contains("0x27"));
+            assert_eq!(rpi_i2c_write(bus, 0x800, ptr::null(), 0), -1);
+            assert_eq!(rpi_lcd_print(bus, 0, 16, 2, c"hi".as_ptr()), -2);
+            rpi_bus_close(bus);
+            assert_eq!(rpi_bus_set_timeout(ptr::null_mut(), 10), -1);
+        }
+
+        // every exported function is declared in the header
+        let exported = include_str!("ffi.rs")
+            .lines()
+            .filter_map(|line| line.split_once("extern \"C\" fn ")?.1.split('(').next());
+        for name in exported {
+            assert!(HEADER.contains(&format!("{}(", name)), "{} is missing from the header", name);
+        }
+    }
+}
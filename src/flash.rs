@@ -0,0 +1,344 @@
+//! Firmware updates for microcontrollers running an I2C bootloader.
+//!
+//! The protocol is deliberately small, so an AVR or STM32 bootloader can
+//! implement it in a few hundred bytes. Every command is one write, and
+//! its result is a status byte the master polls for, plus a payload for
+//! some commands. Offsets are from the start of the application area;
+//! CRCs are [`crc16`](crate::crc::crc16), big-endian, over everything
+//! before them.
+//!
+//! | command | write | reply (after the status byte) |
+//! |---------|-------|-------------------------------|
+//! | info    | `01` | version, largest chunk, application size (u32) |
+//! | erase   | `02` | — |
+//! | write   | `03 offset(u32) len data.. crc(u16)` | — |
+//! | check   | `04 offset(u32) len(u16)` | CRC of that flash (u16) |
+//! | boot    | `05` | — |
+//!
+//! The status byte is [`STATUS_BUSY`] while the command is still running,
+//! then [`STATUS_OK`] or [`STATUS_FAILED`] (bad CRC, offset out of range,
+//! a flash error). A write is verified by a check of the same chunk, and
+//! retried on any failure. [`flash`] with `resume` checks every chunk
+//! first and skips the ones already there, so an interrupted update picks
+//! up where it stopped rather than starting over. It doesn't erase, so
+//! a bootloader has to erase a page itself before writing into it again.
+//!
+//! Images are raw binaries or Intel HEX ([`load_image`]).
+
+mod ihex;
+
+pub use ihex::parse as parse_ihex;
+
+use crate::address::{Address, AddressedI2c};
+use crate::crc::crc16;
+use crate::timing::PreciseDelay;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+pub const CMD_INFO: u8 = 0x01;
+pub const CMD_ERASE: u8 = 0x02;
+pub const CMD_WRITE: u8 = 0x03;
+pub const CMD_CHECK: u8 = 0x04;
+pub const CMD_BOOT: u8 = 0x05;
+
+pub const STATUS_OK: u8 = 0x06;
+pub const STATUS_FAILED: u8 = 0x15;
+pub const STATUS_BUSY: u8 = 0x01;
+
+/// Bytes per write when the bootloader doesn't ask for fewer.
+pub const DEFAULT_CHUNK: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashConfig {
+    pub address: Address,
+    /// Bytes per write, 1-255; the bootloader's own limit wins if lower.
+    pub chunk: usize,
+    /// Tries per chunk before giving up.
+    pub attempts: u32,
+    /// Time between status polls.
+    pub poll: Duration,
+    /// Longest a command may stay busy; an erase needs the most.
+    pub busy_timeout: Duration,
+    /// Skip chunks that already check out, and don't erase.
+    pub resume: bool,
+    /// Start the application once the image is verified.
+    pub boot: bool,
+}
+
+impl Default for FlashConfig {
+    fn default() -> Self {
+        FlashConfig {
+            address: Address::SevenBit(0x29),
+            chunk: DEFAULT_CHUNK,
+            attempts: 3,
+            poll: Duration::from_millis(1),
+            busy_timeout: Duration::from_secs(5),
+            resume: false,
+            boot: true,
+        }
+    }
+}
+
+impl FlashConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !(1..=255).contains(&self.chunk) {
+            return Err(format!("flash chunk size {} out of range (1-255)", self.chunk).into());
+        }
+        if self.attempts == 0 {
+            return Err("flash needs at least one attempt per chunk".into());
+        }
+        Ok(())
+    }
+}
+
+/// What the bootloader says about itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootloaderInfo {
+    pub version: u8,
+    /// Largest chunk it takes in one write.
+    pub max_chunk: u8,
+    /// Bytes available for the application.
+    pub size: u32,
+}
+
+impl fmt::Display for BootloaderInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bootloader v{}, {} bytes of application space, chunks of up to {} bytes",
+            self.version, self.size, self.max_chunk
+        )
+    }
+}
+
+/// How far [`flash`] has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Bytes that are now on the device, written or already there.
+    pub done: usize,
+    pub total: usize,
+    /// Bytes skipped by `resume` because they already checked out.
+    pub skipped: usize,
+    /// Chunk writes that needed another try.
+    pub retries: u32,
+}
+
+impl Progress {
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        self.done as f64 * 100.0 / self.total as f64
+    }
+}
+
+/// An update that stopped part way. Everything before `offset` is on the
+/// device and verified; flashing again with `resume` carries on from there.
+#[derive(Debug)]
+pub struct FlashFailed {
+    pub offset: u32,
+    pub progress: Progress,
+    pub reason: String,
+}
+
+impl fmt::Display for FlashFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "flash stopped at 0x{:06X} ({:.0}% done): {}",
+            self.offset,
+            self.progress.percent(),
+            self.reason
+        )
+    }
+}
+
+impl Error for FlashFailed {}
+
+/// A bootloader at one address.
+pub struct Bootloader<'a, I2C> {
+    i2c: &'a mut I2C,
+    address: Address,
+    poll: Duration,
+    busy_timeout: Duration,
+    delay: PreciseDelay,
+}
+
+impl<'a, I2C: AddressedI2c> Bootloader<'a, I2C> {
+    pub fn new(i2c: &'a mut I2C, address: Address, poll: Duration, busy_timeout: Duration) -> Self {
+        Bootloader {
+            i2c,
+            address,
+            poll,
+            busy_timeout,
+            delay: PreciseDelay::default(),
+        }
+    }
+
+    pub fn info(&mut self) -> Result<BootloaderInfo, Box<dyn Error>> {
+        let mut reply = [0; 6];
+        self.command(&[CMD_INFO], &mut reply)?;
+        Ok(BootloaderInfo {
+            version: reply[0],
+            max_chunk: reply[1],
+            size: u32::from_be_bytes([reply[2], reply[3], reply[4], reply[5]]),
+        })
+    }
+
+    pub fn erase(&mut self) -> Result<(), Box<dyn Error>> {
+        self.command(&[CMD_ERASE], &mut [])
+    }
+
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let len = u8::try_from(data.len()).map_err(|_| format!("{} bytes don't fit one write", data.len()))?;
+        let mut bytes = Vec::with_capacity(data.len() + 8);
+        bytes.push(CMD_WRITE);
+        bytes.extend_from_slice(&offset.to_be_bytes());
+        bytes.push(len);
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&crc16(&bytes).to_be_bytes());
+        self.command(&bytes, &mut [])
+    }
+
+    /// The CRC of `len` bytes of flash from `offset`.
+    pub fn check(&mut self, offset: u32, len: u16) -> Result<u16, Box<dyn Error>> {
+        let mut bytes = vec![CMD_CHECK];
+        bytes.extend_from_slice(&offset.to_be_bytes());
+        bytes.extend_from_slice(&len.to_be_bytes());
+        let mut reply = [0; 2];
+        self.command(&bytes, &mut reply)?;
+        Ok(u16::from_be_bytes(reply))
+    }
+
+    /// Whether the `data` at `offset` is already on the device.
+    pub fn verify(&mut self, offset: u32, data: &[u8]) -> Result<bool, Box<dyn Error>> {
+        Ok(self.check(offset, data.len() as u16)? == crc16(data))
+    }
+
+    pub fn boot(&mut self) -> Result<(), Box<dyn Error>> {
+        self.i2c.write_at(self.address, &[CMD_BOOT])
+    }
+
+    /// Send `bytes` and poll until the bootloader is done with them, then
+    /// fill `reply` from what follows the status byte.
+    fn command(&mut self, bytes: &[u8], reply: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.i2c.write_at(self.address, bytes)?;
+        let mut buf = vec![0; reply.len() + 1];
+        let deadline = Instant::now() + self.busy_timeout;
+        loop {
+            self.delay.delay(self.poll);
+            // a bootloader busy writing flash may not answer at all
+            let answered = self.i2c.read_at(self.address, &mut buf);
+            match (answered, buf[0]) {
+                (Ok(()), STATUS_OK) => {
+                    reply.copy_from_slice(&buf[1..]);
+                    return Ok(());
+                }
+                (Ok(()), STATUS_FAILED) => return Err(format!("bootloader rejected command 0x{:02X}", bytes[0]).into()),
+                (Ok(()), STATUS_BUSY) | (Err(_), _) if Instant::now() < deadline => {}
+                (Ok(()), STATUS_BUSY) => {
+                    return Err(format!("command 0x{:02X} still busy after {:?}", bytes[0], self.busy_timeout).into())
+                }
+                (Ok(()), other) => return Err(format!("unexpected status 0x{:02X}; is this a bootloader?", other).into()),
+                (Err(e), _) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Whether [`load_image`] reads `path` as Intel HEX.
+pub fn is_hex(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("hex" | "ihex"))
+}
+
+/// Load a firmware image: Intel HEX for `.hex` and `.ihex`, raw binary
+/// otherwise. A HEX file's lowest address is returned with it; a binary
+/// has none of its own and gets 0.
+pub fn load_image(path: &Path) -> Result<(u32, Vec<u8>), Box<dyn Error>> {
+    if is_hex(path) {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        parse_ihex(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+    } else {
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok((0, bytes))
+    }
+}
+
+/// Write `image` at `base` through the bootloader, chunk by chunk, each
+/// verified by CRC and retried up to `config.attempts` times, then boot it
+/// if `config.boot`. `progress` gets called after every chunk. Stops early,
+/// with everything so far verified, if `stop` is set.
+pub fn flash<I2C, F>(
+    i2c: &mut I2C,
+    config: &FlashConfig,
+    base: u32,
+    image: &[u8],
+    stop: &AtomicBool,
+    mut progress: F,
+) -> Result<Progress, Box<dyn Error>>
+where
+    I2C: AddressedI2c,
+    F: FnMut(&BootloaderInfo, &Progress),
+{
+    config.validate()?;
+    let mut bootloader = Bootloader::new(i2c, config.address, config.poll, config.busy_timeout);
+    let info = bootloader.info()?;
+    let end = u64::from(base) + image.len() as u64;
+    if end > u64::from(info.size) {
+        return Err(format!("image ends at 0x{:X}, past the 0x{:X} bytes the bootloader has room for", end, info.size).into());
+    }
+    let chunk = config.chunk.min(usize::from(info.max_chunk)).max(1);
+    if !config.resume {
+        bootloader.erase()?;
+    }
+
+    let mut state = Progress {
+        total: image.len(),
+        ..Progress::default()
+    };
+    for (i, data) in image.chunks(chunk).enumerate() {
+        let offset = base + (i * chunk) as u32;
+        let fail = |state: Progress, reason: String| FlashFailed {
+            offset,
+            progress: state,
+            reason,
+        };
+        if stop.load(Ordering::Relaxed) {
+            return Err(fail(state, "interrupted".to_string()).into());
+        }
+        if config.resume && bootloader.verify(offset, data).unwrap_or(false) {
+            state.done += data.len();
+            state.skipped += data.len();
+            progress(&info, &state);
+            continue;
+        }
+        let mut last = String::new();
+        let mut written = false;
+        for attempt in 1..=config.attempts {
+            if attempt > 1 {
+                state.retries += 1;
+            }
+            match bootloader.write(offset, data).and_then(|()| bootloader.verify(offset, data)) {
+                Ok(true) => {
+                    written = true;
+                    break;
+                }
+                Ok(false) => last = "read back a different CRC".to_string(),
+                Err(e) => last = e.to_string(),
+            }
+        }
+        if !written {
+            return Err(fail(state, format!("{} after {} attempt(s)", last, config.attempts)).into());
+        }
+        state.done += data.len();
+        progress(&info, &state);
+    }
+
+    if config.boot {
+        bootloader.boot()?;
+    }
+    Ok(state)
+}
@@ -0,0 +1,65 @@
+use std::error::Error;
+
+/// Bytes between records that fill no address, as erased flash reads.
+const GAP: u8 = 0xFF;
+
+/// The data in an Intel HEX file, as one run from its lowest address: the
+/// address and the bytes, with gaps filled with 0xFF. Extended segment
+/// (02) and linear (04) address records are followed; start address
+/// records (03, 05) are ignored.
+pub fn parse(text: &str) -> Result<(u32, Vec<u8>), Box<dyn Error>> {
+    let mut upper = 0u32;
+    let mut runs: Vec<(u32, Vec<u8>)> = Vec::new();
+    let mut ended = false;
+    for (n, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+        if line.is_empty() {
+            continue;
+        }
+        if ended {
+            return Err(format!("line {}: data after the end-of-file record", n).into());
+        }
+        let hex = line.strip_prefix(':').ok_or_else(|| format!("line {}: a record starts with ':'", n))?;
+        if hex.len() % 2 != 0 || hex.len() < 10 {
+            return Err(format!("line {}: record is too short or has an odd number of digits", n).into());
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format!("line {}: not hex", n))?;
+        if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+            return Err(format!("line {}: bad checksum", n).into());
+        }
+        let len = usize::from(bytes[0]);
+        if bytes.len() != len + 5 {
+            return Err(format!("line {}: length byte says {} data bytes, record has {}", n, len, bytes.len() - 5).into());
+        }
+        let offset = u32::from(u16::from_be_bytes([bytes[1], bytes[2]]));
+        let data = &bytes[4..4 + len];
+        match bytes[3] {
+            0x00 => {
+                let address = upper + offset;
+                match runs.last_mut() {
+                    Some((start, run)) if *start + run.len() as u32 == address => run.extend_from_slice(data),
+                    _ => runs.push((address, data.to_vec())),
+                }
+            }
+            0x01 => ended = true,
+            0x02 if len == 2 => upper = u32::from(u16::from_be_bytes([data[0], data[1]])) << 4,
+            0x04 if len == 2 => upper = u32::from(u16::from_be_bytes([data[0], data[1]])) << 16,
+            0x03 | 0x05 => {}
+            other => return Err(format!("line {}: unsupported record type {:02X}", n, other).into()),
+        }
+    }
+    if !ended {
+        return Err("no end-of-file record; is the file cut short?".into());
+    }
+    let start = runs.iter().map(|(a, _)| *a).min().ok_or("no data records")?;
+    let end = runs.iter().map(|(a, d)| *a + d.len() as u32).max().unwrap_or(start);
+    let mut image = vec![GAP; (end - start) as usize];
+    for (address, data) in runs {
+        let at = (address - start) as usize;
+        image[at..at + data.len()].copy_from_slice(&data);
+    }
+    Ok((start, image))
+}
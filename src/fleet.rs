@@ -0,0 +1,311 @@
+//! Fleet mode: one view over several Pis running `serve`.
+//!
+//! Each Pi is an agent serving the usual HTTP API. A controller, which can
+//! be another Pi or a laptop, polls every agent's `/health`, `/i2c/scan`
+//! and `/readings` and puts the answers side by side. Agents are listed in
+//! the `[fleet]` config section:
+//!
+//! ```toml
+//! [fleet]
+//! interval = "30s"
+//! timeout = "3s"
+//!
+//! [[fleet.agents]]
+//! name = "greenhouse"
+//! url = "http://greenhouse.local:8080"
+//! token = "3f9c..."          # a read-scope token from the agent's --tokens file
+//!
+//! [[fleet.agents]]
+//! name = "garage"
+//! url = "http://10.0.0.21:8080"
+//! ```
+//!
+//! Agents are polled in parallel, so one that is down only costs the
+//! timeout. Readings agents publish over MQTT reach the broker directly;
+//! the fleet view is the HTTP side of the same data.
+
+mod client;
+
+pub use client::{get_json, Endpoint};
+
+use crate::metrics::Metrics;
+use crate::parse::serde_helpers;
+use crate::sysinfo::SystemStatus;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FleetConfig {
+    #[serde(default = "default_interval", deserialize_with = "serde_helpers::duration")]
+    pub interval: Duration,
+    /// Per request to an agent.
+    #[serde(default = "default_timeout", deserialize_with = "serde_helpers::duration")]
+    pub timeout: Duration,
+    #[serde(default)]
+    pub agents: Vec<AgentConfig>,
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(3)
+}
+
+impl Default for FleetConfig {
+    fn default() -> Self {
+        FleetConfig {
+            interval: default_interval(),
+            timeout: default_timeout(),
+            agents: Vec::new(),
+        }
+    }
+}
+
+impl FleetConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.interval.is_zero() || self.timeout.is_zero() {
+            return Err("fleet.interval and fleet.timeout must be greater than zero".into());
+        }
+        let mut names = HashSet::new();
+        for agent in &self.agents {
+            if !names.insert(agent.name.as_str()) {
+                return Err(format!("fleet agent '{}' is listed twice", agent.name).into());
+            }
+            Endpoint::parse(&agent.url).map_err(|e| format!("fleet agent '{}': {}", agent.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentConfig {
+    pub name: String,
+    /// `http://host:port` of the agent's `serve`.
+    pub url: String,
+    /// Bearer token, if the agent was started with `--tokens`.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// What an agent says about itself at `GET /health`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub hostname: Option<String>,
+    pub version: String,
+    pub uptime_s: Option<u64>,
+    /// °C.
+    pub cpu_temp: Option<f64>,
+    pub load: Option<[f64; 3]>,
+    pub disk_percent: Option<f64>,
+    pub memory_percent: Option<f64>,
+}
+
+impl Health {
+    /// The Pi this runs on.
+    pub fn local() -> Self {
+        let status = SystemStatus::read();
+        Health {
+            hostname: status.hostname,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_s: status.uptime.map(|u| u.as_secs()),
+            cpu_temp: status.cpu_temp,
+            load: status.load,
+            disk_percent: status.disk.map(|d| d.percent()),
+            memory_percent: status.memory.map(|m| m.percent()),
+        }
+    }
+}
+
+/// One agent as of the last poll.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AgentStatus {
+    pub name: String,
+    pub url: String,
+    pub online: bool,
+    /// Why it is offline, or which part of the poll failed.
+    pub error: Option<String>,
+    /// Time for the whole poll.
+    pub latency_ms: f64,
+    pub health: Option<Health>,
+    /// Addresses that answered its scan, as the agent writes them.
+    pub devices: Vec<String>,
+    /// Latest value of every measurement it keeps.
+    pub readings: BTreeMap<String, f64>,
+}
+
+impl fmt::Display for AgentStatus {
+    /// One line: name, state, temperature and load, device count, error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.online { "🟢 up" } else { "🔴 down" };
+        write!(f, "{:<16} {:<8}", self.name, state)?;
+        if let Some(health) = &self.health {
+            match health.cpu_temp {
+                Some(t) => write!(f, " {:>5.1}°C", t)?,
+                None => write!(f, " {:>7}", "--")?,
+            }
+            match health.load {
+                Some([load, _, _]) => write!(f, "  load {:>5.2}", load)?,
+                None => write!(f, "  load {:>5}", "--")?,
+            }
+            write!(f, "  {:>2} devices  {:>3} readings  {:>5.0} ms", self.devices.len(), self.readings.len(), self.latency_ms)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, "  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Clones share the last poll.
+#[derive(Clone)]
+pub struct Fleet {
+    config: FleetConfig,
+    last: Arc<Mutex<Vec<AgentStatus>>>,
+}
+
+impl Fleet {
+    pub fn new(config: FleetConfig) -> Result<Self, Box<dyn Error>> {
+        config.validate()?;
+        if config.agents.is_empty() {
+            return Err("no [[fleet.agents]] in the config".into());
+        }
+        Ok(Fleet {
+            config,
+            last: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    pub fn config(&self) -> &FleetConfig {
+        &self.config
+    }
+
+    /// What the last [`Fleet::poll`] found; empty before the first.
+    pub fn last(&self) -> Vec<AgentStatus> {
+        self.last.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Ask every agent at once; in config order.
+    pub fn poll(&self) -> Vec<AgentStatus> {
+        let timeout = self.config.timeout;
+        let statuses: Vec<AgentStatus> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .config
+                .agents
+                .iter()
+                .map(|agent| scope.spawn(move || poll_agent(agent, timeout)))
+                .collect();
+            handles
+                .into_iter()
+                .zip(&self.config.agents)
+                .map(|(handle, agent)| {
+                    handle.join().unwrap_or_else(|_| AgentStatus {
+                        name: agent.name.clone(),
+                        url: agent.url.clone(),
+                        online: false,
+                        error: Some("poll panicked".to_string()),
+                        latency_ms: 0.0,
+                        health: None,
+                        devices: Vec::new(),
+                        readings: BTreeMap::new(),
+                    })
+                })
+                .collect()
+        });
+        *self.last.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = statuses.clone();
+        statuses
+    }
+}
+
+/// Health decides whether the agent is up; a failed scan or readings
+/// request is only noted.
+fn poll_agent(agent: &AgentConfig, timeout: Duration) -> AgentStatus {
+    let started = Instant::now();
+    let mut status = AgentStatus {
+        name: agent.name.clone(),
+        url: agent.url.clone(),
+        online: false,
+        error: None,
+        latency_ms: 0.0,
+        health: None,
+        devices: Vec::new(),
+        readings: BTreeMap::new(),
+    };
+    let endpoint = match Endpoint::parse(&agent.url) {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            status.error = Some(e.to_string());
+            return status;
+        }
+    };
+    let token = agent.token.as_deref();
+    let get = |path: &str| get_json(&endpoint, path, token, timeout);
+    match get("/health").and_then(|v| Ok(serde_json::from_value::<Health>(v)?)) {
+        Ok(health) => {
+            status.online = true;
+            status.health = Some(health);
+        }
+        Err(e) => {
+            status.error = Some(e.to_string());
+            status.latency_ms = started.elapsed().as_secs_f64() * 1e3;
+            return status;
+        }
+    }
+    let mut errors = Vec::new();
+    match get("/i2c/scan") {
+        Ok(reply) => {
+            status.devices = reply["devices"]
+                .as_array()
+                .map(|found| found.iter().filter_map(|d| d.as_str().map(String::from)).collect())
+                .unwrap_or_default();
+        }
+        Err(e) => errors.push(format!("scan: {}", e)),
+    }
+    match get("/readings") {
+        Ok(reply) => {
+            if let Some(readings) = reply["readings"].as_object() {
+                status.readings = readings
+                    .iter()
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_f64()?)))
+                    .collect();
+            }
+        }
+        Err(e) => errors.push(format!("readings: {}", e)),
+    }
+    if !errors.is_empty() {
+        status.error = Some(errors.join("; "));
+    }
+    status.latency_ms = started.elapsed().as_secs_f64() * 1e3;
+    status
+}
+
+/// Export a poll as gauges: whether each agent is up and every reading,
+/// labelled with the agent.
+pub fn export(metrics: &Metrics, statuses: &[AgentStatus]) {
+    for status in statuses {
+        let agent = [("agent", status.name.as_str())];
+        let up = if status.online { 1.0 } else { 0.0 };
+        metrics.set("rpi_peripherals_agent_up", "Whether the fleet agent answered its last poll", &agent, up);
+        metrics.set(
+            "rpi_peripherals_agent_devices",
+            "I2C devices that answered the agent's last scan",
+            &agent,
+            status.devices.len() as f64,
+        );
+        if let Some(temp) = status.health.as_ref().and_then(|h| h.cpu_temp) {
+            metrics.set("rpi_peripherals_agent_cpu_temp_celsius", "SoC temperature of the agent", &agent, temp);
+        }
+        for (measurement, &value) in &status.readings {
+            let labels = [("agent", status.name.as_str()), ("measurement", measurement.as_str())];
+            metrics.set("rpi_peripherals_agent_reading", "Latest reading kept by the agent", &labels, value);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! A minimal HTTP/1.1 client for talking to agents: `GET` only, JSON
+//! replies, one request per connection.
+
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Largest reply read; a scan, health and readings are a few KB.
+const MAX_REPLY: u64 = 1024 * 1024;
+
+/// `http://host[:port]` split up, with the port defaulting to 80.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Self, Box<dyn Error>> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("agent URL '{}' must start with http://", url))?;
+        let authority = rest.trim_end_matches('/');
+        if authority.contains('/') {
+            return Err(format!("agent URL '{}' must not have a path", url).into());
+        }
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !host.ends_with(']') || authority.starts_with('[') => {
+                let port = port.parse().map_err(|_| format!("invalid port in agent URL '{}'", url))?;
+                (host, port)
+            }
+            _ => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("agent URL '{}' has no host", url).into());
+        }
+        Ok(Endpoint { host: host.to_string(), port })
+    }
+}
+
+/// `GET path` from `endpoint` and parse the body as JSON.
+pub fn get_json(
+    endpoint: &Endpoint,
+    path: &str,
+    token: Option<&str>,
+    timeout: Duration,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    let address = (endpoint.host.trim_matches(|c| c == '[' || c == ']'), endpoint.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| format!("{} does not resolve", endpoint.host))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let auth = token.map(|t| format!("Authorization: Bearer {}\r\n", t)).unwrap_or_default();
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}:{}\r\n{}Accept: application/json\r\nConnection: close\r\n\r\n",
+        path, endpoint.host, endpoint.port, auth
+    )?;
+    stream.flush()?;
+
+    let mut reply = Vec::new();
+    stream.take(MAX_REPLY).read_to_end(&mut reply)?;
+    let split = reply
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("reply ends in the headers")?;
+    let head = String::from_utf8_lossy(&reply[..split]);
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| format!("malformed status line '{}'", head.lines().next().unwrap_or("")))?;
+    let body: serde_json::Value = serde_json::from_slice(&reply[split + 4..])
+        .map_err(|e| format!("{} {}: reply is not JSON: {}", status, path, e))?;
+    if status != 200 {
+        let message = body["error"].as_str().unwrap_or("no error message");
+        return Err(format!("{} {}: {}", status, path, message).into());
+    }
+    Ok(body)
+}
@@ -0,0 +1,217 @@
+//! Serial GPS modules: NMEA sentences in, typed fixes out.
+//!
+//! Modules such as the u-blox NEO-6M send a burst of NMEA 0183 sentences
+//! every second. [`parse_sentence`] checks each one's checksum and decodes
+//! GGA (position, altitude, HDOP, satellites) and RMC (position, speed,
+//! course and the date); [`GpsReader`] merges them into one [`Fix`] per
+//! burst.
+//!
+//! ```no_run
+//! use rpi_peripherals::gps::GpsReader;
+//!
+//! let mut gps = GpsReader::open("/dev/serial0", 9600)?;
+//! loop {
+//!     if let Some(fix) = gps.next_fix()? {
+//!         println!("{}", fix);
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod nmea;
+
+pub use nmea::{parse_sentence, Date, Gga, Rmc, Sentence, UtcTime};
+
+use crate::uart::SerialPort;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// NMEA caps sentences at 82 characters; anything much longer is noise.
+const MAX_LINE: usize = 128;
+
+/// What the receiver knows, from the latest GGA and RMC.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Fix {
+    pub time: Option<UtcTime>,
+    pub date: Option<Date>,
+    /// Degrees, negative to the south.
+    pub latitude: Option<f64>,
+    /// Degrees, negative to the west.
+    pub longitude: Option<f64>,
+    /// Metres above mean sea level.
+    pub altitude: Option<f64>,
+    pub hdop: Option<f64>,
+    pub satellites: Option<u8>,
+    /// GGA's fix quality, 0 for none.
+    pub quality: u8,
+    pub speed_knots: Option<f64>,
+    pub course: Option<f64>,
+}
+
+impl Fix {
+    /// A position to trust: GGA reports a fix and it has coordinates.
+    pub fn valid(&self) -> bool {
+        self.quality > 0 && self.latitude.is_some() && self.longitude.is_some()
+    }
+
+    pub fn update(&mut self, sentence: &Sentence) {
+        match sentence {
+            Sentence::Gga(gga) => {
+                self.time = gga.time.or(self.time);
+                self.latitude = gga.latitude;
+                self.longitude = gga.longitude;
+                self.altitude = gga.altitude;
+                self.hdop = gga.hdop;
+                self.satellites = gga.satellites;
+                self.quality = gga.quality;
+            }
+            Sentence::Rmc(rmc) => {
+                self.time = rmc.time.or(self.time);
+                self.date = rmc.date.or(self.date);
+                self.speed_knots = rmc.speed_knots;
+                self.course = rmc.course;
+                if rmc.latitude.is_some() {
+                    self.latitude = rmc.latitude;
+                    self.longitude = rmc.longitude;
+                }
+                // For modules that send RMC alone, its status is the quality
+                if !rmc.valid {
+                    self.quality = 0;
+                } else if self.quality == 0 {
+                    self.quality = 1;
+                }
+            }
+            Sentence::Other(_) => {}
+        }
+    }
+
+    /// `51.50073N 0.12463W`, or `no fix`.
+    pub fn position(&self) -> String {
+        match (self.valid(), self.latitude, self.longitude) {
+            (true, Some(lat), Some(lon)) => format!(
+                "{:.5}{} {:.5}{}",
+                lat.abs(),
+                if lat < 0.0 { 'S' } else { 'N' },
+                lon.abs(),
+                if lon < 0.0 { 'W' } else { 'E' }
+            ),
+            _ => "no fix".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.time {
+            Some(time) => write!(f, "{} ", time)?,
+            None => write!(f, "--:--:-- ")?,
+        }
+        write!(f, "{}", self.position())?;
+        if let (true, Some(altitude)) = (self.valid(), self.altitude) {
+            write!(f, " {:.1} m", altitude)?;
+        }
+        if let Some(satellites) = self.satellites {
+            write!(f, ", {} satellites", satellites)?;
+        }
+        if let Some(hdop) = self.hdop {
+            write!(f, ", HDOP {:.1}", hdop)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sentences from a GPS module, a line at a time.
+pub struct GpsReader<R> {
+    reader: R,
+    /// A line cut off by a read timeout, finished on the next read.
+    pending: String,
+    fix: Fix,
+    seen_gga: bool,
+    rejected: u64,
+}
+
+impl GpsReader<BufReader<SerialPort>> {
+    /// The module on `path` at `baud_rate` (9600 on most).
+    pub fn open<P: AsRef<Path>>(path: P, baud_rate: u32) -> Result<Self, Box<dyn Error>> {
+        Ok(GpsReader::new(BufReader::new(SerialPort::open(path, baud_rate)?)))
+    }
+}
+
+impl<R: BufRead> GpsReader<R> {
+    /// Also takes a logged NMEA file, to replay a drive.
+    pub fn new(reader: R) -> Self {
+        GpsReader {
+            reader,
+            pending: String::new(),
+            fix: Fix::default(),
+            seen_gga: false,
+            rejected: 0,
+        }
+    }
+
+    /// The next good sentence, or `None` when none came in the port's read
+    /// timeout (or a file ended). Lines with a bad checksum or fields are
+    /// counted in [`Self::rejected`] and skipped.
+    pub fn next_sentence(&mut self) -> Result<Option<Sentence>, Box<dyn Error>> {
+        loop {
+            let read = match self.reader.read_line(&mut self.pending) {
+                Ok(n) => n,
+                // Modules send bursts of noise at power-up
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    self.pending.clear();
+                    self.rejected += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if !self.pending.ends_with('\n') {
+                if self.pending.len() > MAX_LINE {
+                    self.pending.clear();
+                    self.rejected += 1;
+                }
+                if read == 0 {
+                    return Ok(None);
+                }
+                continue;
+            }
+            let line = std::mem::take(&mut self.pending);
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_sentence(&line) {
+                Ok(sentence) => return Ok(Some(sentence)),
+                Err(_) => self.rejected += 1,
+            }
+        }
+    }
+
+    /// The fix once a GGA comes in, or an RMC from a module that sends no
+    /// GGA; `None` as for [`Self::next_sentence`].
+    pub fn next_fix(&mut self) -> Result<Option<Fix>, Box<dyn Error>> {
+        while let Some(sentence) = self.next_sentence()? {
+            self.fix.update(&sentence);
+            match sentence {
+                Sentence::Gga(_) => {
+                    self.seen_gga = true;
+                    return Ok(Some(self.fix.clone()));
+                }
+                Sentence::Rmc(_) if !self.seen_gga => return Ok(Some(self.fix.clone())),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// The fix so far.
+    pub fn fix(&self) -> &Fix {
+        &self.fix
+    }
+
+    /// Lines dropped for a bad checksum or unreadable fields.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+}
@@ -0,0 +1,268 @@
+use serde::{Serialize, Serializer};
+use std::error::Error;
+use std::fmt;
+
+/// UTC time of day from a sentence's `hhmmss.ss` field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UtcTime {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: f64,
+}
+
+impl fmt::Display for UtcTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:05.2}", self.hour, self.minute, self.second)
+    }
+}
+
+impl Serialize for UtcTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The date from RMC's `ddmmyy` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// GGA: the fix itself, with its quality, altitude and HDOP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gga {
+    pub time: Option<UtcTime>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// 0 no fix, 1 GPS, 2 differential, 4 and 5 RTK, 6 dead reckoning.
+    pub quality: u8,
+    pub satellites: Option<u8>,
+    pub hdop: Option<f64>,
+    /// Metres above mean sea level.
+    pub altitude: Option<f64>,
+}
+
+/// RMC: position, speed and course, and the only sentence with the date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rmc {
+    pub time: Option<UtcTime>,
+    /// `A` in the status field; `V` means the receiver has no fix yet.
+    pub valid: bool,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub speed_knots: Option<f64>,
+    /// Degrees true.
+    pub course: Option<f64>,
+    pub date: Option<Date>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sentence {
+    Gga(Gga),
+    Rmc(Rmc),
+    /// A good sentence of another type, such as `GSV`, by its type.
+    Other(String),
+}
+
+/// One NMEA 0183 line, `$GPGGA,...*47`, from any talker (GP, GN, GL...).
+/// The checksum is required and checked.
+pub fn parse_sentence(line: &str) -> Result<Sentence, Box<dyn Error>> {
+    let line = line.trim();
+    let body = line
+        .strip_prefix('$')
+        .ok_or_else(|| format!("'{}' doesn't start with $", line))?;
+    let (body, checksum) = body.rsplit_once('*').ok_or("sentence has no checksum")?;
+    let sent = u8::from_str_radix(checksum, 16).map_err(|_| format!("'{}' is not a checksum", checksum))?;
+    let computed = body.bytes().fold(0, |acc, b| acc ^ b);
+    if sent != computed {
+        return Err(format!("checksum {:02X} sent, {:02X} computed", sent, computed).into());
+    }
+    let fields: Vec<&str> = body.split(',').collect();
+    let address = fields[0];
+    if address.len() != 5 || !address.is_ascii() {
+        return Err(format!("'{}' is not a talker and sentence type", address).into());
+    }
+    let field = |n: usize| fields.get(n).copied().unwrap_or("");
+    match &address[2..] {
+        "GGA" => Ok(Sentence::Gga(Gga {
+            time: time(field(1))?,
+            latitude: coordinate(field(2), field(3), 2)?,
+            longitude: coordinate(field(4), field(5), 3)?,
+            quality: number::<u8>(field(6))?.unwrap_or(0),
+            satellites: number(field(7))?,
+            hdop: number(field(8))?,
+            altitude: number(field(9))?,
+        })),
+        "RMC" => Ok(Sentence::Rmc(Rmc {
+            time: time(field(1))?,
+            valid: field(2) == "A",
+            latitude: coordinate(field(3), field(4), 2)?,
+            longitude: coordinate(field(5), field(6), 3)?,
+            speed_knots: number(field(7))?,
+            course: number(field(8))?,
+            date: date(field(9))?,
+        })),
+        other => Ok(Sentence::Other(other.to_string())),
+    }
+}
+
+fn number<T: std::str::FromStr>(field: &str) -> Result<Option<T>, Box<dyn Error>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    field.parse().map(Some).map_err(|_| format!("'{}' is not a number", field).into())
+}
+
+fn time(field: &str) -> Result<Option<UtcTime>, Box<dyn Error>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let bad = || format!("'{}' is not hhmmss", field);
+    if field.len() < 6 || !decimal(field) || !field.as_bytes()[..6].iter().all(u8::is_ascii_digit) {
+        return Err(bad().into());
+    }
+    let part = |range: std::ops::Range<usize>| field[range].parse::<u8>().map_err(|_| bad());
+    let time = UtcTime {
+        hour: part(0..2)?,
+        minute: part(2..4)?,
+        second: field[4..].parse().map_err(|_| bad())?,
+    };
+    if time.hour > 23 || time.minute > 59 || time.second >= 61.0 {
+        return Err(bad().into());
+    }
+    Ok(Some(time))
+}
+
+fn date(field: &str) -> Result<Option<Date>, Box<dyn Error>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let bad = || format!("'{}' is not ddmmyy", field);
+    if field.len() != 6 || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(bad().into());
+    }
+    let part = |range: std::ops::Range<usize>| field[range].parse::<u8>().map_err(|_| bad());
+    let date = Date {
+        day: part(0..2)?,
+        month: part(2..4)?,
+        // Two-digit years: receivers in use are all this century's
+        year: 2000 + u16::from(part(4..6)?),
+    };
+    if !(1..=12).contains(&date.month) || !(1..=31).contains(&date.day) {
+        return Err(bad().into());
+    }
+    Ok(Some(date))
+}
+
+/// Degrees from `ddmm.mmmm` (or `dddmm.mmmm` with three degree digits),
+/// negative to the south and west.
+fn coordinate(field: &str, hemisphere: &str, degree_digits: usize) -> Result<Option<f64>, Box<dyn Error>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let bad = || format!("'{}' is not a coordinate", field);
+    if field.len() < degree_digits + 2 || !decimal(field) || !field.as_bytes()[..degree_digits].iter().all(u8::is_ascii_digit) {
+        return Err(bad().into());
+    }
+    let degrees: f64 = field[..degree_digits].parse().map_err(|_| bad())?;
+    let minutes: f64 = field[degree_digits..].parse().map_err(|_| bad())?;
+    if minutes >= 60.0 {
+        return Err(bad().into());
+    }
+    let value = degrees + minutes / 60.0;
+    match hemisphere {
+        "N" | "E" => Ok(Some(value)),
+        "S" | "W" => Ok(Some(-value)),
+        other => Err(format!("'{}' is not a hemisphere", other).into()),
+    }
+}
+
+/// Only ASCII digits and at most one `.`, as every numeric field is sent;
+/// anything else is line noise, and safe to slice by byte once ruled out.
+fn decimal(field: &str) -> bool {
+    field.bytes().all(|b| b.is_ascii_digit() || b == b'.') && field.bytes().filter(|&b| b == b'.').count() <= 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GGA: &str = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47";
+    const RMC: &str = "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A";
+
+    fn error(line: &str) -> String {
+        parse_sentence(line).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_gga() {
+        let Sentence::Gga(gga) = parse_sentence(GGA).unwrap() else { panic!("not GGA") };
+        assert_eq!(gga.time, Some(UtcTime { hour: 12, minute: 35, second: 19.0 }));
+        assert!((gga.latitude.unwrap() - 48.1173).abs() < 1e-6);
+        assert!((gga.longitude.unwrap() - 11.516_666).abs() < 1e-6);
+        assert_eq!((gga.quality, gga.satellites, gga.hdop, gga.altitude), (1, Some(8), Some(0.9), Some(545.4)));
+    }
+
+    #[test]
+    fn parses_rmc() {
+        let Sentence::Rmc(rmc) = parse_sentence(RMC).unwrap() else { panic!("not RMC") };
+        assert!(rmc.valid);
+        assert_eq!((rmc.speed_knots, rmc.course), (Some(22.4), Some(84.4)));
+        assert_eq!(rmc.date, Some(Date { year: 2094, month: 3, day: 23 }));
+        assert_eq!(rmc.date.unwrap().to_string(), "2094-03-23");
+    }
+
+    #[test]
+    fn rmc_without_fix_has_empty_fields() {
+        let Sentence::Rmc(rmc) = parse_sentence("$GPRMC,123519,V,,,,,,,,,*3C").unwrap() else { panic!("not RMC") };
+        assert!(!rmc.valid);
+        assert_eq!((rmc.latitude, rmc.date), (None, None));
+    }
+
+    #[test]
+    fn south_and_west_are_negative() {
+        let Sentence::Gga(gga) = parse_sentence("$GPGGA,123519,4807.038,S,01131.000,W,1,08,0.9,545.4,M,46.9,M,,*48").unwrap() else {
+            panic!("not GGA")
+        };
+        assert!(gga.latitude.unwrap() < 0.0 && gga.longitude.unwrap() < 0.0);
+    }
+
+    #[test]
+    fn other_types_are_named() {
+        assert_eq!(parse_sentence("$GPGSV,1,1,00*79").unwrap(), Sentence::Other("GSV".to_string()));
+    }
+
+    #[test]
+    fn checksum_errors() {
+        assert_eq!(error(&GGA.replace("*47", "*48")), "checksum 48 sent, 47 computed");
+        assert_eq!(error("$GPGGA,123519"), "sentence has no checksum");
+        assert_eq!(error("$GPGGA,123519*ZZ"), "'ZZ' is not a checksum");
+        assert_eq!(error("GPGGA,123519*00"), "'GPGGA,123519*00' doesn't start with $");
+    }
+
+    #[test]
+    fn field_errors() {
+        assert_eq!(error("$GPGGA,+12345,,,,,0,,,*50"), "'+12345' is not hhmmss");
+        assert_eq!(error("$GPGGA,123519,48x7.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*0F"), "'48x7.038' is not a coordinate");
+        assert_eq!(error("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,231394,,*10"), "'231394' is not ddmmyy");
+    }
+
+    #[test]
+    fn non_ascii_noise_is_an_error_not_a_panic() {
+        assert_eq!(error("$GPGGA,1é345,,,,,0,,,*23"), "'1é345' is not hhmmss");
+    }
+}
@@ -0,0 +1,196 @@
+//! Recent values per measurement, kept in memory.
+//!
+//! [`History`] is a cheap-to-clone handle onto one shared set of ring
+//! buffers, so the polling loop can record while the HTTP API, rules and
+//! display widgets query the same data without touching storage. Depth is
+//! set in the `[history]` config section:
+//!
+//! ```toml
+//! [history]
+//! depth = 720                       # samples per measurement
+//!
+//! [history.depths]
+//! "bme280.temperature" = 8640       # a day at one sample per 10 s
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    #[serde(default = "default_depth")]
+    pub depth: usize,
+    /// Per-measurement overrides of `depth`.
+    #[serde(default)]
+    pub depths: BTreeMap<String, usize>,
+}
+
+fn default_depth() -> usize {
+    720
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            depth: default_depth(),
+            depths: BTreeMap::new(),
+        }
+    }
+}
+
+impl HistoryConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.depth == 0 {
+            return Err("history.depth must be at least 1".into());
+        }
+        if let Some((name, _)) = self.depths.iter().find(|(_, &depth)| depth == 0) {
+            return Err(format!("history depth for '{}' must be at least 1", name).into());
+        }
+        Ok(())
+    }
+
+    pub fn depth_of(&self, measurement: &str) -> usize {
+        self.depths.get(measurement).copied().unwrap_or(self.depth)
+    }
+}
+
+/// Summary of the samples in a window.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Aggregate {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+struct Ring {
+    depth: usize,
+    samples: VecDeque<(Instant, f64)>,
+}
+
+type Listener = Box<dyn Fn(&str, f64) + Send>;
+
+#[derive(Clone, Default)]
+pub struct History {
+    config: Arc<HistoryConfig>,
+    rings: Arc<Mutex<BTreeMap<String, Ring>>>,
+    listeners: Arc<Mutex<Vec<Listener>>>,
+}
+
+impl History {
+    pub fn new(config: HistoryConfig) -> Self {
+        History {
+            config: Arc::new(config),
+            rings: Arc::default(),
+            listeners: Arc::default(),
+        }
+    }
+
+    /// Call `listener` with every sample recorded from now on, through any
+    /// clone of this handle.
+    pub fn on_record(&self, listener: impl Fn(&str, f64) + Send + 'static) {
+        self.listeners
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(Box::new(listener));
+    }
+
+    pub fn record(&self, measurement: &str, value: f64) {
+        self.record_at(measurement, value, Instant::now());
+    }
+
+    /// Append a sample, dropping the oldest once the ring is full.
+    pub fn record_at(&self, measurement: &str, value: f64, at: Instant) {
+        let mut rings = self.lock();
+        let ring = rings.entry(measurement.to_string()).or_insert_with(|| {
+            let depth = self.config.depth_of(measurement);
+            Ring {
+                depth,
+                samples: VecDeque::with_capacity(depth.min(4096)),
+            }
+        });
+        if ring.samples.len() == ring.depth {
+            ring.samples.pop_front();
+        }
+        ring.samples.push_back((at, value));
+        drop(rings);
+        for listener in self.listeners.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).iter() {
+            listener(measurement, value);
+        }
+    }
+
+    /// Measurements with at least one sample, in name order.
+    pub fn measurements(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    pub fn latest(&self, measurement: &str) -> Option<f64> {
+        self.latest_sample(measurement).map(|(_, v)| v)
+    }
+
+    /// The newest value and when it was recorded.
+    pub fn latest_sample(&self, measurement: &str) -> Option<(Instant, f64)> {
+        self.lock().get(measurement)?.samples.back().copied()
+    }
+
+    /// Every stored value, oldest first; what a sparkline draws.
+    pub fn values(&self, measurement: &str) -> Vec<f64> {
+        self.lock()
+            .get(measurement)
+            .map(|ring| ring.samples.iter().map(|&(_, v)| v).collect())
+            .unwrap_or_default()
+    }
+
+    /// Values recorded within `window` of now, oldest first.
+    pub fn values_within(&self, measurement: &str, window: Duration) -> Vec<f64> {
+        self.values_within_at(measurement, window, Instant::now())
+    }
+
+    pub fn values_within_at(&self, measurement: &str, window: Duration, now: Instant) -> Vec<f64> {
+        let rings = self.lock();
+        let Some(ring) = rings.get(measurement) else {
+            return Vec::new();
+        };
+        ring.samples
+            .iter()
+            .filter(|&&(at, _)| now.saturating_duration_since(at) <= window)
+            .map(|&(_, v)| v)
+            .collect()
+    }
+
+    /// Min, max and average over `window`, or everything stored if `None`.
+    /// NaN samples are left out; `None` if nothing remains.
+    pub fn aggregate(&self, measurement: &str, window: Option<Duration>) -> Option<Aggregate> {
+        let values = match window {
+            Some(window) => self.values_within(measurement, window),
+            None => self.values(measurement),
+        };
+        aggregate(&values)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Ring>> {
+        self.rings.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+pub fn aggregate(values: &[f64]) -> Option<Aggregate> {
+    let mut values = values.iter().copied().filter(|v| !v.is_nan());
+    let first = values.next()?;
+    let (mut count, mut min, mut max, mut sum) = (1, first, first, first);
+    for v in values {
+        count += 1;
+        min = min.min(v);
+        max = max.max(v);
+        sum += v;
+    }
+    Some(Aggregate {
+        count,
+        min,
+        max,
+        avg: sum / count as f64,
+    })
+}
@@ -0,0 +1,67 @@
+//! The Pi on the other end of the bus: an I2C peripheral rather than the
+//! master.
+//!
+//! A [`SlaveEmulator`] answers at one address with the registers of a
+//! [`SlaveMap`], the way most sensors do: a write sets the register pointer
+//! with its first byte and stores the rest from there, a read returns
+//! bytes from the pointer on, and both move the pointer along. Registers
+//! can be made read-only, so the master's writes to them are ignored, as
+//! a chip's ID register would ignore them.
+//!
+//! ```toml
+//! address = 0x42
+//!
+//! [[registers]]
+//! register = 0x00
+//! value = [0x42, 0x01]   # an ID and a revision, 0x00-0x01
+//! read_only = true
+//!
+//! [[registers]]
+//! register = 0x10
+//! value = [0x00]
+//! ```
+//!
+//! The hardware is the BSC slave of the BCM2835, BCM2836, BCM2837 and
+//! BCM2711 ([`BscSlave`]): SDA on GPIO 18 and SCL on GPIO 19, or GPIO 10 and
+//! 11 on a Pi 4. The Pi 5 has none. It needs root, for `/dev/mem`. Wired
+//! to GPIO 2 and 3 (with the usual pull-ups), it is on the same Pi's bus 1,
+//! so this crate's master commands can test against it with no other
+//! hardware; two Pis work the same way.
+//!
+//! ```no_run
+//! use rpi_peripherals::i2c::{SlaveEmulator, SlaveMap};
+//! use std::sync::atomic::AtomicBool;
+//!
+//! let map: SlaveMap = "address = 0x42\n[[registers]]\nregister = 0\nvalue = [0x42]\n".parse()?;
+//! let mut slave = SlaveEmulator::open(&map)?;
+//! slave.run(&AtomicBool::new(false), |event| println!("{}", event))?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The same peripheral carries the framed link between two Pis: a
+//! [`FrameReceiver`] takes what [`crate::transmitter::Framing::Framed`]
+//! sends, NACKs frames whose length or CRC is wrong, and ACKs the rest.
+//!
+//! The BSC can't stretch the clock, so the bytes of a read have to be in
+//! its FIFO before the master clocks them out. After a write that moves
+//! the pointer, the emulator has about ten bit times to refill it, the
+//! repeated START and address of a write-then-read: 100 µs at 100 kHz.
+//! Keep the master at 100 kHz or below, and run with `--realtime` on a
+//! busy Pi.
+//!
+//! Watching someone else's bus is a different job: the BSC only ever sees
+//! its own address, and ACKs it. An [`Analyzer`] listens instead, on any
+//! two GPIOs wired to SDA and SCL, sampling them and decoding START,
+//! address, data, ACK and STOP as they go by without driving either line.
+//! [`Transactions`] groups what it hears into a `trace::Trace`, to save and
+//! look at later the same way as a recording.
+
+mod analyzer;
+mod bsc;
+mod receiver;
+mod slave;
+
+pub use analyzer::{Analyzer, BusEvent, Decoder, TimedEvent, Transactions};
+pub use bsc::{BscSlave, BscStatus, BSC_FIFO};
+pub use receiver::FrameReceiver;
+pub use slave::{RegisterFile, SlaveEmulator, SlaveEvent, SlaveMap, SlaveRegister};
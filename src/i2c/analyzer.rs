@@ -0,0 +1,330 @@
+use crate::address::Address;
+use crate::trace::{Direction, Trace, TraceEntry, TraceOp};
+use rppal::gpio::{Gpio, InputPin};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Samples between looks at the stop flag, so checking it stays out of
+/// the way of the sampling.
+const STOP_CHECK: u32 = 4096;
+
+/// What the lines did, decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusEvent {
+    /// SDA falling with SCL high; `repeated` without a STOP before it.
+    Start { repeated: bool },
+    /// The first byte after a START.
+    Address { address: u8, read: bool, ack: bool },
+    Data { byte: u8, ack: bool },
+    /// SDA rising with SCL high.
+    Stop,
+}
+
+impl fmt::Display for BusEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ack = |ack: bool| if ack { "ACK" } else { "NACK" };
+        let text = match *self {
+            BusEvent::Start { repeated: false } => "START".to_string(),
+            BusEvent::Start { repeated: true } => "Sr".to_string(),
+            BusEvent::Address { address, read, ack: acked } => {
+                format!("ADDR 0x{:02X} {} {}", address, if read { "R" } else { "W" }, ack(acked))
+            }
+            BusEvent::Data { byte, ack: acked } => format!("DATA 0x{:02X} {}", byte, ack(acked)),
+            BusEvent::Stop => "STOP".to_string(),
+        };
+        f.pad(&text)
+    }
+}
+
+/// A [`BusEvent`] with when it happened, from the start of the capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub at: Duration,
+    pub event: BusEvent,
+}
+
+/// Turns SDA and SCL levels into [`BusEvent`]s. Feed it every change of
+/// either line, in order; a byte is taken on SCL's rising edges and its
+/// ninth bit is the ACK.
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    last: Option<(bool, bool)>,
+    /// Inside a START ... STOP.
+    active: bool,
+    /// Bits of the current byte, MSB first, and how many.
+    bits: u16,
+    count: u8,
+    /// The next byte is an address.
+    expect_address: bool,
+    /// Samples where both lines had changed, so an edge went unseen.
+    missed: u64,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder::default()
+    }
+
+    /// Times SDA and SCL both changed between two samples: each is an
+    /// edge whose order was lost, and the decode around it may be wrong.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    pub fn update(&mut self, sda: bool, scl: bool) -> Option<BusEvent> {
+        let (last_sda, last_scl) = self.last.replace((sda, scl))?;
+        if sda != last_sda && scl != last_scl {
+            self.missed += 1;
+            return None;
+        }
+        if scl && last_scl && sda != last_sda {
+            return Some(if sda {
+                self.active = false;
+                BusEvent::Stop
+            } else {
+                let repeated = self.active;
+                self.active = true;
+                self.expect_address = true;
+                self.count = 0;
+                BusEvent::Start { repeated }
+            });
+        }
+        if !self.active || !scl || last_scl {
+            return None;
+        }
+        // SCL rising: SDA holds the next bit
+        self.bits = self.bits << 1 | u16::from(sda);
+        self.count += 1;
+        if self.count < 9 {
+            return None;
+        }
+        let byte = (self.bits >> 1) as u8;
+        let ack = self.bits & 1 == 0;
+        self.count = 0;
+        self.bits = 0;
+        Some(if std::mem::take(&mut self.expect_address) {
+            BusEvent::Address {
+                address: byte >> 1,
+                read: byte & 1 != 0,
+                ack,
+            }
+        } else {
+            BusEvent::Data { byte, ack }
+        })
+    }
+}
+
+/// Gathers [`TimedEvent`]s into transactions in the shape of a recorded
+/// [`Trace`], so a capture can be diffed, replayed or exported to
+/// PulseView like any other. A repeated START to the same address, as in
+/// a register read, adds an op to the transaction; to another address it
+/// begins a new one.
+#[derive(Debug, Clone, Default)]
+pub struct Transactions {
+    current: Option<TraceEntry>,
+    /// When the START before the coming address was, and if it was repeated.
+    start: Option<(Duration, bool)>,
+    trace: Trace,
+}
+
+impl Transactions {
+    pub fn new() -> Self {
+        Transactions::default()
+    }
+
+    /// Take one event; returns the transaction it finished, if it did.
+    pub fn push(&mut self, event: TimedEvent) -> Option<TraceEntry> {
+        match event.event {
+            BusEvent::Start { repeated } => {
+                self.start = Some((event.at, repeated));
+                // Without a STOP, the last one never ended
+                if repeated {
+                    None
+                } else {
+                    self.finish(event.at)
+                }
+            }
+            BusEvent::Address { address, read, ack } => {
+                let (started, repeated) = self.start.take().unwrap_or((event.at, false));
+                let direction = if read { Direction::Read } else { Direction::Write };
+                let op = TraceOp { direction, bytes: Vec::new() };
+                let error = (!ack).then(|| format!("address 0x{:02X} NACKed", address));
+                if let Some(entry) = self.current.as_mut().filter(|entry| repeated && entry.address == Address::SevenBit(address)) {
+                    entry.ops.push(op);
+                    entry.error = entry.error.take().or(error);
+                    return None;
+                }
+                let done = self.finish(started);
+                self.current = Some(TraceEntry {
+                    timestamp: started,
+                    duration: Duration::ZERO,
+                    address: Address::SevenBit(address),
+                    ops: vec![op],
+                    error,
+                });
+                done
+            }
+            BusEvent::Data { byte, ack } => {
+                let entry = self.current.as_mut()?;
+                let op = entry.ops.last_mut()?;
+                op.bytes.push(byte);
+                if !ack && op.direction == Direction::Write && entry.error.is_none() {
+                    entry.error = Some(format!("byte {} NACKed", op.bytes.len()));
+                }
+                None
+            }
+            BusEvent::Stop => self.finish(event.at),
+        }
+    }
+
+    /// Everything finished so far.
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    pub fn into_trace(mut self, at: Duration) -> Trace {
+        self.finish(at);
+        self.trace
+    }
+
+    fn finish(&mut self, at: Duration) -> Option<TraceEntry> {
+        let mut entry = self.current.take()?;
+        entry.duration = at.saturating_sub(entry.timestamp);
+        self.trace.transactions.push(entry.clone());
+        Some(entry)
+    }
+}
+
+/// Listens on two GPIOs wired to another master's SDA and SCL, without
+/// pulls or drive of its own, and decodes what goes by.
+///
+/// Sampling is a busy loop reading both pins, so it takes a core while it
+/// runs. It keeps up with bit-banged and slow buses; 100 kHz wants a fast
+/// Pi with a core to itself. When it falls behind,
+/// [`missed`](Analyzer::missed) counts the edges it lost.
+pub struct Analyzer {
+    sda: InputPin,
+    scl: InputPin,
+    decoder: Decoder,
+}
+
+impl Analyzer {
+    pub fn from_gpio(sda: u8, scl: u8) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let input = |pin: u8, name: &str| -> Result<InputPin, Box<dyn Error>> {
+            Ok(gpio.get(pin).map_err(|e| format!("{} GPIO {}: {}", name, pin, e))?.into_input())
+        };
+        Ok(Analyzer {
+            sda: input(sda, "SDA")?,
+            scl: input(scl, "SCL")?,
+            decoder: Decoder::new(),
+        })
+    }
+
+    pub fn missed(&self) -> u64 {
+        self.decoder.missed()
+    }
+
+    /// Decode until `stop` is set or `duration` is up, handing each event
+    /// to `on_event` as it happens.
+    pub fn run<F>(&mut self, stop: &AtomicBool, duration: Option<Duration>, mut on_event: F)
+    where
+        F: FnMut(TimedEvent),
+    {
+        let start = Instant::now();
+        let mut last = None;
+        let mut samples = 0u32;
+        loop {
+            samples = samples.wrapping_add(1);
+            if samples.is_multiple_of(STOP_CHECK)
+                && (stop.load(Ordering::Relaxed) || duration.is_some_and(|duration| start.elapsed() >= duration))
+            {
+                return;
+            }
+            let levels = (self.sda.is_high(), self.scl.is_high());
+            if last == Some(levels) {
+                continue;
+            }
+            last = Some(levels);
+            if let Some(event) = self.decoder.update(levels.0, levels.1) {
+                on_event(TimedEvent {
+                    at: start.elapsed(),
+                    event,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The line levels a master produces for `bytes` (address first) with
+    /// the given ninth bits, between a START and a STOP.
+    fn waveform(bytes: &[(u8, bool)]) -> Vec<(bool, bool)> {
+        let mut levels = vec![(true, true), (false, true), (false, false)];
+        for &(byte, ack) in bytes {
+            let bits = (0..8).rev().map(|i| byte >> i & 1 != 0).chain([!ack]);
+            for bit in bits {
+                levels.extend([(bit, false), (bit, true), (bit, false)]);
+            }
+        }
+        levels.extend([(false, false), (false, true), (true, true)]);
+        levels
+    }
+
+    #[test]
+    fn decodes_a_write() {
+        let mut decoder = Decoder::new();
+        let events: Vec<_> = waveform(&[(0x27 << 1, true), (0xA5, true), (0x3C, false)])
+            .into_iter()
+            .filter_map(|(sda, scl)| decoder.update(sda, scl))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                BusEvent::Start { repeated: false },
+                BusEvent::Address { address: 0x27, read: false, ack: true },
+                BusEvent::Data { byte: 0xA5, ack: true },
+                BusEvent::Data { byte: 0x3C, ack: false },
+                BusEvent::Stop,
+            ]
+        );
+        assert_eq!(events[1].to_string(), "ADDR 0x27 W ACK");
+        assert_eq!(decoder.missed(), 0);
+
+        let mut transactions = Transactions::new();
+        let done: Vec<_> = events
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &event)| {
+                transactions.push(TimedEvent {
+                    at: Duration::from_micros(100 * i as u64),
+                    event,
+                })
+            })
+            .collect();
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].address, Address::SevenBit(0x27));
+        assert_eq!(done[0].ops[0].bytes, [0xA5, 0x3C]);
+        assert_eq!(done[0].duration, Duration::from_micros(400));
+        assert_eq!(done[0].error.as_deref(), Some("byte 2 NACKed"));
+
+        // a register read: write the register, repeated START, read
+        let mut transactions = Transactions::new();
+        let at = |event| TimedEvent { at: Duration::ZERO, event };
+        transactions.push(at(BusEvent::Start { repeated: false }));
+        transactions.push(at(BusEvent::Address { address: 0x76, read: false, ack: true }));
+        transactions.push(at(BusEvent::Data { byte: 0xD0, ack: true }));
+        transactions.push(at(BusEvent::Start { repeated: true }));
+        transactions.push(at(BusEvent::Address { address: 0x76, read: true, ack: true }));
+        transactions.push(at(BusEvent::Data { byte: 0x60, ack: false }));
+        let entry = transactions.push(at(BusEvent::Stop)).unwrap();
+        assert_eq!(entry.ops.len(), 2);
+        assert_eq!(entry.ops[1].bytes, [0x60]);
+        assert!(entry.is_ok());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::address::Address;
+use crate::board::{Board, Soc};
+use rppal::gpio::{Gpio, IoPin, Mode};
+use std::error::Error;
+use std::ffi::CString;
+use std::ptr;
+
+/// Depth of each of the BSC's FIFOs.
+pub const BSC_FIFO: usize = 16;
+
+/// The BSC slave's offset from the peripheral base.
+const BSC_OFFSET: u64 = 0x21_4000;
+const BLOCK_SIZE: usize = 4096;
+
+// Registers, as word offsets
+const DR: usize = 0;
+const SLV: usize = 2;
+const CR: usize = 3;
+const FR: usize = 4;
+const IMSC: usize = 6;
+const ICR: usize = 9;
+
+const CR_EN: u32 = 1 << 0;
+const CR_I2C: u32 = 1 << 2;
+/// Clears both FIFOs while set.
+const CR_BRK: u32 = 1 << 7;
+const CR_TXE: u32 = 1 << 8;
+const CR_RXE: u32 = 1 << 9;
+
+const FR_TXBUSY: u32 = 1 << 0;
+const FR_RXFE: u32 = 1 << 1;
+const FR_TXFF: u32 = 1 << 2;
+const FR_RXBUSY: u32 = 1 << 5;
+const FR_TXFLEVEL_SHIFT: u32 = 6;
+
+/// A snapshot of the flag register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BscStatus {
+    /// A master write to our address is under way.
+    pub receiving: bool,
+    /// A master read from our address is under way.
+    pub transmitting: bool,
+    /// Bytes waiting in the transmit FIFO.
+    pub tx_level: usize,
+}
+
+/// The BSC slave peripheral, mapped from `/dev/mem`, and its two pins in
+/// ALT3. Neither rppal nor the kernel drives it, so this is the registers
+/// themselves.
+pub struct BscSlave {
+    registers: *mut u32,
+    _pins: (IoPin, IoPin),
+}
+
+// The mapping belongs to this value alone and is only touched through it
+unsafe impl Send for BscSlave {}
+
+impl BscSlave {
+    /// Answer at `address`, which must be 7-bit, on this board's BSC pins.
+    pub fn open(address: Address) -> Result<Self, Box<dyn Error>> {
+        if address.is_ten_bit() {
+            return Err("the BSC slave only answers 7-bit addresses".into());
+        }
+        let soc = Board::detect()?.soc;
+        let (sda, scl) = BscSlave::pins(soc).ok_or_else(|| format!("the {} has no I2C slave peripheral", soc))?;
+        let base = match soc {
+            Soc::Bcm2835 => 0x2000_0000,
+            Soc::Bcm2836 | Soc::Bcm2837 => 0x3F00_0000,
+            _ => 0xFE00_0000,
+        };
+        let registers = map(base + BSC_OFFSET)?;
+        let gpio = Gpio::new()?;
+        let pin = |pin: u8| -> Result<IoPin, Box<dyn Error>> {
+            Ok(gpio.get(pin).map_err(|e| format!("BSC GPIO {}: {}", pin, e))?.into_io(Mode::Alt3))
+        };
+        let mut bsc = BscSlave {
+            registers,
+            _pins: (pin(sda)?, pin(scl)?),
+        };
+        bsc.write(CR, 0);
+        bsc.write(IMSC, 0);
+        bsc.write(ICR, 0x0F);
+        bsc.write(SLV, u32::from(address.raw()));
+        bsc.flush();
+        Ok(bsc)
+    }
+
+    /// SDA and SCL on `soc`; `None` for the BCM2712, which has no BSC.
+    pub fn pins(soc: Soc) -> Option<(u8, u8)> {
+        match soc {
+            Soc::Bcm2835 | Soc::Bcm2836 | Soc::Bcm2837 => Some((18, 19)),
+            Soc::Bcm2711 => Some((10, 11)),
+            Soc::Bcm2712 => None,
+        }
+    }
+
+    pub fn status(&self) -> BscStatus {
+        let flags = self.read(FR);
+        BscStatus {
+            receiving: flags & FR_RXBUSY != 0,
+            transmitting: flags & FR_TXBUSY != 0,
+            tx_level: ((flags >> FR_TXFLEVEL_SHIFT) & 0x1F) as usize,
+        }
+    }
+
+    /// Everything in the receive FIFO.
+    pub fn drain(&mut self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while self.read(FR) & FR_RXFE == 0 {
+            bytes.push(self.read(DR) as u8);
+        }
+        bytes
+    }
+
+    /// Queue as much of `bytes` as fits; returns how many did.
+    pub fn fill(&mut self, bytes: &[u8]) -> usize {
+        let mut queued = 0;
+        for &byte in bytes {
+            if self.read(FR) & FR_TXFF != 0 {
+                break;
+            }
+            self.write(DR, u32::from(byte));
+            queued += 1;
+        }
+        queued
+    }
+
+    /// Empty both FIFOs and carry on listening.
+    pub fn flush(&mut self) {
+        let enabled = CR_EN | CR_I2C | CR_TXE | CR_RXE;
+        self.write(CR, enabled | CR_BRK);
+        self.write(CR, enabled);
+    }
+
+    fn read(&self, register: usize) -> u32 {
+        // SAFETY: `registers` maps a whole block and every offset is inside it
+        unsafe { ptr::read_volatile(self.registers.add(register)) }
+    }
+
+    fn write(&mut self, register: usize, value: u32) {
+        // SAFETY: as for `read`
+        unsafe { ptr::write_volatile(self.registers.add(register), value) }
+    }
+}
+
+impl Drop for BscSlave {
+    fn drop(&mut self) {
+        self.write(CR, CR_BRK);
+        self.write(CR, 0);
+        // SAFETY: the block was mapped by `map` and nothing uses it after this
+        unsafe {
+            libc::munmap(self.registers.cast(), BLOCK_SIZE);
+        }
+    }
+}
+
+/// One block of physical memory at `address`, which is page-aligned.
+fn map(address: u64) -> Result<*mut u32, Box<dyn Error>> {
+    let path = CString::new("/dev/mem")?;
+    // mmap64, as a 32-bit off_t can't hold the BCM2711's 0xFE00_0000 base
+    let offset = libc::off64_t::try_from(address).map_err(|_| format!("0x{:X} is beyond what mmap can reach", address))?;
+    // SAFETY: plain libc calls; the result is checked before use
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_RDWR | libc::O_SYNC | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(format!("cannot open /dev/mem (run as root): {}", std::io::Error::last_os_error()).into());
+        }
+        let block = libc::mmap64(ptr::null_mut(), BLOCK_SIZE, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, fd, offset);
+        let error = std::io::Error::last_os_error();
+        libc::close(fd);
+        if block == libc::MAP_FAILED {
+            return Err(format!("cannot map the BSC registers: {}", error).into());
+        }
+        Ok(block.cast())
+    }
+}
@@ -0,0 +1,93 @@
+use super::bsc::BscSlave;
+use crate::address::Address;
+use crate::transmitter::{Frame, FrameError, Reply};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Rest between polls while the bus is idle; a frame's first 16 bytes fit
+/// the FIFO, so this only has to be well under their 1.4 ms at 100 kHz.
+const IDLE_POLL: Duration = Duration::from_micros(100);
+
+/// The other end of [`crate::transmitter::SimpleI2cTransmitter::send_frame`]:
+/// takes frames on the BSC slave, checks them and queues the ACK or NACK
+/// the sender reads back.
+pub struct FrameReceiver {
+    bsc: BscSlave,
+    /// Sequence number of the last frame handed out.
+    last: Option<u8>,
+    rejected: u32,
+    last_error: Option<FrameError>,
+    duplicates: u32,
+}
+
+impl FrameReceiver {
+    pub fn open(address: Address) -> Result<Self, Box<dyn Error>> {
+        Ok(FrameReceiver {
+            bsc: BscSlave::open(address)?,
+            last: None,
+            rejected: 0,
+            last_error: None,
+            duplicates: 0,
+        })
+    }
+
+    /// Wait for the next good frame; `None` once `stop` is set. Damaged
+    /// frames are NACKed and a resend of the last frame is ACKed again
+    /// without being handed out twice.
+    pub fn recv_frame(&mut self, stop: &AtomicBool) -> Result<Option<Frame>, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        while !stop.load(Ordering::Relaxed) {
+            bytes.extend(self.bsc.drain());
+            let status = self.bsc.status();
+            if status.receiving {
+                continue;
+            }
+            // A write is over once the FIFO is empty with no transfer under way
+            bytes.extend(self.bsc.drain());
+            if bytes.is_empty() {
+                thread::sleep(IDLE_POLL);
+                continue;
+            }
+            match Frame::decode(&bytes) {
+                Ok(frame) => {
+                    self.reply(Reply::Ack(frame.seq));
+                    if self.last == Some(frame.seq) {
+                        self.duplicates += 1;
+                    } else {
+                        self.last = Some(frame.seq);
+                        return Ok(Some(frame));
+                    }
+                }
+                Err(e) => {
+                    self.rejected += 1;
+                    self.last_error = Some(e);
+                    self.reply(Reply::Nack(bytes.get(1).copied().unwrap_or(0)));
+                }
+            }
+            bytes.clear();
+        }
+        Ok(None)
+    }
+
+    /// Frames NACKed so far.
+    pub fn rejected(&self) -> u32 {
+        self.rejected
+    }
+
+    /// Why the last NACKed frame was.
+    pub fn last_error(&self) -> Option<FrameError> {
+        self.last_error
+    }
+
+    /// Resends ACKed again so far, each one an ACK the sender didn't get.
+    pub fn duplicates(&self) -> u32 {
+        self.duplicates
+    }
+
+    fn reply(&mut self, reply: Reply) {
+        self.bsc.flush();
+        self.bsc.fill(&reply.to_bytes());
+    }
+}
@@ -0,0 +1,255 @@
+use super::bsc::{BscSlave, BSC_FIFO};
+use crate::address::Address;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How long the poll loop rests when the bus is idle. Short: a write that
+/// moves the pointer has to be seen within a few bit times.
+const IDLE_POLL: Duration = Duration::from_micros(20);
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlaveRegister {
+    /// First register of `value`.
+    pub register: u8,
+    /// One byte per register from `register` on.
+    pub value: Vec<u8>,
+    /// Master writes to these registers are dropped.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// The address a [`SlaveEmulator`] answers at and its registers.
+/// Registers the map doesn't list read 0x00 and take writes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlaveMap {
+    pub address: u8,
+    #[serde(default)]
+    pub registers: Vec<SlaveRegister>,
+}
+
+impl SlaveMap {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        text.parse()
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    pub fn address(&self) -> Result<Address, Box<dyn Error>> {
+        Address::seven_bit(self.address)
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        self.address()?;
+        let mut used = [false; 256];
+        for entry in &self.registers {
+            if entry.value.is_empty() {
+                return Err(format!("register 0x{:02X} has no value", entry.register).into());
+            }
+            let end = usize::from(entry.register) + entry.value.len();
+            if end > used.len() {
+                return Err(format!("register 0x{:02X}: {} bytes run past 0xFF", entry.register, entry.value.len()).into());
+            }
+            let start = usize::from(entry.register);
+            if let Some(n) = used[start..end].iter().position(|&u| u) {
+                return Err(format!("register 0x{:02X} is given twice", start + n).into());
+            }
+            used[start..end].fill(true);
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SlaveMap {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let map: SlaveMap = toml::from_str(s)?;
+        map.validate()?;
+        Ok(map)
+    }
+}
+
+/// What the master did, reported once its transfer is over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlaveEvent {
+    /// `data` stored from `register` on; read-only registers kept their values.
+    Write { register: u8, data: Vec<u8> },
+    /// A write of the pointer alone, as before a read.
+    Select { register: u8 },
+    /// `data` sent from `register` on.
+    Read { register: u8, data: Vec<u8> },
+}
+
+impl fmt::Display for SlaveEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlaveEvent::Write { register, data } => write!(f, "write 0x{:02X} {:02X?}", register, data),
+            SlaveEvent::Select { register } => write!(f, "select 0x{:02X}", register),
+            SlaveEvent::Read { register, data } => write!(f, "read 0x{:02X} {:02X?}", register, data),
+        }
+    }
+}
+
+/// 256 registers and the pointer into them, as a typical sensor has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterFile {
+    registers: [u8; 256],
+    read_only: [bool; 256],
+    pointer: u8,
+}
+
+impl RegisterFile {
+    pub fn new(map: &SlaveMap) -> Self {
+        let mut file = RegisterFile {
+            registers: [0; 256],
+            read_only: [false; 256],
+            pointer: 0,
+        };
+        for entry in &map.registers {
+            for (n, &byte) in entry.value.iter().enumerate() {
+                let register = usize::from(entry.register) + n;
+                file.registers[register] = byte;
+                file.read_only[register] = entry.read_only;
+            }
+        }
+        file
+    }
+
+    pub fn pointer(&self) -> u8 {
+        self.pointer
+    }
+
+    pub fn get(&self, register: u8) -> u8 {
+        self.registers[usize::from(register)]
+    }
+
+    /// Change a register from this side, read-only or not, as a sensor
+    /// updates its readings.
+    pub fn set(&mut self, register: u8, value: u8) {
+        self.registers[usize::from(register)] = value;
+    }
+
+    /// The first byte of a master write, which moves the pointer.
+    pub fn select(&mut self, register: u8) {
+        self.pointer = register;
+    }
+
+    /// A data byte of a master write, at the pointer.
+    pub fn write(&mut self, byte: u8) {
+        let register = usize::from(self.pointer);
+        if !self.read_only[register] {
+            self.registers[register] = byte;
+        }
+        self.pointer = self.pointer.wrapping_add(1);
+    }
+
+    /// What a read of `count` bytes would send, without moving the pointer.
+    pub fn peek(&self, count: usize) -> Vec<u8> {
+        (0..count).map(|n| self.get(self.pointer.wrapping_add(n as u8))).collect()
+    }
+
+    /// The master read `count` bytes.
+    pub fn advance(&mut self, count: usize) {
+        self.pointer = self.pointer.wrapping_add(count as u8);
+    }
+}
+
+/// A [`RegisterFile`] served on the BSC slave.
+pub struct SlaveEmulator {
+    bsc: BscSlave,
+    registers: RegisterFile,
+}
+
+impl SlaveEmulator {
+    /// Take over the BSC slave and its pins, answering at the map's address.
+    pub fn open(map: &SlaveMap) -> Result<Self, Box<dyn Error>> {
+        let bsc = BscSlave::open(map.address()?)?;
+        Ok(SlaveEmulator {
+            bsc,
+            registers: RegisterFile::new(map),
+        })
+    }
+
+    pub fn registers(&self) -> &RegisterFile {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut RegisterFile {
+        &mut self.registers
+    }
+
+    /// Serve the master until `stop` is set, handing each transfer to
+    /// `on_event` once it is over.
+    pub fn run<F: FnMut(&SlaveEvent)>(&mut self, stop: &AtomicBool, mut on_event: F) -> Result<(), Box<dyn Error>> {
+        // The transfers in progress: their first register and data so far
+        let mut writing: Option<(u8, Vec<u8>)> = None;
+        let mut reading: Option<(u8, Vec<u8>)> = None;
+        // Bytes in the transmit FIFO, from the pointer on
+        let mut queued = self.refill(0);
+        while !stop.load(Ordering::Relaxed) {
+            let received = self.bsc.drain();
+            if !received.is_empty() {
+                for byte in received {
+                    match &mut writing {
+                        None => {
+                            self.registers.select(byte);
+                            writing = Some((byte, Vec::new()));
+                        }
+                        Some((_, data)) => {
+                            self.registers.write(byte);
+                            data.push(byte);
+                        }
+                    }
+                }
+                // The pointer moved or its registers changed: what's queued is stale
+                self.bsc.flush();
+                queued = self.refill(0);
+                continue;
+            }
+            let status = self.bsc.status();
+            let sent = queued.saturating_sub(status.tx_level);
+            if sent > 0 {
+                let register = self.registers.pointer();
+                let data = self.registers.peek(sent);
+                self.registers.advance(sent);
+                reading.get_or_insert_with(|| (register, Vec::new())).1.extend(data);
+                // Keep ahead of a read longer than the FIFO
+                queued = self.refill(status.tx_level);
+            }
+            if !status.receiving {
+                if let Some((register, data)) = writing.take() {
+                    on_event(&if data.is_empty() {
+                        SlaveEvent::Select { register }
+                    } else {
+                        SlaveEvent::Write { register, data }
+                    });
+                }
+            }
+            if !status.transmitting {
+                if let Some((register, data)) = reading.take() {
+                    on_event(&SlaveEvent::Read { register, data });
+                }
+            }
+            if writing.is_none() && !status.transmitting {
+                std::thread::sleep(IDLE_POLL);
+            }
+        }
+        Ok(())
+    }
+
+    /// Top the transmit FIFO up from the pointer, past the `level` bytes
+    /// already in it. Returns how many bytes it then holds.
+    fn refill(&mut self, level: usize) -> usize {
+        let bytes = self.registers.peek(BSC_FIFO);
+        level + self.bsc.fill(&bytes[level..])
+    }
+}
@@ -0,0 +1,325 @@
+//! Telling chips apart by address and ID registers, for `scan --identify`.
+//!
+//! Addresses are shared: 0x68 is an MPU6050 or a DS3231, 0x40 an INA219,
+//! an INA226 or a PCA9685. Each [`DeviceProfile`] lists the addresses a chip
+//! can be strapped to and, where it has one, an [`IdCheck`]: a WHO_AM_I or
+//! chip-ID register and the values it reads. [`identify`] runs the checks
+//! of every profile at an address and reports the first that matches:
+//!
+//! ```text
+//! 0x68: MPU6050 or DS3231 (WHO_AM_I=0x68 → MPU6050)
+//! ```
+//!
+//! An ID read writes the register number first. A PCF8574 has no
+//! registers and takes that byte as its new outputs, so at an address one
+//! could be at, the port is read beforehand and written back if nothing
+//! matched.
+//!
+//! [`conflicts`] goes further, for when two chips may be answering at one
+//! address: it reads each ID register several times and looks for reads
+//! that change or come back as two IDs ANDed together, and writes a
+//! scratch register and reads it back. A [`ConflictReport`] says what
+//! gave it away and whether restrapping one chip will do or it needs a
+//! mux.
+
+mod conflict;
+
+pub use conflict::{conflicts, Conflict, ConflictReport, Remedy};
+
+use crate::address::{Address, AddressedI2c};
+use std::error::Error;
+use std::fmt;
+
+/// How to recognise a chip from what it reads back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdCheck {
+    /// Read `mask.len()` bytes from `register`, most significant first;
+    /// it is the chip if, masked, they are one of `values`.
+    Register {
+        /// The datasheet's name for it, used in reports.
+        name: &'static str,
+        register: u8,
+        mask: &'static [u8],
+        values: &'static [&'static [u8]],
+    },
+    /// For chips with no ID, such as RTCs: `register` holds BCD no higher
+    /// than `max`. Weaker evidence, so profiles with one go last.
+    Bcd {
+        name: &'static str,
+        register: u8,
+        max: u8,
+    },
+}
+
+impl IdCheck {
+    /// What was read, as `WHO_AM_I=0x68`, if it matches.
+    pub fn run<I2C: AddressedI2c>(&self, i2c: &mut I2C, address: Address) -> Result<Option<String>, Box<dyn Error>> {
+        let raw = self.read(i2c, address)?;
+        Ok(self.matches(&raw).then(|| self.describe(&raw)))
+    }
+
+    /// The register or registers the check looks at, unmasked.
+    pub fn read<I2C: AddressedI2c>(&self, i2c: &mut I2C, address: Address) -> Result<Vec<u8>, Box<dyn Error>> {
+        let (register, len) = match *self {
+            IdCheck::Register { register, mask, .. } => (register, mask.len()),
+            IdCheck::Bcd { register, .. } => (register, 1),
+        };
+        let mut buf = vec![0; len];
+        i2c.write_read_at(address, &[register], &mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn matches(&self, raw: &[u8]) -> bool {
+        match *self {
+            IdCheck::Register { mask, values, .. } => {
+                let masked: Vec<u8> = raw.iter().zip(mask).map(|(b, m)| b & m).collect();
+                values.contains(&masked.as_slice())
+            }
+            IdCheck::Bcd { max, .. } => {
+                let &[byte] = raw else {
+                    return false;
+                };
+                let bcd = byte & 0x0F <= 9 && byte >> 4 <= 9;
+                bcd && byte <= max
+            }
+        }
+    }
+
+    /// `raw` as reported, e.g. `WHO_AM_I=0x68`.
+    pub fn describe(&self, raw: &[u8]) -> String {
+        let name = match *self {
+            IdCheck::Register { name, .. } | IdCheck::Bcd { name, .. } => name,
+        };
+        let hex: String = raw.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{}=0x{}", name, hex)
+    }
+}
+
+/// A chip that can turn up on the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceProfile {
+    /// As printed, e.g. `MPU6050`.
+    pub name: &'static str,
+    /// The `drivers::DRIVERS` entry for it, if this crate has one.
+    pub driver: Option<&'static str>,
+    pub addresses: &'static [u8],
+    pub id: Option<IdCheck>,
+    /// Register writes that bring the chip out of reset or sleep, in
+    /// order, as `(register, value)`.
+    pub init: &'static [(u8, u8)],
+    /// Takes every byte written as data, with no register pointer.
+    pub registerless: bool,
+    /// A register that can be written and read back without side effects,
+    /// for the write-then-readback probe in [`conflicts`].
+    pub scratch: Option<u8>,
+}
+
+impl DeviceProfile {
+    /// Send the [`init`](Self::init) sequence.
+    pub fn init<I2C: AddressedI2c>(&self, i2c: &mut I2C, address: Address) -> Result<(), Box<dyn Error>> {
+        for &(register, value) in self.init {
+            i2c.write_at(address, &[register, value])?;
+        }
+        Ok(())
+    }
+}
+
+const fn profile(name: &'static str, driver: Option<&'static str>, addresses: &'static [u8], id: Option<IdCheck>) -> DeviceProfile {
+    DeviceProfile {
+        name,
+        driver,
+        addresses,
+        id,
+        init: &[],
+        registerless: false,
+        scratch: None,
+    }
+}
+
+const fn who_am_i(register: u8, values: &'static [&'static [u8]]) -> Option<IdCheck> {
+    Some(IdCheck::Register {
+        name: "WHO_AM_I",
+        register,
+        mask: &[0xFF],
+        values,
+    })
+}
+
+const fn chip_id(register: u8, values: &'static [&'static [u8]]) -> Option<IdCheck> {
+    Some(IdCheck::Register {
+        name: "CHIP_ID",
+        register,
+        mask: &[0xFF],
+        values,
+    })
+}
+
+const EXPANDER_ADDRESSES: &[u8] = &[0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F];
+const INA_ADDRESSES: &[u8] = &[0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F];
+
+/// Chips with an ID first, so a match beats a guess.
+pub const PROFILES: &[DeviceProfile] = &[
+    DeviceProfile {
+        init: &[(0x6B, 0x00)],
+        ..profile("MPU6050", None, &[0x68, 0x69], who_am_i(0x75, &[&[0x68]]))
+    },
+    DeviceProfile {
+        init: &[(0x6B, 0x00)],
+        ..profile("MPU6500", None, &[0x68, 0x69], who_am_i(0x75, &[&[0x70]]))
+    },
+    DeviceProfile {
+        init: &[(0x6B, 0x00)],
+        ..profile("MPU9250", None, &[0x68, 0x69], who_am_i(0x75, &[&[0x71], &[0x73]]))
+    },
+    profile("BME280", None, &[0x76, 0x77], chip_id(0xD0, &[&[0x60]])),
+    profile("BMP280", None, &[0x76, 0x77], chip_id(0xD0, &[&[0x56], &[0x57], &[0x58]])),
+    profile("BME680", None, &[0x76, 0x77], chip_id(0xD0, &[&[0x61]])),
+    profile("APDS-9960", Some("apds9960"), &[0x39], who_am_i(0x92, &[&[0xAB], &[0xA8], &[0x9C]])),
+    profile("VL53L0X", None, &[0x29], who_am_i(0xC0, &[&[0xEE]])),
+    profile("ADXL345", None, &[0x1D, 0x53], who_am_i(0x00, &[&[0xE5]])),
+    profile("LIS3DH", None, &[0x18, 0x19], who_am_i(0x0F, &[&[0x33]])),
+    profile("LSM6DS3", None, &[0x6A, 0x6B], who_am_i(0x0F, &[&[0x69], &[0x6A]])),
+    profile(
+        "MCP9808",
+        None,
+        &[0x18, 0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E, 0x1F],
+        Some(IdCheck::Register {
+            name: "MANUFACTURER_ID",
+            register: 0x06,
+            mask: &[0xFF, 0xFF],
+            values: &[&[0x00, 0x54]],
+        }),
+    ),
+    profile(
+        "INA226",
+        Some("ina226"),
+        INA_ADDRESSES,
+        Some(IdCheck::Register {
+            name: "MANUFACTURER_ID",
+            register: 0xFE,
+            mask: &[0xFF, 0xFF],
+            values: &[&[0x54, 0x49]],
+        }),
+    ),
+    profile(
+        "HMC5883L",
+        None,
+        &[0x1E],
+        Some(IdCheck::Register {
+            name: "ID",
+            register: 0x0A,
+            mask: &[0xFF, 0xFF, 0xFF],
+            values: &[b"H43"],
+        }),
+    ),
+    DeviceProfile {
+        // alarm 1 seconds
+        scratch: Some(0x07),
+        ..profile(
+            "DS3231",
+            None,
+            &[0x68],
+            Some(IdCheck::Bcd {
+                name: "SECONDS",
+                register: 0x00,
+                max: 0x59,
+            }),
+        )
+    },
+    profile("INA219", Some("ina219"), INA_ADDRESSES, None),
+    DeviceProfile {
+        // SUBADR1, only answered to when MODE1 enables it
+        scratch: Some(0x02),
+        ..profile("PCA9685", None, &[0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47], None)
+    },
+    profile("ADS1115", None, &[0x48, 0x49, 0x4A, 0x4B], None),
+    DeviceProfile {
+        // DEFVALA, only compared against when interrupts are set to
+        scratch: Some(0x06),
+        ..profile("MCP23017", Some("mcp23017"), &[0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27], None)
+    },
+    DeviceProfile {
+        registerless: true,
+        ..profile("PCF8574", Some("pcf8574"), EXPANDER_ADDRESSES, None)
+    },
+    profile("SSD1306", None, &[0x3C, 0x3D], None),
+    profile("BH1750", None, &[0x23, 0x5C], None),
+    profile("AT24C EEPROM", None, &[0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57], None),
+    profile("TCA9548A", Some("tca9548a"), &[0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77], None),
+];
+
+/// Every profile that can be at `address`, in [`PROFILES`] order.
+pub fn candidates(address: Address) -> Vec<&'static DeviceProfile> {
+    match address {
+        Address::SevenBit(a) => PROFILES.iter().filter(|p| p.addresses.contains(&a)).collect(),
+        Address::TenBit(_) => Vec::new(),
+    }
+}
+
+/// Look up a profile by name, ignoring case.
+pub fn find(name: &str) -> Option<&'static DeviceProfile> {
+    PROFILES.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+/// What [`identify`] made of one address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identification {
+    pub address: Address,
+    /// Everything that could be there.
+    pub candidates: Vec<&'static DeviceProfile>,
+    /// The profile whose check matched, and what it read.
+    pub identified: Option<(&'static DeviceProfile, String)>,
+}
+
+impl Identification {
+    /// The identified chip, or the only candidate.
+    pub fn best(&self) -> Option<&'static DeviceProfile> {
+        match (&self.identified, self.candidates.as_slice()) {
+            (Some((profile, _)), _) => Some(profile),
+            (None, [only]) => Some(only),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Identification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.address)?;
+        if self.candidates.is_empty() {
+            return f.write_str("unknown");
+        }
+        let names: Vec<_> = self.candidates.iter().map(|p| p.name).collect();
+        f.write_str(&names.join(" or "))?;
+        if let Some((profile, evidence)) = &self.identified {
+            if self.candidates.len() > 1 {
+                write!(f, " ({} → {})", evidence, profile.name)?;
+            } else {
+                write!(f, " ({})", evidence)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run the checks of every candidate at `address` until one matches. A
+/// failed read just rules that candidate out.
+pub fn identify<I2C: AddressedI2c>(i2c: &mut I2C, address: Address) -> Identification {
+    let candidates = candidates(address);
+    let mut identified = None;
+    let checks: Vec<_> = candidates.iter().filter_map(|p| Some((*p, p.id?))).collect();
+    if !checks.is_empty() {
+        let mut port = [0];
+        let restore = candidates.iter().any(|p| p.registerless) && i2c.read_at(address, &mut port).is_ok();
+        identified = checks
+            .into_iter()
+            .find_map(|(profile, check)| Some((profile, check.run(i2c, address).ok()??)));
+        if restore && identified.is_none() {
+            let _ = i2c.write_at(address, &port);
+        }
+    }
+    Identification {
+        address,
+        candidates,
+        identified,
+    }
+}
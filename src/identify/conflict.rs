@@ -0,0 +1,240 @@
+use super::{identify, DeviceProfile, IdCheck, Identification};
+use crate::address::{Address, AddressedI2c};
+use std::fmt;
+
+/// Times each ID register is read, to catch two chips fighting over it.
+const READS: usize = 3;
+
+/// Written to a scratch register in turn; between them bits 1-7 get to be
+/// both 0 and 1. Bit 0 stays clear, as a PCA9685's SUBADR1 reads it as 0.
+const PATTERNS: [u8; 2] = [0x54, 0xAA];
+
+/// Evidence that more than one chip answers at an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// The ID checks of two different chips both passed.
+    BothMatched {
+        first: &'static DeviceProfile,
+        second: &'static DeviceProfile,
+    },
+    /// An ID register read back as two chips' IDs ANDed together, which is
+    /// what the open-drain lines give when both drive them.
+    WiredAnd {
+        first: &'static DeviceProfile,
+        second: &'static DeviceProfile,
+        evidence: String,
+    },
+    /// Reads of the same ID register disagreed.
+    Unstable { reads: Vec<String> },
+    /// A scratch register didn't read back what was written to it.
+    Readback {
+        profile: &'static DeviceProfile,
+        register: u8,
+        wrote: u8,
+        read: u8,
+    },
+}
+
+impl Conflict {
+    /// The known chips this points at.
+    pub fn profiles(&self) -> Vec<&'static DeviceProfile> {
+        match *self {
+            Conflict::BothMatched { first, second } | Conflict::WiredAnd { first, second, .. } => vec![first, second],
+            Conflict::Readback { profile, .. } => vec![profile],
+            Conflict::Unstable { .. } => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::BothMatched { first, second } => {
+                write!(f, "both the {} and the {} ID checks pass", first.name, second.name)
+            }
+            Conflict::WiredAnd { first, second, evidence } => {
+                write!(f, "{} is the {} and {} IDs ANDed: both are answering", evidence, first.name, second.name)
+            }
+            Conflict::Unstable { reads } => write!(f, "the ID reads back differently each time ({})", reads.join(", ")),
+            Conflict::Readback { profile, register, wrote, read } => write!(
+                f,
+                "{} register 0x{:02X} read back 0x{:02X} after writing 0x{:02X}: something else is pulling bits low",
+                profile.name, register, read, wrote
+            ),
+        }
+    }
+}
+
+/// How to pull two chips at one address apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remedy {
+    /// Strap this chip to a free address it supports.
+    Restrap {
+        profile: &'static DeviceProfile,
+        to: Address,
+    },
+    /// No free address will do; one needs its own TCA9548A channel.
+    Mux,
+}
+
+impl fmt::Display for Remedy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Remedy::Restrap { profile, to } => write!(f, "strap the {} to {} instead", profile.name, to),
+            Remedy::Mux => f.write_str("no free address for either chip; put one behind a TCA9548A mux"),
+        }
+    }
+}
+
+/// What [`conflicts`] made of one address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictReport {
+    pub identification: Identification,
+    pub conflicts: Vec<Conflict>,
+    /// Set when there are conflicts.
+    pub remedy: Option<Remedy>,
+}
+
+impl ConflictReport {
+    pub fn is_shared(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
+/// Look for signs that more than one chip answers at `address`: ID
+/// checks of different chips passing together, ID registers reading as
+/// the AND of two IDs or changing between reads, and a scratch register
+/// of the identified chip not reading back what was written (its old
+/// value is put back). `answering` is everything the scan found, so a
+/// remedy only suggests free addresses.
+///
+/// Two chips of the same type at one address answer identically and
+/// can't be told from one.
+pub fn conflicts<I2C: AddressedI2c>(i2c: &mut I2C, address: Address, answering: &[Address]) -> ConflictReport {
+    let identification = identify(i2c, address);
+    let mut conflicts = Vec::new();
+
+    // identify() already put a PCF8574's port back; more register reads
+    // would only scribble on it again
+    let expander = identification.candidates.iter().any(|p| p.registerless) && identification.identified.is_none();
+    let checks: Vec<(&'static DeviceProfile, IdCheck)> = if expander {
+        Vec::new()
+    } else {
+        identification.candidates.iter().filter_map(|p| Some((*p, p.id?))).collect()
+    };
+    let strong = |check: &IdCheck| matches!(check, IdCheck::Register { .. });
+    let mut first_reads = Vec::new();
+    for &(profile, check) in &checks {
+        let reads: Vec<Vec<u8>> = (0..READS).filter_map(|_| check.read(i2c, address).ok()).collect();
+        let Some(first) = reads.first() else {
+            continue;
+        };
+        // a BCD clock register is meant to change
+        if strong(&check) && reads.iter().any(|r| r != first) {
+            let unstable = Conflict::Unstable {
+                reads: reads.iter().map(|r| check.describe(r)).collect(),
+            };
+            if !conflicts.contains(&unstable) {
+                conflicts.push(unstable);
+            }
+        }
+        first_reads.push((profile, check, first.clone()));
+    }
+
+    let matched: Vec<_> = first_reads.iter().filter(|(_, check, raw)| strong(check) && check.matches(raw)).collect();
+    if let [(first, ..), (second, ..), ..] = matched.as_slice() {
+        conflicts.push(Conflict::BothMatched { first, second });
+    }
+    if matched.is_empty() {
+        if let Some(conflict) = wired_and(&first_reads) {
+            conflicts.push(conflict);
+        }
+    }
+
+    if let Some(profile) = identification.best() {
+        if let Some(conflict) = profile.scratch.and_then(|register| readback(i2c, address, profile, register)) {
+            conflicts.push(conflict);
+        }
+    }
+
+    let remedy = (!conflicts.is_empty()).then(|| remedy(address, answering, &identification, &conflicts));
+    ConflictReport {
+        identification,
+        conflicts,
+        remedy,
+    }
+}
+
+/// Two chips sharing an ID register, read as the AND of an ID of each.
+fn wired_and(reads: &[(&'static DeviceProfile, IdCheck, Vec<u8>)]) -> Option<Conflict> {
+    for (i, (first, a, raw)) in reads.iter().enumerate() {
+        for (second, b, _) in &reads[i + 1..] {
+            let (
+                IdCheck::Register { register: ra, mask, values: va, .. },
+                IdCheck::Register { register: rb, mask: mb, values: vb, .. },
+            ) = (a, b)
+            else {
+                continue;
+            };
+            if ra != rb || mask.len() != mb.len() || first.name == second.name {
+                continue;
+            }
+            let masked: Vec<u8> = raw.iter().zip(mask.iter()).map(|(b, m)| b & m).collect();
+            let anded = va.iter().flat_map(|x| vb.iter().map(move |y| x.iter().zip(y.iter()).map(|(p, q)| p & q).collect::<Vec<u8>>()));
+            if anded.into_iter().any(|v| v == masked) {
+                return Some(Conflict::WiredAnd {
+                    first,
+                    second,
+                    evidence: a.describe(raw),
+                });
+            }
+        }
+    }
+    None
+}
+
+fn readback<I2C: AddressedI2c>(i2c: &mut I2C, address: Address, profile: &'static DeviceProfile, register: u8) -> Option<Conflict> {
+    let mut original = [0];
+    i2c.write_read_at(address, &[register], &mut original).ok()?;
+    let mut conflict = None;
+    for wrote in PATTERNS {
+        let mut read = [0];
+        if i2c.write_at(address, &[register, wrote]).is_err() || i2c.write_read_at(address, &[register], &mut read).is_err() {
+            break;
+        }
+        if read[0] != wrote {
+            conflict = Some(Conflict::Readback {
+                profile,
+                register,
+                wrote,
+                read: read[0],
+            });
+            break;
+        }
+    }
+    let _ = i2c.write_at(address, &[register, original[0]]);
+    conflict
+}
+
+/// The first chip involved, then any other candidate, that has a free
+/// address to move to; else a mux.
+fn remedy(address: Address, answering: &[Address], identification: &Identification, conflicts: &[Conflict]) -> Remedy {
+    let mut involved: Vec<&'static DeviceProfile> = conflicts.iter().flat_map(Conflict::profiles).collect();
+    // the other chip could be any of the rest
+    for candidate in &identification.candidates {
+        if !involved.contains(candidate) {
+            involved.push(candidate);
+        }
+    }
+    for profile in involved {
+        let free = profile
+            .addresses
+            .iter()
+            .map(|&a| Address::SevenBit(a))
+            .find(|&a| a != address && !answering.contains(&a));
+        if let Some(to) = free {
+            return Remedy::Restrap { profile, to };
+        }
+    }
+    Remedy::Mux
+}
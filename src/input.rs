@@ -0,0 +1,429 @@
+//! Push buttons and rotary encoders on GPIO.
+//!
+//! Both are read by calling `poll` every millisecond or so from whatever
+//! loop owns them, which keeps them free of interrupt threads and lets the
+//! caller decide the rate. A [`Button`] debounces and tells a click from a
+//! long press; a [`Rotary`] decodes quadrature into detent steps.
+//!
+//! A [`RotaryEncoder`] is the same knob driven by edge interrupts instead:
+//! nothing to poll, and no steps lost to a busy loop. Its events arrive on
+//! a callback or a channel:
+//!
+//! ```no_run
+//! use rpi_peripherals::input::{EncoderEvent, RotaryEncoder};
+//!
+//! let (_knob, events) = RotaryEncoder::with_channel(5, 6, Some(13))?;
+//! for event in events {
+//!     match event {
+//!         EncoderEvent::Clockwise => println!("+1"),
+//!         EncoderEvent::CounterClockwise => println!("-1"),
+//!         EncoderEvent::Pressed => println!("click"),
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! An [`IrReceiver`] decodes an NEC infrared remote from a 38 kHz receiver
+//! module, also on interrupts, into address and command pairs; holding a
+//! key sends repeats, marked as such.
+//!
+//! A [`KeypadMatrix`] scans a membrane keypad's rows and columns, wired
+//! to GPIOs ([`GpioLines`]) or through a PCF8574 ([`Pcf8574Lines`]) or
+//! MCP23017 ([`Mcp23017Lines`]), and reports presses, holds and releases.
+//!
+//! USB macro keypads, volume knobs and keyboards come in through evdev as
+//! a [`HidInput`], polled the same way; `menu::HidControls` turns their keys
+//! into the same navigation a knob gives.
+//!
+//! Polled:
+//!
+//! ```no_run
+//! use rpi_peripherals::input::{Button, ButtonEvent, Rotary};
+//! use std::time::Duration;
+//!
+//! let mut knob = Rotary::from_gpio(5, 6)?;
+//! let mut push = Button::from_gpio(13)?;
+//! loop {
+//!     match knob.poll()? {
+//!         0 => {}
+//!         steps => println!("turned {}", steps),
+//!     }
+//!     if let Some(ButtonEvent::Click) = push.poll()? {
+//!         println!("click");
+//!     }
+//!     std::thread::sleep(Duration::from_millis(1));
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod hid;
+mod ir;
+mod keypad;
+
+pub use hid::{codes, devices, HidDevice, HidEvent, HidInput, KeyState, INPUT_CLASS};
+pub use ir::{IrEvent, IrReceiver, NecDecoder, REPEAT_WINDOW};
+pub use keypad::{
+    GpioLines, KeyAction, KeyEvent, KeypadMatrix, KeypadScanner, MatrixLines, Mcp23017Lines, Pcf8574Lines, DEFAULT_SCAN, KEYS_4X3,
+    KEYS_4X4,
+};
+
+use crate::clock::{self, Clock};
+use embedded_hal::digital::InputPin;
+use rppal::gpio::{Gpio, Trigger};
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Contact bounce on cheap tactile switches settles within a few ms.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(20);
+
+/// Held this long, a press is a long press rather than a click.
+pub const DEFAULT_LONG_PRESS: Duration = Duration::from_millis(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    /// Pressed and released before the long-press time.
+    Click,
+    /// Still held at the long-press time; no click follows the release.
+    LongPress,
+}
+
+fn open_input(gpio: &Gpio, pin: u8) -> Result<rppal::gpio::InputPin, Box<dyn Error>> {
+    Ok(gpio.get(pin).map_err(|e| format!("input GPIO {}: {}", pin, e))?.into_input_pullup())
+}
+
+/// A switch to ground (or to 3V3 with `active_low` off).
+pub struct Button<P> {
+    pin: P,
+    active_low: bool,
+    debounce: Duration,
+    long_press: Duration,
+    /// Debounced state.
+    pressed: bool,
+    /// Raw level last seen, and since when.
+    raw: bool,
+    raw_since: Instant,
+    pressed_at: Instant,
+    long_sent: bool,
+    clock: Arc<dyn Clock>,
+}
+
+impl Button<rppal::gpio::InputPin> {
+    /// A button from BCM `pin` to ground, using the internal pull-up.
+    pub fn from_gpio(pin: u8) -> Result<Self, Box<dyn Error>> {
+        let pin = open_input(&Gpio::new()?, pin)?;
+        Button::new(pin, true)
+    }
+}
+
+impl<P: InputPin> Button<P>
+where
+    P::Error: Error + 'static,
+{
+    pub fn new(mut pin: P, active_low: bool) -> Result<Self, Box<dyn Error>> {
+        let raw = pin.is_high()? != active_low;
+        let clock = clock::system();
+        let now = clock.now();
+        Ok(Button {
+            pin,
+            active_low,
+            debounce: DEFAULT_DEBOUNCE,
+            long_press: DEFAULT_LONG_PRESS,
+            pressed: raw,
+            raw,
+            raw_since: now,
+            pressed_at: now,
+            // Held down at startup: don't report it until it's let go
+            long_sent: raw,
+            clock,
+        })
+    }
+
+    /// Time debounce and long presses by `clock` from now on.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        let now = clock.now();
+        self.raw_since = now;
+        self.pressed_at = now;
+        self.clock = clock;
+    }
+
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.debounce = debounce;
+    }
+
+    pub fn set_long_press(&mut self, long_press: Duration) {
+        self.long_press = long_press;
+    }
+
+    /// Debounced state.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    pub fn poll(&mut self) -> Result<Option<ButtonEvent>, Box<dyn Error>> {
+        let now = self.clock.now();
+        let raw = self.pin.is_high()? != self.active_low;
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = now;
+        }
+        if raw != self.pressed && now - self.raw_since >= self.debounce {
+            self.pressed = raw;
+            if raw {
+                self.pressed_at = now;
+                self.long_sent = false;
+            } else if !self.long_sent {
+                return Ok(Some(ButtonEvent::Click));
+            }
+        }
+        if self.pressed && !self.long_sent && now - self.pressed_at >= self.long_press {
+            self.long_sent = true;
+            return Ok(Some(ButtonEvent::LongPress));
+        }
+        Ok(None)
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+/// Valid quadrature transitions, indexed by `old << 2 | new` (A as the high
+/// bit): +1 one way, -1 the other, 0 for no change or a skipped state.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Detents from a stream of A/B states.
+#[derive(Debug, Clone, Copy)]
+struct Quadrature {
+    state: u8,
+    /// Transitions since the last whole detent.
+    partial: i8,
+    steps_per_detent: i8,
+}
+
+impl Quadrature {
+    fn new(state: u8) -> Self {
+        Quadrature {
+            state,
+            partial: 0,
+            steps_per_detent: 4,
+        }
+    }
+
+    fn set_steps_per_detent(&mut self, steps: u8) -> Result<(), Box<dyn Error>> {
+        if !matches!(steps, 1 | 2 | 4) {
+            return Err(format!("steps per detent must be 1, 2 or 4, not {}", steps).into());
+        }
+        self.steps_per_detent = steps as i8;
+        Ok(())
+    }
+
+    /// Move to `new` (A as bit 1, B as bit 0) and return the detents
+    /// completed: -1, 0 or 1.
+    fn update(&mut self, new: u8) -> i8 {
+        if new == self.state {
+            return 0;
+        }
+        self.partial += TRANSITIONS[usize::from(self.state << 2 | new)];
+        self.state = new;
+        let detents = self.partial / self.steps_per_detent;
+        self.partial %= self.steps_per_detent;
+        detents
+    }
+}
+
+/// A mechanical quadrature encoder with its common pin to ground.
+pub struct Rotary<P> {
+    a: P,
+    b: P,
+    quadrature: Quadrature,
+}
+
+impl Rotary<rppal::gpio::InputPin> {
+    /// Channels A and B on BCM pins, using the internal pull-ups.
+    pub fn from_gpio(a: u8, b: u8) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        Rotary::new(open_input(&gpio, a)?, open_input(&gpio, b)?)
+    }
+}
+
+impl<P: InputPin> Rotary<P>
+where
+    P::Error: Error + 'static,
+{
+    pub fn new(mut a: P, mut b: P) -> Result<Self, Box<dyn Error>> {
+        let state = (u8::from(a.is_high()?) << 1) | u8::from(b.is_high()?);
+        Ok(Rotary {
+            a,
+            b,
+            quadrature: Quadrature::new(state),
+        })
+    }
+
+    /// Quadrature transitions per click: 4 on most encoders, 2 or 1 on some.
+    pub fn set_steps_per_detent(&mut self, steps: u8) -> Result<(), Box<dyn Error>> {
+        self.quadrature.set_steps_per_detent(steps)
+    }
+
+    /// Detents turned since the last poll: positive clockwise (A leading).
+    /// Poll faster than the transitions come, or steps are lost.
+    pub fn poll(&mut self) -> Result<i32, Box<dyn Error>> {
+        let new = (u8::from(self.a.is_high()?) << 1) | u8::from(self.b.is_high()?);
+        Ok(i32::from(self.quadrature.update(new)))
+    }
+
+    pub fn release(self) -> (P, P) {
+        (self.a, self.b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderEvent {
+    /// One detent clockwise (A leading).
+    Clockwise,
+    CounterClockwise,
+    /// The shaft's push button went down.
+    Pressed,
+}
+
+/// Handler shared by the pins' interrupt threads.
+type Callback = Arc<Mutex<dyn FnMut(EncoderEvent) + Send>>;
+
+/// A quadrature encoder decoded from edge interrupts on both channels.
+///
+/// Each edge carries the new level of its pin, so the decoder follows every
+/// transition in order without reading the pins back, and a bounce that
+/// goes one way and back cancels out. Interrupts stop when this is dropped.
+pub struct RotaryEncoder {
+    a: rppal::gpio::InputPin,
+    b: rppal::gpio::InputPin,
+    button: Option<rppal::gpio::InputPin>,
+    quadrature: Arc<Mutex<Quadrature>>,
+}
+
+impl RotaryEncoder {
+    /// Channels A and B and an optional push button on BCM pins, all to
+    /// ground with the internal pull-ups. `callback` runs on an interrupt
+    /// thread for every event.
+    pub fn with_callback<F>(a: u8, b: u8, button: Option<u8>, callback: F) -> Result<Self, Box<dyn Error>>
+    where
+        F: FnMut(EncoderEvent) + Send + 'static,
+    {
+        let gpio = Gpio::new()?;
+        let mut a = open_input(&gpio, a)?;
+        let mut b = open_input(&gpio, b)?;
+        let state = (u8::from(a.is_high()) << 1) | u8::from(b.is_high());
+        let quadrature = Arc::new(Mutex::new(Quadrature::new(state)));
+        let callback: Callback = Arc::new(Mutex::new(callback));
+
+        for (pin, bit) in [(&mut a, 0b10), (&mut b, 0b01)] {
+            let quadrature = Arc::clone(&quadrature);
+            let callback = Arc::clone(&callback);
+            let number = pin.pin();
+            pin.set_async_interrupt(Trigger::Both, None, move |event| {
+                let detents = {
+                    let mut quadrature = quadrature.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                    let state = match event.trigger {
+                        Trigger::RisingEdge => quadrature.state | bit,
+                        _ => quadrature.state & !bit,
+                    };
+                    quadrature.update(state)
+                };
+                let event = match detents {
+                    1 => EncoderEvent::Clockwise,
+                    -1 => EncoderEvent::CounterClockwise,
+                    _ => return,
+                };
+                (callback.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))(event);
+            })
+            .map_err(|e| format!("encoder GPIO {}: {}", number, e))?;
+        }
+
+        let button = match button {
+            Some(pin) => {
+                let mut input = open_input(&gpio, pin)?;
+                let callback = Arc::clone(&callback);
+                // rppal drops edges within the debounce time of the last one
+                input
+                    .set_async_interrupt(Trigger::FallingEdge, Some(DEFAULT_DEBOUNCE), move |_| {
+                        (callback.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))(EncoderEvent::Pressed);
+                    })
+                    .map_err(|e| format!("button GPIO {}: {}", pin, e))?;
+                Some(input)
+            }
+            None => None,
+        };
+        Ok(RotaryEncoder { a, b, button, quadrature })
+    }
+
+    /// Like [`RotaryEncoder::with_callback`], with the events sent to the
+    /// returned channel. Events are dropped once it is.
+    pub fn with_channel(a: u8, b: u8, button: Option<u8>) -> Result<(Self, Receiver<EncoderEvent>), Box<dyn Error>> {
+        let (tx, rx) = mpsc::channel();
+        let encoder = RotaryEncoder::with_callback(a, b, button, move |event| {
+            let _ = tx.send(event);
+        })?;
+        Ok((encoder, rx))
+    }
+
+    /// Quadrature transitions per click: 4 on most encoders, 2 or 1 on some.
+    pub fn set_steps_per_detent(&self, steps: u8) -> Result<(), Box<dyn Error>> {
+        self.quadrature.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).set_steps_per_detent(steps)
+    }
+
+    /// BCM numbers of A, B and the button.
+    pub fn pins(&self) -> (u8, u8, Option<u8>) {
+        (self.a.pin(), self.b.pin(), self.button.as_ref().map(|b| b.pin()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A leading B: one full detent clockwise, as A/B states.
+    const CLOCKWISE: [u8; 4] = [0b10, 0b11, 0b01, 0b00];
+    const COUNTER_CLOCKWISE: [u8; 4] = [0b01, 0b11, 0b10, 0b00];
+
+    /// A decoder counting every step as a detent, from `state`.
+    fn single_steps(state: u8) -> Quadrature {
+        let mut quadrature = Quadrature::new(state);
+        quadrature.set_steps_per_detent(1).unwrap();
+        quadrature
+    }
+
+    fn feed(quadrature: &mut Quadrature, states: &[u8]) -> Vec<i8> {
+        states.iter().map(|&state| quadrature.update(state)).collect()
+    }
+
+    #[test]
+    fn gray_code_gives_direction() {
+        let mut quadrature = single_steps(0b00);
+        assert_eq!(feed(&mut quadrature, &CLOCKWISE), [1, 1, 1, 1]);
+        assert_eq!(feed(&mut quadrature, &COUNTER_CLOCKWISE), [-1, -1, -1, -1]);
+        // bouncing on one edge
+        assert_eq!(feed(&mut quadrature, &[0b10, 0b00, 0b10, 0b00]), [1, -1, 1, -1]);
+    }
+
+    #[test]
+    fn skipped_states_count_for_nothing() {
+        let mut quadrature = single_steps(0b00);
+        assert_eq!(feed(&mut quadrature, &[0b11, 0b00, 0b11]), [0, 0, 0]);
+        let mut quadrature = single_steps(0b01);
+        assert_eq!(feed(&mut quadrature, &[0b10, 0b01, 0b01]), [0, 0, 0]);
+    }
+
+    #[test]
+    fn four_steps_make_a_detent() {
+        let mut quadrature = Quadrature::new(0b00);
+        quadrature.set_steps_per_detent(4).unwrap();
+        assert_eq!(feed(&mut quadrature, &CLOCKWISE), [0, 0, 0, 1]);
+        assert_eq!(feed(&mut quadrature, &CLOCKWISE), [0, 0, 0, 1]);
+        assert_eq!(feed(&mut quadrature, &COUNTER_CLOCKWISE), [0, 0, 0, -1]);
+        // half a detent and back again leaves nothing over
+        assert_eq!(feed(&mut quadrature, &[0b10, 0b11, 0b10, 0b00]), [0, 0, 0, 0]);
+        assert_eq!(quadrature.partial, 0);
+        assert!(quadrature.set_steps_per_detent(3).is_err());
+    }
+}
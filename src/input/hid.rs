@@ -0,0 +1,202 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const INPUT_CLASS: &str = "/sys/class/input";
+
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+
+/// `_IOW('E', 0x90, int)`.
+const EVIOCGRAB: u32 = 0x4004_4590;
+
+/// Key and axis codes from `linux/input-event-codes.h`, as sent by the
+/// usual keypads and knobs.
+pub mod codes {
+    pub const KEY_ESC: u16 = 1;
+    pub const KEY_BACKSPACE: u16 = 14;
+    pub const KEY_ENTER: u16 = 28;
+    pub const KEY_SPACE: u16 = 57;
+    pub const KEY_KPENTER: u16 = 96;
+    pub const KEY_UP: u16 = 103;
+    pub const KEY_LEFT: u16 = 105;
+    pub const KEY_RIGHT: u16 = 106;
+    pub const KEY_DOWN: u16 = 108;
+    pub const KEY_MUTE: u16 = 113;
+    pub const KEY_VOLUMEDOWN: u16 = 114;
+    pub const KEY_VOLUMEUP: u16 = 115;
+    pub const KEY_PLAYPAUSE: u16 = 164;
+    pub const REL_HWHEEL: u16 = 0x06;
+    pub const REL_DIAL: u16 = 0x07;
+    pub const REL_WHEEL: u16 = 0x08;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Released,
+    Pressed,
+    /// Auto-repeat while held.
+    Repeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HidEvent {
+    Key { code: u16, state: KeyState },
+    /// A knob or wheel moved `steps` along `axis`, negative for the other
+    /// way.
+    Turn { axis: u16, steps: i32 },
+}
+
+/// One `/dev/input/event*` node and the name its device reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HidDevice {
+    pub path: PathBuf,
+    pub name: String,
+}
+
+/// Every evdev node, in kernel order.
+pub fn devices() -> Result<Vec<HidDevice>, Box<dyn Error>> {
+    let entries = fs::read_dir(INPUT_CLASS).map_err(|e| format!("{}: {}", INPUT_CLASS, e))?;
+    let mut found = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let node = entry.file_name().to_string_lossy().into_owned();
+        let Some(number) = node.strip_prefix("event").and_then(|n| n.parse::<u32>().ok()) else {
+            continue;
+        };
+        let name = fs::read_to_string(entry.path().join("device/name")).unwrap_or_default();
+        found.push((
+            number,
+            HidDevice {
+                path: Path::new("/dev/input").join(&node),
+                name: name.trim().to_string(),
+            },
+        ));
+    }
+    found.sort_by_key(|(number, _)| *number);
+    Ok(found.into_iter().map(|(_, device)| device).collect())
+}
+
+/// A USB keypad, knob or keyboard read through evdev.
+///
+/// Like the GPIO inputs this is polled: [`HidInput::next`] waits for up to
+/// a timeout and returns `None` when nothing happened.
+pub struct HidInput {
+    file: File,
+    name: String,
+}
+
+impl HidInput {
+    /// `device` is an event node path, or part of a device name as in
+    /// `list input`, matched ignoring case.
+    pub fn open(device: &str) -> Result<Self, Box<dyn Error>> {
+        if device.starts_with('/') {
+            let path = Path::new(device);
+            let name = devices()?
+                .into_iter()
+                .find(|d| d.path == path)
+                .map(|d| d.name)
+                .unwrap_or_else(|| device.to_string());
+            return HidInput::open_path(path, name);
+        }
+        let all = devices()?;
+        let wanted = device.to_lowercase();
+        match all.iter().find(|d| d.name.to_lowercase().contains(&wanted)) {
+            Some(found) => HidInput::open_path(&found.path, found.name.clone()),
+            None => {
+                let names: Vec<&str> = all.iter().map(|d| d.name.as_str()).collect();
+                Err(format!("no input device matching '{}' (have: {})", device, names.join(", ")).into())
+            }
+        }
+    }
+
+    fn open_path(path: &Path, name: String) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path).map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => format!("{}: {}; add the user to the 'input' group", path.display(), e),
+            _ => format!("{}: {}", path.display(), e),
+        })?;
+        Ok(HidInput { file, name })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Take the device for ourselves, so its keys stop reaching the console
+    /// and any desktop. Let go when this is dropped.
+    pub fn grab(&self) -> Result<(), Box<dyn Error>> {
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), EVIOCGRAB as _, 1 as libc::c_int) } != 0 {
+            let e = io::Error::last_os_error();
+            return Err(format!("{}: cannot grab: {}", self.name, e).into());
+        }
+        Ok(())
+    }
+
+    /// The next key or turn within `timeout`; `Duration::ZERO` only looks.
+    /// Fails once the device is unplugged.
+    pub fn next(&mut self, timeout: Duration) -> Result<Option<HidEvent>, Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if !self.wait(deadline.saturating_duration_since(Instant::now()))? {
+                return Ok(None);
+            }
+            let event = self.read_event()?;
+            match event.type_ {
+                EV_KEY => {
+                    let state = match event.value {
+                        0 => KeyState::Released,
+                        1 => KeyState::Pressed,
+                        _ => KeyState::Repeat,
+                    };
+                    return Ok(Some(HidEvent::Key { code: event.code, state }));
+                }
+                EV_REL if event.value != 0 => {
+                    return Ok(Some(HidEvent::Turn {
+                        axis: event.code,
+                        steps: event.value,
+                    }))
+                }
+                // Sync reports, scan codes and pointer motion
+                _ => {}
+            }
+        }
+    }
+
+    fn wait(&self, timeout: Duration) -> Result<bool, Box<dyn Error>> {
+        let mut fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // Round up, so a wait of less than a millisecond still waits
+        let ms = timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as libc::c_int;
+        let ready = unsafe { libc::poll(&mut fd, 1, ms) };
+        if ready < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                return Ok(false);
+            }
+            return Err(format!("{}: {}", self.name, e).into());
+        }
+        if fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+            return Err(format!("{} was unplugged", self.name).into());
+        }
+        Ok(ready > 0)
+    }
+
+    fn read_event(&mut self) -> Result<libc::input_event, Box<dyn Error>> {
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        // evdev hands out whole events, never part of one
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(&mut event as *mut libc::input_event as *mut u8, std::mem::size_of::<libc::input_event>())
+        };
+        self.file.read_exact(bytes).map_err(|e| match e.raw_os_error() {
+            Some(libc::ENODEV) => format!("{} was unplugged", self.name),
+            _ => format!("{}: {}", self.name, e),
+        })?;
+        Ok(event)
+    }
+}
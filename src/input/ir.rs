@@ -0,0 +1,208 @@
+use rppal::gpio::{Gpio, Trigger};
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// NEC timings, in microseconds: the leader's mark and spaces, and one
+/// bit's mark and its spaces for 0 and 1.
+const LEADER_MARK: u64 = 9000;
+const LEADER_SPACE: u64 = 4500;
+const REPEAT_SPACE: u64 = 2250;
+const BIT_MARK: u64 = 562;
+const ZERO_SPACE: u64 = 562;
+const ONE_SPACE: u64 = 1687;
+
+/// A repeat code only counts this soon after the last frame or repeat;
+/// they come every 108 ms while a key is held.
+pub const REPEAT_WINDOW: Duration = Duration::from_millis(150);
+
+/// One key of an NEC remote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IrEvent {
+    /// 8 bits, or 16 on remotes using the extended format, whose second
+    /// address byte isn't the inverse of the first.
+    pub address: u16,
+    pub command: u8,
+    /// A repeat code from a held key, rather than the frame of a press.
+    pub repeat: bool,
+}
+
+impl fmt::Display for IrEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = if self.address > 0xFF { 4 } else { 2 };
+        write!(f, "address 0x{:0width$X} command 0x{:02X}", self.address, self.command, width = width)?;
+        if self.repeat {
+            write!(f, " (repeat)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Within a quarter of `nominal`: receivers stretch marks and shrink
+/// spaces by up to 100 µs or so, and remotes run on cheap resonators.
+fn near(duration: u64, nominal: u64) -> bool {
+    duration.abs_diff(nominal) <= nominal / 4
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// In the 9 ms leader mark.
+    Leader,
+    /// In the space after it.
+    LeaderSpace,
+    /// In a bit's mark, `bits` received so far.
+    Mark { bits: u8 },
+    /// In the space that says what the bit is.
+    Space { bits: u8 },
+    /// In the final mark of a repeat code.
+    RepeatMark,
+}
+
+/// The NEC state machine, fed one edge at a time with the level the line
+/// went to and when. The receiver's output is active low: low while it
+/// sees 38 kHz, a *mark*; high between bursts, a *space*.
+///
+/// Anything out of step with the protocol drops back to waiting for a
+/// leader, so noise and other protocols are ignored rather than misread.
+#[derive(Debug, Clone)]
+pub struct NecDecoder {
+    state: State,
+    last_edge: Duration,
+    data: u32,
+    /// The last frame decoded and when it ended, for repeat codes.
+    last: Option<(IrEvent, Duration)>,
+}
+
+impl Default for NecDecoder {
+    fn default() -> Self {
+        NecDecoder::new()
+    }
+}
+
+impl NecDecoder {
+    pub fn new() -> Self {
+        NecDecoder {
+            state: State::Idle,
+            last_edge: Duration::ZERO,
+            data: 0,
+            last: None,
+        }
+    }
+
+    /// `high` is the line's level after the edge, `at` the edge's time on
+    /// any clock that only goes forwards. Returns the event a frame or
+    /// repeat code completed, if this edge completed one.
+    pub fn edge(&mut self, high: bool, at: Duration) -> Option<IrEvent> {
+        let length = at.saturating_sub(self.last_edge).as_micros() as u64;
+        self.last_edge = at;
+        let (state, event) = match (self.state, high) {
+            // A mark starts: the leader, if it is one
+            (_, false) if !matches!(self.state, State::Space { .. } | State::LeaderSpace) => (State::Leader, None),
+            (State::Leader, true) if near(length, LEADER_MARK) => (State::LeaderSpace, None),
+            (State::LeaderSpace, false) if near(length, LEADER_SPACE) => {
+                self.data = 0;
+                (State::Mark { bits: 0 }, None)
+            }
+            (State::LeaderSpace, false) if near(length, REPEAT_SPACE) => (State::RepeatMark, None),
+            (State::LeaderSpace, false) => (State::Leader, None),
+            (State::RepeatMark, true) if near(length, BIT_MARK) => {
+                let event = match self.last {
+                    Some((event, ended)) if at.saturating_sub(ended) <= REPEAT_WINDOW => {
+                        self.last = Some((event, at));
+                        Some(IrEvent { repeat: true, ..event })
+                    }
+                    _ => None,
+                };
+                (State::Idle, event)
+            }
+            (State::Mark { bits }, true) if near(length, BIT_MARK) => match bits {
+                // The trailing mark after 32 bits
+                32 => (State::Idle, self.frame(at)),
+                _ => (State::Space { bits }, None),
+            },
+            (State::Space { bits }, false) => {
+                let bit = if near(length, ZERO_SPACE) {
+                    0
+                } else if near(length, ONE_SPACE) {
+                    1
+                } else {
+                    // Fits no bit, but the mark it ended with may be a leader
+                    self.state = State::Leader;
+                    return None;
+                };
+                // LSB first
+                self.data |= bit << bits;
+                (State::Mark { bits: bits + 1 }, None)
+            }
+            // A mark that ends at the wrong time, or a space out of turn
+            _ => (State::Idle, None),
+        };
+        self.state = state;
+        event
+    }
+
+    fn frame(&mut self, at: Duration) -> Option<IrEvent> {
+        let [address, address_check, command, command_check] = self.data.to_le_bytes();
+        if command != !command_check {
+            return None;
+        }
+        let address = if address == !address_check {
+            u16::from(address)
+        } else {
+            u16::from_le_bytes([address, address_check])
+        };
+        let event = IrEvent { address, command, repeat: false };
+        self.last = Some((event, at));
+        Some(event)
+    }
+}
+
+/// A TSOP38238 or similar 38 kHz receiver module on a GPIO, decoding NEC
+/// remotes. Edges are timestamped by the kernel as they happen, so a busy
+/// interrupt thread doesn't blur the timing. Interrupts stop when this is
+/// dropped.
+pub struct IrReceiver {
+    pin: rppal::gpio::InputPin,
+}
+
+impl IrReceiver {
+    /// The receiver's OUT on BCM pin `pin`. `callback` runs on the
+    /// interrupt thread for every key press and repeat.
+    pub fn with_callback<F>(pin: u8, mut callback: F) -> Result<Self, Box<dyn Error>>
+    where
+        F: FnMut(IrEvent) + Send + 'static,
+    {
+        let mut input = Gpio::new()?
+            .get(pin)
+            .map_err(|e| format!("IR GPIO {}: {}", pin, e))?
+            .into_input_pullup();
+        let decoder = Arc::new(Mutex::new(NecDecoder::new()));
+        input
+            .set_async_interrupt(Trigger::Both, None, move |event| {
+                let high = event.trigger == Trigger::RisingEdge;
+                let decoded = decoder.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).edge(high, event.timestamp);
+                if let Some(event) = decoded {
+                    callback(event);
+                }
+            })
+            .map_err(|e| format!("IR GPIO {}: {}", pin, e))?;
+        Ok(IrReceiver { pin: input })
+    }
+
+    /// Like [`IrReceiver::with_callback`], with the events sent to the
+    /// returned channel. Events are dropped once it is.
+    pub fn with_channel(pin: u8) -> Result<(Self, Receiver<IrEvent>), Box<dyn Error>> {
+        let (tx, rx) = mpsc::channel();
+        let receiver = IrReceiver::with_callback(pin, move |event| {
+            let _ = tx.send(event);
+        })?;
+        Ok((receiver, rx))
+    }
+
+    pub fn pin(&self) -> u8 {
+        self.pin.pin()
+    }
+}
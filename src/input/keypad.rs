@@ -0,0 +1,459 @@
+use super::{open_input, DEFAULT_DEBOUNCE, DEFAULT_LONG_PRESS};
+use crate::address::{Address, AddressedI2c};
+use crate::clock::{self, Clock};
+use crate::expander::{Direction, Mcp23017};
+use rppal::gpio::{Gpio, IoPin, Mode};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The common 16-key membrane keypad, top row first.
+pub const KEYS_4X4: [&str; 4] = ["123A", "456B", "789C", "*0#D"];
+
+/// The 12-key phone layout.
+pub const KEYS_4X3: [&str; 4] = ["123", "456", "789", "*0#"];
+
+/// How often a [`KeypadMatrix::spawn`]ed scanner goes over the keys.
+pub const DEFAULT_SCAN: Duration = Duration::from_millis(5);
+
+/// The row and column wires of a key matrix. One row at a time is pulled
+/// low with the rest left floating, and a pressed key pulls its column
+/// down with it against the column's pull-up.
+pub trait MatrixLines {
+    fn rows(&self) -> usize;
+
+    fn columns(&self) -> usize;
+
+    /// Pull `row` low and read the columns: bit n set for column n low.
+    fn scan_row(&mut self, row: usize) -> Result<u16, Box<dyn Error>>;
+}
+
+/// A keypad straight on Pi GPIOs. Idle rows are inputs, so two keys held
+/// in one column never short a high output to a low one.
+pub struct GpioLines {
+    rows: Vec<IoPin>,
+    columns: Vec<rppal::gpio::InputPin>,
+}
+
+impl GpioLines {
+    /// BCM pins for the rows, top first, and the columns, left first.
+    pub fn from_gpio(rows: &[u8], columns: &[u8]) -> Result<Self, Box<dyn Error>> {
+        check_size(rows.len(), columns.len())?;
+        let gpio = Gpio::new()?;
+        let rows = rows
+            .iter()
+            .map(|&pin| -> Result<IoPin, Box<dyn Error>> {
+                let mut row = gpio.get(pin).map_err(|e| format!("keypad row GPIO {}: {}", pin, e))?.into_io(Mode::Input);
+                row.set_low();
+                Ok(row)
+            })
+            .collect::<Result<_, _>>()?;
+        let columns = columns.iter().map(|&pin| open_input(&gpio, pin)).collect::<Result<_, _>>()?;
+        Ok(GpioLines { rows, columns })
+    }
+}
+
+impl MatrixLines for GpioLines {
+    fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    fn scan_row(&mut self, row: usize) -> Result<u16, Box<dyn Error>> {
+        let pin = self.rows.get_mut(row).ok_or_else(|| format!("keypad has no row {}", row))?;
+        pin.set_mode(Mode::Output);
+        // Let the column wires follow before reading them
+        thread::sleep(SETTLE);
+        let low = self.columns.iter().enumerate().fold(0, |low, (i, column)| low | u16::from(column.is_low()) << i);
+        pin.set_mode(Mode::Input);
+        Ok(low)
+    }
+}
+
+/// Long enough for a column's pull-up to win over a short cable.
+const SETTLE: Duration = Duration::from_micros(10);
+
+/// A keypad on a PCF8574, whose pins are weak pull-ups until written low,
+/// so rows and columns need no setting up. The usual wiring is rows on
+/// P0-P3 and columns on P4-P7.
+pub struct Pcf8574Lines<I2C> {
+    i2c: I2C,
+    address: Address,
+    rows: Vec<u8>,
+    columns: Vec<u8>,
+}
+
+impl<I2C: AddressedI2c> Pcf8574Lines<I2C> {
+    /// Expander pins (0-7) for the rows and for the columns.
+    pub fn new(i2c: I2C, address: Address, rows: &[u8], columns: &[u8]) -> Result<Self, Box<dyn Error>> {
+        check_size(rows.len(), columns.len())?;
+        check_pins(rows, columns, 8)?;
+        let mut lines = Pcf8574Lines {
+            i2c,
+            address,
+            rows: rows.to_vec(),
+            columns: columns.to_vec(),
+        };
+        lines.i2c.write_at(lines.address, &[0xFF])?;
+        Ok(lines)
+    }
+
+    /// A 4x4 keypad with rows on P0-P3 and columns on P4-P7.
+    pub fn four_by_four(i2c: I2C, address: Address) -> Result<Self, Box<dyn Error>> {
+        Pcf8574Lines::new(i2c, address, &[0, 1, 2, 3], &[4, 5, 6, 7])
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: AddressedI2c> MatrixLines for Pcf8574Lines<I2C> {
+    fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    fn scan_row(&mut self, row: usize) -> Result<u16, Box<dyn Error>> {
+        let pin = *self.rows.get(row).ok_or_else(|| format!("keypad has no row {}", row))?;
+        self.i2c.write_at(self.address, &[!(1 << pin)])?;
+        let mut port = [0];
+        self.i2c.read_at(self.address, &mut port)?;
+        Ok(self.columns.iter().enumerate().fold(0, |low, (i, &pin)| low | u16::from(port[0] & 1 << pin == 0) << i))
+    }
+}
+
+/// A keypad on an MCP23017, leaving its other pins as they are. Columns
+/// get the internal pull-ups; a row is an output, driven low, only while
+/// it's being read.
+pub struct Mcp23017Lines<I2C> {
+    expander: Mcp23017<I2C>,
+    rows: Vec<u8>,
+    columns: Vec<u8>,
+}
+
+impl<I2C: AddressedI2c> Mcp23017Lines<I2C> {
+    /// Expander pins (0-15) for the rows and for the columns.
+    pub fn new(mut expander: Mcp23017<I2C>, rows: &[u8], columns: &[u8]) -> Result<Self, Box<dyn Error>> {
+        check_size(rows.len(), columns.len())?;
+        check_pins(rows, columns, 16)?;
+        for &pin in rows {
+            expander.write_pin(pin, false)?;
+            expander.set_direction(pin, Direction::Input)?;
+        }
+        for &pin in columns {
+            expander.set_direction(pin, Direction::Input)?;
+            expander.set_pull_up(pin, true)?;
+        }
+        Ok(Mcp23017Lines {
+            expander,
+            rows: rows.to_vec(),
+            columns: columns.to_vec(),
+        })
+    }
+
+    pub fn release(self) -> Mcp23017<I2C> {
+        self.expander
+    }
+}
+
+impl<I2C: AddressedI2c> MatrixLines for Mcp23017Lines<I2C> {
+    fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    fn scan_row(&mut self, row: usize) -> Result<u16, Box<dyn Error>> {
+        let pin = *self.rows.get(row).ok_or_else(|| format!("keypad has no row {}", row))?;
+        self.expander.set_direction(pin, Direction::Output)?;
+        let port = self.expander.read_port();
+        self.expander.set_direction(pin, Direction::Input)?;
+        let port = port?;
+        Ok(self.columns.iter().enumerate().fold(0, |low, (i, &pin)| low | u16::from(port & 1 << pin == 0) << i))
+    }
+}
+
+fn check_size(rows: usize, columns: usize) -> Result<(), Box<dyn Error>> {
+    if rows == 0 || columns == 0 || columns > 16 {
+        return Err(format!("a keypad needs at least one row and 1-16 columns, not {}x{}", rows, columns).into());
+    }
+    Ok(())
+}
+
+fn check_pins(rows: &[u8], columns: &[u8], pins: u8) -> Result<(), Box<dyn Error>> {
+    let mut used = 0u32;
+    for &pin in rows.iter().chain(columns) {
+        if pin >= pins {
+            return Err(format!("expander has no pin {} (0-{})", pin, pins - 1).into());
+        }
+        if used & 1 << pin != 0 {
+            return Err(format!("expander pin {} is used twice", pin).into());
+        }
+        used |= 1 << pin;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Pressed,
+    /// Still down at the hold time; comes once per press.
+    Held,
+    Released,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: char,
+    pub action: KeyAction,
+}
+
+/// Debounce state of one key.
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    pressed: bool,
+    raw: bool,
+    raw_since: Instant,
+    pressed_at: Instant,
+    held_sent: bool,
+}
+
+/// A matrix keypad, scanned a row at a time and debounced key by key, so
+/// several keys can be down at once. Call [`poll`](KeypadMatrix::poll)
+/// every few milliseconds, or [`spawn`](KeypadMatrix::spawn) a thread to
+/// do it and read the events from a channel:
+///
+/// ```no_run
+/// use rpi_peripherals::input::{GpioLines, KeyAction, KeypadMatrix, DEFAULT_SCAN, KEYS_4X4};
+///
+/// let lines = GpioLines::from_gpio(&[5, 6, 13, 19], &[12, 16, 20, 21])?;
+/// let (_scanner, events) = KeypadMatrix::new(lines, &KEYS_4X4)?.spawn(DEFAULT_SCAN)?;
+/// for event in events {
+///     if event.action == KeyAction::Pressed {
+///         println!("{}", event.key);
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct KeypadMatrix<L> {
+    lines: L,
+    keys: Vec<Vec<char>>,
+    contacts: Vec<Contact>,
+    debounce: Duration,
+    hold: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl<L: MatrixLines> KeypadMatrix<L> {
+    /// `layout` gives the key on each row, top first, one character per
+    /// column.
+    pub fn new<S: AsRef<str>>(lines: L, layout: &[S]) -> Result<Self, Box<dyn Error>> {
+        let keys: Vec<Vec<char>> = layout.iter().map(|row| row.as_ref().chars().collect()).collect();
+        if keys.len() != lines.rows() || keys.iter().any(|row| row.len() != lines.columns()) {
+            return Err(format!("keypad layout doesn't fit {} rows of {} columns", lines.rows(), lines.columns()).into());
+        }
+        let clock = clock::system();
+        let now = clock.now();
+        let contact = Contact {
+            pressed: false,
+            raw: false,
+            raw_since: now,
+            pressed_at: now,
+            held_sent: false,
+        };
+        Ok(KeypadMatrix {
+            contacts: vec![contact; lines.rows() * lines.columns()],
+            lines,
+            keys,
+            debounce: DEFAULT_DEBOUNCE,
+            hold: DEFAULT_LONG_PRESS,
+            clock,
+        })
+    }
+
+    /// Time debounce and holds by `clock` from now on.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        let now = clock.now();
+        for contact in &mut self.contacts {
+            contact.raw_since = now;
+            contact.pressed_at = now;
+        }
+        self.clock = clock;
+    }
+
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.debounce = debounce;
+    }
+
+    pub fn set_hold(&mut self, hold: Duration) {
+        self.hold = hold;
+    }
+
+    /// The keys down now, debounced, in layout order.
+    pub fn pressed(&self) -> Vec<char> {
+        let columns = self.lines.columns();
+        (0..self.contacts.len())
+            .filter(|&i| self.contacts[i].pressed)
+            .map(|i| self.keys[i / columns][i % columns])
+            .collect()
+    }
+
+    /// Scan every row once and return what changed.
+    pub fn poll(&mut self) -> Result<Vec<KeyEvent>, Box<dyn Error>> {
+        let columns = self.lines.columns();
+        let mut events = Vec::new();
+        for row in 0..self.lines.rows() {
+            let low = self.lines.scan_row(row)?;
+            let now = self.clock.now();
+            for column in 0..columns {
+                let key = self.keys[row][column];
+                let contact = &mut self.contacts[row * columns + column];
+                let raw = low & 1 << column != 0;
+                if raw != contact.raw {
+                    contact.raw = raw;
+                    contact.raw_since = now;
+                }
+                if raw != contact.pressed && now - contact.raw_since >= self.debounce {
+                    contact.pressed = raw;
+                    let action = if raw {
+                        contact.pressed_at = now;
+                        contact.held_sent = false;
+                        KeyAction::Pressed
+                    } else {
+                        KeyAction::Released
+                    };
+                    events.push(KeyEvent { key, action });
+                }
+                if contact.pressed && !contact.held_sent && now - contact.pressed_at >= self.hold {
+                    contact.held_sent = true;
+                    events.push(KeyEvent { key, action: KeyAction::Held });
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    pub fn release(self) -> L {
+        self.lines
+    }
+}
+
+impl<L: MatrixLines + Send + 'static> KeypadMatrix<L> {
+    /// Poll every `scan` on a thread of its own, sending events to the
+    /// returned channel until the [`KeypadScanner`] is dropped.
+    pub fn spawn(mut self, scan: Duration) -> Result<(KeypadScanner, Receiver<KeyEvent>), Box<dyn Error>> {
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let thread = thread::Builder::new().name("keypad".to_string()).spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                for event in self.poll().map_err(|e| e.to_string())? {
+                    if tx.send(event).is_err() {
+                        return Ok(());
+                    }
+                }
+                thread::sleep(scan);
+            }
+            Ok(())
+        })?;
+        Ok((KeypadScanner { stop, thread: Some(thread) }, rx))
+    }
+}
+
+/// The thread behind [`KeypadMatrix::spawn`]; scanning stops when it's
+/// dropped.
+pub struct KeypadScanner {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl KeypadScanner {
+    /// Stop scanning, with the error that stopped it early if there was one.
+    pub fn stop(mut self) -> Result<(), Box<dyn Error>> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take() {
+            Some(thread) => Ok(thread.join().map_err(|_| "keypad thread panicked")??),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for KeypadScanner {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SimClock;
+    use std::sync::Mutex;
+
+    /// Keys held down, by row and column.
+    #[derive(Clone, Default)]
+    struct FakeLines(Arc<Mutex<Vec<(usize, usize)>>>);
+
+    impl MatrixLines for FakeLines {
+        fn rows(&self) -> usize {
+            4
+        }
+
+        fn columns(&self) -> usize {
+            4
+        }
+
+        fn scan_row(&mut self, row: usize) -> Result<u16, Box<dyn Error>> {
+            Ok(self.0.lock().unwrap().iter().filter(|key| key.0 == row).fold(0, |low, key| low | 1 << key.1))
+        }
+    }
+
+    #[test]
+    fn debounces_and_detects_holds() {
+        let lines = FakeLines::default();
+        let clock = SimClock::new(0);
+        let mut keypad = KeypadMatrix::new(lines.clone(), &KEYS_4X4).unwrap();
+        keypad.set_clock(Arc::new(clock.clone()));
+        let event = |key, action| KeyEvent { key, action };
+
+        // a bounce shorter than the debounce time is never seen
+        lines.0.lock().unwrap().push((2, 1));
+        assert!(keypad.poll().unwrap().is_empty());
+        lines.0.lock().unwrap().clear();
+        clock.advance(Duration::from_millis(30));
+        assert!(keypad.poll().unwrap().is_empty());
+
+        // '8' and 'D' together
+        lines.0.lock().unwrap().extend([(2, 1), (3, 3)]);
+        keypad.poll().unwrap();
+        clock.advance(Duration::from_millis(25));
+        assert_eq!(keypad.poll().unwrap(), [event('8', KeyAction::Pressed), event('D', KeyAction::Pressed)]);
+        assert_eq!(keypad.pressed(), ['8', 'D']);
+        clock.advance(Duration::from_millis(600));
+        assert_eq!(keypad.poll().unwrap(), [event('8', KeyAction::Held), event('D', KeyAction::Held)]);
+        assert!(keypad.poll().unwrap().is_empty());
+
+        lines.0.lock().unwrap().retain(|&key| key != (3, 3));
+        keypad.poll().unwrap();
+        clock.advance(Duration::from_millis(25));
+        assert_eq!(keypad.poll().unwrap(), [event('D', KeyAction::Released)]);
+        assert_eq!(keypad.pressed(), ['8']);
+        assert!(KeypadMatrix::new(lines, &KEYS_4X3).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Expected-hardware inventories for end-of-line testing.
+//!
+//! An inventory lists what an assembled board must have on its bus and what
+//! a few registers must read back, e.g. chip IDs and power-on defaults:
+//!
+//! ```toml
+//! # Anything else answering on the bus is a mismatch too.
+//! strict = true
+//!
+//! [[devices]]
+//! name = "rtc"
+//! address = 0x68
+//!
+//! [[devices.registers]]
+//! name = "control"
+//! register = 0x0E
+//! expect = 0x1C
+//!
+//! [[devices]]
+//! name = "imu"
+//! address = 0x6A
+//!
+//! [[devices.registers]]
+//! name = "WHO_AM_I"
+//! register = 0x0F
+//! expect = [0x6C]
+//! mask = 0xFF
+//! ```
+//!
+//! [`Inventory::verify`] checks a live bus against it and returns every
+//! mismatch rather than stopping at the first.
+//!
+//! Without writing one by hand, a [`Snapshot`] records whatever answered
+//! and what it was identified as, as JSON, and a later scan is
+//! [diffed](Snapshot::diff) against it for devices that appeared, vanished
+//! or changed identity.
+
+mod snapshot;
+
+pub use snapshot::{Change, SeenDevice, Snapshot, SNAPSHOT_VERSION};
+
+use crate::address::{Address, AddressedI2c};
+use crate::scan;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Inventory {
+    /// Treat devices answering at addresses not listed here as mismatches.
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(default)]
+    pub devices: Vec<ExpectedDevice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedDevice {
+    pub name: String,
+    pub address: u16,
+    #[serde(default)]
+    pub registers: Vec<RegisterCheck>,
+}
+
+/// Read `expect.len()` bytes from `register`; each must match under `mask`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterCheck {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub register: u8,
+    pub expect: Expected,
+    #[serde(default = "default_mask")]
+    pub mask: u8,
+}
+
+fn default_mask() -> u8 {
+    0xFF
+}
+
+/// One byte or several, so `expect = 0x1C` and `expect = [0x12, 0x34]` both work.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Expected {
+    Byte(u8),
+    Bytes(Vec<u8>),
+}
+
+impl Expected {
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            Expected::Byte(b) => std::slice::from_ref(b),
+            Expected::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+impl RegisterCheck {
+    fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} (0x{:02X})", name, self.register),
+            None => format!("register 0x{:02X}", self.register),
+        }
+    }
+}
+
+/// One way the bus differs from the inventory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    Missing { device: String, address: Address },
+    Unexpected { address: Address },
+    Register { device: String, check: String, expected: Vec<u8>, actual: Vec<u8>, mask: u8 },
+    ReadFailed { device: String, check: String, error: String },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Missing { device, address } => write!(f, "{} not found at {}", device, address),
+            Mismatch::Unexpected { address } => write!(f, "unexpected device at {}", address),
+            Mismatch::Register { device, check, expected, actual, mask } => {
+                write!(f, "{} {}: expected {:02X?}, read {:02X?}", device, check, expected, actual)?;
+                if *mask != 0xFF {
+                    write!(f, " (mask 0x{:02X})", mask)?;
+                }
+                Ok(())
+            }
+            Mismatch::ReadFailed { device, check, error } => write!(f, "{} {}: read failed: {}", device, check, error),
+        }
+    }
+}
+
+impl Inventory {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        text.parse()
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Probe every device and run its register checks. Registers of a
+    /// missing device aren't read; the missing device is the mismatch.
+    pub fn verify<I2C: AddressedI2c>(&self, i2c: &mut I2C) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        for device in &self.devices {
+            let address = Address::from_raw(device.address).expect("validated on load");
+            if !scan::probe(i2c, address) {
+                mismatches.push(Mismatch::Missing {
+                    device: device.name.clone(),
+                    address,
+                });
+                continue;
+            }
+            for check in &device.registers {
+                let expected = check.expect.bytes();
+                let mut actual = vec![0; expected.len()];
+                match i2c.write_read_at(address, &[check.register], &mut actual) {
+                    Ok(()) => {
+                        let same = expected.iter().zip(&actual).all(|(e, a)| e & check.mask == a & check.mask);
+                        if !same {
+                            mismatches.push(Mismatch::Register {
+                                device: device.name.clone(),
+                                check: check.label(),
+                                expected: expected.to_vec(),
+                                actual,
+                                mask: check.mask,
+                            });
+                        }
+                    }
+                    Err(e) => mismatches.push(Mismatch::ReadFailed {
+                        device: device.name.clone(),
+                        check: check.label(),
+                        error: e.to_string(),
+                    }),
+                }
+            }
+        }
+        if self.strict {
+            for address in scan::scan(i2c) {
+                if !self.devices.iter().any(|d| d.address == address.raw()) {
+                    mismatches.push(Mismatch::Unexpected { address });
+                }
+            }
+        }
+        mismatches
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for device in &self.devices {
+            Address::from_raw(device.address).map_err(|e| format!("device '{}': {}", device.name, e))?;
+            if let Some(other) = self
+                .devices
+                .iter()
+                .find(|d| d.address == device.address && d.name != device.name)
+            {
+                return Err(format!("'{}' and '{}' share address 0x{:02X}", device.name, other.name, device.address).into());
+            }
+            for check in &device.registers {
+                if check.expect.bytes().is_empty() {
+                    return Err(format!("device '{}' {}: expect is empty", device.name, check.label()).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Inventory {
+    type Err = Box<dyn Error>;
+
+    /// Parse and validate.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let inventory: Inventory = toml::from_str(s)?;
+        inventory.validate()?;
+        Ok(inventory)
+    }
+}
@@ -0,0 +1,184 @@
+use crate::address::{Address, AddressedI2c};
+use crate::identify;
+use crate::scan;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Format version written by this build.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// One device that answered, and what it was taken for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeenDevice {
+    pub address: Address,
+    /// The chip its ID check matched, or the only one it could be.
+    pub chip: Option<String>,
+    /// What the ID check read, e.g. `WHO_AM_I=0x6C`.
+    #[serde(default)]
+    pub evidence: Option<String>,
+    /// Every chip known to use the address.
+    #[serde(default)]
+    pub candidates: Vec<String>,
+}
+
+impl SeenDevice {
+    /// What to compare between runs: the chip, else the candidates.
+    pub fn identity(&self) -> String {
+        match (&self.chip, self.candidates.as_slice()) {
+            (Some(chip), _) => chip.clone(),
+            (None, []) => "unknown".to_string(),
+            (None, candidates) => candidates.join(" or "),
+        }
+    }
+}
+
+/// What answered on a bus at one moment, for [`diff`](Snapshot::diff)ing
+/// against a later scan: a connector that worked loose shows as a device
+/// gone, a swapped board as a device whose identity changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub bus: u8,
+    /// Seconds since the Unix epoch.
+    pub taken: u64,
+    pub devices: Vec<SeenDevice>,
+}
+
+/// One way a bus differs from its snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Appeared(SeenDevice),
+    Vanished(SeenDevice),
+    Changed { address: Address, was: String, now: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Appeared(device) => write!(f, "{} appeared: {}", device.address, device.identity()),
+            Change::Vanished(device) => write!(f, "{} vanished: was {}", device.address, device.identity()),
+            Change::Changed { address, was, now } => write!(f, "{} changed: was {}, now {}", address, was, now),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Scan `bus` and identify everything that answers.
+    pub fn take<I2C: AddressedI2c>(i2c: &mut I2C, bus: u8) -> Self {
+        let devices = scan::scan(i2c)
+            .into_iter()
+            .map(|address| {
+                let found = identify::identify(i2c, address);
+                SeenDevice {
+                    address,
+                    chip: found.best().map(|p| p.name.to_string()),
+                    evidence: found.identified.as_ref().map(|(_, evidence)| evidence.clone()),
+                    candidates: found.candidates.iter().map(|p| p.name.to_string()).collect(),
+                }
+            })
+            .collect();
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            bus,
+            taken: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            devices,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let snapshot: Snapshot = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "{}: snapshot version {} is not supported (expected {})",
+                path.display(),
+                snapshot.version,
+                SNAPSHOT_VERSION
+            )
+            .into());
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text + "\n").map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// What changed from this snapshot to `now`, in address order.
+    pub fn diff(&self, now: &Snapshot) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for before in &self.devices {
+            match now.devices.iter().find(|d| d.address == before.address) {
+                None => changes.push(Change::Vanished(before.clone())),
+                Some(after) if after.identity() != before.identity() => changes.push(Change::Changed {
+                    address: before.address,
+                    was: before.identity(),
+                    now: after.identity(),
+                }),
+                Some(_) => {}
+            }
+        }
+        for after in &now.devices {
+            if self.devices.iter().all(|d| d.address != after.address) {
+                changes.push(Change::Appeared(after.clone()));
+            }
+        }
+        let address = |change: &Change| match change {
+            Change::Appeared(d) | Change::Vanished(d) => d.address.raw(),
+            Change::Changed { address, .. } => address.raw(),
+        };
+        changes.sort_by_key(address);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seen(address: u8, chip: Option<&str>, candidates: &[&str]) -> SeenDevice {
+        SeenDevice {
+            address: Address::SevenBit(address),
+            chip: chip.map(str::to_string),
+            evidence: None,
+            candidates: candidates.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn reports_appeared_vanished_and_changed() {
+        let before = Snapshot {
+            version: SNAPSHOT_VERSION,
+            bus: 1,
+            taken: 0,
+            devices: vec![
+                seen(0x27, Some("pcf8574"), &["pcf8574"]),
+                seen(0x68, Some("ds3231"), &["ds3231", "mpu6050"]),
+                seen(0x76, None, &["bme280", "bmp280"]),
+            ],
+        };
+        let now = Snapshot {
+            devices: vec![
+                seen(0x27, Some("pcf8574"), &["pcf8574"]),
+                seen(0x68, Some("mpu6050"), &["ds3231", "mpu6050"]),
+                seen(0x50, None, &[]),
+            ],
+            ..before.clone()
+        };
+        let changes: Vec<String> = before.diff(&now).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            changes,
+            ["0x50 appeared: unknown", "0x68 changed: was ds3231, now mpu6050", "0x76 vanished: was bme280 or bmp280"]
+        );
+        assert!(before.diff(&before).is_empty());
+
+        let json = serde_json::to_string(&before).unwrap();
+        assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), before);
+    }
+}
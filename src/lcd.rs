@@ -0,0 +1,594 @@
+//! HD44780 character LCD, behind a PCF8574 I2C backpack or wired straight
+//! to GPIO.
+//!
+//! [`Lcd::new`] drives the usual backpack ([`Backpack`]). For a display on
+//! the header, [`Lcd::with_interface`] takes a [`ParallelLcd`] with four or
+//! eight data lines. In 4-bit mode every byte goes out as two nibbles,
+//! each latched by pulsing E; in 8-bit mode, one latch per byte.
+//!
+//! Eight custom characters fit in CGRAM. Define them with
+//! [`Lcd::create_char`], drawn with [`glyph::parse`] or taken from the
+//! built-ins, then print them with [`Lcd::write_glyph`] or as `'\x00'` to
+//! `'\x07'` in text:
+//!
+//! ```no_run
+//! # fn demo<B: rpi_peripherals::lcd::LcdInterface>(lcd: &mut rpi_peripherals::lcd::Lcd<B>) -> Result<(), Box<dyn std::error::Error>> {
+//! use rpi_peripherals::lcd::glyph;
+//!
+//! lcd.create_char(0, glyph::DEGREE)?;
+//! lcd.create_char(1, glyph::HEART)?;
+//! lcd.show("21.5\x00C\n\x01 ok")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Text goes through a [`Charset`] for the controller's character ROM, so
+//! `°`, `µ` or `ä` print as the ROM's own glyph where it has one and as
+//! ASCII (`é` as `e`, `–` as `-`) where it doesn't. The default is the
+//! Japanese A00 ROM most modules have; [`Lcd::set_charset`] picks A02.
+//!
+//! The backlight switches with [`Lcd::backlight_on`] and
+//! [`Lcd::backlight_off`] (P3 on a backpack), and [`Lcd::flash`] blinks it
+//! in a [`Flash`] pattern, e.g. [`Flash::ERROR`] when something failed.
+//!
+//! Lines longer than the display scroll with [`Lcd::marquee`], or with a
+//! [`Marquee`] ticked from a loop that has other things to do.
+//!
+//! Pages that change while shown, like a clock or a sensor reading, are
+//! [`Template`] lines on a [`Screen`]: fields such as
+//! `{time:%H:%M}` or `{bme280.temperature:.1}` are filled from
+//! [`Sources`] on every draw, and only the characters that came out
+//! different are sent.
+//!
+//! Every instruction is followed by a wait for the controller, as
+//! [`LcdDelays`]: the datasheet's times with some headroom. On a backpack
+//! the busy flag can be read back, and [`Lcd::start_tuning`] uses it to
+//! shrink the waits to what this controller takes.
+//!
+//! [`scan_banner`] is what the demo puts on a display it found, so the
+//! address and bus speed can be checked on the display itself.
+
+pub mod charset;
+mod flash;
+pub mod glyph;
+mod interface;
+mod marquee;
+mod template;
+
+pub use charset::{Charset, Fallback, Rom};
+pub use flash::Flash;
+pub use glyph::Glyph;
+pub use interface::{Backpack, LcdInterface, ParallelLcd};
+pub use marquee::{Marquee, MARQUEE_PAUSE};
+pub use template::{LocalTime, Screen, Source, Sources, Template, Value, TIME};
+
+use crate::address::{Address, AddressedI2c};
+use crate::exit::TimedOut;
+use crate::throttle::DelayTuner;
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the demo leaves the [`scan_banner`] up.
+pub const BANNER_HOLD: Duration = Duration::from_secs(2);
+
+const CLEAR: u8 = 0x01;
+const HOME: u8 = 0x02;
+const ENTRY_LEFT: u8 = 0x06;
+const DISPLAY_ON: u8 = 0x0C;
+const FUNCTION_4BIT_2LINE: u8 = 0x28;
+const FUNCTION_8BIT_2LINE: u8 = 0x38;
+const SET_CGRAM: u8 = 0x40;
+const SET_DDRAM: u8 = 0x80;
+
+/// CGRAM slots; character codes 0-7 show them.
+pub const GLYPH_SLOTS: u8 = 8;
+
+/// DDRAM address of the first column of `row` on a display `cols` wide.
+/// Rows 3 and 4 carry on from the ends of rows 1 and 2, so they start at
+/// 0x10 and 0x50 on a 16x4 and at 0x14 and 0x54 on a 20x4.
+fn row_offset(cols: u8, row: u8) -> u8 {
+    match row {
+        0 => 0x00,
+        1 => 0x40,
+        2 => cols,
+        _ => 0x40 + cols,
+    }
+}
+
+/// Longest the busy flag may stay set after the wait.
+const BUSY_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// How long to wait after an instruction before sending the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LcdDelays {
+    /// Characters and most instructions; the datasheet says 37 µs.
+    pub command: Duration,
+    /// Clear and home; the datasheet says 1.52 ms.
+    pub clear: Duration,
+}
+
+impl Default for LcdDelays {
+    fn default() -> Self {
+        LcdDelays {
+            // Over I2C the write alone nearly covers the 37 µs
+            command: Duration::from_micros(50),
+            clear: Duration::from_millis(2),
+        }
+    }
+}
+
+/// The waits being tuned by [`Lcd::start_tuning`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LcdTuning {
+    pub command: DelayTuner,
+    pub clear: DelayTuner,
+}
+
+impl LcdTuning {
+    pub fn delays(&self) -> LcdDelays {
+        LcdDelays {
+            command: self.command.delay(),
+            clear: self.clear.delay(),
+        }
+    }
+}
+
+pub struct Lcd<B> {
+    bus: B,
+    cols: u8,
+    rows: u8,
+    backlight: bool,
+    charset: Charset,
+    /// What each visible cell holds, row by row; `None` where it isn't known.
+    shadow: Vec<Option<u8>>,
+    /// Where the next character lands; `None` when that isn't known, or
+    /// while the address counter is in CGRAM.
+    ddram: Option<u8>,
+    delays: LcdDelays,
+    tuning: Option<LcdTuning>,
+}
+
+impl<I2C: AddressedI2c> Lcd<Backpack<I2C>> {
+    /// Initialize a `cols` x `rows` display (16x2, 20x4, ...) on a backpack
+    /// at `address`, with the backlight on and the screen cleared.
+    pub fn new(i2c: I2C, address: Address, cols: u8, rows: u8) -> Result<Self, Box<dyn Error>> {
+        Lcd::with_interface(Backpack::new(i2c, address), cols, rows)
+    }
+
+    pub fn address(&self) -> Address {
+        self.bus.address()
+    }
+
+    pub fn release(self) -> I2C {
+        self.bus.release()
+    }
+}
+
+impl<B: LcdInterface> Lcd<B> {
+    /// Initialize a `cols` x `rows` display on `bus`, with the backlight on
+    /// and the screen cleared.
+    pub fn with_interface(bus: B, cols: u8, rows: u8) -> Result<Self, Box<dyn Error>> {
+        if !(1..=4).contains(&rows) || cols == 0 || cols > 40 {
+            return Err(format!("unsupported LCD size {}x{}", cols, rows).into());
+        }
+        if !matches!(bus.width(), 4 | 8) {
+            return Err(format!("an HD44780 takes 4 or 8 data lines, not {}", bus.width()).into());
+        }
+        let mut lcd = Lcd {
+            bus,
+            cols,
+            rows,
+            backlight: true,
+            charset: Charset::default(),
+            shadow: vec![None; cols as usize * rows as usize],
+            ddram: None,
+            delays: LcdDelays::default(),
+            tuning: None,
+        };
+        lcd.init()?;
+        Ok(lcd)
+    }
+
+    /// Run the datasheet's reset-by-instruction sequence. Safe to repeat,
+    /// e.g. after the display lost power.
+    pub fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        thread::sleep(Duration::from_millis(50));
+        // Whatever mode the controller is in, three 8-bit function sets get
+        // it to a known state. On four lines only D4-D7 are seen, which is
+        // all an 8-bit function set needs.
+        let eight_bit = self.bus.width() == 8;
+        let reset = if eight_bit { 0x30 } else { 0x03 };
+        for wait in [4500, 4500, 150] {
+            self.bus.latch(reset, false)?;
+            thread::sleep(Duration::from_micros(wait));
+        }
+        if eight_bit {
+            self.command(FUNCTION_8BIT_2LINE)?;
+        } else {
+            self.bus.latch(0x02, false)?;
+            self.command(FUNCTION_4BIT_2LINE)?;
+        }
+        self.command(DISPLAY_ON)?;
+        self.clear()?;
+        self.command(ENTRY_LEFT)
+    }
+
+    pub fn size(&self) -> (u8, u8) {
+        (self.cols, self.rows)
+    }
+
+    pub fn clear(&mut self) -> Result<(), Box<dyn Error>> {
+        self.command(CLEAR)
+    }
+
+    pub fn home(&mut self) -> Result<(), Box<dyn Error>> {
+        self.command(HOME)
+    }
+
+    /// The waits after each instruction, tuned ones while tuning.
+    pub fn delays(&self) -> LcdDelays {
+        self.tuning.as_ref().map_or(self.delays, LcdTuning::delays)
+    }
+
+    /// Wait `delays` after each instruction from now on, e.g. as tuned
+    /// on an earlier run.
+    pub fn set_delays(&mut self, delays: LcdDelays) {
+        self.delays = delays;
+    }
+
+    /// Shrink the waits to what the controller takes: after each one the
+    /// busy flag is read, and the wait follows how much longer it stayed
+    /// set. The waits start from the current [`delays`](Lcd::delays). Fails
+    /// on an interface that can't read the busy flag.
+    pub fn start_tuning(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.bus.busy()? {
+            None => return Err("this LCD interface can't read the busy flag, so its delays can't be tuned".into()),
+            Some(true) => {
+                // Idle since the last instruction: set now, it always reads set
+                thread::sleep(BUSY_TIMEOUT);
+                if self.bus.busy()? == Some(true) {
+                    return Err("the busy flag reads set on an idle display (is RW wired?), so its delays can't be tuned".into());
+                }
+            }
+            Some(false) => {}
+        }
+        let delays = self.delays();
+        self.tuning = Some(LcdTuning {
+            command: DelayTuner::new(delays.command, Duration::ZERO),
+            clear: DelayTuner::new(delays.clear, Duration::ZERO),
+        });
+        Ok(())
+    }
+
+    /// Stop tuning and keep the tuned waits; what they came to, if tuning.
+    pub fn finish_tuning(&mut self) -> Option<LcdTuning> {
+        let tuning = self.tuning.take()?;
+        self.delays = tuning.delays();
+        Some(tuning)
+    }
+
+    /// Move the cursor; `col` and `row` count from 0.
+    pub fn set_cursor(&mut self, col: u8, row: u8) -> Result<(), Box<dyn Error>> {
+        if col >= self.cols || row >= self.rows {
+            return Err(format!("cursor {},{} is off a {}x{} display", col, row, self.cols, self.rows).into());
+        }
+        self.command(SET_DDRAM | (row_offset(self.cols, row) + col))
+    }
+
+    /// Which ROM [`write_str`](Lcd::write_str) encodes for, and what it
+    /// prints for characters the ROM lacks.
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
+    }
+
+    pub fn charset(&self) -> Charset {
+        self.charset
+    }
+
+    /// Write at the cursor, encoded with the [`charset`](Lcd::charset).
+    /// `'\x00'` to `'\x07'` print the custom characters.
+    pub fn write_str(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let codes = self.charset.encode(text);
+        self.write_raw(&codes)
+    }
+
+    /// Write ROM codes as they are.
+    pub fn write_raw(&mut self, codes: &[u8]) -> Result<(), Box<dyn Error>> {
+        codes.iter().try_for_each(|&code| self.data(code))
+    }
+
+    /// Define custom character `slot` (0-7). Characters already on screen
+    /// from that slot change with it. Writing CGRAM moves the address
+    /// counter off the display, so this leaves the cursor at home.
+    pub fn create_char(&mut self, slot: u8, glyph: Glyph) -> Result<(), Box<dyn Error>> {
+        if slot >= GLYPH_SLOTS {
+            return Err(format!("custom character slot {} out of range (0-{})", slot, GLYPH_SLOTS - 1).into());
+        }
+        self.command(SET_CGRAM | (slot << 3))?;
+        for row in glyph {
+            self.byte(row & 0x1F, true)?;
+        }
+        self.command(SET_DDRAM)
+    }
+
+    /// Print custom character `slot` at the cursor.
+    pub fn write_glyph(&mut self, slot: u8) -> Result<(), Box<dyn Error>> {
+        if slot >= GLYPH_SLOTS {
+            return Err(format!("custom character slot {} out of range (0-{})", slot, GLYPH_SLOTS - 1).into());
+        }
+        self.data(slot)
+    }
+
+    /// Clear and show `text`, one line per row; lines are cut at the width.
+    pub fn show(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        self.clear()?;
+        for (row, line) in text.lines().take(self.rows as usize).enumerate() {
+            self.set_cursor(0, row as u8)?;
+            let mut codes = self.charset.encode(line);
+            codes.truncate(self.cols as usize);
+            self.write_raw(&codes)?;
+        }
+        Ok(())
+    }
+
+    /// Like [`show`](Lcd::show), but writing over the rows, padded with
+    /// spaces, instead of clearing first; no flicker when redrawing often.
+    /// Only the cells from the first to the last that differ from what's on
+    /// the display go out on each row, so an unchanged screen costs nothing.
+    pub fn update(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        self.redraw(text).map(drop)
+    }
+
+    /// [`update`](Lcd::update), returning how many characters it sent.
+    pub fn redraw(&mut self, text: &str) -> Result<usize, Box<dyn Error>> {
+        let cols = self.cols as usize;
+        let mut lines = text.lines();
+        let mut sent = 0;
+        for row in 0..self.rows {
+            let mut codes = self.charset.encode(lines.next().unwrap_or(""));
+            codes.resize(cols, b' ');
+            let shown = &self.shadow[row as usize * cols..][..cols];
+            let changed = |(col, &code): (usize, &u8)| shown[col] != Some(code);
+            let Some(first) = codes.iter().enumerate().position(changed) else {
+                continue;
+            };
+            let last = codes.iter().enumerate().rposition(changed).unwrap_or(first);
+            self.set_cursor(first as u8, row)?;
+            self.write_raw(&codes[first..=last])?;
+            sent += last + 1 - first;
+        }
+        Ok(sent)
+    }
+
+    /// Forget what's on the display, so the next [`update`](Lcd::update)
+    /// rewrites every cell: for when something else may have written to it,
+    /// or a glitch on the bus may have garbled it.
+    pub fn force_full_refresh(&mut self) {
+        self.shadow.fill(None);
+    }
+
+    pub fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.bus.set_backlight(on)?;
+        self.backlight = on;
+        Ok(())
+    }
+
+    pub fn backlight_on(&mut self) -> Result<(), Box<dyn Error>> {
+        self.set_backlight(true)
+    }
+
+    pub fn backlight_off(&mut self) -> Result<(), Box<dyn Error>> {
+        self.set_backlight(false)
+    }
+
+    pub fn backlight(&self) -> bool {
+        self.backlight
+    }
+
+    /// Blink the backlight in `pattern`, then leave it on or off as it was.
+    /// Blocks for [`Flash::duration`].
+    pub fn flash(&mut self, pattern: Flash) -> Result<(), Box<dyn Error>> {
+        let was = self.backlight;
+        pattern.play(|on| self.bus.set_backlight(on))?;
+        self.set_backlight(was)
+    }
+
+    pub fn interface(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    fn command(&mut self, command: u8) -> Result<(), Box<dyn Error>> {
+        // Unknown until it's gone through: a failed write may still have landed
+        let before = self.ddram.take();
+        self.byte(command, false)?;
+        self.ddram = before;
+        if command & SET_DDRAM != 0 {
+            self.ddram = Some(command & !SET_DDRAM);
+        } else if command & SET_CGRAM != 0 {
+            self.ddram = None;
+        } else if command == CLEAR {
+            self.shadow.fill(Some(b' '));
+            self.ddram = Some(0);
+        } else if command & !1 == HOME {
+            self.ddram = Some(0);
+        }
+        Ok(())
+    }
+
+    fn data(&mut self, byte: u8) -> Result<(), Box<dyn Error>> {
+        let Some(address) = self.ddram.take() else {
+            // Could be anywhere on the display
+            self.shadow.fill(None);
+            return self.byte(byte, true);
+        };
+        let cell = self.cell(address);
+        if let Some(cell) = cell {
+            self.shadow[cell] = None;
+        }
+        self.byte(byte, true)?;
+        if let Some(cell) = cell {
+            self.shadow[cell] = Some(byte);
+        }
+        // In two-line mode each line's 40 addresses run on into the other's
+        self.ddram = Some(match address {
+            0x27 => 0x40,
+            0x67 => 0x00,
+            address => address + 1,
+        });
+        Ok(())
+    }
+
+    /// The shadow index of the visible cell at DDRAM `address`, if any.
+    fn cell(&self, address: u8) -> Option<usize> {
+        (0..self.rows).find_map(|row| {
+            let col = address.checked_sub(row_offset(self.cols, row)).filter(|&col| col < self.cols)?;
+            Some(row as usize * self.cols as usize + col as usize)
+        })
+    }
+
+    fn byte(&mut self, byte: u8, data: bool) -> Result<(), Box<dyn Error>> {
+        if self.bus.width() == 8 {
+            self.bus.latch(byte, data)?;
+        } else {
+            self.bus.latch(byte >> 4, data)?;
+            self.bus.latch(byte & 0x0F, data)?;
+        }
+        self.settle(!data && (byte == CLEAR || byte & !1 == HOME))
+    }
+
+    /// Wait out an instruction; with tuning, then until the busy flag
+    /// clears.
+    fn settle(&mut self, slow: bool) -> Result<(), Box<dyn Error>> {
+        let Some(tuning) = &mut self.tuning else {
+            thread::sleep(if slow { self.delays.clear } else { self.delays.command });
+            return Ok(());
+        };
+        let tuner = if slow { &mut tuning.clear } else { &mut tuning.command };
+        thread::sleep(tuner.delay());
+        let start = Instant::now();
+        let mut busy = false;
+        while self.bus.busy()? == Some(true) {
+            busy = true;
+            if start.elapsed() > BUSY_TIMEOUT {
+                return Err(TimedOut {
+                    waiting_for: "the HD44780 busy flag to clear".to_string(),
+                    after: BUSY_TIMEOUT,
+                }
+                .into());
+            }
+        }
+        tuner.feedback(if busy { start.elapsed() } else { Duration::ZERO });
+        Ok(())
+    }
+}
+
+/// Two lines, fitting a 16x2: the backpack chip and address, then the bus
+/// speed (`?` when the kernel doesn't say).
+pub fn scan_banner(address: Address, speed: Option<u32>) -> String {
+    let chip = match address {
+        Address::SevenBit(0x20..=0x27) => "PCF8574",
+        Address::SevenBit(0x38..=0x3F) => "PCF8574A",
+        _ => "LCD",
+    };
+    let speed = match speed {
+        Some(hz) if hz % 1000 == 0 => format!("{}kHz", hz / 1000),
+        Some(hz) => format!("{}Hz", hz),
+        None => "?".to_string(),
+    };
+    format!("{} {}\nI2C {} OK", chip, address, speed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::StubBus;
+
+    const LCD: Address = Address::SevenBit(0x27);
+
+    /// Each latch as the backpack writes it: E high, then E low.
+    fn latch(bits: u8) -> Vec<u8> {
+        vec![bits | 0x04, bits]
+    }
+
+    #[test]
+    fn four_bit_init_over_a_backpack() {
+        let bus = StubBus::new();
+        bus.add_port(LCD, 0);
+        Lcd::new(bus.clone(), LCD, 16, 2).unwrap();
+        let expected = [
+            // three 8-bit function sets, then 4-bit mode
+            0x38, 0x38, 0x38, 0x28,
+            // function set: 4-bit, two lines
+            0x28, 0x88,
+            // display on, cursor off
+            0x08, 0xC8,
+            // clear
+            0x08, 0x18,
+            // entry mode: left to right
+            0x08, 0x68,
+        ]
+        .map(latch);
+        assert_eq!(bus.writes(LCD), expected);
+    }
+
+    #[test]
+    fn text_and_cursor_go_high_nibble_first() {
+        let bus = StubBus::new();
+        bus.add_port(LCD, 0);
+        let mut lcd = Lcd::new(bus.clone(), LCD, 16, 2).unwrap();
+        bus.clear_log();
+        lcd.set_cursor(3, 1).unwrap();
+        lcd.write_str("A").unwrap();
+        // DDRAM 0x43, then 'A' (0x41) with RS high
+        assert_eq!(bus.writes(LCD), [0xC8, 0x38, 0x49, 0x19].map(latch));
+        assert!(lcd.set_cursor(16, 0).is_err());
+    }
+
+    #[test]
+    fn lower_rows_follow_the_width() {
+        let bus = StubBus::new();
+        bus.add_port(LCD, 0);
+        let mut lcd = Lcd::new(bus.clone(), LCD, 16, 4).unwrap();
+        bus.clear_log();
+        lcd.set_cursor(0, 2).unwrap();
+        lcd.set_cursor(0, 3).unwrap();
+        // DDRAM 0x10 and 0x50
+        assert_eq!(bus.writes(LCD), [0x98, 0x08, 0xD8, 0x08].map(latch));
+        let mut lcd = Lcd::new(bus.clone(), LCD, 20, 4).unwrap();
+        bus.clear_log();
+        lcd.set_cursor(0, 3).unwrap();
+        // DDRAM 0x54
+        assert_eq!(bus.writes(LCD), [0xD8, 0x48].map(latch));
+    }
+
+    #[test]
+    fn update_sends_only_changed_cells() {
+        let bus = StubBus::new();
+        bus.add_port(LCD, 0);
+        let mut lcd = Lcd::new(bus.clone(), LCD, 16, 2).unwrap();
+        assert_eq!(lcd.redraw("21.5C\nok").unwrap(), 7);
+        assert_eq!(lcd.redraw("21.5C\nok").unwrap(), 0);
+        bus.clear_log();
+        assert_eq!(lcd.redraw("21.6C\nok").unwrap(), 1);
+        // DDRAM 0x03, then '6'
+        assert_eq!(bus.writes(LCD), [0x88, 0x38, 0x39, 0x69].map(latch));
+        // writes at the cursor are tracked too, so the next update puts it back
+        lcd.set_cursor(0, 1).unwrap();
+        lcd.write_raw(b"X").unwrap();
+        assert_eq!(lcd.redraw("21.6C\nok").unwrap(), 1);
+        lcd.force_full_refresh();
+        assert_eq!(lcd.redraw("21.6C\nok").unwrap(), 32);
+    }
+
+    #[test]
+    fn missing_backpack_fails_init() {
+        assert!(Lcd::new(StubBus::new(), LCD, 16, 2).is_err());
+    }
+
+    #[test]
+    fn banner_names_the_chip() {
+        assert_eq!(scan_banner(LCD, Some(100_000)), "PCF8574 0x27\nI2C 100kHz OK");
+        assert_eq!(scan_banner(Address::SevenBit(0x3F), None), "PCF8574A 0x3F\nI2C ? OK");
+    }
+}
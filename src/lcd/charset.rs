@@ -0,0 +1,164 @@
+//! Unicode to HD44780 character ROM codes.
+//!
+//! Below 0x80 both common ROMs are ASCII, except that A00 has `¥` and
+//! arrows where `\`, `~` and DEL would be. Above that they part ways: A00
+//! (the Japanese ROM, on nearly every cheap module) has half-width
+//! katakana and a few Greek and math symbols; A02 (European) follows
+//! Latin-1 closely. Whatever the ROM lacks goes to the [`Fallback`].
+
+use crate::term;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which mask ROM the controller has; the table printed in its datasheet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rom {
+    #[default]
+    A00,
+    A02,
+}
+
+impl FromStr for Rom {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "a00" => Ok(Rom::A00),
+            "a02" => Ok(Rom::A02),
+            _ => Err(format!("unknown character ROM '{}' (expected a00 or a02)", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for Rom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Rom::A00 => "A00",
+            Rom::A02 => "A02",
+        })
+    }
+}
+
+/// What to print for a character the ROM doesn't have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// This ROM code instead, e.g. `b'?'`.
+    Replace(u8),
+    /// The nearest ASCII: `é` as `e`, `ß` as `ss`, `–` as `-`; the code
+    /// given for what has no such spelling.
+    Transliterate(u8),
+}
+
+impl Default for Fallback {
+    fn default() -> Self {
+        Fallback::Transliterate(b'?')
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Charset {
+    pub rom: Rom,
+    pub fallback: Fallback,
+}
+
+impl Charset {
+    pub fn new(rom: Rom, fallback: Fallback) -> Self {
+        Charset { rom, fallback }
+    }
+
+    /// The ROM code showing `ch`, if the ROM has one. `'\x00'` to `'\x07'`
+    /// are the custom characters.
+    pub fn code(&self, ch: char) -> Option<u8> {
+        match ch {
+            '\x00'..='\x07' => Some(ch as u8),
+            '\u{a0}' => Some(b' '),
+            _ => match self.rom {
+                Rom::A00 => a00(ch),
+                Rom::A02 => a02(ch),
+            },
+        }
+    }
+
+    /// ROM codes for `text`. Transliteration can take more than one cell
+    /// per character, so count cells in the result, not in `text`.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let mut codes = Vec::with_capacity(text.len());
+        for ch in text.chars() {
+            if let Some(code) = self.code(ch) {
+                codes.push(code);
+                continue;
+            }
+            match self.fallback {
+                Fallback::Replace(code) => codes.push(code),
+                Fallback::Transliterate(code) => match transliterate(ch) {
+                    Some(ascii) => codes.extend(ascii.chars().map(|c| self.code(c).unwrap_or(code))),
+                    None => codes.push(code),
+                },
+            }
+        }
+        codes
+    }
+}
+
+/// Printable ASCII, minus what the ROM puts elsewhere.
+fn ascii(ch: char, except: &[char]) -> Option<u8> {
+    (ch.is_ascii() && !ch.is_ascii_control() && !except.contains(&ch)).then_some(ch as u8)
+}
+
+fn a00(ch: char) -> Option<u8> {
+    if let Some(code) = ascii(ch, &['\\', '~']) {
+        return Some(code);
+    }
+    // Half-width katakana sit in the same order as the ROM's 0xA1-0xDF
+    if ('\u{ff61}'..='\u{ff9f}').contains(&ch) {
+        return Some((ch as u32 - 0xff61 + 0xa1) as u8);
+    }
+    Some(match ch {
+        '¥' => 0x5C,
+        '→' => 0x7E,
+        '←' => 0x7F,
+        '°' => 0xDF,
+        'α' => 0xE0,
+        'ä' => 0xE1,
+        'ß' | 'β' => 0xE2,
+        'ε' => 0xE3,
+        'µ' | 'μ' => 0xE4,
+        'σ' => 0xE5,
+        'ρ' => 0xE6,
+        '√' => 0xE8,
+        '¢' => 0xEC,
+        'ñ' => 0xEE,
+        'ö' => 0xEF,
+        'θ' => 0xF2,
+        '∞' => 0xF3,
+        'Ω' => 0xF4,
+        'ü' => 0xF5,
+        'Σ' => 0xF6,
+        'π' => 0xF7,
+        '÷' => 0xFD,
+        '█' => 0xFF,
+        _ => return None,
+    })
+}
+
+fn a02(ch: char) -> Option<u8> {
+    if let Some(code) = ascii(ch, &[]) {
+        return Some(code);
+    }
+    // The upper half is Latin-1, symbols and letters alike
+    match ch as u32 {
+        code @ 0xA1..=0xFF => Some(code as u8),
+        _ => None,
+    }
+}
+
+/// An ASCII spelling of `ch`, for ROMs without it. The A00 ROM also
+/// lacks `\\` and `~`.
+fn transliterate(ch: char) -> Option<&'static str> {
+    match ch {
+        '\\' => Some("/"),
+        '~' => Some("-"),
+        _ => term::transliterate(ch),
+    }
+}
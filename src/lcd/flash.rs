@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+/// A run of backlight blinks: dark for `off`, then lit for `on`, `times`
+/// times over.
+///
+/// Starting dark means every blink shows, whether the backlight was on or
+/// off; [`Lcd::flash`](super::Lcd::flash) puts it back as it was after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flash {
+    pub times: u32,
+    pub on: Duration,
+    pub off: Duration,
+}
+
+impl Flash {
+    /// Three quick blinks, for a failed operation.
+    pub const ERROR: Flash = Flash::new(3, Duration::from_millis(150), Duration::from_millis(150));
+
+    /// One slow blink, to draw the eye to new text.
+    pub const ATTENTION: Flash = Flash::new(1, Duration::from_millis(600), Duration::from_millis(400));
+
+    pub const fn new(times: u32, on: Duration, off: Duration) -> Self {
+        Flash { times, on, off }
+    }
+
+    /// How long the whole run takes.
+    pub fn duration(&self) -> Duration {
+        (self.on + self.off) * self.times
+    }
+
+    /// Blink through `set`, which switches the backlight. Ends lit.
+    pub fn play(&self, mut set: impl FnMut(bool) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+        for _ in 0..self.times {
+            set(false)?;
+            thread::sleep(self.off);
+            set(true)?;
+            thread::sleep(self.on);
+        }
+        Ok(())
+    }
+}
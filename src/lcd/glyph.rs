@@ -0,0 +1,86 @@
+//! 5x8 custom characters for [`Lcd::create_char`](super::Lcd::create_char),
+//! drawn as text: `#` (or `X`, `*`) is a lit dot, `.` or a space is dark.
+//!
+//! ```no_run
+//! use rpi_peripherals::lcd::glyph;
+//!
+//! let smiley = glyph::parse(
+//!     "
+//!     .....
+//!     .#.#.
+//!     .#.#.
+//!     .....
+//!     #...#
+//!     .###.
+//!     .....
+//!     .....
+//!     ",
+//! )?;
+//! assert_eq!(smiley[1], 0b01010);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::error::Error;
+
+/// One row per byte, top first, dots in the low five bits (MSB on the left).
+pub type Glyph = [u8; 8];
+
+pub const WIDTH: usize = 5;
+pub const HEIGHT: usize = 8;
+
+pub const HEART: Glyph = [0b00000, 0b01010, 0b11111, 0b11111, 0b11111, 0b01110, 0b00100, 0b00000];
+
+/// The ROM's 0xDF is close, but sits low on most A00 panels.
+pub const DEGREE: Glyph = [0b01100, 0b10010, 0b10010, 0b01100, 0b00000, 0b00000, 0b00000, 0b00000];
+
+pub const BELL: Glyph = [0b00100, 0b01110, 0b01110, 0b01110, 0b11111, 0b00000, 0b00100, 0b00000];
+
+pub const ARROW_UP: Glyph = [0b00100, 0b01110, 0b10101, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000];
+
+pub const ARROW_DOWN: Glyph = [0b00100, 0b00100, 0b00100, 0b00100, 0b10101, 0b01110, 0b00100, 0b00000];
+
+/// Parse eight rows of five dots. Blank lines and indentation around the
+/// art are ignored, so it can sit in an indented string literal.
+pub fn parse(art: &str) -> Result<Glyph, Box<dyn Error>> {
+    let rows: Vec<&str> = art.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    if rows.len() != HEIGHT {
+        return Err(format!("a glyph has {} rows, got {}", HEIGHT, rows.len()).into());
+    }
+    let mut glyph = [0; HEIGHT];
+    for (n, row) in rows.iter().enumerate() {
+        if row.chars().count() != WIDTH {
+            return Err(format!("glyph row {} '{}' should be {} dots wide", n + 1, row, WIDTH).into());
+        }
+        for ch in row.chars() {
+            let lit = match ch {
+                '#' | 'X' | '*' => 1,
+                '.' | ' ' | '_' => 0,
+                other => return Err(format!("glyph row {}: '{}' is neither a dot nor a gap", n + 1, other).into()),
+            };
+            glyph[n] = (glyph[n] << 1) | lit;
+        }
+    }
+    Ok(glyph)
+}
+
+/// A bar-graph cell with the leftmost `columns` (0-5) lit. Load `bar(1)` to
+/// `bar(5)` into five slots and a bar can grow one dot at a time.
+pub fn bar(columns: u8) -> Glyph {
+    let columns = columns.min(WIDTH as u8);
+    let row = (0b11111 << (WIDTH as u8 - columns)) & 0b11111;
+    [row; HEIGHT]
+}
+
+/// The opposite of [`parse`], for previews and error messages.
+pub fn render(glyph: &Glyph) -> String {
+    glyph
+        .iter()
+        .map(|row| {
+            (0..WIDTH)
+                .rev()
+                .map(|bit| if row & (1 << bit) != 0 { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,241 @@
+use crate::address::{Address, AddressedI2c};
+use crate::expander::{Pcf8574, Sn74hc595};
+use crate::parallel::{ParallelBus, ParallelConfig};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+use rppal::gpio::Gpio;
+use std::error::Error;
+
+/// How bytes reach the controller: the data lines (four or eight), RS and E.
+pub trait LcdInterface {
+    /// Data lines wired: 4 (D4-D7) or 8 (D0-D7).
+    fn width(&self) -> u8;
+
+    /// Put the low [`width`](LcdInterface::width) bits of `bits` on the data
+    /// lines, RS high for data and low for commands, and pulse E.
+    fn latch(&mut self, bits: u8, data: bool) -> Result<(), Box<dyn Error>>;
+
+    fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>>;
+
+    /// Whether the controller is still busy with the last instruction, or
+    /// `None` if RW isn't wired and the busy flag can't be read.
+    fn busy(&mut self) -> Result<Option<bool>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+const RS: u8 = 0x01;
+const RW: u8 = 0x02;
+const ENABLE: u8 = 0x04;
+const BACKLIGHT: u8 = 0x08;
+
+/// The PCF8574 backpack. The common ones wire the expander as RS=P0,
+/// RW=P1, E=P2, backlight=P3 and D4-D7=P4-P7, so the controller runs in
+/// 4-bit mode.
+pub struct Backpack<I2C> {
+    port: Pcf8574<I2C>,
+    backlight: bool,
+}
+
+impl<I2C: AddressedI2c> Backpack<I2C> {
+    /// Backlight on, the state [`Lcd::new`](super::Lcd::new) starts in.
+    pub fn new(i2c: I2C, address: Address) -> Self {
+        Backpack {
+            port: Pcf8574::new(i2c, address),
+            backlight: true,
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.port.address()
+    }
+
+    /// Read the expander back after every write, so a latch that didn't
+    /// take fails the command instead of garbling the display.
+    pub fn verify_writes(&mut self, on: bool) {
+        self.port.verify_writes(on);
+    }
+
+    /// The expander, e.g. for its mismatch count.
+    pub fn port(&self) -> &Pcf8574<I2C> {
+        &self.port
+    }
+
+    /// The bus underneath, e.g. to recover it.
+    pub fn i2c_mut(&mut self) -> &mut I2C {
+        self.port.i2c_mut()
+    }
+
+    pub fn release(self) -> I2C {
+        self.port.release()
+    }
+
+    fn backlight_bit(&self) -> u8 {
+        if self.backlight {
+            BACKLIGHT
+        } else {
+            0
+        }
+    }
+}
+
+impl<I2C: AddressedI2c> LcdInterface for Backpack<I2C> {
+    fn width(&self) -> u8 {
+        4
+    }
+
+    /// Both E edges go in one write: at I2C speeds each byte lasts far
+    /// longer than the 450 ns E pulse needs.
+    fn latch(&mut self, bits: u8, data: bool) -> Result<(), Box<dyn Error>> {
+        let bits = (bits << 4) | if data { RS } else { 0 } | self.backlight_bit();
+        self.port.write_sequence(&[bits | ENABLE, bits])
+    }
+
+    fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.backlight = on;
+        self.port.write(self.backlight_bit())
+    }
+
+    /// RW high with D4-D7 released to the controller, then two E pulses:
+    /// the busy flag is D7 of the first nibble, read while E is high.
+    fn busy(&mut self) -> Result<Option<bool>, Box<dyn Error>> {
+        let read = 0xF0 | RW | self.backlight_bit();
+        self.port.set_inputs(0xF0);
+        let flag = self
+            .port
+            .write_sequence(&[read, read | ENABLE])
+            .and_then(|_| self.port.read())
+            .and_then(|high| self.port.write_sequence(&[read, read | ENABLE, read]).map(|_| high & 0x80 != 0));
+        self.port.set_inputs(0);
+        self.port.write(self.backlight_bit())?;
+        flag.map(Some)
+    }
+}
+
+/// A 74HC595 wired as the PCF8574 backpack is, chip 0 taking the
+/// backpack's byte. Each E edge is its own latch.
+impl<SPI> LcdInterface for Sn74hc595<SPI>
+where
+    SPI: SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    fn width(&self) -> u8 {
+        4
+    }
+
+    fn latch(&mut self, bits: u8, data: bool) -> Result<(), Box<dyn Error>> {
+        let backlight = self.chip(0) & BACKLIGHT;
+        let bits = (bits << 4) | if data { RS } else { 0 } | backlight;
+        self.write_chip(0, bits | ENABLE)?;
+        self.write_chip(0, bits)
+    }
+
+    fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.write_chip(0, if on { BACKLIGHT } else { 0 })
+    }
+}
+
+/// An HD44780 on GPIO: RS, E, and four or eight data lines. RW must be
+/// tied to ground; the driver never reads the busy flag.
+pub struct ParallelLcd<P> {
+    bus: ParallelBus<P>,
+    rs: P,
+    backlight: Option<P>,
+}
+
+impl ParallelLcd<rppal::gpio::OutputPin> {
+    /// BCM pins for RS, E and the data lines, D4-D7 or D0-D7 in order,
+    /// plus an optional backlight switch (active high).
+    pub fn from_gpio(rs: u8, enable: u8, data: &[u8], backlight: Option<u8>) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let open = |pin: u8| -> Result<rppal::gpio::OutputPin, Box<dyn Error>> {
+            Ok(gpio.get(pin).map_err(|e| format!("LCD GPIO {}: {}", pin, e))?.into_output_low())
+        };
+        let rs = open(rs)?;
+        let backlight = backlight.map(open).transpose()?;
+        let bus = ParallelBus::from_gpio(data, enable, ParallelConfig::default())?;
+        ParallelLcd::new(bus, rs, backlight)
+    }
+}
+
+impl<P: OutputPin> ParallelLcd<P>
+where
+    P::Error: Error + 'static,
+{
+    pub fn new(bus: ParallelBus<P>, rs: P, backlight: Option<P>) -> Result<Self, Box<dyn Error>> {
+        if !matches!(bus.width(), 4 | 8) {
+            return Err(format!("an HD44780 takes 4 or 8 data lines, not {}", bus.width()).into());
+        }
+        if bus.config().strobe_active_low {
+            return Err("the HD44780 latches on a high E pulse".into());
+        }
+        let mut lcd = ParallelLcd { bus, rs, backlight };
+        lcd.set_backlight(true)?;
+        Ok(lcd)
+    }
+
+    pub fn release(self) -> (ParallelBus<P>, P, Option<P>) {
+        (self.bus, self.rs, self.backlight)
+    }
+}
+
+impl<P: OutputPin> LcdInterface for ParallelLcd<P>
+where
+    P::Error: Error + 'static,
+{
+    fn width(&self) -> u8 {
+        self.bus.width()
+    }
+
+    fn latch(&mut self, bits: u8, data: bool) -> Result<(), Box<dyn Error>> {
+        if data {
+            self.rs.set_high()?;
+        } else {
+            self.rs.set_low()?;
+        }
+        self.bus.write(bits)
+    }
+
+    fn set_backlight(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        match &mut self.backlight {
+            Some(pin) if on => pin.set_high()?,
+            Some(pin) => pin.set_low()?,
+            None if on => {}
+            None => return Err("this LCD has no backlight pin".into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::StubBus;
+
+    const LCD: Address = Address::SevenBit(0x27);
+
+    fn backpack() -> (StubBus, Backpack<StubBus>) {
+        let bus = StubBus::new();
+        bus.add_port(LCD, 0);
+        (bus.clone(), Backpack::new(bus, LCD))
+    }
+
+    #[test]
+    fn nibble_goes_on_p4_to_p7_with_an_e_pulse() {
+        let (bus, mut backpack) = backpack();
+        backpack.latch(0x4, true).unwrap();
+        backpack.latch(0x1, false).unwrap();
+        // D7-D4 | BL | E | RW | RS
+        assert_eq!(bus.writes(LCD), [vec![0x4D, 0x49], vec![0x1C, 0x18]]);
+    }
+
+    #[test]
+    fn backlight_rides_along_with_every_latch() {
+        let (bus, mut backpack) = backpack();
+        backpack.set_backlight(false).unwrap();
+        backpack.latch(0xF, true).unwrap();
+        backpack.set_backlight(true).unwrap();
+        assert_eq!(bus.writes(LCD), [vec![0x00], vec![0xF5, 0xF1], vec![0x08]]);
+        assert_eq!(bus.port(LCD), Some(0x08));
+    }
+}
@@ -0,0 +1,133 @@
+use super::Lcd;
+use super::LcdInterface;
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the text holds still at each end, so both can be read.
+pub const MARQUEE_PAUSE: Duration = Duration::from_secs(1);
+
+/// One row of text too long for the display, scrolled a column at a time.
+///
+/// Each pass holds at the start, shifts left one column per `step` until
+/// the end is in view, holds again, then jumps back. Text that fits is
+/// shown once and left alone. Drive it with [`tick`](Marquee::tick) from a
+/// loop that has other work, or hand it to [`Lcd::play`] to block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marquee {
+    text: String,
+    row: u8,
+    step: Duration,
+    pause: Duration,
+    offset: usize,
+    next: Instant,
+    passes: u64,
+    drawn: bool,
+}
+
+impl Marquee {
+    pub fn new(text: &str, row: u8, step: Duration) -> Self {
+        Marquee {
+            text: text.replace('\n', " "),
+            row,
+            step,
+            pause: MARQUEE_PAUSE,
+            offset: 0,
+            next: Instant::now(),
+            passes: 0,
+            drawn: false,
+        }
+    }
+
+    /// Hold at each end for `pause`; zero scrolls straight through.
+    pub fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    pub fn row(&self) -> u8 {
+        self.row
+    }
+
+    /// Completed passes, counting one as soon as the end is in view.
+    pub fn passes(&self) -> u64 {
+        self.passes
+    }
+
+    /// When the next [`tick`](Marquee::tick) will redraw.
+    pub fn next_due(&self) -> Instant {
+        self.next
+    }
+
+    /// Start over from the beginning, now.
+    pub fn restart(&mut self) {
+        self.offset = 0;
+        self.next = Instant::now();
+        self.drawn = false;
+    }
+
+    /// Redraw the row if the next shift is due; returns whether it did.
+    /// Never sleeps.
+    pub fn tick<B: LcdInterface>(&mut self, lcd: &mut Lcd<B>) -> Result<bool, Box<dyn Error>> {
+        let now = Instant::now();
+        let width = lcd.size().0 as usize;
+        // Scroll by display cells: transliteration can make one char several
+        let codes = lcd.charset().encode(&self.text);
+        let last = codes.len().saturating_sub(width);
+        if now < self.next || (last == 0 && self.drawn) {
+            return Ok(false);
+        }
+        self.offset = self.offset.min(last);
+
+        let mut window: Vec<u8> = codes.iter().skip(self.offset).take(width).copied().collect();
+        window.resize(width, b' ');
+        lcd.set_cursor(0, self.row)?;
+        lcd.write_raw(&window)?;
+        self.drawn = true;
+
+        let hold = if self.offset == 0 || self.offset == last { self.pause.max(self.step) } else { self.step };
+        // Keep to the schedule so the shifts stay even; after a long stall, resync.
+        self.next += hold;
+        if self.next < now {
+            self.next = now + hold;
+        }
+        if self.offset == last {
+            self.passes += 1;
+            self.offset = 0;
+        } else {
+            self.offset += 1;
+        }
+        Ok(true)
+    }
+}
+
+impl<B: LcdInterface> Lcd<B> {
+    /// Scroll `text` across `row` once, shifting a column every `step`,
+    /// with the default pause at each end. Blocks until the pass is done.
+    pub fn marquee(&mut self, text: &str, row: u8, step: Duration) -> Result<(), Box<dyn Error>> {
+        self.play(&mut Marquee::new(text, row, step))
+    }
+
+    /// Run `marquee` for one pass, including the pause at its end.
+    pub fn play(&mut self, marquee: &mut Marquee) -> Result<(), Box<dyn Error>> {
+        if marquee.row() >= self.size().1 {
+            return Err(format!("row {} is off a {}-row display", marquee.row(), self.size().1).into());
+        }
+        let target = marquee.passes() + 1;
+        while marquee.passes() < target {
+            sleep_until(marquee.next_due());
+            if !marquee.tick(self)? && marquee.passes() < target {
+                // Text fits: drawn once, nothing to scroll
+                break;
+            }
+        }
+        sleep_until(marquee.next_due());
+        Ok(())
+    }
+}
+
+fn sleep_until(deadline: Instant) {
+    if let Some(rest) = deadline.checked_duration_since(Instant::now()) {
+        thread::sleep(rest);
+    }
+}
@@ -0,0 +1,307 @@
+use super::{Lcd, LcdInterface};
+use crate::history::History;
+use crate::localtime;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::time::SystemTime;
+
+/// The name [`LocalTime`] answers to.
+pub const TIME: &str = "time";
+
+/// How `{time}` shows without a format of its own.
+const DEFAULT_TIME: &CStr = c"%H:%M";
+
+/// What a [`Source`] gives for a name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    /// Shown in local time, through a `strftime` format.
+    Time(SystemTime),
+}
+
+/// Answers some of the names in a [`Template`].
+pub trait Source: Send {
+    /// Take a fresh reading; called once per render, before any `value`.
+    fn refresh(&mut self) {}
+
+    /// The value of `name`, or `None` if it isn't one of this source's.
+    fn value(&self, name: &str) -> Option<Value>;
+}
+
+/// The system clock as `{time}`, which the RTC keeps right across reboots.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalTime;
+
+impl Source for LocalTime {
+    fn value(&self, name: &str) -> Option<Value> {
+        (name == TIME).then(|| Value::Time(SystemTime::now()))
+    }
+}
+
+/// The newest sample of each measurement, e.g. `{bme280.temperature:.1}`.
+impl Source for History {
+    fn value(&self, name: &str) -> Option<Value> {
+        self.latest(name).map(Value::Number)
+    }
+}
+
+/// One name bound to a closure.
+struct Binding<F> {
+    name: String,
+    read: F,
+}
+
+impl<F: Fn() -> Option<Value> + Send> Source for Binding<F> {
+    fn value(&self, name: &str) -> Option<Value> {
+        if name == self.name {
+            (self.read)()
+        } else {
+            None
+        }
+    }
+}
+
+/// The sources a [`Screen`] fills its templates from. The first one added
+/// that knows a name gives its value.
+#[derive(Default)]
+pub struct Sources {
+    sources: Vec<Box<dyn Source>>,
+}
+
+impl Sources {
+    pub fn new() -> Self {
+        Sources::default()
+    }
+
+    pub fn add(&mut self, source: impl Source + 'static) -> &mut Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Answer `name` with whatever `read` returns at render time.
+    pub fn bind<F>(&mut self, name: &str, read: F) -> &mut Self
+    where
+        F: Fn() -> Option<Value> + Send + 'static,
+    {
+        self.add(Binding {
+            name: name.to_string(),
+            read,
+        })
+    }
+
+    pub fn refresh(&mut self) {
+        self.sources.iter_mut().for_each(|source| source.refresh());
+    }
+
+    pub fn value(&self, name: &str) -> Option<Value> {
+        self.sources.iter().find_map(|source| source.value(name))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Format {
+    width: Option<usize>,
+    precision: Option<usize>,
+    time: Option<CString>,
+}
+
+impl Format {
+    /// `%H:%M` and the like for times, else `[width][.precision]`.
+    fn parse(spec: &str) -> Result<Self, Box<dyn Error>> {
+        if spec.starts_with('%') {
+            let time = CString::new(spec).map_err(|_| "time format can't contain NUL")?;
+            return Ok(Format {
+                time: Some(time),
+                ..Format::default()
+            });
+        }
+        let bad = || format!("bad format '{}' (a width, .N decimals, or a %H:%M time)", spec);
+        let (width, precision) = match spec.split_once('.') {
+            Some((width, precision)) => (width, Some(precision.parse().map_err(|_| bad())?)),
+            None => (spec, None),
+        };
+        let width = match width {
+            "" => None,
+            width => Some(width.parse().map_err(|_| bad())?),
+        };
+        Ok(Format { width, precision, time: None })
+    }
+
+    fn apply(&self, value: Option<Value>) -> String {
+        let width = self.width.unwrap_or(0);
+        match value {
+            Some(Value::Number(n)) => match self.precision {
+                Some(precision) => format!("{:>width$.precision$}", n),
+                None => format!("{:>width$}", n),
+            },
+            Some(Value::Text(text)) => match self.precision {
+                Some(precision) => format!("{:width$.precision$}", text),
+                None => format!("{:width$}", text),
+            },
+            Some(Value::Time(time)) => {
+                let text = localtime::strftime(time, self.time.as_deref().unwrap_or(DEFAULT_TIME)).unwrap_or_else(|| "--".into());
+                format!("{:width$}", text)
+            }
+            None => format!("{:>width$}", "--"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field { name: String, format: Format },
+}
+
+/// One line of text with `{name}` or `{name:format}` fields, such as
+/// `"{time:%H:%M} {bme280.temperature:5.1}°C"`. Numbers take a width and
+/// decimals and line up on the right; text takes a width and a length
+/// limit; `{time}` takes a `strftime` format. `{{` and `}}` are braces. A
+/// name no source knows shows as `--`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(at) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..at]);
+            let brace = &rest[at..];
+            if brace.starts_with("{{") || brace.starts_with("}}") {
+                literal.push_str(&brace[..1]);
+                rest = &brace[2..];
+                continue;
+            }
+            if let Some(after) = brace.strip_prefix('}') {
+                literal.push('}');
+                rest = after;
+                continue;
+            }
+            let close = brace.find('}').ok_or_else(|| format!("unclosed '{{' in '{}'", text))?;
+            let field = &brace[1..close];
+            let (name, spec) = field.split_once(':').unwrap_or((field, ""));
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(format!("empty field name in '{}'", text).into());
+            }
+            if !literal.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut literal)));
+            }
+            let format = Format::parse(spec).map_err(|e| format!("{} in '{}'", e, text))?;
+            parts.push(Part::Field {
+                name: name.to_string(),
+                format,
+            });
+            rest = &brace[close + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    /// The field names, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Field { name, .. } => Some(name.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    pub fn render(&self, sources: &Sources) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field { name, format } => format.apply(sources.value(name)),
+            })
+            .collect()
+    }
+}
+
+/// A page of [`Template`]s, one per row, kept on an LCD. Each
+/// [`draw`](Screen::draw) renders them again and hands them to
+/// [`Lcd::redraw`], which only sends the cells that came out different, so
+/// a clock ticking over costs one cursor move and a few characters rather
+/// than the whole display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    lines: Vec<Template>,
+}
+
+impl Screen {
+    pub fn new<S: AsRef<str>>(lines: &[S]) -> Result<Self, Box<dyn Error>> {
+        Ok(Screen {
+            lines: lines.iter().map(|line| Template::parse(line.as_ref())).collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn lines(&self) -> &[Template] {
+        &self.lines
+    }
+
+    /// Every line filled in, without touching a display.
+    pub fn render(&self, sources: &mut Sources) -> Vec<String> {
+        sources.refresh();
+        self.lines.iter().map(|line| line.render(sources)).collect()
+    }
+
+    /// Bring the display up to date; returns how many characters went out.
+    pub fn draw<B: LcdInterface>(&self, lcd: &mut Lcd<B>, sources: &mut Sources) -> Result<usize, Box<dyn Error>> {
+        lcd.redraw(&self.render(sources).join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn fields_take_formats() {
+        let template = Template::parse("{time:%Y} {temp:5.1}°C {{{name:.3}}} {missing}").unwrap();
+        assert_eq!(template.names().collect::<Vec<_>>(), ["time", "temp", "name", "missing"]);
+        let mut sources = Sources::new();
+        sources
+            .bind("time", || Some(Value::Time(UNIX_EPOCH + Duration::from_secs(1_000_000_000))))
+            .bind("temp", || Some(Value::Number(21.46)))
+            .bind("name", || Some(Value::Text("kitchen".into())));
+        assert_eq!(template.render(&sources), "2001  21.5°C {kit} --");
+        assert!(Template::parse("{temp").is_err());
+        assert!(Template::parse("{temp:x}").is_err());
+        assert!(Template::parse("{}").is_err());
+    }
+
+    #[test]
+    fn redraws_only_what_changed() {
+        use crate::address::Address;
+        use crate::bus::StubBus;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        let stub = StubBus::new();
+        let address = Address::SevenBit(0x27);
+        stub.add_port(address, 0);
+        let mut lcd = Lcd::new(stub.clone(), address, 16, 2).unwrap();
+        let count = Arc::new(AtomicU32::new(8));
+        let mut sources = Sources::new();
+        let counter = Arc::clone(&count);
+        sources.bind("n", move || Some(Value::Number(f64::from(counter.load(Ordering::Relaxed)))));
+        let screen = Screen::new(&["Count {n:3}", "static"]).unwrap();
+        // the cleared display is already spaces: only the text goes out
+        assert_eq!(screen.draw(&mut lcd, &mut sources).unwrap(), 15);
+        assert_eq!(screen.draw(&mut lcd, &mut sources).unwrap(), 0);
+        count.store(9, Ordering::Relaxed);
+        assert_eq!(screen.draw(&mut lcd, &mut sources).unwrap(), 1);
+        count.store(10, Ordering::Relaxed);
+        assert_eq!(screen.draw(&mut lcd, &mut sources).unwrap(), 2);
+        lcd.force_full_refresh();
+        assert_eq!(screen.draw(&mut lcd, &mut sources).unwrap(), 32);
+    }
+}
@@ -0,0 +1,387 @@
+//! WS2812 ("NeoPixel") LED strips driven from the SPI MOSI pin.
+//!
+//! The strip's 800 kHz single-wire protocol is built out of SPI: at
+//! 2.4 MHz each data bit becomes three SPI bits, `110` for a one and `100`
+//! for a zero, so the DMA engine times every pulse and a busy scheduler
+//! can't stretch one. Only MOSI (GPIO 10 on SPI0) is used; wire it to DIN,
+//! through a 3.3 V to 5 V level shifter if the strip is picky.
+//!
+//! Colours go through a [`Ws2812`]'s frame buffer, are gamma corrected and
+//! scaled by the brightness, and reach the strip on [`Ws2812::show`]:
+//!
+//! ```no_run
+//! use rpi_peripherals::leds::{Rgb, Strip, Ws2812};
+//! use std::time::Duration;
+//!
+//! let mut strip = Ws2812::from_spi(0, 30)?;
+//! strip.set_brightness(64);
+//! strip.set(0, Rgb::new(255, 0, 0))?;
+//! strip.show()?;
+//! strip.wipe(Rgb::new(0, 0, 255), Duration::from_millis(20))?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`Apa102`] strips (DotStar, SK9822) take the same calls through the
+//! [`Strip`] trait, and [`reactive`] animates either from a microphone.
+//!
+//! On a Pi 3 or older the SPI clock follows the core clock, which throttles
+//! under load; pin it with `core_freq=250` in `config.txt`, or the colours
+//! flicker.
+
+mod apa102;
+#[cfg(feature = "audio")]
+pub mod reactive;
+
+pub use apa102::{Apa102, APA102_CLOCK};
+
+use embedded_hal::spi::SpiBus;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Three SPI bits per WS2812 bit at 800 kHz.
+pub const SPI_CLOCK: u32 = 2_400_000;
+
+/// 300 µs of low MOSI at [`SPI_CLOCK`], enough for the WS2812B (older parts
+/// latch after 50 µs).
+const RESET_BYTES: usize = 90;
+
+/// Length of an animation frame.
+const FRAME: Duration = Duration::from_millis(20);
+
+/// Gamma of the correction curve; LEDs look linear to the eye at about
+/// 2.8.
+const GAMMA: f64 = 2.8;
+
+/// Where spidev says how long one transfer may be.
+const SPIDEV_BUFSIZ: &str = "/sys/module/spidev/parameters/bufsiz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const OFF: Rgb = Rgb::new(0, 0, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// Around the colour wheel: red at 0, green at 85, blue at 170.
+    pub fn wheel(position: u8) -> Self {
+        let p = position;
+        match p {
+            0..=84 => Rgb::new(255 - p * 3, p * 3, 0),
+            85..=169 => Rgb::new(0, 255 - (p - 85) * 3, (p - 85) * 3),
+            _ => Rgb::new((p - 170) * 3, 0, 255 - (p - 170) * 3),
+        }
+    }
+
+    /// Every channel times `factor`, 0.0 to 1.0.
+    pub fn scaled(self, factor: f32) -> Self {
+        let scale = |c: u8| (f32::from(c) * factor.clamp(0.0, 1.0)).round() as u8;
+        Rgb::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+
+    /// `t` of the way from this colour to `other`.
+    pub fn blend(self, other: Rgb, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * t).round() as u8;
+        Rgb::new(mix(self.r, other.r), mix(self.g, other.g), mix(self.b, other.b))
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.r, self.g, self.b)
+    }
+}
+
+/// `255,0,0` or `#ff0000`.
+impl FromStr for Rgb {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix('#') {
+            let value = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .ok_or_else(|| format!("'{}' is not a #rrggbb colour", s))?;
+            return Ok(Rgb::new((value >> 16) as u8, (value >> 8) as u8, value as u8));
+        }
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        match parts.as_slice() {
+            [r, g, b] => {
+                let channel = |c: &str| c.parse::<u8>().map_err(|_| format!("'{}' is not 0-255 in colour '{}'", c, s));
+                Ok(Rgb::new(channel(r)?, channel(g)?, channel(b)?))
+            }
+            _ => Err(format!("'{}' is not a colour; use R,G,B or #rrggbb", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The order a strip's chips take the channels in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorOrder {
+    /// WS2812 and WS2812B.
+    #[default]
+    Grb,
+    /// WS2811 and some clones.
+    Rgb,
+}
+
+/// A strip with a frame buffer, whichever chips it is made of.
+pub trait Strip {
+    fn pixels(&self) -> &[Rgb];
+
+    fn pixels_mut(&mut self) -> &mut [Rgb];
+
+    /// Scale every channel by `brightness`/255 on the way out. A 5 m strip
+    /// of 60/m at full white draws 18 A, so pick this with the supply in
+    /// mind.
+    fn set_brightness(&mut self, brightness: u8);
+
+    /// Without gamma correction, values go out as they are.
+    fn set_gamma(&mut self, enabled: bool);
+
+    /// Send the frame buffer to the strip.
+    fn show(&mut self) -> Result<(), Box<dyn Error>>;
+
+    fn len(&self) -> usize {
+        self.pixels().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pixels().is_empty()
+    }
+
+    /// Set one LED in the frame buffer; shown on the next [`Strip::show`].
+    fn set(&mut self, index: usize, color: Rgb) -> Result<(), Box<dyn Error>> {
+        let count = self.len();
+        let pixel = self
+            .pixels_mut()
+            .get_mut(index)
+            .ok_or_else(|| format!("LED {} is past the end of a {}-LED strip", index, count))?;
+        *pixel = color;
+        Ok(())
+    }
+
+    fn fill(&mut self, color: Rgb) {
+        self.pixels_mut().fill(color);
+    }
+
+    fn clear(&mut self) {
+        self.fill(Rgb::OFF);
+    }
+
+    /// Light the LEDs one after another in `color`, `step` apart, leaving
+    /// the rest as they were.
+    fn wipe(&mut self, color: Rgb, step: Duration) -> Result<(), Box<dyn Error>> {
+        for index in 0..self.len() {
+            self.pixels_mut()[index] = color;
+            self.show()?;
+            thread::sleep(step);
+        }
+        Ok(())
+    }
+
+    /// Run a rainbow along the strip, once round the wheel every `period`,
+    /// until `stop` is set.
+    fn rainbow(&mut self, period: Duration, stop: &AtomicBool) -> Result<(), Box<dyn Error>> {
+        let frames = (period.as_secs_f64() / FRAME.as_secs_f64()).max(1.0);
+        let mut frame = 0.0;
+        while !stop.load(Ordering::Relaxed) {
+            let offset = frame / frames * 256.0;
+            let count = self.len();
+            for (index, pixel) in self.pixels_mut().iter_mut().enumerate() {
+                let position = (index as f64 * 256.0 / count as f64 + offset) as usize % 256;
+                *pixel = Rgb::wheel(position as u8);
+            }
+            self.show()?;
+            frame = (frame + 1.0) % frames;
+            thread::sleep(FRAME);
+        }
+        Ok(())
+    }
+}
+
+/// Gamma and brightness, applied to each channel on the way out.
+#[derive(Debug, Clone)]
+struct Correction {
+    gamma: Option<[u8; 256]>,
+    brightness: u8,
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Correction {
+            gamma: Some(gamma_table()),
+            brightness: 255,
+        }
+    }
+}
+
+impl Correction {
+    fn set_gamma(&mut self, enabled: bool) {
+        self.gamma = enabled.then(gamma_table);
+    }
+
+    fn apply(&self, value: u8) -> u8 {
+        let value = match &self.gamma {
+            Some(table) => table[usize::from(value)],
+            None => value,
+        };
+        ((u16::from(value) * u16::from(self.brightness) + 127) / 255) as u8
+    }
+}
+
+pub struct Ws2812<SPI> {
+    spi: SPI,
+    pixels: Vec<Rgb>,
+    correction: Correction,
+    order: ColorOrder,
+}
+
+impl Ws2812<rppal::spi::Spi> {
+    /// A strip of `count` LEDs on SPI bus `bus` (0 is GPIO 10), at
+    /// [`SPI_CLOCK`]. The chip select is driven but not needed.
+    pub fn from_spi(bus: u8, count: usize) -> Result<Self, Box<dyn Error>> {
+        Ws2812::new(open_spi(bus, SPI_CLOCK, frame_len(count))?, count)
+    }
+}
+
+impl<SPI> Ws2812<SPI>
+where
+    SPI: SpiBus,
+    SPI::Error: Error + 'static,
+{
+    /// `spi` must already run at [`SPI_CLOCK`], mode 0. Starts all off,
+    /// at full brightness with gamma correction.
+    pub fn new(spi: SPI, count: usize) -> Result<Self, Box<dyn Error>> {
+        if count == 0 {
+            return Err("a strip needs at least one LED".into());
+        }
+        Ok(Ws2812 {
+            spi,
+            pixels: vec![Rgb::OFF; count],
+            correction: Correction::default(),
+            order: ColorOrder::default(),
+        })
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.correction.brightness
+    }
+
+    pub fn set_order(&mut self, order: ColorOrder) {
+        self.order = order;
+    }
+
+    /// The SPI bytes [`Strip::show`] sends, reset gap included.
+    pub fn frame(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(frame_len(self.pixels.len()));
+        for pixel in &self.pixels {
+            let (first, second) = match self.order {
+                ColorOrder::Grb => (pixel.g, pixel.r),
+                ColorOrder::Rgb => (pixel.r, pixel.g),
+            };
+            for channel in [first, second, pixel.b] {
+                frame.extend_from_slice(&encode(self.correction.apply(channel)));
+            }
+        }
+        frame.resize(frame_len(self.pixels.len()), 0);
+        frame
+    }
+
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+impl<SPI> Strip for Ws2812<SPI>
+where
+    SPI: SpiBus,
+    SPI::Error: Error + 'static,
+{
+    fn pixels(&self) -> &[Rgb] {
+        &self.pixels
+    }
+
+    fn pixels_mut(&mut self) -> &mut [Rgb] {
+        &mut self.pixels
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.correction.brightness = brightness;
+    }
+
+    fn set_gamma(&mut self, enabled: bool) {
+        self.correction.set_gamma(enabled);
+    }
+
+    fn show(&mut self) -> Result<(), Box<dyn Error>> {
+        let frame = self.frame();
+        self.spi.write(&frame)?;
+        self.spi.flush()?;
+        Ok(())
+    }
+}
+
+/// SPI bus `bus` at `clock`, mode 0, checked for taking `frame` bytes in one
+/// transfer.
+fn open_spi(bus: u8, clock: u32, frame: usize) -> Result<rppal::spi::Spi, Box<dyn Error>> {
+    // Longer than spidev takes in one go, the frame would be split and the
+    // gap between the halves latch a half-written strip
+    if let Some(limit) = fs::read_to_string(SPIDEV_BUFSIZ).ok().and_then(|s| s.trim().parse::<usize>().ok()) {
+        if frame > limit {
+            return Err(format!(
+                "a frame is {} bytes, spidev takes {}; add spidev.bufsiz={} to cmdline.txt",
+                frame,
+                limit,
+                frame.next_power_of_two()
+            )
+            .into());
+        }
+    }
+    crate::spi::open(bus, 0, clock)
+}
+
+/// Bytes per frame for `count` LEDs.
+fn frame_len(count: usize) -> usize {
+    count * 9 + RESET_BYTES
+}
+
+/// One colour byte as three SPI bytes, most significant bit first.
+fn encode(value: u8) -> [u8; 3] {
+    let mut bits: u32 = 0;
+    for n in (0..8).rev() {
+        bits = bits << 3 | if value >> n & 1 == 1 { 0b110 } else { 0b100 };
+    }
+    [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8]
+}
+
+fn gamma_table() -> [u8; 256] {
+    let mut table = [0; 256];
+    for (value, out) in table.iter_mut().enumerate() {
+        *out = ((value as f64 / 255.0).powf(GAMMA) * 255.0).round() as u8;
+    }
+    table
+}
@@ -0,0 +1,93 @@
+use super::{open_spi, Correction, Rgb, Strip};
+use embedded_hal::spi::SpiBus;
+use std::error::Error;
+
+/// Well inside what the chips take, and slow enough for a metre or two of
+/// wire to the strip.
+pub const APA102_CLOCK: u32 = 4_000_000;
+
+/// APA102 (DotStar) and SK9822 strips: clocked on SCLK with data on MOSI, so
+/// nothing depends on timing and any SPI clock up to a few MHz works.
+pub struct Apa102<SPI> {
+    spi: SPI,
+    pixels: Vec<Rgb>,
+    correction: Correction,
+}
+
+impl Apa102<rppal::spi::Spi> {
+    /// A strip of `count` LEDs on SPI bus `bus` (0 is MOSI on GPIO 10 and
+    /// SCLK on GPIO 11), at [`APA102_CLOCK`].
+    pub fn from_spi(bus: u8, count: usize) -> Result<Self, Box<dyn Error>> {
+        Apa102::new(open_spi(bus, APA102_CLOCK, frame_len(count))?, count)
+    }
+}
+
+impl<SPI> Apa102<SPI>
+where
+    SPI: SpiBus,
+    SPI::Error: Error + 'static,
+{
+    pub fn new(spi: SPI, count: usize) -> Result<Self, Box<dyn Error>> {
+        if count == 0 {
+            return Err("a strip needs at least one LED".into());
+        }
+        Ok(Apa102 {
+            spi,
+            pixels: vec![Rgb::OFF; count],
+            correction: Correction::default(),
+        })
+    }
+
+    /// The SPI bytes [`Strip::show`] sends: a zero start frame, one frame
+    /// per LED, then the extra clocks the end of the strip needs to latch.
+    pub fn frame(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(frame_len(self.pixels.len()));
+        frame.extend_from_slice(&[0; 4]);
+        for pixel in &self.pixels {
+            // Brightness is scaled into the colour so the 5-bit global
+            // field stays at full and its slow PWM out of the picture
+            let [r, g, b] = [pixel.r, pixel.g, pixel.b].map(|c| self.correction.apply(c));
+            frame.extend_from_slice(&[0xE0 | 31, b, g, r]);
+        }
+        frame.resize(frame_len(self.pixels.len()), 0);
+        frame
+    }
+
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+impl<SPI> Strip for Apa102<SPI>
+where
+    SPI: SpiBus,
+    SPI::Error: Error + 'static,
+{
+    fn pixels(&self) -> &[Rgb] {
+        &self.pixels
+    }
+
+    fn pixels_mut(&mut self) -> &mut [Rgb] {
+        &mut self.pixels
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.correction.brightness = brightness;
+    }
+
+    fn set_gamma(&mut self, enabled: bool) {
+        self.correction.set_gamma(enabled);
+    }
+
+    fn show(&mut self) -> Result<(), Box<dyn Error>> {
+        let frame = self.frame();
+        self.spi.write(&frame)?;
+        self.spi.flush()?;
+        Ok(())
+    }
+}
+
+/// Start frame, 4 bytes per LED, and half a clock per LED at the end.
+fn frame_len(count: usize) -> usize {
+    4 + count * 4 + count / 16 + 1
+}
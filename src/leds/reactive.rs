@@ -0,0 +1,247 @@
+use super::{Rgb, Strip};
+use crate::audio::{Analysis, Analyzer, Capture, FFT_SIZE};
+use crate::parse::serde_helpers;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Most bands a spectrum is split into, however long the strip.
+const MAX_BANDS: usize = 32;
+
+/// The `[reactive]` config section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReactiveConfig {
+    /// ALSA capture device, as `arecord -D` takes it.
+    #[serde(default = "default_device")]
+    pub device: String,
+    #[serde(default = "default_rate")]
+    pub rate: u32,
+    #[serde(default)]
+    pub effect: Effect,
+    /// Colours from quiet (or the start of the strip) to loud (or its end),
+    /// blended in between.
+    #[serde(default = "default_palette")]
+    pub palette: Vec<Rgb>,
+    /// How fast the LEDs follow a rise in level.
+    #[serde(default = "default_attack", deserialize_with = "serde_helpers::duration")]
+    pub attack: Duration,
+    /// How slowly they fall back.
+    #[serde(default = "default_decay", deserialize_with = "serde_helpers::duration")]
+    pub decay: Duration,
+    /// Added to the input level, for quiet microphones.
+    #[serde(default)]
+    pub gain_db: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    /// A bar graph of the level from the start of the strip.
+    Level,
+    /// Frequency bands along the strip, bass first, each lit by its energy.
+    #[default]
+    Spectrum,
+    /// The whole strip in one colour, brighter and further along the
+    /// palette the louder it gets.
+    Pulse,
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Effect::Level => "level",
+            Effect::Spectrum => "spectrum",
+            Effect::Pulse => "pulse",
+        })
+    }
+}
+
+fn default_device() -> String {
+    "default".into()
+}
+
+fn default_rate() -> u32 {
+    crate::audio::DEFAULT_RATE
+}
+
+fn default_palette() -> Vec<Rgb> {
+    vec![Rgb::new(0, 0, 255), Rgb::new(0, 255, 0), Rgb::new(255, 0, 0)]
+}
+
+fn default_attack() -> Duration {
+    Duration::from_millis(20)
+}
+
+fn default_decay() -> Duration {
+    Duration::from_millis(300)
+}
+
+impl Default for ReactiveConfig {
+    fn default() -> Self {
+        ReactiveConfig {
+            device: default_device(),
+            rate: default_rate(),
+            effect: Effect::default(),
+            palette: default_palette(),
+            attack: default_attack(),
+            decay: default_decay(),
+            gain_db: 0.0,
+        }
+    }
+}
+
+impl ReactiveConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.palette.is_empty() {
+            return Err("reactive.palette needs at least one colour".into());
+        }
+        if self.rate < 8000 {
+            return Err(format!("reactive.rate of {} Hz is too low; use 8000 or more", self.rate).into());
+        }
+        if self.decay.is_zero() {
+            return Err("reactive.decay must be greater than zero".into());
+        }
+        Ok(())
+    }
+}
+
+/// Colours blended evenly from one end to the other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette(Vec<Rgb>);
+
+impl Palette {
+    pub fn new(colors: Vec<Rgb>) -> Result<Self, Box<dyn Error>> {
+        if colors.is_empty() {
+            return Err("a palette needs at least one colour".into());
+        }
+        Ok(Palette(colors))
+    }
+
+    /// The colour `t` of the way along, 0.0 to 1.0.
+    pub fn at(&self, t: f32) -> Rgb {
+        let span = (self.0.len() - 1) as f32;
+        let position = t.clamp(0.0, 1.0) * span;
+        let index = (position as usize).min(self.0.len() - 1);
+        let next = (index + 1).min(self.0.len() - 1);
+        self.0[index].blend(self.0[next], position - index as f32)
+    }
+}
+
+/// Follows each value up with one time constant and down with another,
+/// so peaks show at once and fade instead of flickering.
+#[derive(Debug, Clone)]
+pub struct Smoother {
+    attack: Duration,
+    decay: Duration,
+    values: Vec<f32>,
+}
+
+impl Smoother {
+    pub fn new(attack: Duration, decay: Duration) -> Self {
+        Smoother {
+            attack,
+            decay,
+            values: Vec::new(),
+        }
+    }
+
+    /// Move towards `targets`, `elapsed` since the last update.
+    pub fn update(&mut self, targets: &[f32], elapsed: Duration) -> &[f32] {
+        self.values.resize(targets.len(), 0.0);
+        for (value, &target) in self.values.iter_mut().zip(targets) {
+            let tau = if target > *value { self.attack } else { self.decay };
+            let step = if tau.is_zero() {
+                1.0
+            } else {
+                1.0 - (-elapsed.as_secs_f32() / tau.as_secs_f32()).exp()
+            };
+            *value += (target - *value) * step;
+        }
+        &self.values
+    }
+}
+
+/// Turns each [`Analysis`] into a frame for the strip.
+pub struct Reactive {
+    effect: Effect,
+    palette: Palette,
+    smoother: Smoother,
+    gain_db: f32,
+}
+
+impl Reactive {
+    pub fn new(config: &ReactiveConfig) -> Result<Self, Box<dyn Error>> {
+        config.validate()?;
+        Ok(Reactive {
+            effect: config.effect,
+            palette: Palette::new(config.palette.clone())?,
+            smoother: Smoother::new(config.attack, config.decay),
+            gain_db: config.gain_db,
+        })
+    }
+
+    /// Bands to analyse for a strip of `leds`.
+    pub fn bands_for(&self, leds: usize) -> usize {
+        match self.effect {
+            Effect::Spectrum => leds.clamp(1, MAX_BANDS),
+            Effect::Level | Effect::Pulse => 1,
+        }
+    }
+
+    /// Draw `analysis` onto `pixels`, `elapsed` after the last frame.
+    pub fn render(&mut self, analysis: &Analysis, elapsed: Duration, pixels: &mut [Rgb]) {
+        // Analysis values are dB over a FLOOR_DB span, so gain is a shift
+        let shift = self.gain_db / crate::audio::FLOOR_DB;
+        let boost = |v: f32| (v + shift).clamp(0.0, 1.0);
+        let count = pixels.len();
+        match self.effect {
+            Effect::Spectrum => {
+                let targets: Vec<f32> = analysis.bands.iter().map(|&b| boost(b)).collect();
+                let bands = self.smoother.update(&targets, elapsed);
+                for (index, pixel) in pixels.iter_mut().enumerate() {
+                    let along = index as f32 / (count.max(2) - 1) as f32;
+                    let band = bands.get(index * bands.len() / count).copied().unwrap_or(0.0);
+                    *pixel = self.palette.at(along).scaled(band);
+                }
+            }
+            Effect::Level => {
+                let level = self.smoother.update(&[boost(analysis.level)], elapsed)[0];
+                let lit = level * count as f32;
+                for (index, pixel) in pixels.iter_mut().enumerate() {
+                    let along = index as f32 / (count.max(2) - 1) as f32;
+                    // The last LED of the bar fades in rather than jumping
+                    let share = (lit - index as f32).clamp(0.0, 1.0);
+                    *pixel = self.palette.at(along).scaled(share);
+                }
+            }
+            Effect::Pulse => {
+                let level = self.smoother.update(&[boost(analysis.level)], elapsed)[0];
+                pixels.fill(self.palette.at(level).scaled(level));
+            }
+        }
+    }
+}
+
+/// Record from the configured device and animate `strip` until `stop` is
+/// set. Each frame analyses the last [`FFT_SIZE`] samples, moving on by
+/// half of that, so a 44.1 kHz input gives about 86 frames a second.
+pub fn run(strip: &mut dyn Strip, config: &ReactiveConfig, stop: &AtomicBool) -> Result<(), Box<dyn Error>> {
+    let mut reactive = Reactive::new(config)?;
+    let analyzer = Analyzer::new(config.rate, FFT_SIZE, reactive.bands_for(strip.len()))?;
+    let mut capture = Capture::open(&config.device, config.rate)?;
+    let hop = FFT_SIZE / 2;
+    let mut block = vec![0.0; FFT_SIZE];
+    let mut last = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        block.copy_within(hop.., 0);
+        capture.read(&mut block[FFT_SIZE - hop..])?;
+        let analysis = analyzer.analyze(&block);
+        reactive.render(&analysis, last.elapsed(), strip.pixels_mut());
+        last = Instant::now();
+        strip.show()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! Drivers and bus helpers for the peripherals wired to the Raspberry Pi.
+//!
+//! Drivers are written against the `embedded-hal` 1.0 traits, so they work on
+//! top of `rppal` on the Pi and on anything else that implements the traits.
+//!
+//! The bus, expander, config and alerting core always builds. The rest is
+//! behind Cargo features, all on by default through `cli`; to blink a
+//! PCF8574 from your own program, depend on the crate with
+//! `default-features = false` and add only what you use:
+//!
+//! | feature   | brings in |
+//! |-----------|-----------|
+//! | `lcd`     | HD44780 character LCDs, menus, factory fixtures |
+//! | `oled`    | sparklines and frame buffers for pixel displays |
+//! | `sensors` | INA219/INA226, APDS-9960, HC-SR04, energy totals, data logging |
+//! | `spi`     | MCP3008, MAX7219, 74HC595 over spidev, chip-select management |
+//! | `uart`    | serial ports, GPS, RS-485 |
+//! | `can`     | MCP2515 CAN (with `spi`) |
+//! | `server`  | the HTTP API over HTTP or HTTPS, and the remote bus proxy (with `lcd` and `fleet`) |
+//! | `i2cdev`  | a bus backend on the kernel's i2c-dev ioctls, for non-Pi boards |
+//! | `mqtt`    | publishing readings, alerts and rule events to a broker |
+//! | `fleet`   | polling other Pis' `/health` and `/readings` |
+//! | `leds`    | WS2812 and APA102 strips, status LEDs for alerts (with `spi`) |
+//! | `printer` | ESC/POS thermal receipt printers |
+//! | `modbus`  | a Modbus RTU master |
+//! | `onewire` | 1-Wire devices through the kernel's w1 drivers |
+//! | `motor`   | servos and steppers |
+//! | `audio`   | microphone capture, sound level and, with `leds`, audio-reactive strips |
+//! | `cli`     | the `rpi_peripherals` command, with all of the above |
+//! | `async`   | tokio wrappers for the bus |
+//! | `ffi`     | `extern "C"` functions for linking from C (with `lcd`) |
+
+pub mod address;
+#[cfg(feature = "spi")]
+pub mod adc;
+pub mod alert;
+#[cfg(feature = "async")]
+pub mod asynch;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod auth;
+pub mod bench;
+pub mod board;
+pub mod bus;
+#[cfg(feature = "can")]
+pub mod can;
+pub mod charlieplex;
+pub mod clock;
+pub mod config;
+pub mod crc;
+#[cfg(feature = "sensors")]
+pub mod datalog;
+pub mod detect;
+pub mod display;
+pub mod drivers;
+pub mod dump;
+pub mod eeprom;
+#[cfg(feature = "sensors")]
+pub mod energy;
+pub mod exit;
+pub mod expander;
+pub mod expr;
+#[cfg(feature = "lcd")]
+pub mod factory;
+pub mod fault;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod flash;
+#[cfg(feature = "fleet")]
+pub mod fleet;
+#[cfg(feature = "uart")]
+pub mod gps;
+pub mod history;
+pub mod i2c;
+pub mod identify;
+pub mod input;
+pub mod inventory;
+#[cfg(feature = "lcd")]
+pub mod lcd;
+#[cfg(feature = "leds")]
+pub mod leds;
+pub mod localtime;
+pub mod measure;
+pub mod melody;
+#[cfg(feature = "lcd")]
+pub mod menu;
+pub mod metrics;
+#[cfg(feature = "modbus")]
+pub mod modbus;
+pub mod monitor;
+pub mod morse;
+#[cfg(feature = "motor")]
+pub mod motor;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod mux;
+pub mod notify;
+#[cfg(feature = "onewire")]
+pub mod onewire;
+pub mod parallel;
+pub mod parse;
+pub mod pattern;
+pub mod peripherals;
+pub mod pins;
+#[cfg(all(feature = "lcd", feature = "sensors"))]
+pub mod plan;
+pub mod power;
+pub mod preflight;
+pub mod preset;
+#[cfg(feature = "printer")]
+pub mod printer;
+pub mod progress;
+pub mod pwm;
+pub mod regmap;
+#[cfg(feature = "server")]
+pub mod remote;
+#[cfg(feature = "cli")]
+pub mod repl;
+#[cfg(feature = "uart")]
+pub mod rs485;
+pub mod rng;
+pub mod rules;
+pub mod scan;
+pub mod script;
+pub mod segment;
+#[cfg(all(feature = "lcd", feature = "sensors"))]
+pub mod selftest;
+#[cfg(feature = "sensors")]
+pub mod sensors;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session;
+pub mod shutdown;
+pub mod smbus;
+pub mod soak;
+pub mod softi2c;
+#[cfg(feature = "oled")]
+pub mod sparkline;
+pub mod spi;
+pub mod startup;
+pub mod stress;
+pub mod stretch;
+pub mod sysinfo;
+pub mod systemd;
+pub mod term;
+pub mod throttle;
+pub mod timing;
+pub mod totals;
+pub mod trace;
+pub mod transmitter;
+pub mod trigger;
+#[cfg(feature = "uart")]
+pub mod uart;
+pub mod units;
+pub mod watchdog;
+pub mod watches;
@@ -0,0 +1,94 @@
+//! The local time of day and date, for clocks, daily rotation and quiet
+//! hours. Everything that needs the time zone comes through here, so the
+//! one call into libc's `localtime_r` is in one place.
+
+use std::ffi::CStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A moment broken down in the local time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1-12.
+    pub month: u8,
+    /// 1-31.
+    pub day: u8,
+    /// 0-365, 0 being 1 January.
+    pub day_of_year: u16,
+    /// 0-23.
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl LocalTime {
+    /// `2024-06-01`.
+    pub fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// Minutes since midnight.
+    pub fn minute_of_day(&self) -> u16 {
+        u16::from(self.hour) * 60 + u16::from(self.minute)
+    }
+}
+
+/// `time` in the local time zone, or `None` if it is out of libc's range.
+pub fn at(time: SystemTime) -> Option<LocalTime> {
+    let tm = broken_down(time)?;
+    Some(LocalTime {
+        year: tm.tm_year + 1900,
+        month: (tm.tm_mon + 1) as u8,
+        day: tm.tm_mday as u8,
+        day_of_year: tm.tm_yday as u16,
+        hour: tm.tm_hour as u8,
+        minute: tm.tm_min as u8,
+        second: tm.tm_sec as u8,
+    })
+}
+
+pub fn now() -> Option<LocalTime> {
+    at(SystemTime::now())
+}
+
+/// `time` in local time through a `strftime` format, up to 64 bytes.
+pub fn strftime(time: SystemTime, format: &CStr) -> Option<String> {
+    let tm = broken_down(time)?;
+    let mut out = [0u8; 64];
+    // SAFETY: strftime writes at most out.len() bytes of out, reads the
+    // NUL-terminated format and a tm filled in by localtime_r.
+    let len = unsafe { libc::strftime(out.as_mut_ptr().cast(), out.len(), format.as_ptr(), &tm) };
+    Some(String::from_utf8_lossy(&out[..len]).into_owned())
+}
+
+fn broken_down(time: SystemTime) -> Option<libc::tm> {
+    let unix = libc::time_t::try_from(time.duration_since(UNIX_EPOCH).ok()?.as_secs()).ok()?;
+    // SAFETY: tm is plain data, so all zeroes is a valid one, and
+    // localtime_r only writes the tm it is handed; unlike localtime it keeps
+    // no shared state, so threads can call it at once.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&unix, &mut tm).is_null() {
+            return None;
+        }
+        Some(tm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn breaks_down_a_time() {
+        // noon UTC on 1 June is 1 or 2 June in every zone
+        let time = UNIX_EPOCH + Duration::from_secs(1_717_243_200);
+        let local = at(time).unwrap();
+        assert_eq!((local.year, local.month), (2024, 6));
+        assert!((1..=2).contains(&local.day));
+        assert_eq!(local.minute_of_day(), u16::from(local.hour) * 60 + u16::from(local.minute));
+        assert_eq!(strftime(time, c"%Y").as_deref(), Some("2024"));
+        assert_eq!(local.date().len(), 10);
+    }
+}
@@ -1,176 +1,6361 @@
-use rppal::i2c::I2c as RppalI2c;
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use embedded_hal::i2c::I2c;
+use rpi_peripherals::address::{Address, AddressedI2c};
+use rpi_peripherals::adc::{self, Input, Mcp3008};
+use rpi_peripherals::alert::{Alert, Alerter, GpioBuzzer, Severity};
+use rpi_peripherals::audio::spl::{self, Microphone, SplMeter};
+use rpi_peripherals::auth::{self, TokenStore};
+use rpi_peripherals::bench::{self, BenchConfig, BenchMode};
+use rpi_peripherals::board::{Board, Soc};
+use rpi_peripherals::can::{BitTiming, CanFrame, Filter, Mcp2515, OperatingMode};
+use rpi_peripherals::bus::{self, BackendKind, BusControl, BusManager, DryRun, LinuxI2c, StubBus};
+use rpi_peripherals::config::{Config, ConfigWatcher, DeviceConfig, PageConfig, RailConfig};
+use rpi_peripherals::datalog::{self, DataLogger, Format, Rotation, Sample};
+use rpi_peripherals::detect::Detection;
+use rpi_peripherals::display::{font, Max7219, Tm1637};
+use rpi_peripherals::drivers;
+use rpi_peripherals::dump::{self, DumpConfig};
+use rpi_peripherals::eeprom::{self, EepromConfig};
+use rpi_peripherals::energy::{EnergyMonitor, Tariff};
+use rpi_peripherals::exit::{self, DeviceNotFound, ExitStatus, Interrupted, TimedOut, VerificationFailed};
+use rpi_peripherals::factory::{Fixture, Step, TestPlan};
+use rpi_peripherals::fault::{FaultConfig, FaultInjector};
+use rpi_peripherals::flash::{self, FlashConfig, FlashFailed};
+use rpi_peripherals::fleet::{self, Fleet};
+use rpi_peripherals::gps::{Fix, GpsReader};
+use rpi_peripherals::history::History;
+use rpi_peripherals::i2c::{Analyzer, BscSlave, FrameReceiver, SlaveEmulator, SlaveMap, TimedEvent, Transactions};
+use rpi_peripherals::identify;
+use rpi_peripherals::input::{self, HidInput, IrReceiver};
+use rpi_peripherals::inventory::{Change, Inventory, Snapshot};
+use rpi_peripherals::leds::reactive;
+use rpi_peripherals::leds::{Apa102, ColorOrder, Rgb, Strip, Ws2812};
+use rpi_peripherals::lcd::{self, Backpack, Flash, Lcd, LcdDelays, LcdInterface, LocalTime, Screen, Sources};
+use rpi_peripherals::measure;
+use rpi_peripherals::melody;
+use rpi_peripherals::menu::{self, HidControls, IrControls, IrKeyMap, KeyMap, Nav};
+use rpi_peripherals::metrics::{MeteredBus, Metrics};
+use rpi_peripherals::monitor::{Presence, PresenceEvent, Watched};
+use rpi_peripherals::morse::{self, MorseTiming, PinKeyer};
+use rpi_peripherals::motor::{PulseOutput, Ramp, Servo, StepMode, Stepper};
+use rpi_peripherals::modbus::RtuMaster;
+use rpi_peripherals::mqtt::{EventDetector, Publisher};
+use rpi_peripherals::notify::{self, Notification, NotificationSink, Priority};
+use rpi_peripherals::rs485::Rs485;
+use rpi_peripherals::rules::{self, Outputs, Rules};
+use rpi_peripherals::shutdown::Shutdown;
+use rpi_peripherals::soak::{self, SoakConfig, SoakReport};
+use rpi_peripherals::softi2c::{self, SoftI2c, SoftI2cConfig};
+use rpi_peripherals::spi::ChainOrder;
+use rpi_peripherals::startup::StartupPlan;
+use rpi_peripherals::stress::{self, Mix, StressConfig, StressReport};
+use rpi_peripherals::stretch::{self, StretchConfig};
+use rpi_peripherals::sysinfo::{self, StatusSource};
+use rpi_peripherals::systemd;
+use rpi_peripherals::term::{self, ColorChoice, Stream};
+use rpi_peripherals::onewire::{self, Ds18b20};
+use rpi_peripherals::parse;
+use rpi_peripherals::pattern::{Channels, Fired, GpioChannels, Pattern};
+use rpi_peripherals::peripherals::{Claim, Peripherals, Resource};
+use rpi_peripherals::pins;
+use rpi_peripherals::plan::{self, Action, Plan};
+use rpi_peripherals::power::{self, PowerManager};
+use rpi_peripherals::preflight;
+use rpi_peripherals::preset::{self, Preset, PresetOptions};
+use rpi_peripherals::progress::{ProgressBar, Unit};
+use rpi_peripherals::pwm::SoftPwm;
+use rpi_peripherals::remote::{self, RemoteBus};
+use rpi_peripherals::repl;
+use rpi_peripherals::scan;
+use rpi_peripherals::sensors::{Apds9960, Engine, Ina219, Ina226, PowerMonitor, GESTURE_POLL, INA_DEFAULT_ADDRESS};
+use rpi_peripherals::script::Script;
+use rpi_peripherals::selftest::{self, Loopback, SelfTestReport};
+use rpi_peripherals::server::{self, EventBus, Events, Server};
+use rpi_peripherals::session::{self, BusInfo, Journal, MessageRecord, SessionReport};
+use rpi_peripherals::timing::{self, PreciseDelay, Realtime};
+use rpi_peripherals::trace::export::{self, ExportFormat};
+use rpi_peripherals::trace::{self, DiffOptions, Divergence, Recorder, Replayer, Timing, Trace};
+use rpi_peripherals::transmitter::{Cadence, Encoding, FrameCodec, Framing, Gap, Integrity, ManchesterLine, Payload, SimpleI2cTransmitter, DEFAULT_INTER_BYTE, ESCAPE_XOR};
+use rpi_peripherals::trigger::Trigger;
+use rpi_peripherals::uart::SerialPort;
+use rpi_peripherals::units::UnitsConfig;
+use rpi_peripherals::totals::{self, Totals};
+use rpi_peripherals::watchdog::{self, LockupWatchdog, Stage, Watchdog};
+use rpi_peripherals::watches::Watches;
+use rpi_peripherals::{esay, say};
+use std::collections::HashMap;
 use std::error::Error;
-use std::thread;
-use std::time::{Duration, Instant};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-// Common LCD I2C addresses
-const COMMON_ADDRESSES: [u8; 2] = [0x27, 0x3F];
+// How long the rhythm runs without --duration or --repeats
+const RHYTHM_DURATION: Duration = Duration::from_secs(2);
 
-pub struct SimpleI2cTransmitter {
-    i2c: RppalI2c,
-    address: u8,
+// Mismatches `dump --compare` lists before summing up the rest
+const DUMP_DIFFERENCES: usize = 32;
+
+// How often `monitor` and `serve` look for a changed --config file
+const CONFIG_POLL: Duration = Duration::from_secs(2);
+
+// How long `monitor` waits for a power-cycled device to answer before re-init
+const POWER_CYCLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Slowest `adc stream --rate`, one sample every 1000 s
+const MIN_STREAM_RATE: f64 = 0.001;
+
+#[derive(Parser)]
+#[command(version, about = "Dynamic rhythm I2C 'Happy Birthday' transmitter for oscilloscope work", after_help = exit::HELP)]
+#[command(group(ArgGroup::new("run_files").multiple(true)))]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// I2C bus number (/dev/i2c-N); software buses from dtoverlay=i2c-gpio work too
+    #[arg(long, global = true)]
+    bus: Option<u8>,
+
+    /// I2C clock speed you expect, e.g. 100k or 400k (checked against the kernel setting)
+    #[arg(long, value_parser = parse_speed)]
+    speed: Option<u32>,
+
+    /// Per-transaction timeout, e.g. 100ms
+    #[arg(long, value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// TOML config describing buses and devices
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Named profile from the config to apply, e.g. bench
+    #[arg(long, requires = "config")]
+    profile: Option<String>,
+
+    /// Bit-bang the bus on these BCM pins instead, e.g. 17,27 (SDA,SCL); --speed sets its clock
+    #[arg(long, global = true, value_name = "SDA,SCL", value_parser = parse_pins, conflicts_with = "bus")]
+    soft_i2c: Option<(u8, u8)>,
+
+    /// Use the bus of a Pi running `proxy` instead, e.g. pi.local:7700
+    #[arg(long, global = true, value_name = "HOST:PORT", conflicts_with_all = ["soft_i2c", "dry_run"])]
+    remote: Option<String>,
+
+    /// Control-scope token for a --remote proxy started with --tokens
+    #[arg(long, global = true, requires = "remote")]
+    remote_token: Option<String>,
+
+    /// How to reach the bus: rppal, i2cdev (the kernel's ioctls, for boards other than a Pi) or stub (in memory)
+    #[arg(long, global = true, default_value = "rppal", value_parser = parse_backend, conflicts_with_all = ["soft_i2c", "remote"])]
+    backend: BackendKind,
+
+    /// Register map TOML (as for `i2c-slave`) to put on the --backend stub bus; repeat for more devices
+    #[arg(long, global = true, value_name = "MAP")]
+    stub_device: Vec<PathBuf>,
+
+    /// Record every bus transaction to this trace file
+    #[arg(long, global = true, value_name = "TRACE")]
+    record: Option<PathBuf>,
+
+    /// Corrupt, drop or split written bytes on purpose, e.g. corrupt=0.01,stop=0.02,seed=7,address=0x27 (keys: corrupt, drop, stop, framing, seed, address)
+    #[arg(long, global = true, value_name = "FAULTS", value_parser = parse_faults)]
+    inject: Option<FaultConfig>,
+
+    /// Where the demo's device is: an address, or auto (the LCD backpack addresses), auto:scan, auto:<chip> such as auto:bme280, or auto:0x20,0x27 [default: configured devices, then auto:lcd]
+    #[arg(long, value_name = "ADDR|auto[:...]", value_parser = parse_detection)]
+    addr: Option<Detection>,
+
+    /// Demo pattern to play: rhythm, ping, sweep, burst or staircase (see `list presets`)
+    #[arg(long, default_value = "rhythm", value_parser = parse_preset)]
+    preset: Preset,
+
+    /// Bytes the sweep preset writes to every address, e.g. 0x00,0xA5; without it each address gets a read probe
+    #[arg(long, value_name = "BYTES", value_delimiter = ',', value_parser = parse_byte)]
+    payload: Vec<u8>,
+
+    /// Clock speeds the staircase preset steps through, e.g. 10k,50k,100k [default: 10k,25k,50k,75k,100k]
+    #[arg(long, value_name = "SPEEDS", value_delimiter = ',', value_parser = parse_speed)]
+    speeds: Vec<u32>,
+
+    /// Gap between sweep addresses and staircase steps, e.g. 500us or 5ms
+    #[arg(long, default_value = "2ms", value_parser = parse_duration)]
+    spacing: Duration,
+
+    /// How long the rhythm runs, e.g. 10s [default: 2s, or until --repeats are sent or a --stdin stream ends]
+    #[arg(long, value_parser = parse_duration)]
+    duration: Option<Duration>,
+
+    /// Stop the rhythm after this many messages
+    #[arg(long, value_name = "N")]
+    repeats: Option<u32>,
+
+    /// Silence after each rhythm message: equal (as long as the message took), fixed:<time> or random:<min>..<max>, e.g. random:5ms..50ms
+    #[arg(long, default_value = "equal", value_parser = parse_gap)]
+    gap_mode: Gap,
+
+    /// Gap between per-byte writes and after the start marker, e.g. 5ms [default: 50ms]
+    #[arg(long, value_name = "TIME", value_parser = parse_duration)]
+    inter_byte: Option<Duration>,
+
+    /// Send this text instead of "Happy Birthday"
+    #[arg(long, conflicts_with_all = ["hex", "file", "stdin"])]
+    text: Option<String>,
+
+    /// Send these bytes instead, e.g. "DE AD BE EF"
+    #[arg(long, conflicts_with_all = ["file", "stdin"])]
+    hex: Option<String>,
+
+    /// Send this file's contents instead, a --chunk a message, round and round
+    #[arg(long, value_name = "PATH", conflicts_with = "stdin")]
+    file: Option<PathBuf>,
+
+    /// Send what arrives on stdin as it comes, a read a message, until it ends
+    #[arg(long)]
+    stdin: bool,
+
+    /// Most bytes in one message [default: the whole payload; 64 read at a time from stdin; a frame's worth when framed]
+    #[arg(long, value_name = "BYTES")]
+    chunk: Option<usize>,
+
+    /// per-byte (a write per character, 50ms apart), batched (the whole message in one write) or framed (one checked frame, resent until ACKed)
+    #[arg(long, default_value = "per-byte", value_parser = parse_framing)]
+    framing: Framing,
+
+    /// Start, end and escape bytes around per-byte and batched messages: hdlc, none, or e.g. start=0x02,end=0x03,escape=0x10 (a byte may be none)
+    #[arg(long, value_name = "SPEC", default_value = "start=0xFF,end=0x00", value_parser = parse_markers)]
+    markers: FrameCodec,
+
+    /// What the message characters become on the bus: ascii, bcd, gray or manchester
+    #[arg(long, default_value = "ascii", value_parser = parse_encoding)]
+    encoding: Encoding,
+
+    /// Check value after each message, read back to verify: none, xor, crc8 or crc16
+    #[arg(long, default_value = "none", value_parser = parse_integrity)]
+    integrity: Integrity,
+
+    /// Also send each character Manchester-coded on this BCM GPIO
+    #[arg(long, value_name = "GPIO")]
+    manchester_pin: Option<u8>,
+
+    /// Manchester bit time on --manchester-pin, e.g. 1ms or 200us
+    #[arg(long, default_value = "1ms", value_parser = parse_duration, requires = "manchester_pin")]
+    bit_time: Duration,
+
+    /// Pulse this BCM GPIO high right before each message burst, as a scope trigger
+    #[arg(long, value_name = "GPIO")]
+    trigger_pin: Option<u8>,
+
+    /// Print the bus traffic instead of sending it: every address ACKs and reads return zeros
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Print nothing but errors and the command's own output, such as a dump or an export; the exit code says how it went
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// ASCII only: [ok], [warn] and [error] instead of emoji, for serial consoles and log files
+    #[arg(long, global = true)]
+    no_emoji: bool,
+
+    /// Color the status marks: auto (on a terminal, unless NO_COLOR is set), always or never
+    #[arg(long = "color", global = true, value_name = "WHEN", default_value = "auto", value_parser = parse_color_choice)]
+    color_choice: ColorChoice,
+
+    /// Run with SCHED_FIFO priority on a pinned core, locked in RAM, for steadier timing (needs root)
+    #[arg(long, global = true)]
+    realtime: bool,
+
+    /// Core to pin to with --realtime; defaults to the last one
+    #[arg(long, global = true, requires = "realtime")]
+    cpu: Option<usize>,
+
+    /// Never prompt or fall back to guesses; fail with a distinct exit code instead
+    #[arg(long)]
+    non_interactive: bool,
+
+    /// Also write the end-of-run summary here, as JSON or, for .md, Markdown
+    #[arg(long, value_name = "FILE", group = "run_files")]
+    summary: Option<PathBuf>,
+
+    /// Also write a report of the run here, every message with its timing: HTML for .html, else Markdown
+    #[arg(long, value_name = "FILE", group = "run_files")]
+    report: Option<PathBuf>,
+
+    /// Note stored in the --summary and --report files, e.g. "4k7 pull-ups, 30cm leads"
+    #[arg(long, requires = "run_files")]
+    label: Option<String>,
+
+    /// Once the LCD is found, show its address, backpack chip and the bus speed on it before the demo
+    #[arg(long)]
+    banner: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the I2C buses on this machine with their clock speeds
+    Buses,
+    /// List the addresses that answer on the bus, and with --identify what each chip is
+    Scan {
+        /// Read WHO_AM_I and chip-ID registers to tell apart chips that share an address
+        #[arg(long)]
+        identify: bool,
+        /// Look for two chips answering at one address (ID reads that disagree or AND together, scratch register readback) and say how to separate them
+        #[arg(long)]
+        conflicts: bool,
+    },
+    /// Show the Pi model, its I2C buses and PWM channels, and what each header GPIO can do
+    BoardInfo,
+    /// Show every header pin's mode, level and pull, like `gpio readall`
+    Pins,
+    /// List supported drivers or configured devices
+    List {
+        #[command(subcommand)]
+        what: ListCommand,
+    },
+    /// Play a recorded trace back on the bus; exits 6 if the devices answer differently
+    Replay {
+        trace: PathBuf,
+        /// realtime, 2x / 0.5x (scaled realtime), gaps, gaps:2x (recorded idle gaps, scaled), fixed:10ms or fast
+        #[arg(long, default_value = "realtime", value_parser = parse_timing)]
+        timing: Timing,
+    },
+    /// Interactive shell: scan, w 0x27 0xFF, r 0x68 7, wr 0x68 0x00 3, sleep 100
+    Repl,
+    /// Run a production test plan on each unit, reading serial numbers from the operator; exits 6 if any unit fails
+    FactoryTest {
+        plan: PathBuf,
+        /// Test one unit with this serial number instead of prompting for each; needed with --non-interactive
+        #[arg(long)]
+        serial: Option<String>,
+        /// Append one JSON line per unit to this file
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// Run a bring-up script of bus operations and assertions; exits 6 on the first failed assertion
+    Run { script: PathBuf },
+    /// Serve an HTTP API for remote control, and check [[rules]] against the readings: GET /i2c/scan, /health, /readings, /history, /watches, /totals, /ws, /dashboard, POST /i2c/write, /i2c/read, /lcd/text, /watches; [watches] and [[rules]] reload when --config changes
+    Serve {
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// Address to listen on; anything but loopback needs --tokens, and --tls-cert or --allow-plaintext
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Serve HTTPS with this PEM certificate chain, leaf first
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+        /// PEM private key for --tls-cert
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
+        /// Listen on a non-loopback --bind without TLS, so tokens and readings cross the network in the clear
+        #[arg(long, conflicts_with = "tls_cert")]
+        allow_plaintext: bool,
+        /// Token file with `<token> <scope>` lines; needed off loopback, where anyone who can reach --bind would have full control
+        #[arg(long)]
+        tokens: Option<PathBuf>,
+        /// Retry failed bus transactions this many times (counted in /metrics)
+        #[arg(long, default_value_t = 0)]
+        retries: u32,
+    },
+    /// Show what applying --config would switch on, write and claim, compared with the last apply, without touching the hardware
+    Plan {
+        /// Where apply records the config it applied
+        #[arg(long, default_value = plan::DEFAULT_STATE)]
+        state: PathBuf,
+    },
+    /// Carry out the plan for --config: switch on the rails and initialize the devices it lists, then record it as applied
+    Apply {
+        /// Where to record the config once applied
+        #[arg(long, default_value = plan::DEFAULT_STATE)]
+        state: PathBuf,
+        /// Go ahead without asking
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Serve this Pi's bus to `--remote` clients on other machines
+    Proxy {
+        #[arg(long, default_value_t = remote::DEFAULT_PORT)]
+        port: u16,
+        /// Address to listen on; anything but loopback needs --allow-plaintext and --tokens
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Listen on a non-loopback --bind even though there is no TLS, so tokens and bus traffic cross the network in the clear
+        #[arg(long)]
+        allow_plaintext: bool,
+        /// Token file with `<token> <scope>` lines; clients need a control token
+        #[arg(long)]
+        tokens: Option<PathBuf>,
+    },
+    /// Scan every monitor.interval and publish devices appearing and disappearing to the [mqtt] broker
+    Publish {
+        /// Also serve Prometheus metrics at http://BIND:PORT/metrics
+        #[arg(long, value_name = "PORT")]
+        metrics_port: Option<u16>,
+        /// Address --metrics-port listens on; 0.0.0.0 lets a Prometheus on another host scrape it
+        #[arg(long, value_name = "BIND", default_value = "127.0.0.1", requires = "metrics_port")]
+        metrics_bind: String,
+    },
+    /// Probe the configured devices every --interval, log them appearing and disappearing, and recover a stuck bus
+    ///
+    /// Under systemd with Type=notify it reports readiness, status and WatchdogSec= pings.
+    /// Devices going missing raise warnings through [alerts].
+    /// One on a [[rails]] entry that keeps failing past the [watchdog] budget gets its rail power-cycled and is re-initialized.
+    /// A changed --config file is picked up between rounds; an invalid one is ignored.
+    Monitor {
+        /// Addresses to watch besides the configured [[devices]] on this bus
+        #[arg(value_parser = parse_address)]
+        addresses: Vec<Address>,
+        /// Time between probe rounds [default: monitor.interval, 5s]
+        #[arg(long, value_parser = parse_duration)]
+        interval: Option<Duration>,
+        /// Also serve Prometheus metrics at http://BIND:PORT/metrics
+        #[arg(long, value_name = "PORT")]
+        metrics_port: Option<u16>,
+        /// Address --metrics-port listens on; 0.0.0.0 lets a Prometheus on another host scrape it
+        #[arg(long, value_name = "BIND", default_value = "127.0.0.1", requires = "metrics_port")]
+        metrics_bind: String,
+    },
+    /// Poll until an address (or configured device) ACKs, e.g. in a boot script; exits 0 once it does, 4 on timeout
+    WaitFor {
+        /// Address such as 0x27, or the name of a configured device
+        device: String,
+        /// How long to keep trying
+        #[arg(long, default_value = "30s", value_parser = parse_duration)]
+        timeout: Duration,
+        /// Time between probes
+        #[arg(long, default_value = "250ms", value_parser = parse_duration)]
+        interval: Duration,
+    },
+    /// Switch a configured [[rails]] supply, or power-cycle the devices on it and wait for them to come back
+    Power {
+        #[command(subcommand)]
+        what: PowerCommand,
+    },
+    /// Read from ADDRESS every --period for hours, tracking wake-up and response latency and missed deadlines
+    Soak {
+        #[arg(value_parser = parse_address)]
+        address: Address,
+        /// Time between transactions
+        #[arg(long, default_value = "10ms", value_parser = parse_duration)]
+        period: Duration,
+        /// Response latency (slot start to transaction end) that counts as a miss [default: --period]
+        #[arg(long, value_parser = parse_duration)]
+        deadline: Option<Duration>,
+        /// How long to run, e.g. 4h
+        #[arg(long, default_value = "1h", value_parser = parse_duration)]
+        duration: Duration,
+        /// Bytes to read per transaction
+        #[arg(long, default_value = "1", value_parser = parse_count)]
+        read: usize,
+        /// Spin through the last stretch of each wait (0s measures plain sleep wake-ups)
+        #[arg(long, default_value = "0s", value_parser = parse_duration)]
+        spin: Duration,
+        /// Write the response latency histogram here in HdrHistogram .hgrm format (milliseconds)
+        #[arg(long, value_name = "FILE")]
+        hgrm: Option<PathBuf>,
+    },
+    /// Send a random mix of reads, writes and pauses to the addresses, counting errors and the worst latency of each; the same --seed replays the same ops
+    Stress {
+        #[arg(required = true, value_parser = parse_address)]
+        addresses: Vec<Address>,
+        /// Seed for the sequence [default: picked from the clock and printed]
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Ops to run, pauses included
+        #[arg(long, default_value_t = 10_000)]
+        ops: u64,
+        /// Relative weight of each op, e.g. read=4,write-read=4,delay=1 to send no raw writes (keys: read, write, write-read, delay)
+        #[arg(long, default_value = "read=5,write=1,write-read=3,delay=1", value_parser = parse_mix)]
+        mix: Mix,
+        /// Longest read or write
+        #[arg(long, default_value = "32", value_parser = parse_count)]
+        max_len: usize,
+        /// Longest pause
+        #[arg(long, default_value = "5ms", value_parser = parse_duration)]
+        max_delay: Duration,
+    },
+    /// Measure throughput and NACKs to ADDRESS at each clock speed, to see how fast a cable run can go
+    Bench {
+        #[arg(value_parser = parse_address)]
+        address: Address,
+        /// Clock speeds to try, e.g. 10k,50k,100k; switching needs --soft-i2c, otherwise only the bus's own speed works [default: the bus's speed]
+        #[arg(long, value_name = "SPEEDS", value_delimiter = ',', value_parser = parse_speed)]
+        speeds: Vec<u32>,
+        /// Bytes per transfer
+        #[arg(long, default_value = "256", value_parser = parse_count)]
+        size: usize,
+        /// How long to run at each speed
+        #[arg(long, default_value = "2s", value_parser = parse_duration)]
+        duration: Duration,
+        /// Write counting bytes instead of reading; only for a device that doesn't mind
+        #[arg(long)]
+        write: bool,
+    },
+    /// Read from a slave that stretches the clock and report how long SCL was held and whether the master waited
+    Stretch {
+        #[arg(value_parser = parse_address)]
+        address: Address,
+        /// Register to read from, written first with a repeated START [default: plain reads]
+        #[arg(long, value_parser = parse_byte)]
+        register: Option<u8>,
+        /// Bytes per read
+        #[arg(long, default_value = "2", value_parser = parse_count)]
+        len: usize,
+        /// How many reads
+        #[arg(long, default_value = "100", value_parser = parse_count)]
+        reads: usize,
+        /// Pause between reads, e.g. 20ms
+        #[arg(long, default_value = "0s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+    /// Print a device's registers or EEPROM contents as a hexdump, optionally checked against an image
+    Dump {
+        #[arg(long, value_parser = parse_address)]
+        addr: Address,
+        /// First register or memory offset
+        #[arg(long, default_value = "0x00", value_parser = parse_word)]
+        start: u16,
+        /// Bytes to read, e.g. 256 or 4k
+        #[arg(long, default_value = "256", value_parser = parse_size)]
+        len: u64,
+        /// Two-byte memory offsets, for 24C32 and larger EEPROMs
+        #[arg(long)]
+        wide: bool,
+        /// Bytes per read
+        #[arg(long, default_value = "32", value_parser = parse_count)]
+        chunk: usize,
+        /// Expected image; differences are listed and exit with 6
+        #[arg(long, value_name = "FILE")]
+        compare: Option<PathBuf>,
+        /// Also save what was read here, raw
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Write an image to a 24Cxx EEPROM a page at a time, and with --tune find the shortest write-cycle wait it takes
+    Eeprom {
+        /// Raw image to write
+        image: PathBuf,
+        #[arg(long, value_parser = parse_address)]
+        addr: Address,
+        /// Memory offset to write from
+        #[arg(long, default_value = "0x00", value_parser = parse_word)]
+        start: u16,
+        /// Two-byte memory offsets, for 24C32 and larger EEPROMs
+        #[arg(long)]
+        wide: bool,
+        /// Bytes per page write: 8 on a 24C02, 32 on a 24C32/64, 64 on a 24C256
+        #[arg(long, default_value_t = eeprom::DEFAULT_PAGE, value_parser = parse_count)]
+        page: usize,
+        /// Wait after each page [default: the device's write_delay in the config, else 10ms]
+        #[arg(long, value_parser = parse_duration)]
+        write_cycle: Option<Duration>,
+        /// Start from the wait and shrink it by ACK polling to what the chip takes, then print it for the config
+        #[arg(long)]
+        tune: bool,
+        /// Read the image back afterwards; a difference exits with 6
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Update a microcontroller through its I2C bootloader, chunk by chunk with a CRC check of each
+    Flash {
+        /// Firmware image: Intel HEX (.hex) or a raw binary
+        image: PathBuf,
+        #[arg(long, value_parser = parse_address)]
+        addr: Address,
+        /// Offset of a raw binary in the application area
+        #[arg(long, default_value = "0", value_parser = parse_offset)]
+        base: u32,
+        /// Address the application area starts at in a HEX file, e.g. 0x08002000 on an STM32
+        #[arg(long, default_value = "0", value_parser = parse_offset)]
+        origin: u32,
+        /// Bytes per write (the bootloader may ask for fewer)
+        #[arg(long, default_value_t = flash::DEFAULT_CHUNK)]
+        chunk: usize,
+        /// Tries per chunk before giving up
+        #[arg(long, default_value_t = 3)]
+        attempts: u32,
+        /// Carry on after a failed update: skip chunks already on the device, and don't erase
+        #[arg(long)]
+        resume: bool,
+        /// Leave the bootloader running instead of starting the new firmware
+        #[arg(long)]
+        no_boot: bool,
+        /// Longest a command (an erase, mostly) may keep the bootloader busy
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        busy_timeout: Duration,
+    },
+    /// Check that the bus can be opened (driver, device tree, /dev node, permissions) and say how to fix what can't
+    Preflight,
+    /// Check each configured device on the bus, with a PASS/WARN/FAIL line for each; exits 6 if any fails
+    Selftest {
+        /// Check each chip is what the config says (ID registers, a conversion, an LCD test pattern), not just that it answers
+        #[arg(long)]
+        full: bool,
+        /// BCM pins wired together on a test jig, as OUT:IN; repeat for more pairs
+        #[arg(long, value_name = "OUT:IN")]
+        loopback: Vec<Loopback>,
+    },
+    /// Control a character LCD on a PCF8574 backpack without redrawing it
+    Lcd {
+        #[command(subcommand)]
+        what: LcdCommand,
+    },
+    /// Rotate the Pi's IP address, CPU temperature, load and disk usage on the LCD, using [[pages]] if they only show these (reloaded when --config changes)
+    Sysinfo {
+        /// Backpack address [default: the first configured hd44780 on the bus, else 0x27 or 0x3F, whichever answers]
+        #[arg(long, value_parser = parse_address)]
+        address: Option<Address>,
+        #[arg(long, default_value_t = 16)]
+        cols: u8,
+        #[arg(long, default_value_t = 2)]
+        rows: u8,
+        /// How often the values are read again and the page redrawn
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        refresh: Duration,
+        /// Print each page once to the terminal instead, without touching the bus
+        #[arg(long)]
+        print: bool,
+        /// USB keypad or knob (part of its name, or a /dev/input path) that flips pages: down or select for the next, up for the previous
+        #[arg(long, value_name = "DEVICE")]
+        hid: Option<String>,
+        /// IR receiver module on this BCM GPIO whose NEC remote flips pages, as --hid does
+        #[arg(long, value_name = "GPIO", conflicts_with = "hid")]
+        ir: Option<u8>,
+    },
+    /// Ready-made applications built from the drivers
+    App {
+        #[command(subcommand)]
+        what: AppCommand,
+    },
+    /// Same as `app energy`
+    PowerMonitor(EnergyArgs),
+    /// Check the bus against an inventory of expected devices and register values; exits 6 on any mismatch
+    Verify { inventory: PathBuf },
+    /// Save what answers on the bus and what it was identified as, or compare the bus with a saved scan
+    Inventory {
+        #[command(subcommand)]
+        what: InventoryCommand,
+    },
+    /// Play a pattern of I2C transfers, GPIO levels, trigger pulses and PWM tones from FILE on one clock, for aligned scope captures
+    Pattern {
+        file: PathBuf,
+        /// Times to play it
+        #[arg(long, default_value_t = 1)]
+        repeat: u32,
+        /// Start of one play to the start of the next [default: its length plus 10ms]
+        #[arg(long, value_parser = parse_duration)]
+        period: Option<Duration>,
+        /// Spin through the last stretch of each wait (0s for plain sleeps)
+        #[arg(long, default_value = "2ms", value_parser = parse_duration)]
+        spin: Duration,
+    },
+    /// Work with recorded transaction traces
+    Trace {
+        #[command(subcommand)]
+        what: TraceCommand,
+    },
+    /// Drive a WS2812 (NeoPixel) LED strip from SPI MOSI
+    Leds {
+        /// LEDs on the strip
+        #[arg(long, default_value_t = 8)]
+        count: usize,
+        /// SPI bus; 0 is MOSI on GPIO 10
+        #[arg(long, default_value_t = 0)]
+        spi: u8,
+        /// 0-255, applied after gamma correction
+        #[arg(long, default_value_t = 255)]
+        brightness: u8,
+        /// Send the values as given, without gamma correction
+        #[arg(long)]
+        no_gamma: bool,
+        /// The strip takes red first (WS2811) rather than green
+        #[arg(long, conflicts_with = "apa102")]
+        rgb: bool,
+        /// An APA102 or SK9822 strip, with its clock on SCLK (GPIO 11)
+        #[arg(long)]
+        apa102: bool,
+        #[command(subcommand)]
+        what: LedsCommand,
+    },
+    /// Show text or numbers on MAX7219 LED matrices or seven-segment digits
+    Max7219 {
+        /// Chips in the chain
+        #[arg(long, default_value_t = 1)]
+        devices: usize,
+        /// SPI bus; 0 is MOSI on GPIO 10 and SCLK on GPIO 11
+        #[arg(long, default_value_t = 0)]
+        spi: u8,
+        /// Chip select on that bus
+        #[arg(long, default_value_t = 0)]
+        cs: u8,
+        /// 0-15
+        #[arg(long, default_value_t = 7)]
+        intensity: u8,
+        /// Count chips from the far end of the chain, as on FC-16 modules fed from the right
+        #[arg(long)]
+        far_first: bool,
+        /// The matrices are mounted upside down
+        #[arg(long)]
+        flip: bool,
+        #[command(subcommand)]
+        what: Max7219Command,
+    },
+    /// Show text, a number or the time on a TM1637 four-digit display
+    Tm1637 {
+        /// BCM pin for CLK
+        #[arg(long)]
+        clk: u8,
+        /// BCM pin for DIO
+        #[arg(long)]
+        dio: u8,
+        /// 0-7
+        #[arg(long, default_value_t = 7)]
+        brightness: u8,
+        #[command(subcommand)]
+        what: Tm1637Command,
+    },
+    /// Send or dump CAN frames through an MCP2515, e.g. can send 123#DEADBEEF
+    Can {
+        /// SPI bus the MCP2515 is on
+        #[arg(long, default_value_t = 0)]
+        spi: u8,
+        /// Chip select on that bus
+        #[arg(long, default_value_t = 0)]
+        cs: u8,
+        /// Bus bitrate, e.g. 125k or 500k
+        #[arg(long, default_value = "500k", value_parser = parse_speed)]
+        bitrate: u32,
+        /// The module's crystal, 8M on most and 16M on some
+        #[arg(long, default_value = "8M", value_parser = parse_speed)]
+        oscillator: u32,
+        /// Frames loop back inside the chip, nothing reaches the bus
+        #[arg(long, conflicts_with = "listen_only")]
+        loopback: bool,
+        /// Receive without ACKing, to watch a live bus
+        #[arg(long)]
+        listen_only: bool,
+        #[command(subcommand)]
+        what: CanCommand,
+    },
+    /// Talk on an RS-485 bus through a transceiver with DE/RE on a GPIO
+    Rs485 {
+        /// Serial device
+        #[arg(long, default_value = "/dev/serial0")]
+        port: PathBuf,
+        #[arg(long, default_value_t = 9600)]
+        baud: u32,
+        /// BCM GPIO wired to DE and /RE
+        #[arg(long)]
+        de: u8,
+        /// From DE high to the first bit
+        #[arg(long, default_value = "0s", value_parser = parse_duration)]
+        pre_delay: Duration,
+        /// DE held high past the last stop bit
+        #[arg(long, default_value = "0s", value_parser = parse_duration)]
+        post_delay: Duration,
+        #[command(subcommand)]
+        what: Rs485Command,
+    },
+    /// Read or write registers of a Modbus RTU slave, e.g. modbus read --slave 5 --reg 100 --count 4
+    Modbus {
+        /// Serial device
+        #[arg(long, default_value = "/dev/serial0")]
+        port: PathBuf,
+        #[arg(long, default_value_t = 9600)]
+        baud: u32,
+        /// BCM GPIO wired to an RS-485 transceiver's DE and /RE; leave out on a plain serial line
+        #[arg(long)]
+        de: Option<u8>,
+        /// How long a slave gets to start its reply
+        #[arg(long, default_value = "500ms", value_parser = parse_duration)]
+        timeout: Duration,
+        /// Tries in all when a reply times out or fails its CRC
+        #[arg(long, default_value_t = 3)]
+        attempts: u32,
+        #[command(subcommand)]
+        what: ModbusCommand,
+    },
+    /// Sample configured sensors at an interval into a CSV file or SQLite database
+    Log {
+        /// .csv, or .db or .sqlite for SQLite
+        output: PathBuf,
+        /// Configured devices to sample, comma-separated [default: every one on the bus with readings to log]
+        #[arg(long, value_delimiter = ',')]
+        devices: Vec<String>,
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+        /// csv or sqlite [default: from the output file's extension]
+        #[arg(long, value_parser = parse_log_format)]
+        format: Option<Format>,
+        /// Start a new file once this one passes this size, e.g. 10M; the full one is numbered
+        #[arg(long, value_parser = parse_size)]
+        max_size: Option<u64>,
+        /// One file per local day, the date added to its name
+        #[arg(long)]
+        daily: bool,
+        /// How often buffered rows are written to disk; always on exit too
+        #[arg(long, default_value = "10s", value_parser = parse_duration)]
+        flush: Duration,
+        /// Stop after this many rounds of samples
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// Print each fix from a serial GPS module, or JSON lines with --json
+    Gps {
+        /// Serial device
+        #[arg(long, default_value = "/dev/serial0")]
+        port: PathBuf,
+        #[arg(long, default_value_t = 9600)]
+        baud: u32,
+        /// Replay a logged NMEA file instead of reading the port
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// One JSON object per fix
+        #[arg(long)]
+        json: bool,
+        /// Also show position and time on the LCD
+        #[arg(long)]
+        lcd: bool,
+        /// Backpack address [default: the first configured hd44780 on the bus, else 0x27 or 0x3F, whichever answers]
+        #[arg(long, value_parser = parse_address, requires = "lcd")]
+        address: Option<Address>,
+        /// Stop after this many fixes
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// Print proximity and light from an APDS-9960, or the swipes it sees with --gestures
+    Apds9960 {
+        #[arg(long, value_parser = parse_address, default_value = "0x39")]
+        address: Address,
+        /// Print each swipe instead: up, down, left or right
+        #[arg(long)]
+        gestures: bool,
+        /// Time between readings
+        #[arg(long, default_value = "500ms", value_parser = parse_duration, conflicts_with = "gestures")]
+        interval: Duration,
+        /// Stop after this many readings or swipes
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// Read an MCP3008 ADC once, or stream readings to CSV at a fixed rate
+    Adc {
+        /// SPI bus; 0 is MOSI on GPIO 10, MISO on GPIO 9 and SCLK on GPIO 11
+        #[arg(long, default_value_t = 0)]
+        spi: u8,
+        /// Chip select on that bus
+        #[arg(long, default_value_t = 0)]
+        cs: u8,
+        /// Volts on the VREF pin
+        #[arg(long, default_value_t = 3.3)]
+        vref: f64,
+        #[command(subcommand)]
+        what: AdcCommand,
+    },
+    /// Print what an NEC infrared remote sends to a receiver module, until Ctrl-C
+    Ir {
+        /// BCM GPIO the receiver's OUT is on
+        pin: u8,
+    },
+    /// Frequency, duty cycle and pulse width of a signal on a GPIO, from edge timestamps
+    Measure {
+        pin: u8,
+        /// How long to collect edges for each reading
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        gate: Duration,
+        /// Keep measuring, one line per gate, until Ctrl-C
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Software PWM on any GPIO until Ctrl-C, e.g. pwm 23 --duty 0.25 to dim an LED
+    Pwm {
+        pin: u8,
+        /// Hz
+        #[arg(long, default_value_t = 1000.0)]
+        frequency: f64,
+        /// 0.0 (off) to 1.0 (fully on)
+        #[arg(long, default_value_t = 0.5)]
+        duty: f64,
+        /// Stop after this long
+        #[arg(long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// Send text in Morse code on an LED or buzzer, e.g. morse "HAPPY BIRTHDAY" --pin 17
+    Morse {
+        /// Letters, digits and punctuation; prosigns in angle brackets, like <SK>
+        text: String,
+        #[arg(long)]
+        pin: u8,
+        /// Letter speed in words per minute
+        #[arg(long, default_value_t = morse::DEFAULT_WPM)]
+        wpm: u32,
+        /// Slower overall speed, spacing the letters out (Farnsworth timing)
+        #[arg(long, value_name = "WPM")]
+        farnsworth: Option<u32>,
+        /// Sound a passive buzzer with a square wave, 700 Hz unless given, instead of switching the pin
+        #[arg(long, value_name = "HZ", num_args = 0..=1, default_missing_value = "700")]
+        tone: Option<f64>,
+        /// The LED or buzzer is on when the pin is low
+        #[arg(long)]
+        active_low: bool,
+    },
+    /// Play tunes on a passive buzzer
+    Buzzer {
+        #[command(subcommand)]
+        what: BuzzerCommand,
+    },
+    /// Answer as an I2C peripheral from a register map, until Ctrl-C
+    Slave {
+        /// TOML register map; without one, 256 writable registers of 0x00
+        map: Option<PathBuf>,
+        /// 7-bit address to answer at, instead of the map's
+        #[arg(long, value_parser = parse_byte)]
+        address: Option<u8>,
+    },
+    /// Take framed messages from another Pi's --framing framed demo, until Ctrl-C
+    Receive {
+        /// 7-bit address to answer at
+        #[arg(long, value_parser = parse_byte, default_value = "0x27")]
+        address: u8,
+    },
+    /// Listen to another master's bus on two spare GPIOs and decode it, until Ctrl-C
+    Analyze {
+        /// GPIO wired to the bus's SDA
+        #[arg(long)]
+        sda: u8,
+        /// GPIO wired to the bus's SCL
+        #[arg(long)]
+        scl: u8,
+        /// Stop after this long
+        #[arg(long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+        /// Print every START, address, byte, ACK and STOP, not a line per transaction
+        #[arg(long)]
+        events: bool,
+        /// Save the transactions as a trace, for trace diff and trace export
+        #[arg(long, value_name = "PATH")]
+        save: Option<PathBuf>,
+    },
+    /// Move a hobby servo, e.g. servo set 17 90
+    Servo {
+        /// Pulse width at 0°
+        #[arg(long, default_value = "1ms", value_parser = parse_duration)]
+        min: Duration,
+        /// Pulse width at the full travel
+        #[arg(long, default_value = "2ms", value_parser = parse_duration)]
+        max: Duration,
+        /// Degrees from --min to --max
+        #[arg(long, default_value_t = 180.0)]
+        travel: f64,
+        /// Use the pin's hardware PWM channel (needs dtoverlay=pwm) rather than software PWM
+        #[arg(long)]
+        hardware: bool,
+        /// Keep pulsing this long before letting go; Ctrl-C lets go early
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        hold: Duration,
+        #[command(subcommand)]
+        what: ServoCommand,
+    },
+    /// Run a 4-wire stepper on a ULN2003 or H-bridge, e.g. stepper move 200
+    Stepper {
+        /// BCM pins for IN1 to IN4
+        #[arg(long, value_delimiter = ',', num_args = 4, default_value = "17,18,27,22")]
+        pins: Vec<u8>,
+        /// full or half steps
+        #[arg(long, default_value = "full", value_parser = parse_step_mode)]
+        mode: StepMode,
+        /// Top speed, steps per second
+        #[arg(long, default_value_t = 500.0)]
+        speed: f64,
+        /// Speed of the first and last steps, steps per second
+        #[arg(long, default_value_t = 100.0)]
+        start_speed: f64,
+        /// Steps per second per second
+        #[arg(long, default_value_t = 1000.0)]
+        accel: f64,
+        /// Leave the coils on at the end, holding the rotor
+        #[arg(long)]
+        hold: bool,
+        #[command(subcommand)]
+        what: StepperCommand,
+    },
+    /// Raise one alert through the [alerts] outputs, to check the buzzer, LED and MQTT
+    Alert {
+        /// info, warning or critical
+        #[arg(value_parser = parse_severity)]
+        severity: Severity,
+        message: String,
+        /// What it is about; repeats of one key are rate-limited
+        #[arg(long, default_value = "test")]
+        key: String,
+    },
+    /// Print the sound level from an I2S MEMS microphone, once per interval until Ctrl-C
+    Noise {
+        /// ALSA capture device, as `arecord -L` lists it
+        #[arg(long, default_value = "default")]
+        device: String,
+        #[arg(long, value_enum, default_value_t = Mic::Inmp441)]
+        mic: Mic,
+        #[arg(long, default_value_t = spl::MIC_RATE)]
+        rate: u32,
+        /// The microphone's L/R pin is tied high
+        #[arg(long)]
+        right: bool,
+        /// dB added to every reading, from checking against a sound level meter
+        #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+        calibration: f32,
+        /// Time each reading averages over
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+        /// Stop after this many readings
+        #[arg(long)]
+        count: Option<usize>,
+    },
+    /// Read DS18B20 sensors on the kernel's 1-Wire bus
+    Onewire {
+        #[command(subcommand)]
+        what: OnewireCommand,
+    },
+    /// Show or reset the totalizers saved in [totals] state
+    Totals {
+        #[command(subcommand)]
+        what: TotalsCommand,
+    },
+    /// Poll the Pis in [fleet] for health, scans and readings
+    Fleet {
+        #[command(subcommand)]
+        what: FleetCommand,
+    },
+    /// Print a shell completion script, e.g. `completions bash > /etc/bash_completion.d/rpi_peripherals`
+    Completions { shell: Shell },
+}
+
+#[derive(Subcommand)]
+enum InventoryCommand {
+    /// Scan and identify the bus and write it to FILE as JSON
+    Save { file: PathBuf },
+    /// Scan again and list devices that appeared, vanished or changed identity since FILE; exits 6 on any change
+    Diff {
+        file: PathBuf,
+        /// Write the new scan over FILE afterwards
+        #[arg(long)]
+        update: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TraceCommand {
+    /// Align two traces and show differing bytes, ordering and timing; exits 6 if they differ
+    Diff {
+        left: PathBuf,
+        right: PathBuf,
+        /// Timing differences up to this much are ignored
+        #[arg(long, default_value = "500us", value_parser = parse_duration)]
+        tolerance: Duration,
+    },
+    /// Rebuild the intended SCL/SDA waveform as VCD or CSV for PulseView/sigrok
+    Export {
+        trace: PathBuf,
+        output: PathBuf,
+        /// vcd or csv; defaults to the output file's extension
+        #[arg(long, value_parser = parse_format)]
+        format: Option<ExportFormat>,
+        /// Samples per second in a CSV, e.g. 1M; give sigrok the same as samplerate=
+        #[arg(long, default_value_t = export::DEFAULT_SAMPLE_RATE, value_parser = parse_speed)]
+        samplerate: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum AppCommand {
+    /// Log power from an INA219/INA226 with kWh today and in total, and what it cost; exported to [mqtt] and Prometheus
+    Energy(EnergyArgs),
+}
+
+/// `app energy`, also reachable as `power-monitor`.
+#[derive(Args)]
+struct EnergyArgs {
+    /// A configured ina219 or ina226 device [default: --chip at --address]
+    #[arg(long, conflicts_with_all = ["chip", "address"])]
+    device: Option<String>,
+    #[arg(long, value_enum, default_value_t = PowerChip::Ina219)]
+    chip: PowerChip,
+    #[arg(long, value_parser = parse_address, default_value = "0x40")]
+    address: Address,
+    /// Shunt resistor in ohms
+    #[arg(long, default_value_t = 0.1)]
+    shunt: f64,
+    /// Largest current expected, in amps; sets the resolution
+    #[arg(long, default_value_t = 3.2)]
+    max_current: f64,
+    #[arg(long, default_value = "1s", value_parser = parse_duration)]
+    interval: Duration,
+    /// Price per kWh, for cost estimates
+    #[arg(long)]
+    price: Option<f64>,
+    /// Label for --price, e.g. EUR
+    #[arg(long, default_value = "")]
+    currency: String,
+    /// File to keep the totals in across restarts [default: totals.state]
+    #[arg(long)]
+    state: Option<PathBuf>,
+    /// Append one JSON line per sample to this file
+    #[arg(long)]
+    log: Option<PathBuf>,
+    /// Also serve Prometheus metrics at http://BIND:PORT/metrics
+    #[arg(long, value_name = "PORT")]
+    metrics_port: Option<u16>,
+    /// Address --metrics-port listens on; 0.0.0.0 lets a Prometheus on another host scrape it
+    #[arg(long, value_name = "BIND", default_value = "127.0.0.1", requires = "metrics_port")]
+    metrics_bind: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PowerChip {
+    Ina219,
+    Ina226,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mic {
+    Sph0645,
+    Inmp441,
+}
+
+#[derive(Subcommand)]
+enum FleetCommand {
+    /// Poll every agent once and print a line each; exits 1 if any is down
+    Status {
+        /// Print the full poll as JSON instead
+        #[arg(long)]
+        json: bool,
+    },
+    /// Poll every fleet.interval, log agents going down and coming back, and serve GET /fleet and /metrics
+    Serve {
+        #[arg(long, default_value_t = 8090)]
+        port: u16,
+        /// Address to listen on; 0.0.0.0 for the whole network
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum LedsCommand {
+    /// Light one LED; the strip can't be read back, so the others go dark
+    Set {
+        index: usize,
+        /// R,G,B such as 255,0,0, or #rrggbb
+        #[arg(value_parser = parse_color)]
+        color: Rgb,
+    },
+    /// Light every LED in one colour
+    Fill {
+        #[arg(value_parser = parse_color)]
+        color: Rgb,
+    },
+    /// Switch every LED off
+    Clear,
+    /// Light the LEDs one after another
+    Wipe {
+        #[arg(value_parser = parse_color)]
+        color: Rgb,
+        /// Time between LEDs
+        #[arg(long, default_value = "30ms", value_parser = parse_duration)]
+        step: Duration,
+    },
+    /// Run a rainbow along the strip until Ctrl-C
+    Rainbow {
+        /// Once round the colour wheel
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        period: Duration,
+    },
+    /// Animate the strip from the [reactive] microphone until Ctrl-C
+    React {
+        /// ALSA capture device [default: reactive.device]
+        #[arg(long)]
+        device: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum Max7219Command {
+    /// Text on the matrices, scrolled across if it is too long to fit
+    Text {
+        text: String,
+        /// Keep scrolling until Ctrl-C
+        #[arg(long)]
+        repeat: bool,
+        /// Time between columns when scrolling
+        #[arg(long, default_value = "40ms", value_parser = parse_duration)]
+        step: Duration,
+    },
+    /// A number on one chip's digits, with as many decimals as fit
+    Number {
+        #[arg(allow_hyphen_values = true)]
+        value: f64,
+        /// Chip in the chain
+        #[arg(long, default_value_t = 0)]
+        device: usize,
+        /// Always this many decimals
+        #[arg(long)]
+        decimals: Option<usize>,
+    },
+    /// Text on one chip's digits, such as "HELLO" or "21.5°C"
+    Segments {
+        text: String,
+        #[arg(long, default_value_t = 0)]
+        device: usize,
+    },
+    /// Blank every chip
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum AdcCommand {
+    /// Print each input once, such as 0 for channel 0 or 2-3 for channel 2 against 3
+    Read {
+        #[arg(required = true)]
+        inputs: Vec<Input>,
+    },
+    /// Sample the inputs at a fixed rate and write CSV until Ctrl-C
+    Stream {
+        #[arg(required = true)]
+        inputs: Vec<Input>,
+        /// Samples per second of every input, at least 0.001
+        #[arg(long, default_value_t = 10.0, value_parser = parse_stream_rate)]
+        rate: f64,
+        /// Stop after this long
+        #[arg(long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+        /// Write here rather than to stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Raw 0-1023 counts rather than volts
+        #[arg(long)]
+        raw: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModbusCommand {
+    /// Read holding registers, or input registers with --input
+    Read {
+        /// 1-247
+        #[arg(long)]
+        slave: u8,
+        /// First register, 0-based as on the wire
+        #[arg(long, value_parser = parse_word)]
+        reg: u16,
+        #[arg(long, default_value_t = 1)]
+        count: u16,
+        /// Function 4 rather than 3
+        #[arg(long)]
+        input: bool,
+    },
+    /// Write one register (function 6), or several from --reg on (function 16)
+    Write {
+        /// 1-247, or 0 to broadcast
+        #[arg(long)]
+        slave: u8,
+        #[arg(long, value_parser = parse_word)]
+        reg: u16,
+        #[arg(required = true, value_parser = parse_word)]
+        values: Vec<u16>,
+        /// Function 16 even for a single value, for slaves without function 6
+        #[arg(long)]
+        multiple: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum Rs485Command {
+    /// Send bytes, e.g. rs485 --de 17 send 0x01 0x03 0x00 0x00 0x00 0x01 0x84 0x0A
+    Send {
+        #[arg(required = true, value_parser = parse_byte)]
+        bytes: Vec<u8>,
+    },
+    /// Send bytes and print the reply
+    Request {
+        #[arg(required = true, value_parser = parse_byte)]
+        bytes: Vec<u8>,
+        /// Give up when nothing comes back within this long
+        #[arg(long, default_value = "500ms", value_parser = parse_duration)]
+        timeout: Duration,
+        /// The reply is over once the line is quiet this long
+        #[arg(long, default_value = "20ms", value_parser = parse_duration)]
+        gap: Duration,
+        /// Stop the reply at this many bytes
+        #[arg(long, default_value_t = 256)]
+        max: usize,
+    },
+    /// Print what arrives until Ctrl-C, without ever driving the bus
+    Listen,
+}
+
+#[derive(Subcommand)]
+enum CanCommand {
+    /// Send frames written as cansend takes them: 123#DEADBEEF, 12345678#00.11, 123#R
+    Send {
+        #[arg(required = true, value_parser = parse_can_frame)]
+        frames: Vec<CanFrame>,
+        /// How long to wait for each frame to be ACKed
+        #[arg(long, default_value = "100ms", value_parser = parse_duration)]
+        timeout: Duration,
+    },
+    /// Print frames as they arrive until Ctrl-C, as candump does
+    Dump {
+        /// Only IDs matching, as 123 or ID:MASK (100:700 is 0x100-0x1FF); up to 6
+        #[arg(long, value_parser = parse_can_filter)]
+        filter: Vec<Filter>,
+        /// Stop after this many frames
+        #[arg(long)]
+        count: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum Tm1637Command {
+    /// Text such as "21.5" or "HELP", right-aligned
+    Show { text: String },
+    /// A number with as many decimals as fit
+    Number {
+        #[arg(allow_hyphen_values = true)]
+        value: f64,
+        /// Always this many decimals
+        #[arg(long)]
+        decimals: Option<usize>,
+    },
+    /// Show the local time with a blinking colon until Ctrl-C
+    Clock {
+        /// 1-12 rather than 0-23
+        #[arg(long)]
+        twelve_hour: bool,
+    },
+    /// Blank the display
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum BuzzerCommand {
+    /// Play an RTTTL ringtone or a list of notes, e.g. buzzer play song.rtttl --pin 18
+    Play {
+        /// A file, or a built-in song: happy-birthday
+        song: String,
+        #[arg(long)]
+        pin: u8,
+        /// Play this many times as fast, from 0.01 to 100 (0.5 for half speed)
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServoCommand {
+    /// Move to an angle within the travel
+    Set { pin: u8, angle: f64 },
+    /// Send a raw pulse width such as 600us, for finding the end stops
+    Pulse {
+        pin: u8,
+        #[arg(value_parser = parse_duration)]
+        width: Duration,
+    },
+}
+
+#[derive(Subcommand)]
+enum StepperCommand {
+    /// Move this many steps; negative goes backwards
+    Move {
+        #[arg(allow_negative_numbers = true)]
+        steps: i64,
+    },
+}
+
+#[derive(Subcommand)]
+enum OnewireCommand {
+    /// Print every sensor found with its temperature and resolution
+    List,
+    /// Set a sensor's resolution, 9 to 12 bits (needs root)
+    Resolution {
+        /// ROM code such as 28-3c01b556a1ff
+        id: String,
+        bits: u8,
+        /// Also store it in the sensor's EEPROM so it survives a power cycle
+        #[arg(long)]
+        save: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TotalsCommand {
+    /// Print the saved value of every total and when it was last reset
+    List,
+    /// Start totals from zero (all of them without NAME); stop the service first, or use POST /totals/NAME/reset while it runs
+    Reset {
+        names: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PowerCommand {
+    /// Switch the rail on and leave it on
+    On {
+        /// A [[rails]] entry, or a device on one
+        rail: String,
+    },
+    /// Switch the rail off and leave it off
+    Off {
+        /// A [[rails]] entry, or a device on one
+        rail: String,
+    },
+    /// Switch the rail off and back on, wait for its devices to ACK and re-initialize them; --count repeats it for brown-out testing, exiting 6 if any cycle lost a device
+    Cycle {
+        /// A [[rails]] entry, or a device on one
+        rail: String,
+        /// Time held off [default: the rail's off_time, else 500ms]
+        #[arg(long, value_parser = parse_duration)]
+        off_time: Option<Duration>,
+        /// Also wait for this address after each cycle, for a part not in the config
+        #[arg(long, value_name = "ADDRESS", value_parser = parse_address)]
+        wait_for: Vec<Address>,
+        /// Longest to wait for each device to ACK after power returns
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        timeout: Duration,
+        /// Power cycles to run
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+        /// Time between the end of one cycle and the start of the next
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+        /// Only wait for the devices, without re-initializing them
+        #[arg(long)]
+        no_init: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LcdCommand {
+    /// Switch the backlight on or off, or blink it
+    Backlight {
+        #[arg(value_enum)]
+        state: BacklightState,
+        /// Backpack address [default: the first configured hd44780 on the bus, else 0x27 or 0x3F, whichever answers]
+        #[arg(long, value_parser = parse_address)]
+        address: Option<Address>,
+        /// Blinks for `blink`
+        #[arg(long, default_value_t = 3)]
+        times: u32,
+        /// Time lit in each blink
+        #[arg(long, default_value = "250ms", value_parser = parse_duration)]
+        on: Duration,
+        /// Time dark in each blink
+        #[arg(long, default_value = "250ms", value_parser = parse_duration)]
+        off: Duration,
+    },
+    /// Shrink the waits after each instruction to what the display takes, by its busy flag, and print them for the config
+    Tune {
+        /// Backpack address [default: the first configured hd44780 on the bus, else 0x27 or 0x3F, whichever answers]
+        #[arg(long, value_parser = parse_address)]
+        address: Option<Address>,
+        /// Screens of test pattern to write while tuning
+        #[arg(long, default_value_t = 20)]
+        rounds: u32,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BacklightState {
+    On,
+    Off,
+    /// Blink --times times, then leave it on
+    Blink,
+}
+
+#[derive(Subcommand)]
+enum ListCommand {
+    /// Every chip with a driver in this build
+    Drivers,
+    /// Every device in the config, with its driver's capabilities
+    Devices {
+        /// Also check whether each I2C device answers on its bus
+        #[arg(long)]
+        probe: bool,
+    },
+    /// The order rails and devices are brought up in, from their dependencies
+    Startup,
+    /// Demo patterns for --preset, with the scope settings for each
+    Presets,
+    /// USB keyboards, keypads and knobs, for --hid
+    Input,
+}
+
+fn parse_speed(s: &str) -> Result<u32, String> {
+    parse::frequency(s).map_err(|e| e.to_string())
+}
+
+fn parse_stream_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.trim().parse().map_err(|_| format!("invalid rate '{}'", s))?;
+    if !(rate.is_finite() && rate >= MIN_STREAM_RATE) {
+        return Err(format!("rate {} out of range (at least {} samples a second)", s, MIN_STREAM_RATE));
+    }
+    Ok(rate)
+}
+
+fn parse_pins(s: &str) -> Result<(u8, u8), String> {
+    let (sda, scl) = s.split_once(',').ok_or("expected SDA,SCL, e.g. 17,27")?;
+    let pin = |p: &str| p.trim().parse::<u8>().map_err(|_| format!("invalid GPIO number '{}'", p));
+    Ok((pin(sda)?, pin(scl)?))
+}
+
+fn parse_format(s: &str) -> Result<ExportFormat, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_backend(s: &str) -> Result<BackendKind, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_color_choice(s: &str) -> Result<ColorChoice, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_framing(s: &str) -> Result<Framing, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_markers(s: &str) -> Result<FrameCodec, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_gap(s: &str) -> Result<Gap, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_encoding(s: &str) -> Result<Encoding, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_integrity(s: &str) -> Result<Integrity, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_can_frame(s: &str) -> Result<CanFrame, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_can_filter(s: &str) -> Result<Filter, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_step_mode(s: &str) -> Result<StepMode, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_address(s: &str) -> Result<Address, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_detection(s: &str) -> Result<Detection, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    parse::byte_count(s.trim()).map_err(|e| e.to_string())
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    parse::byte(s.trim()).map_err(|e| e.to_string())
+}
+
+fn parse_word(s: &str) -> Result<u16, String> {
+    parse::word(s.trim()).map_err(|e| e.to_string())
+}
+
+fn parse_preset(s: &str) -> Result<Preset, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_timing(s: &str) -> Result<Timing, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_color(s: &str) -> Result<Rgb, String> {
+    s.parse()
+}
+
+fn parse_severity(s: &str) -> Result<Severity, String> {
+    s.parse()
+}
+
+fn parse_faults(s: &str) -> Result<FaultConfig, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_mix(s: &str) -> Result<Mix, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_log_format(s: &str) -> Result<Format, String> {
+    s.parse().map_err(|e: Box<dyn Error>| e.to_string())
+}
+
+fn parse_offset(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(&hex.replace('_', ""), 16),
+        None => s.parse(),
+    };
+    value.map_err(|_| format!("invalid offset '{}'", s))
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    parse::size(s).map_err(|e| e.to_string())
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    parse::duration(s).map_err(|e| e.to_string())
+}
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            // clap's own code for a usage error is 2, which means device not found here
+            return if e.use_stderr() { ExitStatus::Failure.into() } else { ExitCode::SUCCESS };
+        }
+    };
+    term::configure(!cli.no_emoji, cli.color_choice);
+    term::set_quiet(cli.quiet);
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let status = ExitStatus::classify(&*e);
+            if status != ExitStatus::Interrupted {
+                esay!("❌ {}", e);
+            }
+            status.into()
+        }
+    }
+}
+
+fn run(mut cli: Cli) -> Result<(), Box<dyn Error>> {
+    match &cli.command {
+        Some(Command::Trace { what: TraceCommand::Diff { left, right, tolerance } }) => {
+            return diff_traces(left, right, *tolerance);
+        }
+        Some(Command::Trace { what: TraceCommand::Export { trace, output, format, samplerate } }) => {
+            return export_trace(trace, output, *format, *samplerate);
+        }
+        _ => {}
+    }
+    if let Some(Command::Completions { shell }) = cli.command {
+        let mut command = Cli::command();
+        let name = command.get_name().to_string();
+        clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+        return Ok(());
+    }
+    let config = match &cli.config {
+        Some(path) => Config::load_profile(path, cli.profile.as_deref())?,
+        None => Config::default(),
+    };
+    if let Some(name) = &cli.profile {
+        say!("🗂️  Using profile '{}'", name);
+    }
+    match &cli.command {
+        Some(Command::Buses) => {
+            list_buses(&config);
+            return Ok(());
+        }
+        Some(Command::BoardInfo) => {
+            return board_info();
+        }
+        Some(Command::Pins) => {
+            return pin_states();
+        }
+        Some(Command::List { what: ListCommand::Drivers }) => {
+            list_drivers();
+            return Ok(());
+        }
+        Some(Command::List { what: ListCommand::Devices { probe } }) => {
+            return list_devices(&config, *probe && !cli.dry_run);
+        }
+        Some(Command::List { what: ListCommand::Startup }) => {
+            return list_startup(&config);
+        }
+        Some(Command::List { what: ListCommand::Presets }) => {
+            list_presets();
+            return Ok(());
+        }
+        Some(Command::List { what: ListCommand::Input }) => {
+            return list_input();
+        }
+        Some(Command::Totals { what }) => {
+            return saved_totals(&config, what);
+        }
+        Some(Command::Fleet { what }) => {
+            return fleet(&config, what);
+        }
+        Some(Command::Onewire { what }) => {
+            return one_wire(what);
+        }
+        Some(Command::Leds { count, spi, brightness, no_gamma, rgb, apa102, what }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not driving the strip on SPI{}", spi);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Spi { bus: *spi, cs: 0 }, "the LED strip")?;
+            let mut strip: Box<dyn Strip> = if *apa102 {
+                Box::new(Apa102::from_spi(*spi, *count)?)
+            } else {
+                let mut strip = Ws2812::from_spi(*spi, *count)?;
+                strip.set_order(if *rgb { ColorOrder::Rgb } else { ColorOrder::Grb });
+                Box::new(strip)
+            };
+            strip.set_brightness(*brightness);
+            strip.set_gamma(!no_gamma);
+            return leds(&mut *strip, &config, what);
+        }
+        Some(Command::Max7219 { devices, spi, cs, intensity, far_first, flip, what }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not driving the MAX7219 chain on SPI{}.{}", spi, cs);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Spi { bus: *spi, cs: *cs }, "the MAX7219 chain")?;
+            let order = if *far_first { ChainOrder::FarthestFirst } else { ChainOrder::NearestFirst };
+            let mut display = Max7219::from_spi(*spi, *cs, *devices, order)?;
+            display.set_intensity(*intensity)?;
+            display.set_flipped(*flip);
+            return max7219(&mut display, what);
+        }
+        Some(Command::Tm1637 { clk, dio, brightness, what }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not driving the TM1637 on GPIO {} and {}", clk, dio);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _clk = peripherals.claim(Resource::Pin(*clk), "the TM1637 clock")?;
+            let _dio = peripherals.claim(Resource::Pin(*dio), "the TM1637 data line")?;
+            let mut display = Tm1637::from_gpio(*clk, *dio)?;
+            display.set_brightness(*brightness)?;
+            match what {
+                Tm1637Command::Show { text } => display.show(text)?,
+                Tm1637Command::Number { value, decimals } => display.show_number(*value, *decimals)?,
+                Tm1637Command::Clock { twelve_hour } => {
+                    let shutdown = Shutdown::install()?;
+                    say!("🕒 Showing the time, Ctrl-C to stop");
+                    display.clock(*twelve_hour, &shutdown.flag())?;
+                    display.clear()?;
+                }
+                Tm1637Command::Clear => display.clear()?,
+            }
+            return Ok(());
+        }
+        Some(Command::Adc { spi, cs, vref, what }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not reading the MCP3008 on SPI{}.{}", spi, cs);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Spi { bus: *spi, cs: *cs }, "the MCP3008")?;
+            let mut adc = Mcp3008::from_spi(*spi, *cs)?;
+            return adc_command(&mut adc, *vref, what);
+        }
+        Some(Command::Can { spi, cs, bitrate, oscillator, loopback, listen_only, what }) => {
+            let mode = match (loopback, listen_only) {
+                (true, _) => OperatingMode::Loopback,
+                (_, true) => OperatingMode::ListenOnly,
+                _ => OperatingMode::Normal,
+            };
+            let timing = BitTiming::new(*oscillator, *bitrate)?;
+            if cli.dry_run {
+                say!(
+                    "🧪 Dry run: not opening the MCP2515 on SPI{}.{} ({} bit/s in {} mode, {} quanta, sampled at {:.1}%)",
+                    spi, cs, bitrate, mode, timing.quanta(), timing.sample_point()
+                );
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Spi { bus: *spi, cs: *cs }, "the MCP2515")?;
+            let mut can = Mcp2515::from_spi(*spi, *cs)?;
+            can.init(*oscillator, *bitrate, mode)?;
+            say!("🚌 MCP2515 on SPI{}.{}: {} bit/s, {} mode", spi, cs, bitrate, mode);
+            return can_command(&mut can, what);
+        }
+        Some(Command::Rs485 { port, baud, de, pre_delay, post_delay, what }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not opening {} at {} baud (DE on GPIO {})", port.display(), baud, de);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Pin(*de), "RS-485 DE/RE")?;
+            let mut bus = Rs485::open(port, *baud, *de)?;
+            bus.set_pre_delay(*pre_delay);
+            bus.set_post_delay(*post_delay);
+            match what {
+                Rs485Command::Send { bytes } => {
+                    bus.transmit(bytes)?;
+                    say!("📤 {:02X?}", bytes);
+                }
+                Rs485Command::Request { bytes, timeout, gap, max } => {
+                    let reply = bus.request(bytes, *max, *gap, *timeout)?;
+                    say!("📤 {:02X?}", bytes);
+                    if reply.is_empty() {
+                        return Err(TimedOut { waiting_for: format!("an RS-485 reply on {}", port.display()), after: *timeout }.into());
+                    }
+                    say!("📥 {:02X?}", reply);
+                }
+                Rs485Command::Listen => {
+                    let shutdown = Shutdown::install()?;
+                    say!("👂 Listening on {} at {} baud, Ctrl-C to stop", port.display(), baud);
+                    let mut buffer = [0; 256];
+                    while !shutdown.requested() {
+                        match bus.read(&mut buffer) {
+                            Ok(0) => {}
+                            Ok(n) => say!("📥 {:02X?}", &buffer[..n]),
+                            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::Modbus { port, baud, de, timeout, attempts, what }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not opening {} at {} baud", port.display(), baud);
+                return Ok(());
+            }
+            // Short port reads, so the master's own timeout decides
+            let poll = Duration::from_millis(20);
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            return match de {
+                Some(de) => {
+                    let _claim = peripherals.claim(Resource::Pin(*de), "RS-485 DE/RE")?;
+                    let mut bus = Rs485::open(port, *baud, *de)?;
+                    bus.port().set_read_timeout(poll)?;
+                    let mut master = RtuMaster::new(bus, *baud)?;
+                    master.set_timeout(*timeout);
+                    master.set_attempts(*attempts);
+                    modbus_command(&mut master, what)
+                }
+                None => {
+                    let mut serial = SerialPort::open(port, *baud)?;
+                    serial.set_read_timeout(poll)?;
+                    let mut master = RtuMaster::new(serial, *baud)?;
+                    master.set_timeout(*timeout);
+                    master.set_attempts(*attempts);
+                    modbus_command(&mut master, what)
+                }
+            };
+        }
+        Some(Command::Gps { port, baud, file, json, lcd: false, count, .. }) => {
+            let mut gps = open_gps(port, *baud, file.as_deref())?;
+            return gps_loop(&mut gps, file.is_some(), *json, *count, &Shutdown::install()?, |_| Ok(()));
+        }
+        Some(Command::Ir { pin }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not listening for IR on GPIO {}", pin);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Pin(*pin), "the IR receiver")?;
+            let shutdown = Shutdown::install()?;
+            let (_receiver, events) = IrReceiver::with_channel(*pin)?;
+            say!("📡 Listening for NEC remotes on GPIO {}, Ctrl-C to stop", pin);
+            while !shutdown.requested() {
+                match events.recv_timeout(Duration::from_millis(100)) {
+                    Ok(event) => say!("   {}", event),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::Measure { pin, gate, watch }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not measuring GPIO {}", pin);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Pin(*pin), "measure")?;
+            let shutdown = Shutdown::install()?;
+            loop {
+                say!("📏 GPIO {}: {}", pin, measure::measure(*pin, *gate)?);
+                if !*watch || shutdown.requested() {
+                    return Ok(());
+                }
+            }
+        }
+        Some(Command::Pwm { pin, frequency, duty, duration }) => {
+            if cli.dry_run {
+                say!("🧪 Dry run: not driving PWM on GPIO {}", pin);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Pin(*pin), "software PWM")?;
+            let shutdown = Shutdown::install()?;
+            let pwm = SoftPwm::from_gpio(*pin, *frequency, *duty)?;
+            say!("〰️  GPIO {}: {} Hz at {:.0}% duty, Ctrl-C to stop", pin, frequency, duty * 100.0);
+            match duration {
+                Some(duration) => {
+                    shutdown.sleep(*duration);
+                }
+                None => {
+                    while shutdown.sleep(Duration::from_secs(1)) {}
+                }
+            }
+            pwm.stop()?;
+            return Ok(());
+        }
+        Some(Command::Morse { text, pin, wpm, farnsworth, tone, active_low }) => {
+            let timing = MorseTiming { wpm: *wpm, farnsworth: *farnsworth };
+            let length = morse::duration(text, &timing)?;
+            let spelled = morse::spell(text)?;
+            if cli.dry_run {
+                say!("🧪 Dry run: not keying GPIO {}; would send {} ({:.1} s):", pin, text, length.as_secs_f64());
+                say!("   {}", spelled);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Pin(*pin), "Morse")?;
+            let shutdown = Shutdown::install()?;
+            say!("📡 GPIO {}: {} at {} WPM ({:.1} s), Ctrl-C to stop", pin, text, wpm, length.as_secs_f64());
+            say!("   {}", spelled);
+            let stop = shutdown.flag();
+            match tone {
+                Some(hz) => {
+                    let mut buzzer = SoftPwm::from_gpio(*pin, *hz, 0.0)?;
+                    morse::send(&mut buzzer, text, &timing, &stop)?;
+                    buzzer.stop()?;
+                }
+                None => morse::send(&mut PinKeyer::from_gpio(*pin, *active_low)?, text, &timing, &stop)?,
+            }
+            return Ok(());
+        }
+        Some(Command::Buzzer { what: BuzzerCommand::Play { song, pin, speed } }) => {
+            let tune = melody::load(song)?.faster(*speed)?;
+            let length = tune.duration().as_secs_f64();
+            if cli.dry_run {
+                say!("🧪 Dry run: not sounding GPIO {}; would play '{}', {} notes ({:.1} s):", pin, tune.name, tune.notes.len(), length);
+                let notes: Vec<_> = tune.notes.iter().map(|note| note.to_string()).collect();
+                say!("   {}", notes.join(" "));
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claim = peripherals.claim(Resource::Pin(*pin), "the buzzer")?;
+            let shutdown = Shutdown::install()?;
+            say!("🎵 GPIO {}: '{}', {} notes ({:.1} s), Ctrl-C to stop", pin, tune.name, tune.notes.len(), length);
+            let mut buzzer = SoftPwm::from_gpio(*pin, 440.0, 0.0)?;
+            melody::play(&mut buzzer, &tune, &shutdown.flag())?;
+            buzzer.stop()?;
+            return Ok(());
+        }
+        Some(Command::Slave { map, address }) => {
+            let mut map = match map {
+                Some(path) => SlaveMap::load(path)?,
+                None => SlaveMap {
+                    address: address.ok_or("give a register map or --address")?,
+                    registers: Vec::new(),
+                },
+            };
+            if let Some(address) = address {
+                map.address = *address;
+            }
+            let address = map.address()?;
+            if cli.dry_run {
+                say!("🧪 Dry run: would answer at {} with:", address);
+                for entry in &map.registers {
+                    let access = if entry.read_only { "read-only" } else { "read/write" };
+                    say!("   0x{:02X} {:02X?} {}", entry.register, entry.value, access);
+                }
+                return Ok(());
+            }
+            let board = Board::detect()?;
+            let (sda, scl) = BscSlave::pins(board.soc).ok_or_else(|| format!("the {} has no I2C slave peripheral", board.soc))?;
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claims = peripherals.claim_all(&[Resource::Pin(sda), Resource::Pin(scl)], "the I2C slave")?;
+            let shutdown = Shutdown::install()?;
+            let mut slave = SlaveEmulator::open(&map)?;
+            say!("🎭 Answering at {} on SDA GPIO {}, SCL GPIO {}; Ctrl-C to stop", address, sda, scl);
+            slave.run(&shutdown.flag(), |event| say!("   {}", event))?;
+            return Ok(());
+        }
+        Some(Command::Receive { address }) => {
+            let address = Address::seven_bit(*address)?;
+            if cli.dry_run {
+                say!("🧪 Dry run: would take frames at {}", address);
+                return Ok(());
+            }
+            let board = Board::detect()?;
+            let (sda, scl) = BscSlave::pins(board.soc).ok_or_else(|| format!("the {} has no I2C slave peripheral", board.soc))?;
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claims = peripherals.claim_all(&[Resource::Pin(sda), Resource::Pin(scl)], "the frame receiver")?;
+            let shutdown = Shutdown::install()?;
+            let mut receiver = FrameReceiver::open(address)?;
+            say!("📥 Taking frames at {} on SDA GPIO {}, SCL GPIO {}; Ctrl-C to stop", address, sda, scl);
+            let mut rejected = 0;
+            while let Some(frame) = receiver.recv_frame(&shutdown.flag())? {
+                if receiver.rejected() > rejected {
+                    rejected = receiver.rejected();
+                    if let Some(e) = receiver.last_error() {
+                        say!("   ❌ NACKed a frame: {}", e);
+                    }
+                }
+                say!("   ✅ Frame {}: {:02X?} {:?}", frame.seq, frame.payload, String::from_utf8_lossy(&frame.payload));
+            }
+            say!("📊 {} frames NACKed, {} resends ACKed again", receiver.rejected(), receiver.duplicates());
+            return Ok(());
+        }
+        Some(Command::Analyze { sda, scl, duration, events, save }) => {
+            if sda == scl {
+                return Err("SDA and SCL must be different GPIOs".into());
+            }
+            if cli.dry_run {
+                say!("🧪 Dry run: would listen on SDA GPIO {}, SCL GPIO {}", sda, scl);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claims = peripherals.claim_all(&[Resource::Pin(*sda), Resource::Pin(*scl)], "the analyzer")?;
+            let shutdown = Shutdown::install()?;
+            if cli.realtime {
+                let cpu = timing::enable_realtime(Realtime { cpu: cli.cpu, ..Realtime::default() })?;
+                say!("⚡ Real-time scheduling: SCHED_FIFO on CPU {}", cpu);
+            }
+            let mut analyzer = Analyzer::from_gpio(*sda, *scl)?;
+            let mut transactions = Transactions::new();
+            let mut last = Duration::ZERO;
+            say!("🔬 Listening on SDA GPIO {}, SCL GPIO {}; Ctrl-C to stop", sda, scl);
+            analyzer.run(&shutdown.flag(), *duration, |timed: TimedEvent| {
+                last = timed.at;
+                if *events {
+                    say!("   {:>12.6} s  {}", timed.at.as_secs_f64(), timed.event);
+                }
+                if let Some(entry) = transactions.push(timed) {
+                    if !*events {
+                        let mark = if entry.is_ok() { "✅" } else { "❌" };
+                        say!("   {} {:>12.6} s  {}", mark, entry.timestamp.as_secs_f64(), trace::summary(&entry));
+                    }
+                }
+            });
+            let trace = transactions.into_trace(last);
+            let failed = trace.transactions.iter().filter(|entry| !entry.is_ok()).count();
+            say!("📊 {} transactions, {} failed", trace.transactions.len(), failed);
+            if analyzer.missed() > 0 {
+                say!("⚠️  {} edges came too fast to tell apart; the decode near them may be wrong", analyzer.missed());
+            }
+            if let Some(path) = save {
+                trace.save(path)?;
+                say!("💾 Saved the capture to {}", path.display());
+            }
+            return Ok(());
+        }
+        Some(Command::Servo { min, max, travel, hardware, hold, what }) => {
+            let pin = match what {
+                ServoCommand::Set { pin, .. } | ServoCommand::Pulse { pin, .. } => *pin,
+            };
+            if cli.dry_run {
+                say!("🧪 Dry run: not driving the servo on GPIO {}", pin);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let mut claims = vec![peripherals.claim(Resource::Pin(pin), "the servo")?];
+            let out: Box<dyn PulseOutput + Send> = if *hardware {
+                let board = Board::detect()?;
+                let channel = board
+                    .pwm_channels()
+                    .iter()
+                    .find(|c| c.pins.contains(&pin))
+                    .ok_or_else(|| format!("GPIO {} has no hardware PWM channel on this board; drop --hardware", pin))?;
+                claims.push(peripherals.claim(Resource::Pwm(channel.channel), "the servo")?);
+                Box::new(Servo::from_pwm(channel.channel)?.release())
+            } else {
+                Box::new(Servo::from_gpio(pin)?.release())
+            };
+            let mut servo = Servo::new(out);
+            servo.set_range(*min, *max)?;
+            servo.set_travel(*travel)?;
+            match what {
+                ServoCommand::Set { angle, .. } => {
+                    let width = servo.set_angle(*angle)?;
+                    say!("🦾 GPIO {} to {}° ({}µs pulse)", pin, angle, width.as_micros());
+                }
+                ServoCommand::Pulse { width, .. } => {
+                    servo.set_pulse(*width)?;
+                    say!("🦾 GPIO {}: {}µs pulse", pin, width.as_micros());
+                }
+            }
+            Shutdown::install()?.sleep(*hold);
+            return servo.detach();
+        }
+        Some(Command::Stepper { pins, mode, speed, start_speed, accel, hold, what }) => {
+            let StepperCommand::Move { steps } = what;
+            let pins: [u8; 4] = pins.as_slice().try_into().map_err(|_| "--pins takes four pins, IN1 to IN4")?;
+            if cli.dry_run {
+                say!("🧪 Dry run: not stepping the motor on GPIO {:?}", pins);
+                return Ok(());
+            }
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let _claims = peripherals.claim_all(&pins.map(Resource::Pin), "the stepper")?;
+            let mut stepper = Stepper::from_gpio(pins)?;
+            stepper.set_mode(*mode);
+            stepper.set_ramp(Ramp {
+                start_speed: *start_speed,
+                max_speed: *speed,
+                acceleration: *accel,
+            })?;
+            let shutdown = Shutdown::install()?;
+            say!("⚙️  {} {} steps, up to {} steps/s", steps, mode, speed);
+            let started = Instant::now();
+            let moved = stepper.move_by(*steps, &shutdown.flag())?;
+            if !*hold {
+                stepper.power_down()?;
+            }
+            if moved == *steps {
+                say!("✅ Moved {} steps in {:.2}s", moved, started.elapsed().as_secs_f64());
+            } else {
+                say!("⏹️  Stopped after {} of {} steps", moved, steps);
+            }
+            return Ok(());
+        }
+        Some(Command::Alert { severity, message, key }) => {
+            let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+            let (mut alerter, _claims) = alerter(&config, &peripherals, cli.dry_run)?;
+            let alert = Alert::new(key.clone(), *severity, message.clone());
+            let outputs = alerter.raise(&alert)?;
+            if outputs.is_empty() {
+                say!("🔕 {} alert went nowhere: rate-limited, quiet hours, or no outputs attached", severity);
+                return Ok(());
+            }
+            let names: Vec<String> = outputs.iter().map(ToString::to_string).collect();
+            say!("📣 {} alert sent to {}", severity, names.join(", "));
+            // Let the buzzer play before the LED goes back off
+            let pattern = alerter.config().level(*severity).buzzer.map(|p| p.duration()).unwrap_or_default();
+            std::thread::sleep(pattern + Duration::from_secs(1));
+            return alerter.clear(key);
+        }
+        Some(Command::Noise { device, mic, rate, right, calibration, interval, count }) => {
+            let mic = match mic {
+                Mic::Sph0645 => Microphone::Sph0645,
+                Mic::Inmp441 => Microphone::Inmp441,
+            };
+            if cli.dry_run {
+                say!("🧪 Dry run: not recording from {} on {}", mic, device);
+                return Ok(());
+            }
+            return noise(device, mic, *rate, *right, *calibration, *interval, *count);
+        }
+        Some(Command::Plan { state }) => {
+            return show_plan(&cli, &config, state);
+        }
+        Some(Command::Power { what: what @ (PowerCommand::On { rail } | PowerCommand::Off { rail }) }) => {
+            let on = matches!(what, PowerCommand::On { .. });
+            let rail = configured_rail(&config, rail)?;
+            if cli.dry_run {
+                say!("🧪 Dry run: not switching rail '{}' {}", rail.name, if on { "on" } else { "off" });
+                return Ok(());
+            }
+            // Every rail's GPIO is taken over as it is, so only this one moves
+            let mut power = PowerManager::from_config(&config)?;
+            if on {
+                power.rail_on(&rail.name)?;
+            } else {
+                power.rail_off(&rail.name)?;
+            }
+            say!("✅ rail '{}' {}", rail.name, if on { "on" } else { "off" });
+            return Ok(());
+        }
+        Some(Command::Sysinfo { print: true, .. }) => {
+            print_sysinfo(&config)?;
+            return Ok(());
+        }
+        Some(Command::Replay { .. })
+        | Some(Command::Scan { .. })
+        | Some(Command::Verify { .. })
+        | Some(Command::Run { .. })
+        | Some(Command::Serve { .. })
+        | Some(Command::Proxy { .. })
+        | Some(Command::Apply { .. })
+        | Some(Command::Publish { .. })
+        | Some(Command::Monitor { .. })
+        | Some(Command::WaitFor { .. })
+        | Some(Command::Power { what: PowerCommand::Cycle { .. } })
+        | Some(Command::Soak { .. })
+        | Some(Command::Stress { .. })
+        | Some(Command::Pattern { .. })
+        | Some(Command::Inventory { .. })
+        | Some(Command::Bench { .. })
+        | Some(Command::Stretch { .. })
+        | Some(Command::Dump { .. })
+        | Some(Command::Eeprom { .. })
+        | Some(Command::Flash { .. })
+        | Some(Command::Preflight)
+        | Some(Command::Selftest { .. })
+        | Some(Command::Lcd { .. })
+        | Some(Command::App { .. })
+        | Some(Command::PowerMonitor(_))
+        | Some(Command::Sysinfo { .. })
+        | Some(Command::Gps { .. })
+        | Some(Command::Apds9960 { .. })
+        | Some(Command::Log { .. })
+        | Some(Command::Repl)
+        | Some(Command::FactoryTest { .. })
+        | Some(Command::Completions { .. })
+        | Some(Command::Trace { .. })
+        | None => {}
+    }
+
+    // --bus, else the first configured bus; its speed is what --speed defaults to
+    let bus_id = cli.bus.or(config.buses.first().map(|b| b.id)).unwrap_or(1);
+    let bus_config = config.buses.iter().find(|b| b.id == bus_id);
+    let expected_speed = cli.speed.or(bus_config.and_then(|b| b.speed));
+    if !cli.stub_device.is_empty() && cli.backend != BackendKind::Stub {
+        return Err("--stub-device needs --backend stub".into());
+    }
+    let stub_devices = cli.stub_device.iter().map(|path| SlaveMap::load(path)).collect::<Result<_, _>>()?;
+    let target = BusTarget {
+        id: bus_id,
+        soft: cli.soft_i2c,
+        speed: expected_speed,
+        dry_run: cli.dry_run,
+        remote: cli.remote.clone(),
+        remote_token: cli.remote_token.clone(),
+        backend: cli.backend,
+        stub_devices,
+        faults: cli.inject.clone(),
+    };
+    if let Some(Command::Preflight) = &cli.command {
+        let report = preflight::check_bus(bus_id);
+        if report.passed() {
+            say!("{}", report);
+            return Ok(());
+        }
+        return Err(report.into());
+    }
+
+    if cli.dry_run && cli.trigger_pin.take().is_some() {
+        say!("🧪 Dry run: not pulsing --trigger-pin");
+    }
+
+    // Catch pin clashes (say --trigger-pin 2 on bus 1) before anything is driven
+    let peripherals = Peripherals::take().ok_or("peripherals were already taken")?;
+    let nothing_to_claim = cli.dry_run || target.remote.is_some() || target.backend == BackendKind::Stub;
+    let _bus_claim = match (nothing_to_claim, target.soft) {
+        (true, _) => None,
+        (false, Some((sda, scl))) => Some(peripherals.claim_all(&[Resource::Pin(sda), Resource::Pin(scl)], "--soft-i2c")?),
+        (false, None) => Some(peripherals.claim_i2c(target.id, "the I2C bus")?),
+    };
+    let _trigger_claim = cli
+        .trigger_pin
+        .map(|pin| peripherals.claim(Resource::Pin(pin), "--trigger-pin"))
+        .transpose()?;
+
+    if cli.realtime {
+        let cpu = timing::enable_realtime(Realtime { cpu: cli.cpu, ..Realtime::default() })?;
+        say!("⚡ Real-time scheduling: SCHED_FIFO on CPU {}", cpu);
+    }
+
+    if let Some(Command::Replay { trace, timing }) = &cli.command {
+        let job = ReplayJob {
+            trace: Trace::load(trace)?,
+            timing: *timing,
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Repl) = &cli.command {
+        let job = ReplJob { timeout: cli.timeout };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::FactoryTest { plan, serial, report }) = &cli.command {
+        let plan = TestPlan::load(plan)?;
+        if cli.non_interactive {
+            if serial.is_none() {
+                return Err("factory-test needs --serial under --non-interactive".into());
+            }
+            if let Some(Step::Prompt { message }) = plan.steps.iter().find(|s| matches!(s, Step::Prompt { .. })) {
+                return Err(format!("prompt step '{}' needs an operator, which --non-interactive rules out", message).into());
+            }
+        }
+        let job = FactoryJob {
+            plan,
+            serial: serial.clone(),
+            report: report.clone(),
+            units: config.units,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Selftest { full, loopback }) = &cli.command {
+        if cli.dry_run && !loopback.is_empty() {
+            say!("🧪 Dry run: not driving the --loopback pins");
+        }
+        let loopbacks = if cli.dry_run { Vec::new() } else { loopback.clone() };
+        let mut claims = Vec::new();
+        for pair in &loopbacks {
+            claims.push(
+                peripherals.claim_all(&[Resource::Pin(pair.output), Resource::Pin(pair.input)], "--loopback")?,
+            );
+        }
+        let job = SelftestJob {
+            devices: config.devices.iter().filter(|d| d.bus == bus_id).cloned().collect(),
+            full: *full,
+            loopbacks,
+            _claims: claims,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Run { script }) = &cli.command {
+        let job = ScriptJob {
+            script: Script::load(script)?,
+            timeout: cli.timeout,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Pattern { file, repeat, period, spin }) = &cli.command {
+        let pattern = Pattern::load(file)?;
+        let pins = pattern.pins();
+        let setup = PatternSetup {
+            period: period.unwrap_or(pattern.length() + Duration::from_millis(10)),
+            pattern,
+            repeat: *repeat,
+            delay: PreciseDelay::new(*spin),
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        if cli.dry_run {
+            say!("🧪 Dry run: printing the GPIO events instead of driving GPIO {:?}", pins);
+            return with_bus(&target, cli.record.as_deref(), PatternJob { setup, channels: PrintedPins });
+        }
+        let _claims = peripherals.claim_all(&pins.iter().map(|&pin| Resource::Pin(pin)).collect::<Vec<_>>(), "the pattern")?;
+        let channels = GpioChannels::claim(&pins)?;
+        return with_bus(&target, cli.record.as_deref(), PatternJob { setup, channels });
+    }
+    if let Some(Command::Serve { port, bind, tls_cert, tls_key, allow_plaintext, tokens, retries }) = &cli.command {
+        let tls = match (tls_cert, tls_key) {
+            (Some(cert), Some(key)) => Some(server::load_tls(cert, key)?),
+            _ => None,
+        };
+        if !auth::is_loopback(bind) && tokens.is_none() {
+            return Err(format!("--bind {} needs --tokens, or anyone who can reach it controls the bus", bind).into());
+        }
+        if tls.is_none() && !auth::is_loopback(bind) {
+            if !*allow_plaintext {
+                return Err(format!(
+                    "without --tls-cert, --bind {} would send tokens in the clear; \
+                     give a certificate, tunnel to 127.0.0.1 over SSH, or pass --allow-plaintext",
+                    bind
+                )
+                .into());
+            }
+            say!("⚠️  No TLS: tokens and readings go over {} in the clear", bind);
+        }
+        let history = History::new(config.history.clone());
+        let job = ServeJob {
+            listen: format!("{}:{}", bind, port),
+            tls,
+            retries: *retries,
+            tokens: tokens.as_deref().map(TokenStore::load).transpose()?,
+            history: history.clone(),
+            watches: Watches::from_config(&config.watches, history.clone())?,
+            totals: Totals::from_config(&config.totals, history)?,
+            watcher: cli.config.as_deref().map(|path| ConfigWatcher::new(path, cli.profile.as_deref())).transpose()?,
+            config,
+            peripherals,
+            bus: bus_id,
+            dry_run: cli.dry_run,
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Proxy { port, bind, allow_plaintext, tokens }) = &cli.command {
+        if !auth::is_loopback(bind) {
+            if !*allow_plaintext {
+                return Err(format!(
+                    "the proxy has no TLS, so --bind {} would send tokens and bus traffic in the clear; \
+                     tunnel to 127.0.0.1 over SSH, or pass --allow-plaintext",
+                    bind
+                )
+                .into());
+            }
+            if tokens.is_none() {
+                return Err(format!("--bind {} needs --tokens, or anyone who can reach it controls the bus", bind).into());
+            }
+            say!("⚠️  No TLS: tokens and bus traffic go over {} in the clear", bind);
+        }
+        let job = ProxyJob {
+            listen: format!("{}:{}", bind, port),
+            tokens: tokens.as_deref().map(TokenStore::load).transpose()?,
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Apply { state, yes }) = &cli.command {
+        let path = cli.config.as_deref().ok_or("apply needs --config")?;
+        let plan = Plan::new(plan::load_applied(state)?.as_ref(), &config)?;
+        term::write(&plan.to_string(), Stream::Stdout);
+        if !plan.conflicts.is_empty() {
+            return Err(format!("{} conflicting claims; nothing applied", plan.conflicts.len()).into());
+        }
+        if plan.is_empty() {
+            say!("✅ Nothing to apply");
+            return Ok(());
+        }
+        if !*yes {
+            if cli.non_interactive {
+                return Err("not applying without --yes under --non-interactive".into());
+            }
+            if !ask("\nApply? [y/N] ")?.eq_ignore_ascii_case("y") {
+                say!("Nothing applied");
+                return Ok(());
+            }
+        }
+        let switches_rails = plan.actions.iter().any(|a| matches!(a, Action::RailOn(_)));
+        let job = ApplyJob {
+            power: if switches_rails && !cli.dry_run { Some(PowerManager::from_config(&config)?) } else { None },
+            plan,
+            state: state.clone(),
+            config_text: std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+            profile: cli.profile.clone(),
+            bus: bus_id,
+            dry_run: cli.dry_run,
+            timeout: cli.timeout,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Publish { metrics_port, metrics_bind }) = &cli.command {
+        let mqtt = config.mqtt.clone().ok_or("no [mqtt] section in the config")?;
+        let job = PublishJob {
+            publisher: Publisher::new(mqtt)?,
+            interval: config.monitor.interval,
+            metrics_port: *metrics_port,
+            metrics_bind: metrics_bind.clone(),
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Monitor { addresses, interval, metrics_port, metrics_bind }) = &cli.command {
+        let watched = watched_devices(&config, bus_id, addresses)?;
+        let (alerter, alert_claims) = alerter(&config, &peripherals, cli.dry_run)?;
+        // Devices here on a switched rail get power-cycled when they keep failing
+        let powered: Vec<DeviceConfig> = config
+            .devices
+            .iter()
+            .filter(|d| d.bus == bus_id && d.address.is_some() && d.rail.is_some())
+            .cloned()
+            .collect();
+        let (power, rail_claim) = if powered.is_empty() || nothing_to_claim {
+            (None, None)
+        } else {
+            let pins: Vec<Resource> = config.rails.iter().map(|r| Resource::Pin(r.pin)).collect();
+            let claim = peripherals.claim_all(&pins, "the power rails")?;
+            (Some(PowerManager::from_config(&config)?), Some(claim))
+        };
+        let reload = match &cli.config {
+            Some(path) => {
+                let mut watcher = ConfigWatcher::new(path, cli.profile.as_deref())?;
+                let extra = addresses.clone();
+                // Turn down a config the loop couldn't rebuild from, so the old one stays
+                watcher.on_change(move |config| {
+                    watched_devices(config, bus_id, &extra)?;
+                    Alerter::new(config.alerts.clone())?;
+                    Ok(())
+                });
+                Some(MonitorReload {
+                    watcher,
+                    applied: config.clone(),
+                    bus: bus_id,
+                    addresses: addresses.clone(),
+                    interval: *interval,
+                    peripherals,
+                    dry_run: cli.dry_run,
+                })
+            }
+            None => None,
+        };
+        let job = MonitorJob {
+            presence: Presence::new(watched, config.monitor.recover_after),
+            alerter,
+            alert_claims,
+            power,
+            powered,
+            _rail_claim: rail_claim,
+            policy: config.watchdog.clone(),
+            interval: interval.unwrap_or(config.monitor.interval),
+            reload,
+            metrics_port: *metrics_port,
+            metrics_bind: metrics_bind.clone(),
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Lcd { what: LcdCommand::Backlight { state, address, times, on, off } }) = &cli.command {
+        let job = BacklightJob {
+            address: address.or(configured_lcd(&config, bus_id)?),
+            state: *state,
+            flash: Flash::new(*times, *on, *off),
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Lcd { what: LcdCommand::Tune { address, rounds } }) = &cli.command {
+        let job = LcdTuneJob {
+            address: address.or(configured_lcd(&config, bus_id)?),
+            delays: configured_lcd_delays(&config, bus_id),
+            rounds: *rounds,
+            timeout: cli.timeout,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::App { what: AppCommand::Energy(energy) } | Command::PowerMonitor(energy)) = &cli.command {
+        let EnergyArgs {
+            device,
+            chip,
+            address,
+            shunt,
+            max_current,
+            interval,
+            price,
+            currency,
+            state,
+            log,
+            metrics_port,
+            metrics_bind,
+        } = energy;
+        let (name, chip, address) = match device {
+            Some(name) => {
+                let d = config.device(name).ok_or_else(|| format!("no device '{}' in the config", name))?;
+                let chip = match d.driver.as_str() {
+                    "ina219" => PowerChip::Ina219,
+                    "ina226" => PowerChip::Ina226,
+                    other => return Err(format!("device '{}' is a {}, not an ina219 or ina226", name, other).into()),
+                };
+                let address = d.address.map(Address::from_raw).transpose()?;
+                (name.clone(), chip, address.unwrap_or(Address::SevenBit(INA_DEFAULT_ADDRESS)))
+            }
+            None => {
+                let name = match chip {
+                    PowerChip::Ina219 => "ina219",
+                    PowerChip::Ina226 => "ina226",
+                };
+                (name.to_string(), *chip, *address)
+            }
+        };
+        let job = EnergyJob {
+            name,
+            chip,
+            address,
+            shunt: *shunt,
+            max_current: *max_current,
+            interval: *interval,
+            tariff: price.map(|price| Tariff { price, currency: currency.clone() }),
+            state: state.clone().or_else(|| config.totals.state.clone()),
+            log: log.clone(),
+            metrics_port: *metrics_port,
+            metrics_bind: metrics_bind.clone(),
+            publisher: config.mqtt.clone().map(Publisher::new).transpose()?,
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Sysinfo { address, cols, rows, refresh, print: _, hid, ir }) = &cli.command {
+        let job = SysinfoJob {
+            address: address.or(configured_lcd(&config, bus_id)?),
+            delays: configured_lcd_delays(&config, bus_id),
+            cols: *cols,
+            rows: *rows,
+            refresh: *refresh,
+            pages: sysinfo_pages(&config),
+            watcher: match &cli.config {
+                Some(path) => {
+                    let mut watcher = ConfigWatcher::new(path, cli.profile.as_deref())?;
+                    // Turn down pages that don't parse, so the old ones stay up
+                    watcher.on_change(|config| {
+                        for page in &config.pages {
+                            Screen::new(&page.lines)?;
+                        }
+                        Ok(())
+                    });
+                    Some(watcher)
+                }
+                None => None,
+            },
+            units: config.units,
+            hid: hid.as_deref().map(HidInput::open).transpose()?,
+            ir: *ir,
+            timeout: cli.timeout,
+            lockups: config.watchdog.lockups,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Gps { port, baud, file, json, address, count, .. }) = &cli.command {
+        let job = GpsJob {
+            gps: open_gps(port, *baud, file.as_deref())?,
+            replay: file.is_some(),
+            json: *json,
+            count: *count,
+            address: address.or(configured_lcd(&config, bus_id)?),
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Scan { identify, conflicts }) = &cli.command {
+        let job = ScanJob {
+            bus: bus_id,
+            identify: *identify,
+            conflicts: *conflicts,
+            timeout: cli.timeout,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Log { output, devices, interval, format, max_size, daily, flush, count }) = &cli.command {
+        let devices = if devices.is_empty() {
+            let found: Vec<_> = config
+                .devices
+                .iter()
+                .filter(|d| d.bus == bus_id && datalog::LOGGED_DRIVERS.contains(&d.driver.as_str()))
+                .cloned()
+                .collect();
+            if found.is_empty() {
+                return Err(format!("no device on bus {} to log; configure one of: {}", bus_id, datalog::LOGGED_DRIVERS.join(", ")).into());
+            }
+            found
+        } else {
+            devices
+                .iter()
+                .map(|name| match config.device(name) {
+                    Some(d) if d.bus != bus_id => Err(format!("device '{}' is on bus {}; pass --bus {}", name, d.bus, d.bus)),
+                    Some(d) => Ok(d.clone()),
+                    None => Err(format!("no device '{}' in the config", name)),
+                })
+                .collect::<Result<_, _>>()?
+        };
+        let job = LogJob {
+            devices,
+            output: output.clone(),
+            format: format.unwrap_or_else(|| Format::from_path(output)),
+            rotation: Rotation {
+                max_size: *max_size,
+                daily: *daily,
+            },
+            interval: *interval,
+            flush: *flush,
+            count: *count,
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Apds9960 { address, gestures, interval, count }) = &cli.command {
+        let job = Apds9960Job {
+            address: *address,
+            gestures: *gestures,
+            interval: *interval,
+            count: *count,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::WaitFor { device, timeout, interval }) = &cli.command {
+        // A configured device brings its own bus unless --bus overrides it
+        let (waiting_for, address, bus) = match config.device(device) {
+            Some(d) => {
+                let raw = d.address.ok_or_else(|| format!("device '{}' has no address", d.name))?;
+                let address = Address::from_raw(raw)?;
+                (format!("{} ({})", d.name, address), address, cli.bus.unwrap_or(d.bus))
+            }
+            None => {
+                let address = parse_address(device)
+                    .map_err(|_| format!("'{}' is neither an address nor a configured device", device))?;
+                (address.to_string(), address, bus_id)
+            }
+        };
+        let job = WaitJob {
+            waiting_for,
+            address,
+            timeout: *timeout,
+            interval: *interval,
+            shutdown: Shutdown::install()?,
+        };
+        let target = BusTarget { id: bus, ..target };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Power { what: PowerCommand::Cycle { rail, off_time, wait_for, timeout, count, interval, no_init } }) = &cli.command {
+        let rail = configured_rail(&config, rail)?;
+        // Everything on the rail loses power, whichever bus it is on
+        let (devices, elsewhere): (Vec<DeviceConfig>, Vec<DeviceConfig>) = config
+            .devices
+            .iter()
+            .filter(|d| d.rail.as_deref() == Some(rail.name.as_str()))
+            .cloned()
+            .partition(|d| d.bus == bus_id);
+        for device in &elsewhere {
+            say!("⏭️  device '{}' is on bus {}, not {}; not waiting for it", device.name, device.bus, bus_id);
+        }
+        let job = PowerCycleJob {
+            power: if cli.dry_run { None } else { Some(PowerManager::from_config(&config)?) },
+            name: rail.name.clone(),
+            off_time: off_time.unwrap_or(rail.off_time),
+            devices,
+            extra: wait_for.clone(),
+            timeout: *timeout,
+            count: *count,
+            interval: *interval,
+            init: !*no_init,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Soak { address, period, deadline, duration, read, spin, hgrm }) = &cli.command {
+        let config = SoakConfig {
+            address: *address,
+            read: *read,
+            period: *period,
+            deadline: deadline.unwrap_or(*period),
+            duration: *duration,
+            spin: *spin,
+        };
+        config.validate()?;
+        let job = SoakJob {
+            config,
+            hgrm: hgrm.clone(),
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Stress { addresses, seed, ops, mix, max_len, max_delay }) = &cli.command {
+        let seed = match seed {
+            Some(seed) => *seed,
+            None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64,
+        };
+        let config = StressConfig {
+            addresses: addresses.clone(),
+            ops: *ops,
+            seed,
+            mix: *mix,
+            max_len: *max_len,
+            max_delay: *max_delay,
+        };
+        config.validate()?;
+        let job = StressJob {
+            config,
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Bench { address, speeds, size, duration, write }) = &cli.command {
+        let job = BenchJob {
+            address: *address,
+            speeds: speeds.clone(),
+            size: *size,
+            duration: *duration,
+            mode: if *write { BenchMode::Write } else { BenchMode::Read },
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Stretch { address, register, len, reads, interval }) = &cli.command {
+        let job = StretchJob {
+            config: StretchConfig {
+                address: *address,
+                register: *register,
+                len: *len,
+                reads: u32::try_from(*reads).map_err(|_| format!("--reads {} is too many", reads))?,
+                interval: *interval,
+            },
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Dump { addr, start, len, wide, chunk, compare, output }) = &cli.command {
+        let config = DumpConfig {
+            address: *addr,
+            start: u32::from(*start),
+            len: usize::try_from(*len).map_err(|_| format!("--len {} is too long", len))?,
+            pointer_width: if *wide { 2 } else { 1 },
+            chunk: *chunk,
+        };
+        config.validate().map_err(|e| if *wide { e } else { format!("{} (--wide for two-byte offsets)", e).into() })?;
+        let expected = match compare {
+            Some(path) => Some(std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?),
+            None => None,
+        };
+        let job = DumpJob {
+            config,
+            expected,
+            output: output.clone(),
+            timeout: cli.timeout,
+            quiet: cli.quiet,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Eeprom { image, addr, start, wide, page, write_cycle, tune, verify }) = &cli.command {
+        let image_bytes = std::fs::read(image).map_err(|e| format!("{}: {}", image.display(), e))?;
+        if image_bytes.is_empty() {
+            return Err(format!("{} is empty", image.display()).into());
+        }
+        let configured = config.devices.iter().find(|d| d.bus == bus_id && d.address == Some(addr.raw())).and_then(|d| d.write_delay);
+        let config = EepromConfig {
+            address: *addr,
+            start: u32::from(*start),
+            pointer_width: if *wide { 2 } else { 1 },
+            page: *page,
+            write_cycle: write_cycle.or(configured).unwrap_or(eeprom::WRITE_CYCLE),
+            tune: *tune,
+        };
+        config.validate(image_bytes.len()).map_err(|e| if *wide { e } else { format!("{} (--wide for two-byte offsets)", e).into() })?;
+        let job = EepromJob {
+            config,
+            image: image_bytes,
+            verify: *verify,
+            timeout: cli.timeout,
+            quiet: cli.quiet,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Flash { image, addr, base, origin, chunk, attempts, resume, no_boot, busy_timeout }) = &cli.command {
+        let (address, bytes) = flash::load_image(image)?;
+        let offset = if flash::is_hex(image) {
+            address
+                .checked_sub(*origin)
+                .ok_or_else(|| format!("{} starts at 0x{:X}, below --origin 0x{:X}", image.display(), address, origin))?
+        } else {
+            *base
+        };
+        if bytes.is_empty() {
+            return Err(format!("{} is empty", image.display()).into());
+        }
+        let config = FlashConfig {
+            address: *addr,
+            chunk: *chunk,
+            attempts: *attempts,
+            busy_timeout: *busy_timeout,
+            resume: *resume,
+            boot: !*no_boot,
+            ..FlashConfig::default()
+        };
+        config.validate()?;
+        let job = FlashJob {
+            config,
+            base: offset,
+            image: bytes,
+            name: image.display().to_string(),
+            timeout: cli.timeout,
+            shutdown: Shutdown::install()?,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Inventory { what }) = &cli.command {
+        let job = match what {
+            InventoryCommand::Save { file } => SnapshotJob {
+                bus: bus_id,
+                file: file.clone(),
+                before: None,
+                update: true,
+                timeout: cli.timeout,
+            },
+            InventoryCommand::Diff { file, update } => {
+                let before = Snapshot::load(file)?;
+                if before.bus != bus_id {
+                    return Err(format!("{} is a scan of bus {}, not {}; diff it with --bus {}", file.display(), before.bus, bus_id, before.bus).into());
+                }
+                SnapshotJob {
+                    bus: bus_id,
+                    file: file.clone(),
+                    before: Some(before),
+                    update: *update,
+                    timeout: cli.timeout,
+                }
+            }
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(Command::Verify { inventory }) = &cli.command {
+        let job = VerifyJob {
+            inventory: Inventory::load(inventory)?,
+            timeout: cli.timeout,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+    if let Some(pin) = cli.trigger_pin {
+        // Bus 1's SDA/SCL are GPIO 2/3 unless the bus is bit-banged elsewhere
+        let (sda, scl) = cli.soft_i2c.unwrap_or((2, 3));
+        if pin == sda || pin == scl {
+            return Err(format!("--trigger-pin {} is one of the I2C pins", pin).into());
+        }
+    }
+
+    // Without --addr, configured device addresses are tried before the usual LCD backpack ones
+    let detection = match cli.addr.clone() {
+        Some(detection) => detection,
+        None => {
+            let mut candidates: Vec<Address> = config
+                .devices
+                .iter()
+                .filter(|d| d.bus == bus_id)
+                .filter_map(|d| Address::seven_bit(u8::try_from(d.address?).ok()?).ok())
+                .collect();
+            for address in Detection::lcd().addresses() {
+                if !candidates.contains(&address) {
+                    candidates.push(address);
+                }
+            }
+            Detection::Candidates(candidates)
+        }
+    };
+
+    if cli.preset == Preset::Staircase && cli.soft_i2c.is_none() {
+        return Err("the staircase preset changes the clock as it goes, which needs --soft-i2c".into());
+    }
+    let mut payload = match (&cli.text, &cli.hex, &cli.file, cli.stdin) {
+        (Some(text), ..) => Payload::text(text)?,
+        (_, Some(hex), ..) => Payload::hex(hex)?,
+        (_, _, Some(path), _) => Payload::file(path)?,
+        (.., true) => Payload::stdin(),
+        _ => Payload::message(),
+    };
+    if let Some(chunk) = cli.chunk {
+        payload.set_chunk(chunk)?;
+    }
+    let custom_payload = cli.text.is_some() || cli.hex.is_some() || cli.file.is_some() || cli.stdin;
+    if cli.preset != Preset::Rhythm && custom_payload {
+        return Err(format!("--text, --hex, --file and --stdin are for the rhythm preset, not {}", cli.preset).into());
+    }
+    let custom_cadence = cli.duration.is_some() || cli.repeats.is_some() || cli.gap_mode != Gap::Equal || cli.inter_byte.is_some();
+    if cli.preset != Preset::Rhythm && custom_cadence {
+        return Err(format!("--duration, --repeats, --gap-mode and --inter-byte are for the rhythm preset, not {}", cli.preset).into());
+    }
+    if cli.repeats == Some(0) {
+        return Err("--repeats must be at least 1".into());
+    }
+    // A stream or a repeat count runs as long as it takes, unless --duration says otherwise
+    let limit = match cli.duration {
+        Some(duration) => Some(duration),
+        None if payload.is_stream() || cli.repeats.is_some() => None,
+        None => Some(RHYTHM_DURATION),
+    };
+    if cli.preset != Preset::Rhythm {
+        say!("🚀 Preset '{}': {}", cli.preset, cli.preset.description());
+        say!();
+        print_scope_setup(cli.preset, cli.soft_i2c, cli.trigger_pin);
+        if cli.preset == Preset::Sweep {
+            let count = u32::from(scan::LAST_ADDRESS - scan::FIRST_ADDRESS) + 1;
+            say!(
+                "🧹 {} addresses, {:.1}ms apart ({:.0}ms in all; set the timebase to about a tenth of that)",
+                count,
+                cli.spacing.as_secs_f64() * 1e3,
+                (cli.spacing * count).as_secs_f64() * 1e3
+            );
+            if cli.payload.is_empty() {
+                say!("   Each address gets a one-byte read probe");
+            } else {
+                say!("   Each address gets {:02X?}", cli.payload);
+            }
+            say!();
+        }
+        let speeds = if cli.speeds.is_empty() { preset::STAIRCASE_SPEEDS.to_vec() } else { cli.speeds.clone() };
+        if cli.preset == Preset::Staircase {
+            if let Some(hz) = speeds.iter().find(|&&hz| hz == 0 || hz > softi2c::MAX_FREQUENCY) {
+                return Err(format!("--speeds {} Hz is outside software I2C's 1-{} Hz", hz, softi2c::MAX_FREQUENCY).into());
+            }
+            say!("🪜 {} steps, {:.1}ms apart:", speeds.len(), cli.spacing.as_secs_f64() * 1e3);
+            for &hz in &speeds {
+                say!("   {:>7} Hz  bit time {:.1}µs", hz, 1e6 / f64::from(hz));
+            }
+            say!();
+        }
+        let job = PresetJob {
+            preset: cli.preset,
+            options: PresetOptions {
+                spacing: cli.spacing,
+                payload: cli.payload.clone(),
+                speeds,
+            },
+            timeout: cli.timeout,
+            detection,
+            non_interactive: cli.non_interactive,
+            trigger_pin: cli.trigger_pin,
+            expected_speed,
+            banner: cli.banner,
+        };
+        return with_bus(&target, cli.record.as_deref(), job);
+    }
+
+    if custom_payload {
+        say!("🚀 Dynamic Rhythm I2C Transmitter");
+    } else {
+        say!("🚀 Dynamic Rhythm I2C 'Happy Birthday' Transmitter");
+    }
+    let wait = match cli.gap_mode {
+        Gap::Equal => "Wait(same duration)".to_string(),
+        gap => format!("Wait({})", gap),
+    };
+    match (limit, cli.repeats) {
+        (Some(limit), _) => say!("🎵 Pattern: Send → {} → Send → {} → repeat for {:?}", wait, wait, limit),
+        (None, Some(repeats)) => say!("🎵 Pattern: Send → {} → Send → {} → {} times", wait, wait, repeats),
+        (None, None) => say!("🎵 Pattern: Send → {} → Send → {} → repeat until {} ends", wait, wait, payload.label()),
+    }
+    if custom_payload {
+        match payload.size() {
+            Some(len) => say!("📦 Payload: {} ({} bytes)", payload.label(), len),
+            None => say!("📦 Payload: {}", payload.label()),
+        }
+    }
+    say!("⚠️  Make sure to run with: sudo ./your_program");
+    say!();
+    print_scope_setup(Preset::Rhythm, cli.soft_i2c, cli.trigger_pin);
+
+    // Ctrl-C stops the rhythm loop at a safe point instead of mid-write
+    let shutdown = Shutdown::install()?;
+
+    // Optional dev notifications (RPI_PERIPHERALS_NOTIFY=dbus | ntfy:<url>)
+    let notifier = notify::from_env()?;
+
+    let demo = Demo {
+        timeout: cli.timeout,
+        expected_speed,
+        detection,
+        non_interactive: cli.non_interactive,
+        framing: cli.framing,
+        markers: cli.markers,
+        encoding: cli.encoding,
+        integrity: cli.integrity,
+        manchester: cli.manchester_pin.map(|pin| (pin, cli.bit_time)),
+        trigger_pin: cli.trigger_pin,
+        banner: cli.banner,
+        summary: cli.summary.clone(),
+        report: cli.report.clone(),
+        label: cli.label.clone(),
+        shutdown,
+        notifier,
+        payload,
+        limit,
+        repeats: cli.repeats,
+        cadence: Cadence::new(cli.gap_mode, SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64),
+        inter_byte: cli.inter_byte.unwrap_or(DEFAULT_INTER_BYTE),
+    };
+
+    with_bus(&target, cli.record.as_deref(), demo)
+}
+
+fn print_scope_setup(preset: Preset, soft_i2c: Option<(u8, u8)>, trigger_pin: Option<u8>) {
+    let scope = preset.scope();
+    say!("🔧 Oscilloscope Setup:");
+    match soft_i2c {
+        Some((sda, scl)) => {
+            say!("   - SDA: GPIO {}", sda);
+            say!("   - SCL: GPIO {}", scl);
+        }
+        None => {
+            say!("   - SDA: GPIO 2 (Pin 3)");
+            say!("   - SCL: GPIO 3 (Pin 5)");
+        }
+    }
+    say!("   - GND: Pin 6");
+    say!("   - Timebase: {}", scope.timebase);
+    match trigger_pin {
+        Some(pin) => say!("   - Trigger: GPIO {} rising edge (external trigger input)", pin),
+        None => say!("   - Trigger: {}", scope.trigger),
+    }
+    say!("   - Acquisition: {}", scope.mode);
+    say!();
+}
+
+/// Which bus to open: an `i2c-dev` bus, or software I2C on two GPIOs.
+struct BusTarget {
+    id: u8,
+    soft: Option<(u8, u8)>,
+    /// Expected speed, or the bit-bang clock for software I2C.
+    speed: Option<u32>,
+    /// Print the traffic through [`DryRun`] instead of opening anything.
+    dry_run: bool,
+    /// `host:port` of a proxy to run everything on instead.
+    remote: Option<String>,
+    remote_token: Option<String>,
+    /// How to open a hardware bus.
+    backend: BackendKind,
+    /// What answers on a [`StubBus`].
+    stub_devices: Vec<SlaveMap>,
+    /// Wrap whatever bus it is in a [`FaultInjector`].
+    faults: Option<FaultConfig>,
+}
+
+/// Work to run once the bus is open, whichever kind of bus it turns out to be.
+trait BusJob {
+    fn run<I2C>(self, i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static;
+}
+
+fn with_bus(target: &BusTarget, record: Option<&Path>, job: impl BusJob) -> Result<(), Box<dyn Error>> {
+    if target.dry_run {
+        let clock = target.speed.unwrap_or(SoftI2cConfig::default().frequency);
+        say!("🧪 Dry run: printing bus traffic instead of sending it");
+        return run_recorded(DryRun::new(clock), target, record, job);
+    }
+    if let Some(address) = &target.remote {
+        let i2c = RemoteBus::connect(address, target.remote_token.as_deref())?;
+        say!("📡 Remote bus on {} ({} Hz)", i2c.peer(), i2c.clock_speed()?);
+        return run_recorded(i2c, target, record, job);
+    }
+    // Initialize I2C
+    match target.soft {
+        Some((sda, scl)) => {
+            let config = SoftI2cConfig {
+                frequency: target.speed.unwrap_or(SoftI2cConfig::default().frequency),
+                ..SoftI2cConfig::default()
+            };
+            let i2c = SoftI2c::from_gpio(sda, scl, config)?;
+            say!("📡 Software I2C on GPIO {}/{} at {} Hz", sda, scl, config.frequency);
+            run_recorded(i2c, target, record, job)
+        }
+        None => match target.backend {
+            BackendKind::Rppal => {
+                preflight::check_bus(target.id).into_result()?;
+                let i2c = bus::open(target.id)?;
+                say!("📡 I2C bus {} initialized", target.id);
+                run_recorded(i2c, target, record, job)
+            }
+            BackendKind::I2cdev => {
+                preflight::check_bus(target.id).into_result()?;
+                let i2c = LinuxI2c::open(target.id)?;
+                say!("📡 I2C bus {} initialized through i2c-dev", target.id);
+                run_recorded(i2c, target, record, job)
+            }
+            BackendKind::Stub => {
+                let mut i2c = StubBus::new();
+                for map in &target.stub_devices {
+                    i2c.add_map(map)?;
+                }
+                let addresses: Vec<String> = i2c.addresses().iter().map(Address::to_string).collect();
+                match addresses.as_slice() {
+                    [] => say!("🧩 Stub bus: nothing answers"),
+                    _ => say!("🧩 Stub bus: devices at {}", addresses.join(", ")),
+                }
+                if let Some(hz) = target.speed {
+                    i2c.set_clock_speed(hz)?;
+                }
+                run_recorded(i2c, target, record, job)
+            }
+        },
+    }
+}
+
+/// Run `job`, through the fault injector and recorder if asked for. Faults
+/// go in below the recorder, so the trace shows what was really sent.
+fn run_recorded<I2C>(i2c: I2C, target: &BusTarget, record: Option<&Path>, job: impl BusJob) -> Result<(), Box<dyn Error>>
+where
+    I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+    I2C::Error: Error + 'static,
+{
+    let Some(faults) = &target.faults else {
+        return run_traced(i2c, record, job);
+    };
+    let injector = FaultInjector::new(i2c, faults.clone());
+    let log = injector.log();
+    match faults.address {
+        Some(address) => say!("💉 Injecting faults into writes to {} (seed {})", address, faults.seed),
+        None => say!("💉 Injecting faults into every write (seed {})", faults.seed),
+    }
+    let result = run_traced(injector, record, job);
+    say!("💉 {}", log.snapshot());
+    result
+}
+
+fn run_traced<I2C>(i2c: I2C, record: Option<&Path>, job: impl BusJob) -> Result<(), Box<dyn Error>>
+where
+    I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+    I2C::Error: Error + 'static,
+{
+    let Some(path) = record else {
+        return job.run(i2c);
+    };
+    let recorder = Recorder::new(i2c);
+    let recording = recorder.recording();
+    // Save even when the job fails: failing runs are the ones worth keeping
+    let result = job.run(recorder);
+    recording.save(path)?;
+    say!("💾 Recorded {} transactions to {}", recording.len(), path.display());
+    result
+}
+
+/// Everything the rhythm demo needs besides the bus itself.
+struct Demo {
+    timeout: Option<Duration>,
+    expected_speed: Option<u32>,
+    detection: Detection,
+    non_interactive: bool,
+    framing: Framing,
+    markers: FrameCodec,
+    encoding: Encoding,
+    integrity: Integrity,
+    /// Pin and bit time
+    manchester: Option<(u8, Duration)>,
+    trigger_pin: Option<u8>,
+    banner: bool,
+    summary: Option<PathBuf>,
+    report: Option<PathBuf>,
+    label: Option<String>,
+    shutdown: Shutdown,
+    notifier: Option<Box<dyn NotificationSink>>,
+    payload: Payload,
+    /// How long to keep going; `None` is until `repeats` or the payload runs out.
+    limit: Option<Duration>,
+    repeats: Option<u32>,
+    cadence: Cadence,
+    inter_byte: Duration,
+}
+
+impl BusJob for Demo {
+    fn run<I2C>(self, i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        transmit(i2c, self)
+    }
+}
+
+/// [`Detection::detect`], printing each attempt; a whole-bus scan only
+/// prints what it finds.
+fn detect<I2C: AddressedI2c>(i2c: &mut I2C, detection: &Detection) -> Option<Address> {
+    say!("🔍 Scanning for I2C device ({})...", detection);
+    let quiet = *detection == Detection::Scan;
+    detection.detect_with(i2c, |address, found| {
+        if found {
+            say!("   ✅ Found working device at {}!", address);
+        } else if !quiet {
+            say!("   ❌ No response at {}", address);
+        }
+    })
+}
+
+/// The scan result, on the display that was found.
+fn show_banner<I2C: AddressedI2c + BusControl>(i2c: &mut I2C, address: Address, expected_speed: Option<u32>) -> Result<(), Box<dyn Error>> {
+    let speed = i2c.clock_speed().ok().or(expected_speed);
+    let text = lcd::scan_banner(address, speed);
+    Lcd::new(&mut *i2c, address, 16, 2)?.show(&text)?;
+    say!("🪧 Banner on the LCD: {}", text.replace('\n', " / "));
+    Ok(())
+}
+
+fn transmit<I2C>(mut i2c: I2C, demo: Demo) -> Result<(), Box<dyn Error>>
+where
+    I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+    I2C::Error: Error + 'static,
+{
+    let Demo { timeout, expected_speed, detection, non_interactive, framing, markers, encoding, integrity, manchester, trigger_pin, banner, summary, report, label, shutdown, notifier, mut payload, limit, repeats, mut cadence, inter_byte } = demo;
+    let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    if let Some(timeout) = timeout {
+        BusControl::set_timeout(&mut i2c, timeout)?;
+        say!("🔧 I2C timeout: {}ms", timeout.as_millis());
+    }
+
+    // Show I2C speed if available (checked against --speed once the transmitter is up)
+    if expected_speed.is_none() {
+        if let Ok(speed) = i2c.clock_speed() {
+            say!("🔧 I2C speed: {} Hz", speed);
+        }
+    }
+    
+    let transmitter_speed = i2c.clock_speed().ok();
+    let working_address = detect(&mut i2c, &detection);
+    
+    if working_address.is_none() && non_interactive {
+        return Err(detection.not_found().into());
+    }
+    let target = working_address.unwrap_or(detection.fallback());
+    let Address::SevenBit(target_address) = target else {
+        return Err(format!("the rhythm demo sends to 7-bit addresses, not {}", target).into());
+    };
+    if let (true, Some(found)) = (banner, working_address) {
+        show_banner(&mut i2c, found, expected_speed)?;
+        shutdown.sleep(lcd::BANNER_HOLD);
+    }
+    if working_address.is_none() {
+        say!("⚠️  No I2C device found, using 0x{:02X} anyway for scope analysis", target_address);
+        if let Some(sink) = &notifier {
+            let note = Notification::new("No I2C device found", detection.not_found().to_string(), Priority::High);
+            if let Err(e) = sink.notify(&note) {
+                say!("⚠️  Notification failed: {}", e);
+            }
+        }
+    }
+    
+    // The bus manager keeps the bus shareable for other devices alongside the LCD
+    let bus = BusManager::new(i2c);
+    let mut transmitter = SimpleI2cTransmitter::new(bus.shared(), target)?;
+    transmitter.set_cancel_flag(shutdown.flag());
+    transmitter.set_framing(framing);
+    transmitter.set_codec(markers)?;
+    if framing == Framing::Framed && markers != FrameCodec::default() {
+        say!("⚠️  --markers has no effect when framed: a frame carries its length");
+    }
+    transmitter.set_encoder(encoding);
+    transmitter.set_integrity(integrity);
+    transmitter.set_inter_byte(inter_byte);
+    if let Some((pin, bit_time)) = manchester {
+        let mut line = ManchesterLine::from_gpio(pin)?;
+        line.set_bit_time(bit_time)?;
+        transmitter.set_manchester_line(line);
+        say!("〰️  Manchester copy on GPIO {} at {} bit/s", pin, (1.0 / bit_time.as_secs_f64()).round());
+    }
+    if let Some(speed) = expected_speed {
+        transmitter.set_clock_speed(speed);
+    }
+    if let Some(pin) = trigger_pin {
+        transmitter.set_trigger(Trigger::from_gpio(pin)?);
+        say!("🔔 Scope trigger on GPIO {} (pulses high before each message)", pin);
+    }
+    if let Some(limit) = transmitter.max_message() {
+        payload.limit_chunk(limit)?;
+    }
+
+    // On interrupt, drive every PCF8574 output low: backlight off, LCD enable idle
+    let mut expander = bus.device(target_address);
+    shutdown.on_shutdown("PCF8574 outputs released", move || expander.write(&[0x00]));
+    
+    say!("🎯 Starting dynamic rhythm transmission...");
+    say!("📍 Target address: {}", transmitter.address());
+    match (limit, repeats) {
+        (Some(limit), Some(repeats)) => say!("⏱️  Total duration: {:?} or {} messages, whichever comes first", limit, repeats),
+        (Some(limit), None) => say!("⏱️  Total duration: {:?}", limit),
+        (None, Some(repeats)) => say!("⏱️  Total duration: {} messages", repeats),
+        (None, None) => say!("⏱️  Total duration: until {} ends", payload.label()),
+    }
+    if framing == Framing::PerByte && inter_byte != DEFAULT_INTER_BYTE {
+        say!("⏱️  Inter-byte gap: {:?}", inter_byte);
+    }
+    say!();
+
+    // Dynamic rhythm pattern until the limit, the repeats or the payload run out
+    let start_time = Instant::now();
+    let mut message_count = 0;
+    let mut bytes_sent = 0;
+    let mut first_message = None;
+    let mut journal = Journal::new();
+    
+    say!("🎵 Starting rhythm pattern...");
+    let mut bar = limit.map(|limit| ProgressBar::new("rhythm", limit.as_millis() as u64, Unit::Millis, Stream::Stdout));
+    
+    while limit.is_none_or(|limit| start_time.elapsed() < limit) && !shutdown.requested() {
+        let Some(message) = payload.next_message()? else {
+            say!("📭 {} ended", payload.label());
+            break;
+        };
+        transmitter.set_message(&message);
+        bytes_sent += message.len();
+        journal.set_payload(payload.label(), &message);
+        let bytes = message.len();
+        first_message.get_or_insert(message);
+        
+        message_count += 1;
+        match (limit, repeats) {
+            (Some(limit), _) => say!("⏰ Rhythm cycle {} (Remaining: {:.1}s)", message_count, limit.saturating_sub(start_time.elapsed()).as_secs_f32()),
+            (None, Some(repeats)) => say!("⏰ Rhythm cycle {} of {}", message_count, repeats),
+            (None, None) => say!("⏰ Rhythm cycle {}", message_count),
+        }
+        if let Some(bar) = &mut bar {
+            bar.set_message(format!("cycle {}", message_count));
+        }
+        
+        // Send message and measure how long it takes
+        let at = start_time.elapsed();
+        let before = transmitter.timing();
+        let transmission_time = transmitter.send_message(message_count)?;
+        let after = transmitter.timing();
+        journal.record(MessageRecord {
+            number: message_count,
+            at,
+            took: transmission_time,
+            gap: Duration::ZERO,
+            bytes,
+            errors: after.errors - before.errors,
+            mismatch: after.mismatches > before.mismatches,
+        });
+        if shutdown.requested() {
+            break;
+        }
+        
+        if let Some(bar) = &mut bar {
+            bar.set(start_time.elapsed().as_millis() as u64);
+        }
+        if repeats.is_some_and(|repeats| message_count >= repeats) {
+            break;
+        }
+        
+        // The next cycle starts after the wait, so only stop if that is past the end
+        let wait_time = cadence.next(transmission_time);
+        journal.set_gap(wait_time);
+        if limit.is_some_and(|limit| start_time.elapsed() + wait_time >= limit) {
+            say!("⏰ No time left for another cycle, stopping...");
+            break;
+        }
+        match cadence.gap() {
+            Gap::Equal => say!("⏳ Waiting {:.1}ms (same as transmission time)...", wait_time.as_millis()),
+            gap => say!("⏳ Waiting {:.1}ms ({})...", wait_time.as_millis(), gap),
+        }
+        
+        // Sleep most of it (waking on Ctrl-C), then spin to the exact deadline
+        let deadline = Instant::now().checked_add(wait_time);
+        if !shutdown.sleep(wait_time.saturating_sub(timing::DEFAULT_SPIN)) {
+            break;
+        }
+        if let Some(deadline) = deadline {
+            PreciseDelay::default().until(deadline);
+        }
+    }
+    
+    if let Some(mut bar) = bar {
+        bar.set(start_time.elapsed().as_millis() as u64);
+        bar.finish();
+    }
+    let actual_duration = start_time.elapsed();
+    let interrupted = shutdown.requested();
+    if interrupted {
+        say!("🛑 Interrupted - cleaning up and reporting partial results");
+        shutdown.run_hooks();
+    } else {
+        say!("🏁 Rhythm pattern complete!");
+    }
+    say!();
+    say!("📊 Summary:");
+    say!("   - Messages sent: {}{}", message_count, if interrupted { " (interrupted)" } else { "" });
+    say!("   - Actual duration: {:.2}s", actual_duration.as_secs_f32());
+    say!("   - Payload: {}, {} bytes in {} messages", payload.label(), bytes_sent, message_count);
+    let bus = transmitter.timing();
+    say!(
+        "   - Framing: {} ({} transactions, {}µs on the bus, {}µs per byte)",
+        framing,
+        bus.transactions,
+        bus.bus_time.as_micros(),
+        bus.per_byte().as_micros()
+    );
+    say!("   - Failed writes: {}", bus.errors);
+    if !integrity.is_none() {
+        say!("   - Integrity: {} ({} of {} messages read back wrong)", integrity, bus.mismatches, message_count);
+    }
+    say!("   - Pattern: Send → Wait({}) → Repeat", if cadence.gap() == Gap::Equal { "same time".to_string() } else { cadence.gap().to_string() });
+    say!();
+    say!("🔍 Oscilloscope Analysis:");
+    for hint in Preset::Rhythm.scope().look_for {
+        say!("   📍 Look for {}", hint);
+    }
+    say!("   📍 {} complete cycles in {:.2}s", message_count, actual_duration.as_secs_f32());
+    say!("   📍 Address: 0x{:02X} (0b{:08b})", target_address << 1, target_address << 1);
+    if working_address.is_some() {
+        say!("   ✅ Should see ACK responses (SDA low on 9th clock)");
+    } else {
+        say!("   ❌ Will see NACK responses (SDA high on 9th clock)");
+    }
+    say!();
+    // Byte by byte is only readable for something message-sized
+    match first_message.filter(|message| message.len() <= 32) {
+        Some(message) => {
+            say!("📝 Byte values in {}:", if message_count > 1 && payload.size() != Some(message.len()) { "the first message" } else { "each message" });
+            for byte in message {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    say!("   '{}' = 0x{:02X}", byte as char, byte);
+                } else {
+                    say!("   0x{:02X}", byte);
+                }
+            }
+        }
+        None if framing == Framing::Framed => {}
+        None => say!("📝 Messages are framed by:"),
+    }
+    if framing != Framing::Framed {
+        let byte = |b: Option<u8>| b.map_or("none".to_string(), |b| format!("0x{:02X}", b));
+        say!("   Start marker = {}", byte(markers.start));
+        say!("   End marker = {}", byte(markers.end));
+        if let Some(escape) = markers.escape {
+            say!("   Escape = 0x{:02X} (then the byte XOR 0x{:02X})", escape, ESCAPE_XOR);
+        }
+    }
+
+    if summary.is_some() || report.is_some() {
+        let mut session = SessionReport::new(started, &Preset::Rhythm.to_string(), &framing.to_string(), &encoding.to_string());
+        session.label = label;
+        session.integrity = integrity.to_string();
+        session.markers = if framing == Framing::Framed { FrameCodec::NONE.to_string() } else { markers.to_string() };
+        session.bus = BusInfo {
+            speed_hz: transmitter_speed,
+            expected_speed_hz: expected_speed,
+        };
+        session.detection = session::Detection {
+            candidates: detection.addresses(),
+            found: working_address,
+            target: Some(target),
+        };
+        session.messages = message_count;
+        session.interrupted = interrupted;
+        session.duration_us = actual_duration.as_micros() as u64;
+        session.transactions = bus.transactions;
+        session.bytes = bus.bytes;
+        session.bus_time_us = bus.bus_time.as_micros() as u64;
+        session.per_byte_ns = bus.per_byte().as_nanos() as u64;
+        session.errors = bus.errors;
+        session.mismatches = bus.mismatches;
+        if let Some(path) = &summary {
+            session.save(path)?;
+            say!("🗂️  Summary written to {}", path.display());
+        }
+        if let Some(path) = &report {
+            journal.save(path, &session)?;
+            say!("🗂️  Report written to {}", path.display());
+        }
+    }
+    
+    if interrupted {
+        return Err(Interrupted.into());
+    }
+    Ok(())
+}
+
+fn list_buses(config: &Config) {
+    let buses = bus::available_buses();
+    if buses.is_empty() {
+        say!("No I2C buses found (enable one with raspi-config or dtparam=i2c_arm=on)");
+    }
+    for info in &buses {
+        let speed = info.clock_speed.map_or("unknown speed".to_string(), |hz| format!("{} Hz", hz));
+        let kind = if info.is_software() { " [software]" } else { "" };
+        say!(
+            "{}  {}  {}{}",
+            info.path.display(),
+            info.name.as_deref().unwrap_or("?"),
+            speed,
+            kind
+        );
+        if let Some(configured) = config.buses.iter().find(|b| b.id == info.id).and_then(|b| b.speed) {
+            say!("    configured: {} Hz{}", configured, if info.clock_speed.is_some_and(|hz| hz != configured) { " (mismatch)" } else { "" });
+        }
+    }
+    for missing in config.buses.iter().filter(|b| !buses.iter().any(|i| i.id == b.id)) {
+        say!("⚠️  Bus {} is in the config but {} does not exist", missing.id, bus::bus_path(missing.id).display());
+    }
+}
+
+fn list_presets() {
+    for preset in Preset::ALL {
+        let scope = preset.scope();
+        say!("{:<10} {}", preset, preset.description());
+        say!("    scope: {}, trigger on {}, {} acquisition", scope.timebase, scope.trigger, scope.mode);
+    }
+}
+
+fn saved_totals(config: &Config, what: &TotalsCommand) -> Result<(), Box<dyn Error>> {
+    let path = config.totals.state.as_deref().ok_or("no totals.state file in the config")?;
+    match what {
+        TotalsCommand::List => {
+            let saved = totals::saved(path)?;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            for name in config.totals.counters.keys() {
+                match saved.get(name) {
+                    Some(&(value, since)) => {
+                        let days = now.saturating_sub(since) as f64 / 86400.0;
+                        say!("{:<20} {:>14.3}   since reset {:.1} days ago", name, value, days);
+                    }
+                    None => say!("{:<20} {:>14}   not saved yet", name, "-"),
+                }
+            }
+            for name in saved.keys().filter(|name| !config.totals.counters.contains_key(*name)) {
+                say!("{:<20} {:>14}   no longer configured", name, "-");
+            }
+        }
+        TotalsCommand::Reset { names } => {
+            for name in totals::reset_saved(path, names)? {
+                say!("🔄 Reset {}", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn leds(strip: &mut dyn Strip, config: &Config, what: &LedsCommand) -> Result<(), Box<dyn Error>> {
+    match what {
+        LedsCommand::Set { index, color } => {
+            strip.set(*index, *color)?;
+            strip.show()?;
+        }
+        LedsCommand::Fill { color } => {
+            strip.fill(*color);
+            strip.show()?;
+        }
+        LedsCommand::Clear => {
+            strip.clear();
+            strip.show()?;
+        }
+        LedsCommand::Wipe { color, step } => strip.wipe(*color, *step)?,
+        LedsCommand::Rainbow { period } => {
+            let shutdown = Shutdown::install()?;
+            say!("🌈 Rainbow on {} LEDs, Ctrl-C to stop", strip.len());
+            strip.rainbow(*period, &shutdown.flag())?;
+            strip.clear();
+            strip.show()?;
+        }
+        LedsCommand::React { device } => {
+            let mut reactive = config.reactive.clone();
+            if let Some(device) = device {
+                reactive.device = device.clone();
+            }
+            let shutdown = Shutdown::install()?;
+            say!("🎵 {} on {} LEDs from {}, Ctrl-C to stop", reactive.effect, strip.len(), reactive.device);
+            reactive::run(strip, &reactive, &shutdown.flag())?;
+            strip.clear();
+            strip.show()?;
+        }
+    }
+    Ok(())
+}
+
+fn max7219<SPI>(display: &mut Max7219<SPI>, what: &Max7219Command) -> Result<(), Box<dyn Error>>
+where
+    SPI: embedded_hal::spi::SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    match what {
+        Max7219Command::Text { text, repeat, step } => {
+            if font::render(text).len() <= display.device_count() * 8 && !repeat {
+                return display.show_text(text);
+            }
+            let shutdown = Shutdown::install()?;
+            if *repeat {
+                say!("📜 Scrolling on {} matrices, Ctrl-C to stop", display.device_count());
+            }
+            loop {
+                display.scroll_text(text, *step, &shutdown.flag())?;
+                if !repeat || shutdown.requested() {
+                    break;
+                }
+            }
+            display.clear()?;
+        }
+        Max7219Command::Number { value, device, decimals } => display.show_number(*device, *value, *decimals)?,
+        Max7219Command::Segments { text, device } => display.show_segments(*device, text)?,
+        Max7219Command::Clear => display.clear()?,
+    }
+    Ok(())
+}
+
+fn modbus_command<P: Read + Write>(master: &mut RtuMaster<P>, what: &ModbusCommand) -> Result<(), Box<dyn Error>> {
+    match what {
+        ModbusCommand::Read { slave, reg, count, input } => {
+            let values = if *input {
+                master.read_input_registers(*slave, *reg, *count)?
+            } else {
+                master.read_holding_registers(*slave, *reg, *count)?
+            };
+            for (n, value) in values.iter().enumerate() {
+                say!("{:>5}  0x{:04X}  {:>5}  {:>6}", usize::from(*reg) + n, value, value, *value as i16);
+            }
+        }
+        ModbusCommand::Write { slave, reg, values, multiple } => {
+            match values.as_slice() {
+                [value] if !multiple => master.write_single_register(*slave, *reg, *value)?,
+                _ => master.write_multiple_registers(*slave, *reg, values)?,
+            }
+            say!("✅ Wrote {} register{} from {} on slave {}", values.len(), if values.len() == 1 { "" } else { "s" }, reg, slave);
+        }
+    }
+    Ok(())
+}
+
+fn can_command<SPI>(can: &mut Mcp2515<SPI>, what: &CanCommand) -> Result<(), Box<dyn Error>>
+where
+    SPI: embedded_hal::spi::SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    match what {
+        CanCommand::Send { frames, timeout } => {
+            for frame in frames {
+                can.send(frame)?;
+                can.flush(*timeout)?;
+                say!("📤 {}", frame);
+                if can.mode() == OperatingMode::Loopback {
+                    if let Some(echo) = can.receive()? {
+                        say!("📥 {}", echo);
+                    }
+                }
+            }
+        }
+        CanCommand::Dump { filter, count } => {
+            can.set_filters(filter)?;
+            let shutdown = Shutdown::install()?;
+            let mut received = 0u64;
+            let mut overflowed = false;
+            while !shutdown.requested() && count.is_none_or(|c| received < c) {
+                match can.receive()? {
+                    Some(frame) => {
+                        received += 1;
+                        say!("{}", frame);
+                    }
+                    None => {
+                        let errors = can.errors()?;
+                        if errors.overflowed() && !overflowed {
+                            say!("⚠️  Frames lost to a full receive buffer ({})", errors);
+                        }
+                        overflowed = errors.overflowed();
+                        if overflowed {
+                            can.clear_overflow()?;
+                        }
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                }
+            }
+            say!("📊 {} frames ({})", received, can.errors()?);
+        }
+    }
+    Ok(())
+}
+
+fn adc_command<SPI>(adc: &mut Mcp3008<SPI>, vref: f64, what: &AdcCommand) -> Result<(), Box<dyn Error>>
+where
+    SPI: embedded_hal::spi::SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    match what {
+        AdcCommand::Read { inputs } => {
+            for &input in inputs {
+                let raw = adc.read(input)?;
+                say!("{:<8} {:>4}  {:.3} V", input, raw, adc::volts(raw, vref));
+            }
+        }
+        AdcCommand::Stream { inputs, rate, duration, output, raw } => {
+            let period = Duration::from_secs_f64(1.0 / rate);
+            let mut out: Box<dyn Write> = match output {
+                Some(path) => Box::new(io::BufWriter::new(
+                    std::fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+                )),
+                None => Box::new(io::stdout().lock()),
+            };
+            let names: Vec<String> = inputs.iter().map(ToString::to_string).collect();
+            writeln!(out, "time_s,{}", names.join(","))?;
+            let shutdown = Shutdown::install()?;
+            // Progress goes to stderr when the CSV is on stdout
+            let status = |line: String| if output.is_some() { say!("{}", line) } else { esay!("{}", line) };
+            status(format!("📈 Sampling {} inputs at {} Hz, Ctrl-C to stop", inputs.len(), rate));
+            let start = Instant::now();
+            let mut samples = 0u64;
+            let mut missed = 0u64;
+            let mut slot = 0u32;
+            while !shutdown.requested() && duration.is_none_or(|d| start.elapsed() < d) {
+                // Each sample has its own slot on the start's clock, so the
+                // rate doesn't drift with the time a sample takes
+                let deadline = start + period * slot;
+                let now = Instant::now();
+                if now > deadline + period {
+                    let behind = ((now - deadline).as_secs_f64() / period.as_secs_f64()) as u32;
+                    missed += u64::from(behind);
+                    slot += behind;
+                    continue;
+                }
+                if !shutdown.sleep(deadline.saturating_duration_since(now).saturating_sub(timing::DEFAULT_SPIN)) {
+                    break;
+                }
+                PreciseDelay::default().until(deadline);
+                let time = start.elapsed().as_secs_f64();
+                let readings = adc.read_all(inputs)?;
+                let fields: Vec<String> = readings
+                    .into_iter()
+                    .map(|r| if *raw { r.to_string() } else { format!("{:.4}", adc::volts(r, vref)) })
+                    .collect();
+                writeln!(out, "{:.6},{}", time, fields.join(","))?;
+                samples += 1;
+                slot += 1;
+            }
+            out.flush()?;
+            let missed = if missed > 0 { format!(", {} missed for running late", missed) } else { String::new() };
+            status(format!(
+                "🏁 {} samples in {:.1}s{}",
+                samples,
+                start.elapsed().as_secs_f64(),
+                missed
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn noise(
+    device: &str,
+    mic: Microphone,
+    rate: u32,
+    right: bool,
+    calibration: f32,
+    interval: Duration,
+    count: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let mut capture = mic.open(device, rate)?;
+    capture.select_channel(usize::from(right))?;
+    let mut meter = SplMeter::new(mic, rate);
+    meter.set_calibration(calibration);
+    // Counted in samples, so readings keep to the recording's own clock
+    let per_reading = ((interval.as_secs_f64() * f64::from(rate)) as usize).max(1);
+    let mut block = vec![0.0; (rate as usize / 20).clamp(1, per_reading)];
+    let shutdown = Shutdown::install()?;
+    say!("🎙️ {} on {}, Ctrl-C to stop", mic, device);
+    let mut readings = 0;
+    let mut fed = 0;
+    while !shutdown.requested() && count.is_none_or(|count| readings < count) {
+        let take = block.len().min(per_reading - fed);
+        capture.read(&mut block[..take])?;
+        meter.feed(&block[..take]);
+        fed += take;
+        if fed < per_reading {
+            continue;
+        }
+        fed = 0;
+        readings += 1;
+        if let Some(reading) = meter.take() {
+            let floor = if reading.leq <= mic.noise_floor_db() { " (at the microphone's noise floor)" } else { "" };
+            say!(
+                "🔊 {:.1} dB SPL, min {:.1}, max {:.1}{}",
+                reading.leq, reading.min, reading.max, floor
+            );
+        }
+    }
+    Ok(())
+}
+
+fn one_wire(what: &OnewireCommand) -> Result<(), Box<dyn Error>> {
+    match what {
+        OnewireCommand::List => {
+            let sensors = onewire::sensors()?;
+            if sensors.is_empty() {
+                say!("No DS18B20 sensors under {}", onewire::W1_DEVICES);
+            }
+            for sensor in sensors {
+                match sensor.read() {
+                    Ok(reading) => say!("{:<16} {:>8.3} °C  {} bit", sensor.id(), reading.celsius, reading.resolution),
+                    Err(e) => say!("{:<16} ❌ {}", sensor.id(), e),
+                }
+            }
+        }
+        OnewireCommand::Resolution { id, bits, save } => {
+            let sensor = Ds18b20::open(id)?;
+            sensor.set_resolution(*bits)?;
+            if *save {
+                sensor.save()?;
+            }
+            say!("✅ {} at {} bit{}", id, sensor.resolution()?, if *save { ", saved" } else { "" });
+        }
+    }
+    Ok(())
+}
+
+fn show_plan(cli: &Cli, config: &Config, state: &Path) -> Result<(), Box<dyn Error>> {
+    if cli.config.is_none() {
+        return Err("plan needs --config".into());
+    }
+    let applied = plan::load_applied(state)?;
+    if applied.is_none() {
+        say!("Nothing applied yet ({}), so everything is new\n", state.display());
+    }
+    let plan = Plan::new(applied.as_ref(), config)?;
+    term::write(&plan.to_string(), Stream::Stdout);
+    if !plan.conflicts.is_empty() {
+        return Err(format!("{} conflicting claims", plan.conflicts.len()).into());
+    }
+    Ok(())
+}
+
+fn fleet(config: &Config, what: &FleetCommand) -> Result<(), Box<dyn Error>> {
+    let fleet = Fleet::new(config.fleet.clone())?;
+    match what {
+        FleetCommand::Status { json } => {
+            let statuses = fleet.poll();
+            if *json {
+                say!("{}", serde_json::to_string_pretty(&statuses)?);
+            } else {
+                for status in &statuses {
+                    say!("{}", status);
+                }
+            }
+            let down = statuses.iter().filter(|s| !s.online).count();
+            if down > 0 {
+                return Err(format!("{} of {} agents down", down, statuses.len()).into());
+            }
+        }
+        FleetCommand::Serve { port, bind } => {
+            let shutdown = Shutdown::install()?;
+            let metrics = Metrics::new();
+            let listen = format!("{}:{}", bind, port);
+            let listener = TcpListener::bind(&listen).map_err(|e| format!("cannot listen on {}: {}", listen, e))?;
+            let server = server::spawn_fleet(listener, fleet.clone(), metrics.clone(), shutdown.flag())?;
+            say!(
+                "🛰️  Polling {} agents every {:.0}s, view on http://{}/fleet",
+                fleet.config().agents.len(),
+                fleet.config().interval.as_secs_f64(),
+                listen
+            );
+            let mut online: HashMap<String, bool> = HashMap::new();
+            loop {
+                let statuses = fleet.poll();
+                fleet::export(&metrics, &statuses);
+                for status in &statuses {
+                    let was = online.insert(status.name.clone(), status.online);
+                    match (was, status.online) {
+                        (Some(false) | None, true) => say!("🟢 {} up", status.name),
+                        (Some(true) | None, false) => {
+                            say!("🔴 {} down: {}", status.name, status.error.as_deref().unwrap_or("no reply"))
+                        }
+                        _ => {}
+                    }
+                }
+                if !shutdown.sleep(fleet.config().interval) {
+                    break;
+                }
+            }
+            let _ = server.join();
+            say!("👋 Fleet view stopped");
+        }
+    }
+    Ok(())
+}
+
+fn list_drivers() {
+    for driver in drivers::DRIVERS {
+        say!("{:<12} {:<7} {}", driver.name, driver.interface, driver.description);
+        let caps: Vec<String> = driver.capabilities.iter().map(|c| c.to_string()).collect();
+        say!("    capabilities: {}", caps.join(", "));
+        if !driver.addresses.is_empty() {
+            let addrs: Vec<String> = driver.addresses.iter().map(|a| format!("0x{:02X}", a)).collect();
+            say!("    addresses: {}", addrs.join(" "));
+        }
+    }
+}
+
+fn list_devices(config: &Config, probe: bool) -> Result<(), Box<dyn Error>> {
+    if config.devices.is_empty() {
+        say!("No devices configured (pass --config with [[devices]] entries)");
+        return Ok(());
+    }
+    for device in &config.devices {
+        let address = match device.address {
+            Some(raw) => Address::from_raw(raw)?.to_string(),
+            None => "-".to_string(),
+        };
+        say!("{:<12} {:<12} bus {}  {}", device.name, device.driver, device.bus, address);
+        match drivers::find(&device.driver) {
+            Some(driver) => {
+                let caps: Vec<String> = driver.capabilities.iter().map(|c| c.to_string()).collect();
+                say!("    capabilities: {}", caps.join(", "));
+            }
+            None => say!("    ⚠️  unknown driver (see `list drivers`)"),
+        }
+        if let Some(rail) = device.rail.as_deref().and_then(|r| config.rails.iter().find(|c| c.name == r)) {
+            say!("    power: rail '{}' (GPIO {})", rail.name, rail.pin);
+        }
+        if probe {
+            if let Some(raw) = device.address {
+                let found = bus::open(device.bus)
+                    .map(|mut i2c| scan::probe(&mut i2c, Address::from_raw(raw).expect("validated by Config")));
+                match found {
+                    Ok(true) => say!("    ✅ responding"),
+                    Ok(false) => say!("    ❌ no response"),
+                    Err(e) => say!("    ❌ {}", e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn board_info() -> Result<(), Box<dyn Error>> {
+    let board = Board::detect()?;
+    say!("🍓 {}", board.model);
+    match board.revision {
+        Some(revision) => say!("   Revision: {:#08x}", revision),
+        None => say!("   Revision: unknown"),
+    }
+    say!("   SoC: {}", board.soc);
+    if let Some(mb) = board.memory_mb {
+        say!("   Memory: {} MB", mb);
+    }
+    say!("   Header: {} pins", board.header);
+    say!();
+    say!("I2C buses:");
+    for bus in board.i2c_buses() {
+        let state = if bus::bus_path(bus.bus).exists() { "enabled" } else { "off" };
+        say!(
+            "   i2c-{}  SDA GPIO {:<2}  SCL GPIO {:<2}  {:<8} ({})",
+            bus.bus, bus.sda, bus.scl, state, bus.enable
+        );
+    }
+    say!("PWM channels:");
+    for channel in board.pwm_channels() {
+        let pins: Vec<String> = channel.pins.iter().map(|p| format!("GPIO {}", p)).collect();
+        say!("   PWM{}  {}", channel.channel, pins.join(" or "));
+    }
+    say!("Header GPIOs:");
+    for pin in board.header_gpios() {
+        let functions = board.pin_functions(pin);
+        if functions.is_empty() {
+            say!("   GPIO {:<2}", pin);
+        } else {
+            say!("   GPIO {:<2}  {}", pin, functions.join(", "));
+        }
+    }
+    Ok(())
+}
+
+fn pin_states() -> Result<(), Box<dyn Error>> {
+    let board = Board::detect()?;
+    let states = pins::read_all(&board)?;
+    say!("🍓 {} ({}-pin header)", board.model, board.header);
+    term::write(&pins::table(&board, &states), Stream::Stdout);
+    if states.iter().all(|s| s.pull.is_none()) {
+        match board.soc {
+            Soc::Bcm2711 | Soc::Bcm2712 => say!("💡 Couldn't read the pulls from /dev/gpiomem; is this user in the gpio group?"),
+            soc => say!("💡 The {}'s pull settings are write-only, so they show as ?", soc),
+        }
+    }
+    Ok(())
+}
+
+fn list_input() -> Result<(), Box<dyn Error>> {
+    let devices = input::devices()?;
+    if devices.is_empty() {
+        say!("No input devices under {}", input::INPUT_CLASS);
+    }
+    for device in devices {
+        say!("{:<20} {}", device.path.display(), device.name);
+    }
+    Ok(())
+}
+
+fn list_startup(config: &Config) -> Result<(), Box<dyn Error>> {
+    let plan = StartupPlan::from_config(config)?;
+    if plan.steps().is_empty() {
+        say!("Nothing to start (pass --config with [[rails]] or [[devices]] entries)");
+        return Ok(());
+    }
+    for (n, step) in plan.steps().iter().enumerate() {
+        let after: Vec<String> = step.depends_on.iter().map(|d| d.to_string()).collect();
+        let optional = if step.optional { " (optional)" } else { "" };
+        let after = if after.is_empty() { String::new() } else { format!("  after {}", after.join(", ")) };
+        say!(
+            "{:>3}. {:<24} timeout {}ms{}{}",
+            n + 1,
+            step.node.to_string(),
+            step.timeout.as_millis(),
+            optional,
+            after
+        );
+    }
+    Ok(())
+}
+
+fn diff_traces(left: &PathBuf, right: &PathBuf, tolerance: Duration) -> Result<(), Box<dyn Error>> {
+    let (a, b) = (Trace::load(left)?, Trace::load(right)?);
+    let result = trace::diff(&a, &b, &DiffOptions { tolerance });
+    for divergence in &result.divergences {
+        say!("{}", trace::describe(divergence, &a, &b));
+    }
+    say!(
+        "{} transactions matched, {} divergences ({} vs {} transactions)",
+        result.matched,
+        result.divergences.len(),
+        a.transactions.len(),
+        b.transactions.len()
+    );
+    if result.is_identical() {
+        Ok(())
+    } else {
+        Err(VerificationFailed { details: format!("{} and {} differ", left.display(), right.display()) }.into())
+    }
+}
+
+struct ReplayJob {
+    trace: Trace,
+    timing: Timing,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for ReplayJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let mut replayer = Replayer::new(i2c);
+        replayer.set_timing(self.timing)?;
+        replayer.set_cancel_flag(self.shutdown.flag());
+
+        say!(
+            "▶️  Replaying {} transactions ({:.2}s recorded, timing: {})",
+            self.trace.transactions.len(),
+            self.trace.span().as_secs_f32(),
+            self.timing
+        );
+        let report = replayer.replay(&self.trace);
+        for &i in &report.mismatches {
+            say!("{}", trace::describe(&Divergence::Content { left: i, right: i }, &self.trace, &report.actual));
+        }
+        say!(
+            "📊 Replayed {} transactions in {:.2}s, {} differed from the recording",
+            report.actual.transactions.len(),
+            report.actual.span().as_secs_f32(),
+            report.mismatches.len()
+        );
+
+        if report.cancelled {
+            return Err(Interrupted.into());
+        }
+        if !report.mismatches.is_empty() {
+            return Err(VerificationFailed {
+                details: format!("{} transactions differed from the recording", report.mismatches.len()),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// A named demo pattern other than the rhythm.
+struct PresetJob {
+    preset: Preset,
+    options: PresetOptions,
+    timeout: Option<Duration>,
+    detection: Detection,
+    non_interactive: bool,
+    trigger_pin: Option<u8>,
+    expected_speed: Option<u32>,
+    banner: bool,
+}
+
+impl BusJob for PresetJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        // The sweep covers every address, so it needs no target
+        let target = if self.preset == Preset::Sweep {
+            self.detection.fallback()
+        } else {
+            match detect(&mut i2c, &self.detection) {
+                Some(addr) if self.banner => {
+                    show_banner(&mut i2c, addr, self.expected_speed)?;
+                    std::thread::sleep(lcd::BANNER_HOLD);
+                    addr
+                }
+                Some(addr) => addr,
+                None if self.non_interactive => return Err(self.detection.not_found().into()),
+                None => {
+                    let fallback = self.detection.fallback();
+                    say!("⚠️  No I2C device found, using {} anyway for scope analysis", fallback);
+                    fallback
+                }
+            }
+        };
+        let mut trigger = self.trigger_pin.map(Trigger::from_gpio).transpose()?;
+
+        say!("🎯 Playing {}...", self.preset);
+        let report = preset::run(self.preset, &mut i2c, target, &self.options, || match &mut trigger {
+            Some(trigger) => trigger.pulse(),
+            None => Ok(()),
+        })?;
+
+        say!();
+        say!("📊 Summary:");
+        say!("   - Transactions: {} ({} bytes written)", report.transactions, report.bytes);
+        say!("   - Duration: {:.1}ms", report.elapsed.as_secs_f64() * 1e3);
+        let acked: Vec<String> = report.acked.iter().map(Address::to_string).collect();
+        say!("   - ACKed: {}", if acked.is_empty() { "nothing".to_string() } else { acked.join(" ") });
+        for &(hz, acked) in &report.steps {
+            say!("     {} {:>7} Hz", if acked { "✅" } else { "❌" }, hz);
+        }
+        say!();
+        say!("🔍 Oscilloscope Analysis:");
+        for hint in self.preset.scope().look_for {
+            say!("   📍 Look for {}", hint);
+        }
+        Ok(())
+    }
+}
+
+struct ReplJob {
+    timeout: Option<Duration>,
+}
+
+impl BusJob for ReplJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        say!("🐚 I2C shell, type help for commands");
+        let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".rpi_peripherals_history"));
+        repl::run(&mut i2c, history.as_deref())
+    }
+}
+
+struct FactoryJob {
+    plan: TestPlan,
+    serial: Option<String>,
+    report: Option<PathBuf>,
+    units: UnitsConfig,
+}
+
+impl BusJob for FactoryJob {
+    fn run<I2C>(self, i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        let mut fixture = Fixture::new(i2c);
+        fixture.set_units(self.units);
+        if self.plan.steps.iter().any(|s| matches!(s, Step::Gpio { .. })) {
+            fixture.use_pi_gpio()?;
+        }
+        fixture.set_operator(|message| Ok(ask(&format!("❓ {} [y/n] ", message))?.eq_ignore_ascii_case("y")));
+
+        say!("🏭 {} ({} steps)", self.plan.name, self.plan.steps.len());
+        let (mut tested, mut failed) = (0, 0);
+        loop {
+            let serial = match &self.serial {
+                Some(serial) if tested == 0 => serial.clone(),
+                Some(_) => break,
+                None => {
+                    let serial = ask("🔖 Serial number (empty to finish): ")?;
+                    if serial.is_empty() {
+                        break;
+                    }
+                    serial
+                }
+            };
+            let report = fixture.run(&self.plan, &serial);
+            for step in &report.steps {
+                match &step.detail {
+                    Some(detail) => say!("   {} {:<32} {}", step.verdict, step.step, detail),
+                    None => say!("   {} {}", step.verdict, step.step),
+                }
+            }
+            say!("{} {}", if report.passed { "✅ PASS" } else { "❌ FAIL" }, serial);
+            if let Some(path) = &self.report {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                writeln!(file, "{}", serde_json::to_string(&report)?)?;
+            }
+            tested += 1;
+            if !report.passed {
+                failed += 1;
+            }
+        }
+
+        say!("📊 {} units tested, {} failed", tested, failed);
+        if failed > 0 {
+            return Err(VerificationFailed { details: format!("{} of {} units failed", failed, tested) }.into());
+        }
+        Ok(())
+    }
+}
+
+struct SelftestJob {
+    devices: Vec<DeviceConfig>,
+    full: bool,
+    loopbacks: Vec<Loopback>,
+    _claims: Vec<Claim>,
+}
+
+impl BusJob for SelftestJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        say!(
+            "🩺 {} self-test of {} devices{}",
+            if self.full { "Full" } else { "Quick" },
+            self.devices.len(),
+            if self.loopbacks.is_empty() { String::new() } else { format!(" and {} loopbacks", self.loopbacks.len()) }
+        );
+        let mut report = SelfTestReport::default();
+        for device in &self.devices {
+            report.checks.push(selftest::check_device(&mut i2c, device, self.full));
+        }
+        for &pair in &self.loopbacks {
+            report.checks.push(selftest::check_loopback(pair));
+        }
+        say!("{}", report);
+        if !report.passed() {
+            let failed = report.count(preflight::Status::Fail);
+            return Err(VerificationFailed { details: format!("{} of {} checks failed", failed, report.checks.len()) }.into());
+        }
+        Ok(())
+    }
+}
+
+/// Prompt on stdout and read one trimmed line from stdin.
+fn ask(prompt: &str) -> Result<String, Box<dyn Error>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+struct SnapshotJob {
+    bus: u8,
+    file: PathBuf,
+    /// The saved scan to compare with; none to just save.
+    before: Option<Snapshot>,
+    /// Write the new scan to `file`.
+    update: bool,
+    timeout: Option<Duration>,
+}
+
+impl BusJob for SnapshotJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        say!("🔍 Scanning and identifying bus {}...", self.bus);
+        let now = Snapshot::take(&mut i2c, self.bus);
+        let changes = match &self.before {
+            Some(before) => {
+                let age = match now.taken.saturating_sub(before.taken) {
+                    secs if secs < 120 => format!("{}s", secs),
+                    secs if secs < 2 * 3600 => format!("{}m", secs / 60),
+                    secs if secs < 2 * 86400 => format!("{}h", secs / 3600),
+                    secs => format!("{}d", secs / 86400),
+                };
+                say!("📋 Against {} ({} devices, saved {} ago)", self.file.display(), before.devices.len(), age);
+                before.diff(&now)
+            }
+            None => {
+                for device in &now.devices {
+                    say!("   {}: {}", device.address, device.identity());
+                }
+                Vec::new()
+            }
+        };
+        for change in &changes {
+            let mark = match change {
+                Change::Appeared(_) => "➕",
+                Change::Vanished(_) => "❌",
+                Change::Changed { .. } => "🔀",
+            };
+            say!("   {} {}", mark, change);
+        }
+        if self.update {
+            now.save(&self.file)?;
+            say!("💾 Saved {} devices to {}", now.devices.len(), self.file.display());
+        }
+        if !changes.is_empty() {
+            return Err(VerificationFailed {
+                details: format!("{} changes since {}", changes.len(), self.file.display()),
+            }
+            .into());
+        }
+        if self.before.is_some() {
+            say!("✅ Same {} devices as {}", now.devices.len(), self.file.display());
+        }
+        Ok(())
+    }
+}
+
+struct VerifyJob {
+    inventory: Inventory,
+    timeout: Option<Duration>,
+}
+
+impl BusJob for VerifyJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let checks: usize = self.inventory.devices.iter().map(|d| d.registers.len()).sum();
+        say!(
+            "🔍 Verifying {} devices and {} registers{}",
+            self.inventory.devices.len(),
+            checks,
+            if self.inventory.strict { " (strict: no other devices allowed)" } else { "" }
+        );
+        let mismatches = self.inventory.verify(&mut i2c);
+        for mismatch in &mismatches {
+            say!("   ❌ {}", mismatch);
+        }
+        if !mismatches.is_empty() {
+            return Err(VerificationFailed {
+                details: format!("{} mismatches against the inventory", mismatches.len()),
+            }
+            .into());
+        }
+        say!("✅ Bus matches the inventory");
+        Ok(())
+    }
+}
+
+struct ScriptJob {
+    script: Script,
+    timeout: Option<Duration>,
+}
+
+impl BusJob for ScriptJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        say!("📜 Running {} statements", self.script.statements.len());
+        let report = self.script.run(&mut i2c)?;
+        say!("✅ {} operations, {} assertions passed", report.operations, report.assertions);
+        if report.retried_writes > 0 {
+            say!("⚠️  {} writes needed retries to stick; check the wiring and pull-ups", report.retried_writes);
+        }
+        Ok(())
+    }
+}
+
+struct PatternSetup {
+    pattern: Pattern,
+    repeat: u32,
+    period: Duration,
+    delay: PreciseDelay,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+struct PatternJob<C> {
+    setup: PatternSetup,
+    channels: C,
+}
+
+/// GPIO events printed rather than driven, for a dry run.
+struct PrintedPins;
+
+impl Channels for PrintedPins {
+    fn set(&mut self, pin: u8, high: bool) -> Result<(), Box<dyn Error>> {
+        say!("🧪 GPIO {} {}", pin, if high { "high" } else { "low" });
+        Ok(())
+    }
+
+    fn pwm(&mut self, pin: u8, tone: Option<(f64, f64)>) -> Result<(), Box<dyn Error>> {
+        match tone {
+            Some((frequency, duty)) => say!("🧪 PWM GPIO {} at {}Hz, duty {}", pin, frequency, duty),
+            None => say!("🧪 PWM GPIO {} off", pin),
+        }
+        Ok(())
+    }
+}
+
+impl<C: Channels> BusJob for PatternJob<C> {
+    fn run<I2C>(mut self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        let setup = &self.setup;
+        if let Some(timeout) = setup.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let events = &setup.pattern.events;
+        say!(
+            "🎼 Playing {} events over {:.3}ms, {} times every {:.1}ms",
+            events.len(),
+            setup.pattern.length().as_secs_f64() * 1e3,
+            setup.repeat,
+            setup.period.as_secs_f64() * 1e3
+        );
+        let first = Instant::now();
+        let mut worst: Option<Fired> = None;
+        let mut failed = 0;
+        let mut played = 0;
+        for play in 0..setup.repeat {
+            if setup.shutdown.requested() {
+                break;
+            }
+            let fired = setup.pattern.run(&mut i2c, &mut self.channels, &setup.delay, first + setup.period * play)?;
+            if play == 0 {
+                for (f, scheduled) in fired.iter().zip(events) {
+                    let outcome = f.error.as_ref().map(|e| format!("  ❌ {}", e)).unwrap_or_default();
+                    say!("   {:>9.3}ms  +{:>4}µs  {}{}", f.at.as_secs_f64() * 1e3, f.skew().as_micros(), scheduled.event, outcome);
+                }
+            }
+            failed += fired.iter().filter(|f| f.error.is_some()).count();
+            if let Some(late) = fired.into_iter().max_by_key(Fired::skew) {
+                if worst.as_ref().is_none_or(|w| late.skew() > w.skew()) {
+                    worst = Some(late);
+                }
+            }
+            played += 1;
+        }
+        if let Some(worst) = &worst {
+            say!("📊 {} plays: worst skew {}µs (line {}), {} transfers failed", played, worst.skew().as_micros(), worst.line, failed);
+        }
+        if played < setup.repeat {
+            return Err(Interrupted.into());
+        }
+        Ok(())
+    }
+}
+
+struct ServeJob {
+    listen: String,
+    /// HTTPS settings, with --tls-cert.
+    tls: Option<Arc<rustls::ServerConfig>>,
+    retries: u32,
+    tokens: Option<TokenStore>,
+    history: History,
+    watches: Watches,
+    totals: Totals,
+    /// What the rules and their outputs are built from.
+    config: Config,
+    /// Reloads `config`'s file while serving, with --config.
+    watcher: Option<ConfigWatcher>,
+    peripherals: Peripherals,
+    bus: u8,
+    dry_run: bool,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+type Backlight = Box<dyn FnMut() -> Result<(), Box<dyn Error>> + Send>;
+
+/// The `[[rules]]` thread of `serve`, restarted with new outputs when a
+/// reload changes the rules or what they drive.
+struct RuleRunner {
+    history: History,
+    interval: Duration,
+    peripherals: Peripherals,
+    bus: u8,
+    dry_run: bool,
+    /// Blinks the backpack at an address, for the `flash` action.
+    flash: Box<dyn FnMut(Address) -> Backlight + Send>,
+    running: Option<(Arc<AtomicBool>, JoinHandle<()>, Vec<Claim>)>,
+}
+
+impl RuleRunner {
+    /// Stop the rules running now, if any, and start `config`'s.
+    fn start(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        self.stop();
+        let (mut outputs, claims) = rule_outputs(config, &self.peripherals, self.dry_run)?;
+        if let Some(address) = configured_lcd(config, self.bus)? {
+            outputs.set_flash((self.flash)(address));
+        }
+        let rules = Rules::new(&config.rules, self.history.clone(), outputs);
+        if rules.is_empty() {
+            return Ok(());
+        }
+        say!("🚨 Checking {} rule(s) every {:.1}s", rules.len(), self.interval.as_secs_f64());
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = rules.spawn(self.interval, Arc::clone(&stop));
+        self.running = Some((stop, handle, claims));
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some((stop, handle, _claims)) = self.running.take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = handle.join();
+        }
+    }
+}
+
+impl BusJob for ServeJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let listener = TcpListener::bind(&self.listen).map_err(|e| format!("cannot listen on {}: {}", self.listen, e))?;
+        let events = Events::new();
+        let mut bus = MeteredBus::new(EventBus::new(i2c, events.clone()), Metrics::new());
+        bus.set_retries(self.retries);
+        let metrics = bus.metrics();
+        let readings = events.clone();
+        self.history.on_record(move |measurement, value| readings.reading(measurement, value));
+        let mut watches = self.watches;
+        watches.set_metrics(metrics.clone());
+        let interval = self.config.monitor.interval;
+        let evaluator = watches.clone().spawn(interval, self.shutdown.flag());
+        let mut totals = self.totals;
+        totals.set_metrics(metrics.clone());
+        let totalizer = totals.clone().spawn(interval, self.shutdown.flag());
+        let manager = BusManager::new(bus);
+        let backpacks = manager.shared();
+        let runner = Arc::new(Mutex::new(RuleRunner {
+            history: self.history.clone(),
+            interval,
+            peripherals: self.peripherals,
+            bus: self.bus,
+            dry_run: self.dry_run,
+            flash: Box::new(move |address| {
+                let mut backpack = Backpack::new(backpacks.clone(), address);
+                Box::new(move || Flash::ERROR.play(|on| backpack.set_backlight(on)))
+            }),
+            running: None,
+        }));
+        lock(&runner).start(&self.config)?;
+        let reloader = self.watcher.map(|mut watcher| {
+            let live = watches.clone();
+            let mut watched = self.config.watches.clone();
+            watcher.on_change(move |config| {
+                if config.watches != watched {
+                    live.set_config(&config.watches)?;
+                    watched = config.watches.clone();
+                }
+                Ok(())
+            });
+            let rules = Arc::clone(&runner);
+            let driven = |c: &Config| (c.rules.clone(), c.alerts.clone(), c.mqtt.clone(), c.devices.clone());
+            let mut started = driven(&self.config);
+            watcher.on_change(move |config| {
+                if driven(config) != started {
+                    // Recorded first, so a rollback restarts the old rules
+                    started = driven(config);
+                    lock(&rules).start(config)?;
+                }
+                Ok(())
+            });
+            let mut applied = self.config.clone();
+            watcher.on_change(move |config| {
+                let restart = [
+                    ("history", config.history != applied.history),
+                    ("totals", config.totals != applied.totals),
+                    ("monitor", config.monitor != applied.monitor),
+                ];
+                for (section, _) in restart.iter().filter(|(_, changed)| *changed) {
+                    say!("ℹ️  [{}] changed; restart serve to apply it", section);
+                }
+                applied = config.clone();
+                Ok(())
+            });
+            say!("👁️  Reloading watches and rules when the config changes");
+            watcher.spawn(CONFIG_POLL, self.shutdown.flag())
+        });
+        let mut server = Server::new(manager.shared());
+        server.set_history(self.history);
+        server.set_metrics(metrics);
+        server.set_watches(watches);
+        server.set_totals(totals);
+        server.set_events(events);
+        match self.tokens {
+            Some(tokens) => server.set_tokens(tokens),
+            None => say!("⚠️  No --tokens file: anyone who can reach {} controls the bus", self.listen),
+        }
+        let scheme = match self.tls {
+            Some(tls) => {
+                server.set_tls(tls);
+                "https"
+            }
+            None => "http",
+        };
+        say!("🌐 Serving on {}://{} (live bus view at /dashboard)", scheme, self.listen);
+        server.serve(&listener, &self.shutdown.flag())?;
+        let _ = evaluator.join();
+        let _ = totalizer.join();
+        if let Some(reloader) = reloader {
+            let _ = reloader.join();
+        }
+        lock(&runner).stop();
+        say!("👋 Server stopped");
+        Ok(())
+    }
+}
+
+struct ProxyJob {
+    listen: String,
+    tokens: Option<TokenStore>,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for ProxyJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let listener = TcpListener::bind(&self.listen).map_err(|e| format!("cannot listen on {}: {}", self.listen, e))?;
+        if self.tokens.is_none() {
+            say!("⚠️  No --tokens file: anyone who can reach {} controls the bus", self.listen);
+        }
+        say!("🔌 Proxying the bus on {}", self.listen);
+        remote::serve(&mut i2c, &listener, self.tokens.as_ref(), &self.shutdown.flag())?;
+        say!("👋 Proxy stopped");
+        Ok(())
+    }
+}
+
+struct ApplyJob {
+    /// The rails, when the plan switches any on and it isn't a dry run.
+    power: Option<PowerManager<rppal::gpio::OutputPin>>,
+    plan: Plan,
+    state: PathBuf,
+    /// The config file as read, which is what gets recorded.
+    config_text: String,
+    profile: Option<String>,
+    bus: u8,
+    dry_run: bool,
+    timeout: Option<Duration>,
+}
+
+impl BusJob for ApplyJob {
+    fn run<I2C>(mut self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let mut skipped = 0;
+        for action in &self.plan.actions {
+            match action {
+                Action::RailOn(rail) => {
+                    let Some(power) = &mut self.power else {
+                        say!("🧪 Dry run: not switching on rail '{}'", rail.name);
+                        continue;
+                    };
+                    power.rail_on(&rail.name)?;
+                    say!("✅ rail '{}' on", rail.name);
+                }
+                Action::Nothing(device) => say!("✅ device '{}' needs no init", device.name),
+                Action::Init { device, .. } if device.bus != self.bus => {
+                    say!("⏭️  device '{}' is on bus {}, not {}; apply there with --bus {}", device.name, device.bus, self.bus, device.bus);
+                    skipped += 1;
+                }
+                Action::Init { device, .. } => match plan::init_device(&mut i2c, device) {
+                    Ok(_) => say!("✅ device '{}' initialized", device.name),
+                    Err(e) if device.optional => {
+                        say!("⚠️  device '{}': {}; optional, carrying on without it", device.name, e);
+                    }
+                    Err(e) => return Err(format!("device '{}': {}; not recorded as applied", device.name, e).into()),
+                },
+            }
+        }
+        if self.dry_run {
+            say!("🧪 Dry run: not recording {} as applied", self.state.display());
+        } else if skipped > 0 {
+            say!("⚠️  {} devices on other buses left out, so {} is not updated", skipped, self.state.display());
+        } else {
+            plan::save_applied(&self.state, &self.config_text, self.profile.as_deref())?;
+            say!("💾 Recorded as applied in {}", self.state.display());
+        }
+        Ok(())
+    }
+}
+
+struct PublishJob {
+    publisher: Publisher,
+    interval: Duration,
+    metrics_port: Option<u16>,
+    metrics_bind: String,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for PublishJob {
+    fn run<I2C>(mut self, i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        let mut i2c = MeteredBus::new(i2c, Metrics::new());
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        if let Some(port) = self.metrics_port {
+            let listen = format!("{}:{}", self.metrics_bind, port);
+            let listener = TcpListener::bind(&listen).map_err(|e| format!("cannot listen on {}: {}", listen, e))?;
+            server::spawn_metrics(listener, i2c.metrics(), self.shutdown.flag())?;
+            say!("📈 Metrics on http://{}/metrics", listen);
+        }
+        // Scans only see presence; drivers feed NACKs through EventDetector::record
+        let mut detector = EventDetector::new(10, Duration::from_secs(60));
+        detector.scanned(&scan::scan(&mut i2c));
+        say!(
+            "📡 Publishing bus events to {} every {:.1}s",
+            self.publisher.config().broker,
+            self.interval.as_secs_f64()
+        );
+        while self.shutdown.sleep(self.interval) {
+            for event in detector.scanned(&scan::scan(&mut i2c)) {
+                say!("🔔 {}", event);
+                if let Err(e) = self.publisher.event(&event) {
+                    say!("⚠️  MQTT publish failed: {}", e);
+                }
+            }
+            if let Err(e) = self.publisher.tick() {
+                say!("⚠️  MQTT keep-alive failed: {}", e);
+            }
+        }
+        self.publisher.disconnect()?;
+        say!("👋 Stopped publishing");
+        Ok(())
+    }
+}
+
+struct EnergyJob {
+    name: String,
+    chip: PowerChip,
+    address: Address,
+    shunt: f64,
+    max_current: f64,
+    interval: Duration,
+    tariff: Option<Tariff>,
+    state: Option<PathBuf>,
+    log: Option<PathBuf>,
+    metrics_port: Option<u16>,
+    metrics_bind: String,
+    publisher: Option<Publisher>,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for EnergyJob {
+    fn run<I2C>(mut self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let sensor: Box<dyn PowerMonitor> = match self.chip {
+            PowerChip::Ina219 => Box::new(Ina219::new(i2c, self.address, self.shunt, self.max_current)?),
+            PowerChip::Ina226 => Box::new(Ina226::new(i2c, self.address, self.shunt, self.max_current)?),
+        };
+        if self.state.is_none() {
+            say!("⚠️  No --state file or totals.state: totals start from zero on every run");
+        }
+        let mut monitor = EnergyMonitor::new(sensor, &self.name, self.state.take(), self.interval, self.tariff.take())?;
+        let metrics = Metrics::new();
+        monitor.set_metrics(metrics.clone());
+        if let Some(port) = self.metrics_port {
+            let listen = format!("{}:{}", self.metrics_bind, port);
+            let listener = TcpListener::bind(&listen).map_err(|e| format!("cannot listen on {}: {}", listen, e))?;
+            server::spawn_metrics(listener, metrics, self.shutdown.flag())?;
+            say!("📈 Metrics on http://{}/metrics", listen);
+        }
+        let mut log = match &self.log {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+        say!("⚡ Monitoring {} at {} every {:.1}s", self.name, self.address, self.interval.as_secs_f64());
+        loop {
+            match monitor.sample() {
+                Ok(sample) => {
+                    let cost = match (sample.cost_today, monitor.tariff()) {
+                        (Some(cost), Some(tariff)) => format!(" ({})", format!("{:.2} {}", cost, tariff.currency).trim_end()),
+                        _ => String::new(),
+                    };
+                    say!(
+                        "⚡ {:8.3} W {:7.3} V {:7.4} A   today {:.4} kWh{}   total {:.3} kWh",
+                        sample.power, sample.voltage, sample.current, sample.today_kwh, cost, sample.total_kwh
+                    );
+                    if let Some(file) = &mut log {
+                        writeln!(file, "{}", serde_json::to_string(&sample)?)?;
+                    }
+                    if let Some(publisher) = &mut self.publisher {
+                        let readings = [
+                            ("power", sample.power, "W"),
+                            ("voltage", sample.voltage, "V"),
+                            ("current", sample.current, "A"),
+                            ("energy", sample.total_kwh, "kWh"),
+                            ("energy_today", sample.today_kwh, "kWh"),
+                        ];
+                        let cost = sample.cost_today.zip(monitor.tariff()).map(|(cost, t)| ("cost_today", cost, t.currency.as_str()));
+                        for (quantity, value, unit) in readings.into_iter().chain(cost) {
+                            if let Err(e) = publisher.reading(&self.name, quantity, value, unit) {
+                                say!("⚠️  MQTT publish failed: {}", e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => say!("⚠️  {}: {}", self.name, e),
+            }
+            if !self.shutdown.sleep(self.interval) {
+                break;
+            }
+        }
+        monitor.save()?;
+        if let Some(publisher) = &mut self.publisher {
+            publisher.disconnect()?;
+        }
+        say!("👋 Stopped monitoring {}", self.name);
+        Ok(())
+    }
+}
+
+/// The first hd44780 configured on bus `bus_id`.
+fn configured_lcd(config: &Config, bus_id: u8) -> Result<Option<Address>, Box<dyn Error>> {
+    config
+        .devices
+        .iter()
+        .find(|d| d.driver == "hd44780" && d.bus == bus_id)
+        .and_then(|d| d.address)
+        .map(Address::from_raw)
+        .transpose()
+}
+
+/// The waits the first hd44780 on bus `bus_id` is configured with, else
+/// the defaults.
+fn configured_lcd_delays(config: &Config, bus_id: u8) -> LcdDelays {
+    let defaults = LcdDelays::default();
+    let Some(device) = config.devices.iter().find(|d| d.driver == "hd44780" && d.bus == bus_id) else {
+        return defaults;
+    };
+    LcdDelays {
+        command: device.write_delay.unwrap_or(defaults.command),
+        clear: device.clear_delay.unwrap_or(defaults.clear),
+    }
+}
+
+/// `address`, else whichever of the usual backpack addresses answers.
+fn find_lcd<I2C: AddressedI2c>(i2c: &mut I2C, address: Option<Address>) -> Result<Address, Box<dyn Error>> {
+    match address {
+        Some(address) => Ok(address),
+        None => {
+            let detection = Detection::lcd();
+            Ok(detect(i2c, &detection).ok_or_else(|| detection.not_found())?)
+        }
+    }
+}
+
+/// The configured pages if they only show system values, else the defaults.
+fn sysinfo_pages(config: &Config) -> Vec<PageConfig> {
+    let pages: Vec<_> = config.pages.iter().filter(|page| sysinfo::fills(page)).cloned().collect();
+    if pages.is_empty() {
+        return sysinfo::default_pages();
+    }
+    if pages.len() < config.pages.len() {
+        say!("ℹ️  Skipping {} page(s) showing more than system values", config.pages.len() - pages.len());
+    }
+    pages
+}
+
+/// Where sysinfo pages get their values.
+fn sysinfo_sources(units: UnitsConfig) -> Sources {
+    let mut sources = Sources::new();
+    sources.add(StatusSource::new(units)).add(LocalTime);
+    sources
+}
+
+fn print_sysinfo(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut sources = sysinfo_sources(config.units);
+    for (n, page) in sysinfo_pages(config).iter().enumerate() {
+        if n > 0 {
+            say!();
+        }
+        for line in Screen::new(&page.lines)?.render(&mut sources) {
+            say!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+struct SysinfoJob {
+    address: Option<Address>,
+    cols: u8,
+    rows: u8,
+    refresh: Duration,
+    pages: Vec<PageConfig>,
+    /// Reloads `pages` when `[[pages]]` changes, with --config.
+    watcher: Option<ConfigWatcher>,
+    units: UnitsConfig,
+    hid: Option<HidInput>,
+    ir: Option<u8>,
+    timeout: Option<Duration>,
+    /// Lockups in a row before the bus is recovered and the LCD re-inited.
+    lockups: u32,
+    shutdown: Shutdown,
+    delays: LcdDelays,
+}
+
+type Controls = Box<dyn FnMut() -> Result<Option<Nav>, Box<dyn Error>>>;
+
+impl BusJob for SysinfoJob {
+    fn run<I2C>(mut self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let address = find_lcd(&mut i2c, self.address)?;
+        let mut lcd = Lcd::new(i2c, address, self.cols, self.rows)?;
+        lcd.set_delays(self.delays);
+        say!("🖥️  Showing system status on the LCD at {}, {} page(s)", address, self.pages.len());
+        let mut controls: Option<Controls> = match (self.hid.take(), self.ir) {
+            (Some(input), _) => {
+                input.grab()?;
+                say!("⌨️  Flipping pages with {}", input.name());
+                let mut hid = HidControls::new(input, KeyMap::default());
+                Some(Box::new(move || hid.poll()))
+            }
+            (None, Some(pin)) => {
+                let mut ir = IrControls::from_gpio(pin, IrKeyMap::default())?;
+                say!("📡 Flipping pages with the IR remote on GPIO {}", pin);
+                Some(Box::new(move || ir.poll()))
+            }
+            (None, None) => None,
+        };
+        let mut watchdog = LockupWatchdog::new("lcd", self.lockups);
+        let mut sources = sysinfo_sources(self.units);
+        let mut index = 0;
+        'pages: loop {
+            let page = self.pages[index].clone();
+            let screen = Screen::new(&page.lines)?;
+            let shown = Instant::now();
+            let mut back = false;
+            let cleared = lcd.clear();
+            watchdog.check(cleared, || reinit_lcd(&mut lcd))?;
+            'page: loop {
+                let drawn = screen.draw(&mut lcd, &mut sources);
+                watchdog.check(drawn, || reinit_lcd(&mut lcd))?;
+                if self.reload_pages() {
+                    index = 0;
+                    continue 'pages;
+                }
+                let left = page.duration.saturating_sub(shown.elapsed());
+                if left.is_zero() {
+                    break;
+                }
+                match &mut controls {
+                    None => {
+                        if !self.shutdown.sleep(self.refresh.min(left)) {
+                            break 'pages;
+                        }
+                    }
+                    Some(controls) => {
+                        let redraw = Instant::now() + self.refresh.min(left);
+                        while Instant::now() < redraw {
+                            match controls()? {
+                                Some(Nav::Up) => {
+                                    back = true;
+                                    break 'page;
+                                }
+                                Some(_) => break 'page,
+                                None => {}
+                            }
+                            if !self.shutdown.sleep(menu::POLL) {
+                                break 'pages;
+                            }
+                        }
+                    }
+                }
+            }
+            let count = self.pages.len();
+            index = if back { (index + count - 1) % count } else { (index + 1) % count };
+        }
+        say!("👋 Stopped showing system status");
+        Ok(())
+    }
+}
+
+impl SysinfoJob {
+    /// Pick up a changed config file's `[[pages]]`; whether they changed.
+    fn reload_pages(&mut self) -> bool {
+        let Some(watcher) = &mut self.watcher else {
+            return false;
+        };
+        if !watcher.poll_logged() {
+            return false;
+        }
+        let pages = sysinfo_pages(&watcher.snapshot());
+        if pages == self.pages {
+            return false;
+        }
+        say!("🖥️  Now showing {} page(s)", pages.len());
+        self.pages = pages;
+        true
+    }
+}
+
+/// Free a hung bus and bring the LCD back to a known state.
+fn reinit_lcd<I2C: AddressedI2c + BusControl>(lcd: &mut Lcd<Backpack<I2C>>) -> Result<(), Box<dyn Error>> {
+    lcd.interface().i2c_mut().recover()?;
+    lcd.init()
+}
+
+type GpsSource = GpsReader<Box<dyn BufRead>>;
+
+fn open_gps(port: &Path, baud: u32, file: Option<&Path>) -> Result<GpsSource, Box<dyn Error>> {
+    let reader: Box<dyn BufRead> = match file {
+        Some(path) => Box::new(io::BufReader::new(
+            std::fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        )),
+        None => Box::new(io::BufReader::new(SerialPort::open(port, baud)?)),
+    };
+    Ok(GpsReader::new(reader))
+}
+
+/// Print fixes until Ctrl-C, `count` fixes or the end of a replayed file,
+/// handing each to `show` as well.
+fn gps_loop(
+    gps: &mut GpsSource,
+    replay: bool,
+    json: bool,
+    count: Option<u64>,
+    shutdown: &Shutdown,
+    mut show: impl FnMut(&Fix) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut fixes = 0u64;
+    while !shutdown.requested() && count.is_none_or(|c| fixes < c) {
+        match gps.next_fix()? {
+            Some(fix) => {
+                fixes += 1;
+                if json {
+                    say!("{}", serde_json::to_string(&fix)?);
+                } else {
+                    say!("🛰️  {}", fix);
+                }
+                show(&fix)?;
+            }
+            None if replay => break,
+            None => {}
+        }
+    }
+    if !json {
+        say!("📊 {} fixes, {} bad sentences dropped", fixes, gps.rejected());
+    }
+    Ok(())
+}
+
+/// Two LCD lines: where, then when and how well.
+fn gps_lcd_text(fix: &Fix) -> String {
+    let position = match (fix.valid(), fix.latitude, fix.longitude) {
+        (true, Some(lat), Some(lon)) => format!(
+            "{:.4}{}{:.4}{}",
+            lat.abs(),
+            if lat < 0.0 { 'S' } else { 'N' },
+            lon.abs(),
+            if lon < 0.0 { 'W' } else { 'E' }
+        ),
+        _ => "Waiting for fix".to_string(),
+    };
+    let time = fix.time.map(|t| format!("{:02}:{:02}:{:02.0}", t.hour, t.minute, t.second.floor())).unwrap_or_else(|| "--:--:--".to_string());
+    format!("{}\n{} {}sat", position, time, fix.satellites.unwrap_or(0))
+}
+
+struct GpsJob {
+    gps: GpsSource,
+    replay: bool,
+    json: bool,
+    count: Option<u64>,
+    address: Option<Address>,
+    shutdown: Shutdown,
 }
 
-impl SimpleI2cTransmitter {
-    pub fn new(mut i2c: RppalI2c, address: u8) -> Result<Self, Box<dyn Error>> {
-        // Set the slave address for this I2C instance
-        i2c.set_slave_address(address as u16)?;
-        Ok(SimpleI2cTransmitter { i2c, address })
+impl BusJob for GpsJob {
+    fn run<I2C>(mut self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        let address = find_lcd(&mut i2c, self.address)?;
+        let mut lcd = Lcd::new(i2c, address, 16, 2)?;
+        lcd.show("Waiting for GPS")?;
+        gps_loop(&mut self.gps, self.replay, self.json, self.count, &self.shutdown, |fix| lcd.update(&gps_lcd_text(fix)))
     }
+}
 
-    /// Send single byte with detailed error logging
-    fn send_byte(&mut self, data: u8, description: &str) -> Result<(), Box<dyn Error>> {
-        print!("📡 TX: 0x{:02X} {} ", data, description);
-        
-        match self.i2c.write(&[data]) {
-            Ok(_) => {
-                println!("✅ ACK - PCF8574 responded!");
-                Ok(())
-            },
-            Err(e) => {
-                println!("❌ Error: {}", e);
-                // Don't fail completely, continue for scope analysis
-                Ok(())
+struct ScanJob {
+    bus: u8,
+    identify: bool,
+    conflicts: bool,
+    timeout: Option<Duration>,
+}
+
+impl BusJob for ScanJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        say!("🔍 Scanning bus {}...", self.bus);
+        let found = scan::scan(&mut i2c);
+        let mut shared = 0;
+        for &address in &found {
+            if self.conflicts {
+                let report = identify::conflicts(&mut i2c, address, &found);
+                say!("   {}", report.identification);
+                for conflict in &report.conflicts {
+                    say!("      ⚠️  {}", conflict);
+                }
+                if let Some(remedy) = report.remedy {
+                    say!("      💡 {}", remedy);
+                }
+                if report.is_shared() {
+                    shared += 1;
+                }
+            } else if self.identify {
+                say!("   {}", identify::identify(&mut i2c, address));
+            } else {
+                say!("   {}", address);
             }
         }
+        if found.is_empty() {
+            return Err(DeviceNotFound { tried: Vec::new() }.into());
+        }
+        say!("✅ {} device(s) answered", found.len());
+        if shared > 0 {
+            say!("⚠️  {} address(es) look shared by more than one chip", shared);
+        }
+        Ok(())
     }
+}
 
-    /// Send "Happy Birthday" message and measure timing
-    pub fn send_message(&mut self, message_number: u8) -> Result<Duration, Box<dyn Error>> {
-        println!("\n🎉 MESSAGE {} - Sending 'Happy Birthday'", message_number);
-        let start_time = Instant::now();
-        
-        // Start marker
-        self.send_byte(0xFF, "START")?;
-        thread::sleep(Duration::from_millis(50));
-        
-        // Send each character
-        let text = "Happy Birthday";
-        for ch in text.chars() {
-            let ascii = ch as u8;
-            self.send_byte(ascii, &format!("'{}'", ch))?;
-            thread::sleep(Duration::from_millis(50)); // 50ms between characters
+struct LogJob {
+    devices: Vec<DeviceConfig>,
+    output: PathBuf,
+    format: Format,
+    rotation: Rotation,
+    interval: Duration,
+    flush: Duration,
+    count: Option<u64>,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for LogJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
         }
-        
-        // End marker
-        self.send_byte(0x00, "END")?;
-        
-        let transmission_time = start_time.elapsed();
-        println!("✅ Message {} complete in {:.1}ms\n", message_number, transmission_time.as_millis());
-        
-        Ok(transmission_time)
+        let manager = BusManager::new(i2c);
+        for device in &self.devices {
+            if let Some(address) = device.address {
+                manager.set_policy(Address::from_raw(address)?, device.policy())?;
+            }
+        }
+        let mut devices = Vec::new();
+        for device in &self.devices {
+            match datalog::open_device(device, manager.shared()) {
+                Ok(logged) => devices.push(logged),
+                Err(e) if device.optional => say!("⚠️  Logging without optional device '{}': {}", device.name, e),
+                Err(e) => return Err(e),
+            }
+        }
+        if devices.is_empty() {
+            return Err("none of the devices to log came up".into());
+        }
+        let mut logger = DataLogger::open(&self.output, self.format, self.rotation)?;
+        say!(
+            "📝 Logging {} device(s) every {:.1}s to {} ({})",
+            devices.len(),
+            self.interval.as_secs_f64(),
+            logger.path().display(),
+            logger.format()
+        );
+        let mut rounds = 0;
+        let mut flushed = Instant::now();
+        loop {
+            let time = SystemTime::now();
+            let mut samples = Vec::new();
+            for device in &mut devices {
+                match device.sample() {
+                    Ok(values) => samples.extend(
+                        values
+                            .into_iter()
+                            .map(|(quantity, value, unit)| Sample::new(time, device.name(), quantity, value, unit)),
+                    ),
+                    Err(e) => say!("⚠️  {}: {}", device.name(), e),
+                }
+            }
+            logger.append(&samples)?;
+            rounds += 1;
+            if flushed.elapsed() >= self.flush {
+                if let Some(rotated) = logger.flush()? {
+                    say!("🔄 Full log moved to {}", rotated.display());
+                }
+                flushed = Instant::now();
+            }
+            if self.count.is_some_and(|count| rounds >= count) || !self.shutdown.sleep(self.interval) {
+                break;
+            }
+        }
+        if let Some(rotated) = logger.flush()? {
+            say!("🔄 Full log moved to {}", rotated.display());
+        }
+        say!("💾 {} row(s) from {} round(s), last in {}", logger.rows(), rounds, logger.path().display());
+        for usage in manager.usage() {
+            say!(
+                "🚌 {}: {} transaction(s), {:.1}% of the bus, {:.2}s waiting",
+                usage.address,
+                usage.transactions,
+                usage.utilization * 100.0,
+                usage.waited.as_secs_f64()
+            );
+        }
+        Ok(())
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    println!("🚀 Dynamic Rhythm I2C 'Happy Birthday' Transmitter");
-    println!("🎵 Pattern: Send → Wait(same duration) → Send → Wait → repeat for 2s");
-    println!("⚠️  Make sure to run with: sudo ./your_program");
-    println!("");
-    println!("🔧 Oscilloscope Setup:");
-    println!("   - SDA: GPIO 2 (Pin 3)");
-    println!("   - SCL: GPIO 3 (Pin 5)");
-    println!("   - GND: Pin 6");
-    println!("   - Timebase: 200ms/div (to see rhythm pattern)");
-    println!("   - Trigger: SDA falling edge");
-    println!("");
+struct Apds9960Job {
+    address: Address,
+    gestures: bool,
+    interval: Duration,
+    count: Option<u64>,
+    shutdown: Shutdown,
+}
 
-    // Initialize I2C
-    let mut i2c = RppalI2c::with_bus(1)?;
-    println!("📡 I2C bus 1 initialized");
-    
-    // Show I2C speed if available
-    if let Ok(speed) = i2c.clock_speed() {
-        println!("🔧 I2C speed: {} Hz", speed);
-    }
-    
-    // Auto-detect I2C address
-    let mut working_address = None;
-    println!("🔍 Scanning for LCD I2C controller...");
-    
-    for &addr in &COMMON_ADDRESSES {
-        println!("   Testing address 0x{:02X}...", addr);
-        i2c.set_slave_address(addr as u16)?;
-        match i2c.write(&[0x00]) {
-            Ok(_) => {
-                println!("   ✅ Found working device at 0x{:02X}!", addr);
-                working_address = Some(addr);
+impl BusJob for Apds9960Job {
+    fn run<I2C>(self, i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        let mut sensor = Apds9960::new(i2c, self.address)?;
+        let mut seen = 0;
+        if self.gestures {
+            sensor.set_engine(Engine::Gesture, true)?;
+            say!("👋 Watching for swipes over the APDS-9960 at {}", self.address);
+            while self.count.is_none_or(|count| seen < count) {
+                match sensor.read_gesture()? {
+                    Some(gesture) => {
+                        say!("{}", gesture);
+                        seen += 1;
+                    }
+                    None => {
+                        if !self.shutdown.sleep(GESTURE_POLL) {
+                            break;
+                        }
+                    }
+                }
+            }
+            return sensor.set_engine(Engine::Gesture, false);
+        }
+        sensor.set_engine(Engine::Proximity, true)?;
+        sensor.set_engine(Engine::Color, true)?;
+        // The first colour cycle takes one integration time
+        while self.shutdown.sleep(self.interval) {
+            let proximity = sensor.proximity()?;
+            let color = sensor.color()?;
+            say!(
+                "proximity {:>3}  clear {:>5}  red {:>5}  green {:>5}  blue {:>5}",
+                proximity, color.clear, color.red, color.green, color.blue
+            );
+            seen += 1;
+            if self.count.is_some_and(|count| seen >= count) {
                 break;
-            },
-            Err(_) => {
-                println!("   ❌ No response at 0x{:02X}", addr);
             }
         }
+        Ok(())
     }
-    
-    let target_address = working_address.unwrap_or(COMMON_ADDRESSES[0]);
-    if working_address.is_none() {
-        println!("⚠️  No I2C device found, using 0x{:02X} anyway for scope analysis", target_address);
+}
+
+struct BacklightJob {
+    address: Option<Address>,
+    state: BacklightState,
+    flash: Flash,
+}
+
+impl BusJob for BacklightJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        let address = find_lcd(&mut i2c, self.address)?;
+        // Only the backlight bit changes, so whatever is on screen stays
+        let mut backpack = Backpack::new(i2c, address);
+        match self.state {
+            BacklightState::On => backpack.set_backlight(true)?,
+            BacklightState::Off => backpack.set_backlight(false)?,
+            BacklightState::Blink => {
+                say!("💡 Blinking the backlight at {} {} times", address, self.flash.times);
+                self.flash.play(|on| backpack.set_backlight(on))?;
+                return Ok(());
+            }
+        }
+        say!("💡 Backlight at {} {}", address, if self.state == BacklightState::On { "on" } else { "off" });
+        Ok(())
     }
-    
-    let mut transmitter = SimpleI2cTransmitter::new(i2c, target_address)?;
-    
-    println!("🎯 Starting dynamic rhythm transmission...");
-    println!("📍 Target address: 0x{:02X}", target_address);
-    println!("⏱️  Total duration: 2 seconds");
-    println!("");
+}
 
-    // Dynamic rhythm pattern for 2 seconds
-    let start_time = Instant::now();
-    let total_duration = Duration::from_secs(2);
-    let mut message_count = 0;
-    
-    println!("🎵 Starting rhythm pattern...");
-    
-    while start_time.elapsed() < total_duration {
-        let remaining_time = total_duration - start_time.elapsed();
-        
-        message_count += 1;
-        println!("⏰ Rhythm cycle {} (Remaining: {:.1}s)", message_count, remaining_time.as_secs_f32());
-        
-        // Send message and measure how long it takes
-        let transmission_time = transmitter.send_message(message_count)?;
-        
-        // Wait for the same duration as the transmission took
-        let wait_time = transmission_time;
-        println!("⏳ Waiting {:.1}ms (same as transmission time)...", wait_time.as_millis());
-        
-        // Check if we have enough time for both wait and next transmission
-        let time_needed = wait_time + transmission_time; // Estimate for next transmission
-        if start_time.elapsed() + time_needed >= total_duration {
-            println!("⏰ Not enough time for complete cycle, stopping...");
-            break;
+/// Whole microseconds, rounded up so a tuned wait isn't cut short.
+fn micros_up(duration: Duration) -> u128 {
+    duration.as_nanos().div_ceil(1000)
+}
+
+struct LcdTuneJob {
+    address: Option<Address>,
+    delays: LcdDelays,
+    rounds: u32,
+    timeout: Option<Duration>,
+}
+
+impl BusJob for LcdTuneJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
         }
-        
-        thread::sleep(wait_time);
+        let address = find_lcd(&mut i2c, self.address)?;
+        let mut lcd = Lcd::new(i2c, address, 16, 2)?;
+        lcd.set_delays(self.delays);
+        lcd.start_tuning()?;
+        say!("🎛️  Tuning the LCD at {} from {:?} per instruction and {:?} per clear...", address, self.delays.command, self.delays.clear);
+        for round in 0..self.rounds {
+            let fill = char::from(b'0' + (round % 10) as u8).to_string().repeat(16);
+            lcd.show(&format!("{}\n{}", fill, fill))?;
+        }
+        lcd.show("Tuned")?;
+        let tuning = lcd.finish_tuning().expect("tuning was started");
+        for (name, tuner) in [("instruction", &tuning.command), ("clear/home", &tuning.clear)] {
+            match tuner.longest() {
+                Some(longest) => say!(
+                    "   {:<11} {:?} (busy up to {:?}, caught busy after {} of {} waits)",
+                    name,
+                    tuner.delay(),
+                    longest,
+                    tuner.caught_busy(),
+                    tuner.waits()
+                ),
+                None => say!("   {:<11} {:?} (never caught busy; the bus is slower than the controller)", name, tuner.delay()),
+            }
+        }
+        let delays = tuning.delays();
+        say!();
+        say!("📋 For the hd44780 device in the config:");
+        say!("   write_delay = \"{}us\"", micros_up(delays.command));
+        say!("   clear_delay = \"{}us\"", micros_up(delays.clear));
+        Ok(())
     }
-    
-    let actual_duration = start_time.elapsed();
-    println!("🏁 Rhythm pattern complete!");
-    println!("");
-    println!("📊 Summary:");
-    println!("   - Messages sent: {}", message_count);
-    println!("   - Actual duration: {:.2}s", actual_duration.as_secs_f32());
-    println!("   - Characters per message: 14 ('Happy Birthday')");
-    println!("   - Pattern: Send → Wait(same time) → Repeat");
-    println!("");
-    println!("🔍 Oscilloscope Analysis:");
-    println!("   📍 Look for rhythmic bursts of I2C activity");
-    println!("   📍 Each burst followed by quiet period of same duration");
-    println!("   📍 {} complete cycles in 2 seconds", message_count);
-    println!("   📍 Address: 0x{:02X} (0b{:08b})", target_address << 1, target_address << 1);
-    if working_address.is_some() {
-        println!("   ✅ Should see ACK responses (SDA low on 9th clock)");
-    } else {
-        println!("   ❌ Will see NACK responses (SDA high on 9th clock)");
+}
+
+struct EepromJob {
+    config: EepromConfig,
+    image: Vec<u8>,
+    verify: bool,
+    timeout: Option<Duration>,
+    quiet: bool,
+}
+
+impl BusJob for EepromJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let config = self.config;
+        say!(
+            "💾 Writing {} bytes to {} from 0x{:04X}, {}-byte pages, {:?} per page{}",
+            self.image.len(),
+            config.address,
+            config.start,
+            config.page,
+            config.write_cycle,
+            if config.tune { " to start" } else { "" }
+        );
+        let mut bar = (!self.quiet).then(|| ProgressBar::new("eeprom", self.image.len() as u64, Unit::Bytes, Stream::Stderr));
+        let report = eeprom::write_with(&mut i2c, &config, &self.image, |done| {
+            if let Some(bar) = &mut bar {
+                bar.set(done as u64);
+            }
+        })?;
+        drop(bar);
+        say!("✅ {} pages in {:.2}s", report.pages, report.elapsed.as_secs_f64());
+        if config.tune {
+            match report.longest {
+                Some(longest) => say!(
+                    "🎛️  Tuned write cycle: {:?} (pages took up to {:?}; {} of {} were still busy after the wait)",
+                    report.write_cycle,
+                    longest,
+                    report.caught_busy,
+                    report.pages
+                ),
+                None => say!("🎛️  Never caught busy; {:?} is enough, and shorter may be too (write more pages to tune further)", report.write_cycle),
+            }
+            say!("📋 For the device in the config: write_delay = \"{}us\"", micros_up(report.write_cycle));
+        }
+        if !self.verify {
+            return Ok(());
+        }
+        let readback = DumpConfig {
+            address: config.address,
+            start: config.start,
+            len: self.image.len(),
+            pointer_width: config.pointer_width,
+            chunk: 32,
+        };
+        let bytes = dump::read(&mut i2c, &readback)?;
+        let differences = dump::compare(&bytes, &self.image, config.start);
+        if differences.is_empty() {
+            say!("✅ Read back the same ({} bytes)", bytes.len());
+            return Ok(());
+        }
+        for difference in differences.iter().take(DUMP_DIFFERENCES) {
+            say!("   ❌ {}", difference);
+        }
+        if differences.len() > DUMP_DIFFERENCES {
+            say!("   ... and {} more", differences.len() - DUMP_DIFFERENCES);
+        }
+        Err(VerificationFailed {
+            details: format!("{} byte(s) read back different from the image", differences.len()),
+        }
+        .into())
+    }
+}
+
+/// The `[[rails]]` entry called `name`, or the one feeding device `name`.
+fn configured_rail<'a>(config: &'a Config, name: &str) -> Result<&'a RailConfig, Box<dyn Error>> {
+    let rail = match config.device(name) {
+        Some(device) => device.rail.as_deref().ok_or_else(|| format!("device '{}' is not on a switched rail", name))?,
+        None => name,
+    };
+    config.rails.iter().find(|r| r.name == rail).ok_or_else(|| format!("no rail or device '{}' in the config", name).into())
+}
+
+struct PowerCycleJob {
+    /// None on a dry run, which leaves the GPIOs alone.
+    power: Option<PowerManager<rppal::gpio::OutputPin>>,
+    name: String,
+    off_time: Duration,
+    devices: Vec<DeviceConfig>,
+    /// Addresses to wait for that aren't configured devices.
+    extra: Vec<Address>,
+    timeout: Duration,
+    count: u32,
+    interval: Duration,
+    init: bool,
+    shutdown: Shutdown,
+}
+
+impl BusJob for PowerCycleJob {
+    fn run<I2C>(mut self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        let mut waits: Vec<(String, Address, Option<&DeviceConfig>)> = Vec::new();
+        for device in &self.devices {
+            let Some(raw) = device.address else { continue };
+            let address = Address::from_raw(raw)?;
+            waits.push((format!("device '{}' ({})", device.name, address), address, Some(device)));
+        }
+        waits.extend(self.extra.iter().map(|&address| (address.to_string(), address, None)));
+        if waits.is_empty() {
+            say!("⚠️  Nothing configured on rail '{}' to wait for; give --wait-for ADDRESS", self.name);
+        }
+        let probe_interval = Duration::from_millis(5);
+        let mut failed_cycles = Vec::new();
+        let mut slowest = Duration::ZERO;
+        for cycle in 1..=self.count {
+            if cycle > 1 && !self.shutdown.sleep(self.interval) {
+                return Err(Interrupted.into());
+            }
+            match &mut self.power {
+                Some(power) => {
+                    power.cycle_rail_for(&self.name, self.off_time)?;
+                }
+                None => say!("🧪 Dry run: not switching rail '{}'", self.name),
+            }
+            say!("🔌 Cycle {}/{}: rail '{}' off for {:.0}ms and back on", cycle, self.count, self.name, self.off_time.as_secs_f64() * 1e3);
+            let mut problems = Vec::new();
+            for (what, address, device) in &waits {
+                match power::wait_for_ack(&mut i2c, *address, self.timeout, probe_interval) {
+                    Ok(took) => {
+                        slowest = slowest.max(took);
+                        say!("✅ {} answered after {:.1}ms", what, took.as_secs_f64() * 1e3);
+                    }
+                    Err(e) => {
+                        say!("❌ {}: {}", what, e);
+                        problems.push(format!("{} never answered", what));
+                        continue;
+                    }
+                }
+                if let Some(device) = device.filter(|_| self.init) {
+                    match plan::init_device(&mut i2c, device) {
+                        Ok(true) => say!("✅ device '{}' re-initialized", device.name),
+                        Ok(false) => {}
+                        Err(e) => {
+                            say!("❌ device '{}': re-init failed: {}", device.name, e);
+                            problems.push(format!("{} re-init failed", what));
+                        }
+                    }
+                }
+            }
+            if !problems.is_empty() {
+                failed_cycles.push(format!("cycle {}: {}", cycle, problems.join(", ")));
+            }
+        }
+        if self.count > 1 {
+            say!(
+                "📊 {} cycles, {} lost a device; slowest to answer {:.1}ms",
+                self.count,
+                failed_cycles.len(),
+                slowest.as_secs_f64() * 1e3
+            );
+        }
+        if failed_cycles.is_empty() {
+            Ok(())
+        } else {
+            Err(VerificationFailed { details: failed_cycles.join("; ") }.into())
+        }
+    }
+}
+
+struct WaitJob {
+    waiting_for: String,
+    address: Address,
+    timeout: Duration,
+    interval: Duration,
+    shutdown: Shutdown,
+}
+
+impl BusJob for WaitJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        say!("⏳ Waiting up to {:.1}s for {}...", self.timeout.as_secs_f64(), self.waiting_for);
+        let start = Instant::now();
+        loop {
+            if scan::probe(&mut i2c, self.address) {
+                say!("✅ {} answered after {:.1}s", self.waiting_for, start.elapsed().as_secs_f64());
+                return Ok(());
+            }
+            let waited = start.elapsed();
+            if waited >= self.timeout {
+                return Err(TimedOut {
+                    waiting_for: self.waiting_for,
+                    after: waited,
+                }
+                .into());
+            }
+            if !self.shutdown.sleep(self.interval.min(self.timeout - waited)) {
+                return Err(Interrupted.into());
+            }
+        }
     }
-    println!("");
-    println!("📝 ASCII values in each message:");
-    for ch in "Happy Birthday".chars() {
-        println!("   '{}' = 0x{:02X}", ch, ch as u8);
+}
+
+struct SoakJob {
+    config: SoakConfig,
+    hgrm: Option<PathBuf>,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for SoakJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        say!(
+            "🔥 Soaking {} every {:.1}ms for {:.1}h (deadline {:.1}ms)",
+            self.config.address,
+            self.config.period.as_secs_f64() * 1e3,
+            self.config.duration.as_secs_f64() / 3600.0,
+            self.config.deadline.as_secs_f64() * 1e3
+        );
+        let flag = self.shutdown.flag();
+        let report = soak::run(&mut i2c, &self.config, &flag, Duration::from_secs(60), |report| {
+            say!(
+                "⏱️  {:>5.0}m: {} transactions, response p99 {} max {}, {} missed, {} failed",
+                report.elapsed.as_secs_f64() / 60.0,
+                report.transactions,
+                micros(report.response.percentile(99.0)),
+                micros(report.response.max()),
+                report.missed_deadlines,
+                report.failures
+            );
+        })?;
+        print_soak(&report);
+        if let Some(path) = &self.hgrm {
+            let file = std::fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            report.response.write_hgrm(io::BufWriter::new(file))?;
+            say!("💾 Wrote the response histogram to {}", path.display());
+        }
+        if self.shutdown.requested() {
+            return Err(Interrupted.into());
+        }
+        Ok(())
     }
-    println!("   Start marker = 0xFF");
-    println!("   End marker = 0x00");
-    
+}
+
+fn print_soak(report: &SoakReport) {
+    say!();
+    say!("📊 Soak summary ({:.1} min):", report.elapsed.as_secs_f64() / 60.0);
+    say!(
+        "   - Transactions: {} ({} failed, {} slots skipped)",
+        report.transactions, report.failures, report.skipped
+    );
+    say!("   - Missed deadlines: {}", report.missed_deadlines);
+    for (name, histogram) in [("Wake-up", &report.wakeup), ("Response", &report.response)] {
+        say!(
+            "   - {}: min {}  p50 {}  p99 {}  p99.9 {}  p99.99 {}  max {}",
+            name,
+            micros(histogram.min()),
+            micros(histogram.percentile(50.0)),
+            micros(histogram.percentile(99.0)),
+            micros(histogram.percentile(99.9)),
+            micros(histogram.percentile(99.99)),
+            micros(histogram.max())
+        );
+    }
+}
+
+fn micros(d: Duration) -> String {
+    format!("{}µs", d.as_micros())
+}
+
+struct StressJob {
+    config: StressConfig,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for StressJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let names: Vec<String> = self.config.addresses.iter().map(ToString::to_string).collect();
+        say!("🎲 {} random ops to {} (seed {})", self.config.ops, names.join(", "), self.config.seed);
+        let flag = self.shutdown.flag();
+        let report = stress::run(&mut i2c, &self.config, &flag, Duration::from_secs(60), |report| {
+            say!(
+                "⏱️  {:>5.0}m: {} of {} ops, {} errors",
+                report.elapsed.as_secs_f64() / 60.0,
+                report.ops,
+                self.config.ops,
+                report.errors()
+            );
+        })?;
+        print_stress(&report);
+        if self.shutdown.requested() {
+            return Err(Interrupted.into());
+        }
+        if report.errors() > 0 {
+            return Err(VerificationFailed {
+                details: format!("{} of {} ops failed; --seed {} replays them", report.errors(), report.ops, self.config.seed),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+fn print_stress(report: &StressReport) {
+    say!();
+    say!("📊 Stress summary ({:.1} min, {} ops, {} pauses):", report.elapsed.as_secs_f64() / 60.0, report.ops, report.pauses);
+    for stats in &report.addresses {
+        say!(
+            "   - {}: {} ops, {} errors ({:.3}%), p50 {}  p99 {}  worst {} at op {}",
+            stats.address,
+            stats.ops,
+            stats.errors,
+            stats.error_rate() * 100.0,
+            micros(stats.latency.percentile(50.0)),
+            micros(stats.latency.percentile(99.0)),
+            micros(stats.worst),
+            stats.worst_at
+        );
+    }
+    for failure in &report.failures {
+        say!("   ❌ op {}: {}: {}", failure.number, failure.op, failure.error);
+    }
+    if report.errors() > report.failures.len() as u64 {
+        say!("   …and {} more errors", report.errors() - report.failures.len() as u64);
+    }
+}
+
+struct BenchJob {
+    address: Address,
+    speeds: Vec<u32>,
+    size: usize,
+    duration: Duration,
+    mode: BenchMode,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for BenchJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        let speeds = if self.speeds.is_empty() { vec![i2c.clock_speed()?] } else { self.speeds };
+        let config = BenchConfig {
+            address: self.address,
+            speeds,
+            size: self.size,
+            duration: self.duration,
+            mode: self.mode,
+        };
+        config.validate()?;
+        say!(
+            "🏎️  Benchmarking {}: {}-byte {} for {:.1}s at each of {} speed(s)",
+            config.address,
+            config.size,
+            if config.mode == BenchMode::Write { "writes" } else { "reads" },
+            config.duration.as_secs_f64(),
+            config.speeds.len()
+        );
+        say!("{}", bench::TABLE_HEADER);
+        let flag = self.shutdown.flag();
+        let results = bench::run(&mut i2c, &config, &flag, |result| say!("{}", result))?;
+        match bench::fastest_clean(&results) {
+            Some(hz) => say!("✅ Fastest clean speed: {} Hz", hz),
+            None => say!("❌ No speed got through without errors"),
+        }
+        if self.shutdown.requested() {
+            return Err(Interrupted.into());
+        }
+        Ok(())
+    }
+}
+
+struct StretchJob {
+    config: StretchConfig,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for StretchJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        self.config.validate()?;
+        let what = match self.config.register {
+            Some(register) => format!("register 0x{:02X}", register),
+            None => "plain reads".to_string(),
+        };
+        say!("⏱️  Reading {} x{} byte(s) from {} ({}) to watch for clock stretching", self.config.reads, self.config.len, self.config.address, what);
+        let report = stretch::run(&mut i2c, &self.config, &self.shutdown.flag())?;
+        for line in report.to_string().lines() {
+            say!("   {}", line);
+        }
+        if let Some(e) = &report.last_error {
+            say!("   last error: {}", e);
+        }
+        if self.shutdown.requested() {
+            return Err(Interrupted.into());
+        }
+        if report.completed == 0 && report.nacks == report.reads {
+            return Err(DeviceNotFound { tried: vec![self.config.address] }.into());
+        }
+        if report.honored() && report.longest_hold().is_zero() {
+            say!("✅ Every read came back the same, but the slave never held SCL: nothing to honor");
+            Ok(())
+        } else if report.honored() {
+            say!("✅ The master waited out every stretch (longest {}µs)", report.longest_hold().as_micros());
+            Ok(())
+        } else {
+            Err(VerificationFailed {
+                details: format!(
+                    "clock stretching not honored: {} of {} read(s) failed, {} came back different",
+                    report.reads - report.completed,
+                    report.reads,
+                    report.mismatches
+                ),
+            }
+            .into())
+        }
+    }
+}
+
+struct DumpJob {
+    config: DumpConfig,
+    expected: Option<Vec<u8>>,
+    output: Option<PathBuf>,
+    timeout: Option<Duration>,
+    quiet: bool,
+}
+
+impl BusJob for DumpJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        // on stderr so a redirected hexdump stays clean, which --quiet doesn't silence
+        let mut bar = (!self.quiet).then(|| ProgressBar::new("dump", self.config.len as u64, Unit::Bytes, Stream::Stderr));
+        let bytes = dump::read_with(&mut i2c, &self.config, |done| {
+            if let Some(bar) = &mut bar {
+                bar.set(done as u64);
+            }
+        })?;
+        drop(bar);
+        print!("{}", dump::hexdump(&bytes, self.config.start));
+        if let Some(path) = &self.output {
+            std::fs::write(path, &bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+            say!("💾 Wrote {} bytes to {}", bytes.len(), path.display());
+        }
+        let Some(expected) = &self.expected else {
+            return Ok(());
+        };
+        let differences = dump::compare(&bytes, expected, self.config.start);
+        if differences.is_empty() {
+            say!("✅ Matches the image ({} bytes)", bytes.len());
+            return Ok(());
+        }
+        for difference in differences.iter().take(DUMP_DIFFERENCES) {
+            say!("   ❌ {}", difference);
+        }
+        if differences.len() > DUMP_DIFFERENCES {
+            say!("   ... and {} more", differences.len() - DUMP_DIFFERENCES);
+        }
+        Err(VerificationFailed {
+            details: format!("{} byte(s) differ from the image", differences.len()),
+        }
+        .into())
+    }
+}
+
+struct FlashJob {
+    config: FlashConfig,
+    base: u32,
+    image: Vec<u8>,
+    name: String,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl BusJob for FlashJob {
+    fn run<I2C>(self, mut i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        say!(
+            "⚡ Flashing {} ({} bytes at 0x{:06X}) to {}{}",
+            self.name,
+            self.image.len(),
+            self.base,
+            self.config.address,
+            if self.config.resume { ", resuming" } else { "" }
+        );
+        let flag = self.shutdown.flag();
+        let mut bar: Option<ProgressBar> = None;
+        let result = flash::flash(&mut i2c, &self.config, self.base, &self.image, &flag, |info, progress| {
+            let bar = bar.get_or_insert_with(|| {
+                say!("🔌 {}", info);
+                ProgressBar::new("flash", progress.total as u64, Unit::Bytes, Stream::Stdout)
+            });
+            bar.set(progress.done as u64);
+        });
+        drop(bar);
+        let progress = match result {
+            Ok(progress) => progress,
+            Err(e) => {
+                if e.is::<FlashFailed>() {
+                    say!("💡 Run it again with --resume to carry on from there");
+                }
+                return Err(e);
+            }
+        };
+        if progress.skipped > 0 {
+            say!("⏭️  {} bytes were already there", progress.skipped);
+        }
+        if progress.retries > 0 {
+            say!("⚠️  {} chunk write(s) needed another try; check the wiring and pull-ups", progress.retries);
+        }
+        if self.config.boot {
+            say!("✅ Flashed and verified; started the new firmware");
+        } else {
+            say!("✅ Flashed and verified; the bootloader is still running");
+        }
+        Ok(())
+    }
+}
+
+struct MonitorJob {
+    presence: Presence,
+    alerter: Alerter,
+    alert_claims: Vec<Claim>,
+    /// The rails, unless nothing here is on one or it's a dry run, stub or
+    /// remote bus; the watchdog's power-cycle remedy switches them.
+    power: Option<PowerManager<rppal::gpio::OutputPin>>,
+    /// Devices on this bus with an address and a rail. Taken at startup;
+    /// changes to `[[rails]]` need a restart.
+    powered: Vec<DeviceConfig>,
+    _rail_claim: Option<Claim>,
+    policy: watchdog::Policy,
+    interval: Duration,
+    reload: Option<MonitorReload>,
+    metrics_port: Option<u16>,
+    metrics_bind: String,
+    timeout: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+/// What `monitor` needs to rebuild itself from a changed --config.
+struct MonitorReload {
+    watcher: ConfigWatcher,
+    /// What the running devices and alerts were built from.
+    applied: Config,
+    bus: u8,
+    addresses: Vec<Address>,
+    /// --interval, which wins over `monitor.interval`.
+    interval: Option<Duration>,
+    peripherals: Peripherals,
+    dry_run: bool,
+}
+
+impl MonitorJob {
+    /// Pick up a changed config file: the devices to watch, the interval
+    /// and, if `[alerts]` or `[mqtt]` changed, the alert outputs.
+    fn reload(&mut self) {
+        let Some(reload) = &mut self.reload else {
+            return;
+        };
+        if !reload.watcher.poll_logged() {
+            return;
+        }
+        let config = reload.watcher.snapshot();
+        // The watcher only applies a config these were checked to build from
+        if let Ok(watched) = watched_devices(&config, reload.bus, &reload.addresses) {
+            self.presence.reconfigure(watched, config.monitor.recover_after);
+        }
+        self.interval = reload.interval.unwrap_or(config.monitor.interval);
+        self.policy = config.watchdog.clone();
+        if config.alerts != reload.applied.alerts || config.mqtt != reload.applied.mqtt {
+            // The old outputs let go of their pins first, in case the new ones want them
+            self.alert_claims.clear();
+            if let Ok(bare) = Alerter::new(config.alerts.clone()) {
+                self.alerter = bare;
+            }
+            match alerter(&config, &reload.peripherals, reload.dry_run) {
+                Ok((alerter, claims)) => (self.alerter, self.alert_claims) = (alerter, claims),
+                Err(e) => say!("⚠️  New alert outputs failed ({}); alerts go to the log only", e),
+            }
+        }
+        reload.applied = config;
+        say!("👀 Monitoring {} devices every {:.1}s", self.presence.states().count(), self.interval.as_secs_f64());
+    }
+}
+
+impl BusJob for MonitorJob {
+    fn run<I2C>(mut self, i2c: I2C) -> Result<(), Box<dyn Error>>
+    where
+        I2C: I2c + AddressedI2c + BusControl + Send + 'static,
+        I2C::Error: Error + 'static,
+    {
+        let i2c = MeteredBus::new(i2c, Metrics::new());
+        let metrics = i2c.metrics();
+        // Shared, so a power-cycle remedy can re-init devices between rounds
+        let manager = BusManager::new(i2c);
+        let mut i2c = manager.shared();
+        if let Some(timeout) = self.timeout {
+            BusControl::set_timeout(&mut i2c, timeout)?;
+        }
+        self.presence.set_metrics(metrics.clone());
+        if let Some(port) = self.metrics_port {
+            let listen = format!("{}:{}", self.metrics_bind, port);
+            let listener = TcpListener::bind(&listen).map_err(|e| format!("cannot listen on {}: {}", listen, e))?;
+            server::spawn_metrics(listener, metrics.clone(), self.shutdown.flag())?;
+            say!("📈 Metrics on http://{}/metrics", listen);
+        }
+        let mut watchdog = Watchdog::new(self.policy.clone())?;
+        watchdog.set_metrics(metrics);
+        if let Some(mut power) = self.power.take() {
+            for device in &self.powered {
+                let (mut bus, device) = (i2c.clone(), device.clone());
+                let address = Address::from_raw(device.address.ok_or("powered device has no address")?)?;
+                power.on_reinit(&device.name.clone(), move || {
+                    power::wait_for_ack(&mut bus, address, POWER_CYCLE_TIMEOUT, Duration::from_millis(5))?;
+                    plan::init_device(&mut bus, &device)?;
+                    Ok(())
+                });
+            }
+            watchdog.set_remedy(Stage::PowerCycle, move |device| {
+                let rail = power.rail_of(device).ok_or_else(|| format!("device '{}' is not on a rail", device))?.to_string();
+                say!("🔌 Power-cycling rail '{}' for device '{}'", rail, device);
+                power.cycle_device(device)?;
+                Ok(())
+            });
+        } else if !self.powered.is_empty() {
+            say!("🧪 Not power-cycling failing devices: the rails aren't switched on a dry run, stub or remote bus");
+        }
+        if let Some(watchdog) = systemd::watchdog_interval() {
+            // The ping rides on the probe loop, so it has to come round in time
+            if self.interval > watchdog / 2 {
+                say!(
+                    "⚠️  --interval {:.1}s is over half of WatchdogSec={:.1}s; systemd may restart the monitor",
+                    self.interval.as_secs_f64(),
+                    watchdog.as_secs_f64()
+                );
+            }
+        }
+
+        let round = self.presence.poll(&mut i2c);
+        let mut missing = Vec::new();
+        for (watched, present) in self.presence.states() {
+            match present {
+                Some(true) => say!("✅ {} at {}", watched.name, watched.address),
+                _ => {
+                    say!("❌ {} at {} not responding", watched.name, watched.address);
+                    missing.push(watched.clone());
+                }
+            }
+        }
+        for watched in &missing {
+            raise(&mut self.alerter, &missing_alert(watched));
+        }
+        say!(
+            "👀 Monitoring {} devices every {:.1}s",
+            round.total,
+            self.interval.as_secs_f64()
+        );
+        report_systemd(systemd::ready());
+        report_systemd(systemd::status(&format!("{}/{} devices present", round.present, round.total)));
+
+        while self.shutdown.sleep(self.interval) {
+            self.reload();
+            if *watchdog.policy() != self.policy {
+                if let Err(e) = watchdog.set_policy(self.policy.clone()) {
+                    say!("⚠️  Keeping the old [watchdog] budget: {}", e);
+                }
+            }
+            let round = self.presence.poll(&mut i2c);
+            // The powered devices' answers feed the error budget
+            let answers: Vec<(String, bool)> = self
+                .presence
+                .states()
+                .filter(|(watched, _)| self.powered.iter().any(|d| d.name == watched.name))
+                .map(|(watched, present)| (watched.name.clone(), present == Some(true)))
+                .collect();
+            for (device, ok) in answers {
+                watchdog.record(&device, ok);
+            }
+            for event in &round.events {
+                say!("🔔 {}", event);
+                match event {
+                    PresenceEvent::Disappeared(watched) => raise(&mut self.alerter, &missing_alert(watched)),
+                    PresenceEvent::Appeared(watched) => {
+                        if let Err(e) = self.alerter.clear(&format!("presence.{}", watched.name)) {
+                            say!("⚠️  Alert output failed: {}", e);
+                        }
+                    }
+                }
+            }
+            match &round.recovery {
+                Some(Ok(())) => say!("🔧 Bus looked stuck; ran the recovery sequence"),
+                Some(Err(e)) => {
+                    say!("⚠️  Bus recovery failed: {}", e);
+                    let alert = Alert::new("bus.recovery", Severity::Critical, format!("bus recovery failed: {}", e));
+                    raise(&mut self.alerter, &alert);
+                }
+                None => {}
+            }
+            if !round.events.is_empty() || round.recovery.is_some() {
+                report_systemd(systemd::status(&format!("{}/{} devices present", round.present, round.total)));
+            }
+            report_systemd(systemd::watchdog());
+        }
+        report_systemd(systemd::stopping());
+        say!("👋 Stopped monitoring");
+        Ok(())
+    }
+}
+
+fn missing_alert(watched: &Watched) -> Alert {
+    Alert::new(
+        format!("presence.{}", watched.name),
+        Severity::Warning,
+        format!("{} at {} not responding", watched.name, watched.address),
+    )
+}
+
+/// A failed output is logged; the others still went out.
+fn raise(alerter: &mut Alerter, alert: &Alert) {
+    match alerter.raise(alert) {
+        Ok(outputs) if !outputs.is_empty() => {
+            let names: Vec<String> = outputs.iter().map(ToString::to_string).collect();
+            say!("📣 {} → {}", alert.severity, names.join(", "));
+        }
+        Ok(_) => {}
+        Err(e) => say!("⚠️  Alert output failed: {}", e),
+    }
+}
+
+/// [`Outputs`] for `[[rules]]`: the `[alerts.buzzer]`, the pins they
+/// drive and the `[mqtt]` broker, each only if some rule uses it. The LCD
+/// backlight is added once the bus is open. A dry run attaches none.
+fn rule_outputs(config: &Config, peripherals: &Peripherals, dry_run: bool) -> Result<(Outputs, Vec<Claim>), Box<dyn Error>> {
+    let mut outputs = Outputs::new();
+    let mut claims = Vec::new();
+    if dry_run {
+        return Ok((outputs, claims));
+    }
+    let uses = |wanted: fn(&rules::Action) -> bool| config.rules.iter().flat_map(|rule| &rule.actions).any(wanted);
+    if let Some(buzzer) = config.alerts.buzzer.as_ref().filter(|_| uses(|a| matches!(a, rules::Action::Buzz(_)))) {
+        claims.push(peripherals.claim(Resource::Pin(buzzer.pin), "the alert buzzer")?);
+        outputs.set_buzzer(Box::new(GpioBuzzer::from_gpio(buzzer)?));
+    }
+    for pin in rules::pins(&config.rules) {
+        claims.push(peripherals.claim(Resource::Pin(pin), "a rule")?);
+        let output = rppal::gpio::Gpio::new()?.get(pin).map_err(|e| format!("rule GPIO {}: {}", pin, e))?;
+        outputs.set_pin(pin, output.into_output_low());
+    }
+    if let Some(mqtt) = config.mqtt.clone().filter(|_| uses(|a| *a == rules::Action::Mqtt)) {
+        outputs.set_publisher(Publisher::new(mqtt)?);
+    }
+    Ok((outputs, claims))
+}
+
+/// The configured [[devices]] on bus `bus` with an address, then any of
+/// `addresses` not among them.
+fn watched_devices(config: &Config, bus: u8, addresses: &[Address]) -> Result<Vec<Watched>, Box<dyn Error>> {
+    let mut watched = Vec::new();
+    for device in config.devices.iter().filter(|d| d.bus == bus) {
+        if let Some(raw) = device.address {
+            watched.push(Watched {
+                name: device.name.clone(),
+                address: Address::from_raw(raw)?,
+            });
+        }
+    }
+    for &address in addresses {
+        if !watched.iter().any(|w| w.address == address) {
+            watched.push(Watched {
+                name: address.to_string(),
+                address,
+            });
+        }
+    }
+    if watched.is_empty() {
+        return Err("nothing to monitor: pass addresses or --config with [[devices]] on this bus".into());
+    }
+    Ok(watched)
+}
+
+/// The lock, even if a thread panicked holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An [`Alerter`] for `[alerts]`, with the buzzer and LED it configures and
+/// the `[mqtt]` broker if any severity publishes. A dry run attaches none.
+fn alerter(config: &Config, peripherals: &Peripherals, dry_run: bool) -> Result<(Alerter, Vec<Claim>), Box<dyn Error>> {
+    let alerts = &config.alerts;
+    let mut alerter = Alerter::new(alerts.clone())?;
+    let mut claims = Vec::new();
+    if dry_run {
+        return Ok((alerter, claims));
+    }
+    if let Some(buzzer) = &alerts.buzzer {
+        claims.push(peripherals.claim(Resource::Pin(buzzer.pin), "the alert buzzer")?);
+        alerter.set_buzzer(Box::new(GpioBuzzer::from_gpio(buzzer)?));
+    }
+    if let Some(led) = &alerts.led {
+        claims.push(peripherals.claim(Resource::Spi { bus: led.spi, cs: 0 }, "the alert LED")?);
+        let mut strip = Ws2812::from_spi(led.spi, led.count)?;
+        strip.set_brightness(led.brightness);
+        alerter.set_led(Box::new(strip));
+    }
+    if let Some(mqtt) = config.mqtt.clone().filter(|_| alerts.uses_mqtt()) {
+        alerter.set_publisher(Publisher::new(mqtt)?);
+    }
+    Ok((alerter, claims))
+}
+
+/// Losing the notify socket shouldn't take the monitor down with it.
+fn report_systemd(result: io::Result<bool>) {
+    if let Err(e) = result {
+        say!("⚠️  sd_notify failed: {}", e);
+    }
+}
+
+fn export_trace(trace: &Path, output: &Path, format: Option<ExportFormat>, sample_rate: u32) -> Result<(), Box<dyn Error>> {
+    let format = format
+        .or_else(|| ExportFormat::from_path(output))
+        .ok_or("can't tell the format from the output name; pass --format vcd or --format csv")?;
+    let trace = Trace::load(trace)?;
+    let mut file = std::io::BufWriter::new(std::fs::File::create(output)?);
+    export::write(&trace, format, sample_rate, &mut file)?;
+    std::io::Write::flush(&mut file)?;
+    say!("💾 Wrote {} transactions to {}", trace.transactions.len(), output.display());
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,162 @@
+//! Frequency and duty cycle of a signal on a GPIO, from edge timestamps.
+//!
+//! [`measure`] collects every edge on a pin for a gate time. The kernel
+//! timestamps each edge as its interrupt fires, so pulse widths come out
+//! to a few microseconds however late the thread reading them runs, and a
+//! [`Measurement`] reports the frequency, duty cycle and pulse widths
+//! over the whole periods in the gate:
+//!
+//! ```no_run
+//! use rpi_peripherals::measure;
+//! use std::time::Duration;
+//!
+//! let m = measure::measure(18, Duration::from_secs(1))?;
+//! if let Some(hz) = m.frequency() {
+//!     println!("{:.1} Hz at {:.1}% duty", hz, m.duty().unwrap_or(0.0) * 100.0);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Interrupts keep up to a few tens of kHz; beyond that the kernel drops
+//! edges, which the measurement counts as `missed` rather than folding
+//! into the figures.
+
+use rppal::gpio::{Gpio, Trigger};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// One edge: the level it went to and when.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub high: bool,
+    pub at: Duration,
+}
+
+/// Pulse statistics over the edges of one gate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub gate: Duration,
+    pub edges: usize,
+    /// Edges the kernel dropped, from gaps in its sequence numbers.
+    pub missed: u32,
+    /// The level with no edges at all.
+    pub level: Option<bool>,
+    periods: Vec<Duration>,
+    highs: Vec<Duration>,
+}
+
+impl Measurement {
+    /// Whole periods are rising edge to rising edge; any pattern of two
+    /// edges the same way (a dropped edge) splits the run there.
+    pub fn from_edges(edges: &[Edge], gate: Duration, missed: u32, level: Option<bool>) -> Self {
+        let mut periods = Vec::new();
+        let mut highs = Vec::new();
+        for window in edges.windows(3) {
+            let [rise, fall, next] = window else { continue };
+            if rise.high && !fall.high && next.high {
+                periods.push(next.at.saturating_sub(rise.at));
+                highs.push(fall.at.saturating_sub(rise.at));
+            }
+        }
+        Measurement {
+            gate,
+            edges: edges.len(),
+            missed,
+            level,
+            periods,
+            highs,
+        }
+    }
+
+    /// Whole periods seen.
+    pub fn cycles(&self) -> usize {
+        self.periods.len()
+    }
+
+    /// Mean period.
+    pub fn period(&self) -> Option<Duration> {
+        mean(&self.periods)
+    }
+
+    /// Hz; `None` with fewer than one whole period in the gate.
+    pub fn frequency(&self) -> Option<f64> {
+        self.period().filter(|p| !p.is_zero()).map(|p| 1.0 / p.as_secs_f64())
+    }
+
+    /// 0.0 to 1.0, high time over the whole periods.
+    pub fn duty(&self) -> Option<f64> {
+        let total: Duration = self.periods.iter().sum();
+        if total.is_zero() {
+            return None;
+        }
+        Some(self.highs.iter().sum::<Duration>().as_secs_f64() / total.as_secs_f64())
+    }
+
+    /// Mean, shortest and longest high pulse.
+    pub fn pulse_width(&self) -> Option<(Duration, Duration, Duration)> {
+        Some((mean(&self.highs)?, *self.highs.iter().min()?, *self.highs.iter().max()?))
+    }
+}
+
+fn mean(values: &[Duration]) -> Option<Duration> {
+    let count = u32::try_from(values.len()).ok().filter(|&n| n > 0)?;
+    Some(values.iter().sum::<Duration>() / count)
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (Some(hz), Some(duty), Some((width, min, max))) = (self.frequency(), self.duty(), self.pulse_width()) else {
+            return match self.level {
+                Some(high) => write!(f, "no whole period in {:?}: {} edges, line {}", self.gate, self.edges, if high { "high" } else { "low" }),
+                None => write!(f, "no whole period in {:?}: {} edges", self.gate, self.edges),
+            };
+        };
+        write!(
+            f,
+            "{:.2} Hz, duty {:.1}%, high {:.1}µs (min {:.1}, max {:.1}), {} periods",
+            hz,
+            duty * 100.0,
+            width.as_secs_f64() * 1e6,
+            min.as_secs_f64() * 1e6,
+            max.as_secs_f64() * 1e6,
+            self.cycles()
+        )?;
+        if self.missed > 0 {
+            write!(f, ", {} edges missed", self.missed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collect edges on BCM pin `pin`, with no pull, for `gate`.
+pub fn measure(pin: u8, gate: Duration) -> Result<Measurement, Box<dyn Error>> {
+    let mut input = Gpio::new()?
+        .get(pin)
+        .map_err(|e| format!("measure GPIO {}: {}", pin, e))?
+        .into_input();
+    // Edges and the last sequence number seen
+    let seen = Arc::new(Mutex::new((Vec::new(), None::<u32>, 0u32)));
+    let sink = Arc::clone(&seen);
+    input
+        .set_async_interrupt(Trigger::Both, None, move |event| {
+            let mut seen = sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(last) = seen.1 {
+                seen.2 += event.seqno.wrapping_sub(last).saturating_sub(1);
+            }
+            seen.1 = Some(event.seqno);
+            seen.0.push(Edge {
+                high: event.trigger == Trigger::RisingEdge,
+                at: event.timestamp,
+            });
+        })
+        .map_err(|e| format!("measure GPIO {}: {}", pin, e))?;
+    thread::sleep(gate);
+    input.clear_async_interrupt()?;
+    let level = input.is_high();
+    let seen = seen.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let (edges, _, missed) = &*seen;
+    Ok(Measurement::from_edges(edges, gate, *missed, edges.is_empty().then_some(level)))
+}
@@ -0,0 +1,369 @@
+//! Tunes for a passive buzzer sounded through [`SoftPwm`].
+//!
+//! Two ways to write one down:
+//!
+//! * RTTTL, the old Nokia ringtone format: a name, defaults for duration,
+//!   octave and tempo, then the notes, e.g.
+//!   `Scale:d=8,o=5,b=120:c,d,e,f,g,a,b,4c6.,p`. Each note is
+//!   `[duration]pitch[#][.][octave][.]`, with `p` for a rest.
+//! * A plain list of `pitch:duration`, split by spaces, commas or lines,
+//!   e.g. `C4:4 E4:8 G4:8 Bb4:4. R:2 C5:500ms`. Durations are note values
+//!   (4 a quarter, 8. a dotted eighth) at 120 beats a minute, or a time;
+//!   a `bpm=90` token changes the tempo for what follows. Without a
+//!   duration a note is a quarter.
+//!
+//! ```no_run
+//! use rpi_peripherals::melody::{self, Melody};
+//! use rpi_peripherals::pwm::SoftPwm;
+//! use std::sync::atomic::AtomicBool;
+//!
+//! let tune: Melody = melody::HAPPY_BIRTHDAY.parse()?;
+//! let mut buzzer = SoftPwm::from_gpio(18, 440.0, 0.0)?;
+//! melody::play(&mut buzzer, &tune, &AtomicBool::new(false))?;
+//! buzzer.stop()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::parse;
+use crate::pwm::SoftPwm;
+use embedded_hal::digital::OutputPin;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+pub const HAPPY_BIRTHDAY: &str = "HappyBirthday:d=4,o=5,b=125:8g.,16g,a,g,c6,2b,8g.,16g,a,g,d6,2c6,8g.,16g,g6,e6,c6,b,a,8f6.,16f6,e6,c6,d6,2c6";
+
+/// Songs `load` knows by name.
+pub const BUILT_IN: &[(&str, &str)] = &[("happy-birthday", HAPPY_BIRTHDAY)];
+
+/// Tempo of the plain format until a `bpm=` says otherwise.
+pub const DEFAULT_BPM: u32 = 120;
+
+/// How much of each note sounds; the rest is silence, so repeated notes
+/// come out as separate notes.
+const ARTICULATION: f64 = 0.9;
+
+const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// A note as a MIDI number: 60 is middle C (C4), 69 the A above at 440 Hz.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pitch(pub u8);
+
+impl Pitch {
+    /// `letter` with `shift` semitones of sharps (or flats, negative) in
+    /// scientific octave `octave`.
+    fn new(letter: char, shift: i32, octave: i32) -> Result<Self, Box<dyn Error>> {
+        if !(-1..=9).contains(&octave) {
+            return Err(format!("octave {} out of range (-1 to 9)", octave).into());
+        }
+        let semitone = match letter.to_ascii_lowercase() {
+            'c' => 0,
+            'd' => 2,
+            'e' => 4,
+            'f' => 5,
+            'g' => 7,
+            'a' => 9,
+            'b' => 11,
+            _ => return Err(format!("unknown note '{}'", letter).into()),
+        };
+        let midi = 12 * (octave + 1) + semitone + shift;
+        u8::try_from(midi)
+            .ok()
+            .filter(|&midi| midi <= 127)
+            .map(Pitch)
+            .ok_or_else(|| format!("note {} in octave {} is out of range", letter, octave).into())
+    }
+
+    pub fn frequency(&self) -> f64 {
+        440.0 * 2f64.powf((f64::from(self.0) - 69.0) / 12.0)
+    }
+}
+
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = format!("{}{}", NAMES[usize::from(self.0 % 12)], i32::from(self.0 / 12) - 1);
+        f.pad(&name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    /// `None` for a rest.
+    pub pitch: Option<Pitch>,
+    pub duration: Duration,
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.pitch {
+            Some(pitch) => write!(f, "{}:{}ms", pitch, self.duration.as_millis()),
+            None => write!(f, "R:{}ms", self.duration.as_millis()),
+        }
+    }
+}
+
+/// Slowest and fastest [`Melody::faster`] plays: a hundred times either way.
+pub const SPEED_RANGE: RangeInclusive<f64> = 0.01..=100.0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Melody {
+    pub name: String,
+    pub notes: Vec<Note>,
+}
+
+impl Melody {
+    /// Read as RTTTL, then as the plain format.
+    pub fn parse(name: &str, text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut melody = if is_rtttl(text) { parse_rtttl(text)? } else { parse_notes(text)? };
+        if melody.name.is_empty() {
+            melody.name = name.to_string();
+        }
+        Ok(melody)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.notes.iter().map(|note| note.duration).sum()
+    }
+
+    /// Everything `speed` times as fast, for a `speed` in [`SPEED_RANGE`].
+    pub fn faster(mut self, speed: f64) -> Result<Self, Box<dyn Error>> {
+        if !SPEED_RANGE.contains(&speed) {
+            return Err(format!("speed must be from {} to {}, got {}", SPEED_RANGE.start(), SPEED_RANGE.end(), speed).into());
+        }
+        for note in &mut self.notes {
+            note.duration = Duration::try_from_secs_f64(note.duration.as_secs_f64() / speed)
+                .map_err(|_| format!("{} slowed {}x is too long", note, speed.recip()))?;
+        }
+        Ok(self)
+    }
+}
+
+impl FromStr for Melody {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Melody::parse("", s)
+    }
+}
+
+/// A built-in song by name, or else a file of RTTTL or notes.
+pub fn load(song: &str) -> Result<Melody, Box<dyn Error>> {
+    if let Some((name, text)) = BUILT_IN.iter().find(|(name, _)| *name == song) {
+        return Melody::parse(name, text);
+    }
+    let text = fs::read_to_string(song).map_err(|e| {
+        let names: Vec<_> = BUILT_IN.iter().map(|(name, _)| *name).collect();
+        format!("{}: {} (built-in songs: {})", song, e, names.join(", "))
+    })?;
+    let stem = std::path::Path::new(song).file_stem().and_then(|s| s.to_str()).unwrap_or(song);
+    Melody::parse(stem, &text).map_err(|e| format!("{}: {}", song, e).into())
+}
+
+/// `name:defaults:notes`, where the defaults are empty or `key=value`s.
+fn is_rtttl(text: &str) -> bool {
+    let sections: Vec<_> = text.trim().splitn(3, ':').collect();
+    sections.len() == 3 && (sections[1].trim().is_empty() || sections[1].contains('='))
+}
+
+/// A whole note at `bpm` quarter-note beats a minute.
+fn whole_note(bpm: u32) -> Result<Duration, Box<dyn Error>> {
+    if !(1..=900).contains(&bpm) {
+        return Err(format!("tempo {} bpm out of range (1-900)", bpm).into());
+    }
+    Ok(Duration::from_secs_f64(240.0 / f64::from(bpm)))
+}
+
+/// A note value like `4` or `8.` as a share of `whole`.
+fn note_value(text: &str, whole: Duration) -> Result<Duration, Box<dyn Error>> {
+    let (value, dotted) = match text.strip_suffix('.') {
+        Some(value) => (value, true),
+        None => (text, false),
+    };
+    let value: u32 = value.parse().map_err(|_| format!("bad duration '{}'", text))?;
+    if ![1, 2, 4, 8, 16, 32, 64].contains(&value) {
+        return Err(format!("duration {} isn't a note value (1, 2, 4 ... 64)", value).into());
+    }
+    let duration = whole / value;
+    Ok(if dotted { duration.mul_f64(1.5) } else { duration })
+}
+
+pub fn parse_rtttl(text: &str) -> Result<Melody, Box<dyn Error>> {
+    let mut sections = text.trim().splitn(3, ':');
+    let (Some(name), Some(defaults), Some(notes)) = (sections.next(), sections.next(), sections.next()) else {
+        return Err("RTTTL needs name:defaults:notes".into());
+    };
+    let (mut duration, mut octave, mut bpm) = (4, 6, 63u32);
+    for setting in defaults.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (key, value) = setting.split_once('=').ok_or_else(|| format!("bad RTTTL default '{}'", setting))?;
+        let value: u32 = value.trim().parse().map_err(|_| format!("bad RTTTL default '{}'", setting))?;
+        match key.trim().to_ascii_lowercase().as_str() {
+            "d" => duration = value,
+            "o" => octave = i32::try_from(value)?,
+            "b" => bpm = value,
+            _ => return Err(format!("unknown RTTTL default '{}' (d, o or b)", key.trim()).into()),
+        }
+    }
+    let whole = whole_note(bpm)?;
+    let default_duration = note_value(&duration.to_string(), whole)?;
+    let notes = notes
+        .split(',')
+        .map(str::trim)
+        .filter(|note| !note.is_empty())
+        .map(|note| rtttl_note(note, whole, default_duration, octave).map_err(|e| format!("'{}': {}", note, e).into()))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    if notes.is_empty() {
+        return Err("RTTTL has no notes".into());
+    }
+    Ok(Melody {
+        name: name.trim().to_string(),
+        notes,
+    })
+}
+
+/// `[duration]pitch[#][.][octave][.]`
+fn rtttl_note(note: &str, whole: Duration, default_duration: Duration, default_octave: i32) -> Result<Note, Box<dyn Error>> {
+    let digits = note.find(|c: char| !c.is_ascii_digit()).ok_or("no pitch")?;
+    let mut duration = match &note[..digits] {
+        "" => default_duration,
+        value => note_value(value, whole)?,
+    };
+    let mut chars = note[digits..].chars().peekable();
+    let letter = chars.next().ok_or("no pitch")?;
+    let sharp = chars.next_if_eq(&'#').is_some();
+    let mut dotted = chars.next_if_eq(&'.').is_some();
+    let octave: String = std::iter::from_fn(|| chars.next_if(char::is_ascii_digit)).collect();
+    dotted |= chars.next_if_eq(&'.').is_some();
+    if let Some(extra) = chars.next() {
+        return Err(format!("unexpected '{}'", extra).into());
+    }
+    if dotted {
+        duration = duration.mul_f64(1.5);
+    }
+    let pitch = if letter.eq_ignore_ascii_case(&'p') {
+        None
+    } else {
+        let octave = match octave.as_str() {
+            "" => default_octave,
+            octave => octave.parse()?,
+        };
+        Some(Pitch::new(letter, i32::from(sharp), octave)?)
+    };
+    Ok(Note { pitch, duration })
+}
+
+pub fn parse_notes(text: &str) -> Result<Melody, Box<dyn Error>> {
+    let mut whole = whole_note(DEFAULT_BPM)?;
+    let mut notes = Vec::new();
+    for token in text.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+        if let Some(bpm) = token.strip_prefix("bpm=") {
+            whole = whole_note(bpm.parse().map_err(|_| format!("bad tempo '{}'", token))?)?;
+            continue;
+        }
+        notes.push(plain_note(token, whole).map_err(|e| format!("'{}': {}", token, e))?);
+    }
+    if notes.is_empty() {
+        return Err("no notes".into());
+    }
+    Ok(Melody {
+        name: String::new(),
+        notes,
+    })
+}
+
+/// `C4`, `F#5:8`, `Bb3:4.`, `R:250ms`
+fn plain_note(token: &str, whole: Duration) -> Result<Note, Box<dyn Error>> {
+    let (pitch, duration) = token.split_once(':').unwrap_or((token, "4"));
+    let duration = if duration.ends_with(|c: char| c.is_ascii_alphabetic()) {
+        parse::duration(duration)?
+    } else {
+        note_value(duration, whole)?
+    };
+    let mut chars = pitch.chars();
+    let letter = chars.next().ok_or("no pitch")?;
+    if matches!(letter, 'R' | 'r' | 'P' | 'p') && chars.as_str().is_empty() {
+        return Ok(Note { pitch: None, duration });
+    }
+    let rest = chars.as_str();
+    let octave_at = rest.find(|c: char| c.is_ascii_digit() || c == '-').ok_or("no octave")?;
+    let shift = rest[..octave_at].chars().try_fold(0, |shift, c| match c {
+        '#' => Ok(shift + 1),
+        'b' => Ok(shift - 1),
+        _ => Err(format!("unexpected '{}'", c)),
+    })?;
+    let octave: i32 = rest[octave_at..].parse().map_err(|_| format!("bad octave '{}'", &rest[octave_at..]))?;
+    Ok(Note {
+        pitch: Some(Pitch::new(letter, shift, octave)?),
+        duration,
+    })
+}
+
+/// Play `melody` on `buzzer`, returning early once `stop` is set. The
+/// buzzer is left silent either way. Start the PWM at duty 0.
+pub fn play<P>(buzzer: &mut SoftPwm<P>, melody: &Melody, stop: &AtomicBool) -> Result<(), Box<dyn Error>>
+where
+    P: OutputPin + Send + 'static,
+    P::Error: fmt::Debug,
+{
+    let result = (|| {
+        for note in &melody.notes {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let sounding = match note.pitch {
+                Some(pitch) => {
+                    buzzer.set_frequency(pitch.frequency())?;
+                    buzzer.set_duty(0.5)?;
+                    note.duration.mul_f64(ARTICULATION)
+                }
+                None => Duration::ZERO,
+            };
+            thread::sleep(sounding);
+            buzzer.set_duty(0.0)?;
+            thread::sleep(note.duration - sounding);
+        }
+        Ok(())
+    })();
+    buzzer.set_duty(0.0)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_rtttl() {
+        let melody: Melody = "Test:d=8,o=5,b=120:c,4d#6.,p,16b4,2a".parse().unwrap();
+        assert_eq!(melody.name, "Test");
+        let shown: Vec<_> = melody.notes.iter().map(|note| note.to_string()).collect();
+        assert_eq!(shown, ["C5:250ms", "D#6:750ms", "R:250ms", "B4:125ms", "A5:1000ms"]);
+        assert_eq!(melody.notes[4].pitch.unwrap().frequency(), 880.0);
+        assert_eq!(melody.duration(), Duration::from_millis(2375));
+        assert!("Bad:d=3,o=5,b=120:c".parse::<Melody>().is_err());
+        assert!("Bad::h".parse::<Melody>().is_err());
+        assert!("Bad:x=1:c".parse::<Melody>().is_err());
+        load("happy-birthday").unwrap();
+    }
+
+    #[test]
+    fn reads_note_lists() {
+        let melody: Melody = "C4 E4:8\nBb3:4., R:500ms bpm=60 G4:2".parse().unwrap();
+        let shown: Vec<_> = melody.notes.iter().map(|note| note.to_string()).collect();
+        assert_eq!(shown, ["C4:500ms", "E4:250ms", "A#3:750ms", "R:500ms", "G4:2000ms"]);
+        assert_eq!(melody.notes[0].pitch, Some(Pitch(60)));
+        let faster = melody.clone().faster(2.0).unwrap();
+        assert_eq!(faster.notes[0].duration, Duration::from_millis(250));
+        for speed in [1e-300, 1e300, 0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(melody.clone().faster(speed).is_err(), "{}", speed);
+        }
+        assert!(melody.clone().faster(0.01).is_ok());
+        assert!("H4".parse::<Melody>().is_err());
+        assert!("C".parse::<Melody>().is_err());
+        assert!("C4:3".parse::<Melody>().is_err());
+    }
+}
@@ -0,0 +1,852 @@
+//! Menus on a character LCD, driven by a rotary encoder or buttons.
+//!
+//! A [`Menu`] is a tree of [`Item`]s: submenus, actions with a callback,
+//! numbers edited in steps between limits, and choices from a list. The
+//! cursor moves with [`Nav::Up`]/[`Nav::Down`], [`Nav::Select`] enters a
+//! submenu, runs an action or starts editing a value (select again to
+//! accept it), and [`Nav::Back`] cancels an edit or leaves a submenu.
+//! Every submenu ends with a `Back` entry, so one knob with a push switch
+//! is enough to get everywhere. A USB keypad or volume knob does the same
+//! through [`HidControls`] and a [`KeyMap`], and an infrared remote
+//! through [`IrControls`] and an [`IrKeyMap`]. [`GestureControls`] takes
+//! swipes over an APDS-9960 instead, for hands-free use, and
+//! [`KeypadControls`] the keys of a matrix keypad. A [`PinEntry`] uses
+//! the same keypad to ask for a code, shown masked.
+//!
+//! ```no_run
+//! use rpi_peripherals::input::{Button, Rotary};
+//! use rpi_peripherals::lcd::Lcd;
+//! use rpi_peripherals::menu::{Item, KnobControls, Menu};
+//! use std::sync::atomic::AtomicBool;
+//! # fn demo<I2C: rpi_peripherals::address::AddressedI2c>(i2c: I2C) -> Result<(), Box<dyn std::error::Error>> {
+//!
+//! let mut menu = Menu::new(vec![
+//!     Item::submenu("Display", vec![
+//!         Item::number("Brightness", 80.0, 0.0, 100.0, 10.0, |v| Ok(println!("brightness {}", v))),
+//!         Item::choice("Units", &["°C", "°F"], 0, |i| Ok(println!("units {}", i))),
+//!     ]),
+//!     Item::action("Rescan bus", || Ok(println!("rescanning"))),
+//! ]);
+//! let mut lcd = Lcd::new(i2c, rpi_peripherals::address::Address::SevenBit(0x27), 16, 2)?;
+//! let mut controls = KnobControls::new(Rotary::from_gpio(5, 6)?, Button::from_gpio(13)?);
+//! menu.run(&mut lcd, || controls.poll(), &AtomicBool::new(false))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::input::{codes, Button, ButtonEvent, HidEvent, HidInput, IrEvent, IrReceiver, KeyAction, KeyEvent, KeyState, Rotary};
+use crate::lcd::{Lcd, LcdInterface};
+use crate::say;
+#[cfg(feature = "sensors")]
+use crate::sensors::Gesture;
+use embedded_hal::digital::InputPin;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// How often [`Menu::run`] polls its input.
+pub const POLL: Duration = Duration::from_millis(2);
+
+/// Label of the entry that ends every submenu.
+const BACK: &str = "Back";
+
+type Callback<T> = Box<dyn FnMut(T) -> Result<(), Box<dyn Error>> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nav {
+    Up,
+    Down,
+    Select,
+    Back,
+}
+
+pub enum Item {
+    Submenu {
+        label: String,
+        items: Vec<Item>,
+    },
+    Action {
+        label: String,
+        run: Callback<()>,
+    },
+    Number {
+        label: String,
+        value: f64,
+        min: f64,
+        max: f64,
+        step: f64,
+        on_change: Callback<f64>,
+    },
+    Choice {
+        label: String,
+        options: Vec<String>,
+        selected: usize,
+        on_change: Callback<usize>,
+    },
+}
+
+impl Item {
+    pub fn submenu(label: &str, items: Vec<Item>) -> Self {
+        Item::Submenu {
+            label: label.to_string(),
+            items,
+        }
+    }
+
+    pub fn action(label: &str, mut run: impl FnMut() -> Result<(), Box<dyn Error>> + Send + 'static) -> Self {
+        Item::Action {
+            label: label.to_string(),
+            run: Box::new(move |()| run()),
+        }
+    }
+
+    /// A value from `min` to `max` in `step`s; `on_change` gets it when an
+    /// edit is accepted.
+    pub fn number(
+        label: &str,
+        value: f64,
+        min: f64,
+        max: f64,
+        step: f64,
+        on_change: impl FnMut(f64) -> Result<(), Box<dyn Error>> + Send + 'static,
+    ) -> Self {
+        Item::Number {
+            label: label.to_string(),
+            value: value.clamp(min, max),
+            min,
+            max,
+            step,
+            on_change: Box::new(on_change),
+        }
+    }
+
+    /// One of `options`; `on_change` gets the index when an edit is accepted.
+    pub fn choice(
+        label: &str,
+        options: &[&str],
+        selected: usize,
+        on_change: impl FnMut(usize) -> Result<(), Box<dyn Error>> + Send + 'static,
+    ) -> Self {
+        Item::Choice {
+            label: label.to_string(),
+            options: options.iter().map(|o| o.to_string()).collect(),
+            selected: selected.min(options.len().saturating_sub(1)),
+            on_change: Box::new(on_change),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            Item::Submenu { label, .. }
+            | Item::Action { label, .. }
+            | Item::Number { label, .. }
+            | Item::Choice { label, .. } => label,
+        }
+    }
+
+    /// The value as shown next to the label, for numbers and choices.
+    fn value_text(&self) -> Option<String> {
+        match self {
+            Item::Number { value, step, .. } => Some(format_number(*value, *step)),
+            Item::Choice { options, selected, .. } => options.get(*selected).cloned(),
+            _ => None,
+        }
+    }
+}
+
+/// Decimals enough to show `step`: 0.5 steps get one, whole steps none.
+fn format_number(value: f64, step: f64) -> String {
+    let mut decimals = 0;
+    while decimals < 4 && (step * 10f64.powi(decimals)).fract().abs() > 1e-9 {
+        decimals += 1;
+    }
+    format!("{:.*}", decimals as usize, value)
+}
+
+/// Where the cursor is in one level of the tree.
+#[derive(Debug, Clone, Copy, Default)]
+struct Level {
+    /// Index into the level's items; one past the end is `Back`.
+    cursor: usize,
+    /// First item on the top row.
+    scroll: usize,
+}
+
+/// A value being edited: the old one comes back on [`Nav::Back`].
+#[derive(Debug, Clone, Copy)]
+enum Edit {
+    Number(f64),
+    Choice(usize),
+}
+
+pub struct Menu {
+    items: Vec<Item>,
+    /// Submenu indices from the root down to the open level.
+    path: Vec<usize>,
+    levels: Vec<Level>,
+    editing: Option<Edit>,
+}
+
+impl Menu {
+    pub fn new(items: Vec<Item>) -> Self {
+        Menu {
+            items,
+            path: Vec::new(),
+            levels: vec![Level::default()],
+            editing: None,
+        }
+    }
+
+    /// How deep the open submenu is; 0 at the top.
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    /// Apply one input. Callback errors are returned, with the menu left
+    /// where it was.
+    pub fn handle(&mut self, nav: Nav) -> Result<(), Box<dyn Error>> {
+        if let Some(edit) = self.editing {
+            return self.handle_edit(nav, edit);
+        }
+        let at_root = self.path.is_empty();
+        let count = self.current().len() + usize::from(!at_root);
+        let level = self.levels.last_mut().expect("the root level is never popped");
+        match nav {
+            Nav::Up => level.cursor = level.cursor.saturating_sub(1),
+            Nav::Down => level.cursor = (level.cursor + 1).min(count.saturating_sub(1)),
+            Nav::Back => self.leave(),
+            Nav::Select => {
+                let cursor = level.cursor;
+                // Past the items is the `Back` entry
+                let Some(item) = items_at(&mut self.items, &self.path).get_mut(cursor) else {
+                    self.leave();
+                    return Ok(());
+                };
+                match item {
+                    Item::Submenu { .. } => {
+                        self.path.push(cursor);
+                        self.levels.push(Level::default());
+                    }
+                    Item::Action { run, .. } => run(())?,
+                    Item::Number { value, .. } => self.editing = Some(Edit::Number(*value)),
+                    Item::Choice { selected, .. } => self.editing = Some(Edit::Choice(*selected)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_edit(&mut self, nav: Nav, edit: Edit) -> Result<(), Box<dyn Error>> {
+        let cursor = self.levels.last().map_or(0, |l| l.cursor);
+        let Some(item) = items_at(&mut self.items, &self.path).get_mut(cursor) else {
+            self.editing = None;
+            return Ok(());
+        };
+        match (item, nav) {
+            (Item::Number { value, min, max, step, .. }, Nav::Up | Nav::Down) => {
+                let delta = if nav == Nav::Up { *step } else { -*step };
+                *value = (*value + delta).clamp(*min, *max);
+            }
+            (Item::Choice { selected, .. }, Nav::Up) => *selected = selected.saturating_sub(1),
+            (Item::Choice { options, selected, .. }, Nav::Down) => {
+                *selected = (*selected + 1).min(options.len().saturating_sub(1))
+            }
+            (Item::Number { value, on_change, .. }, Nav::Select) => {
+                self.editing = None;
+                on_change(*value)?;
+            }
+            (Item::Choice { selected, on_change, .. }, Nav::Select) => {
+                self.editing = None;
+                on_change(*selected)?;
+            }
+            (item, Nav::Back) => {
+                match (item, edit) {
+                    (Item::Number { value, .. }, Edit::Number(old)) => *value = old,
+                    (Item::Choice { selected, .. }, Edit::Choice(old)) => *selected = old,
+                    _ => {}
+                }
+                self.editing = None;
+            }
+            _ => self.editing = None,
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        if self.path.pop().is_some() {
+            self.levels.pop();
+        }
+    }
+
+    fn current(&self) -> &[Item] {
+        let mut items = &self.items;
+        for &index in &self.path {
+            match &items[index] {
+                Item::Submenu { items: children, .. } => items = children,
+                _ => unreachable!("the path only goes through submenus"),
+            }
+        }
+        items
+    }
+
+    /// The screen as `rows` lines of `cols` characters. The cursor line
+    /// starts with `>`; submenus end in `→`; a value being edited is shown
+    /// in brackets.
+    pub fn render(&mut self, cols: usize, rows: usize) -> Vec<String> {
+        let at_root = self.path.is_empty();
+        let count = self.current().len() + usize::from(!at_root);
+        let rows = rows.max(1);
+        let level = self.levels.last_mut().expect("the root level is never popped");
+        if level.cursor < level.scroll {
+            level.scroll = level.cursor;
+        } else if level.cursor >= level.scroll + rows {
+            level.scroll = level.cursor + 1 - rows;
+        }
+        let Level { cursor, scroll } = *level;
+        let editing = self.editing.is_some();
+        let items = self.current();
+        (scroll..scroll + rows)
+            .map(|i| {
+                if i >= count {
+                    return " ".repeat(cols);
+                }
+                let marker = if i == cursor { '>' } else { ' ' };
+                let (label, value) = match items.get(i) {
+                    Some(item @ Item::Submenu { .. }) => (format!("{}→", item.label()), None),
+                    Some(item) => (item.label().to_string(), item.value_text()),
+                    None => (BACK.to_string(), None),
+                };
+                let value = match value {
+                    Some(value) if editing && i == cursor => format!("[{}]", value),
+                    Some(value) => value,
+                    None => String::new(),
+                };
+                line(marker, &label, &value, cols)
+            })
+            .collect()
+    }
+
+    /// Draw [`render`](Menu::render) over the whole display.
+    pub fn draw<B: LcdInterface>(&mut self, lcd: &mut Lcd<B>) -> Result<(), Box<dyn Error>> {
+        let (cols, rows) = lcd.size();
+        for (row, text) in self.render(cols as usize, rows as usize).iter().enumerate() {
+            lcd.set_cursor(0, row as u8)?;
+            lcd.write_str(text)?;
+        }
+        Ok(())
+    }
+
+    /// Draw, then redraw after every input from `next`, until `stop` is
+    /// set. `next` is polled every [`POLL`] and returns `None` when nothing
+    /// happened.
+    pub fn run<B, F>(&mut self, lcd: &mut Lcd<B>, mut next: F, stop: &AtomicBool) -> Result<(), Box<dyn Error>>
+    where
+        B: LcdInterface,
+        F: FnMut() -> Result<Option<Nav>, Box<dyn Error>>,
+    {
+        self.draw(lcd)?;
+        while !stop.load(Ordering::Relaxed) {
+            if let Some(nav) = next()? {
+                if let Err(e) = self.handle(nav) {
+                    say!("⚠️  Menu: {}", e);
+                }
+                self.draw(lcd)?;
+            }
+            thread::sleep(POLL);
+        }
+        Ok(())
+    }
+}
+
+/// The items of the submenu at `path`, borrowed apart from the rest of the
+/// menu's state.
+fn items_at<'a>(mut items: &'a mut [Item], path: &[usize]) -> &'a mut [Item] {
+    for &index in path {
+        match &mut items[index] {
+            Item::Submenu { items: children, .. } => items = children,
+            _ => unreachable!("the path only goes through submenus"),
+        }
+    }
+    items
+}
+
+/// `marker` and `label` on the left, `value` on the right, cut to `cols`.
+fn line(marker: char, label: &str, value: &str, cols: usize) -> String {
+    let value_len = value.chars().count();
+    let room = cols.saturating_sub(1 + value_len + usize::from(value_len > 0));
+    let label: String = label.chars().take(room).collect();
+    let pad = cols.saturating_sub(1 + label.chars().count() + value_len);
+    let mut text = format!("{}{}{}{}", marker, label, " ".repeat(pad), value);
+    if text.chars().count() > cols {
+        text = text.chars().take(cols).collect();
+    }
+    text
+}
+
+/// One encoder and its push switch: turning moves, a click selects and a
+/// long press goes back.
+pub struct KnobControls<P> {
+    rotary: Rotary<P>,
+    button: Button<P>,
+    /// Detents turned but not yet handed out.
+    pending: i32,
+}
+
+impl<P: InputPin> KnobControls<P>
+where
+    P::Error: Error + 'static,
+{
+    pub fn new(rotary: Rotary<P>, button: Button<P>) -> Self {
+        KnobControls {
+            rotary,
+            button,
+            pending: 0,
+        }
+    }
+
+    /// One input per call; a fast turn comes out one detent at a time.
+    pub fn poll(&mut self) -> Result<Option<Nav>, Box<dyn Error>> {
+        self.pending += self.rotary.poll()?;
+        match self.button.poll()? {
+            Some(ButtonEvent::Click) => return Ok(Some(Nav::Select)),
+            Some(ButtonEvent::LongPress) => return Ok(Some(Nav::Back)),
+            None => {}
+        }
+        Ok(match self.pending {
+            0 => None,
+            n if n > 0 => {
+                self.pending -= 1;
+                Some(Nav::Down)
+            }
+            _ => {
+                self.pending += 1;
+                Some(Nav::Up)
+            }
+        })
+    }
+}
+
+/// Which keys and knobs of a USB device mean which [`Nav`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    keys: HashMap<u16, Nav>,
+    /// Axes whose positive direction moves down, as a clockwise knob does.
+    down_axes: Vec<u16>,
+    /// Axes whose positive direction moves up, as a wheel rolled away does.
+    up_axes: Vec<u16>,
+}
+
+impl Default for KeyMap {
+    /// Arrows, Enter and Esc on a keyboard; on a volume knob turning moves,
+    /// a press (mute or play/pause) selects.
+    fn default() -> Self {
+        let keys = [
+            (codes::KEY_UP, Nav::Up),
+            (codes::KEY_LEFT, Nav::Up),
+            (codes::KEY_VOLUMEDOWN, Nav::Up),
+            (codes::KEY_DOWN, Nav::Down),
+            (codes::KEY_RIGHT, Nav::Down),
+            (codes::KEY_VOLUMEUP, Nav::Down),
+            (codes::KEY_ENTER, Nav::Select),
+            (codes::KEY_KPENTER, Nav::Select),
+            (codes::KEY_SPACE, Nav::Select),
+            (codes::KEY_MUTE, Nav::Select),
+            (codes::KEY_PLAYPAUSE, Nav::Select),
+            (codes::KEY_ESC, Nav::Back),
+            (codes::KEY_BACKSPACE, Nav::Back),
+        ];
+        KeyMap {
+            keys: keys.into_iter().collect(),
+            down_axes: vec![codes::REL_DIAL, codes::REL_HWHEEL],
+            up_axes: vec![codes::REL_WHEEL],
+        }
+    }
+}
+
+impl KeyMap {
+    /// A map with nothing bound, for keypads whose keys send arbitrary codes.
+    pub fn empty() -> Self {
+        KeyMap {
+            keys: HashMap::new(),
+            down_axes: Vec::new(),
+            up_axes: Vec::new(),
+        }
+    }
+
+    /// Make `code` (see `evtest` for what a key sends) mean `nav`.
+    pub fn bind(&mut self, code: u16, nav: Nav) -> &mut Self {
+        self.keys.insert(code, nav);
+        self
+    }
+
+    /// Detents of `event` as moves: positive down, negative up. A key
+    /// counts on press and auto-repeat, not on release.
+    fn moves(&self, event: HidEvent) -> Option<(Nav, i32)> {
+        match event {
+            HidEvent::Key { state: KeyState::Released, .. } => None,
+            HidEvent::Key { code, .. } => self.keys.get(&code).map(|&nav| (nav, 1)),
+            HidEvent::Turn { axis, steps } if self.down_axes.contains(&axis) => Some((Nav::Down, steps)),
+            HidEvent::Turn { axis, steps } if self.up_axes.contains(&axis) => Some((Nav::Down, -steps)),
+            HidEvent::Turn { .. } => None,
+        }
+    }
+}
+
+/// A USB keypad or knob read through evdev, mapped by a [`KeyMap`]. Drop-in
+/// for [`KnobControls`].
+pub struct HidControls {
+    input: HidInput,
+    map: KeyMap,
+    pending: i32,
+}
+
+impl HidControls {
+    pub fn new(input: HidInput, map: KeyMap) -> Self {
+        HidControls { input, map, pending: 0 }
+    }
+
+    /// One input per call, without waiting; a fast turn comes out one
+    /// detent at a time.
+    pub fn poll(&mut self) -> Result<Option<Nav>, Box<dyn Error>> {
+        if self.pending == 0 {
+            while let Some(event) = self.input.next(Duration::ZERO)? {
+                match self.map.moves(event) {
+                    Some((Nav::Down, steps)) => {
+                        self.pending += steps;
+                        break;
+                    }
+                    Some((nav, _)) => return Ok(Some(nav)),
+                    None => {}
+                }
+            }
+        }
+        Ok(match self.pending {
+            0 => None,
+            n if n > 0 => {
+                self.pending -= 1;
+                Some(Nav::Down)
+            }
+            _ => {
+                self.pending += 1;
+                Some(Nav::Up)
+            }
+        })
+    }
+}
+
+/// Which commands of an NEC remote mean which [`Nav`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrKeyMap {
+    commands: HashMap<u8, Nav>,
+    /// Only this remote's keys count, when set.
+    address: Option<u16>,
+}
+
+impl Default for IrKeyMap {
+    /// The 21-key remotes sold with Arduino and Pi kits: |<< and >>| or -
+    /// and + move, play/pause selects, CH- goes back.
+    fn default() -> Self {
+        let commands = [(0x44, Nav::Up), (0x07, Nav::Up), (0x40, Nav::Down), (0x15, Nav::Down), (0x43, Nav::Select), (0x45, Nav::Back)];
+        IrKeyMap {
+            commands: commands.into_iter().collect(),
+            address: None,
+        }
+    }
+}
+
+impl IrKeyMap {
+    pub fn empty() -> Self {
+        IrKeyMap {
+            commands: HashMap::new(),
+            address: None,
+        }
+    }
+
+    /// Make `command` (see the `ir` command for what a key sends) mean `nav`.
+    pub fn bind(&mut self, command: u8, nav: Nav) -> &mut Self {
+        self.commands.insert(command, nav);
+        self
+    }
+
+    /// Ignore remotes with other addresses, such as the TV's in the same room.
+    pub fn only_address(&mut self, address: u16) -> &mut Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// A held key repeats moves; a held select or back counts once.
+    fn nav(&self, event: IrEvent) -> Option<Nav> {
+        if self.address.is_some_and(|address| address != event.address) {
+            return None;
+        }
+        let nav = *self.commands.get(&event.command)?;
+        if event.repeat && !matches!(nav, Nav::Up | Nav::Down) {
+            return None;
+        }
+        Some(nav)
+    }
+}
+
+/// An NEC infrared remote, mapped by an [`IrKeyMap`]. Drop-in for
+/// [`KnobControls`].
+pub struct IrControls {
+    /// Kept so its interrupts keep running.
+    _receiver: Option<IrReceiver>,
+    events: Receiver<IrEvent>,
+    map: IrKeyMap,
+}
+
+impl IrControls {
+    /// A receiver module on BCM pin `pin`.
+    pub fn from_gpio(pin: u8, map: IrKeyMap) -> Result<Self, Box<dyn Error>> {
+        let (receiver, events) = IrReceiver::with_channel(pin)?;
+        Ok(IrControls {
+            _receiver: Some(receiver),
+            events,
+            map,
+        })
+    }
+
+    /// Events from elsewhere, such as an [`IrReceiver`] shared with other code.
+    pub fn new(events: Receiver<IrEvent>, map: IrKeyMap) -> Self {
+        IrControls {
+            _receiver: None,
+            events,
+            map,
+        }
+    }
+
+    /// One input per call, without waiting.
+    pub fn poll(&mut self) -> Result<Option<Nav>, Box<dyn Error>> {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => {
+                    if let Some(nav) = self.map.nav(event) {
+                        return Ok(Some(nav));
+                    }
+                }
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err("IR receiver stopped".into()),
+            }
+        }
+    }
+}
+
+/// Swipes from a [`GestureWatcher`](crate::sensors::GestureWatcher)'s
+/// channel: up and down move, right selects and left goes back. Drop-in
+/// for [`KnobControls`].
+#[cfg(feature = "sensors")]
+pub struct GestureControls {
+    gestures: Receiver<Gesture>,
+}
+
+#[cfg(feature = "sensors")]
+impl GestureControls {
+    pub fn new(gestures: Receiver<Gesture>) -> Self {
+        GestureControls { gestures }
+    }
+
+    /// One input per call, without waiting.
+    pub fn poll(&mut self) -> Result<Option<Nav>, Box<dyn Error>> {
+        match self.gestures.try_recv() {
+            Ok(gesture) => Ok(Some(match gesture {
+                Gesture::Up => Nav::Up,
+                Gesture::Down => Nav::Down,
+                Gesture::Right => Nav::Select,
+                Gesture::Left => Nav::Back,
+            })),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err("gesture sensor stopped".into()),
+        }
+    }
+}
+
+/// Which keys of a matrix keypad mean which [`Nav`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeypadMap {
+    keys: HashMap<char, Nav>,
+}
+
+impl Default for KeypadMap {
+    /// 2 and 8 or A and B move, # selects and * goes back.
+    fn default() -> Self {
+        let keys = [('2', Nav::Up), ('A', Nav::Up), ('8', Nav::Down), ('B', Nav::Down), ('#', Nav::Select), ('*', Nav::Back)];
+        KeypadMap {
+            keys: keys.into_iter().collect(),
+        }
+    }
+}
+
+impl KeypadMap {
+    pub fn empty() -> Self {
+        KeypadMap { keys: HashMap::new() }
+    }
+
+    pub fn bind(&mut self, key: char, nav: Nav) -> &mut Self {
+        self.keys.insert(key, nav);
+        self
+    }
+
+    /// On a press; a held move key moves once more, select and back don't.
+    fn nav(&self, event: KeyEvent) -> Option<Nav> {
+        let nav = *self.keys.get(&event.key)?;
+        match event.action {
+            KeyAction::Pressed => Some(nav),
+            KeyAction::Held if matches!(nav, Nav::Up | Nav::Down) => Some(nav),
+            _ => None,
+        }
+    }
+}
+
+/// Keys from a [`KeypadMatrix`](crate::input::KeypadMatrix)'s channel,
+/// mapped by a [`KeypadMap`]. Drop-in for [`KnobControls`].
+pub struct KeypadControls {
+    events: Receiver<KeyEvent>,
+    map: KeypadMap,
+}
+
+impl KeypadControls {
+    pub fn new(events: Receiver<KeyEvent>, map: KeypadMap) -> Self {
+        KeypadControls { events, map }
+    }
+
+    /// One input per call, without waiting.
+    pub fn poll(&mut self) -> Result<Option<Nav>, Box<dyn Error>> {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => {
+                    if let Some(nav) = self.map.nav(event) {
+                        return Ok(Some(nav));
+                    }
+                }
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err("keypad stopped".into()),
+            }
+        }
+    }
+}
+
+/// What a key did to a [`PinEntry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinInput {
+    /// Still typing.
+    Pending,
+    Entered(String),
+    Cancelled,
+}
+
+/// A code typed on a keypad: digits add to it, `*` rubs out the last one
+/// (or cancels when there's none) and `#` accepts. The prompt is on the
+/// top row and the code under it as `*`s.
+///
+/// ```no_run
+/// use rpi_peripherals::address::Address;
+/// use rpi_peripherals::input::{KeypadMatrix, Pcf8574Lines, DEFAULT_SCAN, KEYS_4X4};
+/// use rpi_peripherals::lcd::Lcd;
+/// use rpi_peripherals::menu::PinEntry;
+/// use std::sync::atomic::AtomicBool;
+/// # fn demo<I2C: rpi_peripherals::address::AddressedI2c + Send + 'static>(keys: I2C, display: I2C) -> Result<(), Box<dyn std::error::Error>> {
+///
+/// let lines = Pcf8574Lines::four_by_four(keys, Address::SevenBit(0x20))?;
+/// let (_scanner, events) = KeypadMatrix::new(lines, &KEYS_4X4)?.spawn(DEFAULT_SCAN)?;
+/// let mut lcd = Lcd::new(display, Address::SevenBit(0x27), 16, 2)?;
+/// if let Some(pin) = PinEntry::new("Enter PIN:", 6).run(&mut lcd, &events, &AtomicBool::new(false))? {
+///     println!("{} digits entered", pin.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinEntry {
+    prompt: String,
+    digits: String,
+    max: usize,
+}
+
+impl PinEntry {
+    /// Digits past `max` are ignored.
+    pub fn new(prompt: &str, max: usize) -> Self {
+        PinEntry {
+            prompt: prompt.to_string(),
+            digits: String::new(),
+            max,
+        }
+    }
+
+    /// How many digits are in so far.
+    pub fn len(&self) -> usize {
+        self.digits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// Apply one key press. After `Entered` or `Cancelled` it starts over.
+    pub fn key(&mut self, key: char) -> PinInput {
+        match key {
+            '0'..='9' if self.digits.len() < self.max => self.digits.push(key),
+            '*' if self.digits.is_empty() => return PinInput::Cancelled,
+            '*' => {
+                self.digits.pop();
+            }
+            '#' => return PinInput::Entered(std::mem::take(&mut self.digits)),
+            _ => {}
+        }
+        PinInput::Pending
+    }
+
+    /// The prompt and the masked code, `rows` lines of `cols` characters.
+    pub fn render(&self, cols: usize, rows: usize) -> Vec<String> {
+        let masked = "*".repeat(self.digits.len());
+        [self.prompt.as_str(), masked.as_str()]
+            .into_iter()
+            .chain(std::iter::repeat(""))
+            .take(rows.max(1))
+            .map(|text| format!("{:<cols$.cols$}", text))
+            .collect()
+    }
+
+    pub fn draw<B: LcdInterface>(&self, lcd: &mut Lcd<B>) -> Result<(), Box<dyn Error>> {
+        let (cols, rows) = lcd.size();
+        for (row, text) in self.render(cols as usize, rows as usize).iter().enumerate() {
+            lcd.set_cursor(0, row as u8)?;
+            lcd.write_str(text)?;
+        }
+        Ok(())
+    }
+
+    /// Ask on `lcd` until a code is accepted, or `None` once it's
+    /// cancelled or `stop` is set.
+    pub fn run<B: LcdInterface>(
+        &mut self,
+        lcd: &mut Lcd<B>,
+        events: &Receiver<KeyEvent>,
+        stop: &AtomicBool,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        self.draw(lcd)?;
+        while !stop.load(Ordering::Relaxed) {
+            let event = match events.recv_timeout(POLL * 50) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err("keypad stopped".into()),
+            };
+            if event.action != KeyAction::Pressed {
+                continue;
+            }
+            match self.key(event.key) {
+                PinInput::Pending => self.draw(lcd)?,
+                PinInput::Entered(pin) => return Ok(Some(pin)),
+                PinInput::Cancelled => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+}
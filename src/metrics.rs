@@ -0,0 +1,176 @@
+//! In-process metrics in the Prometheus text format.
+//!
+//! [`Metrics`] is a cheap-to-clone handle onto one shared registry. Subsystems
+//! bump counters, set gauges and observe histograms on it; [`Metrics::render`]
+//! produces the text a Prometheus scrape expects. [`MeteredBus`] wraps a bus
+//! to count its transactions, NACKs and retries.
+
+mod bus;
+
+pub use bus::{is_nack, MeteredBus};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        }
+    }
+}
+
+struct Family {
+    help: &'static str,
+    kind: Kind,
+    /// Rendered label set (`{device="lcd"}`) to value.
+    values: BTreeMap<String, f64>,
+    /// Same keying, for histogram families.
+    histograms: BTreeMap<String, Histogram>,
+}
+
+/// Upper bounds for I2C transaction latency, in seconds: 50 µs to 1 s.
+pub const LATENCY_BUCKETS: &[f64] = &[0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.1, 1.0];
+
+struct Histogram {
+    bounds: &'static [f64],
+    /// Per-bucket counts, not yet cumulative.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct Metrics {
+    families: Arc<Mutex<BTreeMap<&'static str, Family>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Add `by` to a counter, creating it at zero first if needed.
+    pub fn inc(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)], by: f64) {
+        self.update(name, help, Kind::Counter, labels, |v| *v += by);
+    }
+
+    pub fn set(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.update(name, help, Kind::Gauge, labels, |v| *v = value);
+    }
+
+    /// Count `value` into a histogram with the bucket upper bounds `bounds`.
+    pub fn observe(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        bounds: &'static [f64],
+        value: f64,
+    ) {
+        let mut families = self.lock();
+        let family = families.entry(name).or_insert_with(|| Family::new(help, Kind::Histogram));
+        debug_assert_eq!(family.kind, Kind::Histogram, "metric {} used as histogram and as {}", name, family.kind.as_str());
+        let histogram = family.histograms.entry(render_labels(labels)).or_insert_with(|| Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        });
+        if let Some(bucket) = histogram.bounds.iter().position(|&le| value <= le) {
+            histogram.counts[bucket] += 1;
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    /// Current value of one series, if it has been touched.
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let families = self.lock();
+        families.get(name)?.values.get(&render_labels(labels)).copied()
+    }
+
+    pub fn render(&self) -> String {
+        let families = self.lock();
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", name, family.kind.as_str());
+            for (labels, value) in &family.values {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+            for (labels, histogram) in &family.histograms {
+                let mut cumulative = 0;
+                for (le, count) in histogram.bounds.iter().zip(&histogram.counts) {
+                    cumulative += count;
+                    let _ = writeln!(out, "{}_bucket{} {}", name, with_le(labels, &le.to_string()), cumulative);
+                }
+                let _ = writeln!(out, "{}_bucket{} {}", name, with_le(labels, "+Inf"), histogram.count);
+                let _ = writeln!(out, "{}_sum{} {}", name, labels, histogram.sum);
+                let _ = writeln!(out, "{}_count{} {}", name, labels, histogram.count);
+            }
+        }
+        out
+    }
+
+    fn update(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        kind: Kind,
+        labels: &[(&str, &str)],
+        f: impl FnOnce(&mut f64),
+    ) {
+        let mut families = self.lock();
+        let family = families.entry(name).or_insert_with(|| Family::new(help, kind));
+        debug_assert_eq!(family.kind, kind, "metric {} used as both counter and gauge", name);
+        f(family.values.entry(render_labels(labels)).or_insert(0.0));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<&'static str, Family>> {
+        self.families.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Family {
+    fn new(help: &'static str, kind: Kind) -> Self {
+        Family {
+            help,
+            kind,
+            values: BTreeMap::new(),
+            histograms: BTreeMap::new(),
+        }
+    }
+}
+
+/// Add the `le` label to an already rendered label set.
+fn with_le(labels: &str, le: &str) -> String {
+    match labels.strip_suffix('}') {
+        Some(inner) => format!("{},le=\"{}\"}}", inner, le),
+        None => format!("{{le=\"{}\"}}", le),
+    }
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| {
+            let escaped = v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", k, escaped)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
@@ -0,0 +1,164 @@
+use super::{Metrics, LATENCY_BUCKETS};
+use crate::address::{Address, AddressedI2c};
+use crate::bus::{BusControl, StubError};
+use crate::softi2c::{SoftI2cError, Stretching};
+use embedded_hal::i2c::{Error as _, ErrorKind, ErrorType, I2c, Operation};
+use std::convert::Infallible;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Wraps a bus and counts what goes through it, per address:
+///
+/// - `rpi_peripherals_i2c_transactions_total{address, result}`
+/// - `rpi_peripherals_i2c_nacks_total{address}`
+/// - `rpi_peripherals_i2c_retries_total{address}`
+/// - `rpi_peripherals_i2c_transaction_seconds{address}` (histogram)
+///
+/// With [`MeteredBus::set_retries`], failed transactions are retried that
+/// many times before the error is passed on; each attempt is counted.
+pub struct MeteredBus<I2C> {
+    i2c: I2C,
+    metrics: Metrics,
+    retries: u32,
+}
+
+impl<I2C> MeteredBus<I2C> {
+    pub fn new(i2c: I2C, metrics: Metrics) -> Self {
+        MeteredBus { i2c, metrics, retries: 0 }
+    }
+
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Run `attempt` with retries, recording every try.
+    fn metered<E>(
+        &mut self,
+        address: Address,
+        mut attempt: impl FnMut(&mut I2C) -> Result<(), E>,
+        nack: impl Fn(&E) -> bool,
+    ) -> Result<(), E> {
+        let label = address.to_string();
+        let labels = [("address", label.as_str())];
+        let mut tries = 0;
+        loop {
+            let began = Instant::now();
+            let result = attempt(&mut self.i2c);
+            self.record(&labels, began.elapsed(), &result, &nack);
+            match result {
+                Err(_) if tries < self.retries => {
+                    tries += 1;
+                    self.metrics.inc(
+                        "rpi_peripherals_i2c_retries_total",
+                        "Transactions retried after a failure",
+                        &labels,
+                        1.0,
+                    );
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn record<E>(&self, labels: &[(&str, &str)], elapsed: Duration, result: &Result<(), E>, nack: impl Fn(&E) -> bool) {
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        self.metrics.inc(
+            "rpi_peripherals_i2c_transactions_total",
+            "I2C transactions by result",
+            &[labels[0], ("result", outcome)],
+            1.0,
+        );
+        self.metrics.observe(
+            "rpi_peripherals_i2c_transaction_seconds",
+            "I2C transaction latency",
+            labels,
+            LATENCY_BUCKETS,
+            elapsed.as_secs_f64(),
+        );
+        if result.as_ref().err().is_some_and(nack) {
+            self.metrics.inc(
+                "rpi_peripherals_i2c_nacks_total",
+                "Transactions the slave did not acknowledge",
+                labels,
+                1.0,
+            );
+        }
+    }
+}
+
+/// Whether an error from [`AddressedI2c`] was a NACK, for the buses this
+/// crate opens: i2c-dev reports ENXIO / EREMOTEIO, software I2C and the
+/// stub bus say so.
+pub fn is_nack(err: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(err);
+    while let Some(e) = current {
+        if let Some(rppal::i2c::Error::Io(io)) = e.downcast_ref::<rppal::i2c::Error>() {
+            return matches!(io.raw_os_error(), Some(6) | Some(121));
+        }
+        if let Some(soft) = e.downcast_ref::<SoftI2cError<Infallible>>() {
+            return matches!(soft.kind(), ErrorKind::NoAcknowledge(_));
+        }
+        #[cfg(feature = "i2cdev")]
+        if let Some(linux) = e.downcast_ref::<crate::bus::LinuxI2cError>() {
+            return matches!(linux.kind(), ErrorKind::NoAcknowledge(_));
+        }
+        if e.is::<StubError>() {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
+
+impl<I2C: I2c> ErrorType for MeteredBus<I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> I2c for MeteredBus<I2C> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.metered(
+            Address::SevenBit(address),
+            |i2c| i2c.transaction(address, operations),
+            |e| matches!(e.kind(), ErrorKind::NoAcknowledge(_)),
+        )
+    }
+}
+
+impl<I2C: AddressedI2c> AddressedI2c for MeteredBus<I2C> {
+    fn transaction_at(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+        self.metered(address, |i2c| i2c.transaction_at(address, operations), |e| is_nack(e.as_ref()))
+    }
+
+    fn repeated_start(&self, address: Address) -> bool {
+        self.i2c.repeated_start(address)
+    }
+}
+
+impl<I2C: BusControl> BusControl for MeteredBus<I2C> {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        self.i2c.clock_speed()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.i2c.set_timeout(timeout)
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        self.i2c.set_clock_speed(hz)
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        self.i2c.recover()
+    }
+    fn stretching(&self) -> Option<Stretching> {
+        self.i2c.stretching()
+    }
+}
@@ -0,0 +1,268 @@
+//! Modbus RTU master, over RS-485 or a plain serial line.
+//!
+//! [`RtuMaster`] covers the register function codes most sensors, meters
+//! and drives use: read holding registers (3), read input registers (4),
+//! write single register (6) and write multiple registers (16). Requests
+//! are framed with the CRC and the 3.5-character silence RTU needs, and a
+//! reply that times out or fails its CRC is retried. An exception reply is
+//! the slave answering, not a line fault, so it comes back at once as a
+//! [`ModbusException`].
+//!
+//! ```no_run
+//! use rpi_peripherals::modbus::RtuMaster;
+//! use rpi_peripherals::rs485::Rs485;
+//!
+//! let mut master = RtuMaster::new(Rs485::open("/dev/serial0", 9600, 17)?, 9600)?;
+//! let registers = master.read_holding_registers(5, 100, 4)?;
+//! master.write_single_register(5, 200, 0x0001)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::crc::crc16_modbus;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a slave gets to start its reply.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Tries in all, the first included.
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+
+/// Registers one read may ask for.
+pub const MAX_READ: u16 = 125;
+
+/// Registers one write-multiple may carry.
+pub const MAX_WRITE: u16 = 123;
+
+const READ_HOLDING: u8 = 0x03;
+const READ_INPUT: u8 = 0x04;
+const WRITE_SINGLE: u8 = 0x06;
+const WRITE_MULTIPLE: u8 = 0x10;
+
+/// The spec fixes the gap above 19200 baud rather than scaling it.
+const FAST_FRAME_GAP: Duration = Duration::from_micros(1750);
+
+/// A slave's exception reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModbusException {
+    pub slave: u8,
+    pub function: u8,
+    pub code: u8,
+}
+
+impl ModbusException {
+    pub fn description(&self) -> &'static str {
+        match self.code {
+            0x01 => "illegal function",
+            0x02 => "illegal data address",
+            0x03 => "illegal data value",
+            0x04 => "slave device failure",
+            0x05 => "acknowledge (still working on it)",
+            0x06 => "slave device busy",
+            0x08 => "memory parity error",
+            0x0A => "gateway path unavailable",
+            0x0B => "gateway target failed to respond",
+            _ => "unknown exception",
+        }
+    }
+}
+
+impl fmt::Display for ModbusException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "slave {} answered function {} with exception {}: {}",
+            self.slave,
+            self.function,
+            self.code,
+            self.description()
+        )
+    }
+}
+
+impl Error for ModbusException {}
+
+/// The master on `port`: a [`crate::rs485::Rs485`] or a
+/// [`crate::uart::SerialPort`] with the port's read timeout shorter than
+/// the reply timeout.
+pub struct RtuMaster<P> {
+    port: P,
+    frame_gap: Duration,
+    timeout: Duration,
+    attempts: u32,
+    last_activity: Option<Instant>,
+}
+
+impl<P: Read + Write> RtuMaster<P> {
+    /// `baud_rate` is what `port` runs at, for the gap between frames.
+    pub fn new(port: P, baud_rate: u32) -> Result<Self, Box<dyn Error>> {
+        if baud_rate == 0 {
+            return Err("baud rate must be above 0".into());
+        }
+        let frame_gap = if baud_rate > 19_200 {
+            FAST_FRAME_GAP
+        } else {
+            // 3.5 characters of 11 bits
+            Duration::from_micros(38_500_000 / u64::from(baud_rate))
+        };
+        Ok(RtuMaster {
+            port,
+            frame_gap,
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            last_activity: None,
+        })
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_attempts(&mut self, attempts: u32) {
+        self.attempts = attempts.max(1);
+    }
+
+    /// Function 3.
+    pub fn read_holding_registers(&mut self, slave: u8, start: u16, count: u16) -> Result<Vec<u16>, Box<dyn Error>> {
+        self.read_registers(READ_HOLDING, slave, start, count)
+    }
+
+    /// Function 4.
+    pub fn read_input_registers(&mut self, slave: u8, start: u16, count: u16) -> Result<Vec<u16>, Box<dyn Error>> {
+        self.read_registers(READ_INPUT, slave, start, count)
+    }
+
+    /// Function 6. To slave 0, every slave takes it and none replies.
+    pub fn write_single_register(&mut self, slave: u8, register: u16, value: u16) -> Result<(), Box<dyn Error>> {
+        let mut body = register.to_be_bytes().to_vec();
+        body.extend(value.to_be_bytes());
+        let reply = self.exchange(slave, WRITE_SINGLE, &body, 4)?;
+        if slave != 0 && reply != body {
+            return Err(format!("slave {} echoed {:02X?} to a write of {:02X?}", slave, reply, body).into());
+        }
+        Ok(())
+    }
+
+    /// Function 16, up to [`MAX_WRITE`] registers from `start`.
+    pub fn write_multiple_registers(&mut self, slave: u8, start: u16, values: &[u16]) -> Result<(), Box<dyn Error>> {
+        if values.is_empty() || values.len() > usize::from(MAX_WRITE) {
+            return Err(format!("{} registers; a write takes 1-{}", values.len(), MAX_WRITE).into());
+        }
+        let count = values.len() as u16;
+        let mut body = start.to_be_bytes().to_vec();
+        body.extend(count.to_be_bytes());
+        body.push(2 * values.len() as u8);
+        body.extend(values.iter().flat_map(|v| v.to_be_bytes()));
+        let reply = self.exchange(slave, WRITE_MULTIPLE, &body, 4)?;
+        if slave != 0 && reply != body[..4] {
+            return Err(format!("slave {} confirmed {:02X?} to a write of {} registers from {}", slave, reply, count, start).into());
+        }
+        Ok(())
+    }
+
+    pub fn release(self) -> P {
+        self.port
+    }
+
+    fn read_registers(&mut self, function: u8, slave: u8, start: u16, count: u16) -> Result<Vec<u16>, Box<dyn Error>> {
+        if slave == 0 {
+            return Err("reads can't be broadcast; give a slave address 1-247".into());
+        }
+        if !(1..=MAX_READ).contains(&count) {
+            return Err(format!("{} registers; a read takes 1-{}", count, MAX_READ).into());
+        }
+        let mut body = start.to_be_bytes().to_vec();
+        body.extend(count.to_be_bytes());
+        let reply = self.exchange(slave, function, &body, 1 + 2 * usize::from(count))?;
+        if usize::from(reply[0]) != 2 * usize::from(count) {
+            return Err(format!("slave {} sent {} bytes for {} registers", slave, reply[0], count).into());
+        }
+        Ok(reply[1..].chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect())
+    }
+
+    /// Send a request and return the reply's data, after the slave and
+    /// function bytes and before the CRC, retrying line faults.
+    fn exchange(&mut self, slave: u8, function: u8, body: &[u8], reply_len: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        if slave > 247 {
+            return Err(format!("slave address {} is outside 0-247", slave).into());
+        }
+        let mut request = vec![slave, function];
+        request.extend_from_slice(body);
+        request.extend(crc16_modbus(&request).to_le_bytes());
+        let mut last = String::new();
+        for _ in 0..self.attempts {
+            self.send(&request)?;
+            if slave == 0 {
+                return Ok(Vec::new());
+            }
+            match self.receive(slave, function, reply_len) {
+                Ok(reply) => return Ok(reply),
+                Err(e) if e.is::<ModbusException>() => return Err(e),
+                Err(e) => last = e.to_string(),
+            }
+        }
+        Err(format!("slave {}: {} (after {} attempts)", slave, last, self.attempts).into())
+    }
+
+    fn send(&mut self, request: &[u8]) -> Result<(), Box<dyn Error>> {
+        if let Some(at) = self.last_activity {
+            let quiet = at.elapsed();
+            if quiet < self.frame_gap {
+                thread::sleep(self.frame_gap - quiet);
+            }
+        }
+        self.port.write_all(request)?;
+        self.port.flush()?;
+        self.last_activity = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Read one reply of `data_len` bytes between header and CRC, or an
+    /// exception.
+    fn receive(&mut self, slave: u8, function: u8, data_len: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let deadline = Instant::now() + self.timeout;
+        let mut frame = Vec::new();
+        let mut want = 2 + data_len + 2;
+        while frame.len() < want {
+            if Instant::now() >= deadline {
+                return Err(if frame.is_empty() {
+                    "no reply".to_string()
+                } else {
+                    format!("reply cut short at {} of {} bytes", frame.len(), want)
+                }
+                .into());
+            }
+            let mut buffer = [0; 256];
+            let n = match self.port.read(&mut buffer[..want - frame.len()]) {
+                Ok(n) => n,
+                Err(e) if matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::TimedOut) => 0,
+                Err(e) => return Err(e.into()),
+            };
+            frame.extend_from_slice(&buffer[..n]);
+            if n > 0 {
+                self.last_activity = Some(Instant::now());
+            }
+            // An exception is five bytes whatever the request
+            if frame.len() >= 2 && frame[1] == function | 0x80 {
+                want = 5;
+            }
+        }
+        let (message, crc) = frame.split_at(frame.len() - 2);
+        if crc16_modbus(message).to_le_bytes() != crc {
+            return Err(format!("reply {:02X?} failed its CRC", frame).into());
+        }
+        if message[0] != slave {
+            return Err(format!("reply came from slave {}", message[0]).into());
+        }
+        if message[1] == function | 0x80 {
+            return Err(ModbusException { slave, function, code: message[2] }.into());
+        }
+        if message[1] != function {
+            return Err(format!("reply is for function {}, not {}", message[1], function).into());
+        }
+        Ok(message[2..].to_vec())
+    }
+}
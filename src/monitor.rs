@@ -0,0 +1,173 @@
+//! Device presence watchdog for the `monitor` daemon.
+//!
+//! [`Presence`] probes a fixed set of addresses once per round and reports
+//! devices appearing and disappearing. It also decides when the bus itself
+//! looks stuck rather than a device being unplugged: a probe failing with
+//! something other than a NACK, or every device seen so far going quiet at
+//! once. After `recover_after` stuck rounds in a row it runs
+//! [`BusControl::recover`] to free a slave holding SDA low.
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use crate::metrics::{self, Metrics};
+use std::fmt;
+
+/// One address to keep an eye on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watched {
+    pub name: String,
+    pub address: Address,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresenceEvent {
+    Appeared(Watched),
+    Disappeared(Watched),
+}
+
+impl fmt::Display for PresenceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresenceEvent::Appeared(w) => write!(f, "{} ({}) appeared", w.name, w.address),
+            PresenceEvent::Disappeared(w) => write!(f, "{} ({}) disappeared", w.name, w.address),
+        }
+    }
+}
+
+/// What one round of probing found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Round {
+    /// Changes since the last round; empty on the first.
+    pub events: Vec<PresenceEvent>,
+    pub present: usize,
+    pub total: usize,
+    /// Whether this round counted towards recovery.
+    pub stuck: bool,
+    /// Outcome of the recovery attempt, if one was made this round.
+    pub recovery: Option<Result<(), String>>,
+}
+
+struct State {
+    watched: Watched,
+    present: Option<bool>,
+    seen: bool,
+}
+
+pub struct Presence {
+    states: Vec<State>,
+    recover_after: u32,
+    stuck_rounds: u32,
+    metrics: Option<Metrics>,
+}
+
+impl Presence {
+    /// `recover_after` is clamped to at least one round.
+    pub fn new(watched: Vec<Watched>, recover_after: u32) -> Self {
+        Presence {
+            states: watched
+                .into_iter()
+                .map(|watched| State {
+                    watched,
+                    present: None,
+                    seen: false,
+                })
+                .collect(),
+            recover_after: recover_after.max(1),
+            stuck_rounds: 0,
+            metrics: None,
+        }
+    }
+
+    /// Watch `watched` from now on, e.g. after a config reload. Devices
+    /// watched before keep their state, so they don't show as appearing.
+    pub fn reconfigure(&mut self, watched: Vec<Watched>, recover_after: u32) {
+        let mut old = std::mem::take(&mut self.states);
+        self.states = watched
+            .into_iter()
+            .map(|watched| match old.iter().position(|s| s.watched == watched) {
+                Some(i) => old.swap_remove(i),
+                None => State {
+                    watched,
+                    present: None,
+                    seen: false,
+                },
+            })
+            .collect();
+        self.recover_after = recover_after.max(1);
+        self.stuck_rounds = 0;
+    }
+
+    /// Export `rpi_peripherals_device_present` and recovery counts.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Every watched device with its state after the last round (`None`
+    /// before the first).
+    pub fn states(&self) -> impl Iterator<Item = (&Watched, Option<bool>)> {
+        self.states.iter().map(|s| (&s.watched, s.present))
+    }
+
+    /// Probe everything once, attempting recovery if the bus has been
+    /// stuck for long enough.
+    pub fn poll<I2C: AddressedI2c + BusControl>(&mut self, i2c: &mut I2C) -> Round {
+        let mut round = Round {
+            total: self.states.len(),
+            ..Round::default()
+        };
+        let mut bus_fault = false;
+        for state in &mut self.states {
+            let mut buf = [0u8];
+            // A one-byte read, like scan::probe, so expanders keep their outputs
+            let present = match i2c.read_at(state.watched.address, &mut buf) {
+                Ok(()) => true,
+                Err(e) => {
+                    bus_fault |= !metrics::is_nack(e.as_ref());
+                    false
+                }
+            };
+            match (state.present, present) {
+                (Some(false), true) => round.events.push(PresenceEvent::Appeared(state.watched.clone())),
+                (Some(true), false) => round.events.push(PresenceEvent::Disappeared(state.watched.clone())),
+                _ => {}
+            }
+            state.present = Some(present);
+            state.seen |= present;
+            if present {
+                round.present += 1;
+            }
+            if let Some(metrics) = &self.metrics {
+                let address = state.watched.address.to_string();
+                metrics.set(
+                    "rpi_peripherals_device_present",
+                    "Whether the device answered the last probe",
+                    &[("device", &state.watched.name), ("address", &address)],
+                    if present { 1.0 } else { 0.0 },
+                );
+            }
+        }
+
+        let all_gone = round.present == 0 && self.states.iter().any(|s| s.seen);
+        round.stuck = bus_fault || all_gone;
+        if !round.stuck {
+            self.stuck_rounds = 0;
+            return round;
+        }
+        self.stuck_rounds += 1;
+        if self.stuck_rounds >= self.recover_after {
+            self.stuck_rounds = 0;
+            let result = i2c.recover();
+            if let Some(metrics) = &self.metrics {
+                let outcome = if result.is_ok() { "ok" } else { "error" };
+                metrics.inc(
+                    "rpi_peripherals_bus_recoveries_total",
+                    "Bus recovery sequences run by the monitor",
+                    &[("result", outcome)],
+                    1.0,
+                );
+            }
+            round.recovery = Some(result.map_err(|e| e.to_string()));
+        }
+        round
+    }
+}
@@ -0,0 +1,372 @@
+//! Morse code on a GPIO: an LED, an active buzzer, or a passive buzzer
+//! sounded through [`SoftPwm`].
+//!
+//! Timing is the usual PARIS standard: a dit is 1.2 s / WPM, a dah three
+//! dits, with one dit between the elements of a letter, three between
+//! letters and seven between words. With [`MorseTiming::farnsworth`] set,
+//! letters keep their full speed and only the gaps between them stretch,
+//! the ARRL way, so the overall rate comes down without the letters
+//! sounding different.
+//!
+//! Letters, digits and the common punctuation are known; prosigns go in
+//! angle brackets and are sent run together, e.g. `<SK>` at the end of a
+//! message:
+//!
+//! ```no_run
+//! use rpi_peripherals::morse::{self, MorseTiming, PinKeyer};
+//! use std::sync::atomic::AtomicBool;
+//!
+//! let mut led = PinKeyer::from_gpio(17, false)?;
+//! let timing = MorseTiming { wpm: 15, ..MorseTiming::default() };
+//! morse::send(&mut led, "HAPPY BIRTHDAY <SK>", &timing, &AtomicBool::new(false))?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::pwm::SoftPwm;
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::Gpio;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_WPM: u32 = 20;
+
+/// Dit units in "PARIS ", the word WPM counts by.
+const PARIS_UNITS: f64 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MorseTiming {
+    /// Speed of the letters themselves, in words per minute.
+    pub wpm: u32,
+    /// Slower overall speed, reached by stretching only the gaps between
+    /// letters and words.
+    pub farnsworth: Option<u32>,
+}
+
+impl Default for MorseTiming {
+    fn default() -> Self {
+        MorseTiming {
+            wpm: DEFAULT_WPM,
+            farnsworth: None,
+        }
+    }
+}
+
+impl MorseTiming {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !(1..=60).contains(&self.wpm) {
+            return Err(format!("Morse speed {} WPM out of range (1-60)", self.wpm).into());
+        }
+        match self.farnsworth {
+            Some(0) => Err("Farnsworth speed must be greater than zero".into()),
+            Some(wpm) if wpm >= self.wpm => {
+                Err(format!("Farnsworth speed {} WPM must be below the letter speed of {} WPM", wpm, self.wpm).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn dit(&self) -> Duration {
+        Duration::from_secs_f64(1.2 / f64::from(self.wpm))
+    }
+
+    pub fn dah(&self) -> Duration {
+        self.dit() * 3
+    }
+
+    pub fn letter_gap(&self) -> Duration {
+        match self.stretched_unit() {
+            Some(unit) => unit * 3,
+            None => self.dit() * 3,
+        }
+    }
+
+    pub fn word_gap(&self) -> Duration {
+        match self.stretched_unit() {
+            Some(unit) => unit * 7,
+            None => self.dit() * 7,
+        }
+    }
+
+    /// With Farnsworth spacing, one unit of the gaps: "PARIS " has 19 of
+    /// them, and they take whatever of 60 / WPM seconds its 31 units of
+    /// letters at full speed leave over.
+    fn stretched_unit(&self) -> Option<Duration> {
+        let overall = f64::from(self.farnsworth?);
+        let letters = self.dit().as_secs_f64() * (PARIS_UNITS - 19.0);
+        Some(Duration::from_secs_f64((60.0 / overall - letters) / 19.0))
+    }
+}
+
+/// Dits and dahs for `ch`, in either case.
+pub fn code(ch: char) -> Option<&'static str> {
+    Some(match ch.to_ascii_uppercase() {
+        'A' => ".-",
+        'B' => "-...",
+        'C' => "-.-.",
+        'D' => "-..",
+        'E' => ".",
+        'F' => "..-.",
+        'G' => "--.",
+        'H' => "....",
+        'I' => "..",
+        'J' => ".---",
+        'K' => "-.-",
+        'L' => ".-..",
+        'M' => "--",
+        'N' => "-.",
+        'O' => "---",
+        'P' => ".--.",
+        'Q' => "--.-",
+        'R' => ".-.",
+        'S' => "...",
+        'T' => "-",
+        'U' => "..-",
+        'V' => "...-",
+        'W' => ".--",
+        'X' => "-..-",
+        'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----",
+        '1' => ".----",
+        '2' => "..---",
+        '3' => "...--",
+        '4' => "....-",
+        '5' => ".....",
+        '6' => "-....",
+        '7' => "--...",
+        '8' => "---..",
+        '9' => "----.",
+        '.' => ".-.-.-",
+        ',' => "--..--",
+        '?' => "..--..",
+        '\'' => ".----.",
+        '!' => "-.-.--",
+        '/' => "-..-.",
+        '(' => "-.--.",
+        ')' => "-.--.-",
+        '&' => ".-...",
+        ':' => "---...",
+        ';' => "-.-.-.",
+        '=' => "-...-",
+        '+' => ".-.-.",
+        '-' => "-....-",
+        '_' => "..--.-",
+        '"' => ".-..-.",
+        '$' => "...-..-",
+        '@' => ".--.-.",
+        _ => return None,
+    })
+}
+
+/// `text` as letters, each its dits and dahs, with `None` between words.
+fn letters(text: &str) -> Result<Vec<Option<String>>, Box<dyn Error>> {
+    let mut out: Vec<Option<String>> = Vec::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch.is_whitespace() {
+            if matches!(out.last(), Some(Some(_))) {
+                out.push(None);
+            }
+            continue;
+        }
+        let letter = if ch == '<' {
+            let prosign: String = chars.by_ref().take_while(|&c| c != '>').collect();
+            if prosign.is_empty() {
+                return Err("empty prosign '<>'".into());
+            }
+            prosign
+                .chars()
+                .map(|c| code(c).ok_or_else(|| format!("no Morse code for '{}' in prosign <{}>", c, prosign)))
+                .collect::<Result<String, _>>()?
+        } else {
+            code(ch).ok_or_else(|| format!("no Morse code for '{}'", ch))?.to_string()
+        };
+        out.push(Some(letter));
+    }
+    if out.last() == Some(&None) {
+        out.pop();
+    }
+    if out.is_empty() {
+        return Err("nothing to send".into());
+    }
+    Ok(out)
+}
+
+/// `text` written out in dits and dahs, letters apart and words split by
+/// ` / `, e.g. `.... .. / - .... . .-. .`.
+pub fn spell(text: &str) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
+    for letter in letters(text)? {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        match letter {
+            Some(code) => out.push_str(&code),
+            None => out.push('/'),
+        }
+    }
+    Ok(out)
+}
+
+/// `text` as (key down, key up) times, the way [`send`] plays it. The
+/// last key up is zero.
+pub fn keying(text: &str, timing: &MorseTiming) -> Result<Vec<(Duration, Duration)>, Box<dyn Error>> {
+    timing.validate()?;
+    let mut steps: Vec<(Duration, Duration)> = Vec::new();
+    for letter in letters(text)? {
+        let Some(code) = letter else {
+            if let Some(last) = steps.last_mut() {
+                last.1 = timing.word_gap();
+            }
+            continue;
+        };
+        if let Some(last) = steps.last_mut() {
+            last.1 = last.1.max(timing.letter_gap());
+        }
+        for element in code.chars() {
+            let on = if element == '-' { timing.dah() } else { timing.dit() };
+            steps.push((on, timing.dit()));
+        }
+        if let Some(last) = steps.last_mut() {
+            last.1 = Duration::ZERO;
+        }
+    }
+    Ok(steps)
+}
+
+/// How long `text` takes to send.
+pub fn duration(text: &str, timing: &MorseTiming) -> Result<Duration, Box<dyn Error>> {
+    Ok(keying(text, timing)?.iter().map(|(on, off)| *on + *off).sum())
+}
+
+/// Something that can be keyed: a light, a buzzer, a transmitter.
+pub trait Keyer {
+    fn key(&mut self, down: bool) -> Result<(), Box<dyn Error>>;
+}
+
+/// An LED or active buzzer on a pin: on while the key is down.
+pub struct PinKeyer<P> {
+    pin: P,
+    active_low: bool,
+}
+
+impl PinKeyer<rppal::gpio::OutputPin> {
+    pub fn from_gpio(pin: u8, active_low: bool) -> Result<Self, Box<dyn Error>> {
+        let pin = Gpio::new()?.get(pin).map_err(|e| format!("Morse GPIO {}: {}", pin, e))?.into_output();
+        PinKeyer::new(pin, active_low)
+    }
+}
+
+impl<P: OutputPin> PinKeyer<P>
+where
+    P::Error: Error + 'static,
+{
+    /// Starts with the key up.
+    pub fn new(pin: P, active_low: bool) -> Result<Self, Box<dyn Error>> {
+        let mut keyer = PinKeyer { pin, active_low };
+        keyer.key(false)?;
+        Ok(keyer)
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+impl<P: OutputPin> Keyer for PinKeyer<P>
+where
+    P::Error: Error + 'static,
+{
+    fn key(&mut self, down: bool) -> Result<(), Box<dyn Error>> {
+        if down != self.active_low {
+            self.pin.set_high()?;
+        } else {
+            self.pin.set_low()?;
+        }
+        Ok(())
+    }
+}
+
+/// A passive buzzer: a square wave at the PWM's frequency while the key
+/// is down. Start the PWM at duty 0.
+impl<P> Keyer for SoftPwm<P>
+where
+    P: OutputPin + Send + 'static,
+    P::Error: fmt::Debug,
+{
+    fn key(&mut self, down: bool) -> Result<(), Box<dyn Error>> {
+        self.set_duty(if down { 0.5 } else { 0.0 })
+    }
+}
+
+/// Key `text` out on `keyer`, returning early once `stop` is set. The key
+/// is left up either way.
+pub fn send<K: Keyer>(keyer: &mut K, text: &str, timing: &MorseTiming, stop: &AtomicBool) -> Result<(), Box<dyn Error>> {
+    let steps = keying(text, timing)?;
+    let result = (|| {
+        for (on, off) in steps {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            keyer.key(true)?;
+            thread::sleep(on);
+            keyer.key(false)?;
+            thread::sleep(off);
+        }
+        Ok(())
+    })();
+    keyer.key(false)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn units(text: &str, timing: &MorseTiming) -> f64 {
+        duration(text, timing).unwrap().as_secs_f64() / timing.dit().as_secs_f64()
+    }
+
+    #[test]
+    fn spells_words_and_prosigns() {
+        assert_eq!(spell("Hi there").unwrap(), ".... .. / - .... . .-. .");
+        assert_eq!(spell("  SOS  ").unwrap(), "... --- ...");
+        assert_eq!(spell("73 <SK>").unwrap(), "--... ...-- / ...-.-");
+        assert!(spell("#").is_err());
+        assert!(spell("<>").is_err());
+        assert!(spell("   ").is_err());
+    }
+
+    #[test]
+    fn paris_is_fifty_units() {
+        let timing = MorseTiming::default();
+        assert!((units("PARIS", &timing) - 43.0).abs() < 1e-6);
+        assert!((units("PARIS PARIS", &timing) - 93.0).abs() < 1e-6);
+        assert_eq!(timing.dit(), Duration::from_millis(60));
+    }
+
+    #[test]
+    fn farnsworth_keeps_letters_and_slows_words() {
+        let timing = MorseTiming {
+            wpm: 20,
+            farnsworth: Some(10),
+        };
+        let word = duration("PARIS PARIS", &timing).unwrap() - duration("PARIS", &timing).unwrap();
+        assert!((word.as_secs_f64() - 6.0).abs() < 1e-6);
+        assert_eq!(keying("E", &timing).unwrap(), [(Duration::from_millis(60), Duration::ZERO)]);
+        assert!(MorseTiming { wpm: 20, farnsworth: Some(20) }.validate().is_err());
+    }
+
+    #[test]
+    fn keying_spaces_letters_and_words() {
+        let timing = MorseTiming::default();
+        let (dit, dah) = (timing.dit(), timing.dah());
+        assert_eq!(
+            keying("AE T", &timing).unwrap(),
+            [(dit, dit), (dah, dit * 3), (dit, dit * 7), (dah, Duration::ZERO)]
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Hobby servos and 4-wire stepper motors.
+//!
+//! A [`Servo`] maps an angle onto a 50 Hz pulse between its minimum and
+//! maximum width, on a hardware PWM channel or on any GPIO with software
+//! PWM. Hardware PWM keeps the pulse steady under load, software PWM
+//! jitters by tens of microseconds, which a servo shows as a twitch:
+//!
+//! ```no_run
+//! use rpi_peripherals::motor::Servo;
+//! use std::time::Duration;
+//!
+//! let mut servo = Servo::from_gpio(17)?;
+//! servo.set_range(Duration::from_micros(500), Duration::from_micros(2400))?;
+//! servo.set_angle(90.0)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! A [`Stepper`] drives the four coil inputs of a ULN2003 board (the usual
+//! 28BYJ-48 kit) or an H-bridge, in full or half steps, ramping the speed
+//! up and down with a [`Ramp`] so the rotor doesn't lose steps starting
+//! or stopping under load.
+
+mod servo;
+mod stepper;
+
+pub use servo::{PulseOutput, Servo, SERVO_PERIOD};
+pub use stepper::{Ramp, StepMode, Stepper, MIN_STEP_RATE};
@@ -0,0 +1,188 @@
+use rppal::gpio::{Gpio, OutputPin};
+use rppal::pwm::{Channel, Polarity, Pwm};
+use std::error::Error;
+use std::time::Duration;
+
+/// 50 Hz, what analog servos expect; digital ones take it too.
+pub const SERVO_PERIOD: Duration = Duration::from_millis(20);
+
+const DEFAULT_MIN_PULSE: Duration = Duration::from_micros(1000);
+const DEFAULT_MAX_PULSE: Duration = Duration::from_micros(2000);
+const DEFAULT_TRAVEL: f64 = 180.0;
+
+/// Something that can put out a pulse train.
+pub trait PulseOutput {
+    fn set_pulse(&mut self, period: Duration, width: Duration) -> Result<(), Box<dyn Error>>;
+
+    /// No pulses: the servo goes limp.
+    fn stop(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+/// Hardware PWM; the channel has to be routed to a pin with
+/// `dtoverlay=pwm` or `pwm-2chan`.
+impl PulseOutput for Pwm {
+    fn set_pulse(&mut self, period: Duration, width: Duration) -> Result<(), Box<dyn Error>> {
+        // Keep period >= width in every step, whichever way it moves
+        if self.pulse_width()? > period {
+            self.set_pulse_width(width)?;
+            self.set_period(period)?;
+        } else {
+            self.set_period(period)?;
+            self.set_pulse_width(width)?;
+        }
+        self.enable()?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(self.disable()?)
+    }
+}
+
+/// rppal's software PWM, on a thread of its own.
+impl PulseOutput for OutputPin {
+    fn set_pulse(&mut self, period: Duration, width: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(self.set_pwm(period, width)?)
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.clear_pwm()?;
+        self.set_low();
+        Ok(())
+    }
+}
+
+impl PulseOutput for Box<dyn PulseOutput + Send> {
+    fn set_pulse(&mut self, period: Duration, width: Duration) -> Result<(), Box<dyn Error>> {
+        (**self).set_pulse(period, width)
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        (**self).stop()
+    }
+}
+
+/// A hobby servo: 0° at the minimum pulse, the full travel at the maximum.
+/// Defaults are the nominal 1-2 ms over 180°; most servos go further, so
+/// find their end stops and [`set_range`](Servo::set_range) to use it.
+pub struct Servo<O> {
+    out: O,
+    min_pulse: Duration,
+    max_pulse: Duration,
+    travel: f64,
+    angle: Option<f64>,
+}
+
+impl Servo<OutputPin> {
+    /// Software PWM on BCM pin `pin`.
+    pub fn from_gpio(pin: u8) -> Result<Self, Box<dyn Error>> {
+        let pin = Gpio::new()?
+            .get(pin)
+            .map_err(|e| format!("servo GPIO {}: {}", pin, e))?
+            .into_output_low();
+        Ok(Servo::new(pin))
+    }
+}
+
+impl Servo<Pwm> {
+    /// Hardware PWM channel `channel`, as `board-info` lists them.
+    pub fn from_pwm(channel: u8) -> Result<Self, Box<dyn Error>> {
+        let channel = match channel {
+            0 => Channel::Pwm0,
+            1 => Channel::Pwm1,
+            2 => Channel::Pwm2,
+            3 => Channel::Pwm3,
+            _ => return Err(format!("no PWM channel {} (0-3)", channel).into()),
+        };
+        let pwm = Pwm::with_period(channel, SERVO_PERIOD, Duration::ZERO, Polarity::Normal, false)
+            .map_err(|e| format!("PWM channel {}: {}; is dtoverlay=pwm set?", channel, e))?;
+        Ok(Servo::new(pwm))
+    }
+}
+
+impl<O: PulseOutput> Servo<O> {
+    /// Not driven until the first [`set_angle`](Servo::set_angle).
+    pub fn new(out: O) -> Self {
+        Servo {
+            out,
+            min_pulse: DEFAULT_MIN_PULSE,
+            max_pulse: DEFAULT_MAX_PULSE,
+            travel: DEFAULT_TRAVEL,
+            angle: None,
+        }
+    }
+
+    /// Pulse widths at 0° and at the full travel.
+    pub fn set_range(&mut self, min: Duration, max: Duration) -> Result<(), Box<dyn Error>> {
+        if min >= max || max >= SERVO_PERIOD {
+            return Err(format!(
+                "servo pulse range {}-{}µs must rise and stay under the {}ms period",
+                min.as_micros(),
+                max.as_micros(),
+                SERVO_PERIOD.as_millis()
+            )
+            .into());
+        }
+        self.min_pulse = min;
+        self.max_pulse = max;
+        Ok(())
+    }
+
+    /// Degrees between the minimum and maximum pulse.
+    pub fn set_travel(&mut self, degrees: f64) -> Result<(), Box<dyn Error>> {
+        if !(degrees.is_finite() && degrees > 0.0) {
+            return Err(format!("servo travel {}° must be above 0", degrees).into());
+        }
+        self.travel = degrees;
+        Ok(())
+    }
+
+    pub fn range(&self) -> (Duration, Duration) {
+        (self.min_pulse, self.max_pulse)
+    }
+
+    pub fn travel(&self) -> f64 {
+        self.travel
+    }
+
+    /// The pulse width for `degrees`, which has to be within the travel.
+    pub fn pulse_for(&self, degrees: f64) -> Result<Duration, Box<dyn Error>> {
+        if !(0.0..=self.travel).contains(&degrees) {
+            return Err(format!("{}° is outside the servo's 0-{}°", degrees, self.travel).into());
+        }
+        let span = self.max_pulse - self.min_pulse;
+        Ok(self.min_pulse + span.mul_f64(degrees / self.travel))
+    }
+
+    pub fn set_angle(&mut self, degrees: f64) -> Result<Duration, Box<dyn Error>> {
+        let width = self.pulse_for(degrees)?;
+        self.out.set_pulse(SERVO_PERIOD, width)?;
+        self.angle = Some(degrees);
+        Ok(width)
+    }
+
+    /// A pulse width outside the range, for finding the end stops.
+    pub fn set_pulse(&mut self, width: Duration) -> Result<(), Box<dyn Error>> {
+        if width >= SERVO_PERIOD {
+            return Err(format!("a {}µs pulse doesn't fit the {}ms period", width.as_micros(), SERVO_PERIOD.as_millis()).into());
+        }
+        self.out.set_pulse(SERVO_PERIOD, width)?;
+        self.angle = None;
+        Ok(())
+    }
+
+    /// Last angle set, `None` before the first or after a raw pulse.
+    pub fn angle(&self) -> Option<f64> {
+        self.angle
+    }
+
+    /// Stop pulsing; the servo stops holding its position.
+    pub fn detach(&mut self) -> Result<(), Box<dyn Error>> {
+        self.angle = None;
+        self.out.stop()
+    }
+
+    pub fn release(self) -> O {
+        self.out
+    }
+}
@@ -0,0 +1,270 @@
+use crate::timing::PreciseDelay;
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::{Gpio, OutputPin as GpioPin};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Coil patterns, bit 0 on the first pin (IN1, coil A).
+const FULL_STEPS: [u8; 4] = [0b0011, 0b0110, 0b1100, 0b1001];
+const HALF_STEPS: [u8; 8] = [0b0001, 0b0011, 0b0010, 0b0110, 0b0100, 0b1100, 0b1000, 0b1001];
+
+/// Slowest step rate, in steps per second: one step every 100 s.
+pub const MIN_STEP_RATE: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StepMode {
+    /// Two coils on at a time: full torque.
+    #[default]
+    Full,
+    /// One and two coils in turn: twice the steps, smoother, a little less
+    /// torque on the one-coil steps.
+    Half,
+}
+
+impl StepMode {
+    fn sequence(self) -> &'static [u8] {
+        match self {
+            StepMode::Full => &FULL_STEPS,
+            StepMode::Half => &HALF_STEPS,
+        }
+    }
+}
+
+impl FromStr for StepMode {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(StepMode::Full),
+            "half" => Ok(StepMode::Half),
+            other => Err(format!("unknown step mode '{}' (full, half)", other).into()),
+        }
+    }
+}
+
+impl fmt::Display for StepMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            StepMode::Full => "full",
+            StepMode::Half => "half",
+        })
+    }
+}
+
+/// A trapezoidal speed profile: from `start_speed`, up at `acceleration`
+/// to `max_speed`, and down again to stop on the last step. Short moves
+/// turn around before reaching full speed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ramp {
+    /// Steps per second.
+    pub start_speed: f64,
+    /// Steps per second.
+    pub max_speed: f64,
+    /// Steps per second per second.
+    pub acceleration: f64,
+}
+
+impl Default for Ramp {
+    /// Safe for a 28BYJ-48 in half steps.
+    fn default() -> Self {
+        Ramp {
+            start_speed: 100.0,
+            max_speed: 500.0,
+            acceleration: 1000.0,
+        }
+    }
+}
+
+impl Ramp {
+    /// No ramp: every step at `speed`.
+    pub fn constant(speed: f64) -> Self {
+        Ramp {
+            start_speed: speed,
+            max_speed: speed,
+            acceleration: f64::INFINITY,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let speed = |v: f64| v.is_finite() && v >= MIN_STEP_RATE;
+        if !speed(self.start_speed) || !speed(self.max_speed) {
+            return Err(format!("stepper speeds must be at least {} steps/s", MIN_STEP_RATE).into());
+        }
+        if self.acceleration.is_nan() || self.acceleration <= 0.0 {
+            return Err("stepper acceleration must be above 0".into());
+        }
+        if self.start_speed > self.max_speed {
+            return Err(format!("start speed {} is above the top speed {}", self.start_speed, self.max_speed).into());
+        }
+        Ok(())
+    }
+
+    /// Time between step `step` and the next, of a `total`-step move.
+    pub fn interval(&self, step: u64, total: u64) -> Duration {
+        // v² = v0² + 2as, from whichever end of the move is nearer
+        let from_end = step.min(total.saturating_sub(step + 1));
+        if from_end == 0 {
+            return Duration::from_secs_f64(1.0 / self.start_speed);
+        }
+        let speed = (self.start_speed.powi(2) + 2.0 * self.acceleration * from_end as f64).sqrt();
+        Duration::from_secs_f64(1.0 / speed.min(self.max_speed))
+    }
+}
+
+/// A stepper on four pins, one per coil end: IN1-IN4 on a ULN2003 board.
+/// The position counts steps in the current mode from where it started.
+pub struct Stepper<P> {
+    pins: [P; 4],
+    mode: StepMode,
+    ramp: Ramp,
+    phase: usize,
+    position: i64,
+    delay: PreciseDelay,
+}
+
+impl Stepper<GpioPin> {
+    /// BCM pins for IN1 to IN4, in that order.
+    pub fn from_gpio(pins: [u8; 4]) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let open = |pin: u8| -> Result<GpioPin, Box<dyn Error>> {
+            Ok(gpio.get(pin).map_err(|e| format!("stepper GPIO {}: {}", pin, e))?.into_output_low())
+        };
+        Stepper::new([open(pins[0])?, open(pins[1])?, open(pins[2])?, open(pins[3])?])
+    }
+}
+
+impl<P: OutputPin> Stepper<P>
+where
+    P::Error: Error + 'static,
+{
+    /// Coils off until the first step.
+    pub fn new(pins: [P; 4]) -> Result<Self, Box<dyn Error>> {
+        let mut stepper = Stepper {
+            pins,
+            mode: StepMode::default(),
+            ramp: Ramp::default(),
+            phase: 0,
+            position: 0,
+            delay: PreciseDelay::default(),
+        };
+        stepper.energize(0)?;
+        Ok(stepper)
+    }
+
+    /// Switching keeps the rotor where it is, but the position counts steps
+    /// of the new size from then on.
+    pub fn set_mode(&mut self, mode: StepMode) {
+        if mode != self.mode {
+            // The full-step patterns are the odd half-step ones
+            self.phase = match mode {
+                StepMode::Half => self.phase * 2 + 1,
+                StepMode::Full => self.phase / 2,
+            };
+            self.mode = mode;
+        }
+    }
+
+    pub fn mode(&self) -> StepMode {
+        self.mode
+    }
+
+    pub fn set_ramp(&mut self, ramp: Ramp) -> Result<(), Box<dyn Error>> {
+        ramp.validate()?;
+        self.ramp = ramp;
+        Ok(())
+    }
+
+    pub fn ramp(&self) -> Ramp {
+        self.ramp
+    }
+
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    /// Call where the rotor is now `position`, e.g. 0 at a home switch.
+    pub fn set_position(&mut self, position: i64) {
+        self.position = position;
+    }
+
+    /// One step, forwards or back, with no delay.
+    pub fn step(&mut self, forward: bool) -> Result<(), Box<dyn Error>> {
+        let len = self.mode.sequence().len();
+        self.phase = if forward { (self.phase + 1) % len } else { (self.phase + len - 1) % len };
+        self.position += if forward { 1 } else { -1 };
+        self.energize(self.mode.sequence()[self.phase])
+    }
+
+    /// Move `steps` (negative is backwards) on the ramp, stopping early if
+    /// `stop` is set. Returns the steps actually made.
+    pub fn move_by(&mut self, steps: i64, stop: &AtomicBool) -> Result<i64, Box<dyn Error>> {
+        let total = steps.unsigned_abs();
+        let mut deadline = Instant::now();
+        for n in 0..total {
+            if stop.load(Ordering::Relaxed) {
+                return Ok(n as i64 * steps.signum());
+            }
+            self.step(steps > 0)?;
+            // Deadlines from the first step, so the time a step takes doesn't add up
+            deadline += self.ramp.interval(n, total);
+            self.delay.until(deadline);
+        }
+        Ok(steps)
+    }
+
+    pub fn move_to(&mut self, position: i64, stop: &AtomicBool) -> Result<i64, Box<dyn Error>> {
+        self.move_by(position - self.position, stop)
+    }
+
+    /// Coils off: no holding torque and no current, so nothing runs hot.
+    pub fn power_down(&mut self) -> Result<(), Box<dyn Error>> {
+        self.energize(0)
+    }
+
+    /// Coils back on at the current step, holding the rotor.
+    pub fn hold(&mut self) -> Result<(), Box<dyn Error>> {
+        self.energize(self.mode.sequence()[self.phase])
+    }
+
+    pub fn release(self) -> [P; 4] {
+        self.pins
+    }
+
+    fn energize(&mut self, coils: u8) -> Result<(), Box<dyn Error>> {
+        for (n, pin) in self.pins.iter_mut().enumerate() {
+            if coils & (1 << n) != 0 {
+                pin.set_high()?;
+            } else {
+                pin.set_low()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_validate() {
+        assert!(Ramp::default().validate().is_ok());
+        assert!(Ramp::constant(MIN_STEP_RATE).validate().is_ok());
+        for speed in [0.0, 1e-300, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(Ramp::constant(speed).validate().is_err(), "{}", speed);
+        }
+        assert!(Ramp { start_speed: 1e-300, ..Ramp::default() }.validate().is_err());
+        assert!(Ramp { start_speed: 600.0, ..Ramp::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn ramp_speeds_up_and_down() {
+        let ramp = Ramp::default();
+        assert_eq!(ramp.interval(0, 1000), Duration::from_millis(10));
+        assert_eq!(ramp.interval(500, 1000), Duration::from_millis(2));
+        assert_eq!(ramp.interval(999, 1000), Duration::from_millis(10));
+    }
+}
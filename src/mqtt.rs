@@ -0,0 +1,377 @@
+//! MQTT publishing of sensor readings and bus events, e.g. to feed Home
+//! Assistant straight from the Pi.
+//!
+//! Configured in the `[mqtt]` section; `{device}`, `{quantity}` and
+//! `{event}` in topics are filled in per message:
+//!
+//! ```toml
+//! [mqtt]
+//! broker = "homeassistant.local:1883"
+//! client_id = "bench-pi"
+//! username = "rpi"
+//! password = "secret"
+//! readings_topic = "bench/{device}/{quantity}"
+//! events_topic = "bench/bus/{event}"
+//! ```
+//!
+//! [`Alert`]s go to the events topic too, as event `alert`.
+//!
+//! Messages go out at QoS 0 over plain TCP: a reading that's lost is
+//! replaced by the next one. The client reconnects on the next publish
+//! after a failure.
+
+mod packet;
+
+use crate::address::Address;
+use crate::alert::Alert;
+use crate::parse::serde_helpers;
+use crate::rules::RuleEvent;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Time allowed for connecting and for the broker's CONNACK.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    /// `host` or `host:port`; the port defaults to 1883.
+    pub broker: String,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default = "default_keep_alive", deserialize_with = "serde_helpers::duration")]
+    pub keep_alive: Duration,
+    #[serde(default = "default_readings_topic")]
+    pub readings_topic: String,
+    #[serde(default = "default_events_topic")]
+    pub events_topic: String,
+    /// Retain readings so new subscribers see the latest value at once.
+    #[serde(default)]
+    pub retain_readings: bool,
+}
+
+fn default_client_id() -> String {
+    "rpi_peripherals".to_string()
+}
+
+fn default_keep_alive() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_readings_topic() -> String {
+    "rpi_peripherals/{device}/{quantity}".to_string()
+}
+
+fn default_events_topic() -> String {
+    "rpi_peripherals/events/{event}".to_string()
+}
+
+impl MqttConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.broker.trim().is_empty() {
+            return Err("mqtt.broker is empty".into());
+        }
+        if self.client_id.is_empty() || self.client_id.len() > 23 {
+            return Err(format!("mqtt.client_id must be 1-23 characters, got '{}'", self.client_id).into());
+        }
+        if self.password.is_some() && self.username.is_none() {
+            return Err("mqtt.password needs a username".into());
+        }
+        if self.keep_alive.as_secs() > u16::MAX as u64 {
+            return Err(format!("mqtt.keep_alive is over {} seconds", u16::MAX).into());
+        }
+        for (key, topic) in [("readings_topic", &self.readings_topic), ("events_topic", &self.events_topic)] {
+            if topic.is_empty() || topic.contains(['+', '#']) {
+                return Err(format!("mqtt.{} '{}' is not a topic to publish to", key, topic).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn address(&self) -> String {
+        if self.broker.contains(':') {
+            self.broker.clone()
+        } else {
+            format!("{}:1883", self.broker)
+        }
+    }
+}
+
+/// Something that happened on the bus, as published to the events topic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BusEvent {
+    Appeared { address: Address },
+    Disappeared { address: Address },
+    /// `nacks` failed transactions within `window_secs`.
+    NackStorm { address: Address, nacks: usize, window_secs: u64 },
+}
+
+impl BusEvent {
+    /// The `{event}` topic segment.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BusEvent::Appeared { .. } => "appeared",
+            BusEvent::Disappeared { .. } => "disappeared",
+            BusEvent::NackStorm { .. } => "nack_storm",
+        }
+    }
+}
+
+impl fmt::Display for BusEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusEvent::Appeared { address } => write!(f, "{} appeared", address),
+            BusEvent::Disappeared { address } => write!(f, "{} disappeared", address),
+            BusEvent::NackStorm { address, nacks, window_secs } => {
+                write!(f, "{} NACKed {} times in {}s", address, nacks, window_secs)
+            }
+        }
+    }
+}
+
+/// Turns scans and transaction outcomes into [`BusEvent`]s.
+pub struct EventDetector {
+    present: Option<BTreeSet<Address>>,
+    nack_threshold: usize,
+    window: Duration,
+    nacks: HashMap<Address, VecDeque<Instant>>,
+    /// Addresses already reported as storming, until they calm down.
+    storming: BTreeSet<Address>,
+}
+
+impl EventDetector {
+    /// Report a NACK storm at `nack_threshold` failures within `window`.
+    pub fn new(nack_threshold: usize, window: Duration) -> Self {
+        EventDetector {
+            present: None,
+            nack_threshold: nack_threshold.max(1),
+            window,
+            nacks: HashMap::new(),
+            storming: BTreeSet::new(),
+        }
+    }
+
+    /// Compare a scan with the previous one. The first scan is the baseline
+    /// and reports nothing.
+    pub fn scanned(&mut self, found: &[Address]) -> Vec<BusEvent> {
+        let now: BTreeSet<Address> = found.iter().copied().collect();
+        let events = match &self.present {
+            None => Vec::new(),
+            Some(before) => before
+                .difference(&now)
+                .map(|&address| BusEvent::Disappeared { address })
+                .chain(now.difference(before).map(|&address| BusEvent::Appeared { address }))
+                .collect(),
+        };
+        self.present = Some(now);
+        events
+    }
+
+    pub fn record(&mut self, address: Address, ok: bool) -> Option<BusEvent> {
+        self.record_at(address, ok, Instant::now())
+    }
+
+    /// Feed one transaction outcome; a storm is reported once, then again
+    /// only after a window without reaching the threshold.
+    pub fn record_at(&mut self, address: Address, ok: bool, now: Instant) -> Option<BusEvent> {
+        let nacks = self.nacks.entry(address).or_default();
+        if !ok {
+            nacks.push_back(now);
+        }
+        while nacks.front().is_some_and(|&t| now.duration_since(t) > self.window) {
+            nacks.pop_front();
+        }
+        if nacks.len() < self.nack_threshold {
+            self.storming.remove(&address);
+            return None;
+        }
+        if !self.storming.insert(address) {
+            return None;
+        }
+        Some(BusEvent::NackStorm {
+            address,
+            nacks: nacks.len(),
+            window_secs: self.window.as_secs(),
+        })
+    }
+}
+
+/// A connected QoS 0 client.
+pub struct MqttClient {
+    stream: TcpStream,
+    keep_alive: Duration,
+    last_sent: Instant,
+}
+
+impl MqttClient {
+    pub fn connect(config: &MqttConfig) -> Result<Self, Box<dyn Error>> {
+        let address = config.address();
+        let socket = std::net::ToSocketAddrs::to_socket_addrs(&address)
+            .map_err(|e| format!("cannot resolve MQTT broker {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("MQTT broker {} has no address", address))?;
+        let mut stream = TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT)
+            .map_err(|e| format!("cannot connect to MQTT broker {}: {}", address, e))?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+
+        let connect = packet::Connect {
+            client_id: &config.client_id,
+            username: config.username.as_deref(),
+            password: config.password.as_deref(),
+            keep_alive_secs: config.keep_alive.as_secs() as u16,
+        };
+        stream.write_all(&connect.encode()?)?;
+        let mut connack = [0; 4];
+        stream
+            .read_exact(&mut connack)
+            .map_err(|e| format!("no CONNACK from MQTT broker {}: {}", address, e))?;
+        packet::check_connack(connack)?;
+
+        Ok(MqttClient {
+            stream,
+            keep_alive: config.keep_alive,
+            last_sent: Instant::now(),
+        })
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), Box<dyn Error>> {
+        self.send(&packet::publish(topic, payload, retain)?)
+    }
+
+    /// Send a PINGREQ if nothing went out for half the keep-alive, so an
+    /// idle publisher isn't dropped by the broker.
+    pub fn keep_alive(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.keep_alive.is_zero() && self.last_sent.elapsed() >= self.keep_alive / 2 {
+            self.send(&packet::PINGREQ)?;
+        }
+        Ok(())
+    }
+
+    pub fn disconnect(mut self) -> Result<(), Box<dyn Error>> {
+        self.send(&packet::DISCONNECT)
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.stream.write_all(bytes)?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+}
+
+/// Publishes readings and events to the configured topics, connecting
+/// lazily and reconnecting after a failed publish.
+pub struct Publisher {
+    config: MqttConfig,
+    client: Option<MqttClient>,
+}
+
+impl Publisher {
+    pub fn new(config: MqttConfig) -> Result<Self, Box<dyn Error>> {
+        config.validate()?;
+        Ok(Publisher { config, client: None })
+    }
+
+    pub fn config(&self) -> &MqttConfig {
+        &self.config
+    }
+
+    /// Publish `{"device", "quantity", "value", "unit", "timestamp"}`.
+    pub fn reading(&mut self, device: &str, quantity: &str, value: f64, unit: &str) -> Result<(), Box<dyn Error>> {
+        let topic = self
+            .config
+            .readings_topic
+            .replace("{device}", device)
+            .replace("{quantity}", quantity);
+        let payload = json!({
+            "device": device,
+            "quantity": quantity,
+            "value": value,
+            "unit": unit,
+            "timestamp": unix_time(),
+        });
+        let retain = self.config.retain_readings;
+        self.publish(&topic, &payload.to_string(), retain)
+    }
+
+    /// Publish the event as JSON with a `timestamp` added.
+    pub fn event(&mut self, event: &BusEvent) -> Result<(), Box<dyn Error>> {
+        let topic = self.config.events_topic.replace("{event}", event.name());
+        let mut payload = serde_json::to_value(event)?;
+        payload["timestamp"] = json!(unix_time());
+        self.publish(&topic, &payload.to_string(), false)
+    }
+
+    /// Publish an alert to the events topic as event `alert`, with its key,
+    /// severity, message and a `timestamp`.
+    pub fn alert(&mut self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let topic = self.config.events_topic.replace("{event}", "alert");
+        let mut payload = serde_json::to_value(alert)?;
+        payload["event"] = json!("alert");
+        payload["timestamp"] = json!(unix_time());
+        self.publish(&topic, &payload.to_string(), false)
+    }
+
+    /// Publish a rule firing or clearing to the events topic as event
+    /// `rule`, with its name, state, value and a `timestamp`.
+    pub fn rule(&mut self, event: &RuleEvent) -> Result<(), Box<dyn Error>> {
+        let topic = self.config.events_topic.replace("{event}", "rule");
+        let payload = json!({
+            "event": "rule",
+            "rule": event.rule,
+            "state": event.state.to_string(),
+            "value": event.value,
+            "timestamp": unix_time(),
+        });
+        self.publish(&topic, &payload.to_string(), false)
+    }
+
+    /// Keep an idle connection alive; call this from the polling loop.
+    pub fn tick(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = match &mut self.client {
+            Some(client) => client.keep_alive(),
+            None => Ok(()),
+        };
+        if result.is_err() {
+            self.client = None;
+        }
+        result
+    }
+
+    pub fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.client.take() {
+            Some(client) => client.disconnect(),
+            None => Ok(()),
+        }
+    }
+
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<(), Box<dyn Error>> {
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => self.client.insert(MqttClient::connect(&self.config)?),
+        };
+        let result = client.publish(topic, payload.as_bytes(), retain);
+        if result.is_err() {
+            // Dropped connection; the next publish reconnects
+            self.client = None;
+        }
+        result
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
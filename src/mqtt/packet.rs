@@ -0,0 +1,100 @@
+//! MQTT 3.1.1 packet encoding for the handful of packets a QoS 0
+//! publisher needs.
+
+use std::error::Error;
+
+pub const PINGREQ: [u8; 2] = [0xC0, 0x00];
+pub const DISCONNECT: [u8; 2] = [0xE0, 0x00];
+
+/// Largest value the four-byte remaining-length field can carry.
+const MAX_REMAINING: usize = 268_435_455;
+
+pub struct Connect<'a> {
+    pub client_id: &'a str,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    pub keep_alive_secs: u16,
+}
+
+impl Connect<'_> {
+    pub fn encode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut body = Vec::new();
+        string(&mut body, "MQTT")?;
+        body.push(4); // protocol level 3.1.1
+        let mut flags = 0x02; // clean session
+        if self.username.is_some() {
+            flags |= 0x80;
+        }
+        if self.password.is_some() {
+            flags |= 0x40;
+        }
+        body.push(flags);
+        body.extend_from_slice(&self.keep_alive_secs.to_be_bytes());
+        string(&mut body, self.client_id)?;
+        if let Some(username) = self.username {
+            string(&mut body, username)?;
+        }
+        if let Some(password) = self.password {
+            string(&mut body, password)?;
+        }
+        packet(0x10, &body)
+    }
+}
+
+/// A QoS 0 PUBLISH, which needs no packet id and gets no acknowledgement.
+pub fn publish(topic: &str, payload: &[u8], retain: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+    if topic.is_empty() || topic.contains(['+', '#']) {
+        return Err(format!("invalid topic to publish to: '{}'", topic).into());
+    }
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+    string(&mut body, topic)?;
+    body.extend_from_slice(payload);
+    packet(if retain { 0x31 } else { 0x30 }, &body)
+}
+
+/// Check the 4-byte CONNACK the broker answers a CONNECT with.
+pub fn check_connack(bytes: [u8; 4]) -> Result<(), Box<dyn Error>> {
+    if bytes[..2] != [0x20, 0x02] {
+        return Err(format!("expected CONNACK, got {:02X?}", bytes).into());
+    }
+    let reason = match bytes[3] {
+        0 => return Ok(()),
+        1 => "unacceptable protocol version",
+        2 => "client id rejected",
+        3 => "server unavailable",
+        4 => "bad username or password",
+        5 => "not authorized",
+        _ => "unknown return code",
+    };
+    Err(format!("broker refused the connection: {} ({})", reason, bytes[3]).into())
+}
+
+fn packet(header: u8, body: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if body.len() > MAX_REMAINING {
+        return Err(format!("MQTT packet of {} bytes is too large", body.len()).into());
+    }
+    let mut out = Vec::with_capacity(body.len() + 5);
+    out.push(header);
+    // Remaining length: 7 bits per byte, high bit set on all but the last
+    let mut remaining = body.len();
+    loop {
+        let mut byte = (remaining % 128) as u8;
+        remaining /= 128;
+        if remaining > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if remaining == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    Ok(out)
+}
+
+fn string(out: &mut Vec<u8>, s: &str) -> Result<(), Box<dyn Error>> {
+    let len = u16::try_from(s.len()).map_err(|_| format!("MQTT string of {} bytes is too long", s.len()))?;
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+    Ok(())
+}
@@ -0,0 +1,155 @@
+//! TCA9548A 1-to-8 I2C multiplexer.
+//!
+//! The mux owns the upstream bus and hands out [`MuxChannel`] handles. Each
+//! handle implements [`I2c`] and switches the mux to its channel before every
+//! transaction, so two devices with the same address on different channels
+//! can be driven side by side.
+
+use crate::address::{Address, AddressedI2c};
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+/// Address with A0-A2 tied low.
+pub const DEFAULT_ADDRESS: u8 = 0x70;
+
+/// Number of downstream channels on the TCA9548A.
+pub const CHANNELS: u8 = 8;
+
+struct MuxState<I2C> {
+    i2c: I2C,
+    /// Control register contents as last written, `None` if unknown.
+    selected: Option<u8>,
+}
+
+pub struct Tca9548a<I2C> {
+    state: Arc<Mutex<MuxState<I2C>>>,
+    address: u8,
+}
+
+impl<I2C> Tca9548a<I2C>
+where
+    I2C: I2c,
+    I2C::Error: Error + 'static,
+{
+    pub fn new(i2c: I2C, address: u8) -> Result<Self, Box<dyn Error>> {
+        if !(0x70..=0x77).contains(&address) {
+            return Err(format!("0x{:02X} is not a TCA9548A address (0x70-0x77)", address).into());
+        }
+        Ok(Tca9548a {
+            state: Arc::new(Mutex::new(MuxState { i2c, selected: None })),
+            address,
+        })
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Bus handle scoped to one downstream channel (0-7).
+    pub fn channel(&self, channel: u8) -> Result<MuxChannel<I2C>, Box<dyn Error>> {
+        if channel >= CHANNELS {
+            return Err(format!("TCA9548A has no channel {}", channel).into());
+        }
+        Ok(self.handle(1 << channel))
+    }
+
+    /// Bus handle with every channel switched off, reaching only the devices
+    /// that sit on the upstream side of the mux.
+    pub fn upstream(&self) -> MuxChannel<I2C> {
+        self.handle(0)
+    }
+
+    /// Write the control register directly; each set bit enables a channel.
+    pub fn select_mask(&self, mask: u8) -> Result<(), Box<dyn Error>> {
+        let mut state = self.lock();
+        select(&mut state, self.address, mask)?;
+        Ok(())
+    }
+
+    /// Read back the control register from the chip.
+    pub fn read_mask(&self) -> Result<u8, Box<dyn Error>> {
+        let mut state = self.lock();
+        let mut buf = [0u8];
+        state.i2c.read(self.address, &mut buf)?;
+        state.selected = Some(buf[0]);
+        Ok(buf[0])
+    }
+
+    fn handle(&self, mask: u8) -> MuxChannel<I2C> {
+        MuxChannel {
+            state: Arc::clone(&self.state),
+            mux_address: self.address,
+            mask,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MuxState<I2C>> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn select<I2C: I2c>(state: &mut MuxState<I2C>, address: u8, mask: u8) -> Result<(), I2C::Error> {
+    if state.selected == Some(mask) {
+        return Ok(());
+    }
+    // Forget the cached value first so a failed write forces a retry next time.
+    state.selected = None;
+    state.i2c.write(address, &[mask])?;
+    state.selected = Some(mask);
+    Ok(())
+}
+
+/// One downstream channel of a [`Tca9548a`].
+pub struct MuxChannel<I2C> {
+    state: Arc<Mutex<MuxState<I2C>>>,
+    mux_address: u8,
+    mask: u8,
+}
+
+impl<I2C> MuxChannel<I2C> {
+    /// Channel number, or `None` for the upstream handle.
+    pub fn channel(&self) -> Option<u8> {
+        (self.mask != 0).then(|| self.mask.trailing_zeros() as u8)
+    }
+
+    pub fn mux_address(&self) -> u8 {
+        self.mux_address
+    }
+}
+
+impl<I2C: I2c> ErrorType for MuxChannel<I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> I2c for MuxChannel<I2C> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        select(&mut state, self.mux_address, self.mask)?;
+        state.i2c.transaction(address, operations)
+    }
+}
+
+impl<I2C> AddressedI2c for MuxChannel<I2C>
+where
+    I2C: I2c + AddressedI2c,
+    I2C::Error: Error + 'static,
+{
+    fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        select(&mut state, self.mux_address, self.mask)?;
+        state.i2c.transaction_at(address, operations)
+    }
+
+    fn repeated_start(&self, address: Address) -> bool {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).i2c.repeated_start(address)
+    }
+}
@@ -0,0 +1,175 @@
+//! Development notifications: forward alarms and device errors to the
+//! developer's desktop while iterating on a Pi over SSH.
+//!
+//! Two sinks are provided, both shelling out so the crate stays free of
+//! D-Bus and TLS dependencies:
+//!
+//! - [`DbusSink`] runs `notify-send`. With `ssh -R` forwarding the desktop's
+//!   session bus and `DBUS_SESSION_BUS_ADDRESS` pointing at it, the popup
+//!   appears on the laptop.
+//! - [`NtfySink`] posts to an ntfy topic with `curl`, for phones and laptops
+//!   subscribed to it.
+//!
+//! Set `RPI_PERIPHERALS_NOTIFY=dbus` or `RPI_PERIPHERALS_NOTIFY=ntfy:<url>`
+//! and use [`from_env`] to pick one up.
+
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Environment variable selecting the dev notification sink.
+pub const NOTIFY_ENV: &str = "RPI_PERIPHERALS_NOTIFY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub priority: Priority,
+}
+
+impl Notification {
+    pub fn new(title: impl Into<String>, body: impl Into<String>, priority: Priority) -> Self {
+        Notification {
+            title: title.into(),
+            body: body.into(),
+            priority,
+        }
+    }
+}
+
+pub trait NotificationSink: Send + Sync {
+    fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>>;
+}
+
+/// Desktop popups through `notify-send`.
+pub struct DbusSink;
+
+impl NotificationSink for DbusSink {
+    fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        let urgency = match notification.priority {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "critical",
+        };
+        run(Command::new("notify-send")
+            .args(["--app-name", "rpi_peripherals", "--urgency", urgency])
+            // A title or body starting with '-' is text, not an option
+            .arg("--")
+            .arg(&notification.title)
+            .arg(&notification.body))
+    }
+}
+
+/// Pushes to an ntfy topic URL such as `https://ntfy.sh/my-bench`.
+pub struct NtfySink {
+    url: String,
+}
+
+impl NtfySink {
+    pub fn new(url: impl Into<String>) -> Self {
+        NtfySink { url: url.into() }
+    }
+}
+
+impl NotificationSink for NtfySink {
+    fn notify(&self, notification: &Notification) -> Result<(), Box<dyn Error>> {
+        let priority = match notification.priority {
+            Priority::Low => "2",
+            Priority::Normal => "3",
+            Priority::High => "5",
+        };
+        // The body goes on stdin as it is: as an argument, curl would strip
+        // its newlines and read one starting with '@' as a file to upload.
+        // A header can't hold a line break, so the title loses them.
+        let mut curl = Command::new("curl");
+        curl.args(["-fsS", "--max-time", "10"])
+            .arg("-H")
+            .arg(format!("Title: {}", notification.title.replace(['\r', '\n'], " ")))
+            .arg("-H")
+            .arg(format!("Priority: {}", priority))
+            .args(["--data-binary", "@-"])
+            .arg(&self.url);
+        run_with_input(&mut curl, notification.body.as_bytes())
+    }
+}
+
+fn run(command: &mut Command) -> Result<(), Box<dyn Error>> {
+    run_with_input(command, &[])
+}
+
+/// Run `command` with `input` on its stdin, failing with its stderr.
+fn run_with_input(command: &mut Command, input: &[u8]) -> Result<(), Box<dyn Error>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run {}: {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Dropped at the end of the block, so the program sees end of input.
+        // One that quit without reading it says why on stderr.
+        match stdin.write_all(input) {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+                return Err(format!("could not write to {}: {}", program, e).into());
+            }
+            _ => {}
+        }
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("could not run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Build the sink named by a `dbus` / `ntfy:<url>` spec.
+pub fn parse_sink(spec: &str) -> Result<Box<dyn NotificationSink>, Box<dyn Error>> {
+    match spec.split_once(':') {
+        _ if spec == "dbus" => Ok(Box::new(DbusSink)),
+        Some(("ntfy", url)) if !url.is_empty() => Ok(Box::new(NtfySink::new(url))),
+        _ => Err(format!("unknown notification sink '{}' (expected dbus or ntfy:<url>)", spec).into()),
+    }
+}
+
+/// The sink configured through [`NOTIFY_ENV`], if any.
+pub fn from_env() -> Result<Option<Box<dyn NotificationSink>>, Box<dyn Error>> {
+    match std::env::var(NOTIFY_ENV) {
+        Ok(spec) if !spec.trim().is_empty() => parse_sink(spec.trim()).map(Some),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_reaches_stdin_verbatim() {
+        let mut check = Command::new("sh");
+        check.args(["-c", r#"[ "$(cat)" = "$(printf '@/etc/passwd\nsecond line')" ]"#]);
+        run_with_input(&mut check, b"@/etc/passwd\nsecond line").unwrap();
+    }
+
+    #[test]
+    fn failure_carries_stderr() {
+        let mut fail = Command::new("sh");
+        fail.args(["-c", "echo broken >&2; exit 1"]);
+        let e = run_with_input(&mut fail, b"ignored").unwrap_err();
+        assert_eq!(e.to_string(), "sh failed: broken");
+    }
+}
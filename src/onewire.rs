@@ -0,0 +1,233 @@
+//! DS18B20 temperature sensors through the kernel's 1-Wire support.
+//!
+//! With `dtoverlay=w1-gpio` (GPIO 4 by default) the kernel scans the bus and
+//! lists each sensor under `/sys/bus/w1/devices` by its ROM code, e.g.
+//! `28-3c01b556a1ff`. Reading `w1_slave` starts a conversion and returns the
+//! scratchpad; [`Ds18b20::read`] checks its CRC itself rather than trusting
+//! the kernel's verdict, and retries a few times, since long runs of cable
+//! pick up noise.
+//!
+//! ```no_run
+//! use rpi_peripherals::onewire;
+//!
+//! for sensor in onewire::sensors()? {
+//!     println!("{}: {:.2} °C", sensor.id(), sensor.temperature()?);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::crc;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const W1_DEVICES: &str = "/sys/bus/w1/devices";
+
+/// Family codes of the sensors that share the DS18B20's scratchpad layout:
+/// DS18B20, DS1822, DS1825 and DS28EA00.
+pub const FAMILIES: [u8; 4] = [0x28, 0x22, 0x3B, 0x42];
+
+/// Attempts per [`Ds18b20::read`] before a bad CRC is reported.
+const READ_ATTEMPTS: usize = 3;
+
+/// What the scratchpad holds after power-up, before any conversion: seen
+/// when the sensor browns out mid-read.
+const POWER_ON_RESET: i16 = 0x0550;
+
+/// The nine scratchpad bytes: temperature LSB/MSB, alarm high and low,
+/// configuration, three reserved, CRC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scratchpad(pub [u8; 9]);
+
+impl Scratchpad {
+    /// The bytes of a `w1_slave` read, whichever of its two lines they are
+    /// taken from.
+    pub fn parse(w1_slave: &str) -> Result<Self, Box<dyn Error>> {
+        let line = w1_slave.lines().next().ok_or("empty w1_slave")?;
+        let bytes: Vec<u8> = line
+            .split_whitespace()
+            .take_while(|field| *field != ":")
+            .map(|field| u8::from_str_radix(field, 16))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("malformed w1_slave line '{}': {}", line, e))?;
+        let bytes: [u8; 9] = bytes
+            .try_into()
+            .map_err(|b: Vec<u8>| format!("w1_slave has {} scratchpad bytes, expected 9", b.len()))?;
+        Ok(Scratchpad(bytes))
+    }
+
+    pub fn crc_ok(&self) -> bool {
+        crc::crc8_maxim(&self.0[..8]) == self.0[8]
+    }
+
+    /// Raw reading in 1/16 °C.
+    pub fn raw(&self) -> i16 {
+        i16::from_le_bytes([self.0[0], self.0[1]])
+    }
+
+    pub fn celsius(&self) -> f64 {
+        f64::from(self.raw()) / 16.0
+    }
+
+    /// Conversion resolution in bits, 9 to 12.
+    pub fn resolution(&self) -> u8 {
+        9 + (self.0[4] >> 5 & 0b11)
+    }
+}
+
+/// One checked conversion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub celsius: f64,
+    pub resolution: u8,
+    pub scratchpad: Scratchpad,
+}
+
+/// A sensor's scratchpad failed its CRC on every attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrcMismatch {
+    pub id: String,
+    pub scratchpad: Scratchpad,
+}
+
+impl fmt::Display for CrcMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.scratchpad.0.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(
+            f,
+            "{}: scratchpad CRC failed {} times (last {}); check the pull-up and cable length",
+            self.id,
+            READ_ATTEMPTS,
+            bytes.join(" ")
+        )
+    }
+}
+
+impl Error for CrcMismatch {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ds18b20 {
+    id: String,
+    path: PathBuf,
+}
+
+impl Ds18b20 {
+    /// The sensor with ROM code `id`, e.g. `28-3c01b556a1ff`.
+    pub fn open(id: &str) -> Result<Self, Box<dyn Error>> {
+        Ds18b20::open_in(Path::new(W1_DEVICES), id)
+    }
+
+    /// Like [`Ds18b20::open`] under another sysfs root.
+    pub fn open_in(root: &Path, id: &str) -> Result<Self, Box<dyn Error>> {
+        let path = root.join(id);
+        if !path.join("w1_slave").exists() {
+            return Err(format!("no 1-Wire sensor {} under {}", id, root.display()).into());
+        }
+        if !family(id).is_some_and(|f| FAMILIES.contains(&f)) {
+            return Err(format!("{} is not a DS18B20-family sensor", id).into());
+        }
+        Ok(Ds18b20 { id: id.to_string(), path })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Convert and read the scratchpad, retrying on a bad CRC. Takes up to
+    /// 750 ms per attempt at 12 bits.
+    pub fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        let mut last = None;
+        for _ in 0..READ_ATTEMPTS {
+            let text = fs::read_to_string(self.path.join("w1_slave")).map_err(|e| format!("{}: {}", self.id, e))?;
+            let scratchpad = Scratchpad::parse(&text).map_err(|e| format!("{}: {}", self.id, e))?;
+            if !scratchpad.crc_ok() || scratchpad.0.iter().all(|&b| b == 0) {
+                last = Some(scratchpad);
+                continue;
+            }
+            if scratchpad.raw() == POWER_ON_RESET {
+                return Err(format!("{}: reads the power-on 85 °C; the sensor reset during conversion (check its supply)", self.id).into());
+            }
+            return Ok(Reading {
+                celsius: scratchpad.celsius(),
+                resolution: scratchpad.resolution(),
+                scratchpad,
+            });
+        }
+        Err(Box::new(CrcMismatch {
+            id: self.id.clone(),
+            scratchpad: last.unwrap_or(Scratchpad([0; 9])),
+        }))
+    }
+
+    pub fn temperature(&self) -> Result<f64, Box<dyn Error>> {
+        Ok(self.read()?.celsius)
+    }
+
+    /// Resolution in bits, from the kernel's `resolution` attribute or
+    /// else a scratchpad read.
+    pub fn resolution(&self) -> Result<u8, Box<dyn Error>> {
+        match fs::read_to_string(self.path.join("resolution")) {
+            Ok(text) => Ok(text.trim().parse().map_err(|_| format!("{}: bad resolution '{}'", self.id, text.trim()))?),
+            Err(_) => Ok(self.read()?.resolution),
+        }
+    }
+
+    /// Set the resolution: 9 bits converts in 94 ms to 0.5 °C, 12 bits in
+    /// 750 ms to 0.0625 °C. Lost at power-off unless [`Ds18b20::save`]d.
+    /// Needs write access to sysfs, so usually root.
+    pub fn set_resolution(&self, bits: u8) -> Result<(), Box<dyn Error>> {
+        if !(9..=12).contains(&bits) {
+            return Err(format!("resolution must be 9 to 12 bits, not {}", bits).into());
+        }
+        // Kernels before 5.10 only take it through w1_slave
+        let attribute = match self.path.join("resolution") {
+            path if path.exists() => path,
+            _ => self.path.join("w1_slave"),
+        };
+        fs::write(&attribute, bits.to_string()).map_err(|e| format!("{}: {}: {}", self.id, attribute.display(), e))?;
+        Ok(())
+    }
+
+    /// Copy the scratchpad's configuration to the sensor's EEPROM, so the
+    /// resolution survives a power cycle.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = self.path.join("eeprom_cmd");
+        fs::write(&path, "save").map_err(|e| format!("{}: {}: {}", self.id, path.display(), e))?;
+        Ok(())
+    }
+}
+
+/// Family code from the first two characters of a ROM code id.
+pub fn family(id: &str) -> Option<u8> {
+    let (family, serial) = id.split_once('-')?;
+    if serial.len() != 12 {
+        return None;
+    }
+    u8::from_str_radix(family, 16).ok()
+}
+
+/// Every DS18B20-family sensor the kernel has found, sorted by id.
+pub fn sensors() -> Result<Vec<Ds18b20>, Box<dyn Error>> {
+    sensors_in(Path::new(W1_DEVICES))
+}
+
+/// Like [`sensors`] under another sysfs root.
+pub fn sensors_in(root: &Path) -> Result<Vec<Ds18b20>, Box<dyn Error>> {
+    let entries = fs::read_dir(root).map_err(|e| {
+        format!(
+            "{}: {}; enable 1-Wire with dtoverlay=w1-gpio in /boot/firmware/config.txt",
+            root.display(),
+            e
+        )
+    })?;
+    let mut found = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if family(&name).is_some_and(|f| FAMILIES.contains(&f)) {
+            found.push(Ds18b20::open_in(root, &name)?);
+        }
+    }
+    found.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(found)
+}
@@ -0,0 +1,133 @@
+//! Parallel bus on plain GPIO: up to eight data lines and a strobe.
+//!
+//! [`ParallelBus::write`] puts a value on the data lines, waits the setup
+//! time, pulses the strobe and holds the data a little longer, which is all
+//! a write-only parallel peripheral needs: an HD44780 wired straight to the
+//! header ([`lcd::ParallelLcd`](crate::lcd::ParallelLcd)), a printer-port
+//! style device latching on /STROBE, or a row of '574 latches.
+//!
+//! The default timings are far above what the parts ask for (an HD44780
+//! wants 450 ns of E and 80 ns of setup); GPIO writes take a few hundred
+//! nanoseconds each anyway, so they cost little.
+
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::Gpio;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelConfig {
+    /// Printer ports latch on a low /STROBE; the HD44780's E is active high.
+    pub strobe_active_low: bool,
+    /// Data stable before the strobe.
+    pub setup: Duration,
+    /// Width of the strobe pulse.
+    pub pulse: Duration,
+    /// Data stable after the strobe.
+    pub hold: Duration,
+}
+
+impl Default for ParallelConfig {
+    fn default() -> Self {
+        ParallelConfig {
+            strobe_active_low: false,
+            setup: Duration::from_micros(1),
+            pulse: Duration::from_micros(1),
+            hold: Duration::from_micros(1),
+        }
+    }
+}
+
+pub struct ParallelBus<P> {
+    /// Least significant bit first.
+    data: Vec<P>,
+    strobe: P,
+    config: ParallelConfig,
+}
+
+impl ParallelBus<rppal::gpio::OutputPin> {
+    /// Take BCM pins for the data lines, least significant first, and the
+    /// strobe.
+    pub fn from_gpio(data: &[u8], strobe: u8, config: ParallelConfig) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let open = |pin: u8| -> Result<rppal::gpio::OutputPin, Box<dyn Error>> {
+            Ok(gpio.get(pin).map_err(|e| format!("parallel bus GPIO {}: {}", pin, e))?.into_output_low())
+        };
+        let data = data.iter().map(|&pin| open(pin)).collect::<Result<Vec<_>, _>>()?;
+        ParallelBus::new(data, open(strobe)?, config)
+    }
+}
+
+impl<P: OutputPin> ParallelBus<P>
+where
+    P::Error: Error + 'static,
+{
+    /// Drive `data` (1-8 lines, least significant first) and idle the strobe.
+    pub fn new(data: Vec<P>, strobe: P, config: ParallelConfig) -> Result<Self, Box<dyn Error>> {
+        if !(1..=8).contains(&data.len()) {
+            return Err(format!("a parallel bus has 1-8 data lines, not {}", data.len()).into());
+        }
+        let mut bus = ParallelBus { data, strobe, config };
+        bus.set_strobe(false)?;
+        Ok(bus)
+    }
+
+    /// Number of data lines.
+    pub fn width(&self) -> u8 {
+        self.data.len() as u8
+    }
+
+    pub fn config(&self) -> &ParallelConfig {
+        &self.config
+    }
+
+    /// Put the low [`width`](ParallelBus::width) bits of `value` on the data
+    /// lines without strobing.
+    pub fn set_data(&mut self, value: u8) -> Result<(), Box<dyn Error>> {
+        for (bit, pin) in self.data.iter_mut().enumerate() {
+            if value & (1 << bit) != 0 {
+                pin.set_high()?;
+            } else {
+                pin.set_low()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// One strobed write. Spins through the timings: a sleep would stretch
+    /// each microsecond to the scheduler's granularity.
+    pub fn write(&mut self, value: u8) -> Result<(), Box<dyn Error>> {
+        self.set_data(value)?;
+        spin(self.config.setup);
+        self.set_strobe(true)?;
+        spin(self.config.pulse);
+        self.set_strobe(false)?;
+        spin(self.config.hold);
+        Ok(())
+    }
+
+    pub fn write_all(&mut self, values: &[u8]) -> Result<(), Box<dyn Error>> {
+        values.iter().try_for_each(|&value| self.write(value))
+    }
+
+    /// The data pins, least significant first, and the strobe.
+    pub fn release(self) -> (Vec<P>, P) {
+        (self.data, self.strobe)
+    }
+
+    fn set_strobe(&mut self, active: bool) -> Result<(), Box<dyn Error>> {
+        if active != self.config.strobe_active_low {
+            self.strobe.set_high()?;
+        } else {
+            self.strobe.set_low()?;
+        }
+        Ok(())
+    }
+}
+
+fn spin(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        std::hint::spin_loop();
+    }
+}
@@ -0,0 +1,187 @@
+//! Parsing of human-friendly values shared by the CLI and the config file.
+
+use std::error::Error;
+use std::time::Duration;
+
+/// Parse `250ms`, `5s`, `2m`, `1h`, `10us` or a bare number of milliseconds.
+pub fn duration(s: &str) -> Result<Duration, Box<dyn Error>> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+    let seconds = match unit.trim() {
+        "" | "ms" => value / 1e3,
+        "us" | "µs" => value / 1e6,
+        "ns" => value / 1e9,
+        "s" => value,
+        "m" | "min" => value * 60.0,
+        "h" => value * 3600.0,
+        other => return Err(format!("unknown duration unit '{}' in '{}'", other, s).into()),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("duration '{}' is out of range", s).into())
+}
+
+/// Parse `100k`, `400kHz`, `1M`, `3.4MHz` or a bare number of hertz.
+pub fn frequency(s: &str) -> Result<u32, Box<dyn Error>> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    let digits = lower.trim_end_matches("hz");
+    let (number, scale) = match digits.chars().last() {
+        Some('k') => (&digits[..digits.len() - 1], 1e3),
+        Some('m') => (&digits[..digits.len() - 1], 1e6),
+        _ => (digits, 1.0),
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid frequency '{}'", s))?;
+    let hz = value * scale;
+    if !(1.0..=u32::MAX as f64).contains(&hz) {
+        return Err(format!("frequency '{}' out of range", s).into());
+    }
+    Ok(hz.round() as u32)
+}
+
+/// Parse a byte as `0x1F`, `0b0001_1111` or decimal `31`.
+pub fn byte(s: &str) -> Result<u8, Box<dyn Error>> {
+    let value = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u8::from_str_radix(&hex.replace('_', ""), 16)
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        u8::from_str_radix(&bin.replace('_', ""), 2)
+    } else {
+        s.parse()
+    };
+    value.map_err(|_| format!("invalid byte '{}'", s).into())
+}
+
+/// Parse a 16-bit value as `0x1F40`, `0b...` or decimal `8000`.
+pub fn word(s: &str) -> Result<u16, Box<dyn Error>> {
+    let value = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u16::from_str_radix(&hex.replace('_', ""), 16)
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        u16::from_str_radix(&bin.replace('_', ""), 2)
+    } else {
+        s.parse()
+    };
+    value.map_err(|_| format!("invalid 16-bit value '{}'", s).into())
+}
+
+/// Parse hex bytes such as `DE AD BE EF`, `DEADBEEF` or `0xDE,0xAD`;
+/// spaces, commas and colons between them are all the same.
+pub fn hex_bytes(s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    for token in s.split(|c: char| c.is_whitespace() || c == ',' || c == ':').filter(|t| !t.is_empty()) {
+        let digits = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token);
+        if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("invalid hex '{}' (two digits a byte)", token).into());
+        }
+        for pair in digits.as_bytes().chunks(2) {
+            bytes.push(u8::from_str_radix(std::str::from_utf8(pair)?, 16)?);
+        }
+    }
+    if bytes.is_empty() {
+        return Err("no hex bytes given".into());
+    }
+    Ok(bytes)
+}
+
+/// Parse how many bytes to read in one transfer, 1 to 4096.
+pub fn byte_count(s: &str) -> Result<usize, Box<dyn Error>> {
+    match s.parse::<usize>() {
+        Ok(n) if (1..=4096).contains(&n) => Ok(n),
+        _ => Err(format!("invalid byte count '{}' (1-4096)", s).into()),
+    }
+}
+
+/// Parse `512k`, `10M`, `1G` or a bare number of bytes; the suffixes are
+/// powers of 1024.
+pub fn size(s: &str) -> Result<u64, Box<dyn Error>> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let value: f64 = number.parse().map_err(|_| format!("invalid size '{}'", s))?;
+    let scale = match unit.trim().to_ascii_lowercase().trim_end_matches('b') {
+        "" => 1u64,
+        "k" | "ki" => 1 << 10,
+        "m" | "mi" => 1 << 20,
+        "g" | "gi" => 1 << 30,
+        _ => return Err(format!("unknown size unit '{}' in '{}'", unit, s).into()),
+    };
+    let bytes = value * scale as f64;
+    if !(1.0..=u64::MAX as f64).contains(&bytes) {
+        return Err(format!("size '{}' out of range", s).into());
+    }
+    Ok(bytes as u64)
+}
+
+/// `#[serde(deserialize_with = "...")]` helpers so config files can use the
+/// same notation as the command line.
+pub mod serde_helpers {
+    use serde::{Deserialize, Deserializer};
+    use std::time::Duration;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrText {
+        Number(u64),
+        Text(String),
+    }
+
+    pub fn duration<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        match NumberOrText::deserialize(d)? {
+            NumberOrText::Number(ms) => Ok(Duration::from_millis(ms)),
+            NumberOrText::Text(s) => super::duration(&s).map_err(serde::de::Error::custom),
+        }
+    }
+
+    pub fn duration_opt<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        match Option::<NumberOrText>::deserialize(d)? {
+            None => Ok(None),
+            Some(NumberOrText::Number(ms)) => Ok(Some(Duration::from_millis(ms))),
+            Some(NumberOrText::Text(s)) => super::duration(&s)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+
+    pub fn frequency_opt<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u32>, D::Error> {
+        match Option::<NumberOrText>::deserialize(d)? {
+            None => Ok(None),
+            Some(NumberOrText::Number(hz)) => u32::try_from(hz)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+            Some(NumberOrText::Text(s)) => super::frequency(&s)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_units() {
+        assert_eq!(duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(duration("250").unwrap(), Duration::from_millis(250));
+        assert_eq!(duration("10us").unwrap(), Duration::from_micros(10));
+        assert_eq!(duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(duration("1.5 s").unwrap(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn duration_rejects_garbage() {
+        assert_eq!(duration("fast").unwrap_err().to_string(), "invalid duration 'fast'");
+        assert_eq!(duration("5d").unwrap_err().to_string(), "unknown duration unit 'd' in '5d'");
+    }
+
+    #[test]
+    fn duration_out_of_range_is_an_error() {
+        let huge = "99999999999999999999999s";
+        assert_eq!(duration(huge).unwrap_err().to_string(), format!("duration '{}' is out of range", huge));
+        assert!(duration(&("9".repeat(400) + "h")).is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Timed patterns across several channels, for lining up scope captures.
+//!
+//! A pattern file schedules I2C transfers, GPIO levels, trigger pulses and
+//! PWM tones against one clock, so a multi-channel capture shows SDA/SCL,
+//! the trigger pin and the PWM line with the offsets the file asked for:
+//!
+//! ```text
+//! # Trigger, a 2 kHz tone, then a register burst 100 µs after the trigger
+//! 0        trigger 17 10us
+//! 0        pwm 18 2000 0.5
+//! 100us    write 0x27 0xFF 0x00
+//! +1ms     read 0x68 0x3B 6        # 1 ms after the write
+//! 20ms     gpio 22 high
+//! 25ms     gpio 22 low
+//! 50ms     pwm 18 off
+//! ```
+//!
+//! Each line is a time and an event. A bare time is from the start of the
+//! pattern (a bare number is milliseconds); `+TIME` is after the line
+//! before. Events at the same time fire in file order. `trigger PIN
+//! [WIDTH]` pulses high, [`DEFAULT_WIDTH`] by default; `pwm PIN HZ DUTY`
+//! starts a [`SoftPwm`] and `pwm PIN off` stops it low. `write` and `read`
+//! are as in [bring-up scripts](crate::script).
+//!
+//! [`Pattern::run`] waits for each event with a [`PreciseDelay`] and hands
+//! back when each one actually fired, so the skew can be checked against
+//! what the scope shows. A failed transfer is noted and the pattern goes
+//! on, keeping its timing; a pin that can't be driven stops it.
+
+use crate::address::{Address, AddressedI2c};
+use crate::parse;
+use crate::pwm::SoftPwm;
+use crate::timing::PreciseDelay;
+use crate::trigger::DEFAULT_WIDTH;
+use rppal::gpio::{Gpio, OutputPin};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Write(Address, Vec<u8>),
+    /// Write the bytes (if any), then read `count`.
+    Read { address: Address, write: Vec<u8>, count: usize },
+    Gpio(u8, bool),
+    Trigger(u8, Duration),
+    /// Frequency in Hz and duty, or `None` to stop.
+    Pwm(u8, Option<(f64, f64)>),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Write(address, bytes) => write!(f, "write {} to {}", bytes.len(), address),
+            Event::Read { address, write, count } if write.is_empty() => write!(f, "read {} from {}", count, address),
+            Event::Read { address, write, count } => write!(f, "write {} then read {} at {}", write.len(), count, address),
+            Event::Gpio(pin, high) => write!(f, "GPIO {} {}", pin, if *high { "high" } else { "low" }),
+            Event::Trigger(pin, width) => write!(f, "trigger GPIO {} for {}µs", pin, width.as_micros()),
+            Event::Pwm(pin, Some((frequency, duty))) => write!(f, "PWM GPIO {} at {}Hz, duty {}", pin, frequency, duty),
+            Event::Pwm(pin, None) => write!(f, "PWM GPIO {} off", pin),
+        }
+    }
+}
+
+/// An event, when it is due from the start, and the line it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheduled {
+    pub at: Duration,
+    pub line: usize,
+    pub event: Event,
+}
+
+/// Events in the order they fire.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pattern {
+    pub events: Vec<Scheduled>,
+}
+
+/// When an event actually went, against when it was due.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fired {
+    pub line: usize,
+    pub at: Duration,
+    /// From the start of the pattern to the event starting.
+    pub actual: Duration,
+    pub took: Duration,
+    /// A transfer that failed.
+    pub error: Option<String>,
+}
+
+impl Fired {
+    /// How late it went.
+    pub fn skew(&self) -> Duration {
+        self.actual.saturating_sub(self.at)
+    }
+}
+
+/// The pins a pattern drives. [`GpioChannels`] on a Pi; anything else in
+/// tests.
+pub trait Channels {
+    fn set(&mut self, pin: u8, high: bool) -> Result<(), Box<dyn Error>>;
+
+    /// Start a tone of `frequency` Hz at `duty`, or with `None` stop it and
+    /// leave the pin low.
+    fn pwm(&mut self, pin: u8, tone: Option<(f64, f64)>) -> Result<(), Box<dyn Error>>;
+}
+
+impl Pattern {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        text.parse().map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Every GPIO it drives, in order.
+    pub fn pins(&self) -> Vec<u8> {
+        let mut pins: Vec<u8> = self
+            .events
+            .iter()
+            .filter_map(|s| match s.event {
+                Event::Gpio(pin, _) | Event::Trigger(pin, _) | Event::Pwm(pin, _) => Some(pin),
+                Event::Write(..) | Event::Read { .. } => None,
+            })
+            .collect();
+        pins.sort_unstable();
+        pins.dedup();
+        pins
+    }
+
+    /// When the last event is due.
+    pub fn length(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |s| s.at)
+    }
+
+    /// Fire every event at its time from `start`, spinning through the
+    /// last `delay.spin()` of each wait.
+    pub fn run<I2C, C>(&self, i2c: &mut I2C, channels: &mut C, delay: &PreciseDelay, start: Instant) -> Result<Vec<Fired>, Box<dyn Error>>
+    where
+        I2C: AddressedI2c,
+        C: Channels,
+    {
+        let mut fired = Vec::with_capacity(self.events.len());
+        for scheduled in &self.events {
+            delay.until(start + scheduled.at);
+            let began = Instant::now();
+            let result = match &scheduled.event {
+                Event::Write(address, bytes) => i2c.write_at(*address, bytes),
+                Event::Read { address, write, count } => {
+                    let mut buf = vec![0; *count];
+                    if write.is_empty() {
+                        i2c.read_at(*address, &mut buf)
+                    } else {
+                        i2c.write_read_at(*address, write, &mut buf)
+                    }
+                }
+                Event::Gpio(pin, high) => {
+                    channels.set(*pin, *high).map_err(|e| format!("line {}: {}", scheduled.line, e))?;
+                    Ok(())
+                }
+                Event::Trigger(pin, width) => {
+                    channels.set(*pin, true).map_err(|e| format!("line {}: {}", scheduled.line, e))?;
+                    let high = Instant::now();
+                    // Spun, like Trigger::pulse: a sleep would stretch it
+                    while high.elapsed() < *width {
+                        std::hint::spin_loop();
+                    }
+                    channels.set(*pin, false).map_err(|e| format!("line {}: {}", scheduled.line, e))?;
+                    Ok(())
+                }
+                Event::Pwm(pin, tone) => {
+                    channels.pwm(*pin, *tone).map_err(|e| format!("line {}: {}", scheduled.line, e))?;
+                    Ok(())
+                }
+            };
+            fired.push(Fired {
+                line: scheduled.line,
+                at: scheduled.at,
+                actual: began - start,
+                took: began.elapsed(),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+        Ok(fired)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+        let mut last = Duration::ZERO;
+        for (index, raw) in s.lines().enumerate() {
+            let line = index + 1;
+            let text = raw.split('#').next().unwrap_or("").trim();
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let at = |e: Box<dyn Error>| -> Box<dyn Error> { format!("line {}: {}", line, e).into() };
+            let Some((time, rest)) = words.split_first() else {
+                continue;
+            };
+            let when = match time.strip_prefix('+') {
+                Some(after) => last + parse::duration(after).map_err(at)?,
+                None => parse::duration(time).map_err(at)?,
+            };
+            let event = parse_event(rest).map_err(at)?;
+            events.push(Scheduled { at: when, line, event });
+            last = when;
+        }
+        // Stable, so events at the same time keep their file order
+        events.sort_by_key(|s| s.at);
+        Ok(Pattern { events })
+    }
+}
+
+fn parse_event(words: &[&str]) -> Result<Event, Box<dyn Error>> {
+    let bytes = |words: &[&str]| words.iter().map(|b| parse::byte(b)).collect::<Result<Vec<_>, _>>();
+    let pin = |word: &str| word.parse::<u8>().map_err(|_| format!("invalid GPIO '{}'", word));
+    let event = match words {
+        ["write", address, data @ ..] if !data.is_empty() => Event::Write(address.parse()?, bytes(data)?),
+        ["read", address, write @ .., count] => Event::Read {
+            address: address.parse()?,
+            write: bytes(write)?,
+            count: parse::byte_count(count)?,
+        },
+        ["gpio", number, "high"] => Event::Gpio(pin(number)?, true),
+        ["gpio", number, "low"] => Event::Gpio(pin(number)?, false),
+        ["trigger", number] => Event::Trigger(pin(number)?, DEFAULT_WIDTH),
+        ["trigger", number, width] => Event::Trigger(pin(number)?, parse::duration(width)?),
+        ["pwm", number, "off"] => Event::Pwm(pin(number)?, None),
+        ["pwm", number, frequency, duty] => {
+            let frequency = parse::frequency(frequency)?;
+            let duty: f64 = duty.parse().map_err(|_| format!("invalid duty '{}'", duty))?;
+            Event::Pwm(pin(number)?, Some((f64::from(frequency), duty)))
+        }
+        ["write", ..] => return Err("usage: TIME write ADDR BYTE...".into()),
+        ["read", ..] => return Err("usage: TIME read ADDR [BYTE...] COUNT".into()),
+        ["gpio", ..] => return Err("usage: TIME gpio PIN high|low".into()),
+        ["trigger", ..] => return Err("usage: TIME trigger PIN [WIDTH]".into()),
+        ["pwm", ..] => return Err("usage: TIME pwm PIN HZ DUTY, or TIME pwm PIN off".into()),
+        [name, ..] => return Err(format!("unknown event '{}'", name).into()),
+        [] => return Err("a time needs an event after it".into()),
+    };
+    Ok(event)
+}
+
+enum Line {
+    Idle(OutputPin),
+    Playing(SoftPwm<OutputPin>),
+    /// Only while a tone is being stopped or started.
+    Gone,
+}
+
+/// A pattern's pins on the Pi's GPIO, all claimed up front and low, so
+/// nothing is set up once the timing has started. Dropping it stops any
+/// tone still playing.
+pub struct GpioChannels {
+    lines: BTreeMap<u8, Line>,
+}
+
+impl GpioChannels {
+    pub fn claim(pins: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let mut lines = BTreeMap::new();
+        for &pin in pins {
+            let output = gpio.get(pin).map_err(|e| format!("pattern GPIO {}: {}", pin, e))?.into_output_low();
+            lines.insert(pin, Line::Idle(output));
+        }
+        Ok(GpioChannels { lines })
+    }
+
+    fn line(&mut self, pin: u8) -> Result<&mut Line, Box<dyn Error>> {
+        self.lines.get_mut(&pin).ok_or_else(|| format!("GPIO {} was not claimed", pin).into())
+    }
+}
+
+impl Channels for GpioChannels {
+    fn set(&mut self, pin: u8, high: bool) -> Result<(), Box<dyn Error>> {
+        match self.line(pin)? {
+            Line::Idle(output) if high => output.set_high(),
+            Line::Idle(output) => output.set_low(),
+            Line::Playing(_) => return Err(format!("GPIO {} is playing a tone", pin).into()),
+            Line::Gone => return Err(format!("GPIO {} was lost stopping its tone", pin).into()),
+        }
+        Ok(())
+    }
+
+    fn pwm(&mut self, pin: u8, tone: Option<(f64, f64)>) -> Result<(), Box<dyn Error>> {
+        let line = self.line(pin)?;
+        match (std::mem::replace(line, Line::Gone), tone) {
+            (Line::Playing(mut pwm), Some((frequency, duty))) => {
+                pwm.set_frequency(frequency)?;
+                pwm.set_duty(duty)?;
+                *line = Line::Playing(pwm);
+            }
+            (Line::Playing(pwm), None) => *line = Line::Idle(pwm.stop()?),
+            (Line::Idle(output), Some((frequency, duty))) => *line = Line::Playing(SoftPwm::new(output, frequency, duty)?),
+            (idle @ Line::Idle(_), None) => *line = idle,
+            (Line::Gone, _) => return Err(format!("GPIO {} was lost stopping its tone", pin).into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::Operation;
+
+    /// Logs every edge and transfer with its time from the start.
+    #[derive(Default)]
+    struct Bench {
+        start: Option<Instant>,
+        log: Vec<(Duration, String)>,
+    }
+
+    impl Bench {
+        fn note(&mut self, what: String) {
+            let at = self.start.map_or(Duration::ZERO, |s| s.elapsed());
+            self.log.push((at, what));
+        }
+    }
+
+    impl Channels for Bench {
+        fn set(&mut self, pin: u8, high: bool) -> Result<(), Box<dyn Error>> {
+            self.note(format!("{} {}", pin, high));
+            Ok(())
+        }
+
+        fn pwm(&mut self, pin: u8, tone: Option<(f64, f64)>) -> Result<(), Box<dyn Error>> {
+            self.note(format!("{} pwm {:?}", pin, tone));
+            Ok(())
+        }
+    }
+
+    struct Nack;
+
+    impl AddressedI2c for Nack {
+        fn transaction_at(&mut self, _: Address, _: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+            Err("NACK".into())
+        }
+    }
+
+    #[test]
+    fn fires_in_time_order_and_keeps_going_past_a_nack() {
+        let pattern: Pattern = "
+            # out of order on purpose
+            5ms     gpio 22 low
+            0       trigger 17 20us
+            0       pwm 18 2kHz 0.5
+            1ms     write 0x27 0xFF
+            +1ms    gpio 22 high
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(pattern.pins(), [17, 18, 22]);
+        assert_eq!(pattern.length(), Duration::from_millis(5));
+        let lines: Vec<usize> = pattern.events.iter().map(|s| s.line).collect();
+        assert_eq!(lines, [4, 5, 6, 7, 3]);
+        assert_eq!(pattern.events[1].event, Event::Pwm(18, Some((2000.0, 0.5))));
+
+        let start = Instant::now();
+        let mut bench = Bench { start: Some(start), ..Bench::default() };
+        let fired = pattern.run(&mut Nack, &mut bench, &PreciseDelay::default(), start).unwrap();
+        let edges: Vec<&str> = bench.log.iter().map(|(_, what)| what.as_str()).collect();
+        assert_eq!(edges, ["17 true", "17 false", "18 pwm Some((2000.0, 0.5))", "22 true", "22 false"]);
+        // the trigger pulse is at least its width
+        assert!(bench.log[1].0 - bench.log[0].0 >= Duration::from_micros(20));
+        assert!(bench.log[3].0 >= Duration::from_millis(2));
+        assert_eq!(fired[2].error.as_deref(), Some("NACK"));
+        assert!(fired.iter().all(|f| f.actual >= f.at));
+
+        assert!("1ms wiggle 3".parse::<Pattern>().unwrap_err().to_string().starts_with("line 1: unknown event"));
+        assert!("1ms".parse::<Pattern>().is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! Process-wide record of which pins, buses and PWM channels are in use.
+//!
+//! rppal refuses to hand out the same GPIO twice, but it can't know that
+//! GPIO 2 is also SDA on bus 1, or that a PWM channel is already driving a
+//! pin a rail switch wants. [`Peripherals::take`] returns the one registry
+//! for the process; every module claims what it drives before touching it,
+//! and a second claim fails with [`AlreadyClaimed`] naming the first owner.
+//! Claims are released when their [`Claim`] is dropped.
+//!
+//! ```no_run
+//! use rpi_peripherals::peripherals::{Peripherals, Resource};
+//!
+//! let peripherals = Peripherals::take().expect("taken once, in main");
+//! let _bus = peripherals.claim_i2c(1, "lcd")?;
+//! // Fails: GPIO 2 is SDA on bus 1
+//! let trigger = peripherals.claim(Resource::Pin(2), "trigger");
+//! assert!(trigger.is_err());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::bus;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Something only one part of the program may drive at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    /// BCM GPIO number.
+    Pin(u8),
+    /// `/dev/i2c-N`.
+    I2cBus(u8),
+    /// `/dev/spidevBUS.CS`.
+    Spi { bus: u8, cs: u8 },
+    /// Hardware PWM channel.
+    Pwm(u8),
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Pin(pin) => write!(f, "GPIO {}", pin),
+            Resource::I2cBus(id) => write!(f, "I2C bus {}", id),
+            Resource::Spi { bus, cs } => write!(f, "SPI {}.{}", bus, cs),
+            Resource::Pwm(channel) => write!(f, "PWM channel {}", channel),
+        }
+    }
+}
+
+/// A claim on a resource someone else already holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyClaimed {
+    pub resource: Resource,
+    /// Who wanted it.
+    pub by: String,
+    /// Who has it.
+    pub owner: String,
+}
+
+impl fmt::Display for AlreadyClaimed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} can't use {}: {} already does", self.by, self.resource, self.owner)
+    }
+}
+
+impl Error for AlreadyClaimed {}
+
+type Claims = Arc<Mutex<HashMap<Resource, String>>>;
+
+/// Cheap to clone; every clone shares the one set of claims.
+#[derive(Clone)]
+pub struct Peripherals {
+    claims: Claims,
+}
+
+impl Peripherals {
+    /// The registry, the first time this is called; `None` afterwards, so
+    /// two halves of a program can't each think they own the hardware.
+    pub fn take() -> Option<Self> {
+        if TAKEN.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        Some(Peripherals { claims: Arc::default() })
+    }
+
+    /// Claim `resource` for `owner` (used in error messages).
+    pub fn claim(&self, resource: Resource, owner: &str) -> Result<Claim, AlreadyClaimed> {
+        self.claim_all(&[resource], owner)
+    }
+
+    /// Claim several resources at once: all of them, or none if any is taken.
+    pub fn claim_all(&self, resources: &[Resource], owner: &str) -> Result<Claim, AlreadyClaimed> {
+        let mut claims = lock(&self.claims);
+        if let Some((&resource, held)) = resources.iter().find_map(|r| claims.get_key_value(r)) {
+            return Err(AlreadyClaimed {
+                resource,
+                by: owner.to_string(),
+                owner: held.clone(),
+            });
+        }
+        for &resource in resources {
+            claims.insert(resource, owner.to_string());
+        }
+        Ok(Claim {
+            resources: resources.to_vec(),
+            claims: Arc::clone(&self.claims),
+        })
+    }
+
+    /// Claim `/dev/i2c-N` and, for the Pi's fixed buses, the pins it's on.
+    pub fn claim_i2c(&self, id: u8, owner: &str) -> Result<Claim, AlreadyClaimed> {
+        let mut resources = vec![Resource::I2cBus(id)];
+        if let Some((sda, scl)) = bus::hardware_pins(id) {
+            resources.extend([Resource::Pin(sda), Resource::Pin(scl)]);
+        }
+        self.claim_all(&resources, owner)
+    }
+
+    /// Who holds `resource`, if anyone.
+    pub fn owner(&self, resource: Resource) -> Option<String> {
+        lock(&self.claims).get(&resource).cloned()
+    }
+}
+
+/// Held resources; dropping it releases them.
+#[must_use = "the claim is released as soon as it's dropped"]
+pub struct Claim {
+    resources: Vec<Resource>,
+    claims: Claims,
+}
+
+impl Claim {
+    pub fn resources(&self) -> &[Resource] {
+        &self.resources
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        let mut claims = lock(&self.claims);
+        for resource in &self.resources {
+            claims.remove(resource);
+        }
+    }
+}
+
+fn lock(claims: &Claims) -> std::sync::MutexGuard<'_, HashMap<Resource, String>> {
+    claims.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,192 @@
+//! The state of every header pin, for `pins`: `gpio readall`, but read
+//! through rppal rather than wiringPi, so it knows the Pi 4 and Pi 5.
+//!
+//! Modes and levels come from rppal. Pulls are read straight from the GPIO
+//! registers through `/dev/gpiomem`, which only the BCM2711 and the Pi 5's
+//! RP1 allow; the older chips' pull controls are write-only, so there the
+//! pull shows as unknown.
+
+use crate::board::{Board, HeaderPin, Soc};
+use rppal::gpio::{Gpio, Level, Mode};
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::fmt::Write;
+use std::ptr;
+
+/// `GPIO_PUP_PDN_CNTRL_REG0` on the BCM2711, as a word offset.
+const BCM2711_PULL: usize = 0xE4 / 4;
+/// The first GPIO's `PADS_BANK0` register on the RP1, as a word offset.
+const RP1_PADS: usize = (0x2_0000 + 0x04) / 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pull {
+    Off,
+    Up,
+    Down,
+}
+
+impl fmt::Display for Pull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Pull::Off => "off",
+            Pull::Up => "up",
+            Pull::Down => "down",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinState {
+    pub gpio: u8,
+    pub mode: Mode,
+    pub level: Level,
+    /// `None` where the chip can't say.
+    pub pull: Option<Pull>,
+}
+
+/// Every header GPIO's state, in GPIO order.
+pub fn read_all(board: &Board) -> Result<Vec<PinState>, Box<dyn Error>> {
+    let gpio = Gpio::new()?;
+    let pins = board.header_gpios();
+    // best effort: without /dev/gpiomem, the table just lacks pulls
+    let pulls = read_pulls(board.soc, &pins).unwrap_or_default();
+    pins.iter()
+        .enumerate()
+        .map(|(i, &number)| {
+            let pin = gpio.get(number).map_err(|e| format!("GPIO {}: {}", number, e))?;
+            Ok(PinState {
+                gpio: number,
+                mode: pin.mode(),
+                level: pin.read(),
+                pull: pulls.get(i).copied().flatten(),
+            })
+        })
+        .collect()
+}
+
+/// `pins`' pulls, where `soc` lets them be read back.
+fn read_pulls(soc: Soc, pins: &[u8]) -> Result<Vec<Option<Pull>>, Box<dyn Error>> {
+    match soc {
+        Soc::Bcm2711 => {
+            let words = read_words("/dev/gpiomem", BCM2711_PULL + 2)?;
+            Ok(pins
+                .iter()
+                .map(|&pin| match (words[BCM2711_PULL + usize::from(pin) / 16] >> (pin % 16 * 2)) & 0b11 {
+                    0b00 => Some(Pull::Off),
+                    0b01 => Some(Pull::Up),
+                    0b10 => Some(Pull::Down),
+                    _ => None,
+                })
+                .collect())
+        }
+        Soc::Bcm2712 => {
+            let last = pins.iter().copied().max().unwrap_or(0);
+            let words = read_words("/dev/gpiomem0", RP1_PADS + usize::from(last) + 1)?;
+            Ok(pins
+                .iter()
+                .map(|&pin| match (words[RP1_PADS + usize::from(pin)] >> 2) & 0b11 {
+                    0 => Some(Pull::Off),
+                    1 => Some(Pull::Down),
+                    2 => Some(Pull::Up),
+                    _ => None,
+                })
+                .collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// The first `count` 32-bit registers of `path`, mapped read-only.
+fn read_words(path: &str, count: usize) -> Result<Vec<u32>, Box<dyn Error>> {
+    let len = count * 4;
+    let c_path = CString::new(path)?;
+    // SAFETY: plain libc calls, checked before use; the mapping is read
+    // within its length and unmapped before returning
+    unsafe {
+        let fd = libc::open(c_path.as_ptr(), libc::O_RDONLY | libc::O_SYNC | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(format!("{}: {}", path, std::io::Error::last_os_error()).into());
+        }
+        let block = libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, fd, 0);
+        let error = std::io::Error::last_os_error();
+        libc::close(fd);
+        if block == libc::MAP_FAILED {
+            return Err(format!("{}: {}", path, error).into());
+        }
+        let registers: *const u32 = block.cast();
+        let words = (0..count).map(|i| ptr::read_volatile(registers.add(i))).collect();
+        libc::munmap(block, len);
+        Ok(words)
+    }
+}
+
+/// `states` laid out like the header, in two columns, odd pins on the
+/// left. Pins missing from `states` are left blank.
+pub fn table(board: &Board, states: &[PinState]) -> String {
+    let side = |pin: &HeaderPin| -> [String; 5] {
+        match *pin {
+            HeaderPin::Power(rail) => [String::new(), rail.to_string(), String::new(), String::new(), String::new()],
+            HeaderPin::Ground => [String::new(), "0V".to_string(), String::new(), String::new(), String::new()],
+            HeaderPin::Gpio(gpio) => {
+                let Some(state) = states.iter().find(|s| s.gpio == gpio) else {
+                    return [gpio.to_string(), format!("GPIO{}", gpio), String::new(), String::new(), String::new()];
+                };
+                let alt = !matches!(state.mode, Mode::Input | Mode::Output);
+                let name = match board.pin_functions(gpio).first() {
+                    Some(function) if alt => function.clone(),
+                    _ => format!("GPIO{}", gpio),
+                };
+                let level = if state.level == Level::High { "1" } else { "0" };
+                let pull = state.pull.map_or("?".to_string(), |p| p.to_string());
+                [gpio.to_string(), name, state.mode.to_string(), level.to_string(), pull]
+            }
+        }
+    };
+    let rule = "-----+-----------+------+---+------+----++----+------+---+------+-----------+-----";
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", rule);
+    let _ = writeln!(out, " BCM | Name      | Mode | V | Pull | Physical | Pull | V | Mode | Name      | BCM");
+    let _ = writeln!(out, "{}", rule);
+    for (row, pair) in board.header_pins().chunks(2).enumerate() {
+        let [bcm, name, mode, level, pull] = side(&pair[0]);
+        let left = format!(" {:>3} | {:>9} | {:>4} | {:>1} | {:>4} | {:>2}", bcm, name, mode, level, pull, row * 2 + 1);
+        let [bcm, name, mode, level, pull] = pair.get(1).map(side).unwrap_or_default();
+        let right = format!("{:<2} | {:<4} | {:<1} | {:<4} | {:<9} | {}", row * 2 + 2, pull, level, mode, name, bcm);
+        let _ = writeln!(out, "{} || {}", left, right.trim_end().trim_end_matches('|').trim_end());
+    }
+    let _ = writeln!(out, "{}", rule);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_the_header_in_pairs() {
+        let board = Board::from_parts("Raspberry Pi 4 Model B Rev 1.4", Some(0xC03114));
+        let states = [
+            PinState {
+                gpio: 2,
+                mode: Mode::Alt0,
+                level: Level::High,
+                pull: Some(Pull::Up),
+            },
+            PinState {
+                gpio: 14,
+                mode: Mode::Output,
+                level: Level::Low,
+                pull: None,
+            },
+        ];
+        let table = table(&board, &states);
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows.len(), 20 + 4);
+        assert_eq!(rows[3], "     |      3.3V |      |   |      |  1 || 2  |      |   |      | 5V");
+        assert_eq!(rows[4], "   2 |      SDA1 | Alt0 | 1 |   up |  3 || 4  |      |   |      | 5V");
+        assert_eq!(rows[6], "   4 |     GPIO4 |      |   |      |  7 || 8  | ?    | 0 | Out  | GPIO14    | 14");
+        assert_eq!(board.physical_pin(14), Some(8));
+        assert_eq!(board.physical_pin(28), None);
+    }
+}
@@ -0,0 +1,374 @@
+//! Previewing a config change before it reaches the hardware.
+//!
+//! [`Plan::new`] compares a config with the one last applied and works out
+//! which rails are switched on, which devices are initialized (the changed
+//! ones and anything that depends on them or sits on a changed rail), and
+//! which pins and buses the new config claims. Each init sequence runs
+//! against a capture bus that ACKs everything and only takes notes, so the
+//! preview lists the transactions `apply` will send, byte for byte. A
+//! sequence that depends on what the chip answers, such as an ID check,
+//! shows how far it got on zeros.
+//!
+//! `apply` stores the config it applied with [`save_applied`]; the next
+//! `plan` is relative to that.
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus;
+use crate::config::{Config, DeviceConfig, RailConfig};
+use crate::drivers::{self, Interface};
+use crate::lcd::Lcd;
+use crate::peripherals::Resource;
+use crate::sensors::{Ina219, Ina226};
+use crate::startup::{Node, StartupPlan};
+use crate::trace::{Direction, TraceOp};
+use embedded_hal::i2c::Operation;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Where `apply` records what it applied unless told otherwise.
+pub const DEFAULT_STATE: &str = "/var/lib/rpi_peripherals/applied.json";
+
+/// What `apply` assumes for INA2xx breakouts, as `app energy` does.
+const INA_SHUNT_OHMS: f64 = 0.1;
+const INA_MAX_CURRENT: f64 = 3.2;
+
+/// Size `apply` initializes HD44780 displays at.
+const LCD_SIZE: (u8, u8) = (16, 2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added(Node),
+    Removed(Node),
+    Changed(Node),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(node) => write!(f, "+ {}", node),
+            Change::Removed(node) => write!(f, "- {} (left as it is)", node),
+            Change::Changed(node) => write!(f, "~ {}", node),
+        }
+    }
+}
+
+/// One transaction of an init sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub address: Address,
+    /// Reads hold the zeros the capture bus answered with.
+    pub ops: Vec<TraceOp>,
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<6}", self.address.to_string())?;
+        for op in &self.ops {
+            match op.direction {
+                Direction::Write => {
+                    let hex: Vec<String> = op.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                    write!(f, " write [{}]", hex.join(" "))?
+                }
+                Direction::Read => write!(f, " read {}", op.bytes.len())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Drive a rail's enable pin to its on level and wait.
+    RailOn(RailConfig),
+    /// Run the driver's init sequence; the transactions it sent on the
+    /// capture bus, and the error it stopped at if it did.
+    Init {
+        device: DeviceConfig,
+        transactions: Vec<Transaction>,
+        stopped: Option<String>,
+    },
+    /// The driver has nothing to initialize.
+    Nothing(DeviceConfig),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    /// In startup order.
+    pub actions: Vec<Action>,
+    /// Everything the new config drives, with who drives it.
+    pub claims: Vec<(Resource, String)>,
+    /// Resources two owners in the new config both want.
+    pub conflicts: Vec<String>,
+}
+
+impl Plan {
+    /// What applying `config` on top of `applied` (nothing, the first
+    /// time) would do.
+    pub fn new(applied: Option<&Config>, config: &Config) -> Result<Self, Box<dyn Error>> {
+        let empty = Config::default();
+        let applied = applied.unwrap_or(&empty);
+        let changes = changes(applied, config);
+        let touched: HashSet<&Node> = changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Added(node) | Change::Changed(node) => Some(node),
+                Change::Removed(_) => None,
+            })
+            .collect();
+
+        // A step runs if it changed or anything it depends on does
+        let mut run: HashSet<Node> = HashSet::new();
+        let mut actions = Vec::new();
+        for step in StartupPlan::from_config(config)?.steps() {
+            if !touched.contains(&step.node) && !step.depends_on.iter().any(|dep| run.contains(dep)) {
+                continue;
+            }
+            run.insert(step.node.clone());
+            let action = match &step.node {
+                Node::Rail(name) => Action::RailOn(config.rails.iter().find(|r| &r.name == name).cloned().ok_or("rail vanished")?),
+                Node::Device(name) => {
+                    let device = config.devices.iter().find(|d| &d.name == name).ok_or("device vanished")?;
+                    preview(device)
+                }
+            };
+            actions.push(action);
+        }
+        let (claims, conflicts) = claims(config);
+        Ok(Plan {
+            changes,
+            actions,
+            claims,
+            conflicts,
+        })
+    }
+
+    /// Nothing would be switched or written.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            writeln!(f, "No changes since the last apply")?;
+        } else {
+            writeln!(f, "Changes:")?;
+            for change in &self.changes {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+        if !self.actions.is_empty() {
+            writeln!(f, "\nSteps:")?;
+        }
+        for (n, action) in self.actions.iter().enumerate() {
+            match action {
+                Action::RailOn(rail) => {
+                    let level = if rail.active_low { "low" } else { "high" };
+                    writeln!(
+                        f,
+                        "{:>3}. rail '{}': GPIO {} {}, wait {}ms",
+                        n + 1,
+                        rail.name,
+                        rail.pin,
+                        level,
+                        rail.settle.as_millis()
+                    )?;
+                }
+                Action::Init { device, transactions, stopped } => {
+                    writeln!(f, "{:>3}. device '{}' ({}) on bus {}:", n + 1, device.name, device.driver, device.bus)?;
+                    for transaction in transactions {
+                        writeln!(f, "       {}", transaction)?;
+                    }
+                    if let Some(e) = stopped {
+                        writeln!(f, "       … then depends on the chip's reply ({})", e)?;
+                    }
+                }
+                Action::Nothing(device) => {
+                    writeln!(f, "{:>3}. device '{}' ({}): no init sequence", n + 1, device.name, device.driver)?;
+                }
+            }
+        }
+        if !self.claims.is_empty() {
+            writeln!(f, "\nClaims:")?;
+            for (resource, owner) in &self.claims {
+                writeln!(f, "  {:<14} {}", resource.to_string(), owner)?;
+            }
+        }
+        for conflict in &self.conflicts {
+            writeln!(f, "❌ {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `device`'s init sequence on `i2c`. Returns whether it has one.
+pub fn init_device<I2C: AddressedI2c>(i2c: I2C, device: &DeviceConfig) -> Result<bool, Box<dyn Error>> {
+    let address = || -> Result<Address, Box<dyn Error>> {
+        let raw = device.address.ok_or_else(|| format!("device '{}' has no address", device.name))?;
+        Address::from_raw(raw)
+    };
+    let mut i2c = i2c;
+    match device.driver.to_ascii_lowercase().as_str() {
+        // Quasi-bidirectional pins start as inputs, pulled high
+        "pcf8574" => i2c.write_at(address()?, &[0xFF])?,
+        // All channels off, so nothing behind the mux answers until selected
+        "tca9548a" => i2c.write_at(address()?, &[0x00])?,
+        "hd44780" => {
+            Lcd::new(i2c, address()?, LCD_SIZE.0, LCD_SIZE.1)?;
+        }
+        "ina219" => {
+            Ina219::new(i2c, address()?, INA_SHUNT_OHMS, INA_MAX_CURRENT)?;
+        }
+        "ina226" => {
+            Ina226::new(i2c, address()?, INA_SHUNT_OHMS, INA_MAX_CURRENT)?;
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn preview(device: &DeviceConfig) -> Action {
+    let mut capture = Capture::default();
+    match init_device(&mut capture, device) {
+        Ok(false) => Action::Nothing(device.clone()),
+        Ok(true) => Action::Init {
+            device: device.clone(),
+            transactions: capture.transactions,
+            stopped: None,
+        },
+        Err(e) => Action::Init {
+            device: device.clone(),
+            transactions: capture.transactions,
+            stopped: Some(e.to_string()),
+        },
+    }
+}
+
+/// ACKs everything, reads zeros, remembers what it was sent.
+#[derive(Default)]
+struct Capture {
+    transactions: Vec<Transaction>,
+}
+
+impl AddressedI2c for Capture {
+    fn transaction_at(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+        let ops = operations
+            .iter_mut()
+            .map(|op| match op {
+                Operation::Write(bytes) => TraceOp {
+                    direction: Direction::Write,
+                    bytes: bytes.to_vec(),
+                },
+                Operation::Read(buf) => {
+                    buf.fill(0);
+                    TraceOp {
+                        direction: Direction::Read,
+                        bytes: buf.to_vec(),
+                    }
+                }
+            })
+            .collect();
+        self.transactions.push(Transaction { address, ops });
+        Ok(())
+    }
+}
+
+fn changes(applied: &Config, config: &Config) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let rails = |c: &Config| -> BTreeMap<String, RailConfig> { c.rails.iter().map(|r| (r.name.clone(), r.clone())).collect() };
+    let devices = |c: &Config| -> BTreeMap<String, DeviceConfig> { c.devices.iter().map(|d| (d.name.clone(), d.clone())).collect() };
+    diff(&rails(applied), &rails(config), Node::Rail, &mut changes);
+    diff(&devices(applied), &devices(config), Node::Device, &mut changes);
+    changes
+}
+
+fn diff<T: PartialEq>(before: &BTreeMap<String, T>, after: &BTreeMap<String, T>, node: fn(String) -> Node, out: &mut Vec<Change>) {
+    for (name, new) in after {
+        match before.get(name) {
+            None => out.push(Change::Added(node(name.clone()))),
+            Some(old) if old != new => out.push(Change::Changed(node(name.clone()))),
+            Some(_) => {}
+        }
+    }
+    for name in before.keys().filter(|name| !after.contains_key(*name)) {
+        out.push(Change::Removed(node(name.clone())));
+    }
+}
+
+/// Buses (with their pins) and rail pins, as `apply` claims them.
+fn claims(config: &Config) -> (Vec<(Resource, String)>, Vec<String>) {
+    let mut claims: Vec<(Resource, String)> = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut claim = |resource: Resource, owner: String| match claims.iter().find(|(r, _)| *r == resource) {
+        Some((_, first)) if *first != owner => {
+            conflicts.push(format!("{} is wanted by both {} and {}", resource, first, owner));
+        }
+        Some(_) => {}
+        None => claims.push((resource, owner)),
+    };
+    let mut buses: Vec<u8> = config
+        .devices
+        .iter()
+        .filter(|d| drivers::find(&d.driver).is_some_and(|info| info.interface == Interface::I2c))
+        .map(|d| d.bus)
+        .collect();
+    buses.sort_unstable();
+    buses.dedup();
+    for id in buses {
+        let owner = format!("I2C bus {}", id);
+        claim(Resource::I2cBus(id), owner.clone());
+        if let Some((sda, scl)) = bus::hardware_pins(id) {
+            claim(Resource::Pin(sda), format!("{} SDA", owner));
+            claim(Resource::Pin(scl), format!("{} SCL", owner));
+        }
+    }
+    for rail in &config.rails {
+        claim(Resource::Pin(rail.pin), format!("rail '{}'", rail.name));
+    }
+    (claims, conflicts)
+}
+
+/// What [`save_applied`] keeps: the config text, so it is read back the way
+/// the original was, and the profile applied on top of it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Applied {
+    config: String,
+    profile: Option<String>,
+}
+
+/// The config last applied, or `None` if nothing has been yet.
+pub fn load_applied(path: &Path) -> Result<Option<Config>, Box<dyn Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+    };
+    let applied: Applied = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let config: Config = applied.config.parse().map_err(|e| format!("{}: applied config: {}", path.display(), e))?;
+    Ok(Some(match applied.profile {
+        Some(profile) => config.with_profile(&profile)?,
+        None => config,
+    }))
+}
+
+/// Record `config_text` (the file as written, before any profile) as
+/// applied, written atomically.
+pub fn save_applied(path: &Path, config_text: &str, profile: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let applied = Applied {
+        config: config_text.to_string(),
+        profile: profile.map(String::from),
+    };
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(&applied)?).map_err(|e| format!("{}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(())
+}
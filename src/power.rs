@@ -0,0 +1,346 @@
+//! Power rails switched by GPIO-driven load switches or P-FETs.
+//!
+//! Each [`PowerRail`] is one switched supply; devices are assigned to the
+//! rail that feeds them. [`PowerManager::power_up`] brings the rails up in
+//! order, waiting each one's settle time before the next, so parts that must
+//! see their supply first (level shifters, muxes) can come first. Cycling a
+//! rail re-runs the init hook of every device on it, since a chip that lost
+//! power has forgotten its configuration.
+//!
+//! [`PowerManager::from_config`] takes the GPIOs over at the level they are
+//! at and leaves them there when dropped, so switching one rail from the
+//! command line doesn't glitch the others, or the rail itself if it is
+//! already in the state asked for.
+//!
+//! After a cycle a device is back when it ACKs again; [`wait_for_ack`]
+//! polls for that, so a brown-out test can time how long each part takes.
+//!
+//! To let the watchdog power-cycle a failing device, share the manager:
+//!
+//! ```no_run
+//! # use std::sync::{Arc, Mutex};
+//! # use rpi_peripherals::{config::Config, power::PowerManager, watchdog::{Stage, Watchdog}};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let config = Config::default();
+//! let power = Arc::new(Mutex::new(PowerManager::from_config(&config)?));
+//! let mut watchdog = Watchdog::new(config.watchdog.clone())?;
+//! let rails = Arc::clone(&power);
+//! watchdog.set_remedy(Stage::PowerCycle, move |device| {
+//!     rails.lock().unwrap().cycle_device(device).map(|_| ())
+//! });
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::address::{Address, AddressedI2c};
+use crate::config::Config;
+use crate::exit::TimedOut;
+use crate::scan;
+use embedded_hal::digital::OutputPin;
+use std::collections::HashMap;
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Polarity and timing of one load switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwitchConfig {
+    /// P-FET high-side switches conduct with the gate pulled low.
+    pub active_low: bool,
+    /// Time after switching on before the supply is usable.
+    pub settle: Duration,
+    /// Time the rail is held off during a power cycle, long enough for the
+    /// decoupling capacitors to drain and the parts to actually reset.
+    pub off_time: Duration,
+}
+
+impl Default for SwitchConfig {
+    fn default() -> Self {
+        SwitchConfig {
+            active_low: false,
+            settle: Duration::from_millis(100),
+            off_time: Duration::from_millis(500),
+        }
+    }
+}
+
+/// One switched supply.
+pub struct PowerRail<P> {
+    name: String,
+    pin: P,
+    config: SwitchConfig,
+    on: bool,
+}
+
+impl<P: OutputPin> PowerRail<P>
+where
+    P::Error: Error + 'static,
+{
+    /// Take over `pin`, already driven to the rail's `on` state, without
+    /// switching it.
+    pub fn adopt(name: impl Into<String>, pin: P, config: SwitchConfig, on: bool) -> Self {
+        PowerRail {
+            name: name.into(),
+            pin,
+            config,
+            on,
+        }
+    }
+
+    /// Take over `pin` and switch the rail off.
+    pub fn new(name: impl Into<String>, pin: P, config: SwitchConfig) -> Result<Self, Box<dyn Error>> {
+        let mut rail = PowerRail {
+            name: name.into(),
+            pin,
+            config,
+            on: true,
+        };
+        rail.set(false)?;
+        Ok(rail)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn config(&self) -> &SwitchConfig {
+        &self.config
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// Switch on and wait for the supply to settle.
+    pub fn on(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.on {
+            self.set(true)?;
+            thread::sleep(self.config.settle);
+        }
+        Ok(())
+    }
+
+    pub fn off(&mut self) -> Result<(), Box<dyn Error>> {
+        self.set(false)
+    }
+
+    /// Off for the configured off time, then back on and settled.
+    pub fn cycle(&mut self) -> Result<(), Box<dyn Error>> {
+        self.cycle_for(self.config.off_time)
+    }
+
+    /// Off for `off_time` instead, e.g. shorter and shorter to find the
+    /// shortest dropout a part survives.
+    pub fn cycle_for(&mut self, off_time: Duration) -> Result<(), Box<dyn Error>> {
+        self.set(false)?;
+        thread::sleep(off_time);
+        self.on()
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+
+    fn set(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        let result = if on != self.config.active_low {
+            self.pin.set_high()
+        } else {
+            self.pin.set_low()
+        };
+        result.map_err(|e| format!("rail '{}': {}", self.name, e))?;
+        self.on = on;
+        Ok(())
+    }
+}
+
+type Reinit = Box<dyn FnMut() -> Result<(), Box<dyn Error>> + Send>;
+
+/// The rails in power-up order and the devices each one feeds.
+pub struct PowerManager<P> {
+    rails: Vec<PowerRail<P>>,
+    /// Device name to rail name.
+    devices: HashMap<String, String>,
+    reinit: HashMap<String, Reinit>,
+}
+
+impl<P: OutputPin> Default for PowerManager<P> {
+    fn default() -> Self {
+        PowerManager {
+            rails: Vec::new(),
+            devices: HashMap::new(),
+            reinit: HashMap::new(),
+        }
+    }
+}
+
+impl PowerManager<rppal::gpio::OutputPin> {
+    /// Claim the GPIOs of the `[[rails]]` config entries, each left on or
+    /// off as it is, and assign devices by their `rail` field.
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let mut manager = PowerManager::new();
+        if config.rails.is_empty() {
+            return Ok(manager);
+        }
+        let gpio = rppal::gpio::Gpio::new()?;
+        for rail in &config.rails {
+            let pin = gpio
+                .get(rail.pin)
+                .map_err(|e| format!("rail '{}': GPIO {}: {}", rail.name, rail.pin, e))?;
+            // Keep driving the level it reads at, so neither state glitches
+            let high = pin.read() == rppal::gpio::Level::High;
+            let mut pin = if high { pin.into_output_high() } else { pin.into_output_low() };
+            pin.set_reset_on_drop(false);
+            manager.add_rail(PowerRail::adopt(&rail.name, pin, rail.switch(), high != rail.active_low));
+        }
+        for device in &config.devices {
+            if let Some(rail) = &device.rail {
+                manager.assign(&device.name, rail)?;
+            }
+        }
+        Ok(manager)
+    }
+}
+
+impl<P: OutputPin> PowerManager<P>
+where
+    P::Error: Error + 'static,
+{
+    pub fn new() -> Self {
+        PowerManager::default()
+    }
+
+    /// Add a rail after the existing ones in the power-up order.
+    pub fn add_rail(&mut self, rail: PowerRail<P>) {
+        self.rails.push(rail);
+    }
+
+    pub fn rail(&self, name: &str) -> Option<&PowerRail<P>> {
+        self.rails.iter().find(|r| r.name == name)
+    }
+
+    pub fn rails(&self) -> impl Iterator<Item = &PowerRail<P>> {
+        self.rails.iter()
+    }
+
+    /// Name of the rail feeding `device`, if it is on a switched one.
+    pub fn rail_of(&self, device: &str) -> Option<&str> {
+        self.devices.get(device).map(String::as_str)
+    }
+
+    pub fn assign(&mut self, device: &str, rail: &str) -> Result<(), Box<dyn Error>> {
+        if self.rail(rail).is_none() {
+            return Err(format!("device '{}' is on unknown rail '{}'", device, rail).into());
+        }
+        self.devices.insert(device.to_string(), rail.to_string());
+        Ok(())
+    }
+
+    /// Register what brings `device` back after its rail was cycled,
+    /// typically the driver's init sequence.
+    pub fn on_reinit<F>(&mut self, device: &str, reinit: F)
+    where
+        F: FnMut() -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        self.reinit.insert(device.to_string(), Box::new(reinit));
+    }
+
+    /// Switch one rail on and wait for it to settle, e.g. as a
+    /// [`crate::startup::Startup`] step.
+    pub fn rail_on(&mut self, rail: &str) -> Result<(), Box<dyn Error>> {
+        self.rail_mut(rail)?.on()
+    }
+
+    pub fn rail_off(&mut self, rail: &str) -> Result<(), Box<dyn Error>> {
+        self.rail_mut(rail)?.off()
+    }
+
+    /// Switch every rail on, in order, each settled before the next.
+    pub fn power_up(&mut self) -> Result<(), Box<dyn Error>> {
+        for rail in &mut self.rails {
+            rail.on()?;
+        }
+        Ok(())
+    }
+
+    /// Switch every rail off in reverse order. Keeps going past failures so
+    /// as much as possible ends up off, and reports the first one.
+    pub fn power_down(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut first = None;
+        for rail in self.rails.iter_mut().rev() {
+            if let Err(e) = rail.off() {
+                first.get_or_insert(e);
+            }
+        }
+        first.map_or(Ok(()), Err)
+    }
+
+    /// Power-cycle `rail` and re-initialize every device on it. Returns the
+    /// devices re-initialized, in name order.
+    pub fn cycle_rail(&mut self, rail: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let off_time = self.rail_mut(rail)?.config.off_time;
+        self.cycle_rail_for(rail, off_time)
+    }
+
+    /// [`cycle_rail`](Self::cycle_rail), holding the rail off for
+    /// `off_time` instead of its configured time.
+    pub fn cycle_rail_for(&mut self, rail: &str, off_time: Duration) -> Result<Vec<String>, Box<dyn Error>> {
+        self.rail_mut(rail)?.cycle_for(off_time)?;
+
+        let mut devices: Vec<String> = self
+            .devices
+            .iter()
+            .filter(|(_, r)| r.as_str() == rail)
+            .map(|(d, _)| d.clone())
+            .collect();
+        devices.sort();
+        let mut failed = Vec::new();
+        for device in &devices {
+            if let Some(reinit) = self.reinit.get_mut(device) {
+                if let Err(e) = reinit() {
+                    failed.push(format!("{}: {}", device, e));
+                }
+            }
+        }
+        if !failed.is_empty() {
+            return Err(format!("rail '{}' cycled but re-init failed ({})", rail, failed.join("; ")).into());
+        }
+        Ok(devices)
+    }
+
+    fn rail_mut(&mut self, rail: &str) -> Result<&mut PowerRail<P>, Box<dyn Error>> {
+        self.rails
+            .iter_mut()
+            .find(|r| r.name == rail)
+            .ok_or_else(|| format!("no rail '{}'", rail).into())
+    }
+
+    /// Power-cycle the rail feeding `device`. Everything else on that rail
+    /// goes down with it and is re-initialized too.
+    pub fn cycle_device(&mut self, device: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let rail = self
+            .rail_of(device)
+            .ok_or_else(|| format!("device '{}' is not on a switched rail", device))?
+            .to_string();
+        self.cycle_rail(&rail)
+    }
+}
+
+/// Probe `address` every `interval` until it ACKs, as a device does once it
+/// is out of reset after its rail came back. Returns how long that took.
+pub fn wait_for_ack<I2C: AddressedI2c>(i2c: &mut I2C, address: Address, timeout: Duration, interval: Duration) -> Result<Duration, Box<dyn Error>> {
+    let start = Instant::now();
+    loop {
+        if scan::probe(i2c, address) {
+            return Ok(start.elapsed());
+        }
+        let waited = start.elapsed();
+        if waited >= timeout {
+            return Err(TimedOut {
+                waiting_for: format!("{} to ACK after power-up", address),
+                after: waited,
+            }
+            .into());
+        }
+        thread::sleep(interval.min(timeout - waited));
+    }
+}
@@ -0,0 +1,290 @@
+//! Checks to run before opening a bus, so a fresh Pi fails with what to fix
+//! rather than rppal's bare "No such file or directory" or "Permission
+//! denied".
+//!
+//! [`check_bus`] looks at, in order:
+//!
+//! - the `i2c-dev` driver, without which no `/dev/i2c-*` nodes exist;
+//! - whether the device tree has the controller enabled (`dtparam=i2c_arm=on`);
+//! - the `/dev/i2c-N` node itself;
+//! - whether this process may open it: root, or membership of the group
+//!   that owns it (`i2c` on Raspberry Pi OS), or a login since being added.
+//!
+//! The result is a [`PreflightReport`] that prints as a checklist and, when
+//! something failed, doubles as the error.
+
+use crate::board::Board;
+use crate::bus::bus_path;
+use crate::exit::ExitStatus;
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// Boot config locations: Bookworm and later, then older releases.
+const BOOT_CONFIGS: [&str; 2] = ["/boot/firmware/config.txt", "/boot/config.txt"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Pass,
+    /// Couldn't tell, or looks off without being fatal.
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub check: &'static str,
+    pub status: Status,
+    pub message: String,
+    /// What to do about it, for warnings and failures.
+    pub fix: Option<String>,
+    /// Set when the failure is about access rather than missing hardware.
+    pub permission: bool,
+}
+
+impl Finding {
+    fn pass(check: &'static str, message: impl Into<String>) -> Self {
+        Finding {
+            check,
+            status: Status::Pass,
+            message: message.into(),
+            fix: None,
+            permission: false,
+        }
+    }
+
+    fn warn(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Warn,
+            fix: Some(fix.into()),
+            ..Finding::pass(check, message)
+        }
+    }
+
+    fn fail(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Fail,
+            ..Finding::warn(check, message, fix)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightReport {
+    pub bus: u8,
+    pub findings: Vec<Finding>,
+}
+
+impl PreflightReport {
+    /// No check failed; warnings are allowed.
+    pub fn passed(&self) -> bool {
+        self.findings.iter().all(|f| f.status != Status::Fail)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.status == Status::Fail)
+    }
+
+    /// `Ok` if nothing failed, otherwise the report as the error.
+    pub fn into_result(self) -> Result<(), PreflightReport> {
+        if self.passed() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Permission denied if that's what failed, else a bus error.
+    pub fn exit_status(&self) -> ExitStatus {
+        if self.failures().any(|f| f.permission) {
+            ExitStatus::PermissionDenied
+        } else {
+            ExitStatus::BusError
+        }
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "preflight for {}:", bus_path(self.bus).display())?;
+        for finding in &self.findings {
+            let mark = match finding.status {
+                Status::Pass => "✅",
+                Status::Warn => "⚠️ ",
+                Status::Fail => "❌",
+            };
+            write!(f, "\n   {} {}: {}", mark, finding.check, finding.message)?;
+            if let Some(fix) = &finding.fix {
+                write!(f, "\n      → {}", fix)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for PreflightReport {}
+
+/// Everything that has to be true for bus `id` to open.
+pub fn check_bus(id: u8) -> PreflightReport {
+    let mut findings = Vec::new();
+    let node = bus_path(id);
+
+    let driver = Path::new("/sys/class/i2c-dev").exists();
+    findings.push(if driver {
+        Finding::pass("i2c-dev driver", "loaded")
+    } else {
+        Finding::fail(
+            "i2c-dev driver",
+            "not loaded, so no /dev/i2c-* nodes exist",
+            "sudo modprobe i2c-dev, and add i2c-dev to /etc/modules to keep it",
+        )
+    });
+
+    if let Some(finding) = check_device_tree(id) {
+        findings.push(finding);
+    }
+
+    let exists = node.exists();
+    findings.push(if exists {
+        Finding::pass("device node", format!("{} exists", node.display()))
+    } else if id <= 1 {
+        Finding::fail(
+            "device node",
+            format!("{} does not exist", node.display()),
+            "enable I2C with `sudo raspi-config nonint do_i2c 0`, or add dtparam=i2c_arm=on to config.txt, and reboot",
+        )
+    } else {
+        // Pi 4 and 5 have spare controllers; elsewhere the bus has to be bit-banged
+        let enable = Board::current()
+            .and_then(|board| board.i2c_buses().iter().find(|b| b.bus == id))
+            .map_or_else(|| format!("dtoverlay=i2c-gpio,bus={}", id), |b| b.enable.to_string());
+        Finding::fail(
+            "device node",
+            format!("{} does not exist", node.display()),
+            format!("add {} to config.txt and reboot", enable),
+        )
+    });
+
+    if exists {
+        findings.push(check_access(&node));
+    }
+    PreflightReport { bus: id, findings }
+}
+
+/// Only the fixed buses have a known device tree alias; `None` otherwise.
+fn check_device_tree(id: u8) -> Option<Finding> {
+    const CHECK: &str = "device tree";
+    if id > 1 {
+        return None;
+    }
+    let Ok(node) = fs::read_to_string(format!("/proc/device-tree/aliases/i2c{}", id)) else {
+        return Some(Finding::warn(
+            CHECK,
+            format!("no i2c{} alias in /proc/device-tree; is this a Raspberry Pi?", id),
+            "if it is, check that the firmware is up to date",
+        ));
+    };
+    let node = node.trim_end_matches('\0');
+    let status = fs::read_to_string(format!("/proc/device-tree{}/status", node)).unwrap_or_default();
+    let param = if id == 0 { "i2c_vc" } else { "i2c_arm" };
+    let enabled = status.trim_end_matches('\0') == "okay";
+    let configured = boot_config_enables(param);
+    Some(match (enabled, configured) {
+        (true, _) => Finding::pass(CHECK, format!("{} is enabled", node)),
+        (false, true) => Finding::fail(
+            CHECK,
+            format!("dtparam={}=on is in config.txt but {} is still disabled", param, node),
+            "reboot so the firmware picks up config.txt",
+        ),
+        (false, false) => Finding::fail(
+            CHECK,
+            format!("{} is disabled", node),
+            format!("add dtparam={}=on to {} and reboot", param, BOOT_CONFIGS[0]),
+        ),
+    })
+}
+
+/// Whether an uncommented `dtparam=<param>=on` appears in the boot config;
+/// plain `i2c` is the older spelling of `i2c_arm`.
+fn boot_config_enables(param: &str) -> bool {
+    let wanted = format!("{}=on", param);
+    let legacy = param == "i2c_arm";
+    BOOT_CONFIGS
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .any(|text| {
+            text.lines()
+                .map(str::trim)
+                .filter_map(|line| line.strip_prefix("dtparam="))
+                .any(|params| params.split(',').map(str::trim).any(|p| p == wanted || (legacy && p == "i2c=on")))
+        })
+}
+
+fn check_access(node: &Path) -> Finding {
+    const CHECK: &str = "permissions";
+    let Ok(path) = CString::new(node.as_os_str().as_bytes()) else {
+        return Finding::warn(CHECK, "path is not representable", "open it by hand to see why");
+    };
+    // SAFETY: access only reads the NUL-terminated path.
+    if unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) } == 0 {
+        return Finding::pass(CHECK, "readable and writable");
+    }
+
+    let mut finding = match fs::metadata(node).map(|m| m.gid()) {
+        Ok(gid) => {
+            let group = group_name(gid).unwrap_or_else(|| gid.to_string());
+            let user = std::env::var("USER").unwrap_or_else(|_| "$USER".to_string());
+            if group_members(gid).contains(&user) {
+                Finding::fail(
+                    CHECK,
+                    format!("{} is in group '{}', but this session started before that", user, group),
+                    format!("log out and back in (or run `newgrp {}`) to pick up the group", group),
+                )
+            } else {
+                Finding::fail(
+                    CHECK,
+                    format!("{} is owned by group '{}', which {} isn't in", node.display(), group, user),
+                    format!("sudo usermod -aG {} {}, then log out and back in; or run with sudo", group, user),
+                )
+            }
+        }
+        Err(e) => Finding::fail(CHECK, e.to_string(), "run with sudo"),
+    };
+    finding.permission = true;
+    finding
+}
+
+/// Fields of the `/etc/group` line for `gid`: name, then members.
+fn group_entry(gid: u32) -> Option<(String, Vec<String>)> {
+    let text = fs::read_to_string("/etc/group").ok()?;
+    text.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let (name, _, id, members) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+        (id.parse::<u32>().ok()? == gid).then(|| {
+            let members = members.split(',').filter(|m| !m.is_empty()).map(String::from).collect();
+            (name.to_string(), members)
+        })
+    })
+}
+
+fn group_name(gid: u32) -> Option<String> {
+    group_entry(gid).map(|(name, _)| name)
+}
+
+fn group_members(gid: u32) -> Vec<String> {
+    group_entry(gid).map(|(_, members)| members).unwrap_or_default()
+}
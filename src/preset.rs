@@ -0,0 +1,263 @@
+//! Ready-made bus patterns for oscilloscope and logic analyzer work, each
+//! with the scope settings that frame it.
+//!
+//! | preset      | traffic                                                     |
+//! |-------------|-------------------------------------------------------------|
+//! | `rhythm`    | "Happy Birthday" bursts, each followed by an equal silence  |
+//! | `ping`      | one single-byte write, for a single-shot capture            |
+//! | `sweep`     | the payload (or a one-byte read probe) to every 0x08-0x77   |
+//! | `burst`     | 100 bytes in one transaction                                |
+//! | `staircase` | a marker pattern at each of a list of clock speeds (software I2C) |
+//!
+//! `rhythm` runs through [`SimpleI2cTransmitter`](crate::transmitter::SimpleI2cTransmitter);
+//! [`run`] plays the others. [`PresetOptions`] sets the sweep payload, the
+//! staircase speeds, and the spacing between sweep addresses and staircase
+//! steps.
+//!
+//! The staircase doubles as a margin test: [`PresetReport::steps`] records
+//! whether the target still ACKed at each speed.
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use crate::scan;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bytes sent by `burst`: a counting pattern, so dropped bytes stand out.
+pub const BURST_LEN: usize = 100;
+
+/// The single byte `ping` writes; alternating bits make each clock easy to count.
+pub const PING_BYTE: u8 = 0xA5;
+
+/// What `staircase` sends at each step: a start-of-step marker then alternating bits.
+pub const STAIRCASE_MARKER: [u8; 3] = [0xFF, 0x55, 0xAA];
+
+/// Clock speeds `staircase` climbs through by default, in Hz.
+pub const STAIRCASE_SPEEDS: [u32; 5] = [10_000, 25_000, 50_000, 75_000, 100_000];
+
+/// Default gap between `sweep` addresses and between `staircase` steps.
+pub const DEFAULT_SPACING: Duration = Duration::from_millis(2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresetOptions {
+    pub spacing: Duration,
+    /// Bytes `sweep` writes to each address. Empty means a one-byte read
+    /// probe instead, which leaves expanders and EEPROM pointers alone.
+    pub payload: Vec<u8>,
+    /// Clock speeds for `staircase`, in the order they're played.
+    pub speeds: Vec<u32>,
+}
+
+impl Default for PresetOptions {
+    fn default() -> Self {
+        PresetOptions {
+            spacing: DEFAULT_SPACING,
+            payload: Vec::new(),
+            speeds: STAIRCASE_SPEEDS.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preset {
+    #[default]
+    Rhythm,
+    Ping,
+    Sweep,
+    Burst,
+    Staircase,
+}
+
+/// How to set up the scope for a preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScopeSettings {
+    pub timebase: &'static str,
+    pub trigger: &'static str,
+    pub mode: &'static str,
+    pub look_for: &'static [&'static str],
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [Preset::Rhythm, Preset::Ping, Preset::Sweep, Preset::Burst, Preset::Staircase];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Rhythm => "rhythm",
+            Preset::Ping => "ping",
+            Preset::Sweep => "sweep",
+            Preset::Burst => "burst",
+            Preset::Staircase => "staircase",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Preset::Rhythm => "'Happy Birthday' bursts for 2s, each followed by an equal silence",
+            Preset::Ping => "one single-byte write (0xA5) for a single-shot capture",
+            Preset::Sweep => "--payload (default: a one-byte read probe) to every address 0x08-0x77, --spacing apart",
+            Preset::Burst => "100 counting bytes in one transaction",
+            Preset::Staircase => "a marker at each --speeds clock (default 10k, 25k, 50k, 75k, 100k; needs --soft-i2c)",
+        }
+    }
+
+    /// Settings for a 100 kHz bus; scale the timebase with the clock.
+    pub fn scope(self) -> ScopeSettings {
+        match self {
+            Preset::Rhythm => ScopeSettings {
+                timebase: "200ms/div",
+                trigger: "SDA falling edge",
+                mode: "normal",
+                look_for: &[
+                    "rhythmic bursts of I2C activity",
+                    "each burst followed by a quiet period of the same duration",
+                ],
+            },
+            Preset::Ping => ScopeSettings {
+                timebase: "20µs/div",
+                trigger: "SDA falling edge",
+                mode: "single",
+                look_for: &[
+                    "START, 7 address bits, W, ACK slot",
+                    "data 10100101 then the ACK slot and STOP",
+                ],
+            },
+            Preset::Sweep => ScopeSettings {
+                timebase: "50ms/div",
+                trigger: "SDA falling edge",
+                mode: "single",
+                look_for: &[
+                    "112 short frames, one per address",
+                    "SDA held low in the 9th clock where a device ACKs",
+                ],
+            },
+            Preset::Burst => ScopeSettings {
+                timebase: "1ms/div",
+                trigger: "SDA falling edge",
+                mode: "single",
+                look_for: &[
+                    "one unbroken frame of about 9ms",
+                    "clock stretching or gaps between bytes",
+                ],
+            },
+            Preset::Staircase => ScopeSettings {
+                timebase: "2ms/div",
+                trigger: "SDA falling edge",
+                mode: "single",
+                look_for: &[
+                    "one frame per speed, the bit time changing with each step",
+                    "edges rounding off as the clock rises",
+                ],
+            },
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Preset::ALL.into_iter().find(|p| p.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = Preset::ALL.iter().map(|p| p.name()).collect();
+            format!("unknown preset '{}' (expected {})", s, names.join(", ")).into()
+        })
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// What a preset put on the bus.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PresetReport {
+    pub transactions: usize,
+    pub bytes: usize,
+    /// Addresses that ACKed.
+    pub acked: Vec<Address>,
+    /// `staircase` only: each clock speed and whether the target ACKed at it.
+    pub steps: Vec<(u32, bool)>,
+    pub elapsed: Duration,
+}
+
+/// Play `preset` against `target` (ignored by `sweep`). `before` runs
+/// right before each burst, e.g. to pulse a scope trigger. NACKs are
+/// reported, not errors: they're part of what the scope shows.
+pub fn run<I2C, F>(
+    preset: Preset,
+    i2c: &mut I2C,
+    target: Address,
+    options: &PresetOptions,
+    mut before: F,
+) -> Result<PresetReport, Box<dyn Error>>
+where
+    I2C: AddressedI2c + BusControl,
+    F: FnMut() -> Result<(), Box<dyn Error>>,
+{
+    let start = Instant::now();
+    let mut report = PresetReport::default();
+    match preset {
+        Preset::Rhythm => return Err("the rhythm preset runs through the transmitter".into()),
+        Preset::Ping => {
+            before()?;
+            send(i2c, &mut report, target, &[PING_BYTE]);
+        }
+        Preset::Sweep => {
+            before()?;
+            for raw in scan::FIRST_ADDRESS..=scan::LAST_ADDRESS {
+                let address = Address::SevenBit(raw);
+                if options.payload.is_empty() {
+                    report.transactions += 1;
+                    if scan::probe(i2c, address) {
+                        report.acked.push(address);
+                    }
+                } else {
+                    send(i2c, &mut report, address, &options.payload);
+                }
+                thread::sleep(options.spacing);
+            }
+        }
+        Preset::Burst => {
+            let bytes: Vec<u8> = (0..BURST_LEN as u8).collect();
+            before()?;
+            send(i2c, &mut report, target, &bytes);
+        }
+        Preset::Staircase => {
+            if options.speeds.is_empty() {
+                return Err("the staircase needs at least one speed".into());
+            }
+            let original = i2c.clock_speed()?;
+            before()?;
+            let mut climb = || -> Result<(), Box<dyn Error>> {
+                for &hz in &options.speeds {
+                    i2c.set_clock_speed(hz)?;
+                    let acked = send(i2c, &mut report, target, &STAIRCASE_MARKER);
+                    report.steps.push((hz, acked));
+                    thread::sleep(options.spacing);
+                }
+                Ok(())
+            };
+            let result = climb();
+            // Back to the starting clock even if a step failed
+            i2c.set_clock_speed(original)?;
+            result?;
+        }
+    }
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+/// Write `bytes` and count it; returns whether the slave ACKed.
+fn send<I2C: AddressedI2c>(i2c: &mut I2C, report: &mut PresetReport, address: Address, bytes: &[u8]) -> bool {
+    report.transactions += 1;
+    report.bytes += bytes.len();
+    let acked = i2c.write_at(address, bytes).is_ok();
+    if acked && !report.acked.contains(&address) {
+        report.acked.push(address);
+    }
+    acked
+}
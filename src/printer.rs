@@ -0,0 +1,184 @@
+//! ESC/POS thermal receipt printers.
+//!
+//! Covers the command subset nearly every 58/80 mm printer understands: text
+//! styling, barcodes, raster bitmaps, cutting and paper-status queries. The
+//! port is anything `Read + Write`: a [`crate::uart::SerialPort`] for TTL
+//! printers, or `/dev/usb/lp0` opened read/write for USB ones.
+
+use std::error::Error;
+use std::io::{Read, Write};
+
+const ESC: u8 = 0x1B;
+const GS: u8 = 0x1D;
+const DLE: u8 = 0x10;
+const EOT: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+/// Barcode symbologies, numbered as in `GS k` function B.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Barcode {
+    UpcA = 65,
+    UpcE = 66,
+    Ean13 = 67,
+    Ean8 = 68,
+    Code39 = 69,
+    Itf = 70,
+    Codabar = 71,
+    Code93 = 72,
+    Code128 = 73,
+}
+
+/// Where the human-readable digits go relative to a barcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarcodeText {
+    Hidden = 0,
+    Above = 1,
+    Below = 2,
+    Both = 3,
+}
+
+/// Answer to the paper-sensor status query (`DLE EOT 4`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaperStatus {
+    pub near_end: bool,
+    pub out: bool,
+}
+
+pub struct EscPos<P> {
+    port: P,
+}
+
+impl<P: Read + Write> EscPos<P> {
+    pub fn new(port: P) -> Self {
+        EscPos { port }
+    }
+
+    /// Reset the printer to its power-on settings (`ESC @`).
+    pub fn initialize(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send(&[ESC, b'@'])
+    }
+
+    /// Print text without a line break. Characters outside ASCII print as `?`,
+    /// since code pages differ between printer models.
+    pub fn text(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let bytes: Vec<u8> = text
+            .chars()
+            .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+            .collect();
+        self.send(&bytes)
+    }
+
+    pub fn line(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        self.text(text)?;
+        self.send(b"\n")
+    }
+
+    /// Print the buffer and advance `lines` lines (`ESC d`).
+    pub fn feed(&mut self, lines: u8) -> Result<(), Box<dyn Error>> {
+        self.send(&[ESC, b'd', lines])
+    }
+
+    pub fn set_bold(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.send(&[ESC, b'E', on as u8])
+    }
+
+    pub fn set_underline(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.send(&[ESC, b'-', on as u8])
+    }
+
+    pub fn set_align(&mut self, align: Align) -> Result<(), Box<dyn Error>> {
+        self.send(&[ESC, b'a', align as u8])
+    }
+
+    /// Character magnification, 1-8 in each direction (`GS !`).
+    pub fn set_size(&mut self, width: u8, height: u8) -> Result<(), Box<dyn Error>> {
+        if !(1..=8).contains(&width) || !(1..=8).contains(&height) {
+            return Err(format!("text size {}x{} outside 1-8", width, height).into());
+        }
+        self.send(&[GS, b'!', ((width - 1) << 4) | (height - 1)])
+    }
+
+    /// Feed past the cutter and cut (`GS V`), leaving a tab if `partial`.
+    pub fn cut(&mut self, partial: bool) -> Result<(), Box<dyn Error>> {
+        self.send(&[GS, b'V', 65 + partial as u8, 3])
+    }
+
+    /// Print a barcode `height` dots tall with module width 2-6 dots.
+    pub fn barcode(
+        &mut self,
+        kind: Barcode,
+        data: &str,
+        height: u8,
+        module_width: u8,
+        text: BarcodeText,
+    ) -> Result<(), Box<dyn Error>> {
+        if !(2..=6).contains(&module_width) {
+            return Err(format!("barcode module width {} outside 2-6", module_width).into());
+        }
+        let mut payload = data.as_bytes().to_vec();
+        if kind == Barcode::Code128 && !data.starts_with('{') {
+            // CODE128 needs an explicit code set; B covers printable ASCII.
+            payload.splice(0..0, *b"{B");
+        }
+        if payload.is_empty() || payload.len() > 255 {
+            return Err(format!("barcode data must be 1-255 bytes, got {}", payload.len()).into());
+        }
+
+        self.send(&[GS, b'h', height])?;
+        self.send(&[GS, b'w', module_width])?;
+        self.send(&[GS, b'H', text as u8])?;
+        self.send(&[GS, b'k', kind as u8, payload.len() as u8])?;
+        self.send(&payload)
+    }
+
+    /// Print a 1-bit bitmap (`GS v 0`). Rows are packed MSB-first with
+    /// `ceil(width / 8)` bytes per row; a set bit is a black dot.
+    pub fn bitmap(&mut self, width: u16, height: u16, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let row_bytes = width.div_ceil(8);
+        let expected = row_bytes as usize * height as usize;
+        if data.len() != expected {
+            return Err(format!(
+                "{}x{} bitmap needs {} bytes, got {}",
+                width,
+                height,
+                expected,
+                data.len()
+            )
+            .into());
+        }
+        let [xl, xh] = row_bytes.to_le_bytes();
+        let [yl, yh] = height.to_le_bytes();
+        self.send(&[GS, b'v', b'0', 0, xl, xh, yl, yh])?;
+        self.send(data)
+    }
+
+    /// Ask the paper sensors for their state. Needs a port that can read back.
+    pub fn paper_status(&mut self) -> Result<PaperStatus, Box<dyn Error>> {
+        self.send(&[DLE, EOT, 4])?;
+        let mut reply = [0u8];
+        if self.port.read(&mut reply)? == 0 {
+            return Err("printer did not answer the paper status query".into());
+        }
+        Ok(PaperStatus {
+            near_end: reply[0] & 0x0C != 0,
+            out: reply[0] & 0x60 != 0,
+        })
+    }
+
+    /// Give back the port.
+    pub fn release(self) -> P {
+        self.port
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.port.write_all(bytes)?;
+        self.port.flush()?;
+        Ok(())
+    }
+}
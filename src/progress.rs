@@ -0,0 +1,204 @@
+//! Progress for long operations: EEPROM dumps, firmware flashing, rhythm
+//! runs.
+//!
+//! On a terminal, a [`ProgressBar`] is a status line redrawn in place
+//! below whatever else is printed, with the rate and time left:
+//!
+//! ```text
+//! ⏳ flash [██████████░░░░░░░░░░░░░░]  42%  13.4/32.0 KiB  1.9 KiB/s  ETA 10s
+//! ```
+//!
+//! When the stream goes to a file, a pipe or a journal, it prints an
+//! ordinary line every tenth of the way instead, so logs stay readable.
+
+use crate::term::{self, Stream};
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
+
+/// Cells in the bar.
+const WIDTH: usize = 24;
+
+/// The fastest a live bar redraws.
+const REDRAW: Duration = Duration::from_millis(100);
+
+/// What the count is of, for showing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Bytes,
+    /// Fractions of a second; shown as seconds, with no rate.
+    Millis,
+}
+
+impl Unit {
+    fn amount(self, done: u64, total: u64) -> String {
+        match self {
+            Unit::Bytes if total < 1024 => format!("{}/{} B", done, total),
+            Unit::Bytes => format!("{:.1}/{:.1} KiB", done as f64 / 1024.0, total as f64 / 1024.0),
+            Unit::Millis => format!("{:.1}/{:.1} s", done as f64 / 1000.0, total as f64 / 1000.0),
+        }
+    }
+
+    fn rate(self, per_second: f64) -> Option<String> {
+        match self {
+            Unit::Bytes if per_second < 1024.0 => Some(format!("{:.0} B/s", per_second)),
+            Unit::Bytes => Some(format!("{:.1} KiB/s", per_second / 1024.0)),
+            Unit::Millis => None,
+        }
+    }
+}
+
+pub struct ProgressBar {
+    label: String,
+    total: u64,
+    unit: Unit,
+    stream: Stream,
+    live: bool,
+    done: u64,
+    message: String,
+    started: Instant,
+    drawn: Option<Instant>,
+    next_tenth: u64,
+    finished: bool,
+}
+
+impl ProgressBar {
+    /// A bar for `total` of `unit`, live if `stream` is a terminal.
+    pub fn new(label: &str, total: u64, unit: Unit, stream: Stream) -> Self {
+        let live = match stream {
+            Stream::Stdout => io::stdout().is_terminal(),
+            Stream::Stderr => io::stderr().is_terminal(),
+        };
+        ProgressBar {
+            label: label.to_string(),
+            total: total.max(1),
+            unit,
+            stream,
+            live,
+            done: 0,
+            message: String::new(),
+            started: Instant::now(),
+            drawn: None,
+            next_tenth: 1,
+            finished: false,
+        }
+    }
+
+    /// Whether it redraws in place, rather than printing log lines.
+    pub fn is_live(&self) -> bool {
+        self.live
+    }
+
+    pub fn set(&mut self, done: u64) {
+        self.done = done.min(self.total);
+        self.update();
+    }
+
+    pub fn inc(&mut self, n: u64) {
+        self.set(self.done.saturating_add(n));
+    }
+
+    /// A few words shown after the numbers, such as which cycle it's on.
+    pub fn set_message(&mut self, message: impl Into<String>) {
+        self.message = message.into();
+        self.update();
+    }
+
+    /// Leave the bar at its final state, as an ordinary line.
+    pub fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        if self.live {
+            term::set_status(self.stream, None);
+            term::write(&format!("{}\n", self.line()), self.stream);
+        } else if self.done * 10 / self.total < self.next_tenth {
+            // finished short of the next tenth: say where it got to
+            term::write(&format!("{}\n", self.line()), self.stream);
+        }
+    }
+
+    fn update(&mut self) {
+        if self.live {
+            if self.drawn.is_none_or(|at| at.elapsed() >= REDRAW) || self.done == self.total {
+                self.drawn = Some(Instant::now());
+                term::set_status(self.stream, Some(&self.line()));
+            }
+            return;
+        }
+        let tenths = self.done * 10 / self.total;
+        if tenths >= self.next_tenth {
+            self.next_tenth = tenths + 1;
+            term::write(&format!("{}\n", self.line()), self.stream);
+        }
+    }
+
+    fn line(&self) -> String {
+        let fraction = self.done as f64 / self.total as f64;
+        let mut line = format!("⏳ {}", self.label);
+        if self.live {
+            let filled = (fraction * WIDTH as f64).round() as usize;
+            let bar = format!("{}{}", "█".repeat(filled), "░".repeat(WIDTH - filled));
+            if term::color(self.stream) {
+                line.push_str(&format!(" [\x1b[36m{}\x1b[0m]", bar));
+            } else {
+                line.push_str(&format!(" [{}]", bar));
+            }
+        }
+        line.push_str(&format!(" {:>3.0}%  {}", fraction * 100.0, self.unit.amount(self.done, self.total)));
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 && self.done > 0 {
+            let per_second = self.done as f64 / elapsed;
+            if let Some(rate) = self.unit.rate(per_second) {
+                line.push_str(&format!("  {}", rate));
+            }
+            if self.done < self.total {
+                let left = (self.total - self.done) as f64 / per_second;
+                line.push_str(&format!("  ETA {}", eta(Duration::from_secs_f64(left))));
+            } else {
+                line.push_str(&format!("  in {:.1}s", elapsed));
+            }
+        }
+        if !self.message.is_empty() {
+            line.push_str(&format!("  {}", self.message));
+        }
+        line
+    }
+}
+
+/// A bar dropped before [`ProgressBar::finish`] (an error, Ctrl-C) is
+/// left where it got to.
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        if self.live && self.drawn.is_some() {
+            self.finish();
+        }
+    }
+}
+
+/// `left` the way a bar shows time remaining: `42s`, `3m05s`, `1h12m`.
+fn eta(left: Duration) -> String {
+    let secs = left.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_amounts_and_time_left() {
+        assert_eq!(Unit::Bytes.amount(100, 512), "100/512 B");
+        assert_eq!(Unit::Bytes.amount(13_721, 32_768), "13.4/32.0 KiB");
+        assert_eq!(Unit::Millis.amount(1_300, 2_000), "1.3/2.0 s");
+        assert_eq!(Unit::Bytes.rate(1946.0).as_deref(), Some("1.9 KiB/s"));
+        assert_eq!(Unit::Millis.rate(1.0), None);
+        assert_eq!(eta(Duration::from_secs(42)), "42s");
+        assert_eq!(eta(Duration::from_secs(185)), "3m05s");
+        assert_eq!(eta(Duration::from_secs(4_320)), "1h12m");
+    }
+}
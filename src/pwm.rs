@@ -0,0 +1,215 @@
+//! Software PWM on any GPIO.
+//!
+//! The Pi has two hardware PWM channels (four on a Pi 5), on fixed pins.
+//! A [`SoftPwm`] gives any other pin a PWM output from a thread of its
+//! own, good enough to dim an LED or slow a small fan:
+//!
+//! ```no_run
+//! use rpi_peripherals::pwm::SoftPwm;
+//!
+//! let mut led = SoftPwm::from_gpio(23, 500.0, 0.1)?;
+//! led.set_duty(0.75)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Each edge is timed against the start of its period, sleeping most of
+//! the way and spinning through the last [`DEFAULT_SPIN`], so the period
+//! doesn't drift and edges land within a few microseconds on an idle Pi.
+//! The thread asks for `SCHED_FIFO` and a tight timer slack, which keeps
+//! the jitter down under load when it runs as root; without root it runs
+//! at normal priority and still works. Spinning costs CPU in proportion to
+//! the frequency: about a fifth of a core per pin at 1 kHz.
+
+use crate::timing::{self, PreciseDelay, Realtime};
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::Gpio;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Spun at the end of each delay; wake-ups with a 1 µs timer slack are
+/// rarely later than this.
+pub const DEFAULT_SPIN: Duration = Duration::from_micros(100);
+
+/// Above this a period is too short to time from a thread.
+pub const MAX_FREQUENCY: f64 = 20_000.0;
+
+/// Below this a period is over a minute and a half; any slower is a blink
+/// better done by hand.
+pub const MIN_FREQUENCY: f64 = 0.01;
+
+/// What the thread reads every period.
+struct Shared {
+    period_ns: AtomicU64,
+    high_ns: AtomicU64,
+    stop: AtomicBool,
+}
+
+/// A PWM output on one pin. Dropping it stops the thread and leaves the
+/// pin low.
+pub struct SoftPwm<P: Send + 'static> {
+    shared: Arc<Shared>,
+    frequency: f64,
+    duty: f64,
+    thread: Option<JoinHandle<Result<P, String>>>,
+}
+
+impl SoftPwm<rppal::gpio::OutputPin> {
+    /// BCM pin `pin` at `frequency` Hz and `duty` (0.0 to 1.0).
+    pub fn from_gpio(pin: u8, frequency: f64, duty: f64) -> Result<Self, Box<dyn Error>> {
+        let pin = Gpio::new()?
+            .get(pin)
+            .map_err(|e| format!("PWM GPIO {}: {}", pin, e))?
+            .into_output_low();
+        SoftPwm::new(pin, frequency, duty)
+    }
+}
+
+impl<P> SoftPwm<P>
+where
+    P: OutputPin + Send + 'static,
+    P::Error: fmt::Debug,
+{
+    pub fn new(pin: P, frequency: f64, duty: f64) -> Result<Self, Box<dyn Error>> {
+        check_frequency(frequency)?;
+        check_duty(duty)?;
+        let shared = Arc::new(Shared {
+            period_ns: AtomicU64::new(0),
+            high_ns: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        });
+        let mut pwm = SoftPwm {
+            shared: Arc::clone(&shared),
+            frequency,
+            duty,
+            thread: None,
+        };
+        pwm.publish();
+        let thread = thread::Builder::new()
+            .name("soft-pwm".to_string())
+            .spawn(move || run(pin, &shared))?;
+        pwm.thread = Some(thread);
+        Ok(pwm)
+    }
+
+    /// Takes effect from the next period.
+    pub fn set_frequency(&mut self, frequency: f64) -> Result<(), Box<dyn Error>> {
+        check_frequency(frequency)?;
+        self.frequency = frequency;
+        self.publish();
+        Ok(())
+    }
+
+    /// 0.0 holds the pin low, 1.0 high; takes effect from the next period.
+    pub fn set_duty(&mut self, duty: f64) -> Result<(), Box<dyn Error>> {
+        check_duty(duty)?;
+        self.duty = duty;
+        self.publish();
+        Ok(())
+    }
+
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    pub fn duty(&self) -> f64 {
+        self.duty
+    }
+
+    /// Stop the thread with the pin low and hand the pin back, or the pin
+    /// error that stopped the thread early.
+    pub fn stop(mut self) -> Result<P, Box<dyn Error>> {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        let thread = self.thread.take().ok_or("PWM thread already stopped")?;
+        let pin = thread.join().map_err(|_| "PWM thread panicked")??;
+        Ok(pin)
+    }
+
+    fn publish(&self) {
+        let period = Duration::from_secs_f64(1.0 / self.frequency);
+        // High time first, so the thread never sees it above a shorter new period
+        self.shared.high_ns.store(0, Ordering::Relaxed);
+        self.shared.period_ns.store(period.as_nanos() as u64, Ordering::Relaxed);
+        self.shared.high_ns.store(period.mul_f64(self.duty).as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl<P: Send + 'static> Drop for SoftPwm<P> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn check_frequency(frequency: f64) -> Result<(), Box<dyn Error>> {
+    if !(MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
+        return Err(format!("PWM frequency {} Hz is outside {}-{} Hz", frequency, MIN_FREQUENCY, MAX_FREQUENCY).into());
+    }
+    Ok(())
+}
+
+fn check_duty(duty: f64) -> Result<(), Box<dyn Error>> {
+    if !(0.0..=1.0).contains(&duty) {
+        return Err(format!("PWM duty {} is outside 0.0-1.0", duty).into());
+    }
+    Ok(())
+}
+
+fn run<P>(mut pin: P, shared: &Shared) -> Result<P, String>
+where
+    P: OutputPin,
+    P::Error: fmt::Debug,
+{
+    // Both only help; without root they fail and the PWM runs regardless
+    let _ = timing::set_fifo_priority(Realtime::default().priority);
+    // SAFETY: PR_SET_TIMERSLACK takes a plain integer and affects only this thread.
+    unsafe {
+        libc::prctl(libc::PR_SET_TIMERSLACK, 1000 as libc::c_ulong);
+    }
+    let delay = PreciseDelay::new(DEFAULT_SPIN);
+    let level = |pin: &mut P, high: bool| {
+        if high { pin.set_high() } else { pin.set_low() }.map_err(|e| format!("PWM GPIO: {:?}", e))
+    };
+    let mut start = Instant::now();
+    while !shared.stop.load(Ordering::Relaxed) {
+        let period = Duration::from_nanos(shared.period_ns.load(Ordering::Relaxed));
+        let high = Duration::from_nanos(shared.high_ns.load(Ordering::Relaxed)).min(period);
+        if !high.is_zero() {
+            level(&mut pin, true)?;
+        }
+        if high < period {
+            delay.until(start + high);
+            level(&mut pin, false)?;
+        }
+        start += period;
+        // A period missed entirely (the thread was preempted) is dropped
+        // rather than made up with a burst of short ones
+        let now = Instant::now();
+        if now > start + period {
+            start = now;
+        }
+        delay.until(start);
+    }
+    level(&mut pin, false)?;
+    Ok(pin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequency_bounds() {
+        for hz in [MIN_FREQUENCY, 1.0, 440.0, MAX_FREQUENCY] {
+            assert!(check_frequency(hz).is_ok(), "{}", hz);
+        }
+        for hz in [0.0, 1e-300, -1.0, MAX_FREQUENCY + 1.0, f64::NAN, f64::INFINITY] {
+            assert!(check_frequency(hz).is_err(), "{}", hz);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Register maps, and writes that are read back to prove they stuck.
+//!
+//! A plain write that is NACKed fails loudly, but one corrupted on a long
+//! or badly terminated bus goes through and leaves the chip set wrong.
+//! [`write_verify`] reads the register back, retrying as its
+//! [`VerifyPolicy`] says, and fails with [`VerificationFailed`] if it never
+//! reads what was written.
+//!
+//! Some bits never read back as written: reset bits clear themselves,
+//! status flags are read-only, unimplemented bits read 0. A register map
+//! names them so they aren't compared:
+//!
+//! ```toml
+//! [[registers]]
+//! name = "config"
+//! register = 0x00
+//! width = 2
+//! self_clearing = 0x8000
+//! ```
+//!
+//! `self_clearing` is a mask over the register's bytes in the order they go
+//! on the wire, the first byte most significant. The drivers here have
+//! maps built in, through [`RegisterMap::builtin`].
+
+use crate::address::{Address, AddressedI2c};
+use crate::exit::VerificationFailed;
+use crate::parse::serde_helpers;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// Widest register a map entry can describe.
+pub const MAX_WIDTH: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Register {
+    pub name: String,
+    pub register: u8,
+    /// Bytes.
+    #[serde(default = "default_width")]
+    pub width: u8,
+    /// Bits that don't read back what was written.
+    #[serde(default)]
+    pub self_clearing: u32,
+}
+
+fn default_width() -> u8 {
+    1
+}
+
+impl Register {
+    fn new(name: &str, register: u8, width: u8, self_clearing: u32) -> Self {
+        Register {
+            name: name.to_string(),
+            register,
+            width,
+            self_clearing,
+        }
+    }
+
+    /// Per byte, on the wire's order, the bits to compare.
+    pub fn compare_mask(&self) -> Vec<u8> {
+        let bytes = (!self.self_clearing).to_be_bytes();
+        bytes[bytes.len() - usize::from(self.width)..].to_vec()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterMap {
+    #[serde(default)]
+    pub registers: Vec<Register>,
+}
+
+impl RegisterMap {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        text.parse()
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// The writable registers of a driver in `drivers::DRIVERS` whose
+    /// readback differs from what is written; `None` for drivers with
+    /// no registers, or none like that.
+    pub fn builtin(driver: &str) -> Option<RegisterMap> {
+        let registers = match driver {
+            "ina219" => vec![
+                Register::new("config", 0x00, 2, 0x8000),
+                // The LSB is fixed at 0
+                Register::new("calibration", 0x05, 2, 0x0001),
+            ],
+            "ina226" => vec![
+                Register::new("config", 0x00, 2, 0x8000),
+                Register::new("calibration", 0x05, 2, 0x8000),
+                // Alert and conversion-ready flags
+                Register::new("mask/enable", 0x06, 2, 0x001F),
+            ],
+            "mcp23017" => vec![
+                Register::new("IOCON", 0x0A, 1, 0x01),
+                Register::new("IOCON", 0x0B, 1, 0x01),
+            ],
+            _ => return None,
+        };
+        Some(RegisterMap { registers })
+    }
+
+    pub fn get(&self, register: u8) -> Option<&Register> {
+        self.registers.iter().find(|r| r.register == register)
+    }
+
+    /// [`write_verify`], comparing only the bits the map says read back.
+    /// Registers not in the map are compared whole.
+    pub fn write_verify<I2C: AddressedI2c>(
+        &self,
+        i2c: &mut I2C,
+        address: Address,
+        register: u8,
+        value: &[u8],
+        policy: &VerifyPolicy,
+    ) -> Result<Verified, Box<dyn Error>> {
+        let mask = self.get(register).map(Register::compare_mask).unwrap_or_default();
+        write_verify(i2c, address, register, value, &mask, policy)
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for register in &self.registers {
+            if !(1..=MAX_WIDTH).contains(&register.width) {
+                return Err(format!("register '{}': width {} is not 1-{}", register.name, register.width, MAX_WIDTH).into());
+            }
+            if register.width < MAX_WIDTH && register.self_clearing >> (8 * u32::from(register.width)) != 0 {
+                return Err(format!(
+                    "register '{}': self_clearing 0x{:X} is wider than {} bytes",
+                    register.name, register.self_clearing, register.width
+                )
+                .into());
+            }
+            if let Some(other) = self.registers.iter().find(|r| r.register == register.register && r.name != register.name) {
+                return Err(format!("'{}' and '{}' are both register 0x{:02X}", register.name, other.name, register.register).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RegisterMap {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let map: RegisterMap = toml::from_str(s)?;
+        map.validate()?;
+        Ok(map)
+    }
+}
+
+/// How hard [`write_verify`] tries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyPolicy {
+    /// Writes in all, the first included.
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    /// Pause before each retry, for a chip that is busy or a bus that is
+    /// settling.
+    #[serde(default = "default_delay", deserialize_with = "serde_helpers::duration")]
+    pub delay: Duration,
+}
+
+fn default_attempts() -> u32 {
+    3
+}
+
+fn default_delay() -> Duration {
+    Duration::from_millis(2)
+}
+
+impl Default for VerifyPolicy {
+    fn default() -> Self {
+        VerifyPolicy {
+            attempts: default_attempts(),
+            delay: default_delay(),
+        }
+    }
+}
+
+/// A write that read back right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verified {
+    /// Writes it took; more than one means the wiring is marginal.
+    pub attempts: u32,
+}
+
+impl Verified {
+    pub fn retried(&self) -> bool {
+        self.attempts > 1
+    }
+}
+
+/// Write `value` to `register`, read it back and compare under `mask`
+/// (per byte; missing bytes are compared whole), until it matches or the
+/// policy's attempts run out. NACKs and failed reads are retried too. If
+/// the last try read back wrong the error is [`VerificationFailed`];
+/// otherwise it is that try's bus error.
+pub fn write_verify<I2C: AddressedI2c>(
+    i2c: &mut I2C,
+    address: Address,
+    register: u8,
+    value: &[u8],
+    mask: &[u8],
+    policy: &VerifyPolicy,
+) -> Result<Verified, Box<dyn Error>> {
+    if value.is_empty() {
+        return Err("write_verify needs at least one byte to write".into());
+    }
+    let mut frame = vec![register];
+    frame.extend_from_slice(value);
+    let mut readback = vec![0; value.len()];
+    let attempts = policy.attempts.max(1);
+    let mut last = Err::<(), Box<dyn Error>>("no attempt made".into());
+    for attempt in 1..=attempts {
+        if attempt > 1 {
+            thread::sleep(policy.delay);
+        }
+        last = i2c
+            .write_at(address, &frame)
+            .and_then(|()| i2c.write_read_at(address, &[register], &mut readback));
+        if last.is_err() {
+            continue;
+        }
+        let same = value
+            .iter()
+            .zip(&readback)
+            .enumerate()
+            .all(|(n, (w, r))| (w ^ r) & mask.get(n).copied().unwrap_or(0xFF) == 0);
+        if same {
+            return Ok(Verified { attempts: attempt });
+        }
+    }
+    // The last try decides: a bus error, or a readback that didn't match
+    last?;
+    let mut details = format!(
+        "{} register 0x{:02X}: wrote {:02X?}, read back {:02X?} after {} attempts",
+        address, register, value, readback, attempts
+    );
+    if mask.iter().any(|&m| m != 0xFF) {
+        details.push_str(&format!(" (comparing {:02X?})", mask));
+    }
+    Err(VerificationFailed { details }.into())
+}
@@ -0,0 +1,137 @@
+//! Running transactions on another Pi's bus over TCP.
+//!
+//! `proxy` on the Pi with the hardware serves its bus; [`RemoteBus`] on a
+//! laptop connects to it and implements the same bus traits as a local
+//! bus, so drivers, scripts and the CLI (`--remote pi.local:7700`) run as
+//! they would on the Pi itself, without deploying a build for every change.
+//!
+//! The protocol is one JSON object per line in each direction. The client
+//! opens with a hello carrying the protocol version and, if the proxy was
+//! started with `--tokens`, a control-scope token:
+//!
+//! ```text
+//! → {"op":"hello","version":1,"token":"3f9c..."}
+//! ← {"clock":100000}
+//! → {"op":"transaction","address":"0x68","ops":[{"write":[0]},{"read":3}]}
+//! ← {"reads":[[48,89,35]]}
+//! → {"op":"transaction","address":"0x50","ops":[{"write":[0]}]}
+//! ← {"error":"I2C NACK: address","kind":"nack_address"}
+//! ```
+//!
+//! Every transaction is a round trip, so expect a millisecond or so of
+//! network on top of the bus time; fine for bring-up, not for timing work.
+//! The proxy serves one client at a time.
+//!
+//! There is no TLS, so `proxy` listens on loopback unless told
+//! `--allow-plaintext`, and then only with `--tokens`; from elsewhere, reach
+//! it through an SSH tunnel.
+
+mod client;
+mod proxy;
+
+pub use client::RemoteBus;
+pub use proxy::serve;
+
+use crate::address::Address;
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+use serde::{Deserialize, Serialize};
+
+/// Bumped when either side would misread the other.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Port `proxy` listens on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 7700;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+enum Request {
+    Hello {
+        version: u32,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    Transaction {
+        address: Address,
+        ops: Vec<Op>,
+    },
+    SetTimeout {
+        ms: u64,
+    },
+    SetClockSpeed {
+        hz: u32,
+    },
+    Recover,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Op {
+    Write(Vec<u8>),
+    /// Byte count.
+    Read(usize),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Reply {
+    /// One buffer per read op, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    reads: Vec<Vec<u8>>,
+    /// Bus clock in Hz, after a hello or a speed change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    clock: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<Kind>,
+}
+
+impl Reply {
+    fn error(message: impl ToString, kind: Kind) -> Self {
+        Reply {
+            error: Some(message.to_string()),
+            kind: Some(kind),
+            ..Reply::default()
+        }
+    }
+}
+
+/// [`ErrorKind`] on the wire, so a NACK stays a NACK across the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Bus,
+    ArbitrationLoss,
+    NackAddress,
+    NackData,
+    Nack,
+    Overrun,
+    Other,
+}
+
+impl From<ErrorKind> for Kind {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::Bus => Kind::Bus,
+            ErrorKind::ArbitrationLoss => Kind::ArbitrationLoss,
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => Kind::NackAddress,
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => Kind::NackData,
+            ErrorKind::NoAcknowledge(_) => Kind::Nack,
+            ErrorKind::Overrun => Kind::Overrun,
+            _ => Kind::Other,
+        }
+    }
+}
+
+impl From<Kind> for ErrorKind {
+    fn from(kind: Kind) -> Self {
+        match kind {
+            Kind::Bus => ErrorKind::Bus,
+            Kind::ArbitrationLoss => ErrorKind::ArbitrationLoss,
+            Kind::NackAddress => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            Kind::NackData => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+            Kind::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Kind::Overrun => ErrorKind::Overrun,
+            Kind::Other => ErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use super::{Kind, Op, Reply, Request, PROTOCOL_VERSION};
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, Operation};
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// How long to wait for the proxy before giving up on it; longer than any
+/// transaction should take, bus timeout included.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A transaction the proxy or its bus refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteError {
+    pub message: String,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "remote: {}", self.message)
+    }
+}
+
+impl Error for RemoteError {}
+
+impl i2c::Error for RemoteError {
+    fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+/// The bus of a Pi running `proxy`.
+pub struct RemoteBus {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    peer: String,
+    /// As the proxy last reported it.
+    clock: u32,
+}
+
+impl RemoteBus {
+    /// Connect to `address` (`host:port`) and say hello, with `token` if
+    /// the proxy checks them.
+    pub fn connect(address: &str, token: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let stream = TcpStream::connect(address).map_err(|e| format!("cannot reach proxy at {}: {}", address, e))?;
+        // Transactions are small and each waits for its reply
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        let mut bus = RemoteBus {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            peer: address.to_string(),
+            clock: 0,
+        };
+        let reply = bus.call(&Request::Hello {
+            version: PROTOCOL_VERSION,
+            token: token.map(String::from),
+        })?;
+        bus.clock = reply.clock.unwrap_or(0);
+        Ok(bus)
+    }
+
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    fn call(&mut self, request: &Request) -> Result<Reply, RemoteError> {
+        let lost = |e: &dyn fmt::Display| RemoteError {
+            message: format!("connection to {} lost: {}", self.peer, e),
+            kind: ErrorKind::Other,
+        };
+        let mut line = serde_json::to_string(request).map_err(|e| lost(&e))?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).map_err(|e| lost(&e))?;
+        line.clear();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => return Err(lost(&"closed by the proxy")),
+            Ok(_) => {}
+            Err(e) => return Err(lost(&e)),
+        }
+        let reply: Reply = serde_json::from_str(&line).map_err(|e| lost(&e))?;
+        match reply.error {
+            Some(message) => Err(RemoteError {
+                message,
+                kind: reply.kind.unwrap_or(Kind::Other).into(),
+            }),
+            None => Ok(reply),
+        }
+    }
+
+    fn run(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), RemoteError> {
+        let ops = operations
+            .iter()
+            .map(|op| match op {
+                Operation::Write(bytes) => Op::Write(bytes.to_vec()),
+                Operation::Read(buf) => Op::Read(buf.len()),
+            })
+            .collect();
+        let reply = self.call(&Request::Transaction { address, ops })?;
+        let mut reads = reply.reads.into_iter();
+        for op in operations.iter_mut() {
+            if let Operation::Read(buf) = op {
+                match reads.next() {
+                    Some(bytes) if bytes.len() == buf.len() => buf.copy_from_slice(&bytes),
+                    _ => {
+                        return Err(RemoteError {
+                            message: "reply doesn't match the reads asked for".into(),
+                            kind: ErrorKind::Other,
+                        })
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ErrorType for RemoteBus {
+    type Error = RemoteError;
+}
+
+impl I2c for RemoteBus {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), RemoteError> {
+        self.run(Address::SevenBit(address), operations)
+    }
+}
+
+impl AddressedI2c for RemoteBus {
+    fn transaction_at(&mut self, address: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+        Ok(self.run(address, operations)?)
+    }
+}
+
+impl BusControl for RemoteBus {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        Ok(self.clock)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let ms = u64::try_from(timeout.as_millis()).map_err(|_| "timeout too long")?;
+        self.call(&Request::SetTimeout { ms })?;
+        Ok(())
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        let reply = self.call(&Request::SetClockSpeed { hz })?;
+        self.clock = reply.clock.unwrap_or(hz);
+        Ok(())
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        self.call(&Request::Recover)?;
+        Ok(())
+    }
+}
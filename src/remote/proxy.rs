@@ -0,0 +1,204 @@
+use super::{Kind, Op, Reply, Request, PROTOCOL_VERSION};
+use crate::address::{Address, AddressedI2c};
+use crate::auth::{Scope, TokenStore};
+use crate::bus::BusControl;
+use crate::say;
+use embedded_hal::i2c::{Error as _, I2c, Operation};
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Accept-loop poll interval while waiting for a client or a shutdown.
+const POLL: Duration = Duration::from_millis(50);
+
+/// A client idle this long is dropped so the next one can connect.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Largest read a client may ask for, as over HTTP.
+const MAX_READ: usize = 4096;
+
+/// Longest request line, newline included; longer ones end the session.
+/// Room for a transaction of several [`MAX_READ`]-sized writes.
+const MAX_LINE: usize = 64 * 1024;
+
+/// How often a blocked read wakes up to check for a shutdown.
+const READ_WAKE: Duration = Duration::from_secs(1);
+
+/// Serve `i2c` to one client after another until `stop` is set. With
+/// `tokens`, clients need a control-scope token in their hello.
+pub fn serve<I2C>(i2c: &mut I2C, listener: &TcpListener, tokens: Option<&TokenStore>, stop: &AtomicBool) -> Result<(), Box<dyn Error>>
+where
+    I2C: I2c + AddressedI2c + BusControl,
+    I2C::Error: Error + 'static,
+{
+    listener.set_nonblocking(true)?;
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                say!("🔌 {} connected", peer);
+                match session(i2c, stream, tokens, stop) {
+                    Ok(count) => say!("🔌 {} disconnected after {} transactions", peer, count),
+                    Err(e) => say!("⚠️  {}: {}", peer, e),
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// One client, until it hangs up; returns the transactions it ran.
+fn session<I2C>(i2c: &mut I2C, stream: TcpStream, tokens: Option<&TokenStore>, stop: &AtomicBool) -> Result<u64, Box<dyn Error>>
+where
+    I2C: I2c + AddressedI2c + BusControl,
+    I2C::Error: Error + 'static,
+{
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(READ_WAKE))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = String::new();
+    let mut greeted = false;
+    let mut count = 0;
+    let mut idle = Duration::ZERO;
+    while !stop.load(Ordering::Relaxed) {
+        // A timed-out read keeps what it got, so only clear after a full
+        // line; the cap counts what an earlier timed-out read kept
+        let room = (MAX_LINE + 1 - line.len()) as u64;
+        match (&mut reader).take(room).read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => idle = Duration::ZERO,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                idle += READ_WAKE;
+                if idle >= IDLE_TIMEOUT {
+                    return Err(format!("idle for {}s, dropped", IDLE_TIMEOUT.as_secs()).into());
+                }
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if line.len() > MAX_LINE {
+            let reply = Reply::error(format!("request longer than {} bytes", MAX_LINE), Kind::Other);
+            writer.write_all(format!("{}\n", serde_json::to_string(&reply)?).as_bytes())?;
+            return Err(format!("request longer than {} bytes, dropped", MAX_LINE).into());
+        }
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Err(e) => Reply::error(format!("bad request: {}", e), Kind::Other),
+            Ok(Request::Hello { version, token }) => {
+                let reply = hello(i2c, version, token.as_deref(), tokens);
+                greeted = reply.error.is_none();
+                reply
+            }
+            Ok(_) if !greeted => Reply::error("say hello first", Kind::Other),
+            Ok(request) => {
+                if let Request::Transaction { .. } = request {
+                    count += 1;
+                }
+                handle(i2c, request)
+            }
+        };
+        line.clear();
+        let mut out = serde_json::to_string(&reply)?;
+        out.push('\n');
+        writer.write_all(out.as_bytes())?;
+        if !greeted {
+            // A refused hello ends the session
+            break;
+        }
+    }
+    Ok(count)
+}
+
+fn hello<I2C: BusControl>(i2c: &I2C, version: u32, token: Option<&str>, tokens: Option<&TokenStore>) -> Reply {
+    if version != PROTOCOL_VERSION {
+        return Reply::error(
+            format!("protocol version {} not supported (proxy speaks {})", version, PROTOCOL_VERSION),
+            Kind::Other,
+        );
+    }
+    if let Some(tokens) = tokens {
+        let header = token.map(|t| format!("Bearer {}", t));
+        if let Err(e) = tokens.authorize(header.as_deref(), Scope::Control) {
+            return Reply::error(e, Kind::Other);
+        }
+    }
+    Reply {
+        clock: i2c.clock_speed().ok(),
+        ..Reply::default()
+    }
+}
+
+fn handle<I2C>(i2c: &mut I2C, request: Request) -> Reply
+where
+    I2C: I2c + AddressedI2c + BusControl,
+    I2C::Error: Error + 'static,
+{
+    match request {
+        Request::Hello { .. } => Reply::error("already said hello", Kind::Other),
+        Request::Transaction { address, ops } => transaction(i2c, address, ops),
+        Request::SetTimeout { ms } => done(i2c.set_timeout(Duration::from_millis(ms))),
+        Request::SetClockSpeed { hz } => match i2c.set_clock_speed(hz) {
+            Ok(()) => Reply {
+                clock: i2c.clock_speed().ok(),
+                ..Reply::default()
+            },
+            Err(e) => Reply::error(e, Kind::Other),
+        },
+        Request::Recover => done(i2c.recover()),
+    }
+}
+
+fn transaction<I2C>(i2c: &mut I2C, address: Address, ops: Vec<Op>) -> Reply
+where
+    I2C: I2c + AddressedI2c,
+    I2C::Error: Error + 'static,
+{
+    let mut writes = Vec::new();
+    let mut reads = Vec::new();
+    for op in ops {
+        match op {
+            Op::Write(bytes) => writes.push((reads.len(), bytes)),
+            Op::Read(count) if count > MAX_READ => {
+                return Reply::error(format!("read of {} bytes too long (max {})", count, MAX_READ), Kind::Other)
+            }
+            Op::Read(count) => reads.push(vec![0; count]),
+        }
+    }
+    // Rebuild the operations in order: each write sits before the read
+    // whose index it was recorded with
+    let result = {
+        let mut writes = writes.iter().peekable();
+        let mut operations = Vec::new();
+        for (n, buf) in reads.iter_mut().enumerate() {
+            while let Some((_, bytes)) = writes.next_if(|(at, _)| *at == n) {
+                operations.push(Operation::Write(bytes));
+            }
+            operations.push(Operation::Read(buf));
+        }
+        operations.extend(writes.map(|(_, bytes)| Operation::Write(bytes)));
+        match address {
+            // Through embedded-hal, which keeps NACKs apart from other failures
+            Address::SevenBit(a) => i2c.transaction(a, &mut operations).map_err(|e| Reply::error(&e, e.kind().into())),
+            Address::TenBit(_) => i2c.transaction_at(address, &mut operations).map_err(|e| Reply::error(e, Kind::Other)),
+        }
+    };
+    match result {
+        Ok(()) => Reply {
+            reads,
+            ..Reply::default()
+        },
+        Err(reply) => reply,
+    }
+}
+
+fn done(result: Result<(), Box<dyn Error>>) -> Reply {
+    match result {
+        Ok(()) => Reply::default(),
+        Err(e) => Reply::error(e, Kind::Other),
+    }
+}
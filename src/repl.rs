@@ -0,0 +1,216 @@
+//! Interactive shell for poking at a bus.
+//!
+//! ```text
+//! i2c> scan
+//! 0x27 0x68
+//! i2c> w 0x27 0xFF 0x01
+//! i2c> r 0x68 7
+//! 30 59 23 02 14 10 26
+//! i2c> wr 0x68 0x00 3
+//! 30 59 23
+//! i2c> sleep 100
+//! ```
+//!
+//! Numbers take `0x`/`0b` prefixes or plain decimal; addresses also take
+//! `10:0x123` for 10-bit. Tab completes command names and, after a `scan`,
+//! the addresses it found.
+
+use crate::address::{Address, AddressedI2c};
+use crate::parse;
+use crate::say;
+use crate::scan;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Context, Editor, Helper, Highlighter, Hinter, Validator};
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// Command names with their usage, for `help` and completion.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("scan", "scan                      list responding 7-bit addresses"),
+    ("w", "w ADDR BYTE...            write bytes"),
+    ("r", "r ADDR COUNT              read COUNT bytes"),
+    ("wr", "wr ADDR BYTE... COUNT     write then read with a repeated start"),
+    ("sleep", "sleep MS|DURATION         pause, e.g. sleep 100 or sleep 1.5s"),
+    ("help", "help                      show this list"),
+    ("quit", "quit                      leave (Ctrl-D works too)"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplCommand {
+    Scan,
+    Write(Address, Vec<u8>),
+    Read(Address, usize),
+    WriteRead(Address, Vec<u8>, usize),
+    Sleep(Duration),
+    Help,
+    Quit,
+}
+
+impl FromStr for ReplCommand {
+    type Err = Box<dyn Error>;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (&name, args) = words.split_first().ok_or("empty command")?;
+        let usage = || -> Box<dyn Error> {
+            let usage = COMMANDS.iter().find(|(n, _)| *n == name).map_or("", |(_, u)| u);
+            format!("usage: {}", usage.split("  ").next().unwrap_or(usage)).into()
+        };
+        let command = match (name, args) {
+            ("scan", []) => ReplCommand::Scan,
+            ("w", [address, bytes @ ..]) if !bytes.is_empty() => {
+                ReplCommand::Write(address.parse()?, bytes.iter().map(|b| parse::byte(b)).collect::<Result<_, _>>()?)
+            }
+            ("r", [address, count]) => ReplCommand::Read(address.parse()?, parse::byte_count(count)?),
+            ("wr", [address, bytes @ .., count]) if !bytes.is_empty() => ReplCommand::WriteRead(
+                address.parse()?,
+                bytes.iter().map(|b| parse::byte(b)).collect::<Result<_, _>>()?,
+                parse::byte_count(count)?,
+            ),
+            ("sleep", [time]) => ReplCommand::Sleep(match time.parse::<u64>() {
+                Ok(ms) => Duration::from_millis(ms),
+                Err(_) => parse::duration(time)?,
+            }),
+            ("help" | "?", []) => ReplCommand::Help,
+            ("quit" | "exit", []) => ReplCommand::Quit,
+            _ if COMMANDS.iter().any(|(n, _)| *n == name) => return Err(usage()),
+            _ => return Err(format!("unknown command '{}' (try help)", name).into()),
+        };
+        Ok(command)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Run one command and return what to print. `Quit` and `Help` are left to
+/// the caller.
+pub fn execute<I2C: AddressedI2c>(i2c: &mut I2C, command: &ReplCommand) -> Result<String, Box<dyn Error>> {
+    match command {
+        ReplCommand::Scan => {
+            let found = scan::scan(i2c);
+            if found.is_empty() {
+                return Ok("no devices".to_string());
+            }
+            Ok(found.iter().map(Address::to_string).collect::<Vec<_>>().join(" "))
+        }
+        ReplCommand::Write(address, bytes) => {
+            i2c.write_at(*address, bytes)?;
+            Ok(format!("wrote {} bytes", bytes.len()))
+        }
+        ReplCommand::Read(address, count) => {
+            let mut buf = vec![0; *count];
+            i2c.read_at(*address, &mut buf)?;
+            Ok(hex(&buf))
+        }
+        ReplCommand::WriteRead(address, bytes, count) => {
+            let mut buf = vec![0; *count];
+            i2c.write_read_at(*address, bytes, &mut buf)?;
+            Ok(hex(&buf))
+        }
+        ReplCommand::Sleep(duration) => {
+            thread::sleep(*duration);
+            Ok(String::new())
+        }
+        ReplCommand::Help | ReplCommand::Quit => Ok(String::new()),
+    }
+}
+
+/// Completes command names, then addresses seen in the last `scan`.
+#[derive(Helper, Hinter, Highlighter, Validator, Default)]
+struct ReplHelper {
+    addresses: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let candidates: Vec<String> = if start == 0 {
+            COMMANDS
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .filter(|name| name.starts_with(word))
+                .collect()
+        } else if before[..start].split_whitespace().count() == 1 {
+            self.addresses.iter().filter(|a| a.starts_with(word)).cloned().collect()
+        } else {
+            Vec::new()
+        };
+        Ok((start, candidates))
+    }
+}
+
+/// Read commands until `quit` or Ctrl-D, keeping history in `history` if given.
+/// Errors from a command are printed and the shell carries on.
+pub fn run<I2C: AddressedI2c>(i2c: &mut I2C, history: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper::default()));
+    if let Some(path) = history {
+        // No history yet on first use
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        let line = match editor.readline("i2c> ") {
+            Ok(line) => line,
+            // Ctrl-C clears the line, like a shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str())?;
+
+        let command = match line.parse::<ReplCommand>() {
+            Ok(command) => command,
+            Err(e) => {
+                say!("❌ {}", e);
+                continue;
+            }
+        };
+        match command {
+            ReplCommand::Quit => break,
+            ReplCommand::Help => {
+                for (_, usage) in COMMANDS {
+                    say!("  {}", usage);
+                }
+            }
+            _ => match execute(i2c, &command) {
+                Ok(output) => {
+                    if command == ReplCommand::Scan {
+                        if let Some(helper) = editor.helper_mut() {
+                            helper.addresses = output
+                                .split_whitespace()
+                                .filter(|word| word.parse::<Address>().is_ok())
+                                .map(str::to_string)
+                                .collect();
+                        }
+                    }
+                    if !output.is_empty() {
+                        say!("{}", output);
+                    }
+                }
+                Err(e) => say!("❌ {}", e),
+            },
+        }
+    }
+
+    if let Some(path) = history {
+        if let Err(e) = editor.save_history(path) {
+            say!("⚠️  Could not save history to {}: {}", path.display(), e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! RS-485 half duplex: a UART and a transceiver such as the MAX485 whose
+//! driver is switched on only while this end talks.
+//!
+//! DE and /RE are usually tied together to one GPIO: high drives the
+//! bus, low listens. [`Rs485`] raises it before each write and drops it once
+//! the last stop bit is on the wire. The kernel's drain returns when its
+//! buffer is empty, but the UART's FIFO and shift register still hold up
+//! to a character, so the line is held one character time more before the
+//! post delay. Drop it any sooner and the last byte is cut short; keep it
+//! up too long and the first byte of a fast reply collides with it.
+//!
+//! ```no_run
+//! use rpi_peripherals::rs485::Rs485;
+//! use std::io::{Read, Write};
+//!
+//! let mut bus = Rs485::open("/dev/serial0", 9600, 17)?;
+//! bus.write_all(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A])?;
+//! let mut reply = [0; 7];
+//! bus.read_exact(&mut reply)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::timing::PreciseDelay;
+use crate::uart::SerialPort;
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::{Gpio, OutputPin as GpioOutput};
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Bits a character takes on the wire: start, 8 data, stop.
+const BITS_PER_CHARACTER: u32 = 10;
+
+/// A half-duplex port: `port` for the data and `de` for direction.
+pub struct Rs485<P, DE> {
+    port: P,
+    de: DE,
+    character_time: Duration,
+    pre_delay: Duration,
+    post_delay: Duration,
+    delay: PreciseDelay,
+}
+
+impl Rs485<SerialPort, GpioOutput> {
+    /// `path` at `baud_rate` 8N1 with DE/RE on BCM pin `de`.
+    pub fn open<T: AsRef<Path>>(path: T, baud_rate: u32, de: u8) -> Result<Self, Box<dyn Error>> {
+        let port = SerialPort::open(path, baud_rate)?;
+        let de = Gpio::new()?.get(de).map_err(|e| format!("RS-485 DE GPIO {}: {}", de, e))?.into_output_low();
+        Rs485::new(port, de, baud_rate)
+    }
+}
+
+impl<P, DE> Rs485<P, DE>
+where
+    P: Read + Write,
+    DE: OutputPin,
+    DE::Error: Error + 'static,
+{
+    /// Starts listening. `baud_rate` is what `port` runs at, for the
+    /// turnaround.
+    pub fn new(port: P, mut de: DE, baud_rate: u32) -> Result<Self, Box<dyn Error>> {
+        if baud_rate == 0 {
+            return Err("baud rate must be above 0".into());
+        }
+        de.set_low()?;
+        Ok(Rs485 {
+            port,
+            de,
+            character_time: Duration::from_secs(u64::from(BITS_PER_CHARACTER)) / baud_rate,
+            pre_delay: Duration::ZERO,
+            post_delay: Duration::ZERO,
+            delay: PreciseDelay::default(),
+        })
+    }
+
+    /// Driver on to first bit, for transceivers or long lines slow to settle.
+    pub fn set_pre_delay(&mut self, delay: Duration) {
+        self.pre_delay = delay;
+    }
+
+    /// Extra time the driver stays on after the last stop bit, for
+    /// receivers that need the line held idle before they answer.
+    pub fn set_post_delay(&mut self, delay: Duration) {
+        self.post_delay = delay;
+    }
+
+    /// How long one character takes at the baud rate.
+    pub fn character_time(&self) -> Duration {
+        self.character_time
+    }
+
+    /// Send `bytes` with the driver on, then go back to listening.
+    pub fn transmit(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.de.set_high()?;
+        self.delay.delay(self.pre_delay);
+        let result = self.port.write_all(bytes).and_then(|()| self.port.flush());
+        if result.is_ok() {
+            // The last character can still be in the shift register
+            self.delay.delay(self.character_time + self.post_delay);
+        }
+        // Listen again even after a failed write, so the bus isn't held
+        self.de.set_low()?;
+        Ok(result?)
+    }
+
+    /// [`Self::transmit`] `request`, then read the reply: whatever arrives
+    /// until the line has been quiet for `gap`, stopping at `max` bytes or
+    /// after `timeout` with nothing at all.
+    pub fn request(&mut self, request: &[u8], max: usize, gap: Duration, timeout: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.transmit(request)?;
+        let mut reply = Vec::new();
+        let mut buffer = [0; 256];
+        let start = Instant::now();
+        let mut last = None;
+        while reply.len() < max {
+            let quiet = match last {
+                Some(at) => Instant::now().duration_since(at) >= gap,
+                None => start.elapsed() >= timeout,
+            };
+            if quiet {
+                break;
+            }
+            let want = buffer.len().min(max - reply.len());
+            match self.port.read(&mut buffer[..want]) {
+                Ok(0) => {}
+                Ok(n) => {
+                    reply.extend_from_slice(&buffer[..n]);
+                    last = Some(Instant::now());
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(reply)
+    }
+
+    pub fn port(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    pub fn release(self) -> (P, DE) {
+        (self.port, self.de)
+    }
+}
+
+impl<P, DE> Read for Rs485<P, DE>
+where
+    P: Read + Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+}
+
+/// Each write is a whole transmission, turnaround included, so drivers
+/// written for a plain serial port work unchanged.
+impl<P, DE> Write for Rs485<P, DE>
+where
+    P: Read + Write,
+    DE: OutputPin,
+    DE::Error: Error + 'static,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.transmit(buf).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
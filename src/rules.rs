@@ -0,0 +1,571 @@
+//! Rules: conditions on measurements that set off actions.
+//!
+//! Each rule compares two [`Expr`]s over measurement names, in the units
+//! the history stores (°C, hPa, A), and lists what to do when it starts
+//! holding:
+//!
+//! ```toml
+//! [[rules]]
+//! name = "too hot"
+//! when = "bme280.temperature > 30"
+//! clear = "bme280.temperature < 29"   # stay fired until this; default: until `when` fails
+//! repeat = "10m"                      # fire again while it still holds
+//! actions = ["flash", "buzz alarm", "gpio 17 high", "mqtt", "run /usr/local/bin/fan on"]
+//!
+//! [[rules]]
+//! name = "overcurrent"
+//! when = "ina219.current > 1.0"
+//! actions = ["buzz triple", "gpio 22 toggle"]
+//! ```
+//!
+//! The actions are:
+//!
+//! | action | does |
+//! |--------|------|
+//! | `flash` | blinks the LCD backlight |
+//! | `buzz <pattern>` | plays a [`BeepPattern`] on the `[alerts.buzzer]` |
+//! | `gpio <pin> high\|low\|toggle` | drives a BCM pin; `high` and `low` go back when the rule clears |
+//! | `mqtt` | publishes a `rule` event when it fires and when it clears |
+//! | `run <command>` | runs a shell command with `RULE`, `RULE_STATE` and `RULE_VALUE` set |
+//!
+//! [`Rules::evaluate`] checks every rule against the latest values in the
+//! [`History`], so watches work in conditions too, and hands what changed
+//! to its [`Outputs`]. A rule whose measurements have no value yet does
+//! nothing.
+
+use crate::alert::{BeepPattern, Buzzer};
+use crate::expr::Expr;
+use crate::history::History;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::Publisher;
+use crate::parse::serde_helpers;
+use crate::{esay, say};
+use embedded_hal::digital::StatefulOutputPin;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Above,
+    AtLeast,
+    Below,
+    AtMost,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Above => ">",
+            Comparison::AtLeast => ">=",
+            Comparison::Below => "<",
+            Comparison::AtMost => "<=",
+        }
+    }
+
+    fn holds(self, a: f64, b: f64) -> bool {
+        match self {
+            Comparison::Above => a > b,
+            Comparison::AtLeast => a >= b,
+            Comparison::Below => a < b,
+            Comparison::AtMost => a <= b,
+        }
+    }
+}
+
+/// `left <op> right`, such as `bme280.temperature > 30`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Condition {
+    pub left: Expr,
+    pub comparison: Comparison,
+    pub right: Expr,
+}
+
+impl Condition {
+    /// Whether it holds, and the left side's value; `None` while a
+    /// measurement has no value.
+    pub fn check(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<(bool, f64)> {
+        let (a, b) = (self.left.eval(lookup)?, self.right.eval(lookup)?);
+        Some((self.comparison.holds(a, b), a))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let at = s
+            .find(['<', '>'])
+            .ok_or_else(|| format!("condition '{}' needs a comparison (>, >=, < or <=)", s))?;
+        let (left, rest) = s.split_at(at);
+        let (comparison, right) = match rest.as_bytes() {
+            [b'>', b'=', ..] => (Comparison::AtLeast, &rest[2..]),
+            [b'<', b'=', ..] => (Comparison::AtMost, &rest[2..]),
+            [b'>', ..] => (Comparison::Above, &rest[1..]),
+            _ => (Comparison::Below, &rest[1..]),
+        };
+        let side = |text: &str| text.parse::<Expr>().map_err(|e| format!("condition '{}': {}", s, e));
+        Ok(Condition {
+            left: side(left)?,
+            comparison,
+            right: side(right)?,
+        })
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = Box<dyn Error>;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.comparison.symbol(), self.right)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinAction {
+    High,
+    Low,
+    Toggle,
+}
+
+/// One thing a rule does; see the [module docs](self) for the syntax.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Action {
+    Flash,
+    Buzz(BeepPattern),
+    Gpio(u8, PinAction),
+    Mqtt,
+    Run(String),
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (verb, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let rest = rest.trim();
+        match (verb, rest) {
+            ("flash", "") => Ok(Action::Flash),
+            ("mqtt", "") => Ok(Action::Mqtt),
+            ("buzz", "") => Ok(Action::Buzz("beep".parse()?)),
+            ("buzz", pattern) => Ok(Action::Buzz(pattern.parse()?)),
+            ("run", "") => Err("'run' needs a command".to_string()),
+            ("run", command) => Ok(Action::Run(command.to_string())),
+            ("gpio", spec) => {
+                let bad = || format!("'{}' should look like gpio 17 high, gpio 17 low or gpio 17 toggle", s);
+                let (pin, level) = spec.split_once(char::is_whitespace).ok_or_else(bad)?;
+                let pin = pin.parse().map_err(|_| bad())?;
+                let level = match level.trim() {
+                    "high" | "on" => PinAction::High,
+                    "low" | "off" => PinAction::Low,
+                    "toggle" => PinAction::Toggle,
+                    _ => return Err(bad()),
+                };
+                Ok(Action::Gpio(pin, level))
+            }
+            _ => Err(format!("unknown action '{}' (flash, buzz, gpio, mqtt or run)", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Flash => f.pad("flash"),
+            Action::Buzz(_) => f.pad("buzz"),
+            Action::Gpio(pin, _) => f.pad(&format!("gpio {}", pin)),
+            Action::Mqtt => f.pad("mqtt"),
+            Action::Run(_) => f.pad("run"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub name: String,
+    pub when: Condition,
+    /// What has to hold for it to clear; by default, `when` failing.
+    #[serde(default)]
+    pub clear: Option<Condition>,
+    /// Fire again this often while it still holds; by default only once.
+    #[serde(default, deserialize_with = "serde_helpers::duration_opt")]
+    pub repeat: Option<Duration>,
+    pub actions: Vec<Action>,
+}
+
+/// The `[[rules]]` config section: names unique, something to do.
+pub fn validate(rules: &[RuleConfig]) -> Result<(), Box<dyn Error>> {
+    let mut names = HashSet::new();
+    for rule in rules {
+        if !names.insert(rule.name.as_str()) {
+            return Err(format!("rule '{}' is defined twice", rule.name).into());
+        }
+        if rule.actions.is_empty() {
+            return Err(format!("rule '{}' has no actions", rule.name).into());
+        }
+        if rule.repeat.is_some_and(|r| r.is_zero()) {
+            return Err(format!("rule '{}' repeat must be above zero", rule.name).into());
+        }
+    }
+    Ok(())
+}
+
+/// The BCM pins `rules` drive, for claiming and opening.
+pub fn pins(rules: &[RuleConfig]) -> Vec<u8> {
+    let mut pins: Vec<u8> = rules
+        .iter()
+        .flat_map(|rule| &rule.actions)
+        .filter_map(|action| match action {
+            Action::Gpio(pin, _) => Some(*pin),
+            _ => None,
+        })
+        .collect();
+    pins.sort_unstable();
+    pins.dedup();
+    pins
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleState {
+    Fired,
+    Cleared,
+}
+
+impl fmt::Display for RuleState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            RuleState::Fired => "fired",
+            RuleState::Cleared => "cleared",
+        })
+    }
+}
+
+/// A rule firing or clearing, with the value of its `when` left side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleEvent {
+    pub rule: String,
+    pub state: RuleState,
+    pub value: f64,
+}
+
+/// What running an event's actions came to.
+#[derive(Debug)]
+pub struct Outcome {
+    pub event: RuleEvent,
+    pub result: Result<(), Box<dyn Error>>,
+}
+
+type Pin = Box<dyn FnMut(PinAction) -> Result<(), Box<dyn Error>> + Send>;
+type Backlight = Box<dyn FnMut() -> Result<(), Box<dyn Error>> + Send>;
+
+/// Where actions go. One with nothing set up for it fails, and the rest
+/// of the rule's actions still run.
+#[derive(Default)]
+pub struct Outputs {
+    flash: Option<Backlight>,
+    buzzer: Option<Box<dyn Buzzer>>,
+    #[cfg(feature = "mqtt")]
+    publisher: Option<Publisher>,
+    pins: BTreeMap<u8, Pin>,
+}
+
+impl Outputs {
+    pub fn new() -> Self {
+        Outputs::default()
+    }
+
+    /// What `flash` runs: blinking whichever backlight there is.
+    pub fn set_flash(&mut self, flash: impl FnMut() -> Result<(), Box<dyn Error>> + Send + 'static) {
+        self.flash = Some(Box::new(flash));
+    }
+
+    pub fn set_buzzer(&mut self, buzzer: Box<dyn Buzzer>) {
+        self.buzzer = Some(buzzer);
+    }
+
+    #[cfg(feature = "mqtt")]
+    pub fn set_publisher(&mut self, publisher: Publisher) {
+        self.publisher = Some(publisher);
+    }
+
+    /// Drive BCM `pin` through `output`.
+    pub fn set_pin<P>(&mut self, pin: u8, mut output: P)
+    where
+        P: StatefulOutputPin + Send + 'static,
+    {
+        self.pins.insert(
+            pin,
+            Box::new(move |action| {
+                match action {
+                    PinAction::High => output.set_high(),
+                    PinAction::Low => output.set_low(),
+                    PinAction::Toggle => output.toggle(),
+                }
+                .map_err(|e| format!("GPIO {}: {:?}", pin, e).into())
+            }),
+        );
+    }
+
+    /// Run `actions` for `event`. Firing runs them all; clearing only puts
+    /// `high`/`low` pins back and publishes.
+    pub fn run(&mut self, event: &RuleEvent, actions: &[Action]) -> Result<(), Box<dyn Error>> {
+        let fired = event.state == RuleState::Fired;
+        let mut failed = Vec::new();
+        for action in actions {
+            let result = match action {
+                Action::Flash if fired => self.flash.as_mut().map_or(Err("no LCD backlight to flash".into()), |f| f()),
+                Action::Buzz(pattern) if fired => self.buzzer.as_mut().map_or(Err("no [alerts.buzzer]".into()), |b| b.play(pattern)),
+                Action::Gpio(pin, level) => {
+                    let level = match (level, fired) {
+                        (level, true) => Some(*level),
+                        (PinAction::High, false) => Some(PinAction::Low),
+                        (PinAction::Low, false) => Some(PinAction::High),
+                        (PinAction::Toggle, false) => None,
+                    };
+                    match (level, self.pins.get_mut(pin)) {
+                        (None, _) => Ok(()),
+                        (Some(level), Some(set)) => set(level),
+                        (Some(_), None) => Err(format!("GPIO {} isn't set up", pin).into()),
+                    }
+                }
+                #[cfg(feature = "mqtt")]
+                Action::Mqtt => self.publisher.as_mut().map_or(Err("no [mqtt] broker".into()), |p| p.rule(event)),
+                #[cfg(not(feature = "mqtt"))]
+                Action::Mqtt => Err("built without the mqtt feature".into()),
+                Action::Run(command) if fired => run_command(command, event),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                failed.push(format!("{}: {}", action, e));
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("rule '{}': {}", event.rule, failed.join("; ")).into())
+        }
+    }
+}
+
+/// Started without waiting; a thread reaps it.
+fn run_command(command: &str, event: &RuleEvent) -> Result<(), Box<dyn Error>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("RULE", &event.rule)
+        .env("RULE_STATE", event.state.to_string())
+        .env("RULE_VALUE", event.value.to_string())
+        .spawn()
+        .map_err(|e| format!("can't run '{}': {}", command, e))?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+struct Rule {
+    config: RuleConfig,
+    active: bool,
+    fired_at: Option<Instant>,
+}
+
+/// Every rule, with whether each is fired, and the outputs they drive.
+pub struct Rules {
+    rules: Vec<Rule>,
+    history: History,
+    outputs: Outputs,
+}
+
+impl Rules {
+    pub fn new(config: &[RuleConfig], history: History, outputs: Outputs) -> Self {
+        Rules {
+            rules: config
+                .iter()
+                .map(|config| Rule {
+                    config: config.clone(),
+                    active: false,
+                    fired_at: None,
+                })
+                .collect(),
+            history,
+            outputs,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Check every rule against the latest values and run the actions of
+    /// those that fired or cleared. A failed action is reported alongside
+    /// the event rather than stopping the others.
+    pub fn evaluate(&mut self) -> Vec<Outcome> {
+        self.evaluate_at(Instant::now())
+    }
+
+    pub fn evaluate_at(&mut self, now: Instant) -> Vec<Outcome> {
+        let history = &self.history;
+        let lookup = |name: &str| history.latest(name);
+        let mut events = Vec::new();
+        for rule in &mut self.rules {
+            let Some((holds, value)) = rule.config.when.check(&lookup) else {
+                continue;
+            };
+            let state = if rule.active {
+                let cleared = match &rule.config.clear {
+                    Some(clear) => clear.check(&lookup).is_some_and(|(cleared, _)| cleared),
+                    None => !holds,
+                };
+                let again = rule
+                    .config
+                    .repeat
+                    .zip(rule.fired_at)
+                    .is_some_and(|(repeat, at)| holds && now.saturating_duration_since(at) >= repeat);
+                if cleared {
+                    Some(RuleState::Cleared)
+                } else if again {
+                    Some(RuleState::Fired)
+                } else {
+                    None
+                }
+            } else {
+                holds.then_some(RuleState::Fired)
+            };
+            let Some(state) = state else {
+                continue;
+            };
+            rule.active = state == RuleState::Fired;
+            if rule.active {
+                rule.fired_at = Some(now);
+            }
+            let event = RuleEvent {
+                rule: rule.config.name.clone(),
+                state,
+                value,
+            };
+            let result = self.outputs.run(&event, &rule.config.actions);
+            events.push(Outcome { event, result });
+        }
+        events
+    }
+
+    /// Evaluate every `interval` on a background thread until `stop` is
+    /// set, logging what fires.
+    pub fn spawn(mut self, interval: Duration, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                for Outcome { event, result } in self.evaluate() {
+                    say!("🚨 Rule '{}' {} at {}", event.rule, event.state, event.value);
+                    if let Err(e) = result {
+                        esay!("⚠️  {}", e);
+                    }
+                }
+                thread::sleep(interval);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+
+    struct FakePin(Arc<Mutex<bool>>);
+
+    impl embedded_hal::digital::ErrorType for FakePin {
+        type Error = Infallible;
+    }
+
+    impl embedded_hal::digital::OutputPin for FakePin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            *self.0.lock().unwrap() = false;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            *self.0.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    impl StatefulOutputPin for FakePin {
+        fn is_set_high(&mut self) -> Result<bool, Infallible> {
+            Ok(*self.0.lock().unwrap())
+        }
+
+        fn is_set_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!*self.0.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn parses_conditions_and_actions() {
+        let condition: Condition = "ina219.current * 1000 >= 950".parse().unwrap();
+        assert_eq!(condition.comparison, Comparison::AtLeast);
+        assert_eq!(condition.to_string(), "(ina219.current * 1000) >= 950");
+        assert!("bme280.temperature".parse::<Condition>().is_err());
+        assert_eq!("gpio 17 toggle".parse::<Action>(), Ok(Action::Gpio(17, PinAction::Toggle)));
+        assert_eq!("run fan on".parse::<Action>(), Ok(Action::Run("fan on".into())));
+        assert!("gpio 17".parse::<Action>().is_err());
+        assert!("shout".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn fires_once_and_clears_with_hysteresis() {
+        let history = History::default();
+        let config = RuleConfig {
+            name: "hot".into(),
+            when: "t > 30".parse().unwrap(),
+            clear: Some("t < 29".parse().unwrap()),
+            repeat: Some(Duration::from_secs(60)),
+            actions: vec![Action::Gpio(17, PinAction::High)],
+        };
+        let fan = Arc::new(Mutex::new(false));
+        let mut outputs = Outputs::new();
+        outputs.set_pin(17, FakePin(Arc::clone(&fan)));
+        let mut rules = Rules::new(&[config], history.clone(), outputs);
+        let start = Instant::now();
+        assert!(rules.evaluate_at(start).is_empty());
+
+        let states = |events: Vec<Outcome>| events.into_iter().map(|o| (o.event.state, o.result.is_ok())).collect::<Vec<_>>();
+        history.record("t", 31.0);
+        assert_eq!(states(rules.evaluate_at(start)), [(RuleState::Fired, true)]);
+        assert!(*fan.lock().unwrap());
+        history.record("t", 29.5);
+        assert!(rules.evaluate_at(start + Duration::from_secs(1)).is_empty());
+        history.record("t", 30.5);
+        assert_eq!(states(rules.evaluate_at(start + Duration::from_secs(61))), [(RuleState::Fired, true)]);
+        history.record("t", 28.0);
+        assert_eq!(states(rules.evaluate_at(start + Duration::from_secs(62))), [(RuleState::Cleared, true)]);
+        assert!(!*fan.lock().unwrap());
+    }
+}
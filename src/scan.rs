@@ -0,0 +1,80 @@
+//! Bus scanning, including devices hidden behind a TCA9548A mux.
+
+use crate::address::{Address, AddressedI2c};
+use crate::mux::{Tca9548a, CHANNELS};
+use embedded_hal::i2c::I2c;
+use std::error::Error;
+
+/// First and last non-reserved 7-bit addresses, matching `i2cdetect`.
+pub const FIRST_ADDRESS: u8 = 0x08;
+pub const LAST_ADDRESS: u8 = 0x77;
+
+/// Check whether anything ACKs at `address`.
+///
+/// Uses a one-byte read rather than a write so probing never changes
+/// expander outputs or EEPROM address pointers.
+pub fn probe<I2C: AddressedI2c>(i2c: &mut I2C, address: Address) -> bool {
+    let mut buf = [0u8];
+    i2c.read_at(address, &mut buf).is_ok()
+}
+
+/// Every 7-bit address in `FIRST_ADDRESS..=LAST_ADDRESS` that ACKs.
+pub fn scan<I2C: AddressedI2c>(i2c: &mut I2C) -> Vec<Address> {
+    scan_range(i2c, (FIRST_ADDRESS..=LAST_ADDRESS).map(Address::SevenBit))
+}
+
+/// Every 10-bit address that ACKs. Only useful on buses with 10-bit support.
+pub fn scan_ten_bit<I2C: AddressedI2c>(i2c: &mut I2C) -> Vec<Address> {
+    scan_range(i2c, (0..=0x3FF).map(Address::TenBit))
+}
+
+pub fn scan_range<I2C: AddressedI2c>(
+    i2c: &mut I2C,
+    range: impl IntoIterator<Item = Address>,
+) -> Vec<Address> {
+    range.into_iter().filter(|&addr| probe(i2c, addr)).collect()
+}
+
+/// Devices found on each side of a mux.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MuxScan {
+    pub mux_address: u8,
+    /// Devices reachable with every channel disabled (including the mux itself).
+    pub upstream: Vec<Address>,
+    /// Devices that only appear once channel `n` is enabled.
+    pub channels: Vec<Vec<Address>>,
+}
+
+impl MuxScan {
+    /// Every `(channel, address)` pair found behind the mux.
+    pub fn downstream(&self) -> impl Iterator<Item = (u8, Address)> + '_ {
+        self.channels
+            .iter()
+            .enumerate()
+            .flat_map(|(ch, addrs)| addrs.iter().map(move |&addr| (ch as u8, addr)))
+    }
+}
+
+/// Scan the upstream bus and then each mux channel in turn.
+///
+/// Upstream devices answer on every channel, so they are subtracted from the
+/// per-channel results; what's left is what actually lives on that channel.
+pub fn scan_mux<I2C>(mux: &Tca9548a<I2C>) -> Result<MuxScan, Box<dyn Error>>
+where
+    I2C: I2c + AddressedI2c,
+    I2C::Error: Error + 'static,
+{
+    let upstream = scan(&mut mux.upstream());
+    let mut channels = Vec::with_capacity(CHANNELS as usize);
+    for ch in 0..CHANNELS {
+        let found = scan(&mut mux.channel(ch)?);
+        channels.push(found.into_iter().filter(|a| !upstream.contains(a)).collect());
+    }
+    mux.select_mask(0)?;
+
+    Ok(MuxScan {
+        mux_address: mux.address(),
+        upstream,
+        channels,
+    })
+}
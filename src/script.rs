@@ -0,0 +1,294 @@
+//! Bring-up scripts: bus operations and assertions in a text file, so board
+//! checks can live in version control next to the schematic.
+//!
+//! ```text
+//! # Sensor board rev B
+//! expect ack 0x27
+//! expect nack 0x50            # EEPROM is not fitted on this variant
+//! write 0x27 0xFF             # all expander outputs high
+//! write verify 0x20 0x0A 0x44 mask 0xFE  # MCP23017 IOCON; bit 0 reads as 0
+//! delay 10ms
+//! read 0x68 0x00 3            # write 0x00, read 3 bytes and print them
+//! expect 0x6A 0x0F = 0x6C     # WHO_AM_I
+//! expect 0x68 0x0E = 0x1C mask 0x1F
+//! repeat 5 {
+//!     write 0x27 0x00
+//!     delay 50
+//!     write 0x27 0xFF
+//! }
+//! ```
+//!
+//! One operation per line; `#` starts a comment. `read` and `expect` take
+//! optional bytes to write first (a register number, usually), sent with a
+//! repeated start. `expect` reads as many bytes as it lists and compares
+//! them under `mask`. `write verify ADDR REG BYTE...` writes a register
+//! and reads it back, retrying as [`VerifyPolicy::default`] does, with the
+//! same optional `mask`. A bare `delay` number is milliseconds. `repeat N {`
+//! must end its line and `}` stands alone.
+//!
+//! The first failed assertion stops the script with
+//! [`VerificationFailed`], and bus errors stop it too; either way the error
+//! carries the line number.
+
+use crate::address::{Address, AddressedI2c};
+use crate::exit::VerificationFailed;
+use crate::parse;
+use crate::regmap::{self, VerifyPolicy};
+use crate::say;
+use crate::scan;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// Deepest `repeat` nesting accepted, to catch a runaway `{`.
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Write(Address, Vec<u8>),
+    /// Write the bytes (if any), then read and print `count` bytes.
+    Read { address: Address, write: Vec<u8>, count: usize },
+    Expect { address: Address, write: Vec<u8>, expected: Vec<u8>, mask: u8 },
+    /// Write `value` to `register` and read it back under `mask`.
+    WriteVerify { address: Address, register: u8, value: Vec<u8>, mask: u8 },
+    /// Assert the device acknowledges (`true`) or doesn't.
+    ExpectAck(Address, bool),
+    Delay(Duration),
+    Repeat(u32, Vec<Statement>),
+}
+
+/// An operation and the line it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub line: usize,
+    pub op: Op,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Script {
+    pub statements: Vec<Statement>,
+}
+
+/// What a completed run did, counting each pass through a `repeat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScriptReport {
+    pub operations: usize,
+    pub assertions: usize,
+    /// `write verify`s that only read back right after a retry.
+    pub retried_writes: usize,
+}
+
+/// An error stopped the script at `line`.
+#[derive(Debug)]
+pub struct LineError {
+    pub line: usize,
+    pub error: Box<dyn Error>,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl Error for LineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        text.parse()
+            .map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Run every statement in order, printing what `read` returns.
+    pub fn run<I2C: AddressedI2c>(&self, i2c: &mut I2C) -> Result<ScriptReport, Box<dyn Error>> {
+        let mut report = ScriptReport::default();
+        run_block(i2c, &self.statements, &mut report)?;
+        Ok(report)
+    }
+}
+
+fn run_block<I2C: AddressedI2c>(
+    i2c: &mut I2C,
+    statements: &[Statement],
+    report: &mut ScriptReport,
+) -> Result<(), Box<dyn Error>> {
+    for statement in statements {
+        if let Op::Repeat(times, body) = &statement.op {
+            for _ in 0..*times {
+                run_block(i2c, body, report)?;
+            }
+            continue;
+        }
+        execute(i2c, &statement.op, report).map_err(|error| LineError { line: statement.line, error })?;
+    }
+    Ok(())
+}
+
+fn execute<I2C: AddressedI2c>(i2c: &mut I2C, op: &Op, report: &mut ScriptReport) -> Result<(), Box<dyn Error>> {
+    report.operations += 1;
+    match op {
+        Op::Write(address, bytes) => i2c.write_at(*address, bytes)?,
+        Op::Read { address, write, count } => {
+            let mut buf = vec![0; *count];
+            // A plain read if nothing is to be written first
+            if write.is_empty() {
+                i2c.read_at(*address, &mut buf)?;
+            } else {
+                i2c.write_read_at(*address, write, &mut buf)?;
+            }
+            say!("📖 {}: {:02X?}", address, buf);
+        }
+        Op::Expect { address, write, expected, mask } => {
+            report.assertions += 1;
+            let mut actual = vec![0; expected.len()];
+            if write.is_empty() {
+                i2c.read_at(*address, &mut actual)?;
+            } else {
+                i2c.write_read_at(*address, write, &mut actual)?;
+            }
+            if !expected.iter().zip(&actual).all(|(e, a)| e & mask == a & mask) {
+                let mut details = format!("{} expected {:02X?}, read {:02X?}", address, expected, actual);
+                if *mask != 0xFF {
+                    details.push_str(&format!(" (mask 0x{:02X})", mask));
+                }
+                return Err(VerificationFailed { details }.into());
+            }
+        }
+        Op::WriteVerify { address, register, value, mask } => {
+            report.assertions += 1;
+            let masks = vec![*mask; value.len()];
+            let verified = regmap::write_verify(i2c, *address, *register, value, &masks, &VerifyPolicy::default())?;
+            if verified.retried() {
+                report.retried_writes += 1;
+                say!("⚠️  {} register 0x{:02X} took {} writes to stick", address, register, verified.attempts);
+            }
+        }
+        Op::ExpectAck(address, ack) => {
+            report.assertions += 1;
+            if scan::probe(i2c, *address) != *ack {
+                let details = if *ack {
+                    format!("no acknowledge from {}", address)
+                } else {
+                    format!("{} acknowledged but should be absent", address)
+                };
+                return Err(VerificationFailed { details }.into());
+            }
+        }
+        Op::Delay(duration) => thread::sleep(*duration),
+        Op::Repeat(..) => unreachable!("repeat blocks are unrolled by run_block"),
+    }
+    Ok(())
+}
+
+impl FromStr for Script {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Open blocks: the statements collected so far, plus the line and
+        // count of the `repeat` that opened each inner one
+        let mut blocks: Vec<(usize, u32, Vec<Statement>)> = vec![(0, 1, Vec::new())];
+        for (index, raw) in s.lines().enumerate() {
+            let line = index + 1;
+            let text = raw.split('#').next().unwrap_or("").trim();
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let at = |e: Box<dyn Error>| -> Box<dyn Error> { format!("line {}: {}", line, e).into() };
+            match words.as_slice() {
+                [] => {}
+                ["repeat", times, "{"] => {
+                    if blocks.len() > MAX_DEPTH {
+                        return Err(at(format!("repeat nested deeper than {}", MAX_DEPTH).into()));
+                    }
+                    let times = times
+                        .parse::<u32>()
+                        .map_err(|_| at(format!("invalid repeat count '{}'", times).into()))?;
+                    blocks.push((line, times, Vec::new()));
+                }
+                ["}"] => {
+                    if blocks.len() == 1 {
+                        return Err(at("'}' without a matching repeat".into()));
+                    }
+                    let (start, times, body) = blocks.pop().expect("checked above");
+                    let outer = &mut blocks.last_mut().expect("outermost block never pops").2;
+                    outer.push(Statement { line: start, op: Op::Repeat(times, body) });
+                }
+                words => {
+                    let op = parse_op(words).map_err(at)?;
+                    blocks.last_mut().expect("outermost block never pops").2.push(Statement { line, op });
+                }
+            }
+        }
+        if let Some((line, _, _)) = blocks.get(1) {
+            return Err(format!("line {}: repeat is never closed with '}}'", line).into());
+        }
+        let (_, _, statements) = blocks.pop().expect("outermost block never pops");
+        Ok(Script { statements })
+    }
+}
+
+fn parse_op(words: &[&str]) -> Result<Op, Box<dyn Error>> {
+    let bytes = |words: &[&str]| words.iter().map(|b| parse::byte(b)).collect::<Result<Vec<_>, _>>();
+    let op = match words {
+        ["write", "verify", address, register, rest @ ..] if !rest.is_empty() => {
+            let (value, mask) = match rest {
+                [value @ .., "mask", mask] => (value, parse::byte(mask)?),
+                value => (value, 0xFF),
+            };
+            if value.is_empty() {
+                return Err("write verify needs at least one byte to write".into());
+            }
+            Op::WriteVerify {
+                address: address.parse()?,
+                register: parse::byte(register)?,
+                value: bytes(value)?,
+                mask,
+            }
+        }
+        ["write", "verify", ..] => return Err("usage: write verify ADDR REG BYTE... [mask BYTE]".into()),
+        ["write", address, data @ ..] if !data.is_empty() => Op::Write(address.parse()?, bytes(data)?),
+        ["read", address, write @ .., count] => Op::Read {
+            address: address.parse()?,
+            write: bytes(write)?,
+            count: parse::byte_count(count)?,
+        },
+        ["expect", "ack", address] => Op::ExpectAck(address.parse()?, true),
+        ["expect", "nack", address] => Op::ExpectAck(address.parse()?, false),
+        ["expect", address, rest @ ..] => {
+            let equals = rest
+                .iter()
+                .position(|w| *w == "=")
+                .ok_or("usage: expect ADDR [BYTE...] = BYTE... [mask BYTE]")?;
+            let (write, rest) = (&rest[..equals], &rest[equals + 1..]);
+            let (expected, mask) = match rest {
+                [expected @ .., "mask", mask] => (expected, parse::byte(mask)?),
+                expected => (expected, 0xFF),
+            };
+            if expected.is_empty() {
+                return Err("expect needs at least one byte after '='".into());
+            }
+            Op::Expect {
+                address: address.parse()?,
+                write: bytes(write)?,
+                expected: bytes(expected)?,
+                mask,
+            }
+        }
+        ["delay", time] => Op::Delay(parse::duration(time)?),
+        ["write", ..] => return Err("usage: write ADDR BYTE...".into()),
+        ["read", ..] => return Err("usage: read ADDR [BYTE...] COUNT".into()),
+        ["delay", ..] => return Err("usage: delay MS|DURATION".into()),
+        ["repeat", ..] => return Err("usage: repeat N {".into()),
+        [name, ..] => return Err(format!("unknown operation '{}'", name).into()),
+        [] => unreachable!("blank lines are skipped"),
+    };
+    Ok(op)
+}
@@ -0,0 +1,383 @@
+//! Multiplexed seven-segment displays wired straight to GPIO.
+//!
+//! The segments of every digit share eight lines (a-g and the decimal
+//! point), and each digit's common pin has a line of its own. Only one
+//! digit is lit at a time: [`SevenSegment::spawn`] starts a refresh thread
+//! that walks the digits fast enough for persistence of vision to show them
+//! all at once. Brightness is the share of each digit's slot it stays lit.
+//!
+//! ```no_run
+//! use rpi_peripherals::segment::{Common, MultiplexConfig, SevenSegment};
+//!
+//! let config = MultiplexConfig { common: Common::Cathode, ..MultiplexConfig::default() };
+//! // a b c d e f g dp, then the digits left to right
+//! let display = SevenSegment::from_gpio([5, 6, 13, 19, 26, 16, 20, 21], &[17, 27, 22, 23], config)?;
+//! display.show("21.5")?;
+//! display.set_brightness(40);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::timing::PreciseDelay;
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::Gpio;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Segment bits: a is bit 0 through g at bit 6, decimal point at bit 7.
+pub const DP: u8 = 0x80;
+
+/// Which side of the LEDs the digit pin is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Common {
+    /// Segments drive high, the lit digit's pin sinks low.
+    Cathode,
+    /// Segments sink low, the lit digit's pin drives high.
+    Anode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultiplexConfig {
+    pub common: Common,
+    /// Set when each digit is switched through a transistor, which inverts it.
+    pub inverted_digits: bool,
+    /// Full passes over all digits per second; below about 60 it flickers.
+    pub refresh_hz: u32,
+    /// Percent of each digit's slot it is lit, 1-100.
+    pub brightness: u8,
+    /// Spun part of each wait; see [`PreciseDelay`].
+    pub spin: Duration,
+}
+
+impl Default for MultiplexConfig {
+    fn default() -> Self {
+        MultiplexConfig {
+            common: Common::Cathode,
+            inverted_digits: false,
+            refresh_hz: 100,
+            brightness: 100,
+            spin: Duration::from_micros(500),
+        }
+    }
+}
+
+impl MultiplexConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !(1..=10_000).contains(&self.refresh_hz) {
+            return Err(format!("refresh rate {} Hz out of range (1-10000)", self.refresh_hz).into());
+        }
+        if !(1..=100).contains(&self.brightness) {
+            return Err(format!("brightness {}% out of range (1-100)", self.brightness).into());
+        }
+        Ok(())
+    }
+
+    fn segment_level(&self) -> bool {
+        self.common == Common::Cathode
+    }
+
+    fn digit_level(&self) -> bool {
+        (self.common == Common::Anode) != self.inverted_digits
+    }
+}
+
+/// Segment pattern for `ch`: digits, hex letters and the handful of other
+/// letters a seven-segment display can show legibly; `None` otherwise.
+pub fn encode(ch: char) -> Option<u8> {
+    Some(match ch {
+        '0' | 'O' => 0x3F,
+        '1' => 0x06,
+        '2' => 0x5B,
+        '3' => 0x4F,
+        '4' => 0x66,
+        '5' | 'S' | 's' => 0x6D,
+        '6' => 0x7D,
+        '7' => 0x07,
+        '8' => 0x7F,
+        '9' => 0x6F,
+        'A' | 'a' => 0x77,
+        'B' | 'b' => 0x7C,
+        'C' => 0x39,
+        'c' => 0x58,
+        'D' | 'd' => 0x5E,
+        'E' | 'e' => 0x79,
+        'F' | 'f' => 0x71,
+        'G' | 'g' => 0x3D,
+        'H' => 0x76,
+        'h' => 0x74,
+        'I' | 'i' => 0x04,
+        'J' | 'j' => 0x1E,
+        'L' | 'l' => 0x38,
+        'N' | 'n' => 0x54,
+        'o' => 0x5C,
+        'P' | 'p' => 0x73,
+        'R' | 'r' => 0x50,
+        'T' | 't' => 0x78,
+        'U' => 0x3E,
+        'u' => 0x1C,
+        'Y' | 'y' => 0x6E,
+        '-' => 0x40,
+        '_' => 0x08,
+        '=' => 0x48,
+        '°' => 0x63,
+        ' ' => 0x00,
+        _ => return None,
+    })
+}
+
+/// Patterns for `text` on `digits` digits, right-aligned. A `.` lights the
+/// decimal point of the character before it instead of taking a digit.
+pub fn encode_str(text: &str, digits: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut patterns: Vec<u8> = Vec::new();
+    for ch in text.chars() {
+        match (ch, patterns.last_mut()) {
+            ('.', Some(last)) if *last & DP == 0 => *last |= DP,
+            ('.', _) => patterns.push(DP),
+            _ => patterns.push(encode(ch).ok_or_else(|| format!("'{}' can't be shown on seven segments", ch))?),
+        }
+    }
+    if patterns.len() > digits {
+        return Err(format!("'{}' needs {} digits, the display has {}", text, patterns.len(), digits).into());
+    }
+    let mut frame = vec![0; digits - patterns.len()];
+    frame.extend(patterns);
+    Ok(frame)
+}
+
+/// `value` as text for [`encode_str`] on `digits` digits. With `decimals`
+/// it always has that many; without, it gets as many as fit, less any
+/// trailing zeros, so 3.14159 on four digits is `3.142` and 2.5 is `2.5`.
+pub fn format_number(value: f64, digits: usize, decimals: Option<usize>) -> Result<String, Box<dyn Error>> {
+    if !value.is_finite() {
+        return Err(format!("{} can't be shown on seven segments", value).into());
+    }
+    // The point shares a digit, so only the rest count
+    let fits = |text: &String| text.chars().filter(|&c| c != '.').count() <= digits;
+    if let Some(decimals) = decimals {
+        let text = format!("{:.*}", decimals, value);
+        return if fits(&text) {
+            Ok(text)
+        } else {
+            Err(format!("{} needs more than {} digits", text, digits).into())
+        };
+    }
+    for decimals in (0..digits).rev() {
+        let mut text = format!("{:.*}", decimals, value);
+        if fits(&text) {
+            if text.contains('.') {
+                text.truncate(text.trim_end_matches('0').trim_end_matches('.').len());
+            }
+            // -0.0001 rounds to "-0"
+            if text == "-0" {
+                text = "0".into();
+            }
+            return Ok(text);
+        }
+    }
+    Err(format!("{} needs more than {} digits", value, digits).into())
+}
+
+struct Shared {
+    frame: Vec<u8>,
+    brightness: u8,
+}
+
+/// A running display. Dropping it stops the refresh and blanks it.
+pub struct SevenSegment {
+    shared: Arc<Mutex<Shared>>,
+    stop: Arc<AtomicBool>,
+    refresh: Option<JoinHandle<Result<(), String>>>,
+    digits: usize,
+}
+
+impl SevenSegment {
+    /// Take BCM pins for the eight segment lines (a-g, dp) and the digits,
+    /// leftmost first, and start refreshing.
+    pub fn from_gpio(segments: [u8; 8], digits: &[u8], config: MultiplexConfig) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let open = |pin: u8| -> Result<rppal::gpio::OutputPin, Box<dyn Error>> {
+            Ok(gpio.get(pin).map_err(|e| format!("display GPIO {}: {}", pin, e))?.into_output())
+        };
+        let segments = [
+            open(segments[0])?,
+            open(segments[1])?,
+            open(segments[2])?,
+            open(segments[3])?,
+            open(segments[4])?,
+            open(segments[5])?,
+            open(segments[6])?,
+            open(segments[7])?,
+        ];
+        let digits = digits.iter().map(|&pin| open(pin)).collect::<Result<Vec<_>, _>>()?;
+        SevenSegment::spawn(segments, digits, config)
+    }
+
+    /// Start the refresh thread on already-configured output pins.
+    pub fn spawn<P>(segments: [P; 8], digits: Vec<P>, config: MultiplexConfig) -> Result<Self, Box<dyn Error>>
+    where
+        P: OutputPin + Send + 'static,
+        P::Error: Error,
+    {
+        config.validate()?;
+        if digits.is_empty() {
+            return Err("a seven-segment display needs at least one digit".into());
+        }
+        let count = digits.len();
+        let shared = Arc::new(Mutex::new(Shared {
+            frame: vec![0; count],
+            brightness: config.brightness,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut scanner = Scanner { segments, digits, config };
+        scanner.blank().map_err(|e| e.to_string())?;
+        let refresh = {
+            let shared = Arc::clone(&shared);
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name("seven-segment".into())
+                .spawn(move || scanner.run(&shared, &stop))?
+        };
+        Ok(SevenSegment {
+            shared,
+            stop,
+            refresh: Some(refresh),
+            digits: count,
+        })
+    }
+
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    /// Show `text`, right-aligned; see [`encode_str`].
+    pub fn show(&self, text: &str) -> Result<(), Box<dyn Error>> {
+        let frame = encode_str(text, self.digits)?;
+        self.set_raw(&frame)
+    }
+
+    /// Show raw segment patterns, one per digit, leftmost first.
+    pub fn set_raw(&self, patterns: &[u8]) -> Result<(), Box<dyn Error>> {
+        if patterns.len() != self.digits {
+            return Err(format!("{} patterns for {} digits", patterns.len(), self.digits).into());
+        }
+        self.check_running()?;
+        lock(&self.shared).frame.copy_from_slice(patterns);
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
+        self.set_raw(&vec![0; self.digits])
+    }
+
+    /// Set the duty cycle, clamped to 1-100%.
+    pub fn set_brightness(&self, percent: u8) {
+        lock(&self.shared).brightness = percent.clamp(1, 100);
+    }
+
+    pub fn brightness(&self) -> u8 {
+        lock(&self.shared).brightness
+    }
+
+    /// Stop refreshing and blank the display, reporting a pin error that
+    /// ended the refresh thread early.
+    pub fn stop(mut self) -> Result<(), Box<dyn Error>> {
+        self.join()
+    }
+
+    fn check_running(&self) -> Result<(), Box<dyn Error>> {
+        match &self.refresh {
+            Some(handle) if !handle.is_finished() => Ok(()),
+            _ => Err("the display's refresh thread has stopped".into()),
+        }
+    }
+
+    fn join(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.refresh.take().map(JoinHandle::join) {
+            Some(Ok(Err(e))) => Err(format!("seven-segment refresh: {}", e).into()),
+            Some(Err(_)) => Err("seven-segment refresh thread panicked".into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for SevenSegment {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+struct Scanner<P> {
+    segments: [P; 8],
+    digits: Vec<P>,
+    config: MultiplexConfig,
+}
+
+impl<P> Scanner<P>
+where
+    P: OutputPin,
+    P::Error: Error,
+{
+    fn run(&mut self, shared: &Mutex<Shared>, stop: &AtomicBool) -> Result<(), String> {
+        let delay = PreciseDelay::new(self.config.spin);
+        let slot = Duration::from_secs(1) / (self.config.refresh_hz * self.digits.len() as u32);
+        let mut next = Instant::now();
+        let mut digit = 0;
+        while !stop.load(Ordering::Relaxed) {
+            let (pattern, brightness) = {
+                let shared = lock(shared);
+                (shared.frame[digit], shared.brightness)
+            };
+            self.light(digit, pattern).map_err(|e| e.to_string())?;
+            delay.until(next + slot * u32::from(brightness) / 100);
+            self.set_digit(digit, false).map_err(|e| e.to_string())?;
+            next += slot;
+            // Fell behind (a long preemption): resync rather than race
+            if Instant::now() > next + slot {
+                next = Instant::now();
+            }
+            delay.until(next);
+            digit = (digit + 1) % self.digits.len();
+        }
+        self.blank().map_err(|e| e.to_string())
+    }
+
+    /// Segments first, then the digit, so the pattern never ghosts onto it.
+    fn light(&mut self, digit: usize, pattern: u8) -> Result<(), P::Error> {
+        let on = self.config.segment_level();
+        for (bit, pin) in self.segments.iter_mut().enumerate() {
+            set(pin, (pattern & (1 << bit) != 0) == on)?;
+        }
+        self.set_digit(digit, true)
+    }
+
+    fn set_digit(&mut self, digit: usize, lit: bool) -> Result<(), P::Error> {
+        let on = self.config.digit_level();
+        set(&mut self.digits[digit], lit == on)
+    }
+
+    fn blank(&mut self) -> Result<(), P::Error> {
+        for digit in 0..self.digits.len() {
+            self.set_digit(digit, false)?;
+        }
+        let on = self.config.segment_level();
+        for pin in &mut self.segments {
+            set(pin, !on)?;
+        }
+        Ok(())
+    }
+}
+
+fn set<P: OutputPin>(pin: &mut P, high: bool) -> Result<(), P::Error> {
+    if high {
+        pin.set_high()
+    } else {
+        pin.set_low()
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> std::sync::MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
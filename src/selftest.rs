@@ -0,0 +1,249 @@
+//! `selftest`: one check per configured device, leaving each as it was.
+//!
+//! A plain run only looks for an ACK at each address. A full run also asks
+//! each chip something that proves it is the chip the config says:
+//!
+//! - INA219: the config register is sane and the last bus conversion didn't
+//!   overflow;
+//! - INA226: the manufacturer and die ID registers;
+//! - MCP23017: IOCON reading the same at both of its addresses;
+//! - TCA9548A and PCF8574: a read of the control or port register;
+//! - HD44780: every cell filled for a second, for someone to look at, then
+//!   cleared.
+//!
+//! GPIO pairs wired together on a test jig are checked with [`check_loopback`];
+//! a pair that doesn't follow at all is taken as no jig and only warns.
+//!
+//! The report counts PASS, WARN and FAIL and gives a confidence figure: the
+//! share of checks that passed, a warning counting half.
+
+use crate::address::{Address, AddressedI2c};
+use crate::config::DeviceConfig;
+use crate::lcd::Lcd;
+use crate::preflight::Status;
+use crate::scan;
+use crate::sensors::read_register;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// How long the LCD test pattern stays up.
+const PATTERN_TIME: Duration = Duration::from_secs(1);
+
+/// Settling time for a jig wire after the output changes.
+const LOOPBACK_SETTLE: Duration = Duration::from_millis(1);
+
+const INA_CONFIG: u8 = 0x00;
+const INA_BUS_VOLTAGE: u8 = 0x02;
+const INA226_MANUFACTURER_ID: u8 = 0xFE;
+const INA226_DIE_ID: u8 = 0xFF;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// The device's config name, or the pins for a loopback.
+    pub name: String,
+    pub driver: String,
+    pub status: Status,
+    pub message: String,
+}
+
+impl Check {
+    fn new(name: &str, driver: &str, status: Status, message: impl Into<String>) -> Self {
+        Check {
+            name: name.to_string(),
+            driver: driver.to_string(),
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SelfTestReport {
+    pub checks: Vec<Check>,
+}
+
+impl SelfTestReport {
+    pub fn count(&self, status: Status) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+
+    /// No check failed; warnings are allowed.
+    pub fn passed(&self) -> bool {
+        self.count(Status::Fail) == 0
+    }
+
+    /// 0.0 to 1.0; 1.0 with nothing checked.
+    pub fn confidence(&self) -> f64 {
+        if self.checks.is_empty() {
+            return 1.0;
+        }
+        let score = self.count(Status::Pass) as f64 + self.count(Status::Warn) as f64 / 2.0;
+        score / self.checks.len() as f64
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        for check in &self.checks {
+            writeln!(f, "   {} {:<width$}  {:<10} {}", check.status, check.name, check.driver, check.message)?;
+        }
+        write!(
+            f,
+            "{} PASS, {} WARN, {} FAIL: confidence {:.0}%",
+            self.count(Status::Pass),
+            self.count(Status::Warn),
+            self.count(Status::Fail),
+            self.confidence() * 100.0
+        )
+    }
+}
+
+/// Probe `device`, and with `full` check it is the chip it is meant to be.
+/// An optional device that doesn't answer is a warning, not a failure.
+pub fn check_device<I2C: AddressedI2c>(i2c: &mut I2C, device: &DeviceConfig, full: bool) -> Check {
+    let check = |status, message: String| Check::new(&device.name, &device.driver, status, message);
+    let Some(raw) = device.address else {
+        return check(Status::Warn, "no address in the config, so nothing to probe".to_string());
+    };
+    let address = match Address::from_raw(raw) {
+        Ok(address) => address,
+        Err(e) => return check(Status::Fail, e.to_string()),
+    };
+    if !scan::probe(i2c, address) {
+        let status = if device.optional { Status::Warn } else { Status::Fail };
+        return check(status, format!("no ACK at {}", address));
+    }
+    if !full {
+        return check(Status::Pass, format!("answers at {}", address));
+    }
+    match identify(i2c, address, &device.driver) {
+        Ok(Some(found)) => check(Status::Pass, format!("{} at {}", found, address)),
+        Ok(None) => check(Status::Warn, format!("answers at {}; no deeper check for {}", address, device.driver)),
+        Err(e) => check(Status::Fail, format!("answers at {}, but {}", address, e)),
+    }
+}
+
+/// What the driver-specific check found, `None` for drivers without one.
+fn identify<I2C: AddressedI2c>(i2c: &mut I2C, address: Address, driver: &str) -> Result<Option<String>, Box<dyn Error>> {
+    match driver {
+        "ina219" => {
+            let config = read_register(i2c, address, INA_CONFIG)?;
+            if config & 0x8000 != 0 || config == 0xFFFF {
+                return Err(format!("the config register reads 0x{:04X}", config).into());
+            }
+            if config & 0b111 == 0 {
+                return Ok(Some("powered down, so no conversion to check".to_string()));
+            }
+            let bus = read_register(i2c, address, INA_BUS_VOLTAGE)?;
+            if bus & 1 != 0 {
+                return Err("the last conversion overflowed; check the shunt range".into());
+            }
+            Ok(Some(format!("bus {:.2} V", f64::from(bus >> 3) * 0.004)))
+        }
+        "ina226" => {
+            let manufacturer = read_register(i2c, address, INA226_MANUFACTURER_ID)?;
+            let die = read_register(i2c, address, INA226_DIE_ID)?;
+            if manufacturer != 0x5449 || die >> 4 != 0x226 {
+                return Err(format!("IDs are 0x{:04X}/0x{:04X}, not an INA226's 0x5449/0x226x", manufacturer, die).into());
+            }
+            let bus = read_register(i2c, address, INA_BUS_VOLTAGE)?;
+            Ok(Some(format!("INA226 rev {}, bus {:.2} V", die & 0xF, f64::from(bus) * 0.00125)))
+        }
+        "mcp23017" => {
+            // IOCON appears at both 0x0A and 0x0B while BANK is clear
+            let mut iocon = [0; 2];
+            i2c.write_read_at(address, &[0x0A], &mut iocon)?;
+            if iocon[0] != iocon[1] || iocon[0] & 0x80 != 0 {
+                return Err(format!("IOCON reads {:02X?}, not one register twice", iocon).into());
+            }
+            Ok(Some(format!("IOCON 0x{:02X}", iocon[0])))
+        }
+        "tca9548a" => {
+            let mut mask = [0];
+            i2c.read_at(address, &mut mask)?;
+            Ok(Some(format!("channel mask {:08b}", mask[0])))
+        }
+        "pcf8574" => {
+            let mut port = [0];
+            i2c.read_at(address, &mut port)?;
+            Ok(Some(format!("port reads {:08b}", port[0])))
+        }
+        "hd44780" => {
+            // 40x2 covers the DDRAM of every size, 20x4 rows being halves of
+            // the two 40-cell lines
+            let mut lcd = Lcd::new(&mut *i2c, address, 40, 2)?;
+            for row in 0..2 {
+                lcd.set_cursor(0, row)?;
+                lcd.write_raw(&[0xFF; 40])?;
+            }
+            thread::sleep(PATTERN_TIME);
+            lcd.clear()?;
+            Ok(Some("test pattern shown".to_string()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Two BCM pins wired together on a test jig, written `OUT:IN`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Loopback {
+    pub output: u8,
+    pub input: u8,
+}
+
+impl fmt::Display for Loopback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GPIO {}->{}", self.output, self.input)
+    }
+}
+
+impl FromStr for Loopback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (output, input) = s.split_once(':').ok_or_else(|| format!("'{}' is not OUT:IN, as in 17:27", s))?;
+        let pin = |p: &str| p.trim().parse::<u8>().map_err(|_| format!("'{}' is not a BCM pin number", p));
+        let loopback = Loopback {
+            output: pin(output)?,
+            input: pin(input)?,
+        };
+        if loopback.output == loopback.input {
+            return Err(format!("{} loops a pin to itself", s));
+        }
+        Ok(loopback)
+    }
+}
+
+/// Drive the output low then high and read the input each time, pulled the
+/// other way, so an open wire reads the pull and a stuck line the same
+/// level twice. Both pins are left as inputs.
+pub fn check_loopback(loopback: Loopback) -> Check {
+    let name = loopback.to_string();
+    let levels = || -> Result<(bool, bool), Box<dyn Error>> {
+        let gpio = rppal::gpio::Gpio::new()?;
+        let mut output = gpio.get(loopback.output)?.into_output_low();
+        let input = gpio.get(loopback.input)?.into_input_pullup();
+        thread::sleep(LOOPBACK_SETTLE);
+        let low = input.is_high();
+        drop(input);
+        let input = gpio.get(loopback.input)?.into_input_pulldown();
+        output.set_high();
+        thread::sleep(LOOPBACK_SETTLE);
+        Ok((low, input.is_high()))
+    };
+    match levels() {
+        Ok((false, true)) => Check::new(&name, "loopback", Status::Pass, "input follows the output"),
+        Ok((true, false)) => Check::new(&name, "loopback", Status::Warn, "input only reads its pull; is the jig fitted?"),
+        Ok((stuck, _)) => Check::new(
+            &name,
+            "loopback",
+            Status::Fail,
+            format!("input stuck {}; a short, or the other pin is driven too", if stuck { "high" } else { "low" }),
+        ),
+        Err(e) => Check::new(&name, "loopback", Status::Fail, e.to_string()),
+    }
+}
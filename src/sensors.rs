@@ -0,0 +1,77 @@
+//! Sensor drivers.
+//!
+//! I2C drivers talk to the chip through [`AddressedI2c`], so they run on
+//! the hardware bus, bit-banged I2C, a mux channel or a recording alike.
+//! Chips that measure the same thing share a trait, such as
+//! [`PowerMonitor`] for the INA2xx current sensors, so applications don't
+//! care which one is fitted. The [`Apds9960`] reads proximity, colour and
+//! swipes; the [`HcSr04`] sits on plain GPIOs instead.
+
+mod apds9960;
+mod hcsr04;
+mod ina219;
+mod ina226;
+
+pub use apds9960::{Apds9960, Color, Engine, Gesture, GestureDecoder, GestureWatcher, APDS9960_ADDRESS, GESTURE_POLL};
+pub use hcsr04::{speed_of_sound, HcSr04, Readings, HCSR04_MAX_RANGE, HCSR04_MIN_INTERVAL};
+pub use ina219::Ina219;
+pub use ina226::Ina226;
+
+use crate::address::{Address, AddressedI2c};
+use serde::Serialize;
+use std::error::Error;
+
+/// Address with A0 and A1 tied to ground, on both INA219 and INA226.
+pub const INA_DEFAULT_ADDRESS: u8 = 0x40;
+
+/// One reading from a high-side current monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PowerReading {
+    /// Volts on the load side of the shunt.
+    pub bus_voltage: f64,
+    /// Volts across the shunt.
+    pub shunt_voltage: f64,
+    /// Amps, positive into the load.
+    pub current: f64,
+    /// Watts drawn by the load.
+    pub power: f64,
+}
+
+pub trait PowerMonitor {
+    fn read_power(&mut self) -> Result<PowerReading, Box<dyn Error>>;
+}
+
+impl<T: PowerMonitor + ?Sized> PowerMonitor for Box<T> {
+    fn read_power(&mut self) -> Result<PowerReading, Box<dyn Error>> {
+        (**self).read_power()
+    }
+}
+
+/// Current LSB for the calibration register: the 15-bit current register
+/// spans `max_current`.
+fn current_lsb(shunt_ohms: f64, max_current: f64) -> Result<f64, Box<dyn Error>> {
+    if !(shunt_ohms.is_finite() && shunt_ohms > 0.0) {
+        return Err(format!("shunt resistance must be positive, got {} Ω", shunt_ohms).into());
+    }
+    if !(max_current.is_finite() && max_current > 0.0) {
+        return Err(format!("maximum current must be positive, got {} A", max_current).into());
+    }
+    Ok(max_current / 32768.0)
+}
+
+/// INA2xx registers are 16 bits, most significant byte first.
+pub(crate) fn read_register<I2C: AddressedI2c>(i2c: &mut I2C, address: Address, register: u8) -> Result<u16, Box<dyn Error>> {
+    let mut buf = [0; 2];
+    i2c.write_read_at(address, &[register], &mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn write_register<I2C: AddressedI2c>(
+    i2c: &mut I2C,
+    address: Address,
+    register: u8,
+    value: u16,
+) -> Result<(), Box<dyn Error>> {
+    let [high, low] = value.to_be_bytes();
+    i2c.write_at(address, &[register, high, low])
+}
@@ -0,0 +1,386 @@
+//! Broadcom/Avago APDS-9960 proximity, colour and gesture sensor.
+//!
+//! Each measurement has its own engine, switched on with [`Apds9960::set_engine`].
+//! The gesture engine takes over from proximity while something is close,
+//! filling a FIFO with the light seen by four photodiodes (up, down, left,
+//! right); a swipe is read off how the balance between them moves from the
+//! first sample to the last, as [`GestureDecoder`] does. [`GestureWatcher`]
+//! polls for swipes on a thread of its own and hands them to a callback or
+//! a channel, which is what [`GestureControls`](crate::menu::GestureControls)
+//! takes to drive a menu without touching anything.
+
+use crate::address::{Address, AddressedI2c};
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The only address the chip has.
+pub const APDS9960_ADDRESS: u8 = 0x39;
+
+/// How often [`GestureWatcher`] reads the FIFO: it holds 32 samples, and
+/// the engine takes one every 2.8 ms or so.
+pub const GESTURE_POLL: Duration = Duration::from_millis(10);
+
+const ENABLE: u8 = 0x80;
+const ATIME: u8 = 0x81;
+const WTIME: u8 = 0x83;
+const PPULSE: u8 = 0x8E;
+const CONTROL: u8 = 0x8F;
+const CONFIG2: u8 = 0x90;
+const ID: u8 = 0x92;
+const CDATAL: u8 = 0x94;
+const PDATA: u8 = 0x9C;
+const GPENTH: u8 = 0xA0;
+const GEXTH: u8 = 0xA1;
+const GCONF1: u8 = 0xA2;
+const GCONF2: u8 = 0xA3;
+const GPULSE: u8 = 0xA6;
+const GCONF4: u8 = 0xAB;
+const GFLVL: u8 = 0xAE;
+const GSTATUS: u8 = 0xAF;
+const GFIFO_U: u8 = 0xFC;
+
+/// ID register values: the APDS-9960 itself, and the ones clones report.
+const IDS: [u8; 3] = [0xAB, 0xA8, 0x9C];
+
+const PON: u8 = 1 << 0;
+const GMODE: u8 = 1 << 0;
+const GVALID: u8 = 1 << 0;
+
+/// 103 ms colour integration.
+const DEFAULT_ATIME: u8 = 0xDB;
+/// 27 ms between proximity and colour cycles with the wait engine on.
+const DEFAULT_WTIME: u8 = 0xF6;
+/// 8 pulses of 16 µs per proximity reading.
+const DEFAULT_PPULSE: u8 = 0x87;
+/// 100 mA LED, 4x proximity gain, 4x colour gain.
+const DEFAULT_CONTROL: u8 = 0b10 << 2 | 0b01;
+/// Bit 0 is reserved and must stay set.
+const DEFAULT_CONFIG2: u8 = 0x01;
+/// Proximity that starts a gesture, and that ends one.
+const DEFAULT_GPENTH: u8 = 40;
+const DEFAULT_GEXTH: u8 = 30;
+/// Interrupt after 4 samples, exit after 1 below GEXTH.
+const DEFAULT_GCONF1: u8 = 0b01 << 6;
+/// 4x gain, 100 mA LED, 2.8 ms between samples.
+const DEFAULT_GCONF2: u8 = 0b10 << 5 | 0b001;
+/// 10 pulses of 32 µs per sample.
+const DEFAULT_GPULSE: u8 = 0b11 << 6 | 9;
+
+/// Most FIFO samples there can be at once.
+const FIFO_DEPTH: u8 = 32;
+
+/// One of the chip's measurement engines, each with its ENABLE bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// Clear, red, green and blue light.
+    Color,
+    Proximity,
+    /// A pause between proximity and colour cycles, to save power.
+    Wait,
+    /// Needs proximity to see something coming, so switches it on too.
+    Gesture,
+}
+
+impl Engine {
+    fn bit(self) -> u8 {
+        match self {
+            Engine::Color => 1 << 1,
+            Engine::Proximity => 1 << 2,
+            Engine::Wait => 1 << 3,
+            Engine::Gesture => 1 << 6,
+        }
+    }
+}
+
+/// Raw counts from the colour engine; with the defaults they saturate
+/// at 37888.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Color {
+    pub clear: u16,
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+}
+
+/// A swipe across the sensor, as seen looking at it with the chip's
+/// pin 1 at the top left; a sensor mounted another way round swaps them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gesture {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl fmt::Display for Gesture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Gesture::Up => "up",
+            Gesture::Down => "down",
+            Gesture::Left => "left",
+            Gesture::Right => "right",
+        })
+    }
+}
+
+/// Turns the FIFO samples of one gesture into a direction.
+///
+/// Each sample is up, down, left and right photodiode counts. Only samples
+/// where all four see more than `threshold` count, so the edges of the
+/// swipe where one side sees nothing don't swamp the ratios. The up/down
+/// and left/right balance of the first such sample is compared with the
+/// last; whichever moved more, by at least `sensitivity` percent, gives
+/// the direction.
+#[derive(Debug, Clone)]
+pub struct GestureDecoder {
+    samples: Vec<[u8; 4]>,
+    threshold: u8,
+    sensitivity: i32,
+}
+
+impl Default for GestureDecoder {
+    fn default() -> Self {
+        GestureDecoder::new(10, 50)
+    }
+}
+
+impl GestureDecoder {
+    pub fn new(threshold: u8, sensitivity: i32) -> Self {
+        GestureDecoder {
+            samples: Vec::new(),
+            threshold,
+            sensitivity,
+        }
+    }
+
+    /// One FIFO sample: up, down, left, right.
+    pub fn push(&mut self, sample: [u8; 4]) {
+        self.samples.push(sample);
+    }
+
+    /// Whether there are samples waiting for [`finish`](Self::finish).
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The direction of the samples pushed so far, `None` if they don't
+    /// show a clear swipe; either way they are cleared for the next one.
+    pub fn finish(&mut self) -> Option<Gesture> {
+        let samples = std::mem::take(&mut self.samples);
+        let strong = |s: &&[u8; 4]| s.iter().all(|&v| v > self.threshold);
+        let first = samples.iter().find(strong)?;
+        let last = samples.iter().rev().find(strong)?;
+        let ratio = |a: u8, b: u8| (i32::from(a) - i32::from(b)) * 100 / (i32::from(a) + i32::from(b));
+        let up_down = ratio(last[0], last[1]) - ratio(first[0], first[1]);
+        let left_right = ratio(last[2], last[3]) - ratio(first[2], first[3]);
+        if up_down.abs().max(left_right.abs()) < self.sensitivity {
+            return None;
+        }
+        // The lens images the hand onto the opposite photodiodes, so the
+        // balance moves the other way to the hand
+        Some(if up_down.abs() >= left_right.abs() {
+            if up_down > 0 {
+                Gesture::Down
+            } else {
+                Gesture::Up
+            }
+        } else if left_right > 0 {
+            Gesture::Right
+        } else {
+            Gesture::Left
+        })
+    }
+}
+
+pub struct Apds9960<I2C> {
+    i2c: I2C,
+    address: Address,
+    enabled: u8,
+    decoder: GestureDecoder,
+}
+
+impl<I2C: AddressedI2c> Apds9960<I2C> {
+    /// Check the ID register, set the defaults above and power the chip
+    /// up with every engine off.
+    pub fn new(mut i2c: I2C, address: Address) -> Result<Self, Box<dyn Error>> {
+        let mut id = [0];
+        i2c.write_read_at(address, &[ID], &mut id)?;
+        if !IDS.contains(&id[0]) {
+            return Err(format!("device at {} is not an APDS-9960 (ID 0x{:02X})", address, id[0]).into());
+        }
+        let mut sensor = Apds9960 {
+            i2c,
+            address,
+            enabled: 0,
+            decoder: GestureDecoder::default(),
+        };
+        sensor.write(ENABLE, 0)?;
+        for (register, value) in [
+            (ATIME, DEFAULT_ATIME),
+            (WTIME, DEFAULT_WTIME),
+            (PPULSE, DEFAULT_PPULSE),
+            (CONTROL, DEFAULT_CONTROL),
+            (CONFIG2, DEFAULT_CONFIG2),
+            (GPENTH, DEFAULT_GPENTH),
+            (GEXTH, DEFAULT_GEXTH),
+            (GCONF1, DEFAULT_GCONF1),
+            (GCONF2, DEFAULT_GCONF2),
+            (GPULSE, DEFAULT_GPULSE),
+        ] {
+            sensor.write(register, value)?;
+        }
+        sensor.write(ENABLE, PON)?;
+        // Wake-up from sleep takes 5.7 ms
+        thread::sleep(Duration::from_millis(7));
+        Ok(sensor)
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Switch `engine` on or off. Turning gesture off leaves proximity as
+    /// it was.
+    pub fn set_engine(&mut self, engine: Engine, on: bool) -> Result<(), Box<dyn Error>> {
+        let mut bits = engine.bit();
+        if engine == Engine::Gesture && on {
+            bits |= Engine::Proximity.bit();
+        }
+        let enabled = if on { self.enabled | bits } else { self.enabled & !bits };
+        self.write(ENABLE, PON | enabled)?;
+        self.enabled = enabled;
+        if engine == Engine::Gesture && !on {
+            // Drop out of a gesture in progress, so the next starts clean
+            self.write(GCONF4, 0)?;
+            self.decoder.finish();
+        }
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, engine: Engine) -> bool {
+        self.enabled & engine.bit() != 0
+    }
+
+    /// 0 (nothing there) to 255 (close); the engine must be on.
+    pub fn proximity(&mut self) -> Result<u8, Box<dyn Error>> {
+        self.read(PDATA)
+    }
+
+    /// The last colour reading; the engine must be on.
+    pub fn color(&mut self) -> Result<Color, Box<dyn Error>> {
+        let mut buf = [0; 8];
+        self.i2c.write_read_at(self.address, &[CDATAL], &mut buf)?;
+        let channel = |n: usize| u16::from_le_bytes([buf[2 * n], buf[2 * n + 1]]);
+        Ok(Color {
+            clear: channel(0),
+            red: channel(1),
+            green: channel(2),
+            blue: channel(3),
+        })
+    }
+
+    /// Ambient light: the clear channel of [`color`](Self::color).
+    pub fn ambient(&mut self) -> Result<u16, Box<dyn Error>> {
+        Ok(self.color()?.clear)
+    }
+
+    /// Drain the gesture FIFO without waiting, returning the swipe once one
+    /// has ended. Call it at least every [`GESTURE_POLL`] or so while the
+    /// gesture engine is on, or the FIFO overflows mid-swipe.
+    pub fn read_gesture(&mut self) -> Result<Option<Gesture>, Box<dyn Error>> {
+        if self.read(GSTATUS)? & GVALID != 0 {
+            let level = self.read(GFLVL)?.min(FIFO_DEPTH);
+            if level > 0 {
+                let mut buf = vec![0; usize::from(level) * 4];
+                self.i2c.write_read_at(self.address, &[GFIFO_U], &mut buf)?;
+                for sample in buf.chunks_exact(4) {
+                    self.decoder.push([sample[0], sample[1], sample[2], sample[3]]);
+                }
+            }
+            return Ok(None);
+        }
+        if self.decoder.is_empty() || self.read(GCONF4)? & GMODE != 0 {
+            return Ok(None);
+        }
+        Ok(self.decoder.finish())
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, Box<dyn Error>> {
+        let mut buf = [0];
+        self.i2c.write_read_at(self.address, &[register], &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result<(), Box<dyn Error>> {
+        self.i2c.write_at(self.address, &[register, value])
+    }
+}
+
+/// Polls an [`Apds9960`] for swipes on its own thread, switching its
+/// gesture engine on for as long as it runs.
+pub struct GestureWatcher<I2C> {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<Apds9960<I2C>, String>>>,
+}
+
+impl<I2C: AddressedI2c + Send + 'static> GestureWatcher<I2C> {
+    /// `callback` runs on the polling thread for every swipe. The thread
+    /// ends on a bus error, which [`stop`](Self::stop) returns.
+    pub fn with_callback<F>(mut sensor: Apds9960<I2C>, mut callback: F) -> Result<Self, Box<dyn Error>>
+    where
+        F: FnMut(Gesture) + Send + 'static,
+    {
+        sensor.set_engine(Engine::Gesture, true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !flag.load(Ordering::Relaxed) {
+                match sensor.read_gesture() {
+                    Ok(Some(gesture)) => callback(gesture),
+                    Ok(None) => thread::sleep(GESTURE_POLL),
+                    Err(e) => return Err(e.to_string()),
+                }
+            }
+            sensor.set_engine(Engine::Gesture, false).map_err(|e| e.to_string())?;
+            Ok(sensor)
+        });
+        Ok(GestureWatcher {
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Swipes sent down a channel, which disconnects if the thread ends.
+    pub fn with_channel(sensor: Apds9960<I2C>) -> Result<(Self, Receiver<Gesture>), Box<dyn Error>> {
+        let (tx, rx) = mpsc::channel();
+        let watcher = GestureWatcher::with_callback(sensor, move |gesture| {
+            let _ = tx.send(gesture);
+        })?;
+        Ok((watcher, rx))
+    }
+
+    /// End the thread and hand the sensor back, gesture engine off.
+    pub fn stop(mut self) -> Result<Apds9960<I2C>, Box<dyn Error>> {
+        self.stop.store(true, Ordering::Relaxed);
+        let thread = self.thread.take().ok_or("gesture thread already stopped")?;
+        let result = thread.join().map_err(|_| "gesture thread panicked")?;
+        Ok(result?)
+    }
+}
+
+impl<I2C> Drop for GestureWatcher<I2C> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,160 @@
+//! HC-SR04 ultrasonic distance sensor on two GPIOs.
+//!
+//! A 10 µs pulse on TRIG sends a burst of 40 kHz; ECHO then goes high for
+//! as long as the sound took to come back. The pulse is timed from the
+//! kernel's edge timestamps rather than by polling the pin, so scheduling
+//! delays don't show up in the distance. ECHO is a 5 V output: put it
+//! through a divider (1k/2k) before the Pi's pin.
+
+use crate::timing::PreciseDelay;
+use rppal::gpio::{Event, Gpio, InputPin, OutputPin, Trigger};
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TRIGGER_PULSE: Duration = Duration::from_micros(10);
+
+/// The datasheet's measurement cycle: echoes of the last burst have died
+/// down by then.
+pub const HCSR04_MIN_INTERVAL: Duration = Duration::from_millis(60);
+
+/// Beyond this the sensor doesn't see reliably.
+pub const HCSR04_MAX_RANGE: f64 = 4.0;
+
+/// ECHO rises about 0.5 ms after the trigger, once the burst is out.
+const ECHO_START_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Longest echo pulse: about 38 ms when nothing answers.
+const ECHO_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Metres per second in dry air at `celsius`.
+pub fn speed_of_sound(celsius: f64) -> f64 {
+    331.3 + 0.606 * celsius
+}
+
+pub struct HcSr04 {
+    trigger: OutputPin,
+    echo: InputPin,
+    /// Air temperature in °C, for the speed of sound.
+    temperature: f64,
+    last: Option<Instant>,
+}
+
+impl HcSr04 {
+    /// TRIG and ECHO on BCM pins. Distances assume 20 °C air until
+    /// [`HcSr04::set_temperature`] says otherwise.
+    pub fn from_gpio(trigger: u8, echo: u8) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let mut trigger = gpio.get(trigger).map_err(|e| format!("trigger GPIO {}: {}", trigger, e))?.into_output_low();
+        let mut echo_pin = gpio.get(echo).map_err(|e| format!("echo GPIO {}: {}", echo, e))?.into_input_pulldown();
+        echo_pin.set_interrupt(Trigger::Both, None).map_err(|e| format!("echo GPIO {}: {}", echo, e))?;
+        trigger.set_low();
+        Ok(HcSr04 {
+            trigger,
+            echo: echo_pin,
+            temperature: 20.0,
+            last: None,
+        })
+    }
+
+    /// Compensate for the air temperature, e.g. from a BME280 next to it.
+    /// Each degree is about 0.18% of the distance.
+    pub fn set_temperature(&mut self, celsius: f64) {
+        self.temperature = celsius;
+    }
+
+    pub fn temperature(&self) -> f64 {
+        self.temperature
+    }
+
+    /// One measurement, in metres. Waits out [`HCSR04_MIN_INTERVAL`] since the
+    /// previous one first.
+    pub fn measure(&mut self) -> Result<f64, Box<dyn Error>> {
+        let width = self.echo_width()?;
+        let distance = width.as_secs_f64() * speed_of_sound(self.temperature) / 2.0;
+        if distance > HCSR04_MAX_RANGE {
+            return Err(format!("nothing within {} m", HCSR04_MAX_RANGE).into());
+        }
+        Ok(distance)
+    }
+
+    /// Like [`HcSr04::measure`] at `celsius`, which is kept for later
+    /// measurements too.
+    pub fn measure_at(&mut self, celsius: f64) -> Result<f64, Box<dyn Error>> {
+        self.set_temperature(celsius);
+        self.measure()
+    }
+
+    /// A measurement every `interval` (at least [`HCSR04_MIN_INTERVAL`]), forever.
+    pub fn readings(&mut self, interval: Duration) -> Readings<'_> {
+        Readings {
+            sensor: self,
+            interval: interval.max(HCSR04_MIN_INTERVAL),
+            next: Instant::now(),
+        }
+    }
+
+    fn echo_width(&mut self) -> Result<Duration, Box<dyn Error>> {
+        if let Some(wait) = self.last.and_then(|last| (last + HCSR04_MIN_INTERVAL).checked_duration_since(Instant::now())) {
+            thread::sleep(wait);
+        }
+        self.last = Some(Instant::now());
+        // Drop edges left from a pulse that timed out last time
+        while self.echo.poll_interrupt(true, Some(Duration::ZERO))?.is_some() {}
+        if self.echo.is_high() {
+            return Err("HC-SR04 echo is stuck high; check the wiring and the divider".into());
+        }
+
+        self.trigger.set_high();
+        PreciseDelay::new(TRIGGER_PULSE).delay(TRIGGER_PULSE);
+        self.trigger.set_low();
+
+        let rise = self
+            .edge(Trigger::RisingEdge, ECHO_START_TIMEOUT)?
+            .ok_or("no echo pulse from the HC-SR04; check power, TRIG and ECHO")?;
+        let fall = self
+            .edge(Trigger::FallingEdge, ECHO_TIMEOUT)?
+            .ok_or_else(|| format!("echo pulse longer than {:?}; nothing within {} m", ECHO_TIMEOUT, HCSR04_MAX_RANGE))?;
+        Ok(fall.timestamp.saturating_sub(rise.timestamp))
+    }
+
+    /// The next `trigger` edge, skipping others, or `None` after `timeout`.
+    fn edge(&mut self, trigger: Trigger, timeout: Duration) -> Result<Option<Event>, Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                return Ok(None);
+            };
+            match self.echo.poll_interrupt(false, Some(left))? {
+                Some(event) if event.trigger == trigger => return Ok(Some(event)),
+                Some(_) => {}
+                None => return Ok(None),
+            }
+        }
+    }
+
+    pub fn release(self) -> (OutputPin, InputPin) {
+        (self.trigger, self.echo)
+    }
+}
+
+/// Streaming measurements from [`HcSr04::readings`]. Failed pings come
+/// through as errors and the stream carries on.
+pub struct Readings<'a> {
+    sensor: &'a mut HcSr04,
+    interval: Duration,
+    next: Instant,
+}
+
+impl Iterator for Readings<'_> {
+    type Item = Result<f64, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(wait) = self.next.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        // After a slow ping, keep the spacing rather than catching up
+        self.next = self.next.max(Instant::now()) + self.interval;
+        Some(self.sensor.measure())
+    }
+}
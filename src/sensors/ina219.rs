@@ -0,0 +1,106 @@
+//! TI INA219 high-side current and power monitor.
+//!
+//! The calibration register is worked out from the shunt and the largest
+//! current expected, and the shunt ADC gain from their product, so the
+//! full 12 bits cover the range actually used.
+
+use super::{current_lsb, read_register, write_register, PowerMonitor, PowerReading};
+use crate::address::{Address, AddressedI2c};
+use std::error::Error;
+
+const CONFIG: u8 = 0x00;
+const SHUNT_VOLTAGE: u8 = 0x01;
+const BUS_VOLTAGE: u8 = 0x02;
+const POWER: u8 = 0x03;
+const CURRENT: u8 = 0x04;
+const CALIBRATION: u8 = 0x05;
+
+const RESET: u16 = 0x8000;
+/// 32 V bus range.
+const BRNG_32V: u16 = 1 << 13;
+/// 12-bit conversions, no averaging, for both ADCs.
+const ADC_12BIT: u16 = 0b0011;
+/// Shunt and bus, continuous.
+const MODE_CONTINUOUS: u16 = 0b111;
+
+/// Bus voltage register: conversion ready and math overflow flags.
+const OVERFLOW: u16 = 1 << 0;
+
+/// Shunt full-scale for each PGA setting, in volts.
+const SHUNT_RANGES: [f64; 4] = [0.04, 0.08, 0.16, 0.32];
+
+pub struct Ina219<I2C> {
+    i2c: I2C,
+    address: Address,
+    current_lsb: f64,
+}
+
+impl<I2C: AddressedI2c> Ina219<I2C> {
+    /// Reset the chip and calibrate it for `shunt_ohms` (0.1 on most
+    /// breakouts) and currents up to `max_current` amps.
+    pub fn new(mut i2c: I2C, address: Address, shunt_ohms: f64, max_current: f64) -> Result<Self, Box<dyn Error>> {
+        let current_lsb = current_lsb(shunt_ohms, max_current)?;
+        let shunt_max = shunt_ohms * max_current;
+        let gain = SHUNT_RANGES.iter().position(|&range| shunt_max <= range * (1.0 + 1e-9)).ok_or_else(|| {
+            format!(
+                "{} A through {} Ω is {:.0} mV, beyond the INA219's 320 mV shunt range",
+                max_current,
+                shunt_ohms,
+                shunt_max * 1e3
+            )
+        })? as u16;
+        let calibration = (0.04096 / (current_lsb * shunt_ohms)) as u32;
+        if calibration > 0xFFFE {
+            return Err(format!("{} Ω is too small a shunt to calibrate for {} A", shunt_ohms, max_current).into());
+        }
+        let config = BRNG_32V | gain << 11 | ADC_12BIT << 7 | ADC_12BIT << 3 | MODE_CONTINUOUS;
+        write_register(&mut i2c, address, CONFIG, RESET)?;
+        write_register(&mut i2c, address, CONFIG, config)?;
+        // Bit 0 is read-only
+        write_register(&mut i2c, address, CALIBRATION, calibration as u16 & 0xFFFE)?;
+        Ok(Ina219 { i2c, address, current_lsb })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn bus_voltage(&mut self) -> Result<f64, Box<dyn Error>> {
+        let raw = read_register(&mut self.i2c, self.address, BUS_VOLTAGE)?;
+        if raw & OVERFLOW != 0 {
+            return Err("INA219 overflow: current or power beyond the calibrated range".into());
+        }
+        // 13 bits of 4 mV above the two flags
+        Ok(f64::from(raw >> 3) * 4e-3)
+    }
+
+    pub fn shunt_voltage(&mut self) -> Result<f64, Box<dyn Error>> {
+        let raw = read_register(&mut self.i2c, self.address, SHUNT_VOLTAGE)? as i16;
+        Ok(f64::from(raw) * 10e-6)
+    }
+
+    pub fn current(&mut self) -> Result<f64, Box<dyn Error>> {
+        let raw = read_register(&mut self.i2c, self.address, CURRENT)? as i16;
+        Ok(f64::from(raw) * self.current_lsb)
+    }
+
+    pub fn power(&mut self) -> Result<f64, Box<dyn Error>> {
+        let raw = read_register(&mut self.i2c, self.address, POWER)?;
+        Ok(f64::from(raw) * 20.0 * self.current_lsb)
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: AddressedI2c> PowerMonitor for Ina219<I2C> {
+    fn read_power(&mut self) -> Result<PowerReading, Box<dyn Error>> {
+        Ok(PowerReading {
+            bus_voltage: self.bus_voltage()?,
+            shunt_voltage: self.shunt_voltage()?,
+            current: self.current()?,
+            power: self.power()?,
+        })
+    }
+}
@@ -0,0 +1,99 @@
+//! TI INA226 high-side current and power monitor.
+//!
+//! Like the INA219 but with a 16-bit ADC, a bus input up to 36 V and a
+//! fixed ±81.92 mV shunt range. Readings are averaged over 16 conversions.
+
+use super::{current_lsb, read_register, write_register, PowerMonitor, PowerReading};
+use crate::address::{Address, AddressedI2c};
+use std::error::Error;
+
+const CONFIG: u8 = 0x00;
+const SHUNT_VOLTAGE: u8 = 0x01;
+const BUS_VOLTAGE: u8 = 0x02;
+const POWER: u8 = 0x03;
+const CURRENT: u8 = 0x04;
+const CALIBRATION: u8 = 0x05;
+const MANUFACTURER_ID: u8 = 0xFE;
+
+/// "TI" in ASCII.
+const TEXAS_INSTRUMENTS: u16 = 0x5449;
+
+const RESET: u16 = 0x8000;
+/// 16 samples averaged, 1.1 ms bus and shunt conversions, continuous.
+const DEFAULT_CONFIG: u16 = 0x4000 | 0b010 << 9 | 0b100 << 6 | 0b100 << 3 | 0b111;
+
+const SHUNT_RANGE: f64 = 0.08192;
+
+pub struct Ina226<I2C> {
+    i2c: I2C,
+    address: Address,
+    current_lsb: f64,
+}
+
+impl<I2C: AddressedI2c> Ina226<I2C> {
+    /// Check the manufacturer ID, reset the chip and calibrate it for
+    /// `shunt_ohms` and currents up to `max_current` amps.
+    pub fn new(mut i2c: I2C, address: Address, shunt_ohms: f64, max_current: f64) -> Result<Self, Box<dyn Error>> {
+        let current_lsb = current_lsb(shunt_ohms, max_current)?;
+        if shunt_ohms * max_current > SHUNT_RANGE * (1.0 + 1e-9) {
+            return Err(format!(
+                "{} A through {} Ω is {:.1} mV, beyond the INA226's 81.92 mV shunt range",
+                max_current,
+                shunt_ohms,
+                shunt_ohms * max_current * 1e3
+            )
+            .into());
+        }
+        let id = read_register(&mut i2c, address, MANUFACTURER_ID)?;
+        if id != TEXAS_INSTRUMENTS {
+            return Err(format!("device at {} is not an INA226 (manufacturer ID 0x{:04X})", address, id).into());
+        }
+        let calibration = (0.00512 / (current_lsb * shunt_ohms)) as u32;
+        if calibration > 0x7FFF {
+            return Err(format!("{} Ω is too small a shunt to calibrate for {} A", shunt_ohms, max_current).into());
+        }
+        write_register(&mut i2c, address, CONFIG, RESET)?;
+        write_register(&mut i2c, address, CONFIG, DEFAULT_CONFIG)?;
+        write_register(&mut i2c, address, CALIBRATION, calibration as u16)?;
+        Ok(Ina226 { i2c, address, current_lsb })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn bus_voltage(&mut self) -> Result<f64, Box<dyn Error>> {
+        let raw = read_register(&mut self.i2c, self.address, BUS_VOLTAGE)?;
+        Ok(f64::from(raw) * 1.25e-3)
+    }
+
+    pub fn shunt_voltage(&mut self) -> Result<f64, Box<dyn Error>> {
+        let raw = read_register(&mut self.i2c, self.address, SHUNT_VOLTAGE)? as i16;
+        Ok(f64::from(raw) * 2.5e-6)
+    }
+
+    pub fn current(&mut self) -> Result<f64, Box<dyn Error>> {
+        let raw = read_register(&mut self.i2c, self.address, CURRENT)? as i16;
+        Ok(f64::from(raw) * self.current_lsb)
+    }
+
+    pub fn power(&mut self) -> Result<f64, Box<dyn Error>> {
+        let raw = read_register(&mut self.i2c, self.address, POWER)?;
+        Ok(f64::from(raw) * 25.0 * self.current_lsb)
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: AddressedI2c> PowerMonitor for Ina226<I2C> {
+    fn read_power(&mut self) -> Result<PowerReading, Box<dyn Error>> {
+        Ok(PowerReading {
+            bus_voltage: self.bus_voltage()?,
+            shunt_voltage: self.shunt_voltage()?,
+            current: self.current()?,
+            power: self.power()?,
+        })
+    }
+}
@@ -0,0 +1,605 @@
+//! Remote control over HTTP, so test rigs on the network can drive the
+//! Pi's peripherals without SSH.
+//!
+//! | endpoint           | body                                               | reply                        |
+//! |--------------------|----------------------------------------------------|------------------------------|
+//! | `GET /i2c/scan`    | (`?ten_bit=true` for 10-bit)                       | `{"devices": ["0x27"]}`      |
+//! | `POST /i2c/write`  | `{"address": "0x27", "bytes": [255]}`              | `{"written": 1}`             |
+//! | `POST /i2c/read`   | `{"address": "0x68", "write": [0], "count": 3}`    | `{"bytes": [48, 89, 35]}`    |
+//! | `POST /lcd/text`   | `{"address": "0x27", "cols": 16, "rows": 2, "text": "Hello\nworld"}` | `{"shown": true}` |
+//! | `GET /health`      |                                                    | `{"hostname": .., "version": .., "uptime_s": .., "cpu_temp": .., ..}` |
+//! | `GET /readings`    |                                                    | `{"readings": {"bme280.temperature": 21.4}}` |
+//! | `GET /metrics`     |                                                    | Prometheus text format       |
+//! | `GET /history`     |                                                    | `{"measurements": ["bme280.temperature"]}` |
+//! | `GET /history/NAME`| (`?window=5m` to limit the span)                   | `{"values": [...], "min": .., "max": .., "avg": .., "count": ..}` |
+//! | `GET /watches`     |                                                    | `{"watches": [{"name": .., "expr": .., "value": ..}]}` |
+//! | `POST /watches`    | `{"name": "delta", "expr": "bme280.temperature - ds18b20.temperature"}` | `{"watching": "delta"}` |
+//! | `DELETE /watches/NAME` |                                                | `{"removed": "delta"}`       |
+//! | `GET /totals`      |                                                    | `{"totals": [{"name": .., "source": .., "value": .., "since": ..}]}` |
+//! | `POST /totals/NAME/reset` |                                             | `{"reset": "rain"}`          |
+//! | `GET /ws`          | WebSocket upgrade                                  | a JSON event per transaction and reading |
+//! | `GET /dashboard`   |                                                    | a page showing the `/ws` stream |
+//!
+//! Addresses use the CLI notation, as strings. `write` in `/i2c/read` is
+//! optional and goes out with a repeated start. `/lcd/text` initializes the
+//! display on every call, so it also recovers one that lost power; `address`,
+//! `cols` and `rows` default to `0x27` and 16x2. The history endpoints
+//! serve whatever [`History`] was handed to [`Server::set_history`], and
+//! `/metrics` the registry from [`Server::set_metrics`]. Watches registered
+//! over `/watches` go into the [`Watches`] from [`Server::set_watches`]
+//! and show up under `/history` once evaluated; `/totals` reads and resets
+//! the [`Totals`] from [`Server::set_totals`]. For modes without
+//! the full API, [`spawn_metrics`] serves just `/metrics`. `/health` and
+//! `/readings` are what a [fleet](crate::fleet) controller polls, and
+//! [`spawn_fleet`] serves its combined view.
+//!
+//! `/ws` streams what goes on while it's connected, as JSON text frames:
+//! `{"type": "transaction", "address": "0x27", "ops": [..], "duration_us":
+//! .., "time": ..}` for every transaction through an [`EventBus`], the
+//! same shape as a [trace](crate::trace) entry, and `{"type": "reading",
+//! "measurement": .., "value": .., "time": ..}` for every sample recorded
+//! into the history. It needs [`Server::set_events`]; each client gets a
+//! thread of its own, so streaming doesn't hold up the other requests.
+//! Browsers can't send a bearer token on a page load or a WebSocket, so
+//! `/ws` and `/dashboard` also take it as `?token=`.
+//!
+//! Errors come back as `{"error": "..."}`: 400 for a bad request, 401/403
+//! from the token check, 502 when the bus or device fails. Requests are
+//! handled one at a time since they share the bus.
+
+mod events;
+mod http;
+mod tls;
+mod ws;
+
+pub use events::{EventBus, Events};
+pub use http::{HeadersTooLarge, Request, Response, MAX_BODY, MAX_LINE};
+pub use tls::load as load_tls;
+
+use crate::address::{Address, AddressedI2c};
+use crate::auth::{Scope, TokenStore};
+use crate::fleet::{Fleet, Health};
+use crate::history::{self, History};
+use crate::lcd::Lcd;
+use crate::metrics::Metrics;
+use crate::parse;
+use crate::say;
+use crate::scan;
+use crate::totals::Totals;
+use crate::watches::Watches;
+use tls::Conn;
+use rustls::ServerConfig;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accept-loop poll interval while waiting for connections or a shutdown.
+const POLL: Duration = Duration::from_millis(50);
+
+/// Content type of the Prometheus text exposition format.
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The `/dashboard` page.
+const DASHBOARD: &str = include_str!("server/dashboard.html");
+
+/// Close code for a server going away.
+const GOING_AWAY: [u8; 2] = 1001u16.to_be_bytes();
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WriteBody {
+    address: Address,
+    bytes: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadBody {
+    address: Address,
+    #[serde(default)]
+    write: Vec<u8>,
+    count: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WatchBody {
+    name: String,
+    expr: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LcdBody {
+    #[serde(default = "default_lcd_address")]
+    address: Address,
+    #[serde(default = "default_cols")]
+    cols: u8,
+    #[serde(default = "default_rows")]
+    rows: u8,
+    text: String,
+}
+
+fn default_lcd_address() -> Address {
+    Address::SevenBit(0x27)
+}
+
+fn default_cols() -> u8 {
+    16
+}
+
+fn default_rows() -> u8 {
+    2
+}
+
+pub struct Server<I2C> {
+    i2c: I2C,
+    tokens: Option<TokenStore>,
+    history: Option<History>,
+    metrics: Option<Metrics>,
+    watches: Option<Watches>,
+    totals: Option<Totals>,
+    events: Option<Events>,
+    tls: Option<Arc<ServerConfig>>,
+    streams: Vec<JoinHandle<()>>,
+}
+
+impl<I2C: AddressedI2c> Server<I2C> {
+    /// A server that accepts every request. Use [`Server::set_tokens`]
+    /// anywhere but an isolated bench network.
+    pub fn new(i2c: I2C) -> Self {
+        Server {
+            i2c,
+            tokens: None,
+            history: None,
+            metrics: None,
+            watches: None,
+            totals: None,
+            events: None,
+            tls: None,
+            streams: Vec::new(),
+        }
+    }
+
+    /// Require a bearer token; `GET`s need read scope, the rest control.
+    pub fn set_tokens(&mut self, tokens: TokenStore) {
+        self.tokens = Some(tokens);
+    }
+
+    /// Serve `GET /history` from these ring buffers.
+    pub fn set_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    /// Serve `GET /metrics` from this registry.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Serve `/watches` from this set.
+    pub fn set_watches(&mut self, watches: Watches) {
+        self.watches = Some(watches);
+    }
+
+    /// Serve `/totals` from this set.
+    pub fn set_totals(&mut self, totals: Totals) {
+        self.totals = Some(totals);
+    }
+
+    /// Stream these over `/ws`.
+    pub fn set_events(&mut self, events: Events) {
+        self.events = Some(events);
+    }
+
+    /// Speak HTTPS with these settings, from [`load_tls`].
+    pub fn set_tls(&mut self, tls: Arc<ServerConfig>) {
+        self.tls = Some(tls);
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Serve connections one after another until `stop` is set.
+    pub fn serve(&mut self, listener: &TcpListener, stop: &AtomicBool) -> Result<(), Box<dyn Error>> {
+        // Non-blocking accept so a shutdown isn't stuck behind an idle socket
+        listener.set_nonblocking(true)?;
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    if let Err(e) = self.connection(stream) {
+                        say!("⚠️  {}: {}", peer, e);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if let Some(events) = &self.events {
+            events.close();
+        }
+        for stream in self.streams.drain(..) {
+            let _ = stream.join();
+        }
+        Ok(())
+    }
+
+    fn connection(&mut self, stream: TcpStream) -> Result<(), Box<dyn Error>> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut stream = Conn::new(stream, self.tls.as_ref())?;
+        let response = match Request::read_from(&mut stream) {
+            Ok(request) if request.path == "/ws" => return self.stream_events(stream, &request),
+            Ok(request) => {
+                let response = self.handle(&request);
+                say!("🌐 {} {} → {}", request.method, request.path, response.status);
+                response
+            }
+            Err(e) => Response::unreadable(e),
+        };
+        response.write_to(&mut stream)?;
+        stream.close();
+        Ok(())
+    }
+
+    /// Upgrade a `/ws` request and hand the connection to a thread that
+    /// streams events to it until it closes or the server stops.
+    fn stream_events(&mut self, mut stream: Conn, request: &Request) -> Result<(), Box<dyn Error>> {
+        let handshake = self.authorize(request).and_then(|()| {
+            if self.events.is_none() {
+                return Err(Response::error(404, "no event stream is kept"));
+            }
+            if !ws::is_upgrade(request) {
+                return Err(Response::error(400, "/ws needs a WebSocket upgrade"));
+            }
+            ws::handshake(request).map_err(|e| Response::error(400, e))
+        });
+        let handshake = match handshake {
+            Ok(handshake) => handshake,
+            Err(response) => {
+                say!("🌐 {} {} → {}", request.method, request.path, response.status);
+                response.write_to(&mut stream)?;
+                stream.close();
+                return Ok(());
+            }
+        };
+        stream.write_all(handshake.as_bytes())?;
+        let Some(events) = &self.events else {
+            return Ok(());
+        };
+        let subscription = events.subscribe();
+        say!("🌐 {} {} → 101, {} client(s) streaming", request.method, request.path, events.subscribers());
+        self.streams.retain(|stream| !stream.is_finished());
+        let thread = thread::Builder::new().name("ws".into()).spawn(move || {
+            // a client that hangs up is the usual way for this to end
+            let _ = stream_to(stream, subscription);
+        })?;
+        self.streams.push(thread);
+        Ok(())
+    }
+
+    /// The token check, `Err` with the reply to send if it fails.
+    fn authorize(&self, request: &Request) -> Result<(), Response> {
+        let Some(tokens) = &self.tokens else {
+            return Ok(());
+        };
+        // browsers can't set headers on a page load or a WebSocket
+        let query = match request.path.as_str() {
+            "/ws" | "/dashboard" => request.query_param("token").map(|token| format!("Bearer {}", token)),
+            _ => None,
+        };
+        let header = request.header("authorization").or(query.as_deref());
+        match tokens.authorize(header, Scope::required_for(&request.method)) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Response::error(e.status_code(), e)),
+        }
+    }
+
+    /// Authorize and route one request.
+    pub fn handle(&mut self, request: &Request) -> Response {
+        if let Err(response) = self.authorize(request) {
+            return response;
+        }
+        let result = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/i2c/scan") => Ok(self.scan(request)),
+            ("POST", "/i2c/write") => body(request).map(|b| self.write(b)),
+            ("POST", "/i2c/read") => body(request).map(|b| self.read(b)),
+            ("POST", "/lcd/text") => body(request).map(|b| self.lcd_text(b)),
+            ("GET", "/health") => Ok(Response::json(200, &json!(Health::local()))),
+            ("GET", "/dashboard") => Ok(Response::text(200, "text/html; charset=utf-8", DASHBOARD.to_string())),
+            ("GET", "/readings") => Ok(self.readings()),
+            ("GET", "/metrics") => Ok(match &self.metrics {
+                Some(metrics) => Response::text(200, METRICS_CONTENT_TYPE, metrics.render()),
+                None => Response::error(404, "no metrics are kept"),
+            }),
+            ("GET", "/history") => Ok(self.measurements()),
+            ("GET", path) if path.starts_with("/history/") => Ok(self.history(request, &path["/history/".len()..])),
+            ("GET", "/watches") => Ok(self.list_watches()),
+            ("POST", "/watches") => body(request).map(|b| self.add_watch(b)),
+            ("DELETE", path) if path.starts_with("/watches/") => Ok(self.remove_watch(&path["/watches/".len()..])),
+            ("GET", "/totals") => Ok(self.list_totals()),
+            ("POST", path) if path.starts_with("/totals/") && path.ends_with("/reset") => {
+                match path.strip_prefix("/totals/").and_then(|rest| rest.strip_suffix("/reset")) {
+                    Some(name) if !name.is_empty() => Ok(self.reset_total(name)),
+                    _ => Err(Response::error(404, format!("no endpoint {}", path))),
+                }
+            }
+            (_, "/i2c/scan" | "/i2c/write" | "/i2c/read" | "/lcd/text" | "/watches" | "/totals") => {
+                Err(Response::error(405, format!("{} not allowed on {}", request.method, request.path)))
+            }
+            (_, path) => Err(Response::error(404, format!("no endpoint {}", path))),
+        };
+        result.unwrap_or_else(|response| response)
+    }
+
+    fn measurements(&self) -> Response {
+        let names = self.history.as_ref().map(History::measurements).unwrap_or_default();
+        Response::json(200, &json!({ "measurements": names }))
+    }
+
+    fn readings(&self) -> Response {
+        let readings: serde_json::Map<_, _> = self
+            .history
+            .iter()
+            .flat_map(|history| {
+                history
+                    .measurements()
+                    .into_iter()
+                    .filter_map(|name| Some((name.clone(), json!(history.latest(&name)?))))
+            })
+            .collect();
+        Response::json(200, &json!({ "readings": readings }))
+    }
+
+    fn history(&self, request: &Request, name: &str) -> Response {
+        let Some(history) = &self.history else {
+            return Response::error(404, "no history is kept");
+        };
+        let values = match request.query_param("window").map(parse::duration) {
+            None => history.values(name),
+            Some(Ok(window)) => history.values_within(name, window),
+            Some(Err(e)) => return Response::error(400, e),
+        };
+        if values.is_empty() && !history.measurements().iter().any(|m| m == name) {
+            return Response::error(404, format!("no measurement '{}'", name));
+        }
+        let mut reply = json!({ "measurement": name, "values": values });
+        if let Some(summary) = history::aggregate(&values) {
+            reply["count"] = json!(summary.count);
+            reply["min"] = json!(summary.min);
+            reply["max"] = json!(summary.max);
+            reply["avg"] = json!(summary.avg);
+        }
+        Response::json(200, &reply)
+    }
+
+    fn list_watches(&self) -> Response {
+        let Some(watches) = &self.watches else {
+            return Response::error(404, "no watches are kept");
+        };
+        let history = self.history.as_ref();
+        let list: Vec<_> = watches
+            .list()
+            .into_iter()
+            .map(|w| {
+                let value = history.and_then(|h| h.latest(&w.name));
+                json!({ "name": w.name, "expr": w.source, "value": value })
+            })
+            .collect();
+        Response::json(200, &json!({ "watches": list }))
+    }
+
+    fn add_watch(&mut self, body: WatchBody) -> Response {
+        let Some(watches) = &self.watches else {
+            return Response::error(404, "no watches are kept");
+        };
+        match watches.add(&body.name, &body.expr) {
+            Ok(()) => Response::json(200, &json!({ "watching": body.name })),
+            Err(e) => Response::error(400, e),
+        }
+    }
+
+    fn remove_watch(&mut self, name: &str) -> Response {
+        let Some(watches) = &self.watches else {
+            return Response::error(404, "no watches are kept");
+        };
+        if watches.remove(name) {
+            Response::json(200, &json!({ "removed": name }))
+        } else {
+            Response::error(404, format!("no watch '{}'", name))
+        }
+    }
+
+    fn list_totals(&self) -> Response {
+        let Some(totals) = &self.totals else {
+            return Response::error(404, "no totals are kept");
+        };
+        Response::json(200, &json!({ "totals": totals.list() }))
+    }
+
+    fn reset_total(&mut self, name: &str) -> Response {
+        let Some(totals) = &self.totals else {
+            return Response::error(404, "no totals are kept");
+        };
+        match totals.reset(name) {
+            Ok(true) => Response::json(200, &json!({ "reset": name })),
+            Ok(false) => Response::error(404, format!("no total '{}'", name)),
+            // Reset in memory; only saving it failed
+            Err(e) => Response::error(500, e),
+        }
+    }
+
+    fn scan(&mut self, request: &Request) -> Response {
+        let found = match request.query_param("ten_bit") {
+            Some("true" | "1") => scan::scan_ten_bit(&mut self.i2c),
+            _ => scan::scan(&mut self.i2c),
+        };
+        Response::json(200, &json!({ "devices": found }))
+    }
+
+    fn write(&mut self, body: WriteBody) -> Response {
+        match self.i2c.write_at(body.address, &body.bytes) {
+            Ok(()) => Response::json(200, &json!({ "written": body.bytes.len() })),
+            Err(e) => Response::error(502, e),
+        }
+    }
+
+    fn read(&mut self, body: ReadBody) -> Response {
+        if !(1..=4096).contains(&body.count) {
+            return Response::error(400, format!("count {} out of range (1-4096)", body.count));
+        }
+        let mut buf = vec![0; body.count];
+        let result = if body.write.is_empty() {
+            self.i2c.read_at(body.address, &mut buf)
+        } else {
+            self.i2c.write_read_at(body.address, &body.write, &mut buf)
+        };
+        match result {
+            Ok(()) => Response::json(200, &json!({ "bytes": buf })),
+            Err(e) => Response::error(502, e),
+        }
+    }
+
+    fn lcd_text(&mut self, body: LcdBody) -> Response {
+        let result = Lcd::new(&mut self.i2c, body.address, body.cols, body.rows).and_then(|mut lcd| lcd.show(&body.text));
+        match result {
+            Ok(()) => Response::json(200, &json!({ "shown": true })),
+            Err(e) => Response::error(502, e),
+        }
+    }
+}
+
+/// Serve `GET /metrics` on `listener` from a background thread until
+/// `stop` is set, for long-running modes that have no other API.
+pub fn spawn_metrics(listener: TcpListener, metrics: Metrics, stop: Arc<AtomicBool>) -> io::Result<JoinHandle<()>> {
+    spawn_get("metrics", listener, stop, move |path| match path {
+        "/metrics" => Some(Response::text(200, METRICS_CONTENT_TYPE, metrics.render())),
+        _ => None,
+    })
+}
+
+/// Serve a fleet controller's view: `GET /fleet` with the last poll of
+/// every agent, and `/metrics`.
+pub fn spawn_fleet(listener: TcpListener, fleet: Fleet, metrics: Metrics, stop: Arc<AtomicBool>) -> io::Result<JoinHandle<()>> {
+    spawn_get("fleet", listener, stop, move |path| match path {
+        "/fleet" => Some(Response::json(200, &json!({ "agents": fleet.last() }))),
+        "/metrics" => Some(Response::text(200, METRICS_CONTENT_TYPE, metrics.render())),
+        _ => None,
+    })
+}
+
+/// Answer `GET`s on a thread with `route`, which returns `None` for an
+/// unknown path. No auth: these only expose what `/metrics` already does.
+fn spawn_get<F>(name: &str, listener: TcpListener, stop: Arc<AtomicBool>, route: F) -> io::Result<JoinHandle<()>>
+where
+    F: Fn(&str) -> Option<Response> + Send + 'static,
+{
+    listener.set_nonblocking(true)?;
+    let label = name.to_string();
+    thread::Builder::new().name(name.into()).spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(POLL);
+                    continue;
+                }
+                Err(e) => {
+                    say!("⚠️  {} listener failed: {}", label, e);
+                    return;
+                }
+            };
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+            let response = match Request::read_from(&stream) {
+                Ok(request) if request.method == "GET" => {
+                    route(&request.path).unwrap_or_else(|| Response::error(404, format!("no endpoint {}", request.path)))
+                }
+                Ok(request) => Response::error(405, format!("{} not allowed on {}", request.method, request.path)),
+                Err(e) => Response::unreadable(e),
+            };
+            // A scraper that hung up early isn't worth reporting
+            let _ = response.write_to(&stream);
+        }
+    })
+}
+
+/// Send `events` to a WebSocket client as text frames, answering its
+/// pings, until it closes or the events stop.
+fn stream_to(mut stream: Conn, events: Receiver<String>) -> Result<(), Box<dyn Error>> {
+    // short, so reading for pings and closes doesn't hold up events
+    stream.set_read_timeout(Some(Duration::from_millis(1)))?;
+    let mut pending = Vec::new();
+    let mut buf = [0; 512];
+    loop {
+        match events.recv_timeout(POLL) {
+            Ok(event) => {
+                ws::write_frame(&mut stream, ws::TEXT, event.as_bytes())?;
+                for event in events.try_iter() {
+                    ws::write_frame(&mut stream, ws::TEXT, event.as_bytes())?;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(ws::write_frame(&mut stream, ws::CLOSE, &GOING_AWAY)?),
+        }
+        match stream.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => pending.extend_from_slice(&buf[..n]),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e.into()),
+        }
+        while let Some((frame, used)) = ws::parse_frame(&pending)? {
+            pending.drain(..used);
+            match frame.opcode {
+                ws::PING => ws::write_frame(&mut stream, ws::PONG, &frame.payload)?,
+                ws::CLOSE => return Ok(ws::write_frame(&mut stream, ws::CLOSE, &frame.payload)?),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Parse a JSON body, or the 400 to send back.
+fn body<T: DeserializeOwned>(request: &Request) -> Result<T, Response> {
+    serde_json::from_slice(&request.body).map_err(|e| Response::error(400, format!("invalid body: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::StubBus;
+    use crate::history::HistoryConfig;
+    use crate::totals::TotalsConfig;
+
+    fn post(path: &str) -> Request {
+        Request {
+            method: "POST".into(),
+            path: path.into(),
+            query: None,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn totals_reset_needs_a_name() {
+        let config: TotalsConfig = toml::from_str("[counters.energy]\nsource = \"ina219.power\"\nkind = \"rate\"").unwrap();
+        let mut server = Server::new(StubBus::new());
+        server.set_totals(Totals::from_config(&config, History::new(HistoryConfig::default())).unwrap());
+        assert_eq!(server.handle(&post("/totals/reset")).status, 404);
+        assert_eq!(server.handle(&post("/totals//reset")).status, 404);
+        assert_eq!(server.handle(&post("/totals/water/reset")).status, 404);
+        assert_eq!(server.handle(&post("/totals/energy/reset")).status, 200);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use crate::softi2c::Stretching;
+use crate::trace::{Direction, TraceEntry, TraceOp};
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Events queued for one client before it counts as too slow and misses
+/// some; nothing on the bus ever waits for a browser.
+const QUEUE: usize = 256;
+
+/// Fans events out to every `/ws` client.
+#[derive(Clone, Default)]
+pub struct Events {
+    subscribers: Arc<Mutex<Vec<SyncSender<String>>>>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Events::default()
+    }
+
+    pub fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE);
+        self.lock().push(sender);
+        receiver
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Send `event` to every client, stamped with `time` in Unix seconds.
+    /// A client whose queue is full misses it; one that's gone is dropped.
+    pub fn publish(&self, mut event: Value) {
+        let mut subscribers = self.lock();
+        if subscribers.is_empty() {
+            return;
+        }
+        event["time"] = json!(unix_time());
+        let text = event.to_string();
+        subscribers.retain(|sender| !matches!(sender.try_send(text.clone()), Err(TrySendError::Disconnected(_))));
+    }
+
+    /// A sensor reading, as recorded into the history.
+    pub fn reading(&self, measurement: &str, value: f64) {
+        self.publish(json!({ "type": "reading", "measurement": measurement, "value": value }));
+    }
+
+    /// Hang up on every client.
+    pub fn close(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<SyncSender<String>>> {
+        self.subscribers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn unix_time() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs_f64())
+}
+
+/// Wraps a bus and publishes every transaction through it as an event,
+/// in the same shape as a [`crate::trace`] entry.
+pub struct EventBus<I2C> {
+    i2c: I2C,
+    events: Events,
+    start: Instant,
+}
+
+impl<I2C> EventBus<I2C> {
+    pub fn new(i2c: I2C, events: Events) -> Self {
+        EventBus {
+            i2c,
+            events,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn publish(&self, began: Instant, duration: Duration, address: Address, ops: &[Operation<'_>], error: Option<String>) {
+        if self.events.subscribers() == 0 {
+            return;
+        }
+        let entry = TraceEntry {
+            timestamp: began.duration_since(self.start),
+            duration,
+            address,
+            ops: ops
+                .iter()
+                .map(|op| match op {
+                    Operation::Read(buf) => TraceOp {
+                        direction: Direction::Read,
+                        bytes: buf.to_vec(),
+                    },
+                    Operation::Write(buf) => TraceOp {
+                        direction: Direction::Write,
+                        bytes: buf.to_vec(),
+                    },
+                })
+                .collect(),
+            error,
+        };
+        let mut event = json!(entry);
+        event["type"] = json!("transaction");
+        self.events.publish(event);
+    }
+}
+
+impl<I2C: I2c> ErrorType for EventBus<I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> I2c for EventBus<I2C> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let began = Instant::now();
+        let result = self.i2c.transaction(address, operations);
+        let error = result.as_ref().err().map(|e| format!("{:?}", e));
+        self.publish(began, began.elapsed(), Address::SevenBit(address), operations, error);
+        result
+    }
+}
+
+impl<I2C: AddressedI2c> AddressedI2c for EventBus<I2C> {
+    fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Box<dyn Error>> {
+        let began = Instant::now();
+        let result = self.i2c.transaction_at(address, operations);
+        let error = result.as_ref().err().map(|e| e.to_string());
+        self.publish(began, began.elapsed(), address, operations, error);
+        result
+    }
+
+    fn repeated_start(&self, address: Address) -> bool {
+        self.i2c.repeated_start(address)
+    }
+}
+
+impl<I2C: BusControl> BusControl for EventBus<I2C> {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        self.i2c.clock_speed()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.i2c.set_timeout(timeout)
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        self.i2c.set_clock_speed(hz)
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        self.i2c.recover()
+    }
+
+    fn stretching(&self) -> Option<Stretching> {
+        self.i2c.stretching()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::StubBus;
+
+    #[test]
+    fn streams_transactions_to_subscribers() {
+        let events = Events::new();
+        let stub = StubBus::new();
+        stub.add_port(Address::SevenBit(0x20), 0);
+        let mut bus = EventBus::new(stub, events.clone());
+        // nobody listening: nothing is built
+        bus.write_at(Address::SevenBit(0x20), &[0x01]).unwrap();
+        let client = events.subscribe();
+        bus.write_at(Address::SevenBit(0x20), &[0xA5]).unwrap();
+        events.reading("bme280.temperature", 21.5);
+        let sent: Value = serde_json::from_str(&client.try_recv().unwrap()).unwrap();
+        assert_eq!(sent["type"], "transaction");
+        assert_eq!(sent["address"], "0x20");
+        assert_eq!(sent["ops"][0]["direction"], "write");
+        let reading: Value = serde_json::from_str(&client.try_recv().unwrap()).unwrap();
+        assert_eq!(reading["measurement"], "bme280.temperature");
+        assert!(client.try_recv().is_err());
+
+        drop(client);
+        events.reading("bme280.temperature", 21.6);
+        assert_eq!(events.subscribers(), 0);
+    }
+}
@@ -0,0 +1,207 @@
+//! Just enough HTTP/1.1 for the control API: one request per connection,
+//! bodies sized by `Content-Length`, no chunking or keep-alive.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// Largest request body accepted; LCD text and bus writes are tiny.
+pub const MAX_BODY: usize = 64 * 1024;
+
+/// Longest request or header line, its line break included.
+pub const MAX_LINE: usize = 8 * 1024;
+
+const MAX_HEADERS: usize = 64;
+
+/// A request line or header over [`MAX_LINE`], or more than
+/// 64 headers; answered with a 431.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadersTooLarge(String);
+
+impl fmt::Display for HeadersTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for HeadersTooLarge {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string.
+    pub path: String,
+    pub query: Option<String>,
+    /// Header names are lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn read_from<R: Read>(stream: R) -> Result<Self, Box<dyn Error>> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        read_line(&mut reader, &mut line)?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(format!("malformed request line '{}'", line.trim()).into());
+        };
+        if !version.starts_with("HTTP/1.") {
+            return Err(format!("unsupported protocol '{}'", version).into());
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (target.to_string(), None),
+        };
+        let method = method.to_string();
+
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            if read_line(&mut reader, &mut line)? == 0 {
+                return Err("connection closed in the headers".into());
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(HeadersTooLarge(format!("more than {} headers", MAX_HEADERS)).into());
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("malformed header '{}'", line))?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+
+        let length = match headers.iter().find(|(name, _)| name == "content-length") {
+            Some((_, value)) => value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid Content-Length '{}'", value))?,
+            None => 0,
+        };
+        if length > MAX_BODY {
+            return Err(format!("body of {} bytes is over the {} byte limit", length, MAX_BODY).into());
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+
+        Ok(Request { method, path, query, headers, body })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Value of `name` in the query string, without percent-decoding.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .as_deref()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// One line into `line`, refusing to buffer more than [`MAX_LINE`] of it.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> Result<usize, Box<dyn Error>> {
+    let read = reader.take(MAX_LINE as u64).read_line(line)?;
+    if read == MAX_LINE && !line.ends_with('\n') {
+        return Err(HeadersTooLarge(format!("line over the {} byte limit", MAX_LINE)).into());
+    }
+    Ok(read)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: value.to_string(),
+        }
+    }
+
+    pub fn text(status: u16, content_type: &'static str, body: String) -> Self {
+        Response {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    /// `{"error": message}` with `status`.
+    pub fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Response::json(status, &serde_json::json!({ "error": message.to_string() }))
+    }
+
+    /// The answer to a request [`Request::read_from`] couldn't read: 431
+    /// for [`HeadersTooLarge`], else 400.
+    pub fn unreadable(e: Box<dyn Error>) -> Self {
+        let status = if e.is::<HeadersTooLarge>() { 431 } else { 400 };
+        Response::error(status, e)
+    }
+
+    pub fn write_to<W: Write>(&self, mut stream: W) -> std::io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len(),
+            self.body
+        )?;
+        stream.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(request: &[u8]) -> u16 {
+        match Request::read_from(request) {
+            Ok(_) => 200,
+            Err(e) => Response::unreadable(e).status,
+        }
+    }
+
+    #[test]
+    fn oversized_heads_get_431() {
+        assert_eq!(status(b"GET /status HTTP/1.1\r\nHost: pi\r\n\r\n"), 200);
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(status(long.as_bytes()), 431);
+        let header = format!("GET / HTTP/1.1\r\nX-Pad: {}\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(status(header.as_bytes()), 431);
+        let many = format!("GET / HTTP/1.1\r\n{}\r\n", "X-A: 1\r\n".repeat(MAX_HEADERS + 1));
+        assert_eq!(status(many.as_bytes()), 431);
+        assert_eq!(status(b"nonsense\r\n\r\n"), 400);
+    }
+}
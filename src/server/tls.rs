@@ -0,0 +1,92 @@
+//! TLS for the control API, so tokens and readings don't cross the network
+//! in the clear. A PEM certificate chain and private key are all it needs;
+//! a self-signed pair from `openssl req -x509` does for a bench network.
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Server settings from a PEM certificate chain (leaf first) and its key.
+pub fn load(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>, Box<dyn Error>> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{}: {}", cert.display(), e))?;
+    if chain.is_empty() {
+        return Err(format!("{}: no certificates", cert.display()).into());
+    }
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|e| format!("{}: {}", key.display(), e))?;
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|e| format!("certificate and key don't go together: {}", e))?;
+    Ok(Arc::new(config))
+}
+
+/// A client connection, in the clear or over TLS. The handshake happens on
+/// the first read, under whatever read timeout is set.
+pub enum Conn {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ServerConnection, TcpStream>>),
+}
+
+impl Conn {
+    /// Wrap an accepted `stream`, in TLS when there is a `config`.
+    pub fn new(stream: TcpStream, config: Option<&Arc<ServerConfig>>) -> Result<Self, Box<dyn Error>> {
+        Ok(match config {
+            Some(config) => Conn::Tls(Box::new(StreamOwned::new(ServerConnection::new(Arc::clone(config))?, stream))),
+            None => Conn::Plain(stream),
+        })
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Conn::Plain(stream) => stream,
+            Conn::Tls(tls) => tls.get_ref(),
+        }
+    }
+
+    /// End a TLS session with a close_notify, so clients can tell the
+    /// reply wasn't cut short.
+    pub fn close(&mut self) {
+        if let Conn::Tls(tls) = self {
+            tls.conn.send_close_notify();
+            let _ = tls.flush();
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+}
+
+impl Read for Conn {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Conn::Plain(stream) => stream.read(buf),
+            Conn::Tls(tls) => tls.read(buf),
+        }
+    }
+}
+
+impl Write for Conn {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Conn::Plain(stream) => stream.write(buf),
+            Conn::Tls(tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Conn::Plain(stream) => stream.flush(),
+            Conn::Tls(tls) => tls.flush(),
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Just enough WebSocket (RFC 6455) for pushing events to a browser: the
+//! opening handshake, unfragmented server frames, and reading the client's
+//! pings and close. Client messages are otherwise ignored.
+
+use super::Request;
+use std::error::Error;
+use std::io::{self, Write};
+
+/// Appended to the client's key before hashing, per the RFC.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest client frame payload read; pings and closes are tiny.
+const MAX_CLIENT_PAYLOAD: usize = 4096;
+
+pub const TEXT: u8 = 0x1;
+pub const CLOSE: u8 = 0x8;
+pub const PING: u8 = 0x9;
+pub const PONG: u8 = 0xA;
+
+/// Whether `request` asks to switch to WebSocket.
+pub fn is_upgrade(request: &Request) -> bool {
+    request.method == "GET"
+        && request.header("upgrade").is_some_and(|u| u.eq_ignore_ascii_case("websocket"))
+        && request
+            .header("connection")
+            .is_some_and(|c| c.split(',').any(|token| token.trim().eq_ignore_ascii_case("upgrade")))
+}
+
+/// The `101 Switching Protocols` reply to an upgrade request.
+pub fn handshake(request: &Request) -> Result<String, Box<dyn Error>> {
+    let key = request.header("sec-websocket-key").ok_or("missing Sec-WebSocket-Key")?;
+    if request.header("sec-websocket-version") != Some("13") {
+        return Err("only WebSocket version 13 is supported".into());
+    }
+    Ok(format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    ))
+}
+
+/// `Sec-WebSocket-Accept` for a client's `key`.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+/// One unmasked, final frame, as servers send them.
+pub fn write_frame<W: Write>(mut stream: W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+/// A frame from the client, unmasked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// The first whole frame in `bytes` and how many bytes it took, or `None`
+/// until more arrives.
+pub fn parse_frame(bytes: &[u8]) -> Result<Option<(Frame, usize)>, Box<dyn Error>> {
+    let [first, second, ..] = *bytes else {
+        return Ok(None);
+    };
+    let opcode = first & 0x0F;
+    let masked = second & 0x80 != 0;
+    let (len, mut at) = match second & 0x7F {
+        126 if bytes.len() >= 4 => (usize::from(u16::from_be_bytes([bytes[2], bytes[3]])), 4),
+        127 if bytes.len() >= 10 => {
+            let mut be = [0; 8];
+            be.copy_from_slice(&bytes[2..10]);
+            (usize::try_from(u64::from_be_bytes(be)).unwrap_or(usize::MAX), 10)
+        }
+        126 | 127 => return Ok(None),
+        len => (usize::from(len), 2),
+    };
+    if len > MAX_CLIENT_PAYLOAD {
+        return Err(format!("client frame of {} bytes is over the {} byte limit", len, MAX_CLIENT_PAYLOAD).into());
+    }
+    let mask = if masked {
+        let Some(key) = bytes.get(at..at + 4) else {
+            return Ok(None);
+        };
+        at += 4;
+        [key[0], key[1], key[2], key[3]]
+    } else {
+        [0; 4]
+    };
+    let Some(payload) = bytes.get(at..at + len) else {
+        return Ok(None);
+    };
+    let payload = payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]).collect();
+    Ok(Some((Frame { opcode, payload }, at + len)))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    let mut digest = [0; 20];
+    for (out, word) in digest.chunks_mut(4).zip(h) {
+        out.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_rfc_sample_key() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"ab"), "YWI=");
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut sent = Vec::new();
+        write_frame(&mut sent, TEXT, b"hi").unwrap();
+        assert_eq!(sent, [0x81, 2, b'h', b'i']);
+        // a masked client close, as in RFC 6455 section 5.7
+        let close = [0x88, 0x82, 0x37, 0xFA, 0x21, 0x3D, 0x37 ^ 0x03, 0xFA ^ 0xE8];
+        assert_eq!(parse_frame(&close[..5]).unwrap(), None);
+        let frame = Frame {
+            opcode: CLOSE,
+            payload: vec![0x03, 0xE8],
+        };
+        assert_eq!(parse_frame(&close).unwrap(), Some((frame, 8)));
+    }
+}
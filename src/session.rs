@@ -0,0 +1,198 @@
+//! The demo's end-of-run summary as a file, to archive and compare runs.
+//!
+//! A [`SessionReport`] holds what the summary prints: the messages sent,
+//! bus timing, failed writes and what was detected on the bus. Written as
+//! JSON it has a fixed shape, versioned by `schema`, so reports from runs
+//! with different firmware, wiring or pull-ups can be diffed or loaded
+//! side by side:
+//!
+//! ```json
+//! {
+//!   "schema": 1,
+//!   "tool_version": "0.1.0",
+//!   "started": 1760400000,
+//!   "label": "4k7 pull-ups",
+//!   "preset": "rhythm",
+//!   "framing": "per-byte",
+//!   "encoding": "ascii",
+//!   "integrity": "none",
+//!   "markers": "start=0xFF,end=0x00",
+//!   "bus": { "speed_hz": 100000, "expected_speed_hz": null },
+//!   "detection": { "candidates": ["0x27", "0x3F"], "found": "0x27", "target": "0x27" },
+//!   "messages": 5,
+//!   "interrupted": false,
+//!   "duration_us": 2000113,
+//!   "transactions": 80,
+//!   "bytes": 80,
+//!   "bus_time_us": 8420,
+//!   "per_byte_ns": 105250,
+//!   "errors": 0,
+//!   "mismatches": 0
+//! }
+//! ```
+//!
+//! New fields may be added under the same `schema`; renaming or removing
+//! one bumps it. Markdown is the same report as a table, for a lab notebook.
+//!
+//! A [`Journal`] goes further, keeping every message of the run for a
+//! report with a timing table, an error summary, a histogram of message
+//! times and the payload byte by byte, as Markdown or one HTML file.
+
+mod journal;
+
+pub use journal::{Journal, MessageRecord};
+
+use crate::address::Address;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Bumped when a field changes meaning or goes away.
+pub const SCHEMA: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Markdown,
+}
+
+impl ReportFormat {
+    /// `.md` and `.markdown` are Markdown; anything else JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("md" | "markdown") => ReportFormat::Markdown,
+            _ => ReportFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BusInfo {
+    /// What the kernel reports, when it does.
+    pub speed_hz: Option<u32>,
+    /// What `--speed` asked for.
+    pub expected_speed_hz: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Detection {
+    /// Addresses tried, in order.
+    pub candidates: Vec<Address>,
+    /// The first that answered.
+    pub found: Option<Address>,
+    /// Where the messages went: `found`, or the first candidate if nothing was.
+    pub target: Option<Address>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionReport {
+    pub schema: u32,
+    pub tool_version: String,
+    /// Seconds since the Unix epoch.
+    pub started: u64,
+    /// Free text naming the experiment, e.g. the pull-ups fitted.
+    pub label: Option<String>,
+    pub preset: String,
+    pub framing: String,
+    pub encoding: String,
+    pub integrity: String,
+    /// The start, end and escape bytes around each message; `none` when
+    /// framed.
+    pub markers: String,
+    pub bus: BusInfo,
+    pub detection: Detection,
+    pub messages: u32,
+    pub interrupted: bool,
+    pub duration_us: u64,
+    pub transactions: u32,
+    pub bytes: u32,
+    /// Time in bus writes alone, without the gaps between them.
+    pub bus_time_us: u64,
+    pub per_byte_ns: u64,
+    /// Writes that failed: NACKs, timeouts, arbitration losses.
+    pub errors: u32,
+    /// Messages whose check value didn't read back.
+    pub mismatches: u32,
+}
+
+impl SessionReport {
+    /// Everything else starts zeroed, for the run to fill in.
+    pub fn new(started: u64, preset: &str, framing: &str, encoding: &str) -> Self {
+        SessionReport {
+            schema: SCHEMA,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            started,
+            label: None,
+            preset: preset.to_string(),
+            framing: framing.to_string(),
+            encoding: encoding.to_string(),
+            integrity: "none".to_string(),
+            markers: "start=0xFF,end=0x00".to_string(),
+            bus: BusInfo::default(),
+            detection: Detection::default(),
+            messages: 0,
+            interrupted: false,
+            duration_us: 0,
+            transactions: 0,
+            bytes: 0,
+            bus_time_us: 0,
+            per_byte_ns: 0,
+            errors: 0,
+            mismatches: 0,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// The report as (field, value) pairs, in the order the JSON has them.
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        let opt = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+        let candidates: Vec<String> = self.detection.candidates.iter().map(Address::to_string).collect();
+        vec![
+            ("Schema", self.schema.to_string()),
+            ("Tool version", self.tool_version.clone()),
+            ("Started (Unix)", self.started.to_string()),
+            ("Label", opt(self.label.clone())),
+            ("Preset", self.preset.clone()),
+            ("Framing", self.framing.clone()),
+            ("Encoding", self.encoding.clone()),
+            ("Integrity", self.integrity.clone()),
+            ("Markers", self.markers.clone()),
+            ("Bus speed (Hz)", opt(self.bus.speed_hz.map(|hz| hz.to_string()))),
+            ("Expected speed (Hz)", opt(self.bus.expected_speed_hz.map(|hz| hz.to_string()))),
+            ("Candidates", candidates.join(" ")),
+            ("Found", opt(self.detection.found.map(|a| a.to_string()))),
+            ("Target", opt(self.detection.target.map(|a| a.to_string()))),
+            ("Messages", self.messages.to_string()),
+            ("Interrupted", self.interrupted.to_string()),
+            ("Duration (µs)", self.duration_us.to_string()),
+            ("Transactions", self.transactions.to_string()),
+            ("Bytes", self.bytes.to_string()),
+            ("Bus time (µs)", self.bus_time_us.to_string()),
+            ("Per byte (ns)", self.per_byte_ns.to_string()),
+            ("Errors", self.errors.to_string()),
+            ("Mismatches", self.mismatches.to_string()),
+        ]
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Session summary\n\n| Field | Value |\n|---|---|\n");
+        for (field, value) in self.rows() {
+            let _ = writeln!(out, "| {} | {} |", field, value.replace('|', "\\|"));
+        }
+        out
+    }
+
+    /// Write as JSON or Markdown, by `path`'s extension.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let text = match ReportFormat::from_path(path) {
+            ReportFormat::Json => self.to_json()? + "\n",
+            ReportFormat::Markdown => self.to_markdown(),
+        };
+        fs::write(path, text).map_err(|e| format!("cannot write {}: {}", path.display(), e).into())
+    }
+}
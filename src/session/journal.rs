@@ -0,0 +1,289 @@
+use super::SessionReport;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Bars in the message-time histogram.
+const BUCKETS: usize = 10;
+
+/// Bytes of the first message broken down one by one; past it, a count.
+const BREAKDOWN: usize = 64;
+
+/// One message of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageRecord {
+    pub number: u32,
+    /// From the start of the run to the message's first write.
+    pub at: Duration,
+    pub took: Duration,
+    /// The silence after it; zero for the last.
+    pub gap: Duration,
+    pub bytes: usize,
+    /// Writes that failed while it went out.
+    pub errors: u32,
+    /// Its check value didn't read back.
+    pub mismatch: bool,
+}
+
+/// Every message of a run as it went, for a bring-up report: the
+/// [`SessionReport`] as the configuration, then a timing table, errors, a
+/// histogram of message times and the payload byte by byte. Markdown or a
+/// single HTML file with nothing to fetch, so it can be attached to a
+/// ticket as is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Journal {
+    messages: Vec<MessageRecord>,
+    payload: Option<(String, Vec<u8>)>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal::default()
+    }
+
+    pub fn record(&mut self, message: MessageRecord) {
+        self.messages.push(message);
+    }
+
+    /// The wait that followed the last message recorded.
+    pub fn set_gap(&mut self, gap: Duration) {
+        if let Some(last) = self.messages.last_mut() {
+            last.gap = gap;
+        }
+    }
+
+    /// What was sent, and the first message of it; later calls are ignored.
+    pub fn set_payload(&mut self, label: &str, first: &[u8]) {
+        self.payload.get_or_insert_with(|| (label.to_string(), first.to_vec()));
+    }
+
+    pub fn messages(&self) -> &[MessageRecord] {
+        &self.messages
+    }
+
+    /// Message times in [`BUCKETS`] equal ranges from the fastest to the
+    /// slowest, as `(from, to, count)`.
+    pub fn histogram(&self) -> Vec<(Duration, Duration, usize)> {
+        let (Some(min), Some(max)) = (self.messages.iter().map(|m| m.took).min(), self.messages.iter().map(|m| m.took).max()) else {
+            return Vec::new();
+        };
+        let width = ((max - min) / BUCKETS as u32).max(Duration::from_nanos(1));
+        let mut buckets: Vec<_> = (0..BUCKETS as u32).map(|i| (min + width * i, min + width * (i + 1), 0)).collect();
+        for message in &self.messages {
+            let i = (((message.took - min).as_nanos() / width.as_nanos()) as usize).min(BUCKETS - 1);
+            buckets[i].2 += 1;
+        }
+        // A run where every message took the same needs one bar
+        if max == min {
+            buckets.truncate(1);
+        }
+        buckets
+    }
+
+    pub fn to_markdown(&self, summary: &SessionReport) -> String {
+        let mut out = String::from("# Run report\n\n## Configuration\n\n| Field | Value |\n|---|---|\n");
+        for (field, value) in summary.rows() {
+            let _ = writeln!(out, "| {} | {} |", field, value.replace('|', "\\|"));
+        }
+
+        out.push_str("\n## Errors\n\n");
+        for line in self.error_lines(summary) {
+            let _ = writeln!(out, "- {}", line);
+        }
+
+        out.push_str("\n## Message times\n\n```text\n");
+        let histogram = self.histogram();
+        let most = histogram.iter().map(|b| b.2).max().unwrap_or(0).max(1);
+        for (from, to, count) in &histogram {
+            let bar = "█".repeat((count * 40).div_ceil(most));
+            let _ = writeln!(out, "{:>9.3}-{:<9.3}ms {:<40} {}", ms(*from), ms(*to), bar, count);
+        }
+        out.push_str("```\n");
+
+        out.push_str("\n## Messages\n\n| # | At (ms) | Took (ms) | Gap (ms) | Bytes | Errors | Check |\n|---|---|---|---|---|---|---|\n");
+        for m in &self.messages {
+            let _ = writeln!(
+                out,
+                "| {} | {:.3} | {:.3} | {:.3} | {} | {} | {} |",
+                m.number,
+                ms(m.at),
+                ms(m.took),
+                ms(m.gap),
+                m.bytes,
+                m.errors,
+                if m.mismatch { "mismatch" } else { "ok" }
+            );
+        }
+
+        if let Some((label, bytes)) = &self.payload {
+            let _ = writeln!(out, "\n## Payload\n\n{}, first message {} bytes (markers {}):\n", label, bytes.len(), summary.markers);
+            out.push_str("| # | Char | Hex | Binary |\n|---|---|---|---|\n");
+            for (i, &byte) in bytes.iter().take(BREAKDOWN).enumerate() {
+                let _ = writeln!(out, "| {} | {} | 0x{:02X} | {:08b} |", i, shown(byte).replace('|', "\\|"), byte, byte);
+            }
+            if bytes.len() > BREAKDOWN {
+                let _ = writeln!(out, "\n…and {} more bytes.", bytes.len() - BREAKDOWN);
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self, summary: &SessionReport) -> String {
+        let mut out = String::from(concat!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Run report</title>\n<style>\n",
+            "body { font-family: sans-serif; margin: 2em; color: #222; }\n",
+            "table { border-collapse: collapse; margin-bottom: 1.5em; }\n",
+            "td, th { border: 1px solid #ccc; padding: 2px 8px; text-align: right; }\n",
+            "th { background: #eee; } td.text { text-align: left; font-family: monospace; }\n",
+            ".bar { background: #4a7ebb; height: 1em; } .bad { color: #b00; }\n",
+            "</style></head><body>\n<h1>Run report</h1>\n<h2>Configuration</h2>\n<table>\n",
+        ));
+        for (field, value) in summary.rows() {
+            let _ = writeln!(out, "<tr><th>{}</th><td class=\"text\">{}</td></tr>", escape(field), escape(&value));
+        }
+        out.push_str("</table>\n<h2>Errors</h2>\n<ul>\n");
+        for line in self.error_lines(summary) {
+            let _ = writeln!(out, "<li>{}</li>", escape(&line));
+        }
+
+        out.push_str("</ul>\n<h2>Message times</h2>\n<table>\n<tr><th>From (ms)</th><th>To (ms)</th><th>Messages</th><th></th></tr>\n");
+        let histogram = self.histogram();
+        let most = histogram.iter().map(|b| b.2).max().unwrap_or(0).max(1);
+        for (from, to, count) in &histogram {
+            let _ = writeln!(
+                out,
+                "<tr><td>{:.3}</td><td>{:.3}</td><td>{}</td><td class=\"text\" style=\"width: 300px\"><div class=\"bar\" style=\"width: {}%\"></div></td></tr>",
+                ms(*from),
+                ms(*to),
+                count,
+                count * 100 / most
+            );
+        }
+
+        out.push_str("</table>\n<h2>Messages</h2>\n<table>\n<tr><th>#</th><th>At (ms)</th><th>Took (ms)</th><th>Gap (ms)</th><th>Bytes</th><th>Errors</th><th>Check</th></tr>\n");
+        for m in &self.messages {
+            let class = if m.errors > 0 || m.mismatch { " class=\"bad\"" } else { "" };
+            let _ = writeln!(
+                out,
+                "<tr{}><td>{}</td><td>{:.3}</td><td>{:.3}</td><td>{:.3}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                class,
+                m.number,
+                ms(m.at),
+                ms(m.took),
+                ms(m.gap),
+                m.bytes,
+                m.errors,
+                if m.mismatch { "mismatch" } else { "ok" }
+            );
+        }
+        out.push_str("</table>\n");
+
+        if let Some((label, bytes)) = &self.payload {
+            let _ = writeln!(
+                out,
+                "<h2>Payload</h2>\n<p>{}, first message {} bytes (markers {}):</p>\n<table>\n<tr><th>#</th><th>Char</th><th>Hex</th><th>Binary</th></tr>",
+                escape(label),
+                bytes.len(),
+                escape(&summary.markers)
+            );
+            for (i, &byte) in bytes.iter().take(BREAKDOWN).enumerate() {
+                let _ = writeln!(
+                    out,
+                    "<tr><td>{}</td><td class=\"text\">{}</td><td class=\"text\">0x{:02X}</td><td class=\"text\">{:08b}</td></tr>",
+                    i,
+                    escape(&shown(byte)),
+                    byte,
+                    byte
+                );
+            }
+            out.push_str("</table>\n");
+            if bytes.len() > BREAKDOWN {
+                let _ = writeln!(out, "<p>…and {} more bytes.</p>", bytes.len() - BREAKDOWN);
+            }
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+
+    /// HTML for `.html` and `.htm`, Markdown otherwise.
+    pub fn save(&self, path: &Path, summary: &SessionReport) -> Result<(), Box<dyn Error>> {
+        let html = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"));
+        let text = if html { self.to_html(summary) } else { self.to_markdown(summary) };
+        fs::write(path, text).map_err(|e| format!("cannot write {}: {}", path.display(), e).into())
+    }
+
+    fn error_lines(&self, summary: &SessionReport) -> Vec<String> {
+        let mut lines = vec![
+            format!("{} failed writes in {} transactions", summary.errors, summary.transactions),
+            format!("{} messages read back wrong", summary.mismatches),
+        ];
+        let bad: Vec<String> = self.messages.iter().filter(|m| m.errors > 0 || m.mismatch).map(|m| m.number.to_string()).collect();
+        if !bad.is_empty() {
+            lines.push(format!("Messages with errors: {}", bad.join(", ")));
+        }
+        if summary.detection.found.is_none() {
+            lines.push("No device answered; every write was NACKed".to_string());
+        }
+        if summary.interrupted {
+            lines.push("Interrupted before the end".to_string());
+        }
+        lines
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e3
+}
+
+/// The character quoted, or `-` for one that doesn't print.
+fn shown(byte: u8) -> String {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        format!("'{}'", byte as char)
+    } else {
+        "-".to_string()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_message() {
+        let mut journal = Journal::new();
+        for (number, took) in [(1, 10), (2, 11), (3, 30)] {
+            journal.record(MessageRecord {
+                number,
+                at: Duration::from_millis(40 * u64::from(number)),
+                took: Duration::from_millis(took),
+                bytes: 2,
+                errors: u32::from(number == 3),
+                ..MessageRecord::default()
+            });
+            journal.set_gap(Duration::from_millis(took));
+        }
+        journal.set_payload("text <b>", b"H\x01");
+        let histogram = journal.histogram();
+        assert_eq!(histogram.len(), BUCKETS);
+        assert_eq!(histogram.iter().map(|b| b.2).collect::<Vec<_>>(), [2, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        let mut summary = SessionReport::new(0, "rhythm", "per-byte", "ascii");
+        summary.errors = 1;
+        let markdown = journal.to_markdown(&summary);
+        assert!(markdown.contains("| 3 | 120.000 | 30.000 | 30.000 | 2 | 1 | ok |"));
+        assert!(markdown.contains("Messages with errors: 3"));
+        assert!(markdown.contains("| 0 | 'H' | 0x48 | 01001000 |"));
+        let html = journal.to_html(&summary);
+        assert!(html.contains("text &lt;b&gt;"));
+        assert!(html.contains("<tr class=\"bad\"><td>3</td>"));
+    }
+}
@@ -0,0 +1,95 @@
+//! Ctrl-C / SIGTERM handling with cleanup hooks.
+//!
+//! The first signal only sets a flag: loops poll [`Shutdown::requested`] (or
+//! sleep through [`Shutdown::sleep`]) and wind down at a safe point, then
+//! [`Shutdown::run_hooks`] puts the hardware back into a safe state. A second
+//! signal exits immediately, in case the cleanup itself is what's hung.
+
+use crate::say;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Hook = Box<dyn FnOnce() -> Result<(), Box<dyn Error>> + Send>;
+
+/// Exit status used when a second signal forces an immediate exit.
+const FORCED_EXIT_CODE: i32 = 130;
+
+pub struct Shutdown {
+    flag: Arc<AtomicBool>,
+    hooks: Mutex<Vec<(String, Hook)>>,
+}
+
+impl Shutdown {
+    /// Install the SIGINT/SIGTERM handlers.
+    pub fn install() -> Result<Self, Box<dyn Error>> {
+        let flag = Arc::new(AtomicBool::new(false));
+        for signal in [SIGINT, SIGTERM] {
+            // Order matters: the exit check must see the flag before it's set.
+            flag::register_conditional_shutdown(signal, FORCED_EXIT_CODE, Arc::clone(&flag))?;
+            flag::register(signal, Arc::clone(&flag))?;
+        }
+        Ok(Shutdown {
+            flag,
+            hooks: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Whether a signal has arrived.
+    pub fn requested(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    /// The raw flag, for code that should stop early but can't hold a `&Shutdown`.
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.flag)
+    }
+
+    /// Sleep for `duration`, waking early on shutdown. Returns `false` if interrupted.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        // Too far off for an Instant: sleep until shutdown
+        let Some(deadline) = Instant::now().checked_add(duration) else {
+            while !self.requested() {
+                thread::sleep(Duration::from_millis(20));
+            }
+            return false;
+        };
+        while !self.requested() {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            thread::sleep((deadline - now).min(Duration::from_millis(20)));
+        }
+        false
+    }
+
+    /// Register a cleanup step. Hooks run in reverse registration order, so
+    /// later-initialized devices are released before what they depend on.
+    pub fn on_shutdown<F>(&self, name: impl Into<String>, hook: F)
+    where
+        F: FnOnce() -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        self.lock_hooks().push((name.into(), Box::new(hook)));
+    }
+
+    /// Run and drop every registered hook. A failing hook is reported and
+    /// the rest still run.
+    pub fn run_hooks(&self) {
+        let hooks = std::mem::take(&mut *self.lock_hooks());
+        for (name, hook) in hooks.into_iter().rev() {
+            match hook() {
+                Ok(()) => say!("🧹 {}", name),
+                Err(e) => say!("⚠️  {} failed: {}", name, e),
+            }
+        }
+    }
+
+    fn lock_hooks(&self) -> std::sync::MutexGuard<'_, Vec<(String, Hook)>> {
+        self.hooks.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
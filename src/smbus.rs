@@ -0,0 +1,233 @@
+//! SMBus transactions on top of a plain I2C bus.
+//!
+//! Battery gauges, PMBus supplies and similar parts expect SMBus framing:
+//! little-endian words, length-prefixed blocks and, optionally, a Packet
+//! Error Code (CRC-8 over every byte on the wire including the address
+//! bytes). The kernel's SMBus ioctls aren't all supported by the Pi's
+//! controller, so everything here is built from `embedded-hal` transactions.
+
+use crate::crc;
+use embedded_hal::i2c::I2c;
+use std::error::Error;
+
+/// Longest block the SMBus 2.0 spec allows.
+pub const MAX_BLOCK_LEN: usize = 32;
+
+pub struct SmBusDevice<I2C> {
+    i2c: I2C,
+    address: u8,
+    pec: bool,
+}
+
+impl<I2C> SmBusDevice<I2C>
+where
+    I2C: I2c,
+    I2C::Error: Error + 'static,
+{
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        SmBusDevice { i2c, address, pec: false }
+    }
+
+    /// Append PEC to writes and require a valid PEC on reads.
+    pub fn set_pec(&mut self, enabled: bool) {
+        self.pec = enabled;
+    }
+
+    pub fn pec(&self) -> bool {
+        self.pec
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn read_byte(&mut self, command: u8) -> Result<u8, Box<dyn Error>> {
+        let mut buf = [0u8; 1];
+        self.read_with_pec(command, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    pub fn write_byte(&mut self, command: u8, value: u8) -> Result<(), Box<dyn Error>> {
+        self.write_with_pec(&[command, value])
+    }
+
+    pub fn read_word(&mut self, command: u8) -> Result<u16, Box<dyn Error>> {
+        let mut buf = [0u8; 2];
+        self.read_with_pec(command, &mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn write_word(&mut self, command: u8, value: u16) -> Result<(), Box<dyn Error>> {
+        let [lo, hi] = value.to_le_bytes();
+        self.write_with_pec(&[command, lo, hi])
+    }
+
+    /// Block read. The controller can't stop after a length it learns
+    /// mid-transfer, so the maximum block is always clocked in and trimmed
+    /// to the count the device reported.
+    pub fn block_read(&mut self, command: u8) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buf = [0u8; 1 + MAX_BLOCK_LEN + 1];
+        let read_len = if self.pec { buf.len() } else { buf.len() - 1 };
+        self.i2c
+            .write_read(self.address, &[command], &mut buf[..read_len])?;
+
+        let count = buf[0] as usize;
+        if count == 0 || count > MAX_BLOCK_LEN {
+            return Err(format!("SMBus block length {} out of range 1-{}", count, MAX_BLOCK_LEN).into());
+        }
+        if self.pec {
+            // Unlike fixed-size reads, the PEC follows the data the device sent.
+            self.check_pec(command, &buf[..=count], buf[count + 1])?;
+        }
+        Ok(buf[1..=count].to_vec())
+    }
+
+    pub fn block_write(&mut self, command: u8, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        if data.is_empty() || data.len() > MAX_BLOCK_LEN {
+            return Err(format!("SMBus block must be 1-{} bytes, got {}", MAX_BLOCK_LEN, data.len()).into());
+        }
+        let mut frame = Vec::with_capacity(data.len() + 2);
+        frame.push(command);
+        frame.push(data.len() as u8);
+        frame.extend_from_slice(data);
+        self.write_with_pec(&frame)
+    }
+
+    /// Process call: write a word and read the reply word in one transaction.
+    pub fn process_call(&mut self, command: u8, value: u16) -> Result<u16, Box<dyn Error>> {
+        let [lo, hi] = value.to_le_bytes();
+        let mut reply = [0u8; 3];
+        let len = if self.pec { 3 } else { 2 };
+        self.i2c
+            .write_read(self.address, &[command, lo, hi], &mut reply[..len])?;
+        if self.pec {
+            let wr = self.address << 1;
+            let pec = crc::crc8(&[wr, command, lo, hi, wr | 1, reply[0], reply[1]]);
+            check(pec, reply[2])?;
+        }
+        Ok(u16::from_le_bytes([reply[0], reply[1]]))
+    }
+
+    /// Give back the bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn write_with_pec(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        if !self.pec {
+            self.i2c.write(self.address, frame)?;
+            return Ok(());
+        }
+        let pec = crc::crc8(&[self.address << 1]);
+        let pec = frame.iter().fold(pec, |crc, &b| crc::crc8_update(crc, b));
+        // One buffer, one write: not every bus merges adjacent write operations.
+        let mut with_pec = frame.to_vec();
+        with_pec.push(pec);
+        self.i2c.write(self.address, &with_pec)?;
+        Ok(())
+    }
+
+    fn read_with_pec(&mut self, command: u8, buf: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        if !self.pec {
+            self.i2c.write_read(self.address, &[command], buf)?;
+            return Ok(());
+        }
+        let mut with_pec = vec![0u8; buf.len() + 1];
+        self.i2c.write_read(self.address, &[command], &mut with_pec)?;
+        let (data, pec) = with_pec.split_at(buf.len());
+        self.check_pec(command, data, pec[0])?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn check_pec(&self, command: u8, data: &[u8], received: u8) -> Result<(), Box<dyn Error>> {
+        let wr = self.address << 1;
+        let mut crc_input = vec![wr, command, wr | 1];
+        crc_input.extend_from_slice(data);
+        check(crc::crc8(&crc_input), received)
+    }
+}
+
+fn check(expected: u8, received: u8) -> Result<(), Box<dyn Error>> {
+    if expected != received {
+        return Err(format!("SMBus PEC mismatch: expected 0x{:02X}, got 0x{:02X}", expected, received).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+    use crate::bus::StubBus;
+    use crate::i2c::{SlaveMap, SlaveRegister};
+
+    /// A smart battery's address; PEC covers its 0x16 / 0x17 address bytes.
+    const BATTERY: u8 = 0x0B;
+
+    fn battery(registers: &[(u8, &[u8])]) -> (StubBus, SmBusDevice<StubBus>) {
+        let bus = StubBus::new();
+        let map = SlaveMap {
+            address: BATTERY,
+            registers: registers
+                .iter()
+                .map(|&(register, value)| SlaveRegister {
+                    register,
+                    value: value.to_vec(),
+                    read_only: false,
+                })
+                .collect(),
+        };
+        bus.add_map(&map).unwrap();
+        let mut device = SmBusDevice::new(bus.clone(), BATTERY);
+        device.set_pec(true);
+        (bus, device)
+    }
+
+    #[test]
+    fn write_word_appends_pec() {
+        let (bus, mut device) = battery(&[]);
+        device.write_word(0x00, 0x1234).unwrap();
+        assert_eq!(bus.writes(Address::SevenBit(BATTERY)), [vec![0x00, 0x34, 0x12, 0xC0]]);
+    }
+
+    #[test]
+    fn write_without_pec_is_bare() {
+        let (bus, mut device) = battery(&[]);
+        device.set_pec(false);
+        device.write_byte(0x3C, 0x55).unwrap();
+        assert_eq!(bus.writes(Address::SevenBit(BATTERY)), [vec![0x3C, 0x55]]);
+    }
+
+    #[test]
+    fn read_word_checks_pec() {
+        // 0x0E10 mV with the PEC over 16 09 17 10 0E
+        let (_, mut device) = battery(&[(0x09, &[0x10, 0x0E, 0x16])]);
+        assert_eq!(device.read_word(0x09).unwrap(), 0x0E10);
+    }
+
+    #[test]
+    fn read_word_rejects_bad_pec() {
+        let (_, mut device) = battery(&[(0x09, &[0x10, 0x0E, 0x17])]);
+        let err = device.read_word(0x09).unwrap_err();
+        assert_eq!(err.to_string(), "SMBus PEC mismatch: expected 0x16, got 0x17");
+    }
+
+    #[test]
+    fn block_read_takes_pec_after_the_count() {
+        let (bus, mut device) = battery(&[(0x20, &[3, b'B', b'Q', b'2', 0xD2])]);
+        assert_eq!(device.block_read(0x20).unwrap(), b"BQ2");
+        let read = &bus.transactions()[0].ops[1];
+        assert_eq!(read.bytes.len(), 1 + MAX_BLOCK_LEN + 1);
+    }
+
+    #[test]
+    fn block_write_frames_count_and_pec() {
+        let (bus, mut device) = battery(&[]);
+        device.set_pec(false);
+        device.block_write(0x21, &[0xAA, 0xBB]).unwrap();
+        assert_eq!(bus.writes(Address::SevenBit(BATTERY)), [vec![0x21, 0x02, 0xAA, 0xBB]]);
+        assert!(device.block_write(0x21, &[]).is_err());
+        assert!(device.block_write(0x21, &[0; MAX_BLOCK_LEN + 1]).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Long-running latency soak, for deciding whether a Pi (and its load) can
+//! hold a real-time I2C schedule.
+//!
+//! [`run`] issues one transaction per period for hours against a fixed
+//! schedule, the way a control loop would, and keeps two HDR histograms:
+//!
+//! - wake-up latency: how late each transaction started against its slot,
+//!   which is what the scheduler and system load cost;
+//! - response latency: slot start to transaction end, which is what a
+//!   control loop actually sees. Past the deadline counts as a miss.
+//!
+//! Slots that can't be reached at all because an earlier one overran are
+//! skipped rather than fired back to back, and counted. The response
+//! histogram exports to `.hgrm` ([`LatencyHistogram::write_hgrm`]) for
+//! HdrHistogram's plotter. Combine with `--realtime` to compare against
+//! `SCHED_FIFO`.
+
+mod histogram;
+
+pub use histogram::LatencyHistogram;
+
+use crate::address::{Address, AddressedI2c};
+use crate::timing::PreciseDelay;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoakConfig {
+    pub address: Address,
+    /// Bytes read per transaction; a one-byte read disturbs nothing.
+    pub read: usize,
+    pub period: Duration,
+    /// Response latency above this is a missed deadline.
+    pub deadline: Duration,
+    pub duration: Duration,
+    /// How much of each wait to spin; zero measures plain `sleep` wake-ups.
+    pub spin: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SoakReport {
+    pub transactions: u64,
+    /// Transactions that returned an error (counted, not fatal).
+    pub failures: u64,
+    pub missed_deadlines: u64,
+    /// Slots passed over because the loop was already behind them.
+    pub skipped: u64,
+    pub wakeup: LatencyHistogram,
+    pub response: LatencyHistogram,
+    pub elapsed: Duration,
+}
+
+impl SoakConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.period.is_zero() {
+            return Err("soak period must be greater than zero".into());
+        }
+        if self.deadline.is_zero() {
+            return Err("soak deadline must be greater than zero".into());
+        }
+        if !(1..=4096).contains(&self.read) {
+            return Err(format!("soak read size {} out of range (1-4096)", self.read).into());
+        }
+        Ok(())
+    }
+}
+
+/// Soak the bus until `config.duration` has passed or `stop` is set.
+/// `progress` gets the report so far about once every `every`.
+pub fn run<I2C, F>(
+    i2c: &mut I2C,
+    config: &SoakConfig,
+    stop: &AtomicBool,
+    every: Duration,
+    mut progress: F,
+) -> Result<SoakReport, Box<dyn Error>>
+where
+    I2C: AddressedI2c,
+    F: FnMut(&SoakReport),
+{
+    config.validate()?;
+    let delay = PreciseDelay::new(config.spin);
+    let mut buf = vec![0; config.read];
+    let mut report = SoakReport::default();
+    let start = Instant::now();
+    let end = start + config.duration;
+    let mut slot = start;
+    let mut next_progress = start + every;
+
+    while slot < end && !stop.load(Ordering::Relaxed) {
+        delay.until(slot);
+        let began = Instant::now();
+        let result = i2c.read_at(config.address, &mut buf);
+        let finished = Instant::now();
+
+        report.transactions += 1;
+        if result.is_err() {
+            report.failures += 1;
+        }
+        report.wakeup.record(began - slot);
+        let response = finished - slot;
+        report.response.record(response);
+        if response > config.deadline {
+            report.missed_deadlines += 1;
+        }
+
+        slot += config.period;
+        while slot + config.period <= finished {
+            slot += config.period;
+            report.skipped += 1;
+        }
+        if finished >= next_progress {
+            report.elapsed = finished - start;
+            progress(&report);
+            next_progress += every;
+        }
+    }
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
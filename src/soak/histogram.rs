@@ -0,0 +1,190 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Sub-buckets per power of two; 2048 keeps three significant figures.
+const SUB_BUCKET_BITS: u32 = 11;
+const SUB_BUCKET_COUNT: u64 = 1 << SUB_BUCKET_BITS;
+const SUB_BUCKET_HALF: usize = 1 << (SUB_BUCKET_BITS - 1);
+
+/// Percentile lines per halving of the remaining distance in `.hgrm` output,
+/// the same as HdrHistogram's default.
+const TICKS_PER_HALF_DISTANCE: u32 = 5;
+
+/// HDR histogram of latencies in microseconds: exact below 2048 µs, within
+/// 0.05% above, with no upper limit. Memory grows by 8 KiB per doubling of
+/// the largest value seen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        LatencyHistogram::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let us = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let index = index_of(us);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.min = if self.total == 0 { us } else { self.min.min(us) };
+        self.max = self.max.max(us);
+        self.total += 1;
+        self.sum += u128::from(us);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn min(&self) -> Duration {
+        Duration::from_micros(self.min)
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_micros(self.max)
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.total {
+            0 => Duration::ZERO,
+            n => Duration::from_micros((self.sum / u128::from(n)) as u64),
+        }
+    }
+
+    /// Smallest recorded latency that `percentile` (0-100) of samples are at
+    /// or below; zero while empty.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        Duration::from_micros(self.value_at(percentile))
+    }
+
+    fn value_at(&self, percentile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let wanted = ((percentile.clamp(0.0, 100.0) / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= wanted {
+                return highest_equivalent(index).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Samples at or below `us`.
+    fn count_to(&self, us: u64) -> u64 {
+        let last = index_of(us);
+        self.counts.iter().take(last + 1).sum()
+    }
+
+    /// Write the percentile distribution in HdrHistogram's `.hgrm` text
+    /// format, values in milliseconds, for the online plotter or
+    /// `HistogramLogProcessor`.
+    pub fn write_hgrm<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut text = String::new();
+        let _ = writeln!(text, "{:>12} {:>14} {:>10} {:>14}\n", "Value", "Percentile", "TotalCount", "1/(1-Percentile)");
+        if self.total > 0 {
+            let mut level = 0;
+            'levels: loop {
+                let base = 100.0 * (1.0 - 0.5f64.powi(level));
+                let step = 100.0 * 0.5f64.powi(level + 1) / f64::from(TICKS_PER_HALF_DISTANCE);
+                for tick in 0..TICKS_PER_HALF_DISTANCE {
+                    let percentile = base + step * f64::from(tick);
+                    let value = self.value_at(percentile);
+                    line(&mut text, value, percentile, self.count_to(value));
+                    // Past the point where one sample is a whole tick, or at the top already
+                    if value >= self.max || 100.0 / (100.0 - percentile) > self.total as f64 {
+                        break 'levels;
+                    }
+                }
+                level += 1;
+            }
+            let _ = writeln!(
+                text,
+                "{:>12.3} {:>14.12} {:>10}",
+                self.max as f64 / 1000.0,
+                1.0,
+                self.total
+            );
+        }
+        let stddev = self.stddev_us();
+        let _ = writeln!(
+            text,
+            "#[Mean    = {:>12.3}, StdDeviation   = {:>12.3}]",
+            self.mean().as_micros() as f64 / 1000.0,
+            stddev / 1000.0
+        );
+        let _ = writeln!(
+            text,
+            "#[Max     = {:>12.3}, Total count    = {:>12}]",
+            self.max as f64 / 1000.0,
+            self.total
+        );
+        let buckets = self.counts.len().saturating_sub(SUB_BUCKET_HALF).div_ceil(SUB_BUCKET_HALF).max(1);
+        let _ = writeln!(text, "#[Buckets = {:>12}, SubBuckets     = {:>12}]", buckets, SUB_BUCKET_COUNT);
+        out.write_all(text.as_bytes())
+    }
+
+    fn stddev_us(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let mean = self.sum as f64 / self.total as f64;
+        let variance = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(index, &count)| {
+                let delta = median_equivalent(index) as f64 - mean;
+                delta * delta * count as f64
+            })
+            .sum::<f64>()
+            / self.total as f64;
+        variance.sqrt()
+    }
+}
+
+fn line(text: &mut String, value_us: u64, percentile: f64, count: u64) {
+    let fraction = percentile / 100.0;
+    let _ = writeln!(
+        text,
+        "{:>12.3} {:>14.12} {:>10} {:>14.2}",
+        value_us as f64 / 1000.0,
+        fraction,
+        count,
+        1.0 / (1.0 - fraction)
+    );
+}
+
+/// Bucket `b` holds values in `[1024 << b, 2048 << b)` at a resolution of
+/// `1 << b`; bucket 0 also holds everything below 1024 exactly.
+fn index_of(us: u64) -> usize {
+    let bucket = (64 - (us | (SUB_BUCKET_COUNT - 1)).leading_zeros()) - SUB_BUCKET_BITS;
+    bucket as usize * SUB_BUCKET_HALF + (us >> bucket) as usize
+}
+
+fn lowest_equivalent(index: usize) -> u64 {
+    let bucket = (index / SUB_BUCKET_HALF).saturating_sub(1);
+    let sub = index - bucket * SUB_BUCKET_HALF;
+    (sub as u64) << bucket
+}
+
+fn highest_equivalent(index: usize) -> u64 {
+    lowest_equivalent(index + 1) - 1
+}
+
+fn median_equivalent(index: usize) -> u64 {
+    let low = lowest_equivalent(index);
+    low + (highest_equivalent(index) - low) / 2
+}
@@ -0,0 +1,545 @@
+//! Bit-banged I2C master on any two GPIOs.
+//!
+//! The hardware controller runs at 100 kHz or more, which squeezes a whole
+//! byte into a few divisions of a cheap scope. [`SoftI2c`] drives the lines
+//! itself at anything from 1 Hz upwards (1-10 kHz is the sweet spot), so
+//! START, each data bit, ACK/NACK and STOP can be read straight off the trace.
+//!
+//! Both lines are driven open-drain: a pin is either pulled low or released
+//! to its pull-up, never driven high, so slaves can ACK and stretch the clock.
+//! It speaks 10-bit addressing natively, unlike `i2c-dev` on the Pi.
+//! Every time a slave holds SCL low is timed, in [`SoftI2c::stretching`].
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use rppal::gpio::{Bias, Gpio, IoPin, Mode};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Highest clock accepted; GPIO latency makes faster clocks uneven anyway.
+pub const MAX_FREQUENCY: u32 = 100_000;
+
+/// SCL held low for less than this after release is the line's own rise
+/// time through the pull-up, not a slave stretching the clock.
+pub const MIN_STRETCH: Duration = Duration::from_micros(10);
+
+/// How often, and for how long, slaves have held SCL low.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stretching {
+    pub count: u64,
+    pub total: Duration,
+    pub longest: Duration,
+}
+
+impl Stretching {
+    fn add(&mut self, held: Duration) {
+        self.count += 1;
+        self.total += held;
+        self.longest = self.longest.max(held);
+    }
+}
+
+/// A pin that can pull its line low or let it float high.
+pub trait OpenDrainPin {
+    type Error: fmt::Debug;
+
+    /// Stop driving the line so the pull-up (or a slave) decides its level.
+    fn release(&mut self) -> Result<(), Self::Error>;
+
+    fn drive_low(&mut self) -> Result<(), Self::Error>;
+
+    fn is_high(&mut self) -> Result<bool, Self::Error>;
+}
+
+impl OpenDrainPin for IoPin {
+    type Error = Infallible;
+
+    fn release(&mut self) -> Result<(), Infallible> {
+        self.set_mode(Mode::Input);
+        Ok(())
+    }
+
+    fn drive_low(&mut self) -> Result<(), Infallible> {
+        // Latch the low level first so switching to output never glitches high.
+        self.set_low();
+        self.set_mode(Mode::Output);
+        Ok(())
+    }
+
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(IoPin::is_high(self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoftI2cConfig {
+    /// SCL frequency in Hz.
+    pub frequency: u32,
+    /// How long a slave may hold SCL low before the transaction fails.
+    pub stretch_timeout: Duration,
+}
+
+impl Default for SoftI2cConfig {
+    fn default() -> Self {
+        SoftI2cConfig {
+            frequency: 5_000,
+            stretch_timeout: Duration::from_millis(25),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SoftI2cError<E> {
+    Nack(NoAcknowledgeSource),
+    /// SDA read low while we were releasing it: another master or a stuck slave.
+    ArbitrationLoss,
+    /// A line was already low when we tried to send START.
+    BusBusy,
+    ClockStretchTimeout,
+    Pin(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for SoftI2cError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoftI2cError::Nack(source) => write!(f, "NACK: {}", source),
+            SoftI2cError::ArbitrationLoss => write!(f, "arbitration lost (SDA held low)"),
+            SoftI2cError::BusBusy => write!(f, "bus busy (SDA or SCL low before START); try recover()"),
+            SoftI2cError::ClockStretchTimeout => write!(f, "slave held SCL low past the stretch timeout"),
+            SoftI2cError::Pin(e) => write!(f, "GPIO error: {:?}", e),
+        }
+    }
+}
+
+impl<E: fmt::Debug> Error for SoftI2cError<E> {}
+
+impl<E: fmt::Debug> i2c::Error for SoftI2cError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            SoftI2cError::Nack(source) => ErrorKind::NoAcknowledge(*source),
+            SoftI2cError::ArbitrationLoss => ErrorKind::ArbitrationLoss,
+            SoftI2cError::BusBusy => ErrorKind::Bus,
+            SoftI2cError::ClockStretchTimeout | SoftI2cError::Pin(_) => ErrorKind::Other,
+        }
+    }
+}
+
+pub struct SoftI2c<P> {
+    scl: P,
+    sda: P,
+    config: SoftI2cConfig,
+    half_period: Duration,
+    stretching: Stretching,
+}
+
+impl SoftI2c<IoPin> {
+    /// Bit-bang on BCM pins `sda` and `scl` using the internal pull-ups.
+    ///
+    /// The internal pull-ups are ~50 kΩ, fine at these speeds; add external
+    /// 4.7 kΩ ones for anything over a few kHz or long wires.
+    pub fn from_gpio(sda: u8, scl: u8, config: SoftI2cConfig) -> Result<Self, Box<dyn Error>> {
+        let gpio = Gpio::new()?;
+        let open = |pin: u8| -> Result<IoPin, Box<dyn Error>> {
+            let mut pin = gpio.get(pin)?.into_io(Mode::Input);
+            pin.set_bias(Bias::PullUp);
+            Ok(pin)
+        };
+        let sda = open(sda)?;
+        let scl = open(scl)?;
+        SoftI2c::new(scl, sda, config)
+    }
+}
+
+impl<P: OpenDrainPin> SoftI2c<P>
+where
+    P::Error: 'static,
+{
+    pub fn new(mut scl: P, mut sda: P, config: SoftI2cConfig) -> Result<Self, Box<dyn Error>> {
+        check_frequency(config.frequency)?;
+        scl.release().map_err(SoftI2cError::Pin)?;
+        sda.release().map_err(SoftI2cError::Pin)?;
+        Ok(SoftI2c {
+            scl,
+            sda,
+            half_period: half_period(config.frequency),
+            config,
+            stretching: Stretching::default(),
+        })
+    }
+
+    pub fn config(&self) -> SoftI2cConfig {
+        self.config
+    }
+
+    pub fn set_frequency(&mut self, frequency: u32) -> Result<(), Box<dyn Error>> {
+        check_frequency(frequency)?;
+        self.config.frequency = frequency;
+        self.half_period = half_period(frequency);
+        Ok(())
+    }
+
+    /// Clock SCL until a slave stuck mid-byte lets go of SDA, then send STOP.
+    pub fn recover(&mut self) -> Result<(), SoftI2cError<P::Error>> {
+        self.sda.release().map_err(SoftI2cError::Pin)?;
+        for _ in 0..9 {
+            if self.sda.is_high().map_err(SoftI2cError::Pin)? {
+                break;
+            }
+            self.scl.drive_low().map_err(SoftI2cError::Pin)?;
+            self.delay();
+            self.scl_high()?;
+            self.delay();
+        }
+        self.scl.drive_low().map_err(SoftI2cError::Pin)?;
+        self.delay();
+        self.stop()
+    }
+
+    /// Clock stretching since the bus was opened or last reset.
+    pub fn stretching(&self) -> Stretching {
+        self.stretching
+    }
+
+    pub fn reset_stretching(&mut self) {
+        self.stretching = Stretching::default();
+    }
+
+    pub fn release(self) -> (P, P) {
+        (self.scl, self.sda)
+    }
+
+    fn delay(&self) {
+        spin_for(self.half_period);
+    }
+
+    /// Release SCL and wait for it to actually rise, honouring clock stretching.
+    fn scl_high(&mut self) -> Result<(), SoftI2cError<P::Error>> {
+        self.scl.release().map_err(SoftI2cError::Pin)?;
+        let start = Instant::now();
+        while !self.scl.is_high().map_err(SoftI2cError::Pin)? {
+            if start.elapsed() > self.config.stretch_timeout {
+                self.stretching.add(start.elapsed());
+                return Err(SoftI2cError::ClockStretchTimeout);
+            }
+            std::hint::spin_loop();
+        }
+        let held = start.elapsed();
+        if held >= MIN_STRETCH {
+            self.stretching.add(held);
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, repeated: bool) -> Result<(), SoftI2cError<P::Error>> {
+        if repeated {
+            // SCL is low after the last bit: raise SDA, then SCL, then START.
+            self.sda.release().map_err(SoftI2cError::Pin)?;
+            self.delay();
+            self.scl_high()?;
+            self.delay();
+        }
+        if !self.sda.is_high().map_err(SoftI2cError::Pin)? {
+            return Err(if repeated { SoftI2cError::ArbitrationLoss } else { SoftI2cError::BusBusy });
+        }
+        if !self.scl.is_high().map_err(SoftI2cError::Pin)? {
+            return Err(SoftI2cError::BusBusy);
+        }
+        self.sda.drive_low().map_err(SoftI2cError::Pin)?;
+        self.delay();
+        self.scl.drive_low().map_err(SoftI2cError::Pin)?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), SoftI2cError<P::Error>> {
+        self.sda.drive_low().map_err(SoftI2cError::Pin)?;
+        self.delay();
+        self.scl_high()?;
+        self.delay();
+        self.sda.release().map_err(SoftI2cError::Pin)?;
+        self.delay();
+        if !self.sda.is_high().map_err(SoftI2cError::Pin)? {
+            return Err(SoftI2cError::ArbitrationLoss);
+        }
+        Ok(())
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), SoftI2cError<P::Error>> {
+        if bit {
+            self.sda.release().map_err(SoftI2cError::Pin)?;
+        } else {
+            self.sda.drive_low().map_err(SoftI2cError::Pin)?;
+        }
+        self.delay();
+        self.scl_high()?;
+        let lost = bit && !self.sda.is_high().map_err(SoftI2cError::Pin)?;
+        self.delay();
+        self.scl.drive_low().map_err(SoftI2cError::Pin)?;
+        if lost {
+            return Err(SoftI2cError::ArbitrationLoss);
+        }
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool, SoftI2cError<P::Error>> {
+        self.sda.release().map_err(SoftI2cError::Pin)?;
+        self.delay();
+        self.scl_high()?;
+        let bit = self.sda.is_high().map_err(SoftI2cError::Pin)?;
+        self.delay();
+        self.scl.drive_low().map_err(SoftI2cError::Pin)?;
+        Ok(bit)
+    }
+
+    /// Send a byte MSB first; returns whether the slave ACKed.
+    fn write_byte(&mut self, byte: u8) -> Result<bool, SoftI2cError<P::Error>> {
+        for i in (0..8).rev() {
+            self.write_bit(byte & (1 << i) != 0)?;
+        }
+        Ok(!self.read_bit()?)
+    }
+
+    fn read_byte(&mut self, ack: bool) -> Result<u8, SoftI2cError<P::Error>> {
+        let mut byte = 0u8;
+        for _ in 0..8 {
+            byte = (byte << 1) | self.read_bit()? as u8;
+        }
+        self.write_bit(!ack)?;
+        Ok(byte)
+    }
+
+    /// START (or repeated START) plus the address bytes for one direction.
+    fn address_phase(
+        &mut self,
+        address: Address,
+        read: bool,
+        first: bool,
+    ) -> Result<(), SoftI2cError<P::Error>> {
+        self.start(!first)?;
+        let header = match address {
+            Address::SevenBit(_) => address.wire_bytes(read),
+            // A 10-bit read addresses the slave as a write, then turns around
+            // with a repeated START and the high byte alone. Once addressed,
+            // later reads in the same transaction only need the high byte.
+            Address::TenBit(_) if read => {
+                if first {
+                    self.send_address(&address.wire_bytes(false))?;
+                    self.start(true)?;
+                }
+                address.wire_bytes(true)[..1].to_vec()
+            }
+            Address::TenBit(_) => address.wire_bytes(false),
+        };
+        self.send_address(&header)
+    }
+
+    fn send_address(&mut self, bytes: &[u8]) -> Result<(), SoftI2cError<P::Error>> {
+        for &byte in bytes {
+            if !self.write_byte(byte)? {
+                return Err(SoftI2cError::Nack(NoAcknowledgeSource::Address));
+            }
+        }
+        Ok(())
+    }
+
+    fn run(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SoftI2cError<P::Error>> {
+        let mut previous: Option<bool> = None;
+        for i in 0..operations.len() {
+            let read = matches!(operations[i], Operation::Read(_));
+            // NACK the last byte of a run of reads, so the slave lets go of SDA.
+            let read_continues = matches!(operations.get(i + 1), Some(Operation::Read(_)));
+            if previous != Some(read) {
+                self.address_phase(address, read, previous.is_none())?;
+            }
+            match &mut operations[i] {
+                Operation::Write(bytes) => {
+                    for &byte in bytes.iter() {
+                        if !self.write_byte(byte)? {
+                            return Err(SoftI2cError::Nack(NoAcknowledgeSource::Data));
+                        }
+                    }
+                }
+                Operation::Read(buffer) => {
+                    let len = buffer.len();
+                    for (j, byte) in buffer.iter_mut().enumerate() {
+                        *byte = self.read_byte(read_continues || j + 1 < len)?;
+                    }
+                }
+            }
+            previous = Some(read);
+        }
+        if previous.is_some() {
+            self.stop()?;
+        }
+        Ok(())
+    }
+
+    fn transfer(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SoftI2cError<P::Error>> {
+        let result = self.run(address, operations);
+        if let Err(e) = &result {
+            // After a NACK or a stretch timeout the bus is still ours, so end
+            // the transaction properly. After losing arbitration or finding
+            // the bus busy it isn't, and a STOP would trample whoever has it.
+            if matches!(e, SoftI2cError::Nack(_) | SoftI2cError::ClockStretchTimeout) {
+                let _ = self.stop();
+            }
+            // Either way let go of both lines, or a held SCL blocks this
+            // master's next START and every other master too. The original
+            // error matters more than one from here.
+            let _ = self.sda.release();
+            let _ = self.scl.release();
+        }
+        result
+    }
+}
+
+fn check_frequency(frequency: u32) -> Result<(), Box<dyn Error>> {
+    if !(1..=MAX_FREQUENCY).contains(&frequency) {
+        return Err(format!("software I2C clock must be 1-{} Hz, got {}", MAX_FREQUENCY, frequency).into());
+    }
+    Ok(())
+}
+
+fn half_period(frequency: u32) -> Duration {
+    Duration::from_nanos(500_000_000 / u64::from(frequency))
+}
+
+/// Busy-wait: at 10 kHz a half period is 50 µs, well below sleep granularity.
+fn spin_for(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        std::hint::spin_loop();
+    }
+}
+
+impl<P: OpenDrainPin> ErrorType for SoftI2c<P> {
+    type Error = SoftI2cError<P::Error>;
+}
+
+impl<P: OpenDrainPin> I2c for SoftI2c<P>
+where
+    P::Error: 'static,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transfer(Address::SevenBit(address), operations)
+    }
+}
+
+impl<P: OpenDrainPin> AddressedI2c for SoftI2c<P>
+where
+    P::Error: 'static,
+{
+    fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(self.transfer(address, operations)?)
+    }
+}
+
+impl<P: OpenDrainPin> BusControl for SoftI2c<P>
+where
+    P::Error: 'static,
+{
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        Ok(self.config.frequency)
+    }
+
+    /// Bounds clock stretching; a bit-banged bus has no other way to hang.
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.config.stretch_timeout = timeout;
+        Ok(())
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        self.set_frequency(hz)
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(SoftI2c::recover(self)?)
+    }
+
+    fn stretching(&self) -> Option<Stretching> {
+        Some(self.stretching)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Both lines of a wired-AND bus, and who is pulling them low.
+    #[derive(Default)]
+    struct Lines {
+        /// Which of (SCL, SDA) this master drives low.
+        ours: [bool; 2],
+        /// Another master takes SDA from the first clock edge after START.
+        rival: bool,
+        stops: usize,
+    }
+
+    struct Line {
+        lines: Rc<RefCell<Lines>>,
+        index: usize,
+    }
+
+    impl OpenDrainPin for Line {
+        type Error = Infallible;
+
+        fn release(&mut self) -> Result<(), Infallible> {
+            let mut lines = self.lines.borrow_mut();
+            // SDA rising while SCL is high is a STOP
+            if self.index == 1 && lines.ours[1] && !lines.ours[0] {
+                lines.stops += 1;
+            }
+            lines.ours[self.index] = false;
+            Ok(())
+        }
+
+        fn drive_low(&mut self) -> Result<(), Infallible> {
+            let mut lines = self.lines.borrow_mut();
+            if self.index == 0 && lines.ours[1] && lines.stops == 0 {
+                lines.rival = true;
+            }
+            lines.ours[self.index] = true;
+            Ok(())
+        }
+
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            let lines = self.lines.borrow();
+            let held = self.index == 1 && lines.rival;
+            Ok(!lines.ours[self.index] && !held)
+        }
+    }
+
+    #[test]
+    fn lost_arbitration_lets_go_of_the_bus() {
+        let lines = Rc::new(RefCell::new(Lines::default()));
+        let line = |index| Line { lines: Rc::clone(&lines), index };
+        let config = SoftI2cConfig { frequency: MAX_FREQUENCY, ..SoftI2cConfig::default() };
+        let mut bus = SoftI2c::new(line(0), line(1), config).unwrap();
+        // 0x50 starts 0, 1: the rival holding SDA low wins the second bit
+        let e = bus.transfer(Address::SevenBit(0x50), &mut [Operation::Write(&[0])]).unwrap_err();
+        assert!(matches!(e, SoftI2cError::ArbitrationLoss));
+        let lines = lines.borrow();
+        assert_eq!(lines.ours, [false, false]);
+        assert_eq!(lines.stops, 0);
+    }
+}
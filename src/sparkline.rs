@@ -0,0 +1,285 @@
+//! Sparklines: the recent history of a measurement drawn into a few dozen
+//! pixels, for OLED, TFT and LED-matrix displays.
+//!
+//! Drawing goes through [`Canvas`], so any display with a frame buffer can
+//! host one; [`MonoBuffer`] is a ready-made 1-bit buffer that drivers can
+//! flush, with a [`FrameShadow`] to send only what changed since the last
+//! flush. Values come oldest first, and only the newest that fit the width
+//! are drawn.
+//!
+//! ```no_run
+//! use rpi_peripherals::sparkline::{MonoBuffer, Rect, Sparkline, Style};
+//!
+//! let history = [20.5, 20.7, 21.0, 21.8, 22.4, 22.1, 21.6];
+//! let mut frame = MonoBuffer::new(128, 32);
+//! Sparkline::new(Style::Bars).draw(&history, &mut frame, Rect::new(0, 16, 64, 16));
+//! // frame.as_bytes() now goes to the display
+//! ```
+
+/// A pixel surface with (0, 0) at the top left.
+pub trait Canvas {
+    fn size(&self) -> (u32, u32);
+    fn set_pixel(&mut self, x: u32, y: u32, on: bool);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Rect { x, y, width, height }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Style {
+    /// A one-pixel line joining consecutive values.
+    #[default]
+    Line,
+    /// A filled column per value, rising from the bottom.
+    Bars,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sparkline {
+    pub style: Style,
+    /// Fixed value range; by default it fits the values drawn.
+    pub range: Option<(f64, f64)>,
+    /// Pixels per value, so short histories still fill the width.
+    pub step: u32,
+}
+
+impl Sparkline {
+    pub fn new(style: Style) -> Self {
+        Sparkline { style, range: None, step: 1 }
+    }
+
+    /// Pin the vertical scale, so e.g. 0-100 % doesn't rescale as values move.
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    pub fn with_step(mut self, step: u32) -> Self {
+        self.step = step.max(1);
+        self
+    }
+
+    /// Clear `area` and draw the newest values that fit. NaNs leave a gap.
+    pub fn draw<C: Canvas>(&self, values: &[f64], canvas: &mut C, area: Rect) {
+        let area = clip(area, canvas.size());
+        for y in area.y..area.y + area.height {
+            for x in area.x..area.x + area.width {
+                canvas.set_pixel(x, y, false);
+            }
+        }
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        let step = self.step.max(1);
+        let fit = (area.width / step).max(1) as usize;
+        let shown = &values[values.len().saturating_sub(fit)..];
+        let Some((min, max)) = self.range.or_else(|| bounds(shown)) else {
+            return;
+        };
+
+        // Row within the area for a value, 0 at the bottom
+        let level = |value: f64| -> u32 {
+            let span = max - min;
+            let fraction = if span > 0.0 { ((value - min) / span).clamp(0.0, 1.0) } else { 0.5 };
+            (fraction * (area.height - 1) as f64).round() as u32
+        };
+        let bottom = area.y + area.height - 1;
+        let mut previous: Option<u32> = None;
+        for (i, &value) in shown.iter().enumerate() {
+            let x0 = area.x + i as u32 * step;
+            if value.is_nan() {
+                previous = None;
+                continue;
+            }
+            let row = level(value);
+            for x in x0..(x0 + step).min(area.x + area.width) {
+                match self.style {
+                    Style::Bars => {
+                        for y in bottom - row..=bottom {
+                            canvas.set_pixel(x, y, true);
+                        }
+                    }
+                    Style::Line => {
+                        // Join to the previous value with a vertical run at the first column
+                        let (low, high) = match previous {
+                            Some(prev) if x == x0 => (prev.min(row), prev.max(row)),
+                            _ => (row, row),
+                        };
+                        for r in low..=high {
+                            canvas.set_pixel(x, bottom - r, true);
+                        }
+                    }
+                }
+            }
+            previous = Some(row);
+        }
+    }
+}
+
+fn bounds(values: &[f64]) -> Option<(f64, f64)> {
+    values.iter().filter(|v| !v.is_nan()).fold(None, |acc, &v| match acc {
+        None => Some((v, v)),
+        Some((min, max)) => Some((min.min(v), max.max(v))),
+    })
+}
+
+fn clip(area: Rect, (width, height): (u32, u32)) -> Rect {
+    let x = area.x.min(width);
+    let y = area.y.min(height);
+    Rect {
+        x,
+        y,
+        width: area.width.min(width - x),
+        height: area.height.min(height - y),
+    }
+}
+
+/// Eight-level block characters for terminals: `▁▂▄▆█`.
+pub fn to_text(values: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let Some((min, max)) = bounds(values) else {
+        return " ".repeat(values.len());
+    };
+    values
+        .iter()
+        .map(|&v| {
+            if v.is_nan() {
+                ' '
+            } else if max > min {
+                BLOCKS[(((v - min) / (max - min)) * 7.0).round() as usize]
+            } else {
+                BLOCKS[3]
+            }
+        })
+        .collect()
+}
+
+/// A 1-bit frame buffer packed in SSD1306 page order: each byte is a
+/// vertical strip of 8 pixels, least significant bit on top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonoBuffer {
+    width: u32,
+    height: u32,
+    bytes: Vec<u8>,
+}
+
+impl MonoBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let pages = height.div_ceil(8);
+        MonoBuffer {
+            width,
+            height,
+            bytes: vec![0; (width * pages) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.bytes[self.index(x, y)] & (1 << (y % 8)) != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.fill(0);
+    }
+
+    /// The packed pages, ready to stream to the display.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((y / 8) * self.width + x) as usize
+    }
+}
+
+impl Canvas for MonoBuffer {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, on: bool) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let index = self.index(x, y);
+        if on {
+            self.bytes[index] |= 1 << (y % 8);
+        } else {
+            self.bytes[index] &= !(1 << (y % 8));
+        }
+    }
+}
+
+/// The bytes of one page that need sending: `bytes` go at `column` of
+/// `page`, with the display's column address set there first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageSpan<'a> {
+    pub page: u32,
+    pub column: u32,
+    pub bytes: &'a [u8],
+}
+
+/// What an SSD1306-style display was last flushed with, so the next flush
+/// sends only the pages that changed, and in each only the columns from
+/// the first to the last that did. A whole 128x64 frame is 1 KiB, which
+/// takes about 100 ms at 100 kHz.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameShadow {
+    shown: Option<MonoBuffer>,
+}
+
+impl FrameShadow {
+    /// Nothing known yet: the first flush sends it all.
+    pub fn new() -> Self {
+        FrameShadow::default()
+    }
+
+    /// What differs in `frame` from the last flush. Call
+    /// [`flushed`](FrameShadow::flushed) once it's been sent.
+    pub fn changes<'a>(&self, frame: &'a MonoBuffer) -> Vec<PageSpan<'a>> {
+        let width = frame.width as usize;
+        let bytes = frame.as_bytes();
+        let shown = self.shown.as_ref().filter(|shown| shown.size() == frame.size());
+        bytes
+            .chunks(width.max(1))
+            .enumerate()
+            .filter_map(|(page, new)| {
+                let (first, last) = match shown {
+                    Some(shown) => {
+                        let old = &shown.as_bytes()[page * width..][..new.len()];
+                        let first = new.iter().zip(old).position(|(a, b)| a != b)?;
+                        let last = new.iter().zip(old).rposition(|(a, b)| a != b).unwrap_or(first);
+                        (first, last)
+                    }
+                    None => (0, new.len().checked_sub(1)?),
+                };
+                Some(PageSpan {
+                    page: page as u32,
+                    column: first as u32,
+                    bytes: &new[first..=last],
+                })
+            })
+            .collect()
+    }
+
+    /// `frame` is on the display now.
+    pub fn flushed(&mut self, frame: &MonoBuffer) {
+        self.shown = Some(frame.clone());
+    }
+
+    /// Forget what's on the display, so the next flush sends every page:
+    /// after a reset, or when a glitch may have garbled it.
+    pub fn force_full_refresh(&mut self) {
+        self.shown = None;
+    }
+}
@@ -0,0 +1,34 @@
+//! SPI helpers shared by the SPI-attached drivers.
+
+#[cfg(feature = "spi")]
+mod bus_manager;
+mod daisy_chain;
+
+#[cfg(feature = "spi")]
+pub use bus_manager::{ChipSelectConfig, ChipSelectError, GpioCsDevice, SpiBusManager};
+pub use daisy_chain::{ChainConfig, ChainOrder, DaisyChain};
+
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+use std::error::Error;
+
+/// `/dev/spidev<bus>.<cs>` in mode 0 at `clock` Hz, with the kernel driving
+/// chip select.
+pub fn open(bus: u8, cs: u8, clock: u32) -> Result<Spi, Box<dyn Error>> {
+    let spi_bus = match bus {
+        0 => Bus::Spi0,
+        1 => Bus::Spi1,
+        2 => Bus::Spi2,
+        3 => Bus::Spi3,
+        4 => Bus::Spi4,
+        5 => Bus::Spi5,
+        6 => Bus::Spi6,
+        _ => return Err(format!("no SPI bus {}", bus).into()),
+    };
+    let select = match cs {
+        0 => SlaveSelect::Ss0,
+        1 => SlaveSelect::Ss1,
+        2 => SlaveSelect::Ss2,
+        _ => return Err(format!("SPI{} has no chip select {}", bus, cs).into()),
+    };
+    Ok(Spi::new(spi_bus, select, clock, Mode::Mode0).map_err(|e| format!("SPI{}.{}: {}; enable it with dtparam=spi=on", bus, cs, e))?)
+}
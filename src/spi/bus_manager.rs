@@ -0,0 +1,179 @@
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::{self, ErrorKind, ErrorType, Operation, SpiBus, SpiDevice};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Timing and polarity of a GPIO-driven chip select.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChipSelectConfig {
+    /// Time between asserting CS and the first clock edge.
+    pub setup: Duration,
+    /// Time between the last clock edge and releasing CS.
+    pub hold: Duration,
+    /// Most parts select on a low CS; a few (and some level shifters) want high.
+    pub active_high: bool,
+}
+
+impl Default for ChipSelectConfig {
+    fn default() -> Self {
+        ChipSelectConfig {
+            setup: Duration::from_micros(1),
+            hold: Duration::from_micros(1),
+            active_high: false,
+        }
+    }
+}
+
+/// Owns one SPI bus and hands out devices, each selected by its own GPIO.
+///
+/// The Pi only has CE0/CE1, so anything beyond two devices needs extra
+/// selects. Leave the hardware CE lines unconnected on devices that use a GPIO
+/// select, since the kernel still toggles them on every transfer.
+pub struct SpiBusManager<BUS> {
+    bus: Arc<Mutex<BUS>>,
+    devices: usize,
+}
+
+impl<BUS: SpiBus> SpiBusManager<BUS> {
+    pub fn new(bus: BUS) -> Self {
+        SpiBusManager {
+            bus: Arc::new(Mutex::new(bus)),
+            devices: 0,
+        }
+    }
+
+    /// Create a device handle that drives `cs` around each transaction.
+    pub fn device<CS: OutputPin>(
+        &mut self,
+        mut cs: CS,
+        config: ChipSelectConfig,
+    ) -> Result<GpioCsDevice<BUS, CS>, Box<dyn Error>>
+    where
+        CS::Error: Error + 'static,
+    {
+        // Park the select in its idle level before the device is ever used.
+        if config.active_high {
+            cs.set_low()?;
+        } else {
+            cs.set_high()?;
+        }
+        self.devices += 1;
+        Ok(GpioCsDevice {
+            bus: Arc::clone(&self.bus),
+            cs,
+            config,
+        })
+    }
+
+    /// Number of devices handed out so far.
+    pub fn device_count(&self) -> usize {
+        self.devices
+    }
+}
+
+/// Error from a GPIO chip-select device: either the bus or the select pin failed.
+#[derive(Debug)]
+pub enum ChipSelectError<B, P> {
+    Bus(B),
+    Pin(P),
+}
+
+impl<B: fmt::Debug, P: fmt::Debug> fmt::Display for ChipSelectError<B, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChipSelectError::Bus(e) => write!(f, "SPI bus error: {:?}", e),
+            ChipSelectError::Pin(e) => write!(f, "chip select pin error: {:?}", e),
+        }
+    }
+}
+
+impl<B: fmt::Debug, P: fmt::Debug> Error for ChipSelectError<B, P> {}
+
+impl<B: spi::Error, P: fmt::Debug> spi::Error for ChipSelectError<B, P> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ChipSelectError::Bus(e) => e.kind(),
+            ChipSelectError::Pin(_) => ErrorKind::ChipSelectFault,
+        }
+    }
+}
+
+/// One device on a shared bus, selected by a GPIO pin.
+pub struct GpioCsDevice<BUS, CS> {
+    bus: Arc<Mutex<BUS>>,
+    cs: CS,
+    config: ChipSelectConfig,
+}
+
+impl<BUS, CS> GpioCsDevice<BUS, CS>
+where
+    CS: OutputPin,
+{
+    fn select(&mut self, selected: bool) -> Result<(), CS::Error> {
+        if selected == self.config.active_high {
+            self.cs.set_high()
+        } else {
+            self.cs.set_low()
+        }
+    }
+}
+
+impl<BUS, CS> ErrorType for GpioCsDevice<BUS, CS>
+where
+    BUS: SpiBus,
+    CS: OutputPin,
+{
+    type Error = ChipSelectError<BUS::Error, CS::Error>;
+}
+
+impl<BUS, CS> SpiDevice for GpioCsDevice<BUS, CS>
+where
+    BUS: SpiBus,
+    CS: OutputPin,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let bus = Arc::clone(&self.bus);
+        let mut bus = bus.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        self.select(true).map_err(ChipSelectError::Pin)?;
+        spin_for(self.config.setup);
+
+        let result = run_operations(&mut *bus, operations);
+        // Always release the select, even if the transfer failed part-way.
+        let flushed = bus.flush();
+
+        spin_for(self.config.hold);
+        self.select(false).map_err(ChipSelectError::Pin)?;
+
+        result.and(flushed).map_err(ChipSelectError::Bus)
+    }
+}
+
+fn run_operations<BUS: SpiBus>(
+    bus: &mut BUS,
+    operations: &mut [Operation<'_, u8>],
+) -> Result<(), BUS::Error> {
+    for op in operations {
+        match op {
+            Operation::Read(buf) => bus.read(buf)?,
+            Operation::Write(buf) => bus.write(buf)?,
+            Operation::Transfer(read, write) => bus.transfer(read, write)?,
+            Operation::TransferInPlace(buf) => bus.transfer_in_place(buf)?,
+            Operation::DelayNs(ns) => {
+                bus.flush()?;
+                spin_for(Duration::from_nanos(u64::from(*ns)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Busy-wait instead of sleeping: CS setup/hold times are microseconds.
+fn spin_for(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        std::hint::spin_loop();
+    }
+}
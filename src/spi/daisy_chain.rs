@@ -0,0 +1,178 @@
+use embedded_hal::spi::SpiDevice;
+use std::error::Error;
+
+/// Which physical end of the chain device index 0 refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChainOrder {
+    /// Device 0 is wired straight to MOSI, so its slot is shifted out last.
+    #[default]
+    NearestFirst,
+    /// Device 0 is at the far end of the chain, so its slot is shifted out first.
+    FarthestFirst,
+}
+
+/// Shape of a daisy chain: how many devices, how wide each frame slot is and
+/// what to send to devices that should be left alone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainConfig {
+    pub length: usize,
+    pub slot_bytes: usize,
+    pub order: ChainOrder,
+    /// Slot contents that leave a device unchanged (MAX7219: `[0x00, 0x00]`).
+    /// Plain shift registers have none and always get their full state resent.
+    pub no_op: Option<Vec<u8>>,
+}
+
+impl ChainConfig {
+    pub fn new(length: usize, slot_bytes: usize) -> Self {
+        ChainConfig {
+            length,
+            slot_bytes,
+            order: ChainOrder::default(),
+            no_op: None,
+        }
+    }
+
+    pub fn with_order(mut self, order: ChainOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn with_no_op(mut self, no_op: &[u8]) -> Self {
+        self.no_op = Some(no_op.to_vec());
+        self
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.length == 0 || self.slot_bytes == 0 {
+            return Err("daisy chain needs at least one device and one byte per slot".into());
+        }
+        if let Some(no_op) = &self.no_op {
+            if no_op.len() != self.slot_bytes {
+                return Err(format!(
+                    "no-op frame is {} bytes but slots are {} bytes",
+                    no_op.len(),
+                    self.slot_bytes
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Position of `device` counted from the MOSI end of the chain.
+    fn position(&self, device: usize) -> usize {
+        match self.order {
+            ChainOrder::NearestFirst => device,
+            ChainOrder::FarthestFirst => self.length - 1 - device,
+        }
+    }
+}
+
+/// Devices sharing one SPI clock/data line and one latch (chip select).
+///
+/// Every device's slot is kept in a shadow buffer, and each latch shifts a
+/// full frame through the chain so device order is purely configuration.
+pub struct DaisyChain<SPI> {
+    spi: SPI,
+    config: ChainConfig,
+    shadow: Vec<u8>,
+}
+
+impl<SPI> DaisyChain<SPI>
+where
+    SPI: SpiDevice,
+    SPI::Error: Error + 'static,
+{
+    pub fn new(spi: SPI, config: ChainConfig) -> Result<Self, Box<dyn Error>> {
+        config.validate()?;
+        let shadow = vec![0; config.length * config.slot_bytes];
+        Ok(DaisyChain { spi, config, shadow })
+    }
+
+    pub fn config(&self) -> &ChainConfig {
+        &self.config
+    }
+
+    pub fn device_count(&self) -> usize {
+        self.config.length
+    }
+
+    /// Last contents stored for `device`.
+    pub fn slot(&self, device: usize) -> &[u8] {
+        let start = device * self.config.slot_bytes;
+        &self.shadow[start..start + self.config.slot_bytes]
+    }
+
+    /// Update the shadow slot for `device` without touching the bus.
+    pub fn set_slot(&mut self, device: usize, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.check_slot(device, bytes)?;
+        let start = device * self.config.slot_bytes;
+        self.shadow[start..start + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Shift every shadow slot out and latch them together.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let frame = self.build_frame(|chain, device| chain.slot(device).to_vec());
+        self.spi.write(&frame)?;
+        Ok(())
+    }
+
+    /// Send `bytes` to one device. Chains with a no-op frame leave the other
+    /// devices untouched; the rest get their shadow state resent.
+    pub fn write_device(&mut self, device: usize, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.check_slot(device, bytes)?;
+        let Some(no_op) = self.config.no_op.clone() else {
+            self.set_slot(device, bytes)?;
+            return self.flush();
+        };
+
+        let frame = self.build_frame(|_, d| if d == device { bytes.to_vec() } else { no_op.clone() });
+        self.spi.write(&frame)?;
+        Ok(())
+    }
+
+    /// Send the same slot contents to every device in one latch.
+    pub fn write_all(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        for device in 0..self.config.length {
+            self.set_slot(device, bytes)?;
+        }
+        self.flush()
+    }
+
+    /// Give back the underlying SPI device.
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+
+    fn check_slot(&self, device: usize, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        if device >= self.config.length {
+            return Err(format!(
+                "device {} out of range for a chain of {}",
+                device, self.config.length
+            )
+            .into());
+        }
+        if bytes.len() != self.config.slot_bytes {
+            return Err(format!(
+                "slot expects {} bytes, got {}",
+                self.config.slot_bytes,
+                bytes.len()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Assemble a wire-order frame: the far end of the chain goes out first.
+    fn build_frame(&self, slot_for: impl Fn(&Self, usize) -> Vec<u8>) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(self.shadow.len());
+        for position in (0..self.config.length).rev() {
+            // The index/position mapping is its own inverse.
+            let device = self.config.position(position);
+            frame.extend(slot_for(self, device));
+        }
+        frame
+    }
+}
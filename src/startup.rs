@@ -0,0 +1,303 @@
+//! Bringing rails and devices up in dependency order.
+//!
+//! A device depends on the rail that powers it and on anything it lists in
+//! `depends_on`, typically the mux in front of it:
+//!
+//! ```toml
+//! [[devices]]
+//! name = "mux"
+//! driver = "tca9548a"
+//! address = 0x70
+//!
+//! [[devices]]
+//! name = "lcd"
+//! driver = "pcf8574"
+//! address = 0x27
+//! rail = "display"
+//! depends_on = ["mux"]
+//! init_timeout = "500ms"
+//! ```
+//!
+//! [`StartupPlan`] orders the graph, keeping config order wherever the
+//! dependencies allow it, so rails still come up in the sequence they're
+//! listed. [`Startup`] runs one init hook per step, each under its own
+//! timeout. A step that fails or times out doesn't stop the others. Only the
+//! steps that depend on it are skipped, and [`StartupReport`] says which.
+//!
+//! Devices marked `optional = true` are allowed to be missing:
+//! [`StartupReport::ready`] only looks at the required steps, and
+//! [`StartupReport::pages`] and [`StartupReport::measurement_available`] tell
+//! the display and threshold checks what to leave out.
+
+use crate::config::{Config, PageConfig};
+use crate::say;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Init timeout for rails, and for devices that don't set one.
+pub const DEFAULT_INIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Something that has to be brought up.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node {
+    Rail(String),
+    Device(String),
+}
+
+impl Node {
+    pub fn name(&self) -> &str {
+        match self {
+            Node::Rail(name) | Node::Device(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Rail(name) => write!(f, "rail '{}'", name),
+            Node::Device(name) => write!(f, "device '{}'", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub node: Node,
+    pub depends_on: Vec<Node>,
+    pub timeout: Duration,
+    /// Startup may go on without this step.
+    pub optional: bool,
+}
+
+/// Rails and devices in an order that satisfies every dependency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartupPlan {
+    steps: Vec<Step>,
+}
+
+impl StartupPlan {
+    /// Build the plan for `config`. Fails on unknown names and cycles.
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let lookup = |name: &str| -> Option<Node> {
+            if config.rails.iter().any(|r| r.name == name) {
+                Some(Node::Rail(name.to_string()))
+            } else if config.devices.iter().any(|d| d.name == name) {
+                Some(Node::Device(name.to_string()))
+            } else {
+                None
+            }
+        };
+
+        let mut steps: Vec<Step> = config
+            .rails
+            .iter()
+            .map(|rail| Step {
+                node: Node::Rail(rail.name.clone()),
+                depends_on: Vec::new(),
+                timeout: DEFAULT_INIT_TIMEOUT,
+                optional: false,
+            })
+            .collect();
+        for device in &config.devices {
+            let mut depends_on = Vec::new();
+            for name in device.rail.iter().chain(&device.depends_on) {
+                if name == &device.name {
+                    return Err(format!("device '{}' depends on itself", device.name).into());
+                }
+                let node = lookup(name)
+                    .ok_or_else(|| format!("device '{}' depends on unknown '{}'", device.name, name))?;
+                if !depends_on.contains(&node) {
+                    depends_on.push(node);
+                }
+            }
+            steps.push(Step {
+                node: Node::Device(device.name.clone()),
+                depends_on,
+                timeout: device.init_timeout.unwrap_or(DEFAULT_INIT_TIMEOUT),
+                optional: device.optional,
+            });
+        }
+        order(steps).map(|steps| StartupPlan { steps })
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+}
+
+/// Kahn's algorithm, always taking the earliest ready step in config order.
+fn order(steps: Vec<Step>) -> Result<Vec<Step>, Box<dyn Error>> {
+    let index: HashMap<&Node, usize> = steps.iter().enumerate().map(|(i, s)| (&s.node, i)).collect();
+    let mut waiting: Vec<usize> = steps.iter().map(|s| s.depends_on.len()).collect();
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); steps.len()];
+    for (i, step) in steps.iter().enumerate() {
+        for dep in &step.depends_on {
+            dependents[index[dep]].push(i);
+        }
+    }
+
+    let mut done = vec![false; steps.len()];
+    let mut sorted = Vec::with_capacity(steps.len());
+    while let Some(next) = (0..steps.len()).find(|&i| !done[i] && waiting[i] == 0) {
+        done[next] = true;
+        sorted.push(next);
+        for &d in &dependents[next] {
+            waiting[d] -= 1;
+        }
+    }
+    if sorted.len() < steps.len() {
+        let stuck: Vec<String> = (0..steps.len())
+            .filter(|&i| !done[i])
+            .map(|i| steps[i].node.name().to_string())
+            .collect();
+        return Err(format!("dependency cycle among {}", stuck.join(", ")).into());
+    }
+
+    let mut steps: Vec<Option<Step>> = steps.into_iter().map(Some).collect();
+    Ok(sorted.into_iter().map(|i| steps[i].take().expect("each step sorted once")).collect())
+}
+
+/// How one step went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Ready(Duration),
+    Failed(String),
+    TimedOut(Duration),
+    /// Not attempted because this dependency didn't come up.
+    Skipped(Node),
+}
+
+impl Outcome {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Outcome::Ready(_))
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Ready(took) => write!(f, "ready in {}ms", took.as_millis()),
+            Outcome::Failed(e) => write!(f, "failed: {}", e),
+            Outcome::TimedOut(limit) => write!(f, "timed out after {}ms", limit.as_millis()),
+            Outcome::Skipped(dep) => write!(f, "skipped, {} is not up", dep),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StartupReport {
+    /// Every step in the order it was run or skipped.
+    pub outcomes: Vec<(Node, Outcome)>,
+    /// Steps that were allowed to fail.
+    pub optional: HashSet<Node>,
+}
+
+impl StartupReport {
+    pub fn all_ready(&self) -> bool {
+        self.outcomes.iter().all(|(_, o)| o.is_ready())
+    }
+
+    /// Every required step came up; missing optional devices are fine.
+    pub fn ready(&self) -> bool {
+        self.failures().all(|(node, _)| self.optional.contains(node))
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &(Node, Outcome)> {
+        self.outcomes.iter().filter(|(_, o)| !o.is_ready())
+    }
+
+    /// Whether `device` was in the plan and didn't come up.
+    pub fn device_down(&self, device: &str) -> bool {
+        self.failures()
+            .any(|(node, _)| matches!(node, Node::Device(name) if name == device))
+    }
+
+    /// Whether readings named `device.quantity` can be expected. Names that
+    /// aren't about a configured device, like `ip`, always can.
+    pub fn measurement_available(&self, measurement: &str) -> bool {
+        let device = measurement.split_once('.').map_or(measurement, |(device, _)| device);
+        !self.device_down(device)
+    }
+
+    /// The pages whose placeholders can all be filled.
+    pub fn pages<'a>(&self, pages: &'a [PageConfig]) -> Vec<&'a PageConfig> {
+        pages
+            .iter()
+            .filter(|page| page.placeholders().all(|p| self.measurement_available(p)))
+            .collect()
+    }
+}
+
+type Init = Box<dyn FnOnce() -> Result<(), Box<dyn Error>> + Send>;
+
+/// Runs a [`StartupPlan`] with one init hook per step.
+pub struct Startup {
+    plan: StartupPlan,
+    inits: HashMap<Node, Init>,
+}
+
+impl Startup {
+    pub fn new(plan: StartupPlan) -> Self {
+        Startup {
+            plan,
+            inits: HashMap::new(),
+        }
+    }
+
+    /// Register how to bring `node` up. Steps without a hook count as ready.
+    pub fn on_init<F>(&mut self, node: Node, init: F)
+    where
+        F: FnOnce() -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        self.inits.insert(node, Box::new(init));
+    }
+
+    /// Run every step in order. Each hook runs on its own thread, so one
+    /// that hangs is abandoned at its timeout instead of blocking startup.
+    pub fn run(mut self) -> StartupReport {
+        let mut report = StartupReport::default();
+        for step in self.plan.steps {
+            if step.optional {
+                report.optional.insert(step.node.clone());
+            }
+            let blocked = step.depends_on.iter().find(|dep| {
+                !report
+                    .outcomes
+                    .iter()
+                    .any(|(node, outcome)| node == *dep && outcome.is_ready())
+            });
+            let outcome = match (blocked, self.inits.remove(&step.node)) {
+                (Some(dep), _) => Outcome::Skipped(dep.clone()),
+                (None, None) => Outcome::Ready(Duration::ZERO),
+                (None, Some(init)) => run_with_timeout(init, step.timeout),
+            };
+            match &outcome {
+                Outcome::Ready(_) => say!("✅ {} {}", step.node, outcome),
+                _ if step.optional => say!("⚠️  {} {}; optional, carrying on without it", step.node, outcome),
+                _ => say!("❌ {} {}", step.node, outcome),
+            }
+            report.outcomes.push((step.node, outcome));
+        }
+        report
+    }
+}
+
+fn run_with_timeout(init: Init, timeout: Duration) -> Outcome {
+    let (tx, rx) = mpsc::channel();
+    let started = Instant::now();
+    thread::spawn(move || {
+        // The receiver is gone if we already gave up; nothing to report then.
+        let _ = tx.send(init().map_err(|e| e.to_string()));
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(Ok(())) => Outcome::Ready(started.elapsed()),
+        Ok(Err(e)) => Outcome::Failed(e),
+        Err(mpsc::RecvTimeoutError::Timeout) => Outcome::TimedOut(timeout),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Outcome::Failed("init panicked".to_string()),
+    }
+}
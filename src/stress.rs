@@ -0,0 +1,371 @@
+//! Randomized bus stress, for `stress`.
+//!
+//! [`run`] issues a mix of reads, writes, write-then-reads and pauses
+//! against one or more addresses, with random lengths and payloads, and
+//! counts errors and the worst latency per address. It is meant to run
+//! overnight on a bench: marginal pull-ups and long cables fail on some
+//! bit patterns and lengths and not others, which one fixed transaction
+//! repeated never finds.
+//!
+//! Every choice comes from a seeded [`Rng`](crate::rng::Rng), so the same seed and
+//! config replay the same sequence op for op, and a failure reported at op
+//! N can be reproduced with `--ops N`. Writes send random bytes, so point
+//! it at devices that don't mind (an EEPROM's scratch page, a spare
+//! expander), or give the mix no writes.
+
+use crate::address::{Address, AddressedI2c};
+use crate::rng::Rng;
+use crate::soak::LatencyHistogram;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Failures kept with their details; past this they are only counted.
+pub const MAX_FAILURES: usize = 20;
+
+/// Relative weights of each kind of op.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub read: u32,
+    pub write: u32,
+    pub write_read: u32,
+    pub delay: u32,
+}
+
+impl Default for Mix {
+    fn default() -> Self {
+        Mix {
+            read: 5,
+            write: 1,
+            write_read: 3,
+            delay: 1,
+        }
+    }
+}
+
+impl FromStr for Mix {
+    type Err = Box<dyn Error>;
+
+    /// `KEY=WEIGHT` pairs separated by commas, e.g.
+    /// `read=4,write=0,write-read=4,delay=1`; keys left out are 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = Mix {
+            read: 0,
+            write: 0,
+            write_read: 0,
+            delay: 0,
+        };
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| format!("'{}' is not KEY=WEIGHT", pair))?;
+            let weight = value.trim().parse().map_err(|_| format!("{}: '{}' is not a whole number", key, value))?;
+            match key.trim() {
+                "read" => mix.read = weight,
+                "write" => mix.write = weight,
+                "write-read" => mix.write_read = weight,
+                "delay" => mix.delay = weight,
+                other => return Err(format!("unknown op '{}' (read, write, write-read or delay)", other).into()),
+            }
+        }
+        if mix.total() == 0 {
+            return Err("the mix needs at least one op with a weight above 0".into());
+        }
+        Ok(mix)
+    }
+}
+
+impl Mix {
+    fn total(&self) -> u64 {
+        u64::from(self.read) + u64::from(self.write) + u64::from(self.write_read) + u64::from(self.delay)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StressConfig {
+    pub addresses: Vec<Address>,
+    pub ops: u64,
+    pub seed: u64,
+    pub mix: Mix,
+    /// Longest read or write; lengths are 1 to this.
+    pub max_len: usize,
+    /// Longest pause; pauses are 0 to this.
+    pub max_delay: Duration,
+}
+
+impl StressConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.addresses.is_empty() {
+            return Err("stress needs at least one address".into());
+        }
+        if !(1..=4096).contains(&self.max_len) {
+            return Err(format!("stress length {} out of range (1-4096)", self.max_len).into());
+        }
+        Ok(())
+    }
+}
+
+/// One step of a stress run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Read { address: Address, len: usize },
+    Write { address: Address, data: Vec<u8> },
+    /// Write then read with a repeated START, as a register read does.
+    WriteRead { address: Address, data: Vec<u8>, len: usize },
+    Delay(Duration),
+}
+
+impl Op {
+    pub fn address(&self) -> Option<Address> {
+        match self {
+            Op::Read { address, .. } | Op::Write { address, .. } | Op::WriteRead { address, .. } => Some(*address),
+            Op::Delay(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Read { address, len } => write!(f, "read {} from {}", len, address),
+            Op::Write { address, data } => write!(f, "write {} to {}", data.len(), address),
+            Op::WriteRead { address, data, len } => write!(f, "write {} then read {} at {}", data.len(), len, address),
+            Op::Delay(d) => write!(f, "pause {}µs", d.as_micros()),
+        }
+    }
+}
+
+/// The ops of a run, in order; the same config always gives the same
+/// sequence.
+#[derive(Debug, Clone)]
+pub struct OpStream {
+    config: StressConfig,
+    rng: Rng,
+    issued: u64,
+}
+
+impl OpStream {
+    pub fn new(config: StressConfig) -> Self {
+        OpStream {
+            rng: Rng::new(config.seed),
+            config,
+            issued: 0,
+        }
+    }
+
+    fn len(&mut self) -> usize {
+        1 + self.rng.below(self.config.max_len as u64) as usize
+    }
+
+    fn data(&mut self) -> Vec<u8> {
+        let len = self.len();
+        (0..len).map(|_| self.rng.next_u64() as u8).collect()
+    }
+}
+
+impl Iterator for OpStream {
+    type Item = Op;
+
+    fn next(&mut self) -> Option<Op> {
+        if self.issued >= self.config.ops {
+            return None;
+        }
+        self.issued += 1;
+        let mix = self.config.mix;
+        let mut pick = self.rng.below(mix.total());
+        let which = self.rng.below(self.config.addresses.len() as u64) as usize;
+        let address = self.config.addresses[which];
+        let mut within = |weight: u32| {
+            let hit = pick < u64::from(weight);
+            pick = pick.saturating_sub(u64::from(weight));
+            hit
+        };
+        let op = if within(mix.read) {
+            Op::Read { address, len: self.len() }
+        } else if within(mix.write) {
+            Op::Write { address, data: self.data() }
+        } else if within(mix.write_read) {
+            // A register pointer is one or two bytes
+            let pointer = 1 + self.rng.below(2) as usize;
+            let data = (0..pointer).map(|_| self.rng.next_u64() as u8).collect();
+            Op::WriteRead { address, data, len: self.len() }
+        } else {
+            let nanos = self.config.max_delay.as_nanos() as u64;
+            Op::Delay(Duration::from_nanos(self.rng.below(nanos + 1)))
+        };
+        Some(op)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressStats {
+    pub address: Address,
+    pub ops: u64,
+    pub errors: u64,
+    pub latency: LatencyHistogram,
+    pub worst: Duration,
+    /// The number (from 1) of the slowest op.
+    pub worst_at: u64,
+}
+
+impl AddressStats {
+    pub fn error_rate(&self) -> f64 {
+        if self.ops == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.ops as f64
+        }
+    }
+}
+
+/// An op that returned an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// From 1, so `--ops` with it replays up to the failure.
+    pub number: u64,
+    pub op: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StressReport {
+    /// Ops carried out, pauses included.
+    pub ops: u64,
+    pub pauses: u64,
+    pub addresses: Vec<AddressStats>,
+    /// The first [`MAX_FAILURES`].
+    pub failures: Vec<Failure>,
+    pub elapsed: Duration,
+}
+
+impl StressReport {
+    pub fn errors(&self) -> u64 {
+        self.addresses.iter().map(|a| a.errors).sum()
+    }
+}
+
+/// Run the ops of `config` until they are done or `stop` is set. Errors
+/// are counted, not fatal. `progress` gets the report so far about once
+/// every `every`.
+pub fn run<I2C, F>(
+    i2c: &mut I2C,
+    config: &StressConfig,
+    stop: &AtomicBool,
+    every: Duration,
+    mut progress: F,
+) -> Result<StressReport, Box<dyn Error>>
+where
+    I2C: AddressedI2c,
+    F: FnMut(&StressReport),
+{
+    config.validate()?;
+    let mut report = StressReport::default();
+    for &address in &config.addresses {
+        if report.addresses.iter().all(|a| a.address != address) {
+            report.addresses.push(AddressStats {
+                address,
+                ops: 0,
+                errors: 0,
+                latency: LatencyHistogram::new(),
+                worst: Duration::ZERO,
+                worst_at: 0,
+            });
+        }
+    }
+    let start = Instant::now();
+    let mut next_progress = start + every;
+    let mut buf = vec![0; config.max_len];
+    for op in OpStream::new(config.clone()) {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        report.ops += 1;
+        let began = Instant::now();
+        let result = match &op {
+            Op::Read { address, len } => i2c.read_at(*address, &mut buf[..*len]),
+            Op::Write { address, data } => i2c.write_at(*address, data),
+            Op::WriteRead { address, data, len } => i2c.write_read_at(*address, data, &mut buf[..*len]),
+            Op::Delay(pause) => {
+                thread::sleep(*pause);
+                report.pauses += 1;
+                Ok(())
+            }
+        };
+        let took = began.elapsed();
+        if let Some(address) = op.address() {
+            let stats = report.addresses.iter_mut().find(|a| a.address == address).ok_or("address vanished")?;
+            stats.ops += 1;
+            if took > stats.worst || stats.worst_at == 0 {
+                stats.worst = took;
+                stats.worst_at = report.ops;
+            }
+            stats.latency.record(took);
+            if let Err(e) = result {
+                stats.errors += 1;
+                if report.failures.len() < MAX_FAILURES {
+                    report.failures.push(Failure {
+                        number: report.ops,
+                        op: op.to_string(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        let now = Instant::now();
+        if now >= next_progress {
+            report.elapsed = now - start;
+            progress(&report);
+            next_progress += every;
+        }
+    }
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::Operation;
+
+    /// NACKs every write longer than four bytes, the way a marginal bus
+    /// gives up on long bursts.
+    struct Flaky;
+
+    impl AddressedI2c for Flaky {
+        fn transaction_at(&mut self, _: Address, operations: &mut [Operation<'_>]) -> Result<(), Box<dyn Error>> {
+            match operations.first() {
+                Some(Operation::Write(bytes)) if bytes.len() > 4 => Err("NACK".into()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_ops() {
+        let config = StressConfig {
+            addresses: vec![Address::SevenBit(0x50), Address::SevenBit(0x27)],
+            ops: 500,
+            seed: 42,
+            mix: "read=2,write=2,delay=1".parse().unwrap(),
+            max_len: 8,
+            max_delay: Duration::from_micros(20),
+        };
+        let ops: Vec<Op> = OpStream::new(config.clone()).collect();
+        assert_eq!(ops, OpStream::new(config.clone()).collect::<Vec<_>>());
+        assert_ne!(ops, OpStream::new(StressConfig { seed: 43, ..config.clone() }).collect::<Vec<_>>());
+        assert_eq!(ops.len(), 500);
+        assert!(!ops.iter().any(|op| matches!(op, Op::WriteRead { .. })));
+
+        let report = run(&mut Flaky, &config, &AtomicBool::new(false), Duration::from_secs(60), |_| {}).unwrap();
+        let long_writes = ops.iter().filter(|op| matches!(op, Op::Write { data, .. } if data.len() > 4)).count();
+        assert_eq!(report.errors(), long_writes as u64);
+        assert_eq!(report.ops, 500);
+        assert_eq!(report.addresses.iter().map(|a| a.ops).sum::<u64>() + report.pauses, 500);
+        let first = &report.failures[0];
+        assert_eq!(ops[first.number as usize - 1].to_string(), first.op);
+
+        assert!("read=0".parse::<Mix>().is_err());
+        assert!("reads=1".parse::<Mix>().is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Clock-stretching tolerance, for validating slow devices and long cables.
+//!
+//! [`run`] reads from a slave that holds SCL low while it gets its answer
+//! ready (an SHT3x mid-measurement, a microcontroller slave, an EEPROM
+//! busy writing) and reports how long SCL was held and whether the master
+//! waited it out. A master that doesn't shows up as timeouts, or as reads
+//! that come back different from one another: the BCM2835's controller is
+//! known to clock on through a short stretch and corrupt the first bit.
+//!
+//! On `--soft-i2c` the master times every stretch itself (see
+//! [`Stretching`]). Other buses don't say, so the hold is estimated as the
+//! time each transaction took beyond its bits at the clock speed, which
+//! also counts driver overhead: read it as an upper bound.
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use crate::exit::ExitStatus;
+use crate::metrics::is_nack;
+use crate::softi2c::{SoftI2cError, Stretching};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StretchConfig {
+    pub address: Address,
+    /// Register written before each read, with a repeated START; `None`
+    /// for plain reads.
+    pub register: Option<u8>,
+    /// Bytes per read.
+    pub len: usize,
+    pub reads: u32,
+    /// Pause between reads, for devices that stretch only on a fresh
+    /// measurement.
+    pub interval: Duration,
+}
+
+impl StretchConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !(1..=256).contains(&self.len) {
+            return Err(format!("stretch read length {} out of range (1-256)", self.len).into());
+        }
+        if self.reads == 0 {
+            return Err("stretch test needs at least one read".into());
+        }
+        Ok(())
+    }
+
+    /// Bit times one read takes on the wire: nine per byte, addresses
+    /// included, plus one each for START, repeated START and STOP.
+    fn bits(&self) -> u64 {
+        let bytes = match self.register {
+            Some(_) => 3 + self.len,
+            None => 1 + self.len,
+        };
+        let conditions = if self.register.is_some() { 3 } else { 2 };
+        9 * bytes as u64 + conditions
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StretchReport {
+    pub clock: u32,
+    pub reads: u32,
+    /// Reads that came back, whatever they said.
+    pub completed: u32,
+    /// Completed reads that differ from the first one.
+    pub mismatches: u32,
+    pub nacks: u32,
+    /// Reads that failed on a held clock: the master gave up waiting.
+    pub timeouts: u32,
+    pub errors: u32,
+    /// What the master saw, on one that watches SCL.
+    pub measured: Option<Stretching>,
+    /// The longest any read took beyond its bits: the stretch estimate
+    /// on masters that don't measure it.
+    pub longest_excess: Duration,
+    pub last_error: Option<String>,
+}
+
+impl StretchReport {
+    /// Every read made it through and agreed with the others.
+    pub fn honored(&self) -> bool {
+        self.reads > 0 && self.completed == self.reads && self.mismatches == 0
+    }
+
+    /// The longest SCL was held, measured or estimated.
+    pub fn longest_hold(&self) -> Duration {
+        self.measured.map_or(self.longest_excess, |m| m.longest)
+    }
+}
+
+impl fmt::Display for StretchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} read(s) at {} Hz: {} completed, {} differed", self.reads, self.clock, self.completed, self.mismatches)?;
+        writeln!(f, "{} NACK(s), {} timeout(s), {} other error(s)", self.nacks, self.timeouts, self.errors)?;
+        match self.measured {
+            Some(m) => write!(
+                f,
+                "SCL held {} time(s), longest {}µs, {:.1}ms in all (measured)",
+                m.count,
+                m.longest.as_micros(),
+                m.total.as_secs_f64() * 1e3
+            ),
+            None => write!(f, "SCL held up to {}µs (estimated from transaction times)", self.longest_excess.as_micros()),
+        }
+    }
+}
+
+/// Read `config.reads` times, until done or `stop` is set.
+pub fn run<I2C>(i2c: &mut I2C, config: &StretchConfig, stop: &AtomicBool) -> Result<StretchReport, Box<dyn Error>>
+where
+    I2C: AddressedI2c + BusControl,
+{
+    config.validate()?;
+    let clock = i2c.clock_speed()?;
+    let wire = Duration::from_secs_f64(config.bits() as f64 / f64::from(clock.max(1)));
+    let before = i2c.stretching();
+    let mut report = StretchReport {
+        clock,
+        ..StretchReport::default()
+    };
+    let mut first: Option<Vec<u8>> = None;
+    let mut buffer = vec![0; config.len];
+    for n in 0..config.reads {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        if n > 0 && !config.interval.is_zero() {
+            std::thread::sleep(config.interval);
+        }
+        let began = Instant::now();
+        let result = match config.register {
+            Some(register) => i2c.write_read_at(config.address, &[register], &mut buffer),
+            None => i2c.read_at(config.address, &mut buffer),
+        };
+        let took = began.elapsed();
+        report.reads += 1;
+        match result {
+            Ok(()) => {
+                report.completed += 1;
+                report.longest_excess = report.longest_excess.max(took.saturating_sub(wire));
+                match &first {
+                    Some(expected) if *expected != buffer => report.mismatches += 1,
+                    Some(_) => {}
+                    None => first = Some(buffer.clone()),
+                }
+            }
+            Err(e) => {
+                if is_nack(e.as_ref()) {
+                    report.nacks += 1;
+                } else if is_timeout(e.as_ref()) {
+                    report.timeouts += 1;
+                } else {
+                    report.errors += 1;
+                }
+                report.last_error = Some(e.to_string());
+            }
+        }
+    }
+    report.measured = i2c.stretching().map(|after| {
+        let before = before.unwrap_or_default();
+        Stretching {
+            count: after.count - before.count,
+            total: after.total.saturating_sub(before.total),
+            longest: after.longest,
+        }
+    });
+    Ok(report)
+}
+
+fn is_timeout(err: &(dyn Error + 'static)) -> bool {
+    if let Some(soft) = err.downcast_ref::<SoftI2cError<Infallible>>() {
+        return matches!(soft, SoftI2cError::ClockStretchTimeout);
+    }
+    ExitStatus::classify(err) == ExitStatus::Timeout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::StubBus;
+
+    #[test]
+    fn reads_that_agree_are_honored() {
+        let stub = StubBus::new();
+        let address = Address::SevenBit(0x44);
+        stub.add_port(address, 0x3C);
+        let config = StretchConfig {
+            address,
+            register: None,
+            len: 1,
+            reads: 5,
+            interval: Duration::ZERO,
+        };
+        let report = run(&mut stub.clone(), &config, &AtomicBool::new(false)).unwrap();
+        assert!(report.honored());
+        assert_eq!(report.completed, 5);
+        assert_eq!(report.measured, None);
+
+        stub.remove(address);
+        let report = run(&mut stub.clone(), &config, &AtomicBool::new(false)).unwrap();
+        assert!(!report.honored());
+        assert_eq!(report.nacks, 5);
+        assert_eq!(config.bits(), 20);
+    }
+}
@@ -0,0 +1,273 @@
+//! The Pi's own status, for showing on a display.
+//!
+//! [`SystemStatus::read`] takes a snapshot of the network address, SoC
+//! temperature, load, disk, memory and uptime from `/proc` and `/sys`.
+//! Pages name the values in braces, as in the `[[pages]]` config section:
+//!
+//! ```toml
+//! [[pages]]
+//! lines = ["{hostname}", "{ip}"]
+//!
+//! [[pages]]
+//! lines = ["CPU {cpu_temp}", "Load {load1}"]
+//! duration = "8s"
+//! ```
+//!
+//! [`PLACEHOLDERS`] lists the names. Anything else in braces is left as
+//! it is, and a value that can't be read shows as `--`. On the LCD, pages
+//! are [`Screen`](crate::lcd::Screen)s fed by a `StatusSource`, so
+//! `{time:%H:%M}` works alongside them.
+
+use crate::config::PageConfig;
+#[cfg(feature = "lcd")]
+use crate::lcd::{Source, Value};
+use crate::units::{Quantity, UnitsConfig};
+use std::error::Error;
+use std::ffi::CString;
+use std::fs;
+use std::net::{IpAddr, UdpSocket};
+use std::time::Duration;
+
+/// Every placeholder [`SystemStatus::value`] fills, with what it shows.
+pub const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("hostname", "host name"),
+    ("ip", "address of the interface with the default route"),
+    ("cpu_temp", "SoC temperature, in the [units] temperature unit"),
+    ("load", "1, 5 and 15 minute load averages"),
+    ("load1", "1 minute load average"),
+    ("disk", "root filesystem used, in percent"),
+    ("disk_free", "root filesystem free, e.g. 12.3G"),
+    ("mem", "memory used, in percent"),
+    ("uptime", "time since boot, e.g. 3d 04:12"),
+];
+
+const THERMAL_ZONE: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+/// Used and total bytes of a filesystem or of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub used: u64,
+    pub total: u64,
+}
+
+impl Usage {
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.used as f64 * 100.0 / self.total as f64
+    }
+
+    pub fn free(&self) -> u64 {
+        self.total.saturating_sub(self.used)
+    }
+}
+
+/// One reading of everything; `None` where the source isn't there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemStatus {
+    pub hostname: Option<String>,
+    pub ip: Option<IpAddr>,
+    /// °C.
+    pub cpu_temp: Option<f64>,
+    pub load: Option<[f64; 3]>,
+    /// Of the root filesystem.
+    pub disk: Option<Usage>,
+    pub memory: Option<Usage>,
+    pub uptime: Option<Duration>,
+}
+
+impl SystemStatus {
+    pub fn read() -> Self {
+        SystemStatus {
+            hostname: fs::read_to_string("/proc/sys/kernel/hostname").ok().map(|s| s.trim().to_string()),
+            ip: primary_ip(),
+            cpu_temp: fs::read_to_string(THERMAL_ZONE)
+                .ok()
+                .and_then(|s| s.trim().parse::<f64>().ok())
+                .map(|millidegrees| millidegrees / 1000.0),
+            load: fs::read_to_string("/proc/loadavg").ok().and_then(|s| parse_loadavg(&s)),
+            disk: disk_usage("/").ok(),
+            memory: fs::read_to_string("/proc/meminfo").ok().and_then(|s| parse_meminfo(&s)),
+            uptime: fs::read_to_string("/proc/uptime")
+                .ok()
+                .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+                .map(Duration::from_secs_f64),
+        }
+    }
+
+    /// The text for placeholder `name`, or `None` if it isn't one of
+    /// [`PLACEHOLDERS`].
+    pub fn value(&self, name: &str, units: &UnitsConfig) -> Option<String> {
+        let missing = || "--".to_string();
+        Some(match name {
+            "hostname" => self.hostname.clone().unwrap_or_else(missing),
+            "ip" => self.ip.map_or_else(|| "no network".to_string(), |ip| ip.to_string()),
+            "cpu_temp" => self.cpu_temp.map_or_else(missing, |t| units.format(Quantity::Temperature, t, 1)),
+            "load" => self.load.map_or_else(missing, |[a, b, c]| format!("{:.2} {:.2} {:.2}", a, b, c)),
+            "load1" => self.load.map_or_else(missing, |[a, _, _]| format!("{:.2}", a)),
+            "disk" => self.disk.map_or_else(missing, |d| format!("{:.0}%", d.percent())),
+            "disk_free" => self.disk.map_or_else(missing, |d| bytes(d.free())),
+            "mem" => self.memory.map_or_else(missing, |m| format!("{:.0}%", m.percent())),
+            "uptime" => self.uptime.map_or_else(missing, uptime),
+            _ => return None,
+        })
+    }
+
+    /// `page`'s lines with the placeholders filled in.
+    pub fn render(&self, page: &PageConfig, units: &UnitsConfig) -> Vec<String> {
+        page.lines.iter().map(|line| self.fill(line, units)).collect()
+    }
+
+    fn fill(&self, line: &str, units: &UnitsConfig) -> String {
+        let mut out = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(open) = rest.find('{') {
+            out.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+            match after.find('}') {
+                Some(close) => {
+                    let name = &after[..close];
+                    match self.value(name.trim(), units) {
+                        Some(value) => out.push_str(&value),
+                        None => out.push_str(&rest[open..open + close + 2]),
+                    }
+                    rest = &after[close + 1..];
+                }
+                None => {
+                    out.push_str(&rest[open..]);
+                    rest = "";
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// [`SystemStatus`] as a [`Source`] for a [`Screen`](crate::lcd::Screen),
+/// read again on every render.
+#[cfg(feature = "lcd")]
+pub struct StatusSource {
+    status: SystemStatus,
+    units: UnitsConfig,
+}
+
+#[cfg(feature = "lcd")]
+impl StatusSource {
+    pub fn new(units: UnitsConfig) -> Self {
+        StatusSource {
+            status: SystemStatus::read(),
+            units,
+        }
+    }
+}
+
+#[cfg(feature = "lcd")]
+impl Source for StatusSource {
+    fn refresh(&mut self) {
+        self.status = SystemStatus::read();
+    }
+
+    fn value(&self, name: &str) -> Option<Value> {
+        self.status.value(name, &self.units).map(Value::Text)
+    }
+}
+
+/// What `sysinfo` rotates through without `[[pages]]` in the config;
+/// each fits a 16x2 display.
+pub fn default_pages() -> Vec<PageConfig> {
+    let page = |lines: [&str; 2]| PageConfig {
+        lines: lines.iter().map(|l| l.to_string()).collect(),
+        duration: Duration::from_secs(4),
+    };
+    vec![
+        page(["{hostname}", "{ip}"]),
+        page(["CPU {cpu_temp}", "Load {load1}"]),
+        page(["Disk {disk} used", "Mem {mem} used"]),
+        page(["Up {uptime}", "{disk_free} free"]),
+    ]
+}
+
+/// Whether every placeholder on `page` is one of [`PLACEHOLDERS`] or
+/// `{time}`.
+pub fn fills(page: &PageConfig) -> bool {
+    page.placeholders()
+        .all(|name| name == "time" || PLACEHOLDERS.iter().any(|&(known, _)| known == name))
+}
+
+/// The local address outgoing traffic would use. Connecting a UDP socket
+/// only picks a route; nothing is sent.
+fn primary_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    Some(socket.local_addr().ok()?.ip()).filter(|ip| !ip.is_unspecified())
+}
+
+fn parse_loadavg(text: &str) -> Option<[f64; 3]> {
+    let mut fields = text.split_whitespace().map(|f| f.parse().ok());
+    Some([fields.next()??, fields.next()??, fields.next()??])
+}
+
+fn parse_meminfo(text: &str) -> Option<Usage> {
+    let field = |name: &str| -> Option<u64> {
+        let line = text.lines().find(|l| l.starts_with(name))?;
+        let kb: u64 = line[name.len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+        Some(kb * 1024)
+    };
+    let total = field("MemTotal:")?;
+    let available = field("MemAvailable:")?;
+    Some(Usage {
+        used: total.saturating_sub(available),
+        total,
+    })
+}
+
+/// Space on the filesystem holding `path`, counting what's reserved for
+/// root as used, like `df`.
+pub fn disk_usage(path: &str) -> Result<Usage, Box<dyn Error>> {
+    let c_path = CString::new(path)?;
+    // SAFETY: statvfs only writes the struct it is handed, which is plain
+    // data, and c_path is a valid C string for the call.
+    let stat = unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(c_path.as_ptr(), &mut stat) != 0 {
+            return Err(format!("{}: {}", path, std::io::Error::last_os_error()).into());
+        }
+        stat
+    };
+    let block = stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * block;
+    let available = stat.f_bavail as u64 * block;
+    Ok(Usage {
+        used: total.saturating_sub(available),
+        total,
+    })
+}
+
+/// `12.3G`, `850M`: a decimal below 100, binary units.
+fn bytes(n: u64) -> String {
+    let mut value = n as f64;
+    for unit in ["B", "K", "M", "G", "T"] {
+        if value < 1024.0 || unit == "T" {
+            return if value < 100.0 && unit != "B" {
+                format!("{:.1}{}", value, unit)
+            } else {
+                format!("{:.0}{}", value, unit)
+            };
+        }
+        value /= 1024.0;
+    }
+    unreachable!()
+}
+
+/// `3d 04:12` past a day, `04:12` below.
+fn uptime(up: Duration) -> String {
+    let secs = up.as_secs();
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{}d {:02}:{:02}", days, hours, minutes)
+    } else {
+        format!("{:02}:{:02}", hours, minutes)
+    }
+}
@@ -0,0 +1,71 @@
+//! The `sd_notify` protocol, without linking libsystemd.
+//!
+//! With `Type=notify` in the unit file, systemd hands the service a datagram
+//! socket in `$NOTIFY_SOCKET` and waits for `READY=1` before starting what
+//! depends on it. With `WatchdogSec=` it also expects `WATCHDOG=1` at least
+//! every [`watchdog_interval`], and restarts the service when those stop.
+//!
+//! ```ini
+//! [Service]
+//! Type=notify
+//! ExecStart=/usr/local/bin/rpi_peripherals monitor
+//! WatchdogSec=30s
+//! Restart=on-failure
+//! ```
+//!
+//! Outside systemd every call is a no-op that returns `Ok(false)`.
+
+use std::env;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+/// Send `state` (newline-separated `KEY=value` pairs) to the service
+/// manager. Returns whether there was one to send it to.
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let path = path.to_string_lossy();
+    // A leading '@' names a socket in the abstract namespace
+    let address = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes())?,
+        None => SocketAddr::from_pathname(path.as_ref())?,
+    };
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &address)?;
+    Ok(true)
+}
+
+/// Startup is finished.
+pub fn ready() -> io::Result<bool> {
+    notify("READY=1")
+}
+
+/// One-line status shown by `systemctl status`.
+pub fn status(text: &str) -> io::Result<bool> {
+    notify(&format!("STATUS={}", text))
+}
+
+/// Still alive; resets the `WatchdogSec=` timer.
+pub fn watchdog() -> io::Result<bool> {
+    notify("WATCHDOG=1")
+}
+
+/// Shutting down, so a slow exit isn't mistaken for a hang.
+pub fn stopping() -> io::Result<bool> {
+    notify("STOPPING=1")
+}
+
+/// How often systemd expects [`watchdog`], if `WatchdogSec=` is set for
+/// this process. Ping at half this to leave margin.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
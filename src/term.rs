@@ -0,0 +1,328 @@
+//! How the command line's output looks: emoji or plain ASCII, colored or
+//! not.
+//!
+//! Everything meant for a person goes through [`say!`] (stdout) or
+//! [`esay!`] (stderr), which print a line through [`render`] and keep a
+//! [`set_status`] line, such as a progress bar, at the bottom. Without
+//! emoji, the status symbols become tags (`✅` is `[ok]`, `❌` is
+//! `[error]`, `⚠️` is `[warn]`), any other emoji a `*`, and the rest of the
+//! line is spelled in ASCII (`µs` as `us`, `→` as `->`), for serial
+//! consoles and log files that would show them as mojibake. With color,
+//! the status symbols are red, yellow and green.
+//!
+//! [`configure`] sets both once, at startup; until then output is emoji
+//! without color, as it always was. [`set_quiet`] drops everything `say!`
+//! prints, for `--quiet`; what a command writes as its result, such as a
+//! hexdump or an export on stdout, doesn't go through `say!` and still
+//! comes out.
+
+use std::borrow::Cow;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static EMOJI: AtomicBool = AtomicBool::new(true);
+static QUIET: AtomicBool = AtomicBool::new(false);
+static COLOR_STDOUT: AtomicBool = AtomicBool::new(false);
+static COLOR_STDERR: AtomicBool = AtomicBool::new(false);
+static STATUS_LINES: Mutex<[StatusLine; 2]> = Mutex::new([StatusLine::NONE, StatusLine::NONE]);
+
+/// A line kept at the bottom of a terminal, such as a progress bar.
+struct StatusLine {
+    line: Option<String>,
+    /// Output so far ends mid-line, so the status line can't be drawn yet.
+    partial: bool,
+}
+
+impl StatusLine {
+    const NONE: StatusLine = StatusLine { line: None, partial: false };
+}
+
+/// Print a line to stdout through [`write`], as `println!` does, unless
+/// [`set_quiet`] says not to.
+#[macro_export]
+macro_rules! say {
+    () => {
+        if !$crate::term::quiet() {
+            $crate::term::write("\n", $crate::term::Stream::Stdout)
+        }
+    };
+    ($($arg:tt)*) => {
+        if !$crate::term::quiet() {
+            $crate::term::write(&::std::format!("{}\n", ::std::format_args!($($arg)*)), $crate::term::Stream::Stdout)
+        }
+    };
+}
+
+/// Print a line to stderr through [`write`], as `eprintln!` does.
+#[macro_export]
+macro_rules! esay {
+    () => {
+        $crate::term::write("\n", $crate::term::Stream::Stderr)
+    };
+    ($($arg:tt)*) => {
+        $crate::term::write(&::std::format!("{}\n", ::std::format_args!($($arg)*)), $crate::term::Stream::Stderr)
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// When the stream is a terminal, `NO_COLOR` is unset and `TERM`
+    /// isn't `dumb`.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn resolve(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && env::var("TERM").map_or(true, |t| t != "dumb")
+            }
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => Err(format!("unknown color choice '{}' (auto, always, never)", other).into()),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        })
+    }
+}
+
+/// Set how [`render`] prints from now on.
+pub fn configure(emoji: bool, color: ColorChoice) {
+    EMOJI.store(emoji, Ordering::Relaxed);
+    COLOR_STDOUT.store(color.resolve(io::stdout().is_terminal()), Ordering::Relaxed);
+    COLOR_STDERR.store(color.resolve(io::stderr().is_terminal()), Ordering::Relaxed);
+}
+
+/// Whether `say!` stays silent from now on.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub fn emoji() -> bool {
+    EMOJI.load(Ordering::Relaxed)
+}
+
+pub fn color(stream: Stream) -> bool {
+    match stream {
+        Stream::Stdout => COLOR_STDOUT.load(Ordering::Relaxed),
+        Stream::Stderr => COLOR_STDERR.load(Ordering::Relaxed),
+    }
+}
+
+/// Print `text` to `stream` through [`render`], keeping any status line
+/// below it. Output errors are ignored: there's nowhere left to report
+/// them.
+pub fn write(text: &str, stream: Stream) {
+    let text = render(text, stream);
+    let mut status = STATUS_LINES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let status = &mut status[stream as usize];
+    let mut out = Vec::with_capacity(text.len() + 8);
+    if status.line.is_some() && !status.partial {
+        out.extend_from_slice(b"\r\x1b[K");
+    }
+    out.extend_from_slice(text.as_bytes());
+    status.partial = !text.is_empty() && !text.ends_with('\n');
+    if let (Some(line), false) = (&status.line, status.partial) {
+        out.extend_from_slice(line.as_bytes());
+    }
+    emit(&out, stream);
+}
+
+/// Draw `line` at the bottom of `stream`, a terminal, under everything
+/// [`write`] prints, or take it away with `None`.
+pub fn set_status(stream: Stream, line: Option<&str>) {
+    let mut status = STATUS_LINES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let status = &mut status[stream as usize];
+    let mut out = String::new();
+    if !status.partial {
+        out.push_str("\r\x1b[K");
+        if let Some(line) = line {
+            out.push_str(&render(line, stream));
+        }
+    }
+    status.line = line.map(|line| render(line, stream).into_owned());
+    emit(out.as_bytes(), stream);
+}
+
+fn emit(bytes: &[u8], stream: Stream) {
+    let _ = match stream {
+        Stream::Stdout => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(bytes).and_then(|()| stdout.flush())
+        }
+        Stream::Stderr => io::stderr().lock().write_all(bytes),
+    };
+}
+
+/// The status symbols: their ASCII tag and their color.
+const STATUS: &[(&str, &str, &str)] = &[
+    ("✅", "[ok]", GREEN),
+    ("❌", "[error]", RED),
+    ("⚠️", "[warn]", YELLOW),
+    ("⚠", "[warn]", YELLOW),
+    ("💡", "[hint]", CYAN),
+    ("🧪", "[dry-run]", CYAN),
+    ("🟢", "[up]", GREEN),
+    ("🔴", "[down]", RED),
+    ("❓", "[?]", YELLOW),
+];
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// `text` as it should go out on `stream`, under the current settings.
+pub fn render(text: &str, stream: Stream) -> Cow<'_, str> {
+    let emoji = emoji();
+    let color = color(stream);
+    if emoji && !color {
+        return Cow::Borrowed(text);
+    }
+    if !color && text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len() + 8);
+    let mut rest = text;
+    while let Some(ch) = rest.chars().next() {
+        if let Some(&(symbol, tag, paint)) = STATUS.iter().find(|(symbol, ..)| rest.starts_with(symbol)) {
+            let shown = if emoji { symbol } else { tag };
+            if color {
+                out.push_str(paint);
+                out.push_str(shown);
+                out.push_str(RESET);
+            } else {
+                out.push_str(shown);
+            }
+            rest = &rest[symbol.len()..];
+            continue;
+        }
+        rest = &rest[ch.len_utf8()..];
+        if emoji || ch.is_ascii() {
+            out.push(ch);
+        } else if is_emoji(ch) {
+            out.push('*');
+        } else if !is_modifier(ch) {
+            out.push_str(transliterate(ch).unwrap_or("?"));
+        }
+    }
+    Cow::Owned(out)
+}
+
+fn is_emoji(ch: char) -> bool {
+    matches!(ch as u32, 0x1F000..=0x1FAFF | 0x2139 | 0x2300..=0x23FF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x3030)
+}
+
+/// Variation selectors and joiners, which only shape the emoji before them.
+fn is_modifier(ch: char) -> bool {
+    matches!(ch, '\u{FE0E}' | '\u{FE0F}' | '\u{200D}')
+}
+
+/// An ASCII spelling of `ch`, where it has a usual one.
+pub fn transliterate(ch: char) -> Option<&'static str> {
+    Some(match ch {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ą' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ą' => "A",
+        'ç' | 'ć' | 'č' => "c",
+        'Ç' | 'Ć' | 'Č' => "C",
+        'ď' | 'đ' => "d",
+        'Ď' | 'Đ' => "D",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ę' | 'Ě' => "E",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' => "I",
+        'ł' => "l",
+        'Ł' => "L",
+        'ñ' | 'ń' | 'ň' => "n",
+        'Ñ' | 'Ń' | 'Ň' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => "O",
+        'ř' => "r",
+        'Ř' => "R",
+        'ś' | 'š' => "s",
+        'Ś' | 'Š' => "S",
+        'ť' => "t",
+        'Ť' => "T",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' | 'Ÿ' => "Y",
+        'ź' | 'ż' | 'ž' => "z",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        'ß' => "ss",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'œ' => "oe",
+        'Œ' => "OE",
+        '‐' | '‑' | '‒' | '–' | '—' | '−' => "-",
+        '‘' | '’' | '‚' | '′' => "'",
+        '“' | '”' | '„' | '″' => "\"",
+        '«' => "<<",
+        '»' => ">>",
+        '…' => "...",
+        '•' | '·' => "*",
+        '×' => "x",
+        '±' => "+-",
+        '≤' => "<=",
+        '≥' => ">=",
+        '≠' => "!=",
+        '≈' => "~",
+        '²' => "2",
+        '³' => "3",
+        '½' => "1/2",
+        '€' => "EUR",
+        '£' => "GBP",
+        '©' => "(c)",
+        '®' => "(R)",
+        '™' => "TM",
+        '°' => "o",
+        'µ' | 'μ' => "u",
+        'Ω' => "Ohm",
+        '→' => "->",
+        '←' => "<-",
+        '↑' => "^",
+        '↓' => "v",
+        '█' => "#",
+        '░' => "-",
+        _ => return None,
+    })
+}
@@ -0,0 +1,150 @@
+//! Waits between bulk writes, tuned down to what the device needs.
+//!
+//! An EEPROM page write or an HD44780 clear keeps the chip busy for a
+//! while, and the datasheet's figure is the worst case over voltage and
+//! temperature. A [`DelayTuner`] starts at that figure and is told, after
+//! each wait, how much longer the device stayed busy: a 24Cxx by NACKing
+//! its address ([`ack_poll`]), an HD44780 by its busy flag. Waits that
+//! were enough shrink the next one; once the device is caught still busy,
+//! the wait settles a margin above the longest busy time seen, and a
+//! longer one pushes it back up.
+//!
+//! The tuned delay is meant to be written into the config, e.g.
+//! `write_delay = "3.2ms"` on the device, so later runs start from it.
+
+use crate::address::{Address, AddressedI2c};
+use crate::exit::TimedOut;
+use crate::scan;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Waits shrink by this fraction until the device is caught busy.
+const SHRINK: (u32, u32) = (3, 4);
+
+/// Headroom above the longest busy time seen, an eighth.
+const MARGIN: u32 = 8;
+
+/// Tuned delays are rounded up to this.
+const RESOLUTION: Duration = Duration::from_micros(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelayTuner {
+    delay: Duration,
+    floor: Duration,
+    /// Longest the device took from the write to ready, when it was
+    /// caught busy.
+    longest: Option<Duration>,
+    waits: u32,
+    caught_busy: u32,
+}
+
+impl DelayTuner {
+    /// Start at `start`, the datasheet's worst case, and never go below
+    /// `floor`.
+    pub fn new(start: Duration, floor: Duration) -> Self {
+        DelayTuner {
+            delay: start.max(floor),
+            floor,
+            longest: None,
+            waits: 0,
+            caught_busy: 0,
+        }
+    }
+
+    /// How long to wait after the next write.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// After waiting [`delay`](Self::delay), the device was busy `still`
+    /// longer; zero if it was ready straight away.
+    pub fn feedback(&mut self, still: Duration) {
+        self.waits += 1;
+        if still.is_zero() {
+            if self.longest.is_none() {
+                self.delay = (self.delay * SHRINK.0 / SHRINK.1).max(self.floor);
+            }
+            return;
+        }
+        self.caught_busy += 1;
+        let took = self.delay + still;
+        let longest = self.longest.map_or(took, |longest| longest.max(took));
+        self.longest = Some(longest);
+        self.delay = round_up(longest + longest / MARGIN).max(self.floor);
+    }
+
+    /// The longest the device has been seen busy, write to ready.
+    pub fn longest(&self) -> Option<Duration> {
+        self.longest
+    }
+
+    /// Whether the delay has been checked against the device: it was
+    /// caught busy at least once, so the wait isn't just still shrinking.
+    pub fn settled(&self) -> bool {
+        self.longest.is_some()
+    }
+
+    pub fn waits(&self) -> u32 {
+        self.waits
+    }
+
+    /// Waits after which the device was still busy.
+    pub fn caught_busy(&self) -> u32 {
+        self.caught_busy
+    }
+}
+
+fn round_up(duration: Duration) -> Duration {
+    let step = RESOLUTION.as_nanos();
+    Duration::from_nanos((duration.as_nanos().div_ceil(step) * step) as u64)
+}
+
+/// Probe `address` until it ACKs, as a 24Cxx does again once its write
+/// cycle is over. Returns how long that took: zero if it answered first
+/// time.
+pub fn ack_poll<I2C: AddressedI2c>(i2c: &mut I2C, address: Address, timeout: Duration) -> Result<Duration, Box<dyn Error>> {
+    let start = Instant::now();
+    if scan::probe(i2c, address) {
+        return Ok(Duration::ZERO);
+    }
+    loop {
+        if scan::probe(i2c, address) {
+            return Ok(start.elapsed());
+        }
+        if start.elapsed() > timeout {
+            return Err(TimedOut {
+                waiting_for: format!("{} to ACK after a write", address),
+                after: timeout,
+            }
+            .into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrinks_until_caught_busy_then_settles() {
+        let mut tuner = DelayTuner::new(Duration::from_millis(10), Duration::from_micros(100));
+        for _ in 0..4 {
+            tuner.feedback(Duration::ZERO);
+        }
+        // 10 ms * (3/4)^4
+        assert_eq!(tuner.delay(), Duration::from_nanos(3_164_062));
+        assert!(!tuner.settled());
+
+        tuner.feedback(Duration::from_micros(836));
+        assert_eq!(tuner.longest(), Some(Duration::from_nanos(4_000_062)));
+        assert_eq!(tuner.delay(), Duration::from_micros(4510));
+        // ready in time: stays put
+        tuner.feedback(Duration::ZERO);
+        assert_eq!(tuner.delay(), Duration::from_micros(4510));
+        assert_eq!((tuner.waits(), tuner.caught_busy()), (6, 1));
+
+        let mut floored = DelayTuner::new(Duration::from_micros(50), Duration::from_micros(40));
+        floored.feedback(Duration::ZERO);
+        assert_eq!(floored.delay(), Duration::from_micros(40));
+    }
+}
@@ -0,0 +1,150 @@
+//! Low-jitter delays and real-time scheduling.
+//!
+//! `thread::sleep` only promises to sleep at least as long as asked; on a
+//! busy Pi it regularly oversleeps by a few milliseconds, which shows up as
+//! ragged gaps on the scope. [`PreciseDelay`] sleeps for most of the delay
+//! and spins through the last stretch. [`enable_realtime`] goes further and
+//! moves the thread to `SCHED_FIFO` on a pinned core, so it isn't preempted
+//! in the middle of a burst.
+
+use std::error::Error;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How much of each delay is spun instead of slept by default: comfortably
+/// more than the kernel's usual wake-up latency.
+pub const DEFAULT_SPIN: Duration = Duration::from_millis(2);
+
+/// Sleep-then-spin delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreciseDelay {
+    spin: Duration,
+}
+
+impl Default for PreciseDelay {
+    fn default() -> Self {
+        PreciseDelay { spin: DEFAULT_SPIN }
+    }
+}
+
+impl PreciseDelay {
+    /// Spin through the last `spin` of every delay. Zero makes this a plain sleep.
+    pub fn new(spin: Duration) -> Self {
+        PreciseDelay { spin }
+    }
+
+    pub fn spin(&self) -> Duration {
+        self.spin
+    }
+
+    pub fn delay(&self, duration: Duration) {
+        match Instant::now().checked_add(duration) {
+            Some(deadline) => self.until(deadline),
+            // Too far off for an Instant, so nothing to be precise about
+            None => thread::sleep(duration),
+        }
+    }
+
+    /// Return as close to `deadline` as possible; immediately if it has passed.
+    pub fn until(&self, deadline: Instant) {
+        let now = Instant::now();
+        if let Some(rest) = deadline.checked_duration_since(now) {
+            if rest > self.spin {
+                thread::sleep(rest - self.spin);
+            }
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Real-time settings for the `--realtime` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Realtime {
+    /// `SCHED_FIFO` priority, 1-99. Stay below the kernel's own IRQ threads
+    /// (50) so the I2C interrupt can still preempt us.
+    pub priority: i32,
+    /// Core to pin the thread to; `None` picks the last one, which is the
+    /// usual choice for `isolcpus=`.
+    pub cpu: Option<usize>,
+}
+
+impl Default for Realtime {
+    fn default() -> Self {
+        Realtime { priority: 40, cpu: None }
+    }
+}
+
+/// Apply `settings` to the calling thread and lock the process's memory so
+/// page faults can't stall it. Returns the core the thread was pinned to.
+///
+/// Needs root or `CAP_SYS_NICE`; without it this fails with a
+/// permission-denied error.
+pub fn enable_realtime(settings: Realtime) -> Result<usize, Box<dyn Error>> {
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    let cpu = settings.cpu.unwrap_or(cpus - 1);
+    if cpu >= cpus {
+        return Err(format!("CPU {} does not exist (this machine has {})", cpu, cpus).into());
+    }
+    set_fifo_priority(settings.priority)?;
+    pin_to_cpu(cpu)?;
+    lock_memory()?;
+    Ok(cpu)
+}
+
+/// Switch the calling thread to `SCHED_FIFO` at `priority`.
+pub fn set_fifo_priority(priority: i32) -> io::Result<()> {
+    // SAFETY: plain syscalls on the calling thread with a valid sched_param.
+    unsafe {
+        let (min, max) = (
+            libc::sched_get_priority_min(libc::SCHED_FIFO),
+            libc::sched_get_priority_max(libc::SCHED_FIFO),
+        );
+        if !(min..=max).contains(&priority) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("SCHED_FIFO priority must be {}-{}, got {}", min, max, priority),
+            ));
+        }
+        let param = libc::sched_param { sched_priority: priority };
+        if libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) != 0 {
+            return Err(context("SCHED_FIFO", io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+/// Keep the calling thread on core `cpu`.
+pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+    // SAFETY: cpu_set_t is plain data; CPU_SET is bounds-checked by the caller
+    // against the online CPU count, far below CPU_SETSIZE.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(context("CPU affinity", io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+/// Lock current and future pages into RAM.
+pub fn lock_memory() -> io::Result<()> {
+    // SAFETY: mlockall takes flags only.
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+        return Err(context("mlockall", io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Keep the kind, so permission errors still map to their exit code.
+fn context(what: &str, err: io::Error) -> io::Error {
+    let hint = if err.kind() == io::ErrorKind::PermissionDenied {
+        " (run as root or grant CAP_SYS_NICE)"
+    } else {
+        ""
+    };
+    io::Error::new(err.kind(), format!("{}: {}{}", what, err, hint))
+}
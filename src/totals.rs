@@ -0,0 +1,416 @@
+//! Totalizers: running sums of a measurement that survive restarts.
+//!
+//! A total either integrates a rate over time (watts into watt-hours,
+//! litres per minute into litres) or adds up the increments of a hardware
+//! counter (rain gauge tips, flow meter pulses), unwrapping the counter when
+//! it rolls over. Totals are kept in the `[totals]` config section:
+//!
+//! ```toml
+//! [totals]
+//! state = "/var/lib/rpi_peripherals/totals.json"
+//! checkpoint = "1m"
+//!
+//! [totals.counters."rail.energy"]
+//! source = "ina219.power"    # W
+//! kind = "rate"
+//! per = "1h"                 # so the total is in Wh
+//!
+//! [totals.counters."rain"]
+//! source = "rain.tips"
+//! kind = "counter"
+//! rollover = 65536           # a 16-bit pulse counter
+//! scale = 0.2794             # mm per tip
+//! ```
+//!
+//! The values are written to `state` every `checkpoint` and when the
+//! process stops, and read back on start, so a restart loses at most one
+//! checkpoint's worth. [`Totals::update`] records each total into the
+//! [`History`] under its name and sets the `rpi_peripherals_total` gauge.
+
+use crate::history::History;
+use crate::metrics::Metrics;
+use crate::parse::serde_helpers;
+use crate::say;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TotalsConfig {
+    /// Where the values are kept between runs; without it they start from
+    /// zero every time.
+    #[serde(default)]
+    pub state: Option<PathBuf>,
+    #[serde(default = "default_checkpoint", deserialize_with = "serde_helpers::duration")]
+    pub checkpoint: Duration,
+    #[serde(default)]
+    pub counters: BTreeMap<String, TotalConfig>,
+}
+
+fn default_checkpoint() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Default for TotalsConfig {
+    fn default() -> Self {
+        TotalsConfig {
+            state: None,
+            checkpoint: default_checkpoint(),
+            counters: BTreeMap::new(),
+        }
+    }
+}
+
+impl TotalsConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.checkpoint.is_zero() {
+            return Err("totals.checkpoint must be greater than zero".into());
+        }
+        for (name, total) in &self.counters {
+            total.validate().map_err(|e| format!("total '{}': {}", name, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// The source is a rate; the total is its integral over time.
+    Rate,
+    /// The source is a counter that only goes up, until it rolls over or
+    /// the device restarts; the total is the sum of its increments.
+    Counter,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TotalConfig {
+    /// The measurement to total, e.g. `"ina219.power"`.
+    pub source: String,
+    pub kind: Kind,
+    /// Multiplies every increment, e.g. millimetres per tip.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    /// For rates, the time unit of the source: `1h` turns watts into
+    /// watt-hours, `1m` litres per minute into litres.
+    #[serde(default = "default_per", deserialize_with = "serde_helpers::duration")]
+    pub per: Duration,
+    /// For rates, samples further apart than this aren't integrated across,
+    /// rather than assuming the rate held while the source was silent.
+    #[serde(default = "default_max_gap", deserialize_with = "serde_helpers::duration")]
+    pub max_gap: Duration,
+    /// For counters, the value they wrap at, e.g. 65536. A counter going
+    /// backwards without one is taken to have restarted from zero.
+    #[serde(default)]
+    pub rollover: Option<f64>,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_per() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_gap() -> Duration {
+    Duration::from_secs(300)
+}
+
+impl TotalConfig {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.source.is_empty() {
+            return Err("needs a source measurement".into());
+        }
+        if !self.scale.is_finite() {
+            return Err(format!("scale {} is not a number", self.scale).into());
+        }
+        match self.kind {
+            Kind::Rate if self.per.is_zero() => return Err("per must be greater than zero".into()),
+            Kind::Rate if self.rollover.is_some() => return Err("rollover only applies to counters".into()),
+            Kind::Counter if self.rollover.is_some_and(|r| !r.is_finite() || r <= 0.0) => {
+                return Err("rollover must be greater than zero".into())
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// One total as listed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Total {
+    pub name: String,
+    pub source: String,
+    pub value: f64,
+    /// When it was last reset, in seconds since the Unix epoch.
+    pub since: u64,
+}
+
+/// What goes in the state file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Saved {
+    totals: BTreeMap<String, SavedTotal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct SavedTotal {
+    value: f64,
+    since: u64,
+}
+
+struct Entry {
+    config: TotalConfig,
+    value: f64,
+    since: u64,
+    /// The last source sample taken in, to measure the next one against.
+    last: Option<(Instant, f64)>,
+}
+
+impl Entry {
+    /// Take in a new source sample; the increment it adds.
+    fn add(&mut self, at: Instant, sample: f64) -> f64 {
+        let previous = self.last.replace((at, sample));
+        let Some((then, before)) = previous else {
+            return 0.0;
+        };
+        let increment = match self.config.kind {
+            Kind::Rate => {
+                let elapsed = at.saturating_duration_since(then);
+                if elapsed > self.config.max_gap {
+                    return 0.0;
+                }
+                // Trapezoids: the average of the two ends over the interval
+                (before + sample) / 2.0 * elapsed.as_secs_f64() / self.config.per.as_secs_f64()
+            }
+            Kind::Counter => match (sample - before, self.config.rollover) {
+                (delta, _) if delta >= 0.0 => delta,
+                (delta, Some(rollover)) => delta + rollover,
+                (_, None) => sample,
+            },
+        };
+        let increment = increment * self.config.scale;
+        if increment.is_finite() {
+            self.value += increment;
+            increment
+        } else {
+            0.0
+        }
+    }
+}
+
+struct Inner {
+    entries: BTreeMap<String, Entry>,
+    /// Changed since the last checkpoint.
+    dirty: bool,
+}
+
+/// Cheap to clone; every clone shares the one set of totals.
+#[derive(Clone)]
+pub struct Totals {
+    inner: Arc<Mutex<Inner>>,
+    state: Option<PathBuf>,
+    checkpoint: Duration,
+    history: History,
+    metrics: Option<Metrics>,
+}
+
+impl Totals {
+    /// The configured totals, carrying on from the values in the state
+    /// file if there is one.
+    pub fn from_config(config: &TotalsConfig, history: History) -> Result<Self, Box<dyn Error>> {
+        config.validate()?;
+        let saved = match &config.state {
+            Some(path) => load(path)?,
+            None => Saved::default(),
+        };
+        let now = unix_now();
+        let entries = config
+            .counters
+            .iter()
+            .map(|(name, total)| {
+                let saved = saved.totals.get(name);
+                let entry = Entry {
+                    config: total.clone(),
+                    value: saved.map_or(0.0, |s| s.value),
+                    since: saved.map_or(now, |s| s.since),
+                    last: None,
+                };
+                (name.clone(), entry)
+            })
+            .collect();
+        Ok(Totals {
+            inner: Arc::new(Mutex::new(Inner { entries, dirty: false })),
+            state: config.state.clone(),
+            checkpoint: config.checkpoint,
+            history,
+            metrics: None,
+        })
+    }
+
+    /// Also export each value as a gauge.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    pub fn list(&self) -> Vec<Total> {
+        self.lock()
+            .entries
+            .iter()
+            .map(|(name, entry)| Total {
+                name: name.clone(),
+                source: entry.config.source.clone(),
+                value: entry.value,
+                since: entry.since,
+            })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.lock().entries.get(name).map(|entry| entry.value)
+    }
+
+    /// Take in the latest sample of every source and record the totals.
+    pub fn update(&self) {
+        let mut inner = self.lock();
+        let mut changed = false;
+        for (name, entry) in &mut inner.entries {
+            if let Some((at, sample)) = self.history.latest_sample(&entry.config.source) {
+                if entry.last.is_none_or(|(seen, _)| at > seen) {
+                    changed |= entry.add(at, sample) != 0.0;
+                }
+            }
+            self.history.record(name, entry.value);
+            if let Some(metrics) = &self.metrics {
+                metrics.set("rpi_peripherals_total", "Value of a totalizer", &[("name", name)], entry.value);
+            }
+        }
+        inner.dirty |= changed;
+    }
+
+    /// Start `name` again from zero, and save that straight away; `false`
+    /// if there is no such total.
+    pub fn reset(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        {
+            let mut inner = self.lock();
+            let Some(entry) = inner.entries.get_mut(name) else {
+                return Ok(false);
+            };
+            entry.value = 0.0;
+            entry.since = unix_now();
+            inner.dirty = true;
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Write the values to the state file if they changed since the last
+    /// time. The file is replaced in one rename, so a power cut leaves the
+    /// old values or the new ones.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.state else {
+            return Ok(());
+        };
+        let mut inner = self.lock();
+        if !inner.dirty {
+            return Ok(());
+        }
+        let saved = Saved {
+            totals: inner
+                .entries
+                .iter()
+                .map(|(name, entry)| (name.clone(), SavedTotal { value: entry.value, since: entry.since }))
+                .collect(),
+        };
+        store(path, &saved)?;
+        inner.dirty = false;
+        Ok(())
+    }
+
+    /// Update every `interval` and checkpoint on a background thread until
+    /// `stop` is set, then checkpoint once more.
+    pub fn spawn(self, interval: Duration, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut saved_at = Instant::now();
+            while !stop.load(Ordering::Relaxed) {
+                self.update();
+                if saved_at.elapsed() >= self.checkpoint {
+                    if let Err(e) = self.save() {
+                        say!("⚠️  Totals checkpoint: {}", e);
+                    }
+                    saved_at = Instant::now();
+                }
+                thread::sleep(interval);
+            }
+            if let Err(e) = self.save() {
+                say!("⚠️  Totals checkpoint: {}", e);
+            }
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The saved totals in `path`, for reading them without running anything.
+pub fn saved(path: &Path) -> Result<BTreeMap<String, (f64, u64)>, Box<dyn Error>> {
+    Ok(load(path)?
+        .totals
+        .into_iter()
+        .map(|(name, total)| (name, (total.value, total.since)))
+        .collect())
+}
+
+/// Reset `names` in the state file at `path`, or all of them if empty;
+/// the names that were there. Only for when nothing is running on it: a
+/// running process writes its own values over the file at its next
+/// checkpoint.
+pub fn reset_saved(path: &Path, names: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut state = load(path)?;
+    let now = unix_now();
+    let mut reset = Vec::new();
+    for (name, total) in &mut state.totals {
+        if names.is_empty() || names.contains(name) {
+            *total = SavedTotal { value: 0.0, since: now };
+            reset.push(name.clone());
+        }
+    }
+    if let Some(missing) = names.iter().find(|name| !reset.contains(name)) {
+        return Err(format!("no saved total '{}' in {}", missing, path.display()).into());
+    }
+    store(path, &state)?;
+    Ok(reset)
+}
+
+/// A missing file is a first run, not an error.
+fn load(path: &Path) -> Result<Saved, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Saved::default()),
+        Err(e) => Err(format!("{}: {}", path.display(), e).into()),
+    }
+}
+
+fn store(path: &Path, saved: &Saved) -> Result<(), Box<dyn Error>> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let text = serde_json::to_string_pretty(saved)?;
+    fs::write(&temporary, text + "\n").map_err(|e| format!("{}: {}", temporary.display(), e))?;
+    fs::rename(&temporary, path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,153 @@
+//! Recorded I2C transaction traces.
+//!
+//! A trace is a JSON file listing every transaction with its offset from the
+//! start of the recording, how long it took, the address, each read/write
+//! with its bytes, and the error if it failed:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "transactions": [
+//!     { "timestamp_us": 0, "duration_us": 212, "address": "0x27",
+//!       "ops": [{ "direction": "write", "bytes": "FF" }] }
+//!   ]
+//! }
+//! ```
+//!
+//! [`Recorder`] captures a trace from any bus it wraps, [`Replayer`] plays
+//! one back on a live bus, at the recorded pace or time-warped, and [`diff`]
+//! lines two traces up to show where they differ. [`export`] turns a trace
+//! into the SCL/SDA waveform the master intended, for PulseView.
+
+mod diff;
+pub mod export;
+mod record;
+mod replay;
+
+pub use diff::{describe, diff, summary, DiffOptions, Divergence, TraceDiff};
+pub use record::{Recorder, Recording};
+pub use replay::{ReplayReport, Replayer, Timing};
+
+use crate::address::Address;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Format version written by this build.
+pub const TRACE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trace {
+    pub version: u32,
+    pub transactions: Vec<TraceEntry>,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Trace {
+            version: TRACE_VERSION,
+            transactions: Vec::new(),
+        }
+    }
+}
+
+impl Trace {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let trace: Trace = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if trace.version != TRACE_VERSION {
+            return Err(format!(
+                "{}: trace version {} is not supported (expected {})",
+                path.display(),
+                trace.version,
+                TRACE_VERSION
+            )
+            .into());
+        }
+        Ok(trace)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text + "\n").map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Time from the first transaction starting to the last one finishing.
+    pub fn span(&self) -> Duration {
+        self.transactions.last().map_or(Duration::ZERO, TraceEntry::end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    /// Offset from the start of the recording.
+    #[serde(rename = "timestamp_us", with = "micros")]
+    pub timestamp: Duration,
+    #[serde(rename = "duration_us", with = "micros")]
+    pub duration: Duration,
+    pub address: Address,
+    pub ops: Vec<TraceOp>,
+    /// Why the transaction failed; absent if it succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TraceEntry {
+    pub fn end(&self) -> Duration {
+        self.timestamp + self.duration
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceOp {
+    pub direction: Direction,
+    /// Bytes written, or bytes read back.
+    #[serde(with = "hex")]
+    pub bytes: Vec<u8>,
+}
+
+mod micros {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(d.as_micros() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_micros(u64::deserialize(d)?))
+    }
+}
+
+/// Bytes as space-separated hex, e.g. `"48 65 6C"`, so traces diff nicely.
+mod hex {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        let text: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        s.serialize_str(&text.join(" "))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        String::deserialize(d)?
+            .split_whitespace()
+            .map(|b| u8::from_str_radix(b, 16).map_err(|_| serde::de::Error::custom(format!("invalid hex byte '{}'", b))))
+            .collect()
+    }
+}
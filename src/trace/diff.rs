@@ -0,0 +1,279 @@
+use super::{Direction, Trace, TraceEntry, TraceOp};
+use std::time::Duration;
+
+/// Above this many cells the LCS table is skipped and entries are paired by
+/// position instead (a 4000 x 4000 middle section, ~64 MB).
+const MAX_ALIGN_CELLS: usize = 16_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Timing differences up to this much are treated as noise.
+    pub tolerance: Duration,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            tolerance: Duration::from_micros(500),
+        }
+    }
+}
+
+/// One way in which the two traces disagree. Indices are into
+/// `left.transactions` / `right.transactions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    OnlyInLeft(usize),
+    OnlyInRight(usize),
+    /// The same transaction appears in both, but out of order.
+    Moved { left: usize, right: usize },
+    /// Same address and shape, but different bytes or a different outcome.
+    Content { left: usize, right: usize },
+    /// Gap since the previous matched transaction, or the duration, differs
+    /// by more than the tolerance.
+    Timing {
+        left: usize,
+        right: usize,
+        gap_delta: Duration,
+        duration_delta: Duration,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDiff {
+    /// Transactions paired up between the two traces.
+    pub matched: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+impl TraceDiff {
+    pub fn is_identical(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Align `left` and `right` and list where they diverge.
+///
+/// Transactions are paired by address and op shape (directions and lengths)
+/// using a longest-common-subsequence alignment, so an extra or missing
+/// transaction shows up once rather than shifting everything after it.
+pub fn diff(left: &Trace, right: &Trace, options: &DiffOptions) -> TraceDiff {
+    let pairs = align(&left.transactions, &right.transactions);
+    let mut divergences = Vec::new();
+
+    let mut previous: Option<(usize, usize)> = None;
+    for &(l, r) in &pairs {
+        let (a, b) = (&left.transactions[l], &right.transactions[r]);
+        if a.ops != b.ops || a.error.is_some() != b.error.is_some() {
+            divergences.push(Divergence::Content { left: l, right: r });
+        }
+        let gap_delta = match previous {
+            Some((pl, pr)) => abs_diff(
+                a.timestamp.saturating_sub(left.transactions[pl].timestamp),
+                b.timestamp.saturating_sub(right.transactions[pr].timestamp),
+            ),
+            None => Duration::ZERO,
+        };
+        let duration_delta = abs_diff(a.duration, b.duration);
+        if gap_delta > options.tolerance || duration_delta > options.tolerance {
+            divergences.push(Divergence::Timing {
+                left: l,
+                right: r,
+                gap_delta,
+                duration_delta,
+            });
+        }
+        previous = Some((l, r));
+    }
+
+    // Unpaired entries: identical ones on both sides were reordered.
+    let mut paired_left = vec![false; left.transactions.len()];
+    let mut paired_right = vec![false; right.transactions.len()];
+    for &(l, r) in &pairs {
+        paired_left[l] = true;
+        paired_right[r] = true;
+    }
+    let mut only_right: Vec<usize> = (0..right.transactions.len()).filter(|&r| !paired_right[r]).collect();
+    for l in (0..left.transactions.len()).filter(|&l| !paired_left[l]) {
+        let entry = &left.transactions[l];
+        match only_right.iter().position(|&r| same_content(entry, &right.transactions[r])) {
+            Some(pos) => divergences.push(Divergence::Moved {
+                left: l,
+                right: only_right.remove(pos),
+            }),
+            None => divergences.push(Divergence::OnlyInLeft(l)),
+        }
+    }
+    divergences.extend(only_right.into_iter().map(Divergence::OnlyInRight));
+
+    TraceDiff {
+        matched: pairs.len(),
+        divergences,
+    }
+}
+
+/// Render a divergence for humans, with differing bytes in brackets.
+pub fn describe(divergence: &Divergence, left: &Trace, right: &Trace) -> String {
+    let l = |i: usize| &left.transactions[i];
+    let r = |i: usize| &right.transactions[i];
+    match *divergence {
+        Divergence::OnlyInLeft(i) => format!("- #{} only in left: {}", i, summary(l(i))),
+        Divergence::OnlyInRight(i) => format!("+ #{} only in right: {}", i, summary(r(i))),
+        Divergence::Moved { left, right } => {
+            format!("~ #{} moved to #{}: {}", left, right, summary(l(left)))
+        }
+        Divergence::Content { left, right } => {
+            let (a, b) = (l(left), r(right));
+            let mut lines = vec![format!("! #{} / #{} at {} differ:", left, right, a.address)];
+            for (x, y) in a.ops.iter().zip(&b.ops) {
+                if x != y {
+                    let (hx, hy) = highlight(&x.bytes, &y.bytes);
+                    lines.push(format!("    {:<5} {}", direction(x.direction), hx));
+                    lines.push(format!("    {:<5} {}", "", hy));
+                }
+            }
+            if a.error != b.error {
+                lines.push(format!(
+                    "    result {} vs {}",
+                    a.error.as_deref().unwrap_or("ok"),
+                    b.error.as_deref().unwrap_or("ok")
+                ));
+            }
+            lines.join("\n")
+        }
+        Divergence::Timing {
+            left,
+            right,
+            gap_delta,
+            duration_delta,
+        } => format!(
+            "⏱ #{} / #{} timing: gap off by {}us, duration off by {}us",
+            left,
+            right,
+            gap_delta.as_micros(),
+            duration_delta.as_micros()
+        ),
+    }
+}
+
+/// One transaction on a line: the address, each op's bytes, and how it failed.
+pub fn summary(entry: &TraceEntry) -> String {
+    let ops: Vec<String> = entry
+        .ops
+        .iter()
+        .map(|op| format!("{} [{}]", direction(op.direction), hex(&op.bytes)))
+        .collect();
+    let result = entry.error.as_deref().map_or(String::new(), |e| format!(" -> {}", e));
+    format!("{} {}{}", entry.address, ops.join(", "), result)
+}
+
+fn direction(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Read => "read",
+        Direction::Write => "write",
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Both byte strings as hex, with the positions that differ in brackets.
+fn highlight(a: &[u8], b: &[u8]) -> (String, String) {
+    let render = |this: &[u8], other: &[u8]| {
+        this.iter()
+            .enumerate()
+            .map(|(i, byte)| {
+                if other.get(i) == Some(byte) {
+                    format!("{:02X}", byte)
+                } else {
+                    format!("[{:02X}]", byte)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    (render(a, b), render(b, a))
+}
+
+fn abs_diff(a: Duration, b: Duration) -> Duration {
+    a.max(b) - a.min(b)
+}
+
+/// Same address and op shape, so the two are "the same transaction".
+fn same_shape(a: &TraceEntry, b: &TraceEntry) -> bool {
+    a.address == b.address
+        && a.ops.len() == b.ops.len()
+        && a.ops.iter().zip(&b.ops).all(|(x, y)| shape(x) == shape(y))
+}
+
+fn shape(op: &TraceOp) -> (Direction, usize) {
+    (op.direction, op.bytes.len())
+}
+
+fn same_content(a: &TraceEntry, b: &TraceEntry) -> bool {
+    a.address == b.address && a.ops == b.ops && a.error.is_some() == b.error.is_some()
+}
+
+/// Index pairs of matching transactions, in order.
+fn align(left: &[TraceEntry], right: &[TraceEntry]) -> Vec<(usize, usize)> {
+    let prefix = left
+        .iter()
+        .zip(right)
+        .take_while(|(a, b)| same_shape(a, b))
+        .count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(a, b)| same_shape(a, b))
+        .count();
+    let (l_mid, r_mid) = (
+        &left[prefix..left.len() - suffix],
+        &right[prefix..right.len() - suffix],
+    );
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let middle = if l_mid.len().saturating_mul(r_mid.len()) > MAX_ALIGN_CELLS {
+        (0..l_mid.len().min(r_mid.len()))
+            .filter(|&i| same_shape(&l_mid[i], &r_mid[i]))
+            .map(|i| (i, i))
+            .collect()
+    } else {
+        lcs(l_mid, r_mid)
+    };
+    pairs.extend(middle.into_iter().map(|(l, r)| (l + prefix, r + prefix)));
+    pairs.extend((0..suffix).rev().map(|i| (left.len() - 1 - i, right.len() - 1 - i)));
+    pairs
+}
+
+fn lcs(left: &[TraceEntry], right: &[TraceEntry]) -> Vec<(usize, usize)> {
+    let (n, m) = (left.len(), right.len());
+    // table[i][j] = LCS length of left[i..] and right[j..]
+    let mut table = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[at(i, j)] = if same_shape(&left[i], &right[j]) {
+                table[at(i + 1, j + 1)] + 1
+            } else {
+                table[at(i + 1, j)].max(table[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if same_shape(&left[i], &right[j]) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[at(i + 1, j)] >= table[at(i, j + 1)] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
@@ -0,0 +1,333 @@
+use super::{Direction, Trace, TraceEntry};
+use crate::address::Address;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Logic-analyzer file formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Value Change Dump, opened directly by PulseView and GTKWave.
+    Vcd,
+    /// `scl,sda` samples at a fixed rate, which the header gives; import
+    /// into sigrok with `-I csv:column_formats=l,l:header=yes:samplerate=<Hz>`.
+    Csv,
+}
+
+/// CSV sample rate unless told otherwise: a few samples per quarter bit at
+/// 400 kHz. Every level lasting a sample period or more shows up.
+pub const DEFAULT_SAMPLE_RATE: u32 = 4_000_000;
+
+impl ExportFormat {
+    /// Pick the format from a file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "vcd" => Some(ExportFormat::Vcd),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vcd" => Ok(ExportFormat::Vcd),
+            "csv" => Ok(ExportFormat::Csv),
+            other => Err(format!("unknown export format '{}' (vcd, csv)", other).into()),
+        }
+    }
+}
+
+/// SCL and SDA levels from `time` until the next edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub time: Duration,
+    pub scl: bool,
+    pub sda: bool,
+}
+
+/// One clocked slot of the reconstruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Start,
+    RepeatedStart,
+    Bit(bool),
+    Stop,
+}
+
+/// Rebuild the SCL/SDA edges the master meant to produce for `trace`.
+///
+/// The bytes come straight from the trace; the bit period of each
+/// transaction is its measured duration spread evenly over its slots, so
+/// the result lines up with a capture at the same offsets. ACK slots show
+/// the slave ACKing, except the first one of a failed transaction, which
+/// shows the NACK that usually caused the failure.
+pub fn waveform(trace: &Trace) -> Vec<Edge> {
+    let mut edges = vec![Edge {
+        time: Duration::ZERO,
+        scl: true,
+        sda: true,
+    }];
+    for entry in &trace.transactions {
+        let slots = slots(entry);
+        if slots.is_empty() {
+            continue;
+        }
+        let period = entry.duration / slots.len() as u32;
+        let mut t = entry.timestamp;
+        for slot in slots {
+            push_slot(&mut edges, t, period, slot);
+            t += period;
+        }
+    }
+    edges
+}
+
+fn push_slot(edges: &mut Vec<Edge>, t: Duration, period: Duration, slot: Slot) {
+    let quarter = period / 4;
+    let mut set = |offset: Duration, scl: Option<bool>, sda: Option<bool>| {
+        let last = *edges.last().expect("waveform starts with the idle level");
+        let edge = Edge {
+            time: t + offset,
+            scl: scl.unwrap_or(last.scl),
+            sda: sda.unwrap_or(last.sda),
+        };
+        if (edge.scl, edge.sda) != (last.scl, last.sda) {
+            edges.push(edge);
+        }
+    };
+    match slot {
+        Slot::Start => {
+            set(Duration::ZERO, Some(true), Some(true));
+            set(quarter, None, Some(false));
+            set(quarter * 3, Some(false), None);
+        }
+        Slot::RepeatedStart => {
+            set(quarter, None, Some(true));
+            set(quarter * 2, Some(true), None);
+            set(quarter * 3, None, Some(false));
+            set(period, Some(false), None);
+        }
+        Slot::Bit(level) => {
+            set(quarter, None, Some(level));
+            set(quarter * 2, Some(true), None);
+            set(period, Some(false), None);
+        }
+        Slot::Stop => {
+            set(quarter, None, Some(false));
+            set(quarter * 2, Some(true), None);
+            set(quarter * 3, None, Some(true));
+        }
+    }
+}
+
+fn slots(entry: &TraceEntry) -> Vec<Slot> {
+    let mut slots = Vec::new();
+    let mut nack_pending = !entry.is_ok();
+    let byte = |slots: &mut Vec<Slot>, value: u8, ack: bool| {
+        slots.extend((0..8).rev().map(|i| Slot::Bit(value & (1 << i) != 0)));
+        slots.push(Slot::Bit(!ack));
+    };
+    // The slave ACKs what the master sends, until the one NACK we show for
+    // a failed transaction.
+    let mut slave_ack = |slots: &mut Vec<Slot>, value: u8| {
+        let ack = !nack_pending;
+        nack_pending = false;
+        byte(slots, value, ack);
+    };
+
+    let mut previous: Option<Direction> = None;
+    for (i, op) in entry.ops.iter().enumerate() {
+        if previous != Some(op.direction) {
+            slots.push(if previous.is_none() { Slot::Start } else { Slot::RepeatedStart });
+            let read = op.direction == Direction::Read;
+            let header = match entry.address {
+                Address::SevenBit(_) => entry.address.wire_bytes(read),
+                Address::TenBit(_) if read => {
+                    if previous.is_none() {
+                        for b in entry.address.wire_bytes(false) {
+                            slave_ack(&mut slots, b);
+                        }
+                        slots.push(Slot::RepeatedStart);
+                    }
+                    entry.address.wire_bytes(true)[..1].to_vec()
+                }
+                Address::TenBit(_) => entry.address.wire_bytes(false),
+            };
+            for b in header {
+                slave_ack(&mut slots, b);
+            }
+        }
+        match op.direction {
+            Direction::Write => {
+                for &b in &op.bytes {
+                    slave_ack(&mut slots, b);
+                }
+            }
+            Direction::Read => {
+                let more = entry.ops.get(i + 1).is_some_and(|o| o.direction == Direction::Read);
+                for (j, &b) in op.bytes.iter().enumerate() {
+                    // The master ACKs every byte it reads except the last.
+                    byte(&mut slots, b, more || j + 1 < op.bytes.len());
+                }
+            }
+        }
+        previous = Some(op.direction);
+    }
+    if previous.is_some() {
+        slots.push(Slot::Stop);
+    }
+    slots
+}
+
+/// `sample_rate` is for CSV; VCD has the edges at their own times.
+pub fn write(trace: &Trace, format: ExportFormat, sample_rate: u32, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        ExportFormat::Vcd => write_vcd(trace, out),
+        ExportFormat::Csv => write_csv(trace, sample_rate, out),
+    }
+}
+
+pub fn write_vcd(trace: &Trace, out: &mut impl Write) -> io::Result<()> {
+    let level = |b: bool| if b { '1' } else { '0' };
+    writeln!(out, "$version rpi_peripherals trace export $end")?;
+    writeln!(out, "$timescale 1 ns $end")?;
+    writeln!(out, "$scope module i2c $end")?;
+    writeln!(out, "$var wire 1 c SCL $end")?;
+    writeln!(out, "$var wire 1 d SDA $end")?;
+    writeln!(out, "$upscope $end")?;
+    writeln!(out, "$enddefinitions $end")?;
+
+    let mut last: Option<Edge> = None;
+    for edge in waveform(trace) {
+        writeln!(out, "#{}", edge.time.as_nanos())?;
+        if last.is_none_or(|l| l.scl != edge.scl) {
+            writeln!(out, "{}c", level(edge.scl))?;
+        }
+        if last.is_none_or(|l| l.sda != edge.sda) {
+            writeln!(out, "{}d", level(edge.sda))?;
+        }
+        last = Some(edge);
+    }
+    Ok(())
+}
+
+/// The waveform sampled every 1/`sample_rate` seconds from zero through
+/// its last edge, as sigrok's CSV input wants: evenly spaced rows with no
+/// time column. One row per sample, idle time included, so a long trace
+/// makes a big file; VCD only stores the edges.
+pub fn write_csv(trace: &Trace, sample_rate: u32, out: &mut impl Write) -> io::Result<()> {
+    if sample_rate == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the sample rate must be above zero"));
+    }
+    writeln!(out, "; rpi_peripherals trace export")?;
+    writeln!(out, "; Samplerate: {} Hz", sample_rate)?;
+    writeln!(out, "scl,sda")?;
+
+    let edges = waveform(trace);
+    let rate = u128::from(sample_rate);
+    let end = edges.last().map_or(0, |e| e.time.as_nanos());
+    // The last sample at or after the last edge, so its level is in
+    let samples = (end * rate).div_ceil(1_000_000_000);
+    let mut current = 0;
+    for n in 0..=samples {
+        let t = n * 1_000_000_000 / rate;
+        while edges.get(current + 1).is_some_and(|e| e.time.as_nanos() <= t) {
+            current += 1;
+        }
+        writeln!(out, "{},{}", edges[current].scl as u8, edges[current].sda as u8)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::TraceOp;
+
+    /// 0x48 written with one 0x00 byte: 20 slots spread over 200µs, so
+    /// every level lasts a multiple of 2.5µs.
+    fn write_byte() -> Trace {
+        Trace {
+            transactions: vec![TraceEntry {
+                timestamp: Duration::from_micros(10),
+                duration: Duration::from_micros(200),
+                address: Address::SevenBit(0x48),
+                ops: vec![TraceOp {
+                    direction: Direction::Write,
+                    bytes: vec![0x00],
+                }],
+                error: None,
+            }],
+            ..Trace::default()
+        }
+    }
+
+    fn csv(trace: &Trace, sample_rate: u32) -> String {
+        let mut out = Vec::new();
+        write_csv(trace, sample_rate, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// The samples back to edges, as sigrok sees them.
+    fn edges_of(csv: &str, sample_rate: u32) -> Vec<Edge> {
+        let mut edges: Vec<Edge> = Vec::new();
+        for (n, row) in csv.lines().skip(3).enumerate() {
+            let (scl, sda) = row.split_once(',').unwrap();
+            let (scl, sda) = (scl == "1", sda == "1");
+            if edges.last().is_none_or(|e| (e.scl, e.sda) != (scl, sda)) {
+                let time = Duration::from_nanos(n as u64 * 1_000_000_000 / u64::from(sample_rate));
+                edges.push(Edge { time, scl, sda });
+            }
+        }
+        edges
+    }
+
+    #[test]
+    fn csv_header_gives_the_sample_rate() {
+        let csv = csv(&write_byte(), 1_000_000);
+        let header: Vec<&str> = csv.lines().take(3).collect();
+        assert_eq!(header, ["; rpi_peripherals trace export", "; Samplerate: 1000000 Hz", "scl,sda"]);
+    }
+
+    #[test]
+    fn csv_samples_evenly_through_the_last_edge() {
+        // The stop's last edge is at 10 + 200 - 2.5µs; at 1 MHz that's 208 samples on
+        let csv = csv(&write_byte(), 1_000_000);
+        assert_eq!(csv.lines().count() - 3, 209);
+        assert_eq!(csv.lines().last(), Some("1,1"));
+    }
+
+    #[test]
+    fn csv_round_trips_a_known_waveform() {
+        let trace = write_byte();
+        let expected = waveform(&trace);
+        // 500ns divides every edge time, so the samples land on the edges
+        let rate = 2_000_000;
+        assert_eq!(edges_of(&csv(&trace, rate), rate), expected);
+    }
+
+    #[test]
+    fn csv_keeps_every_level_at_a_coarser_rate() {
+        let trace = write_byte();
+        let expected = waveform(&trace);
+        let rate = 1_000_000;
+        let got = edges_of(&csv(&trace, rate), rate);
+        let levels = |edges: &[Edge]| edges.iter().map(|e| (e.scl, e.sda)).collect::<Vec<_>>();
+        assert_eq!(levels(&got), levels(&expected));
+        // Each edge shows up within a sample period after it happened
+        for (got, expected) in got.iter().zip(&expected) {
+            assert!(got.time >= expected.time && got.time - expected.time < Duration::from_micros(1));
+        }
+    }
+
+    #[test]
+    fn csv_rejects_a_zero_sample_rate() {
+        assert!(write_csv(&write_byte(), 0, &mut Vec::new()).is_err());
+    }
+}
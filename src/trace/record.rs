@@ -0,0 +1,149 @@
+use super::{Direction, Trace, TraceEntry, TraceOp};
+use crate::address::{Address, AddressedI2c};
+use crate::bus::BusControl;
+use crate::softi2c::Stretching;
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Wraps a bus and logs every transaction that goes through it.
+///
+/// The log lives behind a [`Recording`] handle, so it can be saved after the
+/// bus has been handed to a driver.
+pub struct Recorder<I2C> {
+    i2c: I2C,
+    recording: Recording,
+}
+
+impl<I2C> Recorder<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Recorder {
+            i2c,
+            recording: Recording {
+                start: Instant::now(),
+                trace: Arc::new(Mutex::new(Trace::default())),
+            },
+        }
+    }
+
+    pub fn recording(&self) -> Recording {
+        self.recording.clone()
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+/// Shared handle to what a [`Recorder`] has captured so far.
+#[derive(Clone)]
+pub struct Recording {
+    start: Instant,
+    trace: Arc<Mutex<Trace>>,
+}
+
+impl Recording {
+    pub fn snapshot(&self) -> Trace {
+        self.trace.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.trace.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        self.snapshot().save(path)
+    }
+
+    fn push(&self, began: Instant, duration: Duration, address: Address, ops: &[Operation<'_>], error: Option<String>) {
+        let entry = TraceEntry {
+            timestamp: began.duration_since(self.start),
+            duration,
+            address,
+            ops: ops
+                .iter()
+                .map(|op| match op {
+                    Operation::Read(buf) => TraceOp {
+                        direction: Direction::Read,
+                        bytes: buf.to_vec(),
+                    },
+                    Operation::Write(buf) => TraceOp {
+                        direction: Direction::Write,
+                        bytes: buf.to_vec(),
+                    },
+                })
+                .collect(),
+            error,
+        };
+        self.trace
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .transactions
+            .push(entry);
+    }
+}
+
+impl<I2C: I2c> ErrorType for Recorder<I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> I2c for Recorder<I2C> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let began = Instant::now();
+        let result = self.i2c.transaction(address, operations);
+        let error = result.as_ref().err().map(|e| format!("{:?}", e));
+        self.recording
+            .push(began, began.elapsed(), Address::SevenBit(address), operations, error);
+        result
+    }
+}
+
+impl<I2C: AddressedI2c> AddressedI2c for Recorder<I2C> {
+    fn transaction_at(
+        &mut self,
+        address: Address,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Box<dyn Error>> {
+        let began = Instant::now();
+        let result = self.i2c.transaction_at(address, operations);
+        let error = result.as_ref().err().map(|e| e.to_string());
+        self.recording
+            .push(began, began.elapsed(), address, operations, error);
+        result
+    }
+
+    fn repeated_start(&self, address: Address) -> bool {
+        self.i2c.repeated_start(address)
+    }
+}
+
+impl<I2C: BusControl> BusControl for Recorder<I2C> {
+    fn clock_speed(&self) -> Result<u32, Box<dyn Error>> {
+        self.i2c.clock_speed()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.i2c.set_timeout(timeout)
+    }
+
+    fn set_clock_speed(&mut self, hz: u32) -> Result<(), Box<dyn Error>> {
+        self.i2c.set_clock_speed(hz)
+    }
+
+    fn recover(&mut self) -> Result<(), Box<dyn Error>> {
+        self.i2c.recover()
+    }
+    fn stretching(&self) -> Option<Stretching> {
+        self.i2c.stretching()
+    }
+}
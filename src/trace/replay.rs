@@ -0,0 +1,314 @@
+use super::{Direction, Trace, TraceEntry, TraceOp};
+use crate::address::AddressedI2c;
+use crate::timing::{self, PreciseDelay};
+use embedded_hal::i2c::Operation;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Slowest and fastest a replay may be scaled: a thousand times either way.
+/// Further out, recorded offsets scale past what a [`Duration`] holds or
+/// down to nothing.
+const FACTOR_RANGE: std::ops::RangeInclusive<f64> = 1e-3..=1e3;
+
+/// How replay spaces transactions out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    /// Start each transaction at its recorded offset divided by `speed`, so
+    /// `2.0` plays twice as fast. Falls behind rather than skip if the bus is
+    /// slower than the schedule.
+    Realtime { speed: f64 },
+    /// Keep the recorded idle time between one transaction ending and the
+    /// next starting, multiplied by `scale`; transactions take as long as
+    /// the live bus needs.
+    Gaps { scale: f64 },
+    /// The same pause between every transaction; zero runs flat out.
+    Fixed(Duration),
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::Realtime { speed: 1.0 }
+    }
+}
+
+impl Timing {
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let factor = match *self {
+            Timing::Realtime { speed } => speed,
+            Timing::Gaps { scale } => scale,
+            Timing::Fixed(_) => return Ok(()),
+        };
+        if !FACTOR_RANGE.contains(&factor) {
+            return Err(format!(
+                "replay factor must be from {}x to {}x, got {}",
+                FACTOR_RANGE.start(),
+                FACTOR_RANGE.end(),
+                factor
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Timing::Realtime { speed: 1.0 } => write!(f, "realtime"),
+            Timing::Realtime { speed } => write!(f, "{}x", speed),
+            Timing::Gaps { scale: 1.0 } => write!(f, "gaps"),
+            Timing::Gaps { scale } => write!(f, "gaps:{}x", scale),
+            Timing::Fixed(gap) if gap.is_zero() => write!(f, "fast"),
+            Timing::Fixed(gap) => write!(f, "fixed:{}us", gap.as_micros()),
+        }
+    }
+}
+
+impl FromStr for Timing {
+    type Err = Box<dyn Error>;
+
+    /// `realtime`, `2x` / `0.5x`, `gaps`, `gaps:3x`, `fixed:10ms`, or `fast`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let factor = |f: &str| -> Result<f64, Box<dyn Error>> {
+            f.strip_suffix('x')
+                .unwrap_or(f)
+                .parse()
+                .map_err(|_| format!("invalid replay factor '{}'", f).into())
+        };
+        let timing = match s.trim() {
+            "realtime" => Timing::Realtime { speed: 1.0 },
+            "gaps" => Timing::Gaps { scale: 1.0 },
+            "fast" => Timing::Fixed(Duration::ZERO),
+            other => {
+                if let Some(scale) = other.strip_prefix("gaps:") {
+                    Timing::Gaps { scale: factor(scale)? }
+                } else if let Some(gap) = other.strip_prefix("fixed:") {
+                    Timing::Fixed(crate::parse::duration(gap)?)
+                } else if other.ends_with('x') {
+                    Timing::Realtime { speed: factor(other)? }
+                } else {
+                    return Err(format!(
+                        "unknown replay timing '{}' (realtime, 2x, gaps, gaps:2x, fixed:10ms, fast)",
+                        other
+                    )
+                    .into());
+                }
+            }
+        };
+        timing.validate()?;
+        Ok(timing)
+    }
+}
+
+/// `duration` times `factor`, as long as a [`Duration`] goes if that's
+/// too long for one.
+fn scaled(duration: Duration, factor: f64) -> Duration {
+    Duration::try_from_secs_f64(duration.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+}
+
+/// What happened when a trace was replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The replay itself, in trace form: live timings, bytes read, errors.
+    pub actual: Trace,
+    /// Indices of transactions whose outcome or read-back bytes differ from
+    /// the recording.
+    pub mismatches: Vec<usize>,
+    /// Stopped early by the cancel flag.
+    pub cancelled: bool,
+}
+
+/// Plays a [`Trace`] back on a bus.
+pub struct Replayer<I2C> {
+    i2c: I2C,
+    timing: Timing,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl<I2C: AddressedI2c> Replayer<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Replayer {
+            i2c,
+            timing: Timing::default(),
+            cancel: None,
+        }
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) -> Result<(), Box<dyn Error>> {
+        timing.validate()?;
+        self.timing = timing;
+        Ok(())
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Stop between transactions once `flag` is set.
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    /// Replay every transaction in order. Bus errors don't stop the replay;
+    /// they are recorded in the report, since reproducing them is the point.
+    pub fn replay(&mut self, trace: &Trace) -> ReplayReport {
+        let start = Instant::now();
+        let mut report = ReplayReport {
+            actual: Trace::default(),
+            mismatches: Vec::new(),
+            cancelled: false,
+        };
+
+        for (i, entry) in trace.transactions.iter().enumerate() {
+            let previous = i.checked_sub(1).map(|p| &trace.transactions[p]);
+            let wait = self.wait_before(entry, previous, start);
+            if !self.sleep(wait) || self.cancelled() {
+                report.cancelled = true;
+                break;
+            }
+
+            let actual = self.run(entry, start);
+            if !same_outcome(entry, &actual) {
+                report.mismatches.push(i);
+            }
+            report.actual.transactions.push(actual);
+        }
+        report
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn wait_before(&self, entry: &TraceEntry, previous: Option<&TraceEntry>, start: Instant) -> Duration {
+        match self.timing {
+            Timing::Realtime { speed } => {
+                let offset = scaled(entry.timestamp, speed.recip());
+                match start.checked_add(offset) {
+                    Some(due) => due.saturating_duration_since(Instant::now()),
+                    None => offset,
+                }
+            }
+            Timing::Gaps { scale } => previous.map_or(Duration::ZERO, |p| {
+                scaled(entry.timestamp.saturating_sub(p.end()), scale)
+            }),
+            Timing::Fixed(gap) => {
+                if previous.is_some() {
+                    gap
+                } else {
+                    Duration::ZERO
+                }
+            }
+        }
+    }
+
+    fn run(&mut self, entry: &TraceEntry, start: Instant) -> TraceEntry {
+        let mut buffers: Vec<Vec<u8>> = entry
+            .ops
+            .iter()
+            .map(|op| match op.direction {
+                Direction::Read => vec![0; op.bytes.len()],
+                Direction::Write => op.bytes.clone(),
+            })
+            .collect();
+        let mut ops: Vec<Operation<'_>> = entry
+            .ops
+            .iter()
+            .zip(buffers.iter_mut())
+            .map(|(op, buf)| match op.direction {
+                Direction::Read => Operation::Read(buf),
+                Direction::Write => Operation::Write(buf),
+            })
+            .collect();
+
+        let began = Instant::now();
+        let result = self.i2c.transaction_at(entry.address, &mut ops);
+        let duration = began.elapsed();
+        drop(ops);
+
+        TraceEntry {
+            timestamp: began.duration_since(start),
+            duration,
+            address: entry.address,
+            ops: entry
+                .ops
+                .iter()
+                .zip(buffers)
+                .map(|(op, bytes)| TraceOp {
+                    direction: op.direction,
+                    bytes,
+                })
+                .collect(),
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+
+    /// Sleep in short steps so the cancel flag is noticed, spinning through
+    /// the last bit for accurate gaps; false if cancelled.
+    fn sleep(&self, duration: Duration) -> bool {
+        let Some(deadline) = Instant::now().checked_add(duration) else {
+            while !self.cancelled() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            return false;
+        };
+        loop {
+            if self.cancelled() {
+                return false;
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left <= timing::DEFAULT_SPIN {
+                PreciseDelay::default().until(deadline);
+                return true;
+            }
+            thread::sleep((left - timing::DEFAULT_SPIN).min(Duration::from_millis(10)));
+        }
+    }
+}
+
+/// Same success/failure, and the same bytes came back from every read.
+fn same_outcome(recorded: &TraceEntry, actual: &TraceEntry) -> bool {
+    if recorded.is_ok() != actual.is_ok() {
+        return false;
+    }
+    !actual.is_ok() || recorded.ops == actual.ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_parses() {
+        assert_eq!("realtime".parse::<Timing>().unwrap(), Timing::Realtime { speed: 1.0 });
+        assert_eq!("0.5x".parse::<Timing>().unwrap(), Timing::Realtime { speed: 0.5 });
+        assert_eq!("gaps:3x".parse::<Timing>().unwrap(), Timing::Gaps { scale: 3.0 });
+        assert_eq!("fast".parse::<Timing>().unwrap(), Timing::Fixed(Duration::ZERO));
+        assert_eq!("fixed:10ms".parse::<Timing>().unwrap(), Timing::Fixed(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn extreme_factors_are_rejected() {
+        for timing in ["1e-300x", "1e300x", "0x", "-2x", "NaNx", "infx", "gaps:1e-300x", "gaps:1e9x"] {
+            assert!(timing.parse::<Timing>().is_err(), "{}", timing);
+        }
+        assert!("0.001x".parse::<Timing>().is_ok());
+        assert!("gaps:1000x".parse::<Timing>().is_ok());
+    }
+
+    #[test]
+    fn scaling_saturates_instead_of_panicking() {
+        assert_eq!(scaled(Duration::from_millis(10), 0.5), Duration::from_millis(5));
+        assert_eq!(scaled(Duration::MAX, 1e3), Duration::MAX);
+        assert_eq!(scaled(Duration::ZERO, 1e3), Duration::ZERO);
+    }
+}
@@ -0,0 +1,620 @@
+//! The "Happy Birthday" demo transmitter.
+//!
+//! The message can be anything else instead: text, hex bytes, a file or a
+//! stream such as stdin, split into messages by a [`Payload`]. A
+//! [`Cadence`] times the silence after each one.
+//!
+//! The message goes out through an [`Encoder`], so the same demo shows
+//! textbook waveforms: the built-in [`Encoding`]s are plain ASCII, BCD,
+//! Gray code and Manchester. A [`ManchesterLine`] also clocks the message
+//! out Manchester-coded on a spare GPIO, for a trace without any bus
+//! protocol around it.
+//!
+//! The per-byte and batched framings mark the message with 0xFF and 0x00,
+//! which is all a scope needs; a [`FrameCodec`] picks other markers, or
+//! none, and escapes message bytes that would read as one. Between two
+//! Pis, [`Framing::Framed`] sends it as a [`Frame`] instead: length,
+//! sequence number and CRC-16, which the receiver
+//! ([`crate::i2c::FrameReceiver`]) checks and answers with an ACK or NACK.
+//! [`SimpleI2cTransmitter::send_frame`] resends until it is acknowledged.
+//!
+//! With an [`Integrity`] mode set, a check value (XOR, CRC-8 or CRC-16)
+//! follows the payload in any framing. Receiver firmware that echoes the
+//! value it computed back on the next read lets the transmitter confirm
+//! each message arrived whole; anything else counts as a mismatch.
+
+mod cadence;
+mod codec;
+mod encoding;
+mod frame;
+mod integrity;
+mod payload;
+
+pub use cadence::{Cadence, Gap};
+pub use codec::{FrameCodec, ESCAPE_XOR};
+pub use encoding::{manchester, Encoder, Encoding, ManchesterLine, DEFAULT_BIT_TIME};
+pub use frame::{Frame, FrameError, FrameReceipt, Reply, DEFAULT_ATTEMPTS, FRAME_OVERHEAD, MAX_PAYLOAD, REPLY_DELAY};
+pub use integrity::Integrity;
+pub use payload::{Payload, DEFAULT_CHUNK};
+
+use crate::address::{Address, AddressedI2c};
+use crate::bus::{self, BusControl, SpeedCheck};
+use crate::say;
+use crate::term::{self, Stream};
+use crate::timing::PreciseDelay;
+use crate::trigger::Trigger;
+use embedded_hal::digital::OutputPin;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How a message is split into bus transactions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// One write per byte, [`DEFAULT_INTER_BYTE`] apart unless set: every
+    /// byte gets its own START, address and STOP, easy to pick out on the
+    /// scope.
+    #[default]
+    PerByte,
+    /// The whole message in a single write: one START/address/STOP.
+    Batched,
+    /// The whole message as one [`Frame`], resent until the receiver ACKs it.
+    Framed,
+}
+
+impl FromStr for Framing {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-byte" => Ok(Framing::PerByte),
+            "batched" => Ok(Framing::Batched),
+            "framed" => Ok(Framing::Framed),
+            other => Err(format!("unknown framing '{}' (per-byte, batched, framed)", other).into()),
+        }
+    }
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Framing::PerByte => "per-byte",
+            Framing::Batched => "batched",
+            Framing::Framed => "framed",
+        })
+    }
+}
+
+/// Time actually spent in bus writes, excluding the gaps between them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BusTiming {
+    pub transactions: u32,
+    pub bytes: u32,
+    pub bus_time: Duration,
+    /// Writes that failed; the demo carries on past them.
+    pub errors: u32,
+    /// Messages whose check value read back wrong, or not at all.
+    pub mismatches: u32,
+}
+
+impl BusTiming {
+    pub fn per_byte(&self) -> Duration {
+        self.bus_time.checked_div(self.bytes).unwrap_or_default()
+    }
+
+    fn add(&mut self, bytes: usize, took: Duration, ok: bool) {
+        self.transactions += 1;
+        self.bytes += bytes as u32;
+        self.bus_time += took;
+        if !ok {
+            self.errors += 1;
+        }
+    }
+}
+
+type Pulse = Box<dyn FnMut() -> Result<(), Box<dyn Error>> + Send>;
+
+type Line = Box<dyn FnMut(&[u8]) -> Result<(), Box<dyn Error>> + Send>;
+
+const MESSAGE: &[u8] = b"Happy Birthday";
+
+/// Between per-byte writes, and after the start marker.
+pub const DEFAULT_INTER_BYTE: Duration = Duration::from_millis(50);
+
+pub struct SimpleI2cTransmitter<I2C> {
+    i2c: I2C,
+    address: Address,
+    cancel: Option<Arc<AtomicBool>>,
+    trigger: Option<Pulse>,
+    delay: PreciseDelay,
+    framing: Framing,
+    codec: FrameCodec,
+    encoder: Box<dyn Encoder>,
+    integrity: Integrity,
+    line: Option<Line>,
+    timing: BusTiming,
+    sequence: u8,
+    attempts: u32,
+    message: Vec<u8>,
+    inter_byte: Duration,
+}
+
+impl<I2C: AddressedI2c> SimpleI2cTransmitter<I2C> {
+    /// Works on a bus of its own or on a [`crate::bus::SharedBus`] handle;
+    /// the slave address is set on every transaction.
+    pub fn new(i2c: I2C, address: Address) -> Result<Self, Box<dyn Error>> {
+        Ok(SimpleI2cTransmitter {
+            i2c,
+            address,
+            cancel: None,
+            trigger: None,
+            delay: PreciseDelay::default(),
+            framing: Framing::default(),
+            codec: FrameCodec::default(),
+            encoder: Box::new(Encoding::default()),
+            integrity: Integrity::default(),
+            line: None,
+            timing: BusTiming::default(),
+            sequence: 0,
+            attempts: DEFAULT_ATTEMPTS,
+            message: MESSAGE.to_vec(),
+            inter_byte: DEFAULT_INTER_BYTE,
+        })
+    }
+
+    /// Stop a message part-way once `flag` is set (e.g. from [`crate::shutdown::Shutdown::flag`])
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag);
+    }
+
+    /// Pulse `trigger` right before each message, for scopes to arm on
+    pub fn set_trigger<P>(&mut self, mut trigger: Trigger<P>)
+    where
+        P: OutputPin + Send + 'static,
+        P::Error: Error + 'static,
+    {
+        self.trigger = Some(Box::new(move || trigger.pulse()));
+    }
+
+    /// How the gaps between bytes are timed
+    pub fn set_delay(&mut self, delay: PreciseDelay) {
+        self.delay = delay;
+    }
+
+    /// The gap between per-byte writes, [`DEFAULT_INTER_BYTE`] unless set
+    pub fn set_inter_byte(&mut self, gap: Duration) {
+        self.inter_byte = gap;
+    }
+
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// The markers and escaping around per-byte and batched messages,
+    /// 0xFF and 0x00 unescaped unless set
+    pub fn set_codec(&mut self, codec: FrameCodec) -> Result<(), Box<dyn Error>> {
+        codec.validate()?;
+        self.codec = codec;
+        Ok(())
+    }
+
+    pub fn codec(&self) -> FrameCodec {
+        self.codec
+    }
+
+    /// What the message characters become on the bus, [`Encoding::Ascii`]
+    /// unless set
+    pub fn set_encoder(&mut self, encoder: impl Encoder + 'static) {
+        self.encoder = Box::new(encoder);
+    }
+
+    pub fn encoder(&self) -> &dyn Encoder {
+        self.encoder.as_ref()
+    }
+
+    /// The check value sent after each message and read back to verify it
+    pub fn set_integrity(&mut self, integrity: Integrity) {
+        self.integrity = integrity;
+    }
+
+    pub fn integrity(&self) -> Integrity {
+        self.integrity
+    }
+
+    /// Also send each character, or the whole message when batched, on
+    /// `line` after it goes on the bus
+    pub fn set_manchester_line<P>(&mut self, mut line: ManchesterLine<P>)
+    where
+        P: OutputPin + Send + 'static,
+        P::Error: Error + 'static,
+    {
+        self.line = Some(Box::new(move |bytes| line.send(bytes)));
+    }
+
+    /// Sends of a frame in all before [`Self::send_frame`] gives up,
+    /// [`DEFAULT_ATTEMPTS`] unless set
+    pub fn set_attempts(&mut self, attempts: u32) {
+        self.attempts = attempts.max(1);
+    }
+
+    /// Bus time of everything sent so far
+    pub fn timing(&self) -> BusTiming {
+        self.timing
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    /// Slave address this transmitter talks to
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Request a bus clock speed. The kernel fixes the speed at boot, so this
+    /// checks the request against it and warns when they differ
+    pub fn set_clock_speed(&mut self, hz: u32) -> SpeedCheck
+    where
+        I2C: BusControl,
+    {
+        let check = bus::check_speed(&self.i2c, hz);
+        match check.actual {
+            Some(actual) if actual == hz => say!("🔧 I2C speed: {} Hz", actual),
+            Some(actual) => {
+                say!("⚠️  Requested {} Hz but the bus runs at {} Hz", hz, actual);
+                say!("   To change it, {}", check.advice());
+            }
+            None => say!("⚠️  Can't read the bus speed to confirm {} Hz", hz),
+        }
+        check
+    }
+
+    /// Per-transaction timeout (10 ms resolution)
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>>
+    where
+        I2C: BusControl,
+    {
+        self.i2c.set_timeout(timeout)
+    }
+
+    /// Send single byte with detailed error logging
+    fn send_byte(&mut self, data: u8, description: &str) -> Result<(), Box<dyn Error>> {
+        term::write(&format!("📡 TX: 0x{:02X} {} ", data, description), Stream::Stdout);
+
+        let began = Instant::now();
+        let result = self.i2c.write_at(self.address, &[data]);
+        self.timing.add(1, began.elapsed(), result.is_ok());
+        match result {
+            Ok(_) => {
+                say!("✅ ACK - PCF8574 responded!");
+                Ok(())
+            },
+            Err(e) => {
+                say!("❌ Error: {}", e);
+                // Don't fail completely, continue for scope analysis
+                Ok(())
+            }
+        }
+    }
+
+    /// Send all of `data` in one write transaction; returns how long the
+    /// write took on the bus
+    pub fn send_bytes(&mut self, data: &[u8]) -> Result<Duration, Box<dyn Error>> {
+        term::write(&format!("📡 TX: {} bytes in one transaction ", data.len()), Stream::Stdout);
+
+        let began = Instant::now();
+        let result = self.i2c.write_at(self.address, data);
+        let took = began.elapsed();
+        self.timing.add(data.len(), took, result.is_ok());
+        match result {
+            Ok(_) => say!("✅ ACK in {}µs", took.as_micros()),
+            // Same as per-byte: keep going for scope analysis
+            Err(e) => say!("❌ Error: {}", e),
+        }
+        Ok(took)
+    }
+
+    /// Write `register` and read `buffer` back in one combined transaction,
+    /// with a repeated START between them and no STOP: what register reads
+    /// on most sensors look like. Where the bus can't combine them (10-bit
+    /// addresses on the rppal backend) they go out with a STOP between, and
+    /// the log says so. Returns how long it took on the bus; a failure is
+    /// logged and counted, then returned
+    pub fn write_read(&mut self, register: &[u8], buffer: &mut [u8]) -> Result<Duration, Box<dyn Error>> {
+        let combined = self.i2c.repeated_start(self.address);
+        term::write(&format!("🔁 {} ", self.sequence_of(register, buffer.len(), combined)), Stream::Stdout);
+
+        let began = Instant::now();
+        let result = self.i2c.write_read_at(self.address, register, buffer);
+        let took = began.elapsed();
+        self.finish_read(register.len() + buffer.len(), took, result, buffer)?;
+        Ok(took)
+    }
+
+    /// The same write and read as [`Self::write_read`], but as two
+    /// transactions with a STOP and a fresh START between them, to compare
+    /// against it on the scope
+    pub fn write_then_read(&mut self, register: &[u8], buffer: &mut [u8]) -> Result<Duration, Box<dyn Error>> {
+        term::write(&format!("⏹️  {} ", self.sequence_of(register, buffer.len(), false)), Stream::Stdout);
+
+        let began = Instant::now();
+        let result = self
+            .i2c
+            .write_at(self.address, register)
+            .and_then(|()| self.i2c.read_at(self.address, buffer));
+        let took = began.elapsed();
+        self.finish_read(register.len() + buffer.len(), took, result, buffer)?;
+        Ok(took)
+    }
+
+    /// What a register read puts on the wire, for matching against the
+    /// decoded trace: `S 0x48+W [00] Sr 0x48+R [2 bytes] P`
+    fn sequence_of(&self, register: &[u8], len: usize, repeated_start: bool) -> String {
+        let restart = if repeated_start { "Sr" } else { "P S" };
+        format!(
+            "S {}+W {:02X?} {} {}+R [{} bytes] P ({})",
+            self.address,
+            register,
+            restart,
+            self.address,
+            len,
+            if repeated_start { "repeated start" } else { "stop-start" }
+        )
+    }
+
+    fn finish_read(
+        &mut self,
+        bytes: usize,
+        took: Duration,
+        result: Result<(), Box<dyn Error>>,
+        buffer: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        self.timing.add(bytes, took, result.is_ok());
+        match &result {
+            Ok(()) => say!("✅ {:02X?} in {}µs", buffer, took.as_micros()),
+            Err(e) => say!("❌ Error: {}", e),
+        }
+        result
+    }
+
+    /// Send `payload` as the next [`Frame`] and read the receiver's reply,
+    /// resending on a NACK, a bus error or no reply at all. The sequence
+    /// number only moves on once a frame is acknowledged, so a receiver can
+    /// spot a resend whose first ACK was lost.
+    pub fn send_frame(&mut self, payload: &[u8]) -> Result<FrameReceipt, Box<dyn Error>> {
+        let frame = Frame::new(self.sequence, payload)?.encode();
+        let mut last = String::new();
+        for attempt in 1..=self.attempts {
+            if self.cancelled() {
+                return Err(format!("frame {} cancelled", self.sequence).into());
+            }
+            term::write(&format!("📡 TX: frame {} ({} bytes) ", self.sequence, frame.len()), Stream::Stdout);
+            let began = Instant::now();
+            let result = self.i2c.write_at(self.address, &frame);
+            self.timing.add(frame.len(), began.elapsed(), result.is_ok());
+            let reply = result.and_then(|()| {
+                self.delay.delay(REPLY_DELAY);
+                let mut reply = [0; 2];
+                self.i2c.read_at(self.address, &mut reply)?;
+                Ok(reply)
+            });
+            last = match reply.map(Reply::parse) {
+                Ok(Some(Reply::Ack(seq))) if seq == self.sequence => {
+                    say!("✅ ACK");
+                    let receipt = FrameReceipt { seq, attempts: attempt };
+                    self.sequence = self.sequence.wrapping_add(1);
+                    return Ok(receipt);
+                }
+                Ok(Some(Reply::Ack(seq))) => format!("ACK for frame {}", seq),
+                Ok(Some(Reply::Nack(_))) => "NACK".to_string(),
+                Ok(None) => "no reply".to_string(),
+                Err(e) => e.to_string(),
+            };
+            say!("❌ {}, attempt {}/{}", last, attempt, self.attempts);
+        }
+        Err(format!("frame {} not acknowledged after {} attempts: {}", self.sequence, self.attempts, last).into())
+    }
+
+    /// Read back as many bytes as `check` and compare, counting a mismatch
+    /// in [`BusTiming`]. Nothing to do without an integrity mode.
+    fn read_back_check(&mut self, check: &[u8]) {
+        if check.is_empty() {
+            return;
+        }
+        let mut echoed = vec![0; check.len()];
+        match self.i2c.read_at(self.address, &mut echoed) {
+            Ok(()) if echoed == check => say!("🔒 {} {:02X?} read back ✅", self.integrity, check),
+            Ok(()) => {
+                self.timing.mismatches += 1;
+                say!("🔒 {} sent {:02X?}, read back {:02X?} ❌", self.integrity, check, echoed);
+            }
+            Err(e) => {
+                self.timing.mismatches += 1;
+                say!("🔒 {} read-back failed: {} ❌", self.integrity, e);
+            }
+        }
+    }
+
+    /// What [`send_message`](SimpleI2cTransmitter::send_message) sends
+    /// from now on, "Happy Birthday" until set.
+    pub fn set_message(&mut self, message: &[u8]) {
+        self.message = message.to_vec();
+    }
+
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    /// The longest message the framing can carry once encoded and checked:
+    /// a frame's worth when framed, otherwise no limit.
+    pub fn max_message(&self) -> Option<usize> {
+        if self.framing != Framing::Framed {
+            return None;
+        }
+        let room = MAX_PAYLOAD - self.integrity.check_len();
+        Some((0..=room).rev().find(|&n| self.encoder.encode(&vec![0; n]).len() <= room).unwrap_or(0))
+    }
+
+    /// Send the message and measure timing
+    pub fn send_message(&mut self, message_number: u32) -> Result<Duration, Box<dyn Error>> {
+        let check = if self.integrity.is_none() { String::new() } else { format!(", {}", self.integrity) };
+        say!("\n🎉 MESSAGE {} - Sending {} ({}, {}{})", message_number, describe(&self.message), self.framing, self.encoder.name(), check);
+        if let Some(pulse) = &mut self.trigger {
+            pulse()?;
+        }
+        let start_time = Instant::now();
+        let before = self.timing;
+
+        let message = std::mem::take(&mut self.message);
+        let result = self.send_encoded(message_number, &message, start_time, before);
+        self.message = message;
+        result
+    }
+
+    fn send_encoded(&mut self, message_number: u32, message: &[u8], start_time: Instant, before: BusTiming) -> Result<Duration, Box<dyn Error>> {
+        let payload = self.encoder.encode(message);
+        let check = self.integrity.check(&payload);
+        if self.framing == Framing::Framed {
+            // The frame's ACK is the read-back here
+            if let Err(e) = self.send_frame(&self.integrity.append(&payload)) {
+                // Keep going, as for unacknowledged bytes
+                say!("❌ {}", e);
+            }
+            if let Some(line) = &mut self.line {
+                line(message)?;
+            }
+            let transmission_time = start_time.elapsed();
+            say!("✅ Message {} complete in {}µs\n", message_number, transmission_time.as_micros());
+            return Ok(transmission_time);
+        }
+
+        if self.framing == Framing::Batched {
+            let mut bytes = payload;
+            bytes.extend(&check);
+            self.send_bytes(&self.codec.encode(&bytes))?;
+            self.read_back_check(&check);
+            if let Some(line) = &mut self.line {
+                line(message)?;
+            }
+            let transmission_time = start_time.elapsed();
+            say!("✅ Message {} complete in {}µs\n", message_number, transmission_time.as_micros());
+            return Ok(transmission_time);
+        }
+
+        if let Some(start) = self.codec.start {
+            self.send_byte(start, "START")?;
+            self.delay.delay(self.inter_byte);
+        }
+
+        // Send each character
+        for &ascii in message {
+            if self.cancelled() {
+                say!("⏹️  Message {} interrupted", message_number);
+                return Ok(start_time.elapsed());
+            }
+            let encoded = self.encoder.encode(&[ascii]);
+            for (n, &byte) in encoded.iter().enumerate() {
+                let shown = if ascii.is_ascii_graphic() || ascii == b' ' { format!("'{}'", ascii as char) } else { format!("0x{:02X}", ascii) };
+                let description = match encoded.len() {
+                    1 if byte == ascii => shown,
+                    1 => format!("{} {}", shown, self.encoder.name()),
+                    len => format!("{} {} {}/{}", shown, self.encoder.name(), n + 1, len),
+                };
+                self.send_stuffed(byte, &description)?;
+            }
+            if let Some(line) = &mut self.line {
+                line(&[ascii])?;
+            }
+            self.delay.delay(self.inter_byte);
+        }
+
+        for (n, &byte) in check.iter().enumerate() {
+            self.send_stuffed(byte, &format!("{} {}/{}", self.integrity, n + 1, check.len()))?;
+        }
+
+        if let Some(end) = self.codec.end {
+            self.send_byte(end, "END")?;
+        }
+        self.read_back_check(&check);
+
+        let transmission_time = start_time.elapsed();
+        let bus_time = self.timing.bus_time - before.bus_time;
+        say!("✅ Message {} complete in {:.1}ms ({}µs of it on the bus)\n",
+            message_number, transmission_time.as_millis(), bus_time.as_micros());
+
+        Ok(transmission_time)
+    }
+
+    /// `data` as the codec has it go between the markers: escaped first if
+    /// it looks like one.
+    fn send_stuffed(&mut self, data: u8, description: &str) -> Result<(), Box<dyn Error>> {
+        match self.codec.escape {
+            Some(escape) if self.codec.needs_escape(data) => {
+                self.send_byte(escape, "ESC")?;
+                self.send_byte(data ^ ESCAPE_XOR, &format!("{} escaped", description))
+            }
+            _ => self.send_byte(data, description),
+        }
+    }
+}
+
+/// A message for the log: quoted if it's printable text, else its length.
+fn describe(message: &[u8]) -> String {
+    match std::str::from_utf8(message) {
+        Ok(text) if !text.chars().any(char::is_control) => format!("'{}'", text),
+        _ => format!("{} bytes", message.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::StubBus;
+
+    #[test]
+    fn repeated_start_is_one_transaction() {
+        let stub = StubBus::new();
+        let address = Address::SevenBit(0x20);
+        stub.add_port(address, 0x5A);
+        let mut transmitter = SimpleI2cTransmitter::new(stub.clone(), address).unwrap();
+        let mut buffer = [0];
+        transmitter.write_read(&[0x5A], &mut buffer).unwrap();
+        assert_eq!(buffer, [0x5A]);
+        assert_eq!(stub.transactions().len(), 1);
+        assert_eq!(stub.transactions()[0].ops.len(), 2);
+
+        stub.clear_log();
+        transmitter.write_then_read(&[0x5A], &mut buffer).unwrap();
+        assert_eq!(stub.transactions().len(), 2);
+        assert_eq!(transmitter.timing().errors, 0);
+    }
+
+    #[test]
+    fn failed_register_read_is_an_error() {
+        let mut transmitter = SimpleI2cTransmitter::new(StubBus::new(), Address::SevenBit(0x20)).unwrap();
+        let mut buffer = [0];
+        assert!(transmitter.write_read(&[0x00], &mut buffer).is_err());
+        assert!(transmitter.write_then_read(&[0x00], &mut buffer).is_err());
+        assert_eq!(transmitter.timing().errors, 2);
+    }
+
+    #[test]
+    fn sequence_shows_where_the_stop_goes() {
+        let transmitter = SimpleI2cTransmitter::new(StubBus::new(), Address::SevenBit(0x48)).unwrap();
+        assert_eq!(
+            transmitter.sequence_of(&[0x00], 2, true),
+            "S 0x48+W [00] Sr 0x48+R [2 bytes] P (repeated start)"
+        );
+        assert_eq!(
+            transmitter.sequence_of(&[0x00], 2, false),
+            "S 0x48+W [00] P S 0x48+R [2 bytes] P (stop-start)"
+        );
+    }
+}
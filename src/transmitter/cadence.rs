@@ -0,0 +1,108 @@
+use crate::parse;
+use crate::rng::Rng;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The silence after each rhythm message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Gap {
+    /// As long as the message took to send, for a 50% duty cycle.
+    #[default]
+    Equal,
+    Fixed(Duration),
+    /// Anywhere from `min` to `max`, a new one each time.
+    Random { min: Duration, max: Duration },
+}
+
+impl FromStr for Gap {
+    type Err = Box<dyn Error>;
+
+    /// `equal`, `fixed:<time>` or `random:<min>..<max>`, times as for
+    /// [`parse::duration`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let bad = || format!("unknown gap mode '{}' (equal, fixed:<time>, random:<min>..<max>)", s);
+        let (mode, value) = s.split_once(':').unwrap_or((s, ""));
+        match mode {
+            "equal" if value.is_empty() => Ok(Gap::Equal),
+            "fixed" => Ok(Gap::Fixed(parse::duration(value)?)),
+            "random" => {
+                let (min, max) = value.split_once("..").ok_or_else(bad)?;
+                let (min, max) = (parse::duration(min)?, parse::duration(max)?);
+                if min > max {
+                    return Err(format!("gap range '{}' runs backwards", value).into());
+                }
+                Ok(Gap::Random { min, max })
+            }
+            _ => Err(bad().into()),
+        }
+    }
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Gap::Equal => "equal".to_string(),
+            Gap::Fixed(gap) => format!("fixed:{:?}", gap),
+            Gap::Random { min, max } => format!("random:{:?}..{:?}", min, max),
+        };
+        f.pad(&text)
+    }
+}
+
+/// Hands out the [`Gap`] after each message; random gaps come from a
+/// seeded [`Rng`], so a seed replays the same rhythm.
+#[derive(Debug, Clone)]
+pub struct Cadence {
+    gap: Gap,
+    rng: Rng,
+}
+
+impl Cadence {
+    pub fn new(gap: Gap, seed: u64) -> Self {
+        Cadence {
+            gap,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn gap(&self) -> Gap {
+        self.gap
+    }
+
+    /// How long to wait after a message that took `sent` to go out.
+    pub fn next(&mut self, sent: Duration) -> Duration {
+        match self.gap {
+            Gap::Equal => sent,
+            Gap::Fixed(gap) => gap,
+            Gap::Random { min, max } => {
+                let span = (max - min).as_nanos() as u64;
+                min + Duration::from_nanos(self.rng.below(span.saturating_add(1)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gaps_parse_and_stay_in_range() {
+        let sent = Duration::from_millis(7);
+        assert_eq!(Cadence::new("equal".parse().unwrap(), 0).next(sent), sent);
+        assert_eq!(Cadence::new("fixed:20ms".parse().unwrap(), 0).next(sent), Duration::from_millis(20));
+        let gap: Gap = "random:5..50ms".parse().unwrap();
+        assert_eq!(gap.to_string(), "random:5ms..50ms");
+        let mut cadence = Cadence::new(gap, 7);
+        for _ in 0..100 {
+            let wait = cadence.next(sent);
+            assert!((Duration::from_millis(5)..=Duration::from_millis(50)).contains(&wait));
+        }
+        assert!("random:50..5".parse::<Gap>().is_err());
+        assert!("equal:3".parse::<Gap>().is_err());
+        assert!("sometimes".parse::<Gap>().is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::parse;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// An escaped byte goes out XORed with this after the escape byte, as in
+/// HDLC, so it can't be taken for a marker.
+pub const ESCAPE_XOR: u8 = 0x20;
+
+/// What the per-byte and batched framings put around a message, and how
+/// message bytes that look like a marker get past the receiver.
+///
+/// The default is the demo's own: 0xFF before, 0x00 after and no escaping,
+/// so a 0x00 in the message reads as its end. With an `escape` byte, any
+/// message or check byte equal to a marker or the escape byte goes out as
+/// the escape byte followed by it XOR [`ESCAPE_XOR`], and
+/// [`decode`](Self::decode) undoes that. [`FrameCodec::HDLC`] is the usual
+/// scheme: 0x7E at both ends, 0x7D to escape. Framed messages don't use
+/// markers; a [`Frame`](super::Frame) carries its length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCodec {
+    pub start: Option<u8>,
+    pub end: Option<u8>,
+    pub escape: Option<u8>,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        FrameCodec {
+            start: Some(0xFF),
+            end: Some(0x00),
+            escape: None,
+        }
+    }
+}
+
+impl FrameCodec {
+    /// HDLC-style byte stuffing: 0x7E flags, 0x7D escape.
+    pub const HDLC: FrameCodec = FrameCodec {
+        start: Some(0x7E),
+        end: Some(0x7E),
+        escape: Some(0x7D),
+    };
+
+    /// No markers and no escaping: the message bytes alone.
+    pub const NONE: FrameCodec = FrameCodec {
+        start: None,
+        end: None,
+        escape: None,
+    };
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let Some(escape) = self.escape else {
+            return Ok(());
+        };
+        if Some(escape) == self.start || Some(escape) == self.end {
+            return Err(format!("the escape byte 0x{:02X} can't also be a marker", escape).into());
+        }
+        if self.start.is_none() && self.end.is_none() {
+            return Err("an escape byte needs a start or end marker to protect".into());
+        }
+        // An escaped byte mustn't come out as one of the special bytes again
+        if let Some(clash) = self.special().find(|&b| self.is_special(b ^ ESCAPE_XOR)) {
+            return Err(format!("0x{:02X} escaped is 0x{:02X}, another special byte", clash, clash ^ ESCAPE_XOR).into());
+        }
+        Ok(())
+    }
+
+    /// Whether `byte` has to be escaped, which is never without an escape
+    /// byte.
+    pub fn needs_escape(&self, byte: u8) -> bool {
+        self.escape.is_some() && self.is_special(byte)
+    }
+
+    /// `bytes` as they go between the markers: each byte that needs it
+    /// escaped.
+    pub fn stuff(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(bytes.len());
+        for &byte in bytes {
+            match self.escape {
+                Some(escape) if self.is_special(byte) => out.extend([escape, byte ^ ESCAPE_XOR]),
+                _ => out.push(byte),
+            }
+        }
+        out
+    }
+
+    /// `bytes` stuffed, between the markers: a whole batched write.
+    pub fn encode(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = self.start.into_iter().collect();
+        out.extend(self.stuff(bytes));
+        out.extend(self.end);
+        out
+    }
+
+    /// One message as [`encode`](Self::encode) put it, back to the bytes:
+    /// markers checked and taken off, escapes undone.
+    pub fn decode(&self, frame: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut body = frame;
+        if let Some(start) = self.start {
+            body = body.strip_prefix(&[start]).ok_or_else(|| format!("no 0x{:02X} start marker", start))?;
+        }
+        if let Some(end) = self.end {
+            body = body.strip_suffix(&[end]).ok_or_else(|| format!("no 0x{:02X} end marker", end))?;
+        }
+        let mut out = Vec::with_capacity(body.len());
+        let mut bytes = body.iter().copied().enumerate();
+        while let Some((i, byte)) = bytes.next() {
+            if Some(byte) == self.escape {
+                let (_, escaped) = bytes.next().ok_or("message ends in the middle of an escape")?;
+                out.push(escaped ^ ESCAPE_XOR);
+            } else if self.escape.is_some() && self.is_special(byte) {
+                return Err(format!("unescaped 0x{:02X} at byte {} of the message", byte, i).into());
+            } else {
+                out.push(byte);
+            }
+        }
+        Ok(out)
+    }
+
+    fn special(&self) -> impl Iterator<Item = u8> {
+        self.start.into_iter().chain(self.end).chain(self.escape)
+    }
+
+    fn is_special(&self, byte: u8) -> bool {
+        self.special().any(|b| b == byte)
+    }
+}
+
+impl FromStr for FrameCodec {
+    type Err = Box<dyn Error>;
+
+    /// `hdlc`, `none`, or `KEY=VALUE` pairs separated by commas, e.g.
+    /// `start=0x02,end=0x03,escape=0x10`; a value of `none` leaves that
+    /// byte out, and keys left out keep the default.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut codec = match s.trim() {
+            "hdlc" => return Ok(FrameCodec::HDLC),
+            "none" => return Ok(FrameCodec::NONE),
+            _ => FrameCodec::default(),
+        };
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| format!("'{}' is not KEY=VALUE", pair))?;
+            let byte = match value.trim() {
+                "none" => None,
+                value => Some(parse::byte(value).map_err(|e| format!("{}: {}", key, e))?),
+            };
+            match key.trim() {
+                "start" => codec.start = byte,
+                "end" => codec.end = byte,
+                "escape" => codec.escape = byte,
+                other => return Err(format!("unknown marker '{}' (start, end or escape)", other).into()),
+            }
+        }
+        codec.validate()?;
+        Ok(codec)
+    }
+}
+
+impl fmt::Display for FrameCodec {
+    /// As [`FromStr`] reads it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == FrameCodec::NONE {
+            return f.pad("none");
+        }
+        let byte = |b: Option<u8>| b.map_or("none".to_string(), |b| format!("0x{:02X}", b));
+        let mut text = format!("start={},end={}", byte(self.start), byte(self.end));
+        if self.escape.is_some() {
+            text.push_str(&format!(",escape={}", byte(self.escape)));
+        }
+        f.pad(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_marker_bytes_and_decodes_them_back() {
+        let message = [b'H', 0x00, 0xFF, 0x1B, b'i'];
+        let plain = FrameCodec::default();
+        assert_eq!(plain.encode(&message), [0xFF, b'H', 0x00, 0xFF, 0x1B, b'i', 0x00]);
+
+        let escaped: FrameCodec = "escape=0x1B".parse().unwrap();
+        let wire = escaped.encode(&message);
+        assert_eq!(wire, [0xFF, b'H', 0x1B, 0x20, 0x1B, 0xDF, 0x1B, 0x3B, b'i', 0x00]);
+        // no marker value between the markers
+        assert!(!wire[1..wire.len() - 1].iter().any(|&b| b == 0xFF || b == 0x00));
+        assert_eq!(escaped.decode(&wire).unwrap(), message);
+        assert!(escaped.decode(&[0xFF, 0x1B, 0x00]).is_err());
+
+        let hdlc: FrameCodec = "hdlc".parse().unwrap();
+        assert_eq!(hdlc.encode(&[0x7E, 0x01]), [0x7E, 0x7D, 0x5E, 0x01, 0x7E]);
+        assert_eq!(hdlc.to_string(), "start=0x7E,end=0x7E,escape=0x7D");
+        assert_eq!(hdlc.to_string().parse::<FrameCodec>().unwrap(), hdlc);
+        assert_eq!("start=none,end=none".parse::<FrameCodec>().unwrap().to_string(), "none");
+
+        assert!("escape=0x00".parse::<FrameCodec>().is_err());
+        // 0x20 escaped is 0x00, the end marker
+        assert!("escape=0x1B,start=0x20".parse::<FrameCodec>().is_err());
+    }
+}
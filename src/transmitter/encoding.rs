@@ -0,0 +1,153 @@
+use crate::timing::PreciseDelay;
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::Gpio;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Default Manchester bit time: 1 kbit/s, a clean trace at 200 µs/div.
+pub const DEFAULT_BIT_TIME: Duration = Duration::from_millis(1);
+
+/// Turns the message into the bytes that go on the bus. The START and END
+/// markers go out as they are, whatever the encoder.
+pub trait Encoder: Send {
+    fn name(&self) -> &str;
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8>;
+}
+
+/// The built-in encoders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// The bytes as they are.
+    #[default]
+    Ascii,
+    /// Each byte's decimal value in packed BCD, two bytes a character:
+    /// the hundreds digit, then tens and units (`'H'`, 72, is `00 72`).
+    Bcd,
+    /// Each byte Gray-coded, so neighbouring values differ in one bit.
+    Gray,
+    /// Each byte as 16 half-bits, MSB first, IEEE 802.3 style: a 0 is a
+    /// falling mid-bit edge (`10`), a 1 a rising one (`01`).
+    Manchester,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 4] = [Encoding::Ascii, Encoding::Bcd, Encoding::Gray, Encoding::Manchester];
+}
+
+impl Encoder for Encoding {
+    fn name(&self) -> &str {
+        match self {
+            Encoding::Ascii => "ascii",
+            Encoding::Bcd => "bcd",
+            Encoding::Gray => "gray",
+            Encoding::Manchester => "manchester",
+        }
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Encoding::Ascii => payload.to_vec(),
+            Encoding::Bcd => payload.iter().flat_map(|&b| [b / 100, ((b / 10 % 10) << 4) | (b % 10)]).collect(),
+            Encoding::Gray => payload.iter().map(|&b| b ^ (b >> 1)).collect(),
+            Encoding::Manchester => payload.iter().flat_map(|&b| manchester(b).to_be_bytes()).collect(),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Encoding::ALL
+            .into_iter()
+            .find(|e| e.name() == s)
+            .ok_or_else(|| format!("unknown encoding '{}' (ascii, bcd, gray, manchester)", s).into())
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// `byte` Manchester-coded, first half-bit in bit 15.
+pub fn manchester(byte: u8) -> u16 {
+    (0..8).rev().fold(0, |acc, bit| {
+        let half = if byte & (1 << bit) != 0 { 0b01 } else { 0b10 };
+        (acc << 2) | half
+    })
+}
+
+/// Manchester-coded bytes on a GPIO, idle low, each byte's half-bits held
+/// for half the bit time. The edges are timed with a [`PreciseDelay`], so
+/// they stay on their slots even at 10 kbit/s.
+pub struct ManchesterLine<P> {
+    pin: P,
+    bit_time: Duration,
+    delay: PreciseDelay,
+}
+
+impl ManchesterLine<rppal::gpio::OutputPin> {
+    pub fn from_gpio(pin: u8) -> Result<Self, Box<dyn Error>> {
+        let pin = Gpio::new()?
+            .get(pin)
+            .map_err(|e| format!("Manchester GPIO {}: {}", pin, e))?
+            .into_output_low();
+        ManchesterLine::new(pin)
+    }
+}
+
+impl<P: OutputPin> ManchesterLine<P>
+where
+    P::Error: Error + 'static,
+{
+    pub fn new(mut pin: P) -> Result<Self, Box<dyn Error>> {
+        pin.set_low()?;
+        Ok(ManchesterLine {
+            pin,
+            bit_time: DEFAULT_BIT_TIME,
+            delay: PreciseDelay::default(),
+        })
+    }
+
+    pub fn set_bit_time(&mut self, bit_time: Duration) -> Result<(), Box<dyn Error>> {
+        if bit_time < Duration::from_micros(20) {
+            return Err(format!("a {}µs bit is too short to bit-bang", bit_time.as_micros()).into());
+        }
+        self.bit_time = bit_time;
+        Ok(())
+    }
+
+    pub fn bit_time(&self) -> Duration {
+        self.bit_time
+    }
+
+    /// Encode and send `bytes`, then go back to idle.
+    pub fn send(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        let half = self.bit_time / 2;
+        let mut slot = Instant::now();
+        for &byte in bytes {
+            let code = manchester(byte);
+            for n in (0..16).rev() {
+                if code & (1 << n) != 0 {
+                    self.pin.set_high()?;
+                } else {
+                    self.pin.set_low()?;
+                }
+                // Deadlines from the start, so set-up time doesn't add up
+                slot += half;
+                self.delay.until(slot);
+            }
+        }
+        self.pin.set_low()?;
+        Ok(())
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
@@ -0,0 +1,186 @@
+use crate::crc::crc16;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Largest payload a frame carries: four times the BSC slave's FIFO, which
+/// its poll loop keeps up with at 100 kHz.
+pub const MAX_PAYLOAD: usize = 64;
+
+/// Length and sequence number before the payload, CRC after.
+pub const FRAME_OVERHEAD: usize = 4;
+
+/// How long the sender waits after a frame before reading the reply, for
+/// the receiver to check it and queue the answer.
+pub const REPLY_DELAY: Duration = Duration::from_millis(2);
+
+/// Sends of a frame in all before [`send_frame`] gives up.
+///
+/// [`send_frame`]: super::SimpleI2cTransmitter::send_frame
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+
+const ACK: u8 = 0x06;
+const NACK: u8 = 0x15;
+
+/// One message on the framed link: `[length, seq, payload.., crc hi, crc lo]`,
+/// in a single write. The length counts the payload; the CRC-16 covers
+/// everything before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub seq: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(seq: u8, payload: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(format!("{} bytes don't fit a frame (at most {})", payload.len(), MAX_PAYLOAD).into());
+        }
+        Ok(Frame {
+            seq,
+            payload: payload.to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.payload.len() + FRAME_OVERHEAD);
+        bytes.push(self.payload.len() as u8);
+        bytes.push(self.seq);
+        bytes.extend_from_slice(&self.payload);
+        let crc = crc16(&bytes);
+        bytes.extend_from_slice(&crc.to_be_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, FrameError> {
+        if bytes.len() < FRAME_OVERHEAD {
+            return Err(FrameError::Short { got: bytes.len() });
+        }
+        let length = usize::from(bytes[0]);
+        if length > MAX_PAYLOAD || bytes.len() != length + FRAME_OVERHEAD {
+            return Err(FrameError::Length { said: length, got: bytes.len().saturating_sub(FRAME_OVERHEAD) });
+        }
+        let (body, crc) = bytes.split_at(bytes.len() - 2);
+        let sent = u16::from_be_bytes([crc[0], crc[1]]);
+        let computed = crc16(body);
+        if sent != computed {
+            return Err(FrameError::Crc { sent, computed });
+        }
+        Ok(Frame {
+            seq: bytes[1],
+            payload: body[2..].to_vec(),
+        })
+    }
+}
+
+/// Why a received frame was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// Fewer bytes than an empty frame.
+    Short { got: usize },
+    /// The length byte doesn't match the payload that came.
+    Length { said: usize, got: usize },
+    Crc { sent: u16, computed: u16 },
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Short { got } => write!(f, "{} bytes is too short for a frame", got),
+            FrameError::Length { said, got } => write!(f, "length byte says {} but {} payload bytes came", said, got),
+            FrameError::Crc { sent, computed } => write!(f, "CRC 0x{:04X} sent, 0x{:04X} computed", sent, computed),
+        }
+    }
+}
+
+impl Error for FrameError {}
+
+/// The receiver's answer to a frame, read back by the sender: the status
+/// byte and the sequence number it is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    Ack(u8),
+    /// The frame was damaged; the number is what its sequence byte read as.
+    Nack(u8),
+}
+
+impl Reply {
+    pub fn to_bytes(self) -> [u8; 2] {
+        match self {
+            Reply::Ack(seq) => [ACK, seq],
+            Reply::Nack(seq) => [NACK, seq],
+        }
+    }
+
+    /// `None` for anything that is neither, as an idle receiver gives.
+    pub fn parse(bytes: [u8; 2]) -> Option<Self> {
+        match bytes[0] {
+            ACK => Some(Reply::Ack(bytes[1])),
+            NACK => Some(Reply::Nack(bytes[1])),
+            _ => None,
+        }
+    }
+}
+
+/// A frame the receiver acknowledged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameReceipt {
+    pub seq: u8,
+    /// Sends it took; more than one means frames are being damaged.
+    pub attempts: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_length_seq_payload_crc() {
+        let frame = Frame::new(7, b"HBD").unwrap();
+        assert_eq!(frame.encode(), [0x03, 0x07, b'H', b'B', b'D', 0x79, 0x11]);
+        assert_eq!(Frame::new(0, &[]).unwrap().encode(), [0x00, 0x00, 0x1D, 0x0F]);
+    }
+
+    #[test]
+    fn round_trips_every_length() {
+        for len in 0..=MAX_PAYLOAD {
+            let payload: Vec<u8> = (0..len).map(|i| (i * 37) as u8).collect();
+            let frame = Frame::new(len as u8, &payload).unwrap();
+            let bytes = frame.encode();
+            assert_eq!(bytes.len(), len + FRAME_OVERHEAD);
+            assert_eq!(Frame::decode(&bytes), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn rejects_every_single_bit_error() {
+        let bytes = Frame::new(0x42, b"happy birthday").unwrap().encode();
+        for bit in 0..bytes.len() * 8 {
+            let mut damaged = bytes.clone();
+            damaged[bit / 8] ^= 1 << (bit % 8);
+            assert!(Frame::decode(&damaged).is_err(), "bit {} flipped went unnoticed", bit);
+        }
+    }
+
+    #[test]
+    fn reports_why() {
+        assert_eq!(Frame::decode(&[0x00, 0x00, 0x1D]), Err(FrameError::Short { got: 3 }));
+        assert_eq!(Frame::decode(&[0x02, 0x00, 0x1D, 0x0F]), Err(FrameError::Length { said: 2, got: 0 }));
+        assert_eq!(
+            Frame::decode(&[0x00, 0x00, 0x1D, 0x0E]),
+            Err(FrameError::Crc {
+                sent: 0x1D0E,
+                computed: 0x1D0F
+            })
+        );
+        assert!(Frame::new(0, &[0; MAX_PAYLOAD + 1]).is_err());
+    }
+
+    #[test]
+    fn replies_round_trip() {
+        for reply in [Reply::Ack(3), Reply::Nack(200)] {
+            assert_eq!(Reply::parse(reply.to_bytes()), Some(reply));
+        }
+        assert_eq!(Reply::parse([0x00, 0x00]), None);
+    }
+}
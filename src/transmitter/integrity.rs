@@ -0,0 +1,87 @@
+use crate::crc::{crc16, crc8};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A check value sent after the payload, so the receiver can tell a
+/// transfer was corrupted. It covers the encoded bytes, not the markers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Integrity {
+    #[default]
+    None,
+    /// Every byte XORed together: catches single-bit errors, misses
+    /// swapped bytes.
+    Xor,
+    /// [`crc8`], the SMBus PEC.
+    Crc8,
+    /// [`crc16`], CCITT-FALSE, sent high byte first.
+    Crc16,
+}
+
+impl Integrity {
+    pub const ALL: [Integrity; 4] = [Integrity::None, Integrity::Xor, Integrity::Crc8, Integrity::Crc16];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integrity::None => "none",
+            Integrity::Xor => "xor",
+            Integrity::Crc8 => "crc8",
+            Integrity::Crc16 => "crc16",
+        }
+    }
+
+    /// Bytes the check value takes.
+    pub fn check_len(&self) -> usize {
+        match self {
+            Integrity::None => 0,
+            Integrity::Xor | Integrity::Crc8 => 1,
+            Integrity::Crc16 => 2,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == Integrity::None
+    }
+
+    /// The check value of `payload`; empty for [`Integrity::None`].
+    pub fn check(&self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Integrity::None => Vec::new(),
+            Integrity::Xor => vec![payload.iter().fold(0, |acc, &b| acc ^ b)],
+            Integrity::Crc8 => vec![crc8(payload)],
+            Integrity::Crc16 => crc16(payload).to_be_bytes().to_vec(),
+        }
+    }
+
+    /// `payload` with its check value on the end.
+    pub fn append(&self, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = payload.to_vec();
+        bytes.extend(self.check(payload));
+        bytes
+    }
+
+    /// Whether `bytes`, a payload with its check value on the end, is whole.
+    pub fn verify(&self, bytes: &[u8]) -> bool {
+        match bytes.len().checked_sub(self.check_len()) {
+            Some(split) => self.check(&bytes[..split]) == bytes[split..],
+            None => false,
+        }
+    }
+}
+
+impl FromStr for Integrity {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Integrity::ALL
+            .into_iter()
+            .find(|i| i.name() == s)
+            .ok_or_else(|| format!("unknown integrity '{}' (none, xor, crc8, crc16)", s).into())
+    }
+}
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
@@ -0,0 +1,179 @@
+use super::MESSAGE;
+use crate::parse;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Where a stream is read in pieces of, unless told otherwise.
+pub const DEFAULT_CHUNK: usize = 64;
+
+enum Source {
+    /// Known up front, sent a chunk a message and then from the start again.
+    Fixed(Vec<u8>),
+    /// Sent as it arrives, one read a message, until it ends.
+    Stream(Box<dyn Read + Send>),
+}
+
+/// What the demo sends: "Happy Birthday" unless given text, hex, a file's
+/// contents or a stream such as stdin.
+///
+/// Each message is one chunk. Fixed payloads go out whole unless
+/// [`set_chunk`](Payload::set_chunk) splits them, and start over when
+/// they run out, so a long file cycles through the messages. Streams are
+/// read a chunk at a time, [`DEFAULT_CHUNK`] bytes unless set; a read
+/// returns what has arrived so far, so text piped in line by line is
+/// sent a line a message.
+pub struct Payload {
+    source: Source,
+    label: String,
+    chunk: Option<usize>,
+    offset: usize,
+}
+
+impl Payload {
+    fn fixed(bytes: Vec<u8>, label: String) -> Result<Self, Box<dyn Error>> {
+        if bytes.is_empty() {
+            return Err(format!("{} is empty", label).into());
+        }
+        Ok(Payload {
+            source: Source::Fixed(bytes),
+            label,
+            chunk: None,
+            offset: 0,
+        })
+    }
+
+    /// The built-in "Happy Birthday".
+    pub fn message() -> Self {
+        Payload {
+            source: Source::Fixed(MESSAGE.to_vec()),
+            label: "'Happy Birthday'".to_string(),
+            chunk: None,
+            offset: 0,
+        }
+    }
+
+    pub fn text(text: &str) -> Result<Self, Box<dyn Error>> {
+        Payload::fixed(text.as_bytes().to_vec(), format!("'{}'", text))
+    }
+
+    /// Bytes as [`parse::hex_bytes`] reads them, e.g. `"DE AD BE EF"`.
+    pub fn hex(hex: &str) -> Result<Self, Box<dyn Error>> {
+        let bytes = parse::hex_bytes(hex)?;
+        let label = format!("{} hex bytes", bytes.len());
+        Payload::fixed(bytes, label)
+    }
+
+    pub fn file(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Payload::fixed(bytes, path.display().to_string())
+    }
+
+    pub fn stdin() -> Self {
+        Payload::stream(io::stdin(), "stdin")
+    }
+
+    pub fn stream(reader: impl Read + Send + 'static, label: &str) -> Self {
+        Payload {
+            source: Source::Stream(Box::new(reader)),
+            label: label.to_string(),
+            chunk: None,
+            offset: 0,
+        }
+    }
+
+    /// At most `bytes` a message.
+    pub fn set_chunk(&mut self, bytes: usize) -> Result<(), Box<dyn Error>> {
+        if bytes == 0 {
+            return Err("chunk size must be at least one byte".into());
+        }
+        self.chunk = Some(bytes);
+        Ok(())
+    }
+
+    /// The chunk size if none was set, for a framing that can only carry
+    /// `limit` bytes a message.
+    pub fn limit_chunk(&mut self, limit: usize) -> Result<(), Box<dyn Error>> {
+        match self.chunk {
+            Some(chunk) if chunk > limit => Err(format!("chunks of {} bytes don't fit; at most {} here", chunk, limit).into()),
+            Some(_) => Ok(()),
+            None => self.set_chunk(limit),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Total bytes, for a fixed payload.
+    pub fn size(&self) -> Option<usize> {
+        match &self.source {
+            Source::Fixed(bytes) => Some(bytes.len()),
+            Source::Stream(_) => None,
+        }
+    }
+
+    /// Whether it ends; a fixed payload never does, it goes round again.
+    pub fn is_stream(&self) -> bool {
+        matches!(self.source, Source::Stream(_))
+    }
+
+    /// The next message, or `None` once a stream has ended.
+    pub fn next_message(&mut self) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        match &mut self.source {
+            Source::Fixed(bytes) => {
+                let chunk = self.chunk.unwrap_or(bytes.len());
+                let end = (self.offset + chunk).min(bytes.len());
+                let message = bytes[self.offset..end].to_vec();
+                self.offset = if end == bytes.len() { 0 } else { end };
+                Ok(Some(message))
+            }
+            Source::Stream(reader) => {
+                let mut buf = vec![0; self.chunk.unwrap_or(DEFAULT_CHUNK)];
+                loop {
+                    match reader.read(&mut buf) {
+                        Ok(0) => return Ok(None),
+                        Ok(n) => {
+                            buf.truncate(n);
+                            return Ok(Some(buf));
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(format!("{}: {}", self.label, e).into()),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Payload").field("label", &self.label).field("chunk", &self.chunk).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_fixed_payloads_and_streams() {
+        let mut payload = Payload::hex("DE AD,0xBEEF 01").unwrap();
+        assert_eq!(payload.size(), Some(5));
+        payload.set_chunk(2).unwrap();
+        let messages: Vec<_> = (0..4).map(|_| payload.next_message().unwrap().unwrap()).collect();
+        assert_eq!(messages, [vec![0xDE, 0xAD], vec![0xBE, 0xEF], vec![0x01], vec![0xDE, 0xAD]]);
+        assert!(Payload::hex("ABC").is_err());
+        assert!(Payload::text("").is_err());
+        assert!(payload.limit_chunk(1).is_err());
+
+        let mut stream = Payload::stream(io::Cursor::new(b"hello".to_vec()), "test");
+        stream.set_chunk(3).unwrap();
+        assert_eq!(stream.next_message().unwrap().unwrap(), b"hel");
+        assert_eq!(stream.next_message().unwrap().unwrap(), b"lo");
+        assert_eq!(stream.next_message().unwrap(), None);
+    }
+}
@@ -0,0 +1,63 @@
+//! External trigger output for oscilloscopes and logic analyzers.
+//!
+//! Triggering on SDA's falling edge fires on every START and most data bits.
+//! A [`Trigger`] instead pulses a spare GPIO high once, right before a burst
+//! of traffic, so the scope can arm on that line and capture the whole burst.
+
+use embedded_hal::digital::OutputPin;
+use rppal::gpio::Gpio;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Default pulse width: easy for any scope to catch, short next to a byte.
+pub const DEFAULT_WIDTH: Duration = Duration::from_micros(10);
+
+pub struct Trigger<P> {
+    pin: P,
+    width: Duration,
+}
+
+impl Trigger<rppal::gpio::OutputPin> {
+    /// Drive BCM pin `pin`, idle low.
+    pub fn from_gpio(pin: u8) -> Result<Self, Box<dyn Error>> {
+        let pin = Gpio::new()?
+            .get(pin)
+            .map_err(|e| format!("trigger GPIO {}: {}", pin, e))?
+            .into_output_low();
+        Trigger::new(pin)
+    }
+}
+
+impl<P: OutputPin> Trigger<P>
+where
+    P::Error: Error + 'static,
+{
+    pub fn new(mut pin: P) -> Result<Self, Box<dyn Error>> {
+        pin.set_low()?;
+        Ok(Trigger { pin, width: DEFAULT_WIDTH })
+    }
+
+    pub fn set_width(&mut self, width: Duration) {
+        self.width = width;
+    }
+
+    pub fn width(&self) -> Duration {
+        self.width
+    }
+
+    /// One high pulse. Spins rather than sleeps: a sleep would stretch a
+    /// 10 µs pulse to the scheduler's granularity.
+    pub fn pulse(&mut self) -> Result<(), Box<dyn Error>> {
+        self.pin.set_high()?;
+        let start = Instant::now();
+        while start.elapsed() < self.width {
+            std::hint::spin_loop();
+        }
+        self.pin.set_low()?;
+        Ok(())
+    }
+
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
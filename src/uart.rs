@@ -0,0 +1,76 @@
+//! Serial ports: the Pi's own UARTs and USB-serial adapters.
+//!
+//! [`SerialPort`] wraps `rppal`'s UART in `std::io::Read`/`Write` so
+//! stream-oriented drivers don't need to know which kind of port they're on.
+//! USB adapters can be opened by [`PortSelector`] instead of by their
+//! `/dev/ttyUSB*` path, which changes with plug order.
+
+mod usb;
+
+pub use usb::{list_usb_serial, PortSelector, UsbSerialPort};
+
+use rppal::uart::{Parity, Queue, Uart};
+use std::error::Error;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Default read timeout so a silent device doesn't hang the caller forever.
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub struct SerialPort {
+    uart: Uart,
+}
+
+impl SerialPort {
+    /// Open `path` (e.g. `/dev/serial0`, `/dev/ttyUSB0`) as 8N1 at `baud_rate`.
+    pub fn open<P: AsRef<Path>>(path: P, baud_rate: u32) -> Result<Self, Box<dyn Error>> {
+        let mut uart = Uart::with_path(path, baud_rate, Parity::None, 8, 1)?;
+        uart.set_read_mode(0, DEFAULT_READ_TIMEOUT)?;
+        uart.set_write_mode(true)?;
+        Ok(SerialPort { uart })
+    }
+
+    /// Open the port a selector currently resolves to.
+    pub fn open_selector(selector: &PortSelector, baud_rate: u32) -> Result<Self, Box<dyn Error>> {
+        SerialPort::open(selector.resolve()?, baud_rate)
+    }
+
+    pub fn with_uart(uart: Uart) -> Self {
+        SerialPort { uart }
+    }
+
+    /// Reads return whatever arrived within `timeout`, possibly nothing.
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.uart.set_read_mode(0, timeout)?;
+        Ok(())
+    }
+
+    /// Drop any bytes received but not yet read.
+    pub fn discard_input(&mut self) -> Result<(), Box<dyn Error>> {
+        self.uart.flush(Queue::Input)?;
+        Ok(())
+    }
+
+    /// Access the underlying UART for settings this wrapper doesn't cover.
+    pub fn uart(&mut self) -> &mut Uart {
+        &mut self.uart
+    }
+}
+
+impl io::Read for SerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.uart.read(buf).map_err(io::Error::other)
+    }
+}
+
+impl io::Write for SerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.uart.write(buf).map_err(io::Error::other)
+    }
+
+    /// Blocks until the transmit queue has physically gone out on the wire.
+    fn flush(&mut self) -> io::Result<()> {
+        self.uart.drain().map_err(io::Error::other)
+    }
+}
@@ -0,0 +1,190 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const TTY_CLASS: &str = "/sys/class/tty";
+
+/// A `/dev/ttyUSB*` or `/dev/ttyACM*` node and the USB device behind it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbSerialPort {
+    pub path: PathBuf,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Interface number, to tell apart the ports of multi-port adapters.
+    pub interface: Option<u8>,
+}
+
+impl UsbSerialPort {
+    /// Identifier that survives replugging and reboots, preferring the serial number.
+    pub fn stable_id(&self) -> PortSelector {
+        PortSelector::Usb {
+            vendor_id: self.vendor_id,
+            product_id: self.product_id,
+            serial: self.serial.clone(),
+            interface: self.interface,
+        }
+    }
+}
+
+/// List the USB-serial adapters currently plugged in, sorted by device path.
+pub fn list_usb_serial() -> Result<Vec<UsbSerialPort>, Box<dyn Error>> {
+    let mut ports = Vec::new();
+    let entries = match fs::read_dir(TTY_CLASS) {
+        Ok(entries) => entries,
+        // No sysfs (not Linux, or a container): nothing to find.
+        Err(_) => return Ok(ports),
+    };
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if !(name.starts_with("ttyUSB") || name.starts_with("ttyACM")) {
+            continue;
+        }
+        if let Some(port) = describe(&name) {
+            ports.push(port);
+        }
+    }
+    ports.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(ports)
+}
+
+fn describe(tty: &str) -> Option<UsbSerialPort> {
+    let device = fs::canonicalize(Path::new(TTY_CLASS).join(tty).join("device")).ok()?;
+    let interface = device
+        .ancestors()
+        .find(|dir| dir.join("bInterfaceNumber").exists())
+        .and_then(|dir| read_attr(dir, "bInterfaceNumber"))
+        .and_then(|n| u8::from_str_radix(&n, 16).ok());
+    let usb = device.ancestors().find(|dir| dir.join("idVendor").exists())?;
+
+    Some(UsbSerialPort {
+        path: Path::new("/dev").join(tty),
+        vendor_id: u16::from_str_radix(&read_attr(usb, "idVendor")?, 16).ok()?,
+        product_id: u16::from_str_radix(&read_attr(usb, "idProduct")?, 16).ok()?,
+        serial: read_attr(usb, "serial"),
+        manufacturer: read_attr(usb, "manufacturer"),
+        product: read_attr(usb, "product"),
+        interface,
+    })
+}
+
+fn read_attr(dir: &Path, name: &str) -> Option<String> {
+    let value = fs::read_to_string(dir.join(name)).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// How a config names a serial port.
+///
+/// Parsed from strings such as `/dev/serial0`, `usb:1a86:7523`,
+/// `usb:0403:6001:A50285BI`, `usb:0403:6010:FT123456#1` (interface 1), or
+/// `serial:A50285BI`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortSelector {
+    Path(PathBuf),
+    Usb {
+        vendor_id: u16,
+        product_id: u16,
+        serial: Option<String>,
+        interface: Option<u8>,
+    },
+    Serial(String),
+}
+
+impl PortSelector {
+    fn matches(&self, port: &UsbSerialPort) -> bool {
+        match self {
+            PortSelector::Path(path) => &port.path == path,
+            PortSelector::Usb {
+                vendor_id,
+                product_id,
+                serial,
+                interface,
+            } => {
+                port.vendor_id == *vendor_id
+                    && port.product_id == *product_id
+                    && (serial.is_none() || port.serial == *serial)
+                    && (interface.is_none() || port.interface == *interface)
+            }
+            PortSelector::Serial(serial) => port.serial.as_ref() == Some(serial),
+        }
+    }
+
+    /// Find the device node this selector refers to right now.
+    ///
+    /// Plain paths are returned as-is so on-board UARTs keep working; USB
+    /// selectors must match exactly one plugged-in adapter.
+    pub fn resolve(&self) -> Result<PathBuf, Box<dyn Error>> {
+        if let PortSelector::Path(path) = self {
+            return Ok(path.clone());
+        }
+        let matches: Vec<_> = list_usb_serial()?.into_iter().filter(|p| self.matches(p)).collect();
+        match matches.as_slice() {
+            [port] => Ok(port.path.clone()),
+            [] => Err(format!("no USB serial adapter matches {}", self).into()),
+            _ => Err(format!(
+                "{} matches {} adapters ({}); add a serial number or #interface",
+                self,
+                matches.len(),
+                matches.iter().map(|p| p.path.display().to_string()).collect::<Vec<_>>().join(", ")
+            )
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for PortSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortSelector::Path(path) => write!(f, "{}", path.display()),
+            PortSelector::Usb {
+                vendor_id,
+                product_id,
+                serial,
+                interface,
+            } => {
+                write!(f, "usb:{:04x}:{:04x}", vendor_id, product_id)?;
+                if let Some(serial) = serial {
+                    write!(f, ":{}", serial)?;
+                }
+                if let Some(interface) = interface {
+                    write!(f, "#{}", interface)?;
+                }
+                Ok(())
+            }
+            PortSelector::Serial(serial) => write!(f, "serial:{}", serial),
+        }
+    }
+}
+
+impl FromStr for PortSelector {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(serial) = s.strip_prefix("serial:") {
+            return Ok(PortSelector::Serial(serial.to_string()));
+        }
+        let Some(rest) = s.strip_prefix("usb:") else {
+            return Ok(PortSelector::Path(PathBuf::from(s)));
+        };
+
+        let (rest, interface) = match rest.split_once('#') {
+            Some((rest, n)) => (rest, Some(n.parse::<u8>()?)),
+            None => (rest, None),
+        };
+        let mut parts = rest.splitn(3, ':');
+        let (Some(vid), Some(pid)) = (parts.next(), parts.next()) else {
+            return Err(format!("expected usb:VID:PID[:SERIAL][#IF], got '{}'", s).into());
+        };
+        Ok(PortSelector::Usb {
+            vendor_id: u16::from_str_radix(vid, 16)?,
+            product_id: u16::from_str_radix(pid, 16)?,
+            serial: parts.next().map(str::to_string),
+            interface,
+        })
+    }
+}
@@ -0,0 +1,163 @@
+//! Unit conversion for presentation.
+//!
+//! Drivers and stored data always use one base unit per quantity: degrees
+//! Celsius, hectopascals and millimetres. Conversion happens only where a
+//! value is shown or exported, using the `[units]` config section:
+//!
+//! ```toml
+//! [units]
+//! temperature = "f"
+//! pressure = "inhg"
+//! length = "in"
+//! decimal_comma = true
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantity {
+    Temperature,
+    Pressure,
+    Length,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum TemperatureUnit {
+    #[default]
+    #[serde(rename = "c", alias = "celsius")]
+    Celsius,
+    #[serde(rename = "f", alias = "fahrenheit")]
+    Fahrenheit,
+    #[serde(rename = "k", alias = "kelvin")]
+    Kelvin,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum PressureUnit {
+    #[default]
+    #[serde(rename = "hpa", alias = "mbar")]
+    Hectopascal,
+    #[serde(rename = "kpa")]
+    Kilopascal,
+    #[serde(rename = "inhg")]
+    InchOfMercury,
+    #[serde(rename = "mmhg")]
+    MillimetreOfMercury,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum LengthUnit {
+    #[default]
+    #[serde(rename = "mm")]
+    Millimetre,
+    #[serde(rename = "cm")]
+    Centimetre,
+    #[serde(rename = "m")]
+    Metre,
+    #[serde(rename = "in")]
+    Inch,
+}
+
+const HPA_PER_INHG: f64 = 33.863_886;
+const HPA_PER_MMHG: f64 = 1.333_224;
+const MM_PER_INCH: f64 = 25.4;
+
+/// Display preferences from the `[units]` config section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnitsConfig {
+    #[serde(default)]
+    pub temperature: TemperatureUnit,
+    #[serde(default)]
+    pub pressure: PressureUnit,
+    #[serde(default)]
+    pub length: LengthUnit,
+    /// Write `21,5` instead of `21.5`, as most of Europe does.
+    #[serde(default)]
+    pub decimal_comma: bool,
+}
+
+impl UnitsConfig {
+    /// `value` in the base unit of `quantity` to the configured unit.
+    pub fn convert(&self, quantity: Quantity, value: f64) -> f64 {
+        match quantity {
+            Quantity::Temperature => match self.temperature {
+                TemperatureUnit::Celsius => value,
+                TemperatureUnit::Fahrenheit => value * 9.0 / 5.0 + 32.0,
+                TemperatureUnit::Kelvin => value + 273.15,
+            },
+            Quantity::Pressure => match self.pressure {
+                PressureUnit::Hectopascal => value,
+                PressureUnit::Kilopascal => value / 10.0,
+                PressureUnit::InchOfMercury => value / HPA_PER_INHG,
+                PressureUnit::MillimetreOfMercury => value / HPA_PER_MMHG,
+            },
+            Quantity::Length => match self.length {
+                LengthUnit::Millimetre => value,
+                LengthUnit::Centimetre => value / 10.0,
+                LengthUnit::Metre => value / 1000.0,
+                LengthUnit::Inch => value / MM_PER_INCH,
+            },
+        }
+    }
+
+    pub fn symbol(&self, quantity: Quantity) -> &'static str {
+        match quantity {
+            Quantity::Temperature => match self.temperature {
+                TemperatureUnit::Celsius => "°C",
+                TemperatureUnit::Fahrenheit => "°F",
+                TemperatureUnit::Kelvin => "K",
+            },
+            Quantity::Pressure => match self.pressure {
+                PressureUnit::Hectopascal => "hPa",
+                PressureUnit::Kilopascal => "kPa",
+                PressureUnit::InchOfMercury => "inHg",
+                PressureUnit::MillimetreOfMercury => "mmHg",
+            },
+            Quantity::Length => match self.length {
+                LengthUnit::Millimetre => "mm",
+                LengthUnit::Centimetre => "cm",
+                LengthUnit::Metre => "m",
+                LengthUnit::Inch => "in",
+            },
+        }
+    }
+
+    /// Converted value with `decimals` places and its symbol, e.g. `70.7°F`.
+    pub fn format(&self, quantity: Quantity, value: f64, decimals: usize) -> String {
+        let number = format!("{:.*}", decimals, self.convert(quantity, value));
+        let number = if self.decimal_comma { number.replace('.', ",") } else { number };
+        // Degree symbols sit on the number, other units get a space
+        match self.symbol(quantity) {
+            symbol @ ("°C" | "°F") => format!("{}{}", number, symbol),
+            symbol => format!("{} {}", number, symbol),
+        }
+    }
+
+    /// Converted value for JSON output, carrying its unit along.
+    pub fn reading(&self, quantity: Quantity, value: f64) -> Reading {
+        Reading {
+            value: self.convert(quantity, value),
+            unit: self.symbol(quantity),
+        }
+    }
+}
+
+/// A value as presented: `{"value": 70.7, "unit": "°F"}`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Reading {
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let space = if self.unit.starts_with('°') { "" } else { " " };
+        match f.precision() {
+            Some(p) => write!(f, "{:.*}{}{}", p, self.value, space, self.unit),
+            None => write!(f, "{}{}{}", self.value, space, self.unit),
+        }
+    }
+}
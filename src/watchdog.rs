@@ -0,0 +1,377 @@
+//! Error-budget watchdog for long-running deployments.
+//!
+//! Every transaction outcome is fed to [`Watchdog::record`]. When a device's
+//! failure rate over the rolling window goes over budget, the watchdog climbs
+//! one rung of the ladder and runs the remedy registered for it:
+//!
+//! 1. [`Stage::Reinit`]: re-run the driver's init sequence
+//! 2. [`Stage::BusRecovery`]: clock the bus free / reopen it
+//! 3. [`Stage::PowerCycle`]: toggle the device's load switch
+//! 4. [`Stage::Alert`]: tell a human
+//!
+//! Each remedy gets a cooldown to take effect before the next rung. A device
+//! that stays within budget for a cooldown drops back to healthy. Rungs with
+//! no remedy registered are skipped; the alert goes to the notification sink
+//! if no remedy is registered for it.
+//!
+//! A bus that hangs outright is a different failure: [`LockupWatchdog`]
+//! counts lockups in a row, and after [`Policy::lockups`] of them recovers
+//! the bus and re-inits the device, so a display left running for weeks
+//! rides out a stuck SDA line on its own.
+
+mod lockup;
+
+pub use lockup::{is_lockup, Incident, LockupWatchdog};
+
+use crate::metrics::Metrics;
+use crate::notify::{Notification, NotificationSink, Priority};
+use crate::parse::serde_helpers;
+use crate::say;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Transitions kept in [`Watchdog::history`].
+const HISTORY_LEN: usize = 256;
+
+/// Error budget, settable from the `[watchdog]` config section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Highest tolerated share of failed transactions in the window (0-1).
+    #[serde(default = "default_error_rate")]
+    pub max_error_rate: f64,
+    #[serde(default = "default_window", deserialize_with = "serde_helpers::duration")]
+    pub window: Duration,
+    /// Windows with fewer transactions than this never break the budget.
+    #[serde(default = "default_min_samples")]
+    pub min_samples: usize,
+    /// Time a remedy gets before the next rung, and time within budget
+    /// before a device counts as healthy again.
+    #[serde(default = "default_cooldown", deserialize_with = "serde_helpers::duration")]
+    pub cooldown: Duration,
+    /// Timeouts or bus errors in a row before a long-running display
+    /// recovers the bus and re-inits.
+    #[serde(default = "default_lockups")]
+    pub lockups: u32,
+}
+
+fn default_error_rate() -> f64 {
+    0.1
+}
+
+fn default_window() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_min_samples() -> usize {
+    10
+}
+
+fn default_cooldown() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_lockups() -> u32 {
+    3
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            max_error_rate: default_error_rate(),
+            window: default_window(),
+            min_samples: default_min_samples(),
+            cooldown: default_cooldown(),
+            lockups: default_lockups(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !(0.0..=1.0).contains(&self.max_error_rate) {
+            return Err(format!("watchdog max_error_rate must be in 0-1, got {}", self.max_error_rate).into());
+        }
+        if self.window.is_zero() {
+            return Err("watchdog window must be greater than zero".into());
+        }
+        if self.min_samples == 0 {
+            return Err("watchdog min_samples must be at least 1".into());
+        }
+        if self.lockups == 0 {
+            return Err("watchdog lockups must be at least 1".into());
+        }
+        Ok(())
+    }
+}
+
+/// Rung of the escalation ladder a device is on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    #[default]
+    Healthy,
+    Reinit,
+    BusRecovery,
+    PowerCycle,
+    Alert,
+}
+
+impl Stage {
+    fn next(self) -> Option<Stage> {
+        match self {
+            Stage::Healthy => Some(Stage::Reinit),
+            Stage::Reinit => Some(Stage::BusRecovery),
+            Stage::BusRecovery => Some(Stage::PowerCycle),
+            Stage::PowerCycle => Some(Stage::Alert),
+            Stage::Alert => None,
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Stage::Healthy => "healthy",
+            Stage::Reinit => "reinit",
+            Stage::BusRecovery => "bus-recovery",
+            Stage::PowerCycle => "power-cycle",
+            Stage::Alert => "alert",
+        })
+    }
+}
+
+/// One logged move up or down the ladder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub at: SystemTime,
+    pub device: String,
+    pub from: Stage,
+    pub to: Stage,
+    pub error_rate: f64,
+    /// Why the remedy failed, if it did.
+    pub failure: Option<String>,
+}
+
+type Remedy = Box<dyn FnMut(&str) -> Result<(), Box<dyn Error>> + Send>;
+
+#[derive(Default)]
+struct DeviceHealth {
+    samples: VecDeque<(Instant, bool)>,
+    stage: Stage,
+    last_action: Option<Instant>,
+    last_breach: Option<Instant>,
+}
+
+pub struct Watchdog {
+    policy: Policy,
+    devices: HashMap<String, DeviceHealth>,
+    remedies: HashMap<Stage, Remedy>,
+    notifier: Option<Box<dyn NotificationSink>>,
+    metrics: Option<Metrics>,
+    history: VecDeque<Transition>,
+}
+
+impl Watchdog {
+    pub fn new(policy: Policy) -> Result<Self, Box<dyn Error>> {
+        policy.validate()?;
+        Ok(Watchdog {
+            policy,
+            devices: HashMap::new(),
+            remedies: HashMap::new(),
+            notifier: None,
+            metrics: None,
+            history: VecDeque::new(),
+        })
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Replace the budget, e.g. after a config reload. Device state is kept.
+    pub fn set_policy(&mut self, policy: Policy) -> Result<(), Box<dyn Error>> {
+        policy.validate()?;
+        self.policy = policy;
+        Ok(())
+    }
+
+    /// Register what to do at `stage`; the remedy gets the device name.
+    pub fn set_remedy<F>(&mut self, stage: Stage, remedy: F)
+    where
+        F: FnMut(&str) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        self.remedies.insert(stage, Box::new(remedy));
+    }
+
+    /// Where alerts go when no [`Stage::Alert`] remedy is registered.
+    pub fn set_notifier(&mut self, sink: Box<dyn NotificationSink>) {
+        self.notifier = Some(sink);
+    }
+
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    pub fn stage(&self, device: &str) -> Stage {
+        self.devices.get(device).map_or(Stage::Healthy, |d| d.stage)
+    }
+
+    /// Failure rate over the current window, `None` before any samples.
+    pub fn error_rate(&self, device: &str) -> Option<f64> {
+        self.devices.get(device).and_then(|d| rate(&d.samples))
+    }
+
+    /// Most recent transitions, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &Transition> {
+        self.history.iter()
+    }
+
+    /// Feed one transaction outcome. Returns the stage escalated to, if the
+    /// budget was broken and a remedy ran.
+    pub fn record(&mut self, device: &str, ok: bool) -> Option<Stage> {
+        self.record_at(device, ok, Instant::now())
+    }
+
+    /// [`Watchdog::record`] with an explicit timestamp.
+    pub fn record_at(&mut self, device: &str, ok: bool, now: Instant) -> Option<Stage> {
+        let policy = self.policy.clone();
+        let health = self.devices.entry(device.to_string()).or_default();
+        health.samples.push_back((now, ok));
+        while health
+            .samples
+            .front()
+            .is_some_and(|&(t, _)| now.duration_since(t) > policy.window)
+        {
+            health.samples.pop_front();
+        }
+        let total = health.samples.len();
+        let error_rate = rate(&health.samples).unwrap_or(0.0);
+        let breached = total >= policy.min_samples && error_rate > policy.max_error_rate;
+        let cooling = health
+            .last_action
+            .is_some_and(|t| now.duration_since(t) < policy.cooldown);
+
+        if let Some(metrics) = &self.metrics {
+            let result = if ok { "ok" } else { "error" };
+            metrics.inc(
+                "rpi_peripherals_device_transactions_total",
+                "Transactions seen by the watchdog",
+                &[("device", device), ("result", result)],
+                1.0,
+            );
+            metrics.set(
+                "rpi_peripherals_device_error_rate",
+                "Failed share of transactions over the watchdog window",
+                &[("device", device)],
+                error_rate,
+            );
+        }
+
+        if breached {
+            health.last_breach = Some(now);
+            if cooling {
+                return None;
+            }
+            let next = health.stage.next()?;
+            return Some(self.escalate(device, next, error_rate, now));
+        }
+
+        let settled = health
+            .last_breach
+            .is_none_or(|t| now.duration_since(t) >= policy.cooldown);
+        if health.stage != Stage::Healthy && settled && !cooling {
+            let from = health.stage;
+            health.stage = Stage::Healthy;
+            health.last_action = None;
+            self.log(device, from, Stage::Healthy, error_rate, None);
+        }
+        None
+    }
+
+    /// Run the first remedy at or above `stage`, falling through rungs that
+    /// have none registered.
+    fn escalate(&mut self, device: &str, mut stage: Stage, error_rate: f64, now: Instant) -> Stage {
+        while stage != Stage::Alert && !self.remedies.contains_key(&stage) {
+            stage = stage.next().unwrap_or(Stage::Alert);
+        }
+        let failure = match self.remedies.get_mut(&stage) {
+            Some(remedy) => remedy(device).err().map(|e| e.to_string()),
+            None => self.alert(device, error_rate).err().map(|e| e.to_string()),
+        };
+
+        let health = self.devices.entry(device.to_string()).or_default();
+        let from = health.stage;
+        health.stage = stage;
+        health.last_action = Some(now);
+        // Give the remedy a clean window; old failures shouldn't re-trip it.
+        health.samples.clear();
+        self.log(device, from, stage, error_rate, failure);
+        stage
+    }
+
+    fn alert(&self, device: &str, error_rate: f64) -> Result<(), Box<dyn Error>> {
+        let Some(sink) = &self.notifier else {
+            return Ok(());
+        };
+        sink.notify(&Notification::new(
+            format!("{} is failing", device),
+            format!(
+                "{:.0}% of transactions failed over the last {}s; automatic recovery did not help",
+                error_rate * 100.0,
+                self.policy.window.as_secs()
+            ),
+            Priority::High,
+        ))
+    }
+
+    fn log(&mut self, device: &str, from: Stage, to: Stage, error_rate: f64, failure: Option<String>) {
+        match &failure {
+            Some(e) => say!(
+                "🐕 {}: {} → {} ({:.0}% errors) failed: {}",
+                device,
+                from,
+                to,
+                error_rate * 100.0,
+                e
+            ),
+            None => say!("🐕 {}: {} → {} ({:.0}% errors)", device, from, to, error_rate * 100.0),
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.set(
+                "rpi_peripherals_watchdog_stage",
+                "Escalation stage per device (0 healthy .. 4 alert)",
+                &[("device", device)],
+                to as u8 as f64,
+            );
+            let to_name = to.to_string();
+            metrics.inc(
+                "rpi_peripherals_watchdog_transitions_total",
+                "Watchdog stage changes",
+                &[("device", device), ("to", &to_name)],
+                1.0,
+            );
+        }
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(Transition {
+            at: SystemTime::now(),
+            device: device.to_string(),
+            from,
+            to,
+            error_rate,
+            failure,
+        });
+    }
+}
+
+fn rate(samples: &VecDeque<(Instant, bool)>) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    let errors = samples.iter().filter(|&&(_, ok)| !ok).count();
+    Some(errors as f64 / samples.len() as f64)
+}
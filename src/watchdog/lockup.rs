@@ -0,0 +1,197 @@
+use super::HISTORY_LEN;
+use crate::exit::ExitStatus;
+use crate::metrics::{is_nack, Metrics};
+use crate::say;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::time::SystemTime;
+
+/// One lockup recovery, as logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Incident {
+    pub at: SystemTime,
+    pub device: String,
+    /// Lockups in a row that set it off.
+    pub lockups: u32,
+    /// The last of them.
+    pub error: String,
+    /// Why the recovery itself failed, if it did.
+    pub failure: Option<String>,
+}
+
+impl fmt::Display for Incident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} lockups in a row ({}), ", self.device, self.lockups, self.error)?;
+        match &self.failure {
+            Some(e) => write!(f, "recovery failed: {}", e),
+            None => f.write_str("recovered"),
+        }
+    }
+}
+
+/// Whether `err` looks like a hung bus rather than a device that isn't
+/// there: a timeout, or a bus error other than a NACK.
+pub fn is_lockup(err: &(dyn Error + 'static)) -> bool {
+    match ExitStatus::classify(err) {
+        ExitStatus::Timeout => true,
+        ExitStatus::BusError => !is_nack(err),
+        _ => false,
+    }
+}
+
+/// Keeps a long-running device, such as an LCD status display, going
+/// through bus lockups.
+///
+/// Every operation's result goes through [`LockupWatchdog::check`]. A
+/// lockup is let go, for the caller to try again next round, until
+/// `threshold` come in a row; then the recovery passed in runs, which
+/// should clock the bus free and re-run the device's init, and the
+/// incident is logged. Anything else that fails is handed back as usual.
+///
+/// ```no_run
+/// # use rpi_peripherals::address::Address;
+/// # use rpi_peripherals::bus::BusControl;
+/// # use rpi_peripherals::lcd::Lcd;
+/// # use rpi_peripherals::watchdog::LockupWatchdog;
+/// # fn run(i2c: rppal::i2c::I2c) -> Result<(), Box<dyn std::error::Error>> {
+/// let mut lcd = Lcd::new(i2c, Address::SevenBit(0x27), 16, 2)?;
+/// let mut watchdog = LockupWatchdog::new("lcd", 3);
+/// loop {
+///     let shown = lcd.update("still here");
+///     watchdog.check(shown, || {
+///         lcd.interface().i2c_mut().recover()?;
+///         lcd.init()
+///     })?;
+///     std::thread::sleep(std::time::Duration::from_secs(1));
+/// }
+/// # }
+/// ```
+pub struct LockupWatchdog {
+    device: String,
+    threshold: u32,
+    consecutive: u32,
+    incidents: VecDeque<Incident>,
+    metrics: Option<Metrics>,
+}
+
+impl LockupWatchdog {
+    /// `threshold` is clamped to at least one lockup.
+    pub fn new(device: &str, threshold: u32) -> Self {
+        LockupWatchdog {
+            device: device.to_string(),
+            threshold: threshold.max(1),
+            consecutive: 0,
+            incidents: VecDeque::new(),
+            metrics: None,
+        }
+    }
+
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Lockups since the last success or recovery.
+    pub fn consecutive(&self) -> u32 {
+        self.consecutive
+    }
+
+    /// Most recent recoveries, oldest first.
+    pub fn incidents(&self) -> impl Iterator<Item = &Incident> {
+        self.incidents.iter()
+    }
+
+    /// `Some` value on success; `None` for a lockup that was let go or
+    /// recovered from. A failed recovery is logged, not returned: the next
+    /// `threshold` lockups try again.
+    pub fn check<T, F>(&mut self, result: Result<T, Box<dyn Error>>, recover: F) -> Result<Option<T>, Box<dyn Error>>
+    where
+        F: FnOnce() -> Result<(), Box<dyn Error>>,
+    {
+        let err = match result {
+            Ok(value) => {
+                self.consecutive = 0;
+                return Ok(Some(value));
+            }
+            Err(e) if is_lockup(e.as_ref()) => e,
+            Err(e) => return Err(e),
+        };
+        self.consecutive += 1;
+        if self.consecutive < self.threshold {
+            return Ok(None);
+        }
+        let failure = recover().err().map(|e| e.to_string());
+        let incident = Incident {
+            at: SystemTime::now(),
+            device: self.device.clone(),
+            lockups: self.consecutive,
+            error: err.to_string(),
+            failure,
+        };
+        self.consecutive = 0;
+        say!("🐕 {}", incident);
+        if let Some(metrics) = &self.metrics {
+            let result = if incident.failure.is_none() { "ok" } else { "error" };
+            metrics.inc(
+                "rpi_peripherals_lockup_recoveries_total",
+                "Bus lockups recovered from by re-initializing the device",
+                &[("device", &self.device), ("result", result)],
+                1.0,
+            );
+        }
+        if self.incidents.len() == HISTORY_LEN {
+            self.incidents.pop_front();
+        }
+        self.incidents.push_back(incident);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn timeout() -> Result<(), Box<dyn Error>> {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "clock stretch timeout").into())
+    }
+
+    #[test]
+    fn recovers_after_lockups_in_a_row() {
+        let mut watchdog = LockupWatchdog::new("lcd", 3);
+        let mut recoveries = 0;
+        for _ in 0..2 {
+            assert_eq!(watchdog.check(timeout(), || unreachable!()).unwrap(), None);
+        }
+        // a success starts the count again
+        assert_eq!(watchdog.check(Ok(7), || unreachable!()).unwrap(), Some(7));
+        for _ in 0..3 {
+            watchdog
+                .check(timeout(), || {
+                    recoveries += 1;
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert_eq!(recoveries, 1);
+        assert_eq!(watchdog.consecutive(), 0);
+        let incidents: Vec<&Incident> = watchdog.incidents().collect();
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].lockups, 3);
+        assert_eq!(incidents[0].failure, None);
+    }
+
+    #[test]
+    fn passes_other_errors_through() {
+        let mut watchdog = LockupWatchdog::new("lcd", 1);
+        let missing: Result<(), Box<dyn Error>> = Err(crate::bus::StubError {
+            address: crate::address::Address::SevenBit(0x27),
+        }
+        .into());
+        assert!(watchdog.check(missing, || unreachable!()).is_err());
+        let failed = watchdog.check(timeout(), || Err("SDA still held low".into())).unwrap();
+        assert_eq!(failed, None);
+        let incident = watchdog.incidents().next().unwrap();
+        assert_eq!(incident.failure.as_deref(), Some("SDA still held low"));
+    }
+}
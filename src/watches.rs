@@ -0,0 +1,188 @@
+//! Watch expressions: measurements computed from other measurements.
+//!
+//! Each watch is a name and an [`Expr`] over measurement names, set in the
+//! `[watches]` config section or registered at runtime over HTTP:
+//!
+//! ```toml
+//! [watches]
+//! "enclosure.delta" = "bme280.temperature - ds18b20.temperature"
+//! "rail.power" = "ina219.voltage * ina219.current"
+//! ```
+//!
+//! [`Watches::evaluate`] records every watch into the [`History`] under its
+//! name, so the history endpoints, pages and rules see it like any other
+//! measurement, and sets the `rpi_peripherals_watch` gauge. Watches may use
+//! other watches; they are evaluated dependencies first, and a cycle is
+//! rejected when it would be created.
+
+use crate::expr::Expr;
+use crate::history::History;
+use crate::metrics::Metrics;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watch {
+    pub name: String,
+    /// As written, for listing back.
+    pub source: String,
+    pub expr: Expr,
+}
+
+/// Cheap to clone; every clone shares the one set of watches.
+#[derive(Clone)]
+pub struct Watches {
+    /// Kept in evaluation order.
+    watches: Arc<Mutex<Vec<Watch>>>,
+    history: History,
+    metrics: Option<Metrics>,
+}
+
+impl Watches {
+    pub fn new(history: History) -> Self {
+        Watches {
+            watches: Arc::default(),
+            history,
+            metrics: None,
+        }
+    }
+
+    /// The `[watches]` config section, name to expression.
+    pub fn from_config(config: &BTreeMap<String, String>, history: History) -> Result<Self, Box<dyn Error>> {
+        let watches = Watches::new(history);
+        *watches.lock() = parse_all(config)?;
+        Ok(watches)
+    }
+
+    /// Replace every watch with the `[watches]` section's, e.g. on a config
+    /// reload; ones added over HTTP since go too. Nothing changes if one
+    /// doesn't parse.
+    pub fn set_config(&self, config: &BTreeMap<String, String>) -> Result<(), Box<dyn Error>> {
+        let watches = parse_all(config)?;
+        *self.lock() = watches;
+        Ok(())
+    }
+
+    /// Also export each value as a gauge.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Add `name`, or replace it if it exists.
+    pub fn add(&self, name: &str, source: &str) -> Result<(), Box<dyn Error>> {
+        let mut watches = self.lock();
+        let mut config: BTreeMap<String, String> =
+            watches.iter().map(|w| (w.name.clone(), w.source.clone())).collect();
+        config.insert(name.to_string(), source.to_string());
+        *watches = parse_all(&config)?;
+        Ok(())
+    }
+
+    /// Drop `name`; `false` if there was no such watch. Its history stays.
+    pub fn remove(&self, name: &str) -> bool {
+        let mut watches = self.lock();
+        let before = watches.len();
+        watches.retain(|w| w.name != name);
+        watches.len() != before
+    }
+
+    /// Every watch, in evaluation order.
+    pub fn list(&self) -> Vec<Watch> {
+        self.lock().clone()
+    }
+
+    /// Evaluate every watch against the latest values and record the
+    /// results; `None` where an input has no value yet.
+    pub fn evaluate(&self) -> Vec<(String, Option<f64>)> {
+        let watches = self.lock().clone();
+        let lookup = |name: &str| self.history.latest(name);
+        watches
+            .into_iter()
+            .map(|watch| {
+                let value = watch.expr.eval(&lookup);
+                if let Some(value) = value {
+                    self.history.record(&watch.name, value);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.set(
+                            "rpi_peripherals_watch",
+                            "Value of a watch expression",
+                            &[("name", &watch.name)],
+                            value,
+                        );
+                    }
+                }
+                (watch.name, value)
+            })
+            .collect()
+    }
+
+    /// Evaluate every `interval` on a background thread until `stop` is set.
+    pub fn spawn(self, interval: Duration, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                self.evaluate();
+                thread::sleep(interval);
+            }
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Watch>> {
+        self.watches.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Parse every expression and order them so each comes after the watches it
+/// uses. What `[watches]` validation runs.
+pub fn parse_all(config: &BTreeMap<String, String>) -> Result<Vec<Watch>, Box<dyn Error>> {
+    let mut parsed = BTreeMap::new();
+    for (name, source) in config {
+        if name.is_empty() {
+            return Err("a watch needs a name".into());
+        }
+        let expr: Expr = source.parse().map_err(|e| format!("watch '{}': {}", name, e))?;
+        parsed.insert(name.as_str(), Watch {
+            name: name.clone(),
+            source: source.clone(),
+            expr,
+        });
+    }
+
+    // Depth-first, marking names in progress to catch cycles
+    let mut state: HashMap<&str, bool> = HashMap::new();
+    let mut order = Vec::new();
+    fn visit<'a>(
+        name: &'a str,
+        parsed: &'a BTreeMap<&'a str, Watch>,
+        state: &mut HashMap<&'a str, bool>,
+        order: &mut Vec<&'a str>,
+        path: &mut Vec<&'a str>,
+    ) -> Result<(), Box<dyn Error>> {
+        match state.get(name) {
+            Some(true) => return Ok(()),
+            Some(false) => {
+                path.push(name);
+                return Err(format!("watches refer to each other in a loop: {}", path.join(" → ")).into());
+            }
+            None => {}
+        }
+        state.insert(name, false);
+        path.push(name);
+        for used in parsed[name].expr.measurements() {
+            if let Some((&key, _)) = parsed.get_key_value(used) {
+                visit(key, parsed, state, order, path)?;
+            }
+        }
+        path.pop();
+        state.insert(name, true);
+        order.push(name);
+        Ok(())
+    }
+    for &name in parsed.keys() {
+        visit(name, &parsed, &mut state, &mut order, &mut Vec::new())?;
+    }
+    Ok(order.into_iter().map(|name| parsed[name].clone()).collect())
+}